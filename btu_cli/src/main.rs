@@ -1,16 +1,42 @@
 // main.rs
 
-use std::io::Read;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
 
-use clap::{App, AppSettings, Arg, SubCommand};  //, ArgMatches, AppSettings;
+use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};  //, ArgMatches, AppSettings;
 use serde_json::Value   as SerdeJsonValue;
 
 use btu_scheduler::{
-    config::AppConfig,
-    rq,
+    config::{self, AppConfig},
+    config_check,
+    btu_cron,
+    diagnose::{DiagnosticFinding, Severity},
+    circuit_breaker,
+    errors::{FrameError, FrappeApiError, FrappeHttpError, RQError, RescheduleError, SmokeTestError},
+    events,
+    frappe_client::FrappeClient,
+    next_run_override::{self, RescheduleTarget},
+    ids::{RqJobId, TaskScheduleId},
+    queue_stats::QueueStatsReport,
+    rq::{self, QueueSummary},
+    run_history::RunRecord,
     scheduler,
-    task::{BtuTask, print_enabled_tasks},
+    socket_frame,
+    timeutil::{compact_instant, parse_duration_arg},
 };
+#[cfg(feature = "sql")]
+use btu_scheduler::{
+    diagnose,
+    ids::TaskId,
+    task::{BtuTask, TaskListOptions, TaskPage, list_enabled_tasks},
+    task_schedule,
+    timeutil::humanize_duration,
+};
+
+mod output;
+mod tests;
+
+use output::OutputFormat;
 
 
 fn add_arguments<'a, 'b>(cli_app: App<'a, 'b>) -> App<'a, 'b> {
@@ -35,6 +61,23 @@ fn add_arguments<'a, 'b>(cli_app: App<'a, 'b>) -> App<'a, 'b> {
             .takes_value(true)
             .value_name("CONFIG_FILE")
         )
+        .arg(
+            Arg::with_name("user")
+            .help("user mode: read the configuration from $XDG_CONFIG_HOME/btu_scheduler, and use a per-user socket")
+            .long("user")
+        )
+        .arg(
+            Arg::with_name("no-color")
+            .help("never color the output (also disabled by the NO_COLOR environment variable, or when not writing to a terminal)")
+            .long("no-color")
+            .global(true)
+        )
+        .arg(
+            Arg::with_name("json")
+            .help("print JSON on stdout, for scripts (list-jobs, list-tasks, list-queues, show-job, and show-scheduled)")
+            .long("json")
+            .global(true)
+        )
         ;
 
     // Add some subcommands for Clap.
@@ -42,11 +85,66 @@ fn add_arguments<'a, 'b>(cli_app: App<'a, 'b>) -> App<'a, 'b> {
         .subcommand(SubCommand::with_name("test-pickler")
             .about("Call the Frappe web server's BTU 'test-pickler' RPC function.")
         )
+        .subcommand(SubCommand::with_name("check")
+            .about("Verify connections to Redis and MariaDB, and that Redis looks like the Python RQ database.")
+        )
+        .subcommand(SubCommand::with_name("show-config-problems")
+            .about("Check every setting the daemon depends on: time zone, MariaDB, Redis, Frappe web server, and the socket's directory and group.")
+        )
+        .subcommand(SubCommand::with_name("worker-smoke-test")
+            .about("Enqueue a trivial test job, and wait for a Python RQ worker to finish it.")
+            .arg(Arg::with_name("queue")
+                .help("the RQ queue to enqueue the test job on (default \"default\")")
+                .long("queue")
+                .takes_value(true)
+                .value_name("QUEUE")
+            )
+            .arg(Arg::with_name("timeout")
+                .help("how long to wait for a worker to finish the job, such as \"90s\" or \"2m\" (default 60 seconds)")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("DURATION")
+            )
+        )
         .subcommand(SubCommand::with_name("list-jobs")
-            .about("List all known Jobs in the Redis Queue.")
+            .about("List known Jobs in the Redis Queue.")
+            .arg(Arg::with_name("limit")
+                .help("maximum number of Jobs to list (default 100)")
+                .long("limit")
+                .takes_value(true)
+                .value_name("NUMBER")
+            )
+            .arg(Arg::with_name("all")
+                .help("list every Job, regardless of --limit")
+                .long("all")
+                .conflicts_with("limit")
+            )
         )
         .subcommand(SubCommand::with_name("list-tasks")
-            .about("List all Submitted Tasks stored in the Frappe MariaDB database.")
+            .about("List the Submitted Tasks stored in the Frappe MariaDB database, ordered by name.")
+            .arg(Arg::with_name("limit")
+                .help("maximum number of Tasks to list (default 50)")
+                .long("limit")
+                .takes_value(true)
+                .value_name("NUMBER")
+            )
+            .arg(Arg::with_name("all")
+                .help("list every Task, regardless of --limit")
+                .long("all")
+                .conflicts_with("limit")
+            )
+            .arg(Arg::with_name("offset")
+                .help("skip this many Tasks first (default 0)")
+                .long("offset")
+                .takes_value(true)
+                .value_name("NUMBER")
+            )
+            .arg(Arg::with_name("filter")
+                .help("only list Tasks whose name or short description contains this text")
+                .long("filter")
+                .takes_value(true)
+                .value_name("TEXT")
+            )
         )
         .subcommand(SubCommand::with_name("test-ping")
             .about("Call the Frappe web server's BTU 'test_ping' RPC function.")
@@ -62,6 +160,7 @@ fn add_arguments<'a, 'b>(cli_app: App<'a, 'b>) -> App<'a, 'b> {
                 .takes_value(true)
                 .value_name("JOB_ID")
             )
+            .arg(create_queue_argument())
         )
         .subcommand(SubCommand::with_name("queue-task-now")
             .about("Queue a Task for immediate execution.")
@@ -71,10 +170,131 @@ fn add_arguments<'a, 'b>(cli_app: App<'a, 'b>) -> App<'a, 'b> {
                 .takes_value(true)
                 .value_name("TASK_ID")
             )
+            .arg(create_queue_argument())
+            .arg(Arg::with_name("override-policy")
+                .help("queue the Task even if its function does not match 'allowed_function_prefixes'")
+                .long("override-policy")
+            )
+            .arg(Arg::with_name("dry-run")
+                .help("read the Task and fetch its pickled function, then print the RQ Job without writing anything to Redis")
+                .long("dry-run")
+            )
         )   
+        .subcommand(SubCommand::with_name("next-run")
+            .about("Show how long until a BTU Task Schedule next runs.")
+            .arg(Arg::with_name("schedule_id")
+                .help("the Task Schedule to examine")
+                .required(true)
+                .takes_value(true)
+                .value_name("SCHEDULE_ID")
+            )
+        )
+        .subcommand(SubCommand::with_name("diagnose")
+            .about("Explain why a BTU Task Schedule is (or is not) running: every check, each with pass, warn, or fail.")
+            .arg(Arg::with_name("schedule_id")
+                .help("the Task Schedule to examine")
+                .required(true)
+                .takes_value(true)
+                .value_name("SCHEDULE_ID")
+            )
+        )
+        .subcommand(SubCommand::with_name("validate-cron")
+            .about("Check a cron expression: how it is normalized, when it runs, and whether Frappe can show it.")
+            .arg(Arg::with_name("expression")
+                .help("the cron expression, of 5, 6, or 7 elements (quote it)")
+                .required(true)
+                .takes_value(true)
+                .value_name("EXPRESSION")
+            )
+        )
         .subcommand(SubCommand::with_name("show-scheduled")
             .about("Show BTU Tasks that are scheduled in the RQ database.")
         )
+        .subcommand(SubCommand::with_name("circuit")
+            .about("List Task Schedules no longer scheduled because they failed too often in a row (see 'max_consecutive_failures').")
+            .arg(Arg::with_name("reset")
+                .help("schedule this Task Schedule again, and forget its failures")
+                .long("reset")
+                .takes_value(true)
+                .value_name("SCHEDULE_ID")
+            )
+        )
+        .subcommand(SubCommand::with_name("list-queues")
+            .about("List the RQ queues in Redis, with their pending jobs, and how many workers listen to each.")
+        )
+        .subcommand(SubCommand::with_name("queue-stats")
+            .about("Show how many jobs BTU enqueued on each RQ queue, per day, and when it last did.")
+            .arg(Arg::with_name("days")
+                .help("how many days to count, including today (default 7)")
+                .long("days")
+                .takes_value(true)
+                .value_name("DAYS")
+            )
+        )
+        .subcommand(SubCommand::with_name("snooze")
+            .about("Move a Task Schedule's next run, without editing its cron expression; later runs follow the cron expression again.")
+            .arg(Arg::with_name("schedule_id")
+                .help("the Task Schedule to move")
+                .required(true)
+                .takes_value(true)
+                .value_name("SCHEDULE_ID")
+            )
+            .arg(Arg::with_name("until")
+                .help("run next at this time, such as \"2024-07-01T04:00:00Z\"")
+                .long("until")
+                .takes_value(true)
+                .value_name("TIME")
+            )
+            .arg(Arg::with_name("by")
+                .help("move the next run by this much, such as \"2h\"; negative (\"-30m\") to run early")
+                .long("by")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .value_name("OFFSET")
+            )
+            .group(ArgGroup::with_name("when")
+                .args(&["until", "by"])
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("show-history")
+            .about("Show a Task Schedule's recent runs: when each was handled, and whether its RQ Job was enqueued.")
+            .arg(Arg::with_name("schedule_id")
+                .help("the Task Schedule whose runs to show")
+                .required(true)
+                .takes_value(true)
+                .value_name("SCHEDULE_ID")
+            )
+        )
+        .subcommand(SubCommand::with_name("internal-queue")
+            .about("Ask the running daemon which Task Schedules are waiting in its internal queue.")
+        )
+        .subcommand(SubCommand::with_name("daemon-status")
+            .about("Ask the running daemon for its health: uptime, internal queue, last full refresh and scheduler pass, and database connections.")
+        )
+        .subcommand(SubCommand::with_name("daemon-config")
+            .about("Ask the running daemon for its live configuration (secrets redacted), and where it was read from.")
+        )
+        .subcommand(SubCommand::with_name("events")
+            .about("Print scheduler events from the Redis stream 'btu_scheduler:events', one JSON object per line.")
+            .arg(Arg::with_name("count")
+                .help("how many events to print (default 20)")
+                .long("count")
+                .takes_value(true)
+                .value_name("COUNT")
+            )
+            .arg(Arg::with_name("since")
+                .help("only print events after this stream ID")
+                .long("since")
+                .takes_value(true)
+                .value_name("STREAM_ID")
+            )
+            .arg(Arg::with_name("follow")
+                .help("keep waiting for new events, until interrupted")
+                .long("follow")
+                .short("f")
+            )
+        )
         .subcommand(SubCommand::with_name("show-job")
             .about("Show all information about a specific RQ Job.")
 			.arg(Arg::with_name("job_id")
@@ -84,11 +304,59 @@ fn add_arguments<'a, 'b>(cli_app: App<'a, 'b>) -> App<'a, 'b> {
 				.value_name("JOB_ID")
 			)
         )
+        .subcommand(SubCommand::with_name("export-state")
+            .about("Save BTU's scheduler state in Redis (all 'btu_scheduler:*' keys) to a JSON file.")
+            .arg(Arg::with_name("out")
+                .help("path of the JSON file to write")
+                .long("out")
+                .required(true)
+                .takes_value(true)
+                .value_name("FILE")
+            )
+        )
+        .subcommand(SubCommand::with_name("import-state")
+            .about("Restore BTU's scheduler state in Redis from a JSON file created by 'export-state'.")
+            .arg(Arg::with_name("in")
+                .help("path of the JSON file to read")
+                .long("in")
+                .required(true)
+                .takes_value(true)
+                .value_name("FILE")
+            )
+            .arg(Arg::with_name("overwrite")
+                .help("replace BTU's Redis state with the file's contents, instead of merging")
+                .long("overwrite")
+            )
+        )
+        .subcommand(SubCommand::with_name("config-upgrade")
+            .about("Convert a configuration file from an older version of BTU into the current format.")
+            .arg(Arg::with_name("in")
+                .help("path of the existing TOML configuration file")
+                .long("in")
+                .required(true)
+                .takes_value(true)
+                .value_name("FILE")
+            )
+            .arg(Arg::with_name("out")
+                .help("path of the upgraded TOML file to write")
+                .long("out")
+                .required(true)
+                .takes_value(true)
+                .value_name("FILE")
+            )
+        )
         ;
 
     ret
 }
 
+/// Shared by the subcommands that enqueue a job.
+fn create_queue_argument<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("create-queue")
+        .help("acknowledge that the job's queue is new, and no worker serves it yet")
+        .long("create-queue")
+}
+
 fn main() {
 
 	// Step 1.  Create the basic skeleton for the command line application.
@@ -103,16 +371,45 @@ fn main() {
 	// Note: The method get_matches() takes ownership of a clap App, and returns a ArgMatches.  Effectively destroying App!
 	// Having read the Clap comments, apparently this is what the developer intended.
 	let matches: clap::ArgMatches = cli_app.get_matches();
+	output::init(matches.is_present("no-color"));
+	let format = OutputFormat::from_json_flag(matches.is_present("json"));
+
+	// Upgrading a configuration file must work even when the current file no longer loads.
+	if let ("config-upgrade", Some(arg_matches)) = matches.subcommand() {
+		let result = cli_config_upgrade(arg_matches.value_of("in").unwrap(), arg_matches.value_of("out").unwrap());
+		if let Err(cli_error) = result {
+			output::print_error(&cli_error.to_string());
+			std::process::exit(cli_error.exit_code());
+		}
+		std::process::exit(0);
+	}
 
 	// Step 2.  Load the application configuration.  If CLI was called with --config, pass that argument.
-	let app_config: AppConfig;
-	match AppConfig::new_from_toml_file(matches.value_of("config")) {
+	//          With --user (or when the system file is unreadable), the configuration and socket follow XDG paths.
+	//          Without --config, and without a file at the chosen path, read BTU_ environment variables instead.
+	let mut app_config: AppConfig;
+	let paths: config::ResolvedPaths = config::resolve_paths(matches.value_of("config"), matches.is_present("user"));
+	if paths.user_mode {
+		eprintln!("Running in {}.", paths);
+	}
+	let from_environment: bool = matches.value_of("config").is_none() && !paths.config_file.exists();
+	let config_result = if from_environment {
+		eprintln!("No configuration file at '{}'; reading the configuration from {}* environment variables (and './.env', if present).",
+		          paths.config_file, config::ENV_PREFIX);
+		AppConfig::new_from_env()
+	} else {
+		AppConfig::new_from_toml_file(Some(paths.config_file.as_str()))
+	};
+	match config_result {
 		Ok(result) => {
 			app_config = result;
+			paths.apply_socket_path(&mut app_config);
 		},
 		Err(error) => {
-			println!("Error while creating AppConfig from TOML configuration file.\n{}", error);
-			std::process::exit(1);
+			let source: &str = if from_environment { "environment variables" } else { "TOML configuration file" };
+			let cli_error = CliError::Configuration(format!("Error while creating AppConfig from {}.\n{}", source, error));
+			output::print_error(&cli_error.to_string());
+			std::process::exit(cli_error.exit_code());
 		}
 	}
 
@@ -122,11 +419,15 @@ fn main() {
         .occurrences_of("debug")
     {
         0 => {
-            println!("Debug mode is off");
+            if format == OutputFormat::Human {
+                println!("Debug mode is off");  // stdout must hold nothing but the JSON document
+            }
             debug_mode = false;
         },
         1 => {
-            println!("Debug mode is on");
+            if format == OutputFormat::Human {
+                println!("Debug mode is on");
+            }
             debug_mode = true;
         },
         _ => {
@@ -135,241 +436,1052 @@ fn main() {
         }
     }
 
-	match matches.subcommand() {
+	let result: Result<(), CliError> = match matches.subcommand() {
 		("test-pickler", Some(_)) => {
-			cli_btu_test_pickler(&app_config, debug_mode);
+			cli_btu_test_pickler(&app_config, debug_mode)
+		},
+		("check", Some(_)) => {
+			cli_check(&app_config)
 		},
-		("list-jobs", Some(_)) => {
-			cli_list_jobs(&app_config);
+		("show-config-problems", Some(_)) => {
+			cli_show_config_problems(&app_config)
 		},
-		("list-tasks", Some(_)) => {
-			cli_list_tasks(&app_config);
+		("worker-smoke-test", Some(arg_matches)) => {
+			match parse_duration_arg(arg_matches.value_of("timeout").unwrap_or("60")) {
+				Ok(timeout) if timeout > chrono::Duration::zero() => {
+					cli_worker_smoke_test(&app_config, arg_matches.value_of("queue").unwrap_or("default"), timeout.num_seconds() as u64)
+				},
+				Ok(_) => Err(CliError::Generic("Argument --timeout must be positive.".to_owned())),
+				Err(error) => Err(CliError::Generic(format!("Argument --timeout: {}", error)))
+			}
+		},
+		("list-jobs", Some(arg_matches)) => {
+			if arg_matches.is_present("all") {
+				cli_list_jobs(&app_config, None, format)
+			}
+			else {
+				match arg_matches.value_of("limit").unwrap_or("100").parse::<usize>() {
+					Ok(limit) => cli_list_jobs(&app_config, Some(limit), format),
+					Err(_) => Err(CliError::Generic("Argument --limit must be a positive number.".to_owned()))
+				}
+			}
+		},
+		#[cfg(feature = "sql")]
+		("list-tasks", Some(arg_matches)) => {
+			task_list_options(arg_matches).and_then(|options| cli_list_tasks(&app_config, &options, format))
 		},
 		("print-config", Some(_)) => {
-			cli_print_config(&app_config);
+			cli_print_config(&app_config)
 		},
         ("queue-job-now", Some(arg_matches)) => {
-            let job_id: &str = arg_matches.value_of("job_id").unwrap();
-			cli_queue_job_immediately(&app_config, job_id);
+            let job_id: &str = arg_matches.value_of("job_id").unwrap();  // clap guarantees required arguments.
+			RqJobId::new(job_id).map_err(CliError::from)
+				.and_then(|job_id| cli_queue_job_immediately(&app_config, &job_id, arg_matches.is_present("create-queue")))
 		},
+        #[cfg(feature = "sql")]
         ("queue-task-now", Some(arg_matches)) => {
             let task_id: &str = arg_matches.value_of("task_id").unwrap();
-			cli_queue_task_immediately(&app_config, task_id);
+			TaskId::new(task_id).map_err(CliError::from)
+				.and_then(|task_id| cli_queue_task_immediately(&app_config, &task_id, QueueTaskOptions {
+					create_queue: arg_matches.is_present("create-queue"),
+					override_policy: arg_matches.is_present("override-policy"),
+					dry_run: arg_matches.is_present("dry-run"),
+				}))
+		},
+		#[cfg(feature = "sql")]
+		("next-run", Some(arg_matches)) => {
+			let schedule_id: &str = arg_matches.value_of("schedule_id").unwrap();
+			TaskScheduleId::new(schedule_id).map_err(CliError::from)
+				.and_then(|schedule_id| cli_next_run(&app_config, &schedule_id))
+		},
+		#[cfg(feature = "sql")]
+		("diagnose", Some(arg_matches)) => {
+			let schedule_id: &str = arg_matches.value_of("schedule_id").unwrap();
+			TaskScheduleId::new(schedule_id).map_err(CliError::from)
+				.and_then(|schedule_id| cli_diagnose(&app_config, &schedule_id))
+		},
+		("validate-cron", Some(arg_matches)) => {
+			cli_validate_cron(&app_config, arg_matches.value_of("expression").unwrap())
 		},
         ("show-scheduled", Some(_)) => {
-			cli_show_scheduled_jobs(&app_config);
+			cli_show_scheduled_jobs(&app_config, format)
+		},
+		("circuit", Some(arg_matches)) => {
+			match arg_matches.value_of("reset") {
+				Some(schedule_id) => TaskScheduleId::new(schedule_id).map_err(CliError::from)
+					.and_then(|schedule_id| cli_circuit(&app_config, Some(&schedule_id))),
+				None => cli_circuit(&app_config, None)
+			}
+		},
+		("list-queues", Some(_)) => {
+			cli_list_queues(&app_config, format)
+		},
+		("queue-stats", Some(arg_matches)) => {
+			match arg_matches.value_of("days").unwrap_or("7").parse::<u32>() {
+				Ok(days) if days > 0 => cli_queue_stats(&app_config, days),
+				_ => Err(CliError::Generic("Argument --days must be a positive number.".to_owned()))
+			}
+		},
+		("snooze", Some(arg_matches)) => {
+			let schedule_id: &str = arg_matches.value_of("schedule_id").unwrap();
+			let target = match arg_matches.value_of("until") {
+				Some(until) => RescheduleTarget::parse_until(until),
+				None => RescheduleTarget::parse_by(arg_matches.value_of("by").unwrap_or_default()),  // clap requires one of them
+			};
+			TaskScheduleId::new(schedule_id).map_err(CliError::from)
+				.and_then(|schedule_id| Ok((schedule_id, target?)))
+				.and_then(|(schedule_id, target)| cli_snooze(&app_config, &schedule_id, target))
+		},
+		("show-history", Some(arg_matches)) => {
+			TaskScheduleId::new(arg_matches.value_of("schedule_id").unwrap()).map_err(CliError::from)
+				.and_then(|schedule_id| cli_show_history(&app_config, &schedule_id))
+		},
+		("internal-queue", Some(_)) => {
+			cli_show_internal_queue(&app_config)
+		},
+		("daemon-status", Some(_)) => {
+			cli_daemon_status(&app_config)
+		},
+		("daemon-config", Some(_)) => {
+			cli_daemon_config(&app_config)
+		},
+		("events", Some(arg_matches)) => {
+			match arg_matches.value_of("count").unwrap_or("20").parse::<usize>() {
+				Ok(count) if count > 0 => cli_events(&app_config, count, arg_matches.value_of("since"), arg_matches.is_present("follow")),
+				_ => Err(CliError::Generic("Argument --count must be a positive number.".to_owned()))
+			}
 		},
 		("show-job", Some(arg_matches)) => {
 			let job_id: &str = arg_matches.value_of("job_id").unwrap();
-			cli_show_job_details(&app_config, job_id);
+			RqJobId::new(job_id).map_err(CliError::from)
+				.and_then(|job_id| cli_show_job_details(&app_config, &job_id, format))
+		},
+		("export-state", Some(arg_matches)) => {
+			let file_path: &str = arg_matches.value_of("out").unwrap();
+			cli_export_state(&app_config, file_path)
+		},
+		#[cfg(feature = "sql")]
+		("import-state", Some(arg_matches)) => {
+			let file_path: &str = arg_matches.value_of("in").unwrap();
+			cli_import_state(&app_config, file_path, arg_matches.is_present("overwrite"))
 		},
 		("test-ping", Some(_)) => {
-			cli_ping_frappe_web(&app_config, debug_mode);
+			cli_ping_frappe_web(&app_config, debug_mode)
+		},
+		#[cfg(not(feature = "sql"))]
+		(subcommand @ ("list-tasks" | "queue-task-now" | "next-run" | "import-state" | "diagnose"), Some(_)) => {
+			Err(not_built_with_sql(subcommand))
 		},
-        ("", None) => println!("Please specify a subcommand (stamp, extract)"), // If no subcommand was used it'll match the tuple ("", None)
 		_ => unreachable!(), // If all subcommands are defined above, anything else is unreachable!()
+	};
+
+	// Wrapper scripts (cron, systemd) rely on the exit code, so errors must never exit with 0.
+	if let Err(error) = result {
+		output::print_error(&error.to_string());
+		std::process::exit(error.exit_code());
 	}
 }
 
 
+/**
+  Errors returned by the CLI's subcommands.  Each variant maps to a distinct process exit code:
+
+  * 0 = success
+  * 1 = generic failure
+  * 2 = configuration error
+  * 3 = connectivity error (Redis, MariaDB, or the Frappe web server)
+  * 4 = not found
+*/
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    Generic(String),
+    Configuration(String),
+    Connectivity(String),
+    NotFound(String),
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Generic(_) => 1,
+            CliError::Configuration(_) => 2,
+            CliError::Connectivity(_) => 3,
+            CliError::NotFound(_) => 4,
+        }
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Generic(message) => write!(f, "Error: {}", message),
+            CliError::Configuration(message) => write!(f, "Configuration error: {}", message),
+            CliError::Connectivity(message) => write!(f, "Connectivity error: {}", message),
+            CliError::NotFound(message) => write!(f, "Not found: {}", message),
+        }
+    }
+}
+
+/// The error for a subcommand that reads MariaDB, in a build without the 'sql' feature (such as '--features redis-only').
+#[cfg(not(feature = "sql"))]
+pub fn not_built_with_sql(subcommand: &str) -> CliError {
+    CliError::Generic(format!("'btu {}' reads from MariaDB, but this btu was not built with SQL support (the 'sql' feature).", subcommand))
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::NotFound => CliError::NotFound(error.to_string()),
+            std::io::ErrorKind::NotConnected
+            | std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::TimedOut => CliError::Connectivity(error.to_string()),
+            _ => CliError::Generic(error.to_string()),
+        }
+    }
+}
+
+impl From<btu_scheduler::errors::SchedulerError> for CliError {
+    fn from(error: btu_scheduler::errors::SchedulerError) -> Self {
+        match error {
+            btu_scheduler::errors::SchedulerError::UnknownTaskSchedule { .. } => CliError::NotFound(error.to_string()),
+            btu_scheduler::errors::SchedulerError::Sql { .. }
+            | btu_scheduler::errors::SchedulerError::SqlUnavailable { .. } => CliError::Connectivity(error.to_string()),
+        }
+    }
+}
+
+impl From<btu_scheduler::errors::IdentifierError> for CliError {
+    fn from(error: btu_scheduler::errors::IdentifierError) -> Self {
+        CliError::Generic(error.to_string())
+    }
+}
+
+impl From<SmokeTestError> for CliError {
+    fn from(error: SmokeTestError) -> Self {
+        match error {
+            SmokeTestError::Fetch { source } => CliError::from(source),
+            SmokeTestError::Save { .. } | SmokeTestError::Enqueue { .. } => CliError::Connectivity(error.to_string()),
+        }
+    }
+}
+
+impl From<RescheduleError> for CliError {
+    fn from(error: RescheduleError) -> Self {
+        match error {
+            RescheduleError::NothingScheduled { .. } => CliError::NotFound(error.to_string()),
+            RescheduleError::NoConnection | RescheduleError::Redis { .. } => CliError::Connectivity(error.to_string()),
+            _ => CliError::Generic(error.to_string()),
+        }
+    }
+}
+
+impl From<RQError> for CliError {
+    fn from(error: RQError) -> Self {
+        match &error {
+            RQError::NoConnection => CliError::Connectivity(error.to_string()),
+            RQError::ScanFailed { source, .. } | RQError::ReadFailed { source, .. } | RQError::Unknown { source } if source.is_io_error() => CliError::Connectivity(error.to_string()),
+            _ => CliError::Generic(error.to_string()),
+        }
+    }
+}
+
+impl From<FrappeApiError> for CliError {
+    fn from(error: FrappeApiError) -> Self {
+        match error {
+            FrappeApiError::Transport { .. } => CliError::Connectivity(error.to_string()),
+            FrappeApiError::Exception { .. } | FrappeApiError::UnexpectedResponse { .. } => CliError::Generic(error.to_string()),
+        }
+    }
+}
+
+impl From<FrappeHttpError> for CliError {
+    fn from(error: FrappeHttpError) -> Self {
+        match error {
+            FrappeHttpError::Timeout { .. } | FrappeHttpError::Transport { .. } => CliError::Connectivity(error.to_string()),
+            FrappeHttpError::Status { .. } | FrappeHttpError::InvalidBody { .. } => CliError::Generic(error.to_string()),
+        }
+    }
+}
+
 
 /*
     The remaining functions below are the "glue" between the CLI and the BTU library.
 */
 
 
-fn cli_btu_test_pickler(app_config: &AppConfig, debug_mode: bool) {
+fn cli_btu_test_pickler(app_config: &AppConfig, debug_mode: bool) -> Result<(), CliError> {
     /*
         Function calls the Frappe web server, and asks for 'Hello World' in bytes.
     */
-    let url: String;
-    if app_config.webserver_port == 443 {
-        url = format!("https://{}/api/method/btu.btu_api.endpoints.test_function_ping_now_bytes",
-                      app_config.webserver_ip);
+    let client = FrappeClient::new(app_config);
+    if debug_mode {
+        println!("Target URL = {}", client.url("/api/method/btu.btu_api.endpoints.test_function_ping_now_bytes"));
+        println!("Timeout = {} seconds", client.timeout().as_secs());
     }
-    else {
-        url = format!("http://{}:{}/api/method/btu.btu_api.endpoints.test_function_ping_now_bytes",
-                      app_config.webserver_ip, app_config.webserver_port);
+    let bytes: Vec<u8> = client.get_bytes("/api/method/btu.btu_api.endpoints.test_function_ping_now_bytes")?;
+    println!("HTTP Response as Bytes: {:?}", bytes);
+    let bytes_as_string = std::str::from_utf8(&bytes)
+        .map_err(|error| CliError::Generic(format!("Invalid UTF-8 sequence: {}", error)))?;
+    println!("HTTP Bytes as UTF-8 String: {}", bytes_as_string);
+    Ok(())
+}
+
+
+fn cli_worker_smoke_test(app_config: &AppConfig, queue_name: &str, timeout_secs: u64) -> Result<(), CliError> {
+    println!("Enqueuing a test job on queue '{}', and waiting up to {} seconds for a worker to finish it...", queue_name, timeout_secs);
+    let report = btu_scheduler::worker_smoke_test(app_config, queue_name, std::time::Duration::from_secs(timeout_secs))?;
+    let round_trip: String = format!("{:.2} seconds", report.round_trip.as_secs_f64());
+    match report.outcome {
+        rq::JobOutcome::Finished { .. } => {
+            output::print_success(&format!("A worker finished test job '{}' on queue '{}'; round trip {}.", report.job_id, queue_name, round_trip));
+            Ok(())
+        },
+        rq::JobOutcome::Vanished { .. } => {
+            output::print_warning(&format!("Test job '{}' disappeared before a final status was seen (after {}); \
+                                            a worker probably finished it with 'result_ttl' 0.", report.job_id, round_trip));
+            Ok(())
+        },
+        rq::JobOutcome::Failed { status, exc_info, .. } => {
+            Err(CliError::Generic(format!("Test job '{}' ended with status '{}' after {}.\n{}",
+                                          report.job_id, status, round_trip, exc_info.unwrap_or("(no exc_info)".to_owned()))))
+        },
+        rq::JobOutcome::TimedOut { last_status, .. } => {
+            Err(CliError::Generic(format!("No worker finished test job '{}' within {} seconds (last status: {}).  Is a worker listening to queue '{}'?",
+                                          report.job_id, timeout_secs, last_status.as_deref().filter(|status| !status.is_empty()).unwrap_or("unknown"), queue_name)))
+        }
     }
+}
 
-    let mut request = ureq::get(&url)
-        .set("Authorization", &app_config.webserver_token)
-        .set("Content-Type", "application/octet-stream");
 
-    // If Frappe is running via gunicorn, in DNS Multi-tenancy mode, then we have to pass a "Host" header.
-    if app_config.webserver_host_header.is_some() {
-        request = request.set("Host", &app_config.webserver_host_header.as_ref().unwrap());
+fn cli_check(app_config: &AppConfig) -> Result<(), CliError> {
+    let report: rq::SanityReport = rq::sanity_check_rq_database(app_config);
+    match report.verdict {
+        rq::SanityVerdict::Unreachable => {
+            return Err(CliError::Connectivity(format!("Unable to connect to Redis at {}:{}", app_config.rq_host, app_config.rq_port)));
+        },
+        rq::SanityVerdict::EmptyDatabase => {
+            output::print_success("Redis: connected.  The database is empty (expected for a new installation).");
+        },
+        rq::SanityVerdict::LooksLikeRq => {
+            output::print_success(&format!("Redis: connected.  Found Python RQ keys: {}", report.found.join(", ")));
+        },
+        rq::SanityVerdict::Suspicious => {
+            // The connection works, but the configuration is probably wrong.
+            return Err(CliError::Configuration(report.warning_message(app_config).unwrap_or_default()));
+        }
     }
+    #[cfg(feature = "sql")]
+    {
+        let report: btu_scheduler::SqlValidationReport = btu_scheduler::validate_sql_credentials(app_config);
+        match report.status {
+            btu_scheduler::SqlValidationStatus::Ok => output::print_success(&format!("MariaDB: {}", report.message)),
+            btu_scheduler::SqlValidationStatus::ConnectionRefused =>
+                return Err(CliError::Connectivity(format!("Unable to connect to MariaDB: {}", report.describe()))),
+            _ => return Err(CliError::Configuration(format!("MariaDB: {}", report.describe()))),
+        }
+    }
+    #[cfg(not(feature = "sql"))]
+    output::print_warning("MariaDB: not checked; this btu was built without SQL support.");
+    Ok(())
+}
 
-    if debug_mode {
-        println!("Target URL = {}", url);
-        println!("Request = {:?}", request.request_url());
+
+fn cli_show_config_problems(app_config: &AppConfig) -> Result<(), CliError> {
+    let findings: Vec<DiagnosticFinding> = config_check::check_config(app_config);
+    for line in format_findings(&findings, output::stdout_color()) {
+        println!("{}", line);
+    }
+    let failed: usize = findings.iter().filter(|finding| finding.severity == Severity::Fail).count();
+    if failed > 0 {
+        return Err(CliError::Configuration(format!("{} of {} configuration checks failed.", failed, findings.len())));
     }
+    Ok(())
+}
 
-    let resp = request.call().unwrap();
 
-    if debug_mode {
-        println!("\nResponse Status = {:?}", resp.status());
-        println!("Response = {:?}", resp);
-        println!("Response Headers Names = {:?}\n", resp.headers_names());
+fn cli_export_state(app_config: &AppConfig, file_path: &str) -> Result<(), CliError> {
+    let state: scheduler::SchedulerState = scheduler::export_state(app_config)?;
+    let state_as_json: String = serde_json::to_string_pretty(&state)
+        .map_err(|error| CliError::Generic(format!("Unable to serialize scheduler state to JSON: {}", error)))?;
+    std::fs::write(file_path, state_as_json)
+        .map_err(|error| CliError::Generic(format!("Unable to write file '{}': {}", file_path, error)))?;
+    println!("Exported {} BTU keys to '{}'.", state.keys.len(), file_path);
+    Ok(())
+}
+
+
+/// How long each XREAD waits for new events, with 'events --follow'.
+const EVENTS_FOLLOW_BLOCK: std::time::Duration = std::time::Duration::from_secs(5);
+
+fn cli_events(app_config: &AppConfig, count: usize, since_id: Option<&str>, follow: bool) -> Result<(), CliError> {
+    let events: Vec<events::StreamedEvent> = scheduler::read_events(app_config, count, since_id)?;
+    if events.is_empty() && !follow {
+        eprintln!("No scheduler events found.  Is 'event_stream_enabled' set in the configuration?");
+    }
+    let mut last_id: String = since_id.unwrap_or("$").to_owned();
+    print_events(&events, &mut last_id)?;
+    if !follow {
+        return Ok(());
+    }
+    loop {
+        let events: Vec<events::StreamedEvent> = scheduler::wait_for_events(app_config, count, &last_id, EVENTS_FOLLOW_BLOCK)?;
+        print_events(&events, &mut last_id)?;
     }
+}
 
-    assert!(resp.has("content-length"));  // will panic if no Content Length.
-    let len = resp.header("content-length")
-        .and_then(|s| s.parse::<usize>().ok()).unwrap();
+/// Print each event as one line of JSON, and remember the last stream ID printed.
+fn print_events(events: &[events::StreamedEvent], last_id: &mut String) -> Result<(), CliError> {
+    for event in events {
+        let event_as_json: String = serde_json::to_string(event)
+            .map_err(|error| CliError::Generic(format!("Unable to serialize event '{}': {}", event.id, error)))?;
+        println!("{}", event_as_json);
+        last_id.clone_from(&event.id);
+    }
+    std::io::stdout().flush()?;
+    Ok(())
+}
 
-    let mut bytes: Vec<u8> = Vec::with_capacity(len);
-    // Read the bytes, up to a maximum:
-    resp.into_reader()
-        .take(10_000_000)
-        .read_to_end(&mut bytes).unwrap();
 
-    assert_eq!(bytes.len(), len);
-    println!("HTTP Response as Bytes: {:?}", bytes);
-    let bytes_as_string = match std::str::from_utf8(&bytes) {
-        Ok(v) => v,
-        Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-    };
-    println!("HTTP Bytes as UTF-8 String: {}", bytes_as_string);
+fn cli_config_upgrade(in_path: &str, out_path: &str) -> Result<(), CliError> {
+    let file_contents: String = std::fs::read_to_string(in_path)
+        .map_err(|error| CliError::NotFound(format!("Unable to read file '{}': {}", in_path, error)))?;
+    let upgrade: config::ConfigUpgrade = config::upgrade_config_toml(&file_contents)
+        .map_err(|error| CliError::Configuration(format!("Unable to upgrade '{}': {}", in_path, error)))?;
+    let toml_string: String = upgrade.to_toml_string()
+        .map_err(|error| CliError::Generic(format!("Unable to serialize the upgraded configuration: {}", error)))?;
+    std::fs::write(out_path, toml_string)
+        .map_err(|error| CliError::Generic(format!("Unable to write file '{}': {}", out_path, error)))?;
+    print!("{}", upgrade);
+    println!("Wrote '{}'.", out_path);
+    Ok(())
 }
 
 
-fn cli_list_jobs(app_config: &AppConfig) {
-    // Prints all jobs currently stored in Python RQ.
-    match rq::get_all_job_ids(app_config) {
-        Some(jobs) => {
-            if jobs.len() == 0 {
-                println!("No jobs were found in Python RQ.");
-                return;
-            }
-            for job in jobs {
-                println!("Job: '{}'", job);
-            }
-        },
-        None => {
-            println!("No jobs were found in Python RQ.");
-        }
+#[cfg(feature = "sql")]
+fn cli_import_state(app_config: &AppConfig, file_path: &str, overwrite: bool) -> Result<(), CliError> {
+    let file_contents: String = std::fs::read_to_string(file_path)
+        .map_err(|error| CliError::NotFound(format!("Unable to read file '{}': {}", file_path, error)))?;
+    let state: scheduler::SchedulerState = serde_json::from_str(&file_contents)
+        .map_err(|error| CliError::Generic(format!("File '{}' is not a valid scheduler state export: {}", file_path, error)))?;
+    let report: scheduler::ImportReport = scheduler::import_state(app_config, &state, overwrite)?;
+    print!("{}", report);
+    Ok(())
+}
+
+
+fn cli_list_jobs(app_config: &AppConfig, limit: Option<usize>, format: OutputFormat) -> Result<(), CliError> {
+    // Prints jobs currently stored in Python RQ.  Without a limit, every job is printed.
+    // An unreachable Redis is an error (exit code 3); an empty one is not.
+    let (jobs, has_more): (Vec<String>, bool) = match limit {
+        Some(limit) => rq::get_job_ids_page(app_config, limit)?,
+        None => (rq::get_all_job_ids(app_config)?, false),
+    };
+    if format == OutputFormat::Json {
+        output::print_json(&job_list_json(&jobs, has_more));
+        return Ok(());
+    }
+    if jobs.is_empty() {
+        println!("{}", no_jobs_message(app_config));
+        return Ok(());
     }
+    let rows: Vec<Vec<String>> = jobs.iter().map(|job| vec![job.clone()]).collect();
+    for line in output::table(&["JOB ID"], &rows, &[Some(output::Color::Cyan)], output::stdout_color()) {
+        println!("{}", line);
+    }
+    if has_more {
+        println!("Showing {} of an unknown total.  Use --all to list every job.", jobs.len());
+    }
+    Ok(())
+}
+
+/// 'list-jobs --json': the Job IDs, and whether there are more than were listed.
+pub fn job_list_json(jobs: &[String], has_more: bool) -> SerdeJsonValue {
+    serde_json::json!({ "jobs": jobs, "has_more": has_more })
+}
+
+/// What 'list-jobs' prints when Redis was reached, but holds no RQ Jobs.
+pub fn no_jobs_message(app_config: &AppConfig) -> String {
+    format!("No jobs were found in Python RQ (Redis at {}:{} is reachable, and has no '{}*' keys).",
+            app_config.rq_host, app_config.rq_port, rq::job_key(""))
 }
 
+#[cfg(feature = "sql")]
+/// Default for 'list-tasks --limit'.
+const DEFAULT_TASK_LIST_LIMIT: usize = 50;
+
+#[cfg(feature = "sql")]
+fn task_list_options(arg_matches: &clap::ArgMatches) -> Result<TaskListOptions, CliError> {
+    let number = |name: &str, default: usize| -> Result<usize, CliError> {
+        arg_matches.value_of(name).map_or(Ok(default), |value| value.parse::<usize>()
+            .map_err(|_| CliError::Generic(format!("Argument --{} must be a positive number.", name))))
+    };
+    Ok(TaskListOptions {
+        limit: if arg_matches.is_present("all") { None } else { Some(number("limit", DEFAULT_TASK_LIST_LIMIT)?) },
+        offset: number("offset", 0)?,
+        filter: arg_matches.value_of("filter").map(str::to_owned),
+    })
+}
 
 /**
-  Prints to console the ID and Description of all enabled BTU Tasks in the MariaDB database.
+  Prints to console the ID and Description of the enabled BTU Tasks in the MariaDB database, one page at a time.
 */ 
-fn cli_list_tasks(app_config: &AppConfig) {
-    print_enabled_tasks(app_config, true);
+#[cfg(feature = "sql")]
+fn cli_list_tasks(app_config: &AppConfig, options: &TaskListOptions, format: OutputFormat) -> Result<(), CliError> {
+    let page: TaskPage = list_enabled_tasks(app_config, options)
+        .map_err(|error| CliError::Connectivity(format!("Unable to read BTU Tasks from the MariaDB database: {}", error)))?;
+    if format == OutputFormat::Json {
+        output::print_json(&serde_json::json!(page));
+        return Ok(());
+    }
+    for line in format_task_list(&page, options) {
+        println!("{}", line);
+    }
+    Ok(())
 }
 
+/// The lines printed by 'list-tasks': one per Task, then a footer with the total.  Or a single line explaining why there are none.
+#[cfg(feature = "sql")]
+pub fn format_task_list(page: &TaskPage, options: &TaskListOptions) -> Vec<String> {
+    if page.tasks.is_empty() {
+        return vec![match (page.total, options.filter.as_deref()) {
+            (0, Some(filter)) => format!("No BTU Tasks match '{}'.", filter),
+            (0, None) => "No BTU Tasks are defined in the MariaDB database.".to_owned(),
+            (total, _) => format!("No BTU Tasks at offset {}; there are {}.", options.offset, thousands(total)),
+        }];
+    }
+    let mut lines: Vec<String> = page.tasks.iter().map(|task| format!("Task {} : {}", task.task_key, task.desc_short)).collect();
+    lines.push(String::new());
+    let mut footer: String = format!("Showing {} of {}", thousands(page.tasks.len()), thousands(page.total));
+    if options.offset > 0 {
+        footer.push_str(&format!(", from offset {}", thousands(options.offset)));
+    }
+    if let Some(filter) = options.filter.as_deref() {
+        footer.push_str(&format!(", matching '{}'", filter));
+    }
+    footer.push('.');
+    lines.push(footer);
+    lines
+}
 
-fn cli_ping_frappe_web(app_config: &AppConfig, debug_mode: bool) {
+#[cfg(feature = "sql")]
+/// A number with commas between each group of three digits, such as "2,031".
+pub fn thousands(number: usize) -> String {
+    let digits: Vec<char> = number.to_string().chars().collect();
+    let groups: Vec<String> = digits.rchunks(3).rev().map(|group| group.iter().collect()).collect();
+    groups.join(",")
+}
+
+
+fn cli_ping_frappe_web(app_config: &AppConfig, debug_mode: bool) -> Result<(), CliError> {
     /*
         Calls a built-in BTU endpoint 'test_ping'
     */
-    let url: String;
-    if app_config.webserver_port == 443 {
-        url = format!("https://{}/api/method/btu.btu_api.endpoints.test_ping",
-                      app_config.webserver_ip);
-    }
-    else {
-        url = format!("http://{}:{}/api/method/btu.btu_api.endpoints.test_ping",
-                      app_config.webserver_ip, app_config.webserver_port);
-    }
+    let client = FrappeClient::new(app_config);
     if debug_mode {
-        println!("Target URL = {}", url);
-    }
-
-    let mut request = ureq::get(&url)
-        .set("Authorization", &app_config.webserver_token)
-        .set("Content-Type", "application/json");
-    // If Frappe is running via gunicorn, in DNS Multi-tenancy mode, then we have to pass a "Host" header.        
-    if app_config.webserver_host_header.is_some() {
-        request = request.set("Host", &app_config.webserver_host_header.as_ref().unwrap());
-    }
-
-    match request.call() {
-        Ok(response) => {
-            let body = response.into_string().unwrap();
-            println!("HTTP Response as String: {}", body);
-            let string_as_json: SerdeJsonValue = serde_json::from_str(&body).unwrap();
-    
-            // Note: The use of 'as_str()' function is because serde's Value automatically displays quotation marks.
-            // Converting to an Option<&str> and unwrapping gets rid of them.
-            // https://docs.serde.rs/serde_json/#operating-on-untyped-json-values
-            let message_value: &str = string_as_json["message"].as_str().unwrap();
-            println!("HTTP Response as JSON:  Key 'message' has value '{}'", message_value);
-        
+        println!("Target URL = {}", client.url("/api/method/btu.btu_api.endpoints.test_ping"));
+    }
+
+    let string_as_json: SerdeJsonValue = client.get_json("/api/method/btu.btu_api.endpoints.test_ping")?;
+    println!("HTTP Response as String: {}", string_as_json);
+
+    // Note: The use of 'as_str()' function is because serde's Value automatically displays quotation marks.
+    // Converting to an Option<&str> and unwrapping gets rid of them.
+    // https://docs.serde.rs/serde_json/#operating-on-untyped-json-values
+    let message_value: &str = string_as_json["message"].as_str()
+        .ok_or_else(|| CliError::Generic("HTTP response JSON has no string key 'message'.".to_owned()))?;
+    println!("HTTP Response as JSON:  Key 'message' has value '{}'", message_value);
+    Ok(())
+}
+
+
+#[cfg(feature = "sql")]
+fn cli_next_run(app_config: &AppConfig, schedule_id: &TaskScheduleId) -> Result<(), CliError> {
+    match scheduler::time_until_next_run(app_config, schedule_id.as_str())? {
+        Some(duration) if duration < chrono::Duration::zero() => {
+            println!("Task Schedule {} is past due by {}.", schedule_id, humanize_duration(-duration));
         },
-        Err(response) => {
-            println!("Error:\n{}", response);
+        Some(duration) => {
+            println!("Task Schedule {} runs next in {}.", schedule_id, humanize_duration(duration));
+        },
+        None => {
+            println!("Task Schedule {} has nothing scheduled.", schedule_id);
+        }
+    }
+    // Describe the schedule.  Schedules with a repeat interval instead of a cron expression say so.
+    if let Ok(Some(task_schedule)) = task_schedule::try_read_btu_task_schedule(app_config, schedule_id) {
+        match task_schedule.repeat_interval() {
+            Some(Ok(interval)) => println!("It repeats {}, counting from its previous run.", btu_cron::describe_interval(interval)),
+            Some(Err(error)) => println!("It cannot be scheduled: {}", error),
+            None => println!("It runs {}.", task_schedule.describe_schedule(app_config.cron_dom_dow_semantics))
+        }
+        if let Some(notice) = scheduler::dom_dow_notice(app_config, &task_schedule) {
+            println!("\nNOTICE: {}", notice);
+        }
+        if let Some(notice) = scheduler::cron_classification(&task_schedule).and_then(|classification| classification.notice()) {
+            println!("\nNOTICE: {}", notice);
         }
     }
-		
+    Ok(())
 }
 
+#[cfg(feature = "sql")]
+fn cli_diagnose(app_config: &AppConfig, schedule_id: &TaskScheduleId) -> Result<(), CliError> {
+    let now: chrono::DateTime<chrono::Utc> = chrono::Utc::now();
+    let inputs = diagnose::gather(app_config, schedule_id, daemon_last_cycle(app_config), now);
+    let findings: Vec<DiagnosticFinding> = diagnose::diagnose(&inputs, now);
+    for line in format_findings(&findings, output::stdout_color()) {
+        println!("{}", line);
+    }
+    let failed: usize = findings.iter().filter(|finding| finding.severity == Severity::Fail).count();
+    if failed > 0 {
+        return Err(CliError::Generic(format!("{} of {} checks failed for Task Schedule {}.", failed, findings.len(), schedule_id)));
+    }
+    Ok(())
+}
 
-fn cli_print_config(app_config: &AppConfig) {
-    println!("{}", app_config);
+/// One line per finding, led by its severity: green PASS, yellow WARN, red FAIL.
+pub fn format_findings(findings: &[DiagnosticFinding], color: bool) -> Vec<String> {
+    let width: usize = findings.iter().map(|finding| finding.check.len()).max().unwrap_or_default();
+    findings.iter().map(|finding| {
+        let severity_color = match finding.severity {
+            Severity::Pass => output::Color::Green,
+            Severity::Warn => output::Color::Yellow,
+            Severity::Fail => output::Color::Red,
+        };
+        format!("{}  {:<width$}  {}", output::paint(&finding.severity.to_string(), severity_color, color), finding.check, finding.explanation, width = width)
+    }).collect()
 }
 
+#[cfg(feature = "sql")]
+/// When the daemon's last scheduling cycle started, from its 'health' reply; None before its first cycle.
+fn daemon_last_cycle(app_config: &AppConfig) -> Result<Option<chrono::DateTime<chrono::Utc>>, String> {
+    let reply: String = send_daemon_request(app_config, "health", None).map_err(|error| error.to_string())?;
+    last_cycle_started_at(&reply)
+}
 
-fn cli_queue_job_immediately(app_config: &AppConfig, rq_job_id: &str) -> () {
-    // Given an existing RQ Job, push it immediately into Redis Queue.
-    if rq::exists_job_by_id(&app_config, &rq_job_id) {
-        match rq::enqueue_job_immediate(&app_config, &rq_job_id) {
-            Ok(ok_message) => {
-                println!("{}", ok_message);
-            }
-            Err(err_message) => {
-                println!("Error while attempting to queue job for execution: {}", err_message);
-            }
-        }
+#[cfg(feature = "sql")]
+/// The 'last_cycle.started_at' of a 'health' reply.
+pub fn last_cycle_started_at(reply: &str) -> Result<Option<chrono::DateTime<chrono::Utc>>, String> {
+    let health: SerdeJsonValue = serde_json::from_str(reply).map_err(|error| format!("the 'health' reply is not JSON: {}", error))?;
+    match health["last_cycle"]["started_at"].as_str() {
+        None => Ok(None),
+        Some(started_at) => chrono::DateTime::parse_from_rfc3339(started_at)
+            .map(|started_at| Some(started_at.with_timezone(&chrono::Utc)))
+            .map_err(|error| format!("the daemon's last cycle has an invalid 'started_at' ('{}'): {}", started_at, error)),
+    }
+}
+
+fn cli_validate_cron(app_config: &AppConfig, expression: &str) -> Result<(), CliError> {
+    for line in validate_cron_lines(app_config, expression)? {
+        println!("{}", line);
     }
-    else {
-        println!("Could not find a job with ID = {}", rq_job_id);
+    Ok(())
+}
+
+/// What 'btu validate-cron' prints: the expression's normalized form, its description, and what Frappe's UI cannot show.
+pub fn validate_cron_lines(app_config: &AppConfig, expression: &str) -> Result<Vec<String>, CliError> {
+    let invalid = |error: btu_scheduler::errors::CronError| CliError::Generic(format!("Invalid cron expression '{}': {}", expression, error));
+    let classification = btu_cron::classify_cron(expression).map_err(invalid)?;
+    let description: String = btu_cron::describe_cron_with_semantics(expression, app_config.cron_dom_dow_semantics).map_err(invalid)?;
+    let mut lines: Vec<String> = vec![
+        format!("Cron '{}' has {} elements, and is read as '{}'.", expression.trim(), classification.elements, classification.cron7),
+        format!("It runs {}.", description),
+    ];
+    if let Some(notice) = classification.notice() {
+        lines.push(format!("NOTICE: {}", notice));
     }
+    Ok(lines)
+}
+
+fn cli_print_config(app_config: &AppConfig) -> Result<(), CliError> {
+    println!("{}", app_config);
+    Ok(())
+}
+
+
+fn cli_queue_job_immediately(app_config: &AppConfig, rq_job_id: &RqJobId, create_queue: bool) -> Result<(), CliError> {
+    // Given an existing RQ Job, push it immediately into Redis Queue.
+    // Reading the job first distinguishes "no such job" from "cannot reach Redis".
+    rq::read_job_by_id(app_config, rq_job_id)?;
+    let outcome = rq::enqueue_job_immediate(app_config, rq_job_id, create_queue)
+        .map_err(|error| CliError::Generic(format!("Error while attempting to queue job for execution: {}", error)))?;
+    println!("{}", outcome);
+    print_warnings(&outcome.warnings);
+    Ok(())
 }
 
 
-fn cli_queue_task_immediately(app_config: &AppConfig, btu_task_id: &str) -> () {
+/// The flags of 'queue-task-now'.
+#[cfg(feature = "sql")]
+struct QueueTaskOptions {
+    create_queue: bool,
+    override_policy: bool,
+    dry_run: bool,  // stop after building the RQ Job; write nothing to Redis
+}
+
+#[cfg(feature = "sql")]
+fn cli_queue_task_immediately(app_config: &AppConfig, btu_task_id: &TaskId, options: QueueTaskOptions) -> Result<(), CliError> {
     // 1. Create a Job, based on this Task.
-    let task: BtuTask = BtuTask::new_from_mysql(btu_task_id, app_config);
+    let task: BtuTask = BtuTask::try_new_from_mysql(btu_task_id, app_config)?;
     println!("Fetched task information from SQL: {}", task.task_key);
     println!("------\n{}\n------", task);
+    if let Some(reason) = task.status.rejection_reason() {
+        return Err(CliError::Generic(format!("Cannot queue BTU Task {}: {}", btu_task_id, reason)));
+    }
+    if let Some(warning) = task.status.warning() {
+        print_warnings(&[format!("The {}.", warning)]);
+    }
+    if let Err(policy_error) = task.check_function_policy(app_config) {
+        if !options.override_policy {
+            return Err(CliError::Generic(format!("{}  Use --override-policy to queue it anyway.", policy_error)));
+        }
+        print_warnings(&[format!("Policy overridden by operator: {}", policy_error)]);
+    }
 
     // 2. Create an RQ Job from that Task.
-    let rq_job: rq::RQJob = task.to_rq_job(app_config);
+    let rq_job: rq::RQJob = task.to_rq_job(app_config)?;
     println!("{}\n------", rq_job);
+    if options.dry_run {
+        for line in dry_run_lines(&rq_job, chrono::Utc::now()) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
 
     // 3. Save the new Job into Redis.
-    rq_job.save_to_redis(app_config);
+    rq_job.save_to_redis(app_config)
+        .map_err(|error| CliError::Connectivity(error.to_string()))?;
 
     // 4. Enqueue that job for immediate execution.
-    match rq::enqueue_job_immediate(&app_config, &rq_job.job_key_short) {
-        Ok(ok_message) => {
-            println!("Successfully enqueued: {}", ok_message);
+    let outcome = rq::enqueue_job_immediate(app_config, &rq_job.job_key_short, options.create_queue)
+        .map_err(|error| CliError::Generic(format!("Error while attempting to queue job for execution: {}", error)))?;
+    println!("Successfully enqueued: {}", outcome);
+    print_warnings(&outcome.warnings);
+    Ok(())
+}
+
+/// What 'queue-task-now --dry-run' prints after the RQ Job: the start of its pickled function, and the keys it would write.
+#[cfg(feature = "sql")]
+pub fn dry_run_lines(rq_job: &rq::RQJob, now: chrono::DateTime<chrono::Utc>) -> Vec<String> {
+    const PREVIEW_BYTES: usize = 32;
+    let preview: Vec<String> = rq_job.data.iter().take(PREVIEW_BYTES).map(|byte| format!("{:02X}", byte)).collect();
+    let mut lines: Vec<String> = vec![
+        format!("Pickled function: {} bytes, beginning {}{}", rq_job.data.len(), preview.join(" "),
+                if rq_job.data.len() > PREVIEW_BYTES { " ..." } else { "" }),
+        format!("Queue: {}    Timeout: {} seconds    Description: {}", rq_job.origin(), rq_job.timeout, rq_job.description),
+        "Redis keys that would be written:".to_owned(),
+    ];
+    lines.extend(rq::keys_written_by_enqueue(rq_job, now).into_iter().map(|key| format!("    {}", key)));
+    lines.push("DRY RUN - nothing was written.".to_owned());
+    lines
+}
+
+fn print_warnings(warnings: &[String]) {
+    for warning in warnings {
+        output::print_warning(warning);
+    }
+}
+
+
+fn cli_show_job_details(app_config: &AppConfig, job_id: &RqJobId, format: OutputFormat) -> Result<(), CliError> {
+	// println!("Attempting to fetch information about Job with ID = {}", job_id);
+    let job: rq::RQJob = rq::read_job_by_id(app_config, job_id)?;
+    match format {
+        OutputFormat::Human => println!("{}", job),
+        OutputFormat::Json => output::print_json(&serde_json::json!(job)),
+    }
+    Ok(())
+}
+
+
+fn cli_show_scheduled_jobs(app_config: &AppConfig, format: OutputFormat) -> Result<(), CliError> {
+    let tasks: scheduler::VecRQScheduledTask = scheduler::rq_get_scheduled_tasks(app_config)?.sort_by_id();
+    if format == OutputFormat::Json {
+        let unschedulable: Vec<scheduler::UnschedulableMarker> = scheduler::read_unschedulable_markers(app_config);
+        output::print_json(&scheduled_tasks_json(tasks.as_slice(), tasks.skipped(), &unschedulable));
+        return Ok(());
+    }
+    let time_zone: chrono_tz::Tz = app_config.tz().unwrap_or_else(|error| {
+        output::print_warning(&format!("Time zone '{}' is not valid ({}); showing times in UTC.", app_config.time_zone_string, error));
+        chrono_tz::UTC
+    });
+    println!("There are {} BTU Tasks scheduled for automatic execution.", tasks.len());
+    if tasks.skipped() > 0 {
+        output::print_warning(&format!("{} malformed entries in Redis were skipped.", tasks.skipped()));
+    }
+    for line in format_scheduled_tasks(tasks.as_slice(), &time_zone, chrono::Utc::now(), output::stdout_color()) {
+        println!("    {}", line);
+    }
+
+    let unschedulable: Vec<scheduler::UnschedulableMarker> = scheduler::read_unschedulable_markers(app_config);
+    if !unschedulable.is_empty() {
+        println!("There are {} enabled BTU Task Schedules that cannot be scheduled:", unschedulable.len());
+    }
+    for marker in &unschedulable {
+        let message: String = format!("[UNSCHEDULABLE] Task Schedule {} with cron '{}' ({}) since {}: {}",
+            marker.task_schedule_id, marker.cron_string,
+            btu_cron::describe_schedule(&marker.cron_string, btu_cron::cron_timezone_label(&marker.cron_timezone, app_config.cron_strings_are_utc), app_config.cron_dom_dow_semantics),
+            marker.detected_at, marker.reason);
+        println!("    {}", output::paint(&message, output::Color::Yellow, output::stdout_color()));
+    }
+    Ok(())
+}
+
+/// 'show-scheduled --json': the scheduled tasks (times in UTC), the count of malformed entries skipped, and the unschedulable markers.
+pub fn scheduled_tasks_json(tasks: &[scheduler::RQScheduledTask], skipped: usize, unschedulable: &[scheduler::UnschedulableMarker]) -> SerdeJsonValue {
+    serde_json::json!({ "scheduled": tasks, "skipped": skipped, "unschedulable": unschedulable })
+}
+
+/// One aligned line per scheduled task: its Task Schedule, and the next run in local time, relative to 'now', and in UTC.
+pub(crate) fn format_scheduled_tasks(tasks: &[scheduler::RQScheduledTask], time_zone: &chrono_tz::Tz, now: chrono::DateTime<chrono::Utc>,
+                                     color: bool) -> Vec<String> {
+    if tasks.is_empty() {
+        return Vec::new();
+    }
+    let rows: Vec<Vec<String>> = tasks.iter().map(|task| vec![
+        task.task_schedule_id.to_string(),
+        task.next_datetime_utc.with_timezone(time_zone).format("%Y-%m-%d %H:%M:%S %Z").to_string(),
+        btu_scheduler::timeutil::compact_instant(task.next_datetime_utc, now),
+        task.next_datetime_utc.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    ]).collect();
+    output::table(&["TASK SCHEDULE", "NEXT RUN (LOCAL)", "WHEN", "NEXT RUN (UTC)"], &rows, &[Some(output::Color::Cyan), None, None, None], color)
+}
+
+
+fn cli_circuit(app_config: &AppConfig, reset: Option<&TaskScheduleId>) -> Result<(), CliError> {
+    let mut redis_conn = rq::get_redis_connection(app_config, false)
+        .ok_or_else(|| CliError::Connectivity(format!("Unable to connect to Redis at {}:{}", app_config.rq_host, app_config.rq_port)))?;
+    if let Some(schedule_id) = reset {
+        if !circuit_breaker::reset_by_operator(app_config, &mut redis_conn, schedule_id) {
+            return Err(CliError::NotFound(format!("Task Schedule {} has no open circuit, and no failures counted.", schedule_id)));
         }
-        Err(err_message) => {
-            println!("Error while attempting to queue job for execution: {}", err_message);
+        output::print_success(&format!("Reset the circuit breaker of Task Schedule {}.", schedule_id));
+        // Ask the daemon to schedule it now, instead of at its next full refresh.
+        match send_daemon_request(app_config, "create_task_schedule", Some(schedule_id.as_str())) {
+            Ok(_) => println!("The daemon is scheduling it again."),
+            Err(_) => println!("The daemon is not reachable; it will schedule it again at its next full refresh."),
         }
+        return Ok(());
+    }
+
+    if app_config.max_consecutive_failures.unwrap_or_default() == 0 {
+        output::print_warning("The circuit breaker is disabled ('max_consecutive_failures' is 0).");
+    }
+    let open: Vec<circuit_breaker::OpenCircuit> = circuit_breaker::open_circuits(&mut redis_conn);
+    if open.is_empty() {
+        println!("No Task Schedule's circuit breaker is open.");
+        return Ok(());
+    }
+    println!("{} Task Schedules are not scheduled, because they failed too many times in a row:", open.len());
+    let rows: Vec<Vec<String>> = open.iter().map(|circuit| vec![
+        circuit.task_schedule_id.to_string(),
+        circuit.failures.map(|failures| failures.to_string()).unwrap_or("(expired)".to_owned()),
+    ]).collect();
+    for line in output::table(&["TASK SCHEDULE", "FAILURES"], &rows, &[Some(output::Color::Red), None], output::stdout_color()) {
+        println!("    {}", line);
     }
+    println!("Reset one with 'btu circuit --reset <SCHEDULE_ID>'.");
+    Ok(())
+}
+
 
-    ()
+fn cli_list_queues(app_config: &AppConfig, format: OutputFormat) -> Result<(), CliError> {
+    let queues: Vec<QueueSummary> = rq::get_queue_summary(app_config)?;
+    if format == OutputFormat::Json {
+        output::print_json(&serde_json::json!(queues));
+        return Ok(());
+    }
+    if queues.is_empty() {
+        println!("No RQ queues were found (Redis at {}:{} is reachable, and '{}' is empty).",
+                 app_config.rq_host, app_config.rq_port, rq::RQ_KEY_QUEUES);
+        return Ok(());
+    }
+    for line in format_queue_summary(&queues, output::stdout_color()) {
+        println!("{}", line);
+    }
+    Ok(())
 }
 
+/// A table of RQ queues: pending jobs, and listening workers ('?' when the workers could not be read).
+pub fn format_queue_summary(queues: &[QueueSummary], color: bool) -> Vec<String> {
+    let rows: Vec<Vec<String>> = queues.iter().map(|queue| vec![
+        queue.queue_name.clone(),
+        queue.pending_jobs.to_string(),
+        queue.workers.map_or("?".to_owned(), |workers| workers.to_string()),
+    ]).collect();
+    output::table(&["QUEUE", "PENDING", "WORKERS"], &rows, &[Some(output::Color::Cyan)], color)
+}
 
-fn cli_show_job_details(app_config: &AppConfig, job_id: &str) -> () {
-	// println!("Attempting to fetch information about Job with ID = {}", job_id);
-    match rq::read_job_by_id(app_config, job_id) {
-        Ok(ok_message) => {
-            println!("{}", ok_message);
+
+fn cli_queue_stats(app_config: &AppConfig, days: u32) -> Result<(), CliError> {
+    let report: QueueStatsReport = scheduler::get_queue_stats(app_config, days)?;
+    if report.queues.is_empty() {
+        println!("BTU has not enqueued any jobs in the last {} days.", days);
+        return Ok(());
+    }
+    println!("Jobs enqueued by BTU from {} to {} (UTC):", report.from, report.to);
+    for line in format_queue_stats(&report, chrono::Utc::now(), output::stdout_color()) {
+        println!("    {}", line);
+    }
+    Ok(())
+}
+
+/// A table of enqueues per queue, with a total row.  Ages are relative to 'now'.
+pub fn format_queue_stats(report: &QueueStatsReport, now: chrono::DateTime<chrono::Utc>, color: bool) -> Vec<String> {
+    let mut rows: Vec<Vec<String>> = report.queues.iter().map(|queue| vec![
+        queue.queue_name.clone(),
+        queue.total.to_string(),
+        format!("{:.1}", report.daily_average(queue)),
+        queue.last_enqueue.map_or("never".to_owned(), |last| btu_scheduler::timeutil::humanize_instant(last, now)),
+    ]).collect();
+    rows.push(vec![
+        "(total)".to_owned(),
+        report.total.to_string(),
+        format!("{:.1}", report.total as f64 / f64::from(report.days.max(1))),
+    ]);
+    output::table(&["QUEUE", "ENQUEUED", "PER DAY", "LAST ENQUEUE"], &rows, &[Some(output::Color::Cyan)], color)
+}
+
+
+fn cli_snooze(app_config: &AppConfig, schedule_id: &TaskScheduleId, target: RescheduleTarget) -> Result<(), CliError> {
+    let mut redis_conn = rq::get_redis_connection(app_config, false).ok_or(RescheduleError::NoConnection)?;
+    let rescheduled = next_run_override::reschedule_by_operator(app_config, &mut redis_conn, schedule_id, target)?;
+    let time_zone: chrono_tz::Tz = app_config.tz().unwrap_or(chrono_tz::UTC);
+    output::print_success(&format!("Task Schedule {} now runs next at {} ({}).", schedule_id,
+                                   rescheduled.next.with_timezone(&time_zone).format("%Y-%m-%d %H:%M:%S %Z"),
+                                   rescheduled.next.format("%Y-%m-%d %H:%M:%S UTC")));
+    println!("It was due at {}.  After this run, it follows its cron expression again.",
+             rescheduled.previous.with_timezone(&time_zone).format("%Y-%m-%d %H:%M:%S %Z"));
+    if rescheduled.replaced > 1 {
+        println!("{} upcoming runs were replaced by this one.", rescheduled.replaced);
+    }
+    Ok(())
+}
+
+
+fn cli_show_history(app_config: &AppConfig, schedule_id: &TaskScheduleId) -> Result<(), CliError> {
+    let history: Vec<RunRecord> = scheduler::get_task_run_history(app_config, schedule_id.as_str())?;
+    if history.is_empty() {
+        println!("No runs of Task Schedule {} are recorded.", schedule_id);
+        return Ok(());
+    }
+    println!("Recent runs of Task Schedule {}, newest first:", schedule_id);
+    for line in format_run_history(&history, app_config.tz().unwrap_or(chrono_tz::UTC), output::stdout_color()) {
+        println!("    {}", line);
+    }
+    Ok(())
+}
+
+/// A table of runs, with times shown in 'time_zone'.  A time that cannot be parsed is shown as stored.
+pub fn format_run_history(history: &[RunRecord], time_zone: chrono_tz::Tz, color: bool) -> Vec<String> {
+    let local = |utc: Option<chrono::DateTime<chrono::Utc>>, stored: &str| {
+        utc.map_or(stored.to_owned(), |utc| utc.with_timezone(&time_zone).format("%Y-%m-%d %H:%M:%S %Z").to_string())
+    };
+    let rows: Vec<Vec<String>> = history.iter().map(|record| vec![
+        local(record.at_utc(), &record.at),
+        local(record.run_at_utc(), &record.run_at),
+        record.outcome.to_string(),
+        record.job_id.as_ref().map_or("-".to_owned(), ToString::to_string),
+        record.detail.clone().unwrap_or_default(),
+    ]).collect();
+    output::table(&["HANDLED AT", "SCHEDULED FOR", "OUTCOME", "RQ JOB", "DETAIL"], &rows, &[None, None, Some(output::Color::Cyan)], color)
+}
+
+
+fn cli_show_internal_queue(app_config: &AppConfig) -> Result<(), CliError> {
+    let reply: String = send_daemon_request(app_config, "list_internal_queue", None)?;
+    let reply: SerdeJsonValue = serde_json::from_str(&reply)
+        .map_err(|_| CliError::Generic(format!("Unexpected reply from the daemon: {}", reply)))?;
+    for line in format_internal_queue(&reply) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn cli_daemon_status(app_config: &AppConfig) -> Result<(), CliError> {
+    let reply: String = send_daemon_request(app_config, "status", None)?;
+    let reply: SerdeJsonValue = serde_json::from_str(&reply)
+        .map_err(|_| CliError::Generic(format!("Unexpected reply from the daemon: {}", reply)))?;
+    for line in format_daemon_status(&reply, chrono::Utc::now(), output::stdout_color()) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// The daemon's reply to 'status', as lines of text.  Each time is followed by how long ago it was.
+pub fn format_daemon_status(reply: &SerdeJsonValue, now: chrono::DateTime<chrono::Utc>, color: bool) -> Vec<String> {
+    let when = |value: &SerdeJsonValue| -> String {
+        match value.as_str() {
+            Some(text) => match chrono::DateTime::parse_from_rfc3339(text) {
+                Ok(at) => format!("{} ({})", text, compact_instant(at.with_timezone(&chrono::Utc), now)),
+                Err(_) => text.to_owned(),
+            },
+            None => "never".to_owned(),
         }
-        Err(err_message) => {
-            println!("{}", err_message);
+    };
+    let connection = |value: &SerdeJsonValue| -> String {
+        match value["succeeded"].as_bool() {
+            Some(true) => format!("{}, at {}", output::paint("connected", output::Color::Green, color), when(&value["at"])),
+            Some(false) => format!("{}, at {}", output::paint("unreachable", output::Color::Red, color), when(&value["at"])),
+            None => "not tried yet".to_owned(),
         }
-    }
+    };
+    let started_at: String = reply["uptime_secs"].as_i64()
+        .map_or_else(|| "?".to_owned(), |uptime_secs| compact_instant(now - chrono::Duration::seconds(uptime_secs), now));
+    let queue_length: String = reply["internal_queue_length"].as_u64()
+        .map_or_else(|| "unknown (the queue is locked)".to_owned(), |length| format!("{} entries", length));
+    vec![
+        format!("Daemon version       {}", reply["daemon_version"].as_str().unwrap_or("?")),
+        format!("Started              {}", started_at),
+        format!("Internal queue       {}", queue_length),
+        format!("Last full refresh    {}", when(&reply["last_full_refresh"])),
+        format!("Last scheduler pass  {}", when(&reply["last_scheduler_pass"])),
+        format!("Jobs enqueued        {}", reply["jobs_enqueued_since_start"].as_u64().unwrap_or_default()),
+        format!("Redis                {}", connection(&reply["redis"])),
+        format!("MariaDB              {}", connection(&reply["mysql"])),
+    ]
 }
 
+fn cli_daemon_config(app_config: &AppConfig) -> Result<(), CliError> {
+    let reply: String = send_daemon_request(app_config, "get_config", None)?;
+    let reply: SerdeJsonValue = serde_json::from_str(&reply)
+        .map_err(|_| CliError::Generic(format!("Unexpected reply from the daemon: {}", reply)))?;
+    println!("{}", serde_json::to_string_pretty(&reply).unwrap_or_else(|_| reply.to_string()));
+    Ok(())
+}
+
+/// The daemon's reply to 'list_internal_queue', as lines of text: a header, then one line per entry.
+pub fn format_internal_queue(reply: &SerdeJsonValue) -> Vec<String> {
+    let entries: &[SerdeJsonValue] = reply["entries"].as_array().map_or(&[], Vec::as_slice);
+    let total: u64 = reply["total"].as_u64().unwrap_or(entries.len() as u64);
+    let mut lines: Vec<String> = vec![match (total, entries.len() as u64) {
+        (0, _) => "The internal queue is empty.".to_owned(),
+        (total, shown) if shown < total => format!("The internal queue holds {} entries (showing the first {}):", total, shown),
+        (total, _) => format!("The internal queue holds {} entries:", total),
+    }];
+    lines.extend(entries.iter().map(|entry| {
+        format!("    {:<6}  {:<16}  {}  {}",
+                entry["priority"].as_str().unwrap_or("?"),
+                entry["source"].as_str().unwrap_or("?"),
+                entry["enqueued_at"].as_str().unwrap_or("?"),
+                entry["schedule_id"].as_str().unwrap_or("?"))
+    }));
+    lines
+}
 
-fn cli_show_scheduled_jobs(app_config: &AppConfig) {
-	scheduler::rq_print_scheduled_tasks(app_config, true);
+/// Send one request to the daemon's Unix Domain Socket, and return the reply.  The daemon closes the connection after replying.
+fn send_daemon_request(app_config: &AppConfig, request_type: &str, request_content: Option<&str>) -> Result<String, CliError> {
+    let mut stream = UnixStream::connect(&app_config.socket_path).map_err(|error| {
+        CliError::Connectivity(format!("Cannot connect to the daemon's socket '{}' (is the daemon running?): {}", app_config.socket_path, error))
+    })?;
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(30)))?;
+    let message = serde_json::json!({ "request_type": request_type, "request_content": request_content });
+    socket_frame::write_frame(&mut stream, message.to_string().as_bytes())?;
+    let reply: Vec<u8> = socket_frame::read_frame(&mut stream, socket_frame::MAX_REPLY_BYTES).map_err(|error| match error {
+        FrameError::Io { source } => CliError::from(source),
+        too_large => CliError::Generic(too_large.to_string()),
+    })?;
+    Ok(String::from_utf8_lossy(&reply).into_owned())
 }
@@ -0,0 +1,135 @@
+// output.rs
+
+/*
+    Terminal styling for the CLI.
+
+    Color is used only when the stream is a terminal, the NO_COLOR environment variable is unset (https://no-color.org),
+    and '--no-color' was not passed.  stdout and stderr are decided separately; 'btu list-jobs | less' still shows
+    red errors on the terminal.
+
+    JSON output ('events', 'daemon-config', and the listing commands with '--json') is never colored.
+*/
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(false);
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Yellow,
+    Green,
+    Cyan,
+}
+
+impl Color {
+    fn ansi_code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Yellow => "33",
+            Color::Green => "32",
+            Color::Cyan => "36",
+        }
+    }
+}
+
+/// How the listing commands print: aligned tables for people, or one JSON document on stdout, for scripts ('--json').
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn from_json_flag(json: bool) -> Self {
+        if json { OutputFormat::Json } else { OutputFormat::Human }
+    }
+}
+
+/// Print a JSON document, pretty-printed, on stdout.
+pub fn print_json(value: &serde_json::Value) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string()));
+}
+
+/// Should a stream be colored?  'no_color_variable' is the value of NO_COLOR; any non-empty value turns color off.
+pub fn color_enabled(no_color_flag: bool, no_color_variable: Option<&str>, is_terminal: bool) -> bool {
+    !no_color_flag && no_color_variable.unwrap_or("").is_empty() && is_terminal
+}
+
+/// Decide, once at startup, whether stdout and stderr are colored.
+pub fn init(no_color_flag: bool) {
+    let no_color_variable: Option<String> = std::env::var("NO_COLOR").ok();
+    STDOUT_COLOR.store(color_enabled(no_color_flag, no_color_variable.as_deref(), atty::is(atty::Stream::Stdout)), Ordering::Relaxed);
+    STDERR_COLOR.store(color_enabled(no_color_flag, no_color_variable.as_deref(), atty::is(atty::Stream::Stderr)), Ordering::Relaxed);
+}
+
+pub fn stdout_color() -> bool {
+    STDOUT_COLOR.load(Ordering::Relaxed)
+}
+
+/// The text wrapped in ANSI color codes; or unchanged, when 'enabled' is false.
+pub fn paint(text: &str, color: Color, enabled: bool) -> String {
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", color.ansi_code(), text)
+    } else {
+        text.to_owned()
+    }
+}
+
+/// An error, in red on stderr.
+pub fn print_error(message: &str) {
+    eprintln!("{}", paint(message, Color::Red, STDERR_COLOR.load(Ordering::Relaxed)));
+}
+
+/// A warning, in yellow on stderr, so it stands out from (and does not pollute) normal output.
+pub fn print_warning(message: &str) {
+    eprintln!("{}", paint(&format!("\u{26A0} Warning: {}", message), Color::Yellow, STDERR_COLOR.load(Ordering::Relaxed)));
+}
+
+/// A line on stdout, led by a green check mark.
+pub fn print_success(message: &str) {
+    println!("{} {}", paint("\u{2714}", Color::Green, stdout_color()), message);
+}
+
+/// The width of each column: the most characters in any row's cell.  Rows may have fewer cells than others.
+pub fn column_widths(rows: &[Vec<String>]) -> Vec<usize> {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (index, cell) in row.iter().enumerate() {
+            let width: usize = cell.chars().count();
+            match widths.get_mut(index) {
+                Some(existing) => *existing = (*existing).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+    widths
+}
+
+/**
+  A header and rows of cells, as lines with aligned columns; separated by two spaces, with no trailing whitespace.\
+  Widths are measured before coloring, so escape codes never upset the alignment.  'colors' applies to each column
+  of the rows in order; the header is never colored.  An empty header prints no header line.
+*/
+pub fn table(header: &[&str], rows: &[Vec<String>], colors: &[Option<Color>], color: bool) -> Vec<String> {
+    let mut all_rows: Vec<Vec<String>> = Vec::with_capacity(rows.len() + 1);
+    if !header.is_empty() {
+        all_rows.push(header.iter().map(|cell| (*cell).to_owned()).collect());
+    }
+    all_rows.extend(rows.iter().cloned());
+    let widths: Vec<usize> = column_widths(&all_rows);
+    let header_rows: usize = all_rows.len() - rows.len();
+
+    all_rows.iter().enumerate().map(|(row_index, row)| {
+        let cells: Vec<String> = row.iter().enumerate().map(|(index, cell)| {
+            let padding: usize = if index + 1 == row.len() { 0 } else { widths[index] - cell.chars().count() };
+            let painted: String = match colors.get(index).copied().flatten() {
+                Some(column_color) if row_index >= header_rows => paint(cell, column_color, color),
+                _ => cell.clone(),
+            };
+            format!("{}{}", painted, " ".repeat(padding))
+        }).collect();
+        cells.join("  ")
+    }).collect()
+}
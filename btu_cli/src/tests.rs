@@ -0,0 +1,432 @@
+/*
+	NOTE: To run tests and display STDOUT, type the following in the shell:
+	
+		cargo test -- --nocapture
+
+*/
+
+#[cfg(test)]
+mod tests {
+
+	use btu_scheduler::config::AppConfig;
+	use crate::CliError;
+	use crate::output::OutputFormat;
+
+	// A configuration whose Redis port is closed; nothing here connects to a real database.
+	static UNREACHABLE_TOML: &str = r#"
+		environment_name = "Unit Test"
+		full_refresh_internal_secs = 900
+		scheduler_polling_interval = 60
+		time_zone_string = "America/Los_Angeles"
+		tracing_level = "INFO"
+		startup_without_database_connections = true
+		email_when_queuing = false
+		mysql_user = "root"
+		mysql_password = "password"
+		mysql_host = "127.0.0.1"
+		mysql_port = 1
+		mysql_database = "test_db"
+		rq_host = "127.0.0.1"
+		rq_port = 1
+		socket_path = "/tmp/btu_scheduler_test.sock"
+		socket_file_group_owner = "frappe"
+		webserver_ip = "127.0.0.1"
+		webserver_port = 1
+		webserver_token = "token abc:def"
+	"#;
+
+	fn unreachable_app_config() -> AppConfig {
+		AppConfig::new_from_toml_string(UNREACHABLE_TOML).unwrap()
+	}
+
+	#[test]
+	fn test_exit_code_mapping() {
+		assert_eq!(CliError::Generic("x".to_owned()).exit_code(), 1);
+		assert_eq!(CliError::Configuration("x".to_owned()).exit_code(), 2);
+		assert_eq!(CliError::Connectivity("x".to_owned()).exit_code(), 3);
+		assert_eq!(CliError::NotFound("x".to_owned()).exit_code(), 4);
+	}
+
+	#[test]
+	fn test_io_error_kinds_map_to_cli_errors() {
+		use std::io::{Error, ErrorKind};
+
+		assert_eq!(CliError::from(Error::new(ErrorKind::NotFound, "no job")).exit_code(), 4);
+		assert_eq!(CliError::from(Error::new(ErrorKind::NotConnected, "no redis")).exit_code(), 3);
+		assert_eq!(CliError::from(Error::new(ErrorKind::InvalidData, "other")).exit_code(), 1);
+	}
+
+	/// When Redis cannot be reached, 'show-job' and 'queue-job-now' fail with the connectivity code, instead of panicking.
+	#[test]
+	fn test_job_subcommands_without_redis() {
+		let app_config = unreachable_app_config();
+
+		let result = crate::cli_show_job_details(&app_config, &"no-such-job".into(), OutputFormat::Human);
+		assert!(matches!(result, Err(CliError::Connectivity(_))), "{:?}", result);
+
+		let result = crate::cli_queue_job_immediately(&app_config, &"no-such-job".into(), false);
+		assert_eq!(result.unwrap_err().exit_code(), 3);
+
+		let result = crate::cli_check(&app_config);
+		assert_eq!(result.unwrap_err().exit_code(), 3);
+
+		let result = crate::cli_list_jobs(&app_config, Some(100), OutputFormat::Human);
+		assert_eq!(result.unwrap_err().exit_code(), 3);
+		let result = crate::cli_list_jobs(&app_config, None, OutputFormat::Human);
+		let error: CliError = result.unwrap_err();
+		assert_eq!(error.exit_code(), 3);
+		// An unreachable Redis is never reported as an empty one.
+		assert!(!error.to_string().contains("No jobs were found"), "{}", error);
+		assert!(crate::no_jobs_message(&app_config).contains("127.0.0.1:1 is reachable, and has no 'rq:job:*' keys"));
+	}
+
+	#[cfg(feature = "sql")]
+	#[test]
+	fn test_import_state_missing_file() {
+		let result = crate::cli_import_state(&unreachable_app_config(), "/nonexistent/btu_state.json", false);
+		assert_eq!(result.unwrap_err().exit_code(), 4);
+	}
+
+	#[test]
+	fn test_ping_without_web_server() {
+		let result = crate::cli_ping_frappe_web(&unreachable_app_config(), false);
+		assert!(matches!(result, Err(CliError::Connectivity(_))), "{:?}", result);
+	}
+
+	#[test]
+	fn test_humanize_duration() {
+		use chrono::Duration;
+		assert_eq!(btu_scheduler::timeutil::humanize_duration(Duration::seconds(1)), "1 second");
+		assert_eq!(btu_scheduler::timeutil::humanize_duration(Duration::seconds(14 * 60 + 59)), "14 minutes");
+		assert_eq!(btu_scheduler::timeutil::humanize_duration(Duration::hours(-3)), "3 hours ago");
+		assert_eq!(btu_scheduler::timeutil::humanize_duration(Duration::days(2)), "2 days");
+	}
+
+	#[test]
+	fn test_format_internal_queue() {
+		let reply = serde_json::json!({
+			"total": 1500,
+			"truncated": true,
+			"entries": [
+				{ "schedule_id": "TS-0002", "source": "socket", "priority": "high", "enqueued_at": "2022-12-02T08:00:05Z" },
+				{ "schedule_id": "TS-0001", "source": "full_refresh", "priority": "normal", "enqueued_at": "2022-12-02T08:00:00Z" },
+			],
+		});
+		let lines: Vec<String> = crate::format_internal_queue(&reply);
+		assert_eq!(lines[0], "The internal queue holds 1500 entries (showing the first 2):");
+		assert_eq!(lines[1], "    high    socket            2022-12-02T08:00:05Z  TS-0002");
+		assert!(lines[2].ends_with("full_refresh      2022-12-02T08:00:00Z  TS-0001"), "{}", lines[2]);
+
+		let empty = serde_json::json!({ "total": 0, "truncated": false, "entries": [] });
+		assert_eq!(crate::format_internal_queue(&empty), vec!["The internal queue is empty."]);
+	}
+
+	#[test]
+	fn test_format_daemon_status() {
+		use chrono::TimeZone;
+
+		let now = chrono::Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 0).unwrap();
+		let reply = serde_json::json!({
+			"daemon_version": "0.4.2",
+			"uptime_secs": 2 * 86_400 + 3 * 3600,
+			"internal_queue_length": 4,
+			"last_full_refresh": "2022-12-02T07:55:00Z",
+			"last_scheduler_pass": null,
+			"jobs_enqueued_since_start": 123,
+			"redis": { "succeeded": true, "at": "2022-12-02T07:59:50Z" },
+			"mysql": { "succeeded": false, "at": "2022-12-02T07:59:00Z" },
+		});
+		assert_eq!(crate::format_daemon_status(&reply, now, false), vec![
+			"Daemon version       0.4.2",
+			"Started              2d 3h ago",
+			"Internal queue       4 entries",
+			"Last full refresh    2022-12-02T07:55:00Z (5m ago)",
+			"Last scheduler pass  never",
+			"Jobs enqueued        123",
+			"Redis                connected, at 2022-12-02T07:59:50Z (10s ago)",
+			"MariaDB              unreachable, at 2022-12-02T07:59:00Z (1m ago)",
+		]);
+		let lines: Vec<String> = crate::format_daemon_status(&serde_json::json!({ "redis": null, "mysql": null }), now, true);
+		assert_eq!(lines[2], "Internal queue       unknown (the queue is locked)");
+		assert_eq!(lines[6], "Redis                not tried yet");
+		assert!(crate::format_daemon_status(&reply, now, true)[7].contains("\x1b[31munreachable\x1b[0m"));
+	}
+
+	#[cfg(feature = "sql")]
+	#[test]
+	fn test_format_task_list() {
+		use btu_scheduler::task::{TaskListOptions, TaskPage, TaskSummary};
+
+		let task = |name: &str, description: &str| TaskSummary { task_key: name.into(), desc_short: description.to_owned() };
+		let page = TaskPage { tasks: vec![task("TASK-0101", "Nightly Invoice Sync"), task("TASK-0102", "Ping")], total: 2031 };
+		let options = TaskListOptions { limit: Some(2), offset: 100, filter: None };
+		assert_eq!(crate::format_task_list(&page, &options),
+		           vec!["Task TASK-0101 : Nightly Invoice Sync", "Task TASK-0102 : Ping", "", "Showing 2 of 2,031, from offset 100."]);
+		let options = TaskListOptions { filter: Some("Sync".to_owned()), ..Default::default() };
+		assert_eq!(crate::format_task_list(&page, &options).last().unwrap(), "Showing 2 of 2,031, matching 'Sync'.");
+
+		// With nothing to show, say why.
+		let empty = TaskPage::default();
+		assert_eq!(crate::format_task_list(&empty, &TaskListOptions::default()), vec!["No BTU Tasks are defined in the MariaDB database."]);
+		assert_eq!(crate::format_task_list(&empty, &TaskListOptions { filter: Some("Payroll".to_owned()), ..Default::default() }),
+		           vec!["No BTU Tasks match 'Payroll'."]);
+		assert_eq!(crate::format_task_list(&TaskPage { tasks: vec![], total: 2031 }, &TaskListOptions { offset: 3000, ..Default::default() }),
+		           vec!["No BTU Tasks at offset 3000; there are 2,031."]);
+
+		assert_eq!((crate::thousands(0), crate::thousands(999), crate::thousands(2031), crate::thousands(1234567)),
+		           ("0".to_owned(), "999".to_owned(), "2,031".to_owned(), "1,234,567".to_owned()));
+	}
+
+	#[cfg(feature = "sql")]
+	#[test]
+	fn test_format_findings() {
+		use btu_scheduler::diagnose::{DiagnosticFinding, Severity};
+
+		let findings = vec![
+			DiagnosticFinding { check: "schedule", severity: Severity::Pass, explanation: "Task Schedule TS-1 exists, and is enabled.".to_owned() },
+			DiagnosticFinding { check: "circuit breaker", severity: Severity::Fail, explanation: "Open.".to_owned() },
+		];
+		assert_eq!(crate::format_findings(&findings, false), vec![
+			"PASS  schedule         Task Schedule TS-1 exists, and is enabled.",
+			"FAIL  circuit breaker  Open.",
+		]);
+		assert!(crate::format_findings(&findings, true)[1].starts_with("\x1b[31mFAIL\x1b[0m  circuit breaker"));
+
+		// The daemon's freshness comes from its 'health' reply.
+		let reply = r#"{"daemon_version": "0.3.8", "last_cycle": {"started_at": "2022-12-02T08:00:00Z", "enqueued": 0}}"#;
+		assert_eq!(crate::last_cycle_started_at(reply).unwrap().unwrap().to_rfc3339(), "2022-12-02T08:00:00+00:00");
+		assert_eq!(crate::last_cycle_started_at(r#"{"last_cycle": null}"#), Ok(None));
+		assert!(crate::last_cycle_started_at("pong").is_err());
+	}
+
+	#[test]
+	fn test_color_enabled() {
+		use crate::output::color_enabled;
+
+		assert!(color_enabled(false, None, true));
+		assert!(color_enabled(false, Some(""), true), "an empty NO_COLOR does not disable color");
+		assert!(!color_enabled(false, Some("1"), true));
+		assert!(!color_enabled(true, None, true));
+		assert!(!color_enabled(false, None, false), "never color a pipe or a file");
+
+		assert_eq!(crate::output::paint("ok", crate::output::Color::Green, true), "\x1b[32mok\x1b[0m");
+		assert_eq!(crate::output::paint("ok", crate::output::Color::Green, false), "ok");
+	}
+
+	#[test]
+	fn test_table_alignment() {
+		use crate::output::{Color, column_widths, table};
+
+		let rows: Vec<Vec<String>> = vec![
+			vec!["TS-0001".to_owned(), "Zürich".to_owned(), "x".to_owned()],
+			vec!["TS-12".to_owned(), "Los Angeles".to_owned()],
+		];
+		// Widths count characters, not bytes; rows may be ragged.
+		assert_eq!(column_widths(&rows), vec![7, 11, 1]);
+
+		let lines: Vec<String> = table(&["ID", "CITY", "N"], &rows, &[Some(Color::Cyan)], false);
+		assert_eq!(lines, vec![
+			"ID       CITY         N",
+			"TS-0001  Zürich       x",
+			"TS-12    Los Angeles",
+		]);
+
+		// Color codes wrap the cell, and never change the padding.  The header stays plain.
+		let colored: Vec<String> = table(&["ID", "CITY"], &rows[1..], &[Some(Color::Cyan)], true);
+		assert_eq!(colored, vec!["ID     CITY", "\x1b[36mTS-12\x1b[0m  Los Angeles"]);
+	}
+
+	#[test]
+	fn test_format_scheduled_tasks() {
+		use btu_scheduler::scheduler::RQScheduledTask;
+		use chrono::{TimeZone, Utc};
+
+		let task = |id: &str, unix: i64| RQScheduledTask {
+			task_schedule_id: id.into(), next_datetime_unix: unix, next_datetime_utc: Utc.timestamp_opt(unix, 0).unwrap()
+		};
+		let tasks = vec![task("TS-000001", 1_700_000_000), task("TS-2", 1_700_003_600)];
+		let now = Utc.timestamp_opt(1_700_000_000 - 3 * 3600 - 12 * 60 - 30, 0).unwrap();
+		let lines: Vec<String> = crate::format_scheduled_tasks(&tasks, &chrono_tz::America::Los_Angeles, now, false);
+		assert_eq!(lines, vec![
+			"TASK SCHEDULE  NEXT RUN (LOCAL)         WHEN       NEXT RUN (UTC)",
+			"TS-000001      2023-11-14 14:13:20 PST  in 3h 12m  2023-11-14 22:13:20 UTC",
+			"TS-2           2023-11-14 15:13:20 PST  in 4h 12m  2023-11-14 23:13:20 UTC",
+		]);
+		assert!(crate::format_scheduled_tasks(&[], &chrono_tz::UTC, now, false).is_empty());
+	}
+
+	/// '--json' output: one document on stdout, with the same content as the tables, and no prose around it.
+	#[test]
+	fn test_json_output() {
+		use btu_scheduler::scheduler::{RQScheduledTask, UnschedulableMarker};
+		use chrono::{TimeZone, Utc};
+		use serde_json::json;
+
+		assert_eq!(OutputFormat::from_json_flag(true), OutputFormat::Json);
+		assert_eq!(OutputFormat::from_json_flag(false), OutputFormat::Human);
+
+		assert_eq!(crate::job_list_json(&["abc".to_owned()], true), json!({ "jobs": ["abc"], "has_more": true }));
+		assert_eq!(crate::job_list_json(&[], false), json!({ "jobs": [], "has_more": false }));
+
+		let task = RQScheduledTask {
+			task_schedule_id: "TS-1".into(), next_datetime_unix: 1_700_000_000, next_datetime_utc: Utc.timestamp_opt(1_700_000_000, 0).unwrap()
+		};
+		let marker = UnschedulableMarker {
+			task_schedule_id: "TS-2".into(), cron_string: "0 0 30 2 *".to_owned(), cron_timezone: "UTC".to_owned(),
+			reason: "no such day".to_owned(), detected_at: "2023-11-14T22:13:20Z".to_owned(),
+		};
+		let document = crate::scheduled_tasks_json(&[task], 2, &[marker]);
+		assert_eq!(document["scheduled"], json!([{
+			"task_schedule_id": "TS-1", "next_datetime_unix": 1_700_000_000, "next_datetime_utc": "2023-11-14T22:13:20Z"
+		}]));
+		assert_eq!(document["skipped"], json!(2));
+		assert_eq!(document["unschedulable"][0]["task_schedule_id"], json!("TS-2"));
+
+		// A Job's pickled 'data' and 'meta' appear only as lengths; 'extra_fields' only as names.
+		let mut job = btu_scheduler::rq::RQJob::new_with_defaults();
+		job.data = vec![0x80, 0x04, 0x95];
+		job.set_correlation_id(&"request-1".into());
+		let job_json = json!(job);
+		assert_eq!(job_json["job_key_short"], json!(job.job_key_short.as_str()));
+		assert_eq!(job_json["data_length"], json!(3));
+		assert_eq!(job_json["meta_length"], json!(0));
+		assert_eq!(job_json["argument_overrides"], json!(null));
+		assert_eq!(job_json["correlation_id"], json!("request-1"));
+		assert_eq!(job_json["extra_fields"], json!(["btu_correlation_id"]));
+		assert!(job_json.get("data").is_none());
+
+		// Failures are still reported through the exit code, never as JSON.
+		assert_eq!(crate::cli_list_queues(&unreachable_app_config(), OutputFormat::Json).unwrap_err().exit_code(), 3);
+		assert_eq!(crate::cli_list_jobs(&unreachable_app_config(), None, OutputFormat::Json).unwrap_err().exit_code(), 3);
+	}
+
+	#[test]
+	fn test_format_queue_stats() {
+		use std::collections::HashMap;
+		use chrono::{NaiveDate, TimeZone, Utc};
+		use btu_scheduler::queue_stats::aggregate;
+
+		let today = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+		let buckets = vec![(today, [("default".to_owned(), "12".to_owned()), ("long".to_owned(), "2".to_owned())].into_iter().collect())];
+		let last_enqueue: HashMap<String, String> = [("default".to_owned(), "2024-03-10T11:45:00Z".to_owned())].into_iter().collect();
+		let report = aggregate(today, 7, &buckets, &last_enqueue);
+		let lines = crate::format_queue_stats(&report, Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap(), false);
+		assert_eq!(lines, vec![
+			"QUEUE    ENQUEUED  PER DAY  LAST ENQUEUE",
+			"default  12        1.7      15 minutes ago",
+			"long     2         0.3      never",
+			"(total)  14        2.0",
+		]);
+	}
+
+	#[test]
+	fn test_format_run_history() {
+		use chrono::{TimeZone, Utc};
+		use btu_scheduler::ids::RqJobId;
+		use btu_scheduler::run_history::{RunOutcome, RunRecord};
+
+		let run_at = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+		let mut enqueued = RunRecord::new(RunOutcome::Enqueued, run_at).job(&RqJobId::new("JOB-1").unwrap());
+		enqueued.at = "2024-03-10T12:00:01Z".to_owned();
+		let mut skipped = RunRecord::new(RunOutcome::Skipped, run_at).detail("circuit_open");
+		skipped.at = "garbled".to_owned();
+		let lines = crate::format_run_history(&[enqueued, skipped], chrono_tz::America::New_York, false);
+		assert_eq!(lines, vec![
+			"HANDLED AT               SCHEDULED FOR            OUTCOME   RQ JOB  DETAIL",
+			"2024-03-10 08:00:01 EDT  2024-03-10 08:00:00 EDT  enqueued  JOB-1   ",
+			"garbled                  2024-03-10 08:00:00 EDT  skipped   -       circuit_open",
+		]);
+	}
+
+	#[test]
+	fn test_format_queue_summary() {
+		use btu_scheduler::rq::summarize_queues;
+
+		let worker_queues: Vec<String> = vec!["default,long".to_owned(), "default".to_owned()];
+		let lengths = vec![("long".to_owned(), 3), ("default".to_owned(), 120), ("short".to_owned(), 0)];
+		let queues = summarize_queues(lengths.clone(), Some(&worker_queues));
+		assert_eq!(crate::format_queue_summary(&queues, false), vec![
+			"QUEUE    PENDING  WORKERS",
+			"default  120      2",
+			"long     3        1",
+			"short    0        0",
+		]);
+
+		// Without the workers, the counts are unknown rather than zero.
+		let lines = crate::format_queue_summary(&summarize_queues(lengths, None), false);
+		assert_eq!(lines[1], "default  120      ?");
+
+		// Redis being down is a connectivity error, not a panic.
+		assert_eq!(crate::cli_list_queues(&unreachable_app_config(), OutputFormat::Human).unwrap_err().exit_code(), 3);
+	}
+
+	#[cfg(feature = "sql")]
+	#[test]
+	fn test_dry_run_lines() {
+		use chrono::{TimeZone, Utc};
+		use btu_scheduler::rq::RQJob;
+
+		let mut rq_job = RQJob::new_with_defaults();
+		rq_job.data = (0..40).collect();
+		rq_job.timeout = 600;
+		rq_job.description = "Nightly ping".to_owned();
+		let lines = crate::dry_run_lines(&rq_job, Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap());
+		assert!(lines[0].starts_with("Pickled function: 40 bytes, beginning 00 01 02"), "{}", lines[0]);
+		assert!(lines[0].ends_with("1E 1F ..."), "{}", lines[0]);
+		assert_eq!(lines[1], "Queue: default    Timeout: 600 seconds    Description: Nightly ping");
+		assert_eq!(&lines[3..], &[
+			format!("    {}", rq_job.job_key),
+			"    rq:queues".to_owned(),
+			"    rq:queue:default".to_owned(),
+			"    btu_scheduler:queue_stats:2024-03-10".to_owned(),
+			"    btu_scheduler:queue_last_enqueue".to_owned(),
+			"DRY RUN - nothing was written.".to_owned(),
+		]);
+
+		// Failing to fetch the pickled function is an error, with the exit code of a connectivity problem.
+		let error: CliError = btu_scheduler::errors::FrappeApiError::Transport { task_id: "TASK-1".to_owned(), message: "refused".to_owned() }.into();
+		assert_eq!(error.exit_code(), 3);
+	}
+
+	#[test]
+	fn test_validate_cron_lines() {
+		let app_config = unreachable_app_config();
+		assert_eq!(crate::validate_cron_lines(&app_config, "30 9 * * Mon-Fri").unwrap(), vec![
+			"Cron '30 9 * * Mon-Fri' has 5 elements, and is read as '0 30 9 * * Mon-Fri *'.",
+			"It runs every weekday at 09:30.",
+		]);
+		let lines: Vec<String> = crate::validate_cron_lines(&app_config, "15 30 9 * * * 2030").unwrap();
+		assert_eq!(lines.len(), 3);
+		assert!(lines[2].starts_with("NOTICE: Cron has 7 elements"), "{}", lines[2]);
+
+		let error: CliError = crate::validate_cron_lines(&app_config, "0 25 * * *").unwrap_err();
+		assert_eq!(error.exit_code(), 1);
+		assert!(error.to_string().contains("Invalid cron expression '0 25 * * *'"), "{}", error);
+		assert!(crate::validate_cron_lines(&app_config, "1 2 3").is_err());
+	}
+
+	#[test]
+	fn test_internal_queue_without_daemon() {
+		let result = crate::send_daemon_request(&unreachable_app_config(), "list_internal_queue", None);
+		assert!(matches!(result, Err(CliError::Connectivity(_))), "{:?}", result);
+	}
+
+	/// Built with '--no-default-features --features redis-only': the subcommands that read MariaDB say why they cannot run.
+	#[cfg(not(feature = "sql"))]
+	mod without_sql {
+		use super::unreachable_app_config;
+		use crate::CliError;
+
+		#[test]
+		fn test_sql_subcommands_are_reported() {
+			for subcommand in ["list-tasks", "queue-task-now", "next-run", "import-state", "diagnose"] {
+				let error: CliError = crate::not_built_with_sql(subcommand);
+				assert_eq!(error.exit_code(), 1);
+				assert!(error.to_string().contains(&format!("'btu {}' reads from MariaDB", subcommand)), "{}", error);
+				assert!(error.to_string().contains("not built with SQL support"), "{}", error);
+			}
+			// 'check' still checks Redis, and fails without it.
+			assert_eq!(crate::cli_check(&unreachable_app_config()).unwrap_err().exit_code(), 3);
+		}
+	}
+}
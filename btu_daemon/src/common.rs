@@ -31,6 +31,9 @@ impl std::error::Error for Error {}
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The daemon's exit code when its Unix Domain Socket cannot be created, bound, or connected to.
+pub const EXIT_SOCKET_SETUP: i32 = 3;
+
 // hepler methods
 
 pub fn exit(code: i32) -> ! {
@@ -42,15 +45,3 @@ pub fn exit_msg<T: Display>(code: i32, msg: T) -> ! {
     std::process::exit(code);
 }
 
-
-pub fn target_linux_distro() -> &'static str {
-    if cfg!(target_linux_distro = "Debian 10") {
-        "Debian 10"
-    }
-    else if cfg!(target_linux_distro = "Debian 11") {
-        "Debian 11"
-    }
-    else {
-        "Unknown Linux Distribution"
-    } 
-}
@@ -1,88 +1,250 @@
 #![forbid(unsafe_code)]
 #![allow(unused_imports)]
 
-use std::{collections::VecDeque,
+use std::{collections::HashSet,
           env,
           fmt::Debug,
           os::unix::net::UnixListener,
-          sync::{Arc, Mutex ,MutexGuard},
+          sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}},
           thread,
           time::{Duration, Instant}};
 
 // Crates.io
+use arc_swap::ArcSwap;
 use chrono::prelude::*;
 use mysql::Result as mysqlResult;
 use mysql::prelude::Queryable;
-use once_cell::sync::Lazy;
+use nix::sys::signal::{SigSet, Signal};
+use once_cell::sync::{Lazy, OnceCell};
 
 // Tracing modules
 use tracing::{trace, debug, info, warn, error, span, Level};
 use tracing::dispatcher::Dispatch;
-use tracing_subscriber::{FmtSubscriber, Registry, filter, prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{FmtSubscriber, Layer, Registry, filter, prelude::__tracing_subscriber_SubscriberExt, reload, util::SubscriberInitExt};
 
 // This Crate
 pub mod common;
+pub mod daemon_status;
+#[cfg(feature = "sentry")]
+pub mod error_reporting;
 pub mod ipc_stream;
 pub mod logging;
-use btu_scheduler::{config, rq, scheduler, task_schedule};
+pub mod status_page;
+pub mod systemd;
+mod tests;
+use btu_scheduler::{config, janitor, rq, scheduler, task_schedule, watchdog};
 use btu_scheduler::config::AppConfig;
-use logging::CustomLayer;
+use btu_scheduler::errors::RQError;
+use btu_scheduler::ids::{CorrelationId, TaskScheduleId};
+use btu_scheduler::metrics::{timed_query, QueryKind};
+use btu_scheduler::runtime::{MaintenanceTask, RefreshSummary, ScheduleTaskError, SchedulerBackend, SchedulerRuntime};
+use btu_scheduler::schedule_queue::{QueueEntry, QueueSource, ScheduleFingerprint, ScheduleQueue};
+use logging::{CustomLayer, LogHistoryLayer};
 
 // GitHub Issue where Brian and Adam discuss Rust thread locking: https://github.com/aeshirey/aeshirey.github.io/issues/5
 
 /**
- Queries the Frappe database, adding every Task Schedule ID to the Scheduler's internal queue.\
- This effectively performs a "full refresh" in Python RQ.
+ The full refresh: every enabled Task Schedule in the Frappe database is scheduled in Redis again.\
+ For each site, the Task Schedules are read with one SQL query, and their Next Execution Times written with one Redis pipeline
+ (scheduler::add_many_task_schedules_to_rq).  The internal queue is only locked to hand Thread #1 what could not be written, so
+ socket requests no longer wait behind hundreds of per-row queries.  When a site's bulk read or write fails, its Task Schedule
+ IDs are queued instead (queue_site_refill), and Thread #1 schedules them one at a time, with its usual retries.
 */
-fn queue_full_refill(queue: &mut VecDeque<String>) ->  mysqlResult<u32> {
-    // For more information on the Rust mysql crate: https://docs.rs/mysql/latest/mysql/index.html
+fn full_refresh(app_config: &AppConfig, queue: &Mutex<ScheduleQueue>, source: QueueSource) -> mysqlResult<RefreshSummary> {
+
+    // On a multi-site bench, every site is refreshed from its own database.  One unreachable site does not stop the others.
+    let mut summary: RefreshSummary = RefreshSummary::default();
+    let mut last_error: Option<mysql::Error> = None;
+    let site_configs: Vec<AppConfig> = app_config.site_configs();
+    let site_count: usize = site_configs.len();
+    for site_config in site_configs {
+        match site_full_refresh(queue, &site_config, source) {
+            Ok(site_summary) => {
+                summary.refreshed += site_summary.refreshed;
+                summary.queued += site_summary.queued;
+            },
+            Err(error) if site_count > 1 => {
+                error!("Unable to refresh the Task Schedules of site '{}': {}", site_config.site_name().unwrap_or("(default)"), error);
+                last_error = Some(error);
+            },
+            Err(error) => return Err(error),
+        }
+    }
+    match last_error {
+        Some(error) if summary.refreshed == 0 && summary.queued == 0 => Err(error),
+        _ => {
+            daemon_status::DAEMON_STATUS.record_full_refresh(chrono::Utc::now());
+            Ok(summary)
+        }
+    }
+}
 
-    let mut rows_added: u32 = 0;
-    
-    /*  The next line below is a bit wild.  Here is the concept:
+fn site_full_refresh(queue: &Mutex<ScheduleQueue>, site_config: &AppConfig, source: QueueSource) -> mysqlResult<RefreshSummary> {
 
-        Goal: Read the APP_CONFIG struct, to obtain information about how to connect to MySQL/MariaDB.
+    // Schedules disabled (or deleted) while flagged as unschedulable are never visited again; clear their markers here.
+    scheduler::prune_unschedulable_markers(site_config);
 
-        1. First, get a lock for APP_CONFIG. This yields a LockResult type.
-        2. Unwrap that LockResult, to reveal a MutexGuard.
-        3. By deferencing the MutexGuard using *, we yield an -owned- AppConfig.
-        4. However...there's no need to -move- AppConfig into 'get_mysql_conn()'.  We just need a reference.  So prefix with '&'
-    */
+    let bulk_result: Result<Vec<scheduler::BulkScheduleResult>, String> = task_schedule::read_many_btu_task_schedules(site_config)
+        .map_err(|error| format!("unable to read the Task Schedules: {}", error))
+        .and_then(|schedules| scheduler::add_many_task_schedules_to_rq(site_config, &schedules)
+            .map_err(|error| format!("unable to write their Next Execution Times: {}", error)));
+    let Ok(mut unlocked_queue) = queue.lock() else {
+        error!("The internal queue's lock is poisoned; the full refresh cannot queue any Task Schedules.");
+        return Ok(RefreshSummary::default());
+    };
+    let results: Vec<scheduler::BulkScheduleResult> = match bulk_result {
+        Ok(results) => results,
+        Err(error) => {
+            warn!("Site '{}': the bulk refresh failed ({}); queuing its Task Schedules one at a time instead.",
+                  site_config.site_name().unwrap_or("(default)"), error);
+            return queue_site_refill(&mut unlocked_queue, site_config, source).map(|queued| RefreshSummary { refreshed: 0, queued });
+        }
+    };
+    let mut summary: RefreshSummary = RefreshSummary::default();
+    for bulk in results {
+        match bulk.result {
+            Ok(_) => summary.refreshed += 1,
+            Err(error) => {
+                // Redis failed for this schedule alone?  Thread #1 tries again, with its usual retries.
+                warn!("Unable to refresh Task Schedule {} in bulk; queuing it for Thread #1: {}", bulk.schedule_key, error);
+                if unlocked_queue.push_back(QueueEntry::new(bulk.schedule_key, source)) {
+                    summary.queued += 1;
+                }
+            }
+        }
+    }
+    Ok(summary)
+}
 
-    let mut conn = config::get_mysql_conn(&*APP_CONFIG.lock().unwrap())?;
+/// The internal queue entry for one row of a site's refill query.  Its ID is qualified by the site's name.
+/// 'context_hash' is ScheduleFingerprint::context_hash() for the site.
+pub fn refill_entry(site_config: &AppConfig, name: &str, modified: Option<&str>, schedule_expression: &str, context_hash: u64, source: QueueSource) -> QueueEntry {
+    let entry = QueueEntry::new(site_config.qualify_schedule_id(name), source);
+    match modified {
+        Some(modified) => entry.with_fingerprint(ScheduleFingerprint::new(modified, schedule_expression).with_context(context_hash)),
+        None => entry
+    }
+}
 
-    conn.query_iter("SELECT `name` FROM `tabBTU Task Schedule` WHERE enabled = 1 ORDER BY name;")
-    .unwrap()
-    .for_each(|row_result| {
-        match row_result {
-            Ok(row) => {
-                let r: String = mysql::from_row(row);  // each value of r is a 'name' from the SQL table.  The primary key of BTU Task Schedule .
-                queue.push_back(r);
-                rows_added += 1;
+fn queue_site_refill(queue: &mut ScheduleQueue, app_config: &AppConfig, source: QueueSource) -> mysqlResult<u32> {
+
+    let mut conn = config::get_mysql_conn(app_config)?;
+
+    // Optionally, limit the refill to certain queues (configuration keys 'include_queues' and 'exclude_queues')
+    let (queue_filter, queue_parameters) = app_config.queue_filter_sql();
+    // 'modified' and the cron string are the fingerprint: Thread #1 skips schedules that have not changed since it last processed them.
+    // Editing the repeat interval changes 'modified' too; the column is not read here, because older BTU Apps do not have it.
+    // The cron time zone, holidays, and configuration generation are in the fingerprint too, so changing them is never skipped.
+    let query_syntax = format!("SELECT TaskSchedule.`name`, CAST(TaskSchedule.`modified` AS CHAR), IFNULL(TaskSchedule.`cron_string`, ''), \
+                                IFNULL(Configuration.value, '') \
+                                FROM `tabBTU Task Schedule` AS TaskSchedule \
+                                LEFT JOIN `tabSingles` AS Configuration \
+                                ON Configuration.doctype = 'BTU Configuration' AND Configuration.`field` = 'cron_time_zone' \
+                                WHERE TaskSchedule.enabled = 1{} ORDER BY TaskSchedule.name;", queue_filter);
+    let holidays: HashSet<NaiveDate> = scheduler::read_holidays(app_config);
+    let config_generation: u64 = CONFIG_GENERATION.load(Ordering::SeqCst);
+
+    timed_query(QueryKind::FullRefill, app_config.slow_query_threshold(), || {
+        let mut rows_added: u32 = 0;
+        conn.exec_iter(query_syntax, queue_parameters)?
+        .for_each(|row_result| {
+            match row_result {
+                Ok(row) => {
+                    // 'name' is the primary key of BTU Task Schedule.
+                    let (name, modified, schedule_expression, cron_time_zone): (String, Option<String>, String, String) = mysql::from_row(row);
+                    let context_hash: u64 = ScheduleFingerprint::context_hash(config_generation, &cron_time_zone, &holidays);
+                    if queue.push_back(refill_entry(app_config, &name, modified.as_deref(), &schedule_expression, context_hash, source)) {  // IDs already pending are not queued twice.
+                        rows_added += 1;
+                    }
+                },
+                Err(error) => {
+                    error!("Error with SQL row result: {:?}", error);
+                }
+            }
+        });
+        Ok(rows_added)
+    })
+}
+
+/// The daemon's SchedulerBackend: Redis and MariaDB, through the library's functions.  Each opens its own connections.
+struct LiveBackend;
+
+impl SchedulerBackend for LiveBackend {
+    fn scheduled_score(&mut self, app_config: &AppConfig, tsik: &str) -> Option<i64> {
+        scheduler::scheduled_score(app_config, tsik)
+    }
+
+    fn schedule_task(&mut self, site_config: &AppConfig, task_schedule_id: &TaskScheduleId, correlation_id: Option<&CorrelationId>)
+        -> Result<Option<DateTime<Utc>>, ScheduleTaskError> {
+        let btu_task_schedule = task_schedule::read_btu_task_schedule(site_config, task_schedule_id).map_err(ScheduleTaskError::Read)?;
+        match scheduler::add_task_schedule_to_rq(site_config, &btu_task_schedule, correlation_id) {
+            Ok(runtime) => Ok(runtime),
+            Err(error) => Err(ScheduleTaskError::Write { task_schedule: Box::new(btu_task_schedule), error }),
+        }
+    }
+
+    fn abandon_schedule_write(&mut self, site_config: &AppConfig, task_schedule: &task_schedule::BtuTaskSchedule, error: &RQError) {
+        scheduler::abandon_schedule_write(site_config, task_schedule, error);
+    }
+
+    fn full_refresh(&mut self, app_config: &AppConfig, queue: &Mutex<ScheduleQueue>, source: QueueSource) -> Result<RefreshSummary, String> {
+        full_refresh(app_config, queue, source).map_err(|error| error.to_string())
+    }
+
+    fn run_eligible(&mut self, app_config: &AppConfig, queue: &mut ScheduleQueue, now: DateTime<Utc>) -> scheduler::CycleSummary {
+        scheduler::check_and_run_eligible_task_schedules(app_config, queue, now)
+    }
+
+    fn run_maintenance(&mut self, app_config: &AppConfig, task: MaintenanceTask) {
+        match task {
+            MaintenanceTask::StaleJobCleanup => {
+                if let Err(error) = janitor::remove_stale_jobs(app_config) {
+                    error!("Error while removing stale RQ Jobs: {}", error);
+                }
             },
-            Err(error) => {
-                error!("Error with SQL row result: {:?}", error);
+            MaintenanceTask::JobWatchdog => {
+                // Only when 'job_watchdog_enabled'.
+                if let Err(error) = watchdog::check_watched_jobs(app_config) {
+                    error!("Error while checking watched RQ Jobs for overruns: {}", error);
+                }
+            },
+            MaintenanceTask::RedisMemoryCheck => {
+                // Near 'maxmemory', Redis may evict the hashes of jobs already on a queue.
+                if let Err(error) = rq::check_memory_pressure(app_config) {
+                    debug!("Unable to read Redis memory use: {}", error);
+                }
             }
         }
-    });
-    Ok(rows_added)
+    }
 }
 
+/**
+ Where the configuration file (and, in user mode, the socket) are.  Resolved once; logged by main() once tracing is initialized.\
+ TODO: Need to parse arguments to Daemon for path to configuration file.
+ With '--user' (or when the system file is unreadable), the configuration file and socket follow XDG paths.
+*/
+static CONFIG_PATHS: Lazy<config::ResolvedPaths> = Lazy::new(|| {
+    let user_mode: bool = env::args().skip(1).any(|arg| arg == "--user");
+    config::resolve_paths(None, user_mode)
+});
+
 /**
  The global configuration for this application.\
  Developer Note:  We need to create a Lazy Static, using a custom struct 'AppConfig', populated from a TOML file.\
- Why a Lazy Static?  So we can pass this configuration struct between threads!
+ Why a Lazy Static?  So we can pass this configuration struct between threads!\
+ Why an ArcSwap instead of a Mutex?  AppConfig is never modified in place; reload_app_config() swaps in a whole new one.
+ Readers take a cheap Arc clone, and never block one another; a slow socket request can no longer stall the scheduling threads.
 */
-static APP_CONFIG: Lazy<Mutex<AppConfig>> = Lazy::new(|| {
-    // TODO: Need to parse arguments to Daemon for path to configuration file.
-    match AppConfig::new_from_toml_file(None) {
-        Ok(app_config) => {
+static APP_CONFIG: Lazy<ArcSwap<AppConfig>> = Lazy::new(|| {
+    match AppConfig::new_from_toml_file(Some(CONFIG_PATHS.config_file.as_str())) {
+        Ok(mut app_config) => {
+            CONFIG_PATHS.apply_socket_path(&mut app_config);
             if app_config.tz().is_err() {
                 error!("Cannot parse time zone string in TOML configuration file: '{}' 
                 See this article for a list of valid names: https://en.wikipedia.org/wiki/List_of_tz_database_time_zones", app_config.time_zone_string);
                 std::process::exit(1);
             }
-            Mutex::new(app_config)
+            ArcSwap::from_pointee(app_config)
         }
         Err(error) => {
             error!("Error while creating AppConfig from TOML configuration file. {}", error);
@@ -91,6 +253,66 @@ static APP_CONFIG: Lazy<Mutex<AppConfig>> = Lazy::new(|| {
     }
 });
 
+/// Incremented on every configuration reload.  Part of each refill fingerprint, so a reload makes Thread #1 process every schedule again.
+pub static CONFIG_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Changes the level of the log written to stdout, when a reloaded configuration has a new 'tracing_level'.
+static TRACING_LEVEL_HANDLE: OnceCell<reload::Handle<filter::LevelFilter, Registry>> = OnceCell::new();
+
+/**
+ Read the 'running' configuration again from its file, and if it is valid, replace APP_CONFIG.  Requested with SIGHUP, or a
+ 'reload_config' socket request.\
+ Each thread reads APP_CONFIG once per loop, so new intervals take effect on its next iteration.  An invalid file is logged,
+ and rejected; the running configuration stays in effect.
+*/
+pub fn reload_app_config(running: &AppConfig) -> Result<config::ConfigReload, config::ConfigError> {
+    let reload: config::ConfigReload = match running.read_again().and_then(|new_config| running.prepare_reload(new_config)) {
+        Ok(reload) => reload,
+        Err(error) => {
+            error!("Configuration was not reloaded; the running configuration stays in effect.  {}", error);
+            return Err(error);
+        }
+    };
+    APP_CONFIG.store(Arc::new(reload.app_config.clone()));
+    CONFIG_GENERATION.fetch_add(1, Ordering::SeqCst);
+    if let Some(handle) = TRACING_LEVEL_HANDLE.get() {
+        if let Err(error) = handle.reload(reload.app_config.tracing_level.get_level()) {
+            warn!("Unable to change the tracing level: {}", error);
+        }
+    }
+    logging::LOG_HISTORY.set_capacity(reload.app_config.log_history_size.unwrap_or(logging::DEFAULT_LOG_HISTORY_SIZE));
+    if reload.changed.is_empty() {
+        info!("Configuration reloaded from {}; no settings changed.", reload.app_config.source());
+    }
+    else {
+        info!("Configuration reloaded from {}; changed: {}.", reload.app_config.source(), reload.changed.join(", "));
+    }
+    if !reload.restart_required.is_empty() {
+        warn!("These settings changed, but take effect only when the daemon restarts: {}.", reload.restart_required.join(", "));
+    }
+    Ok(reload)
+}
+
+/// Wait for SIGHUP (for example, from 'systemctl reload'), and reload the configuration each time it arrives.
+fn spawn_reload_on_hangup(hangup: SigSet) {
+    let spawned = thread::Builder::new().name("Reload_On_SIGHUP".to_string()).spawn(move || {
+        loop {
+            match hangup.wait() {
+                Ok(_) => {
+                    info!("Received SIGHUP; reloading the configuration.");
+                    let _ = reload_app_config(&APP_CONFIG.load());  // errors are already logged
+                },
+                Err(error) => {
+                    error!("Unable to wait for SIGHUP; the configuration can still be reloaded with a 'reload_config' socket request: {}", error);
+                    return;
+                }
+            }
+        }
+    });
+    if let Err(error) = spawned {
+        error!("Cannot spawn thread 'Reload_On_SIGHUP': {}", error);
+    }
+}
 
 fn test_configuration_file() {
       /*
@@ -123,48 +345,96 @@ fn test_configuration_file() {
     let my_dispatch = Dispatch::new(my_subscriber);
     tracing::dispatcher::with_default(&my_dispatch, || {
 
-        // NOTE: I previous had '_', but the compiler actually wants a named variable, as of February 25th 2024.
-        let _foo = APP_CONFIG.lock().unwrap();  // Lock APP_CONFIG for a moment, to populate some immutable variables.
+        Lazy::force(&APP_CONFIG);  // Populate APP_CONFIG, which reads and validates the TOML file.
 
     });
 }
 
 
+/// The shortest pause between two scheduler cycles, even when a cycle took longer than 'scheduler_polling_interval'.
+pub const MIN_CYCLE_SLEEP: Duration = Duration::from_secs(1);
+
+/// How long Thread #3 sleeps after a cycle that took 'elapsed' seconds, so cycles start every 'interval' seconds.
+pub fn remaining_sleep(interval: u64, elapsed: u64) -> Duration {
+    Duration::from_secs(interval.saturating_sub(elapsed)).max(MIN_CYCLE_SLEEP)
+}
+
+/// One structured event per scheduler cycle.  Quiet cycles (nothing eligible) are only logged at debug level.
+fn log_cycle_summary(summary: &scheduler::CycleSummary) {
+    if summary.eligible == 0 {
+        debug!("{}", summary);
+        return;
+    }
+    info!(
+        started_at = summary.started_at.as_str(),
+        cutoff_unix = summary.cutoff_unix,
+        eligible = summary.eligible,
+        enqueued = summary.enqueued,
+        reenqueued = summary.reenqueued,
+        failed = summary.failed,
+        skipped = summary.skipped,
+        blocked_by_policy = summary.blocked_by_policy,
+        unchanged_schedules_skipped = summary.unchanged_schedules_skipped,
+        changed_schedules_processed = summary.changed_schedules_processed,
+        duration_ms = summary.duration_ms,
+        "Scheduler cycle finished."
+    );
+}
+
 fn main() {
 
     // when the daemon is called with argument '--version', display some information, then exit.
     let args: Vec<String> = env::args().collect();
     if (args.len() == 2) && (&args[1] == "--version") {
         println!("Version: {}", btu_scheduler::get_package_version());
-        println!("Linux Distribution: {}", common::target_linux_distro());
+        println!("Platform: {}", btu_scheduler::platform::PlatformInfo::detect());
         std::process::exit(0);  // exit with success code
     }
+    /*  SIGHUP reloads the configuration.  Block it here, before any thread is spawned, so every thread inherits the mask;
+        one thread then receives it with sigwait(), and no signal handler (or unsafe code) is needed.
+    */
+    let mut hangup = SigSet::empty();
+    hangup.add(Signal::SIGHUP);
+    if let Err(error) = hangup.thread_block() {
+        eprintln!("Unable to block SIGHUP; it will stop the daemon instead of reloading the configuration: {}", error);
+    }
+    Lazy::force(&status_page::DAEMON_STARTED_AT);  // the status page's uptime counts from here
 
     test_configuration_file();  // ensure the TOML configuration file meets the struct's requirements.
-    let temp_app_config: MutexGuard<AppConfig> =  APP_CONFIG.lock().unwrap();  // lock the configuration for a while during initialization.
+    let temp_app_config: Arc<AppConfig> = APP_CONFIG.load_full();  // hold a reference to the configuration during initialization.
 
     // Initialize tracing globally.  For the remainder of the program, avoid using the println! macro.
-    tracing_subscriber::registry()
-        .with(CustomLayer)
-        .with(temp_app_config.tracing_level.get_level())
-        .init();
+    // The log history (retrievable over the socket) keeps recent INFO-and-above events, regardless of 'tracing_level'.
+    let _ = logging::INSTANCE_IDENTITY.set(btu_scheduler::logging::InstanceIdentity::from_config(&temp_app_config));
+    logging::LOG_HISTORY.set_capacity(temp_app_config.log_history_size.unwrap_or(logging::DEFAULT_LOG_HISTORY_SIZE));
+    let (tracing_level, tracing_level_handle) = reload::Layer::new(temp_app_config.tracing_level.get_level());
+    let _ = TRACING_LEVEL_HANDLE.set(tracing_level_handle);
+    let registry = tracing_subscriber::registry()
+        .with(CustomLayer.with_filter(tracing_level))
+        .with(LogHistoryLayer.with_filter(filter::LevelFilter::INFO));
+    #[cfg(feature = "sentry")]
+    let registry = registry.with(temp_app_config.sentry_dsn.as_ref().map(|_| error_reporting::tracing_layer(&temp_app_config)));
+    registry.init();
+    #[cfg(feature = "sentry")]
+    let _sentry_guard = error_reporting::init(&temp_app_config);  // held until main() returns, so queued events are flushed
+    if let Some(identity) = logging::INSTANCE_IDENTITY.get() {
+        info!("BTU Scheduler {} is starting as {}, on {}.", btu_scheduler::get_package_version(), identity, btu_scheduler::platform::PlatformInfo::detect());
+    }
+    info!("Configuration paths: {}", *CONFIG_PATHS);
+    if CONFIG_PATHS.socket_path.as_deref() == Some(temp_app_config.socket_path.as_str()) {
+        info!("User mode: using socket '{}' instead of the shared '{}'.", temp_app_config.socket_path, config::SOCKET_FILE_PATH);
+    }
+    if temp_app_config.cron_strings_are_utc {
+        info!("UTC-only mode ('cron_strings_are_utc'): every cron expression is read as UTC; Task Schedule time zones are ignored.");
+    }
 
     let mut handles: Vec<thread::JoinHandle<()>> = Vec::with_capacity(3);  // Daemon requires 3 additional thread handles, besides the main thread.
-    /*  Create a new VecDeque, and -move- into an ArcMutex.  This enables the Internal Queue to be passed between threads.
+    /*  The runtime owns the Internal Queue (a ScheduleQueue in an ArcMutex, so it can be passed between threads), and the
+        full refresh's stopwatches.  Each worker thread below is a loop around one of its ticks.
     */
-    let internal_queue = Arc::new(Mutex::new(VecDeque::<String>::new()));  // using a 'turbofish' to specify the type of the VecDeque (String in this case)
+    let runtime = Arc::new(SchedulerRuntime::new(Instant::now()));
+    let internal_queue: Arc<Mutex<ScheduleQueue>> = runtime.queue();
 
-    /*
-      The interval at which 'Next Execution Times' are examined, to potentially trigger RQ inserts.\
-      I recommend a value of no-more-than 60 seconds.  Otherwise you risk missing a Cron Datetime.
-    */ 
-    let scheduler_polling_interval: u64 =  temp_app_config.scheduler_polling_interval;  
-
-    /* The interval for performing a "full-refresh" of BTU Task Schedules from the MySQL database.
-       A good value might be 3600 seconds (60 minutes)
-    */
-    let full_refresh_internal_secs: u32 = temp_app_config.full_refresh_internal_secs;  
-    
     /* The statement below is basically a sanity check.  If we cannot successfully connnect to Redis RQ on startup?
        The daemon cannot do anything, and should terminate.  This can be tempered with a Restart clause in Systemd Unit Files,
        to handle race conditions on server boot.
@@ -179,21 +449,36 @@ fn main() {
             std::process::exit(1);
         }
     }
+    else if let Some(warning) = rq::sanity_check_rq_database(&temp_app_config).warning_message(&temp_app_config) {
+        warn!("********************************************************************************");
+        warn!("{}", warning);
+        warn!("********************************************************************************");
+    }
+
+    if let Err(error) = temp_app_config.validate_email_settings() {
+        warn!("Email notifications will not work: {}", error);
+    }
+    for site_config in temp_app_config.site_configs() {
+        let (_, warning) = btu_scheduler::frappe_client::base_url(&site_config.webserver_ip, site_config.webserver_port, site_config.webserver_use_tls);
+        if let Some(warning) = warning {
+            warn!("{}", warning);
+        }
+    }
 
     // Another sanity check; try to connect to SQL before going any further.
-    match btu_scheduler::validate_sql_credentials(&temp_app_config) {
-        Ok(_) => {
-        },
-        Err(error) => {
-            error!("{}", error);
-            error!("Unable to establish a connection Frappe MySQL database.");
-            if ! temp_app_config.startup_without_database_connections {
-                std::process::exit(1);
-            }
+    let sql_report: btu_scheduler::SqlValidationReport = btu_scheduler::validate_sql_credentials(&temp_app_config);
+    if !sql_report.is_ok() {
+        error!("{}", sql_report.message);
+        if let Some(hint) = &sql_report.hint {
+            error!("{}", hint);
+        }
+        error!("Unable to use the Frappe MySQL database ({:?}).", sql_report.status);
+        if ! temp_app_config.startup_without_database_connections {
+            std::process::exit(1);
         }
     }
 
-    // Finished reading APP_CONFIG, so we should release the lock.
+    // Finished reading APP_CONFIG during initialization.  From here on, each thread reads it once per loop, so a reload takes effect.
     drop(temp_app_config);
 
     /*
@@ -201,39 +486,19 @@ fn main() {
        Thread #1:  This thread reads the Internal Queue in a FIFO manner.
                    For each Task Schedule ID found:
                    1.  Write the "Next Execution Times" to the Python RQ (Redis Queue) database using zadd.
+                       If that fails, push the ID to the back of the queue, for a few more attempts.
                    2.  Nothing else.
                    3.  Do NOT attempt to construct an RQ Job in-advance.  (deliberate design decision by the author)
       ----------------
     */
-    let queue_counter_1 = Arc::clone(&internal_queue);
+    let runtime_1 = Arc::clone(&runtime);
     let thread_handle_1 = thread::Builder::new().name("1_Internal_Queue".to_string()).spawn(move || {
+        let mut backend = LiveBackend;
         loop {
+            systemd::INTERNAL_QUEUE_HEARTBEAT.beat();
             debug!("Thread 1: Reading from Internal Queue...");
-            // Attempt to acquire a lock...
-            if let Ok(mut unlocked_queue) = queue_counter_1.lock() {
-                // ...lock acquired.
-                if ! (*unlocked_queue).is_empty() {
-
-                    match (*unlocked_queue).pop_front() {  // Pop the next value out of the queue (FIFO)
-                        Some(value) => {
-                            let next_task_schedule_id: String = value;  // BTU Task Schedule 'name'
-                            if let Ok(unlocked_app_config) = APP_CONFIG.lock() {
-                                let sql_result =  task_schedule::read_btu_task_schedule(&*unlocked_app_config, &next_task_schedule_id);
-                                if let Some(btu_task_schedule) = sql_result {
-                                    // We now have an owned struct BtuTaskSchedule.
-                                    let _foo = scheduler::add_task_schedule_to_rq(&*unlocked_app_config, &btu_task_schedule);
-                                } else {
-                                    error!("Error: Unable to find SQL record for BTU Task Schedule = '{}'\n(verify BTU Configuration has a Time Zone)", next_task_schedule_id);
-                                }                              
-                            }
-                            trace!("{} values remain in internal queue.", (*unlocked_queue).len());
-                        },
-                        None => {
-                        }
-                    }
-                }
-            }
-            thread::sleep(Duration::from_millis(1250));  // Yield control to another thread.
+            let pause: Duration = runtime_1.tick_internal_queue(&APP_CONFIG.load_full(), &mut backend, Utc::now());
+            thread::sleep(pause);  // Yield control to another thread.
         }
     });
     if thread_handle_1.is_err() {
@@ -245,44 +510,32 @@ fn main() {
 
     /*
       ----------------
-       Thread #2:  Every N seconds, refill the Internal Queue with -all- Task Schedule IDs.
-                   Once finished, thread #1 will begin processing them one at a time.
+       Thread #2:  Every N seconds, schedule -all- Task Schedules again, in bulk (see full_refresh).
+                   Whatever cannot be written in bulk goes to the Internal Queue, for thread #1.
 
                    This is a type of "safety net" for the BTU system.  By performing a "full refresh" of RQ,
                    we can be confident that Tasks are always running.  Even if the RQ database is flushed or emptied,
                    it will be refilled automatically after a while!
       ----------------
     */
-    let queue_counter_2 = Arc::clone(&internal_queue);
+    let runtime_2 = Arc::clone(&runtime);
     let thread_handle_2 = thread::Builder::new().name("2_Auto_Refill".to_string()).spawn(move || {
-
-        let mut stopwatch: Instant = Instant::now();  // used to keep track of time elapsed.
+        let mut backend = LiveBackend;
         loop {
+            systemd::AUTO_REFILL_HEARTBEAT.beat();
             debug!("Thread 2: Attempting to Auto-Refill the Internal Queue...");
-            let elapsed_seconds = stopwatch.elapsed().as_secs();  // calculate elapsed seconds since last Queue Repopulate
-            // Check if enough time has passed...
-            if elapsed_seconds > full_refresh_internal_secs.into() {  // Dev Note: The 'into()' handles conversion to u64
-                // trace!("Thread 2: Attempting to acquire a lock on the internal queue...");
-                if let Ok(mut unlocked_queue) = queue_counter_2.lock() {
-                    // trace!("Thread 2 unlocked.");
-                    // Achieved a lock.
-                    info!("{} seconds have elapsed.  It's time for a full-refresh of the Task Schedules in Redis!", elapsed_seconds);                    
-                    debug!("  * Before refill, the queue contains {} values.", (*unlocked_queue).len());
-                    match queue_full_refill(&mut *unlocked_queue) {
-                        Ok(rows_added) => {
-                            debug!("  * Added {} values to the internal FIFO queue.", rows_added);
-                            debug!("  * Internal queue contains a total of {} values.", (*unlocked_queue).len());
-                            stopwatch = Instant::now();  // reset the stopwatch, and begin new countdown.
-
-                            // Log the Task Schedule:
-                            if let Ok(unlocked_app_config) = APP_CONFIG.lock() {
-                                crate::scheduler::rq_print_scheduled_tasks(&unlocked_app_config, false);      
-                            }
-                        },
-                        Err(e) => error!("Error while repopulating the internal queue! {:?}", e)
-                    }                       
+            /* The interval for performing a "full-refresh" of BTU Task Schedules from the MySQL database ('full_refresh_internal_secs').
+               A good value might be 3600 seconds (60 minutes)
+            */
+            let app_config: Arc<AppConfig> = APP_CONFIG.load_full();
+            if let Some(Ok(_)) = runtime_2.tick_full_refresh(&app_config, &mut backend, Instant::now()) {
+                // Log the Task Schedule:
+                if let Err(error) = crate::scheduler::rq_print_scheduled_tasks(&app_config, false) {
+                    warn!("Unable to list the scheduled Task Schedules: {}", error);
                 }
             }
+            // Much less often than the refresh: stale RQ Jobs, overrunning jobs, and Redis memory.
+            runtime_2.tick_maintenance(&app_config, &mut backend, Instant::now());
             thread::sleep(Duration::from_millis(750));  // Yield control to another thread for a while.
         } // end of loop
     });
@@ -301,25 +554,37 @@ fn main() {
       ----------------
     */
     
-    let queue_counter_3 = Arc::clone(&internal_queue);
-    let thread_handle_3 = thread::Builder::new().name("3_Scheduler".to_string()).spawn(move || {  // this 'move' is required to own variable 'scheduler_polling_interval'
+    let runtime_3 = Arc::clone(&runtime);
+    let thread_handle_3 = thread::Builder::new().name("3_Scheduler".to_string()).spawn(move || {  // this 'move' is required to own variable 'runtime_3'
+        let mut backend = LiveBackend;
+        systemd::SCHEDULER_HEARTBEAT.beat();
         thread::sleep(Duration::from_secs(10)); // One-time delay of execution: this gives the other Threads a chance to initialize.
         info!("--> Thread '3_Scheduler' has launched.  Eligible RQ Jobs will be placed into RQ Queues at the appropriate time.");
         loop {
+            systemd::SCHEDULER_HEARTBEAT.beat();
             debug!("Thread 3: Attempting to add new Jobs to RQ...");
-            // This thread requires a lock on the Internal Queue, so that after a Task runs, it can be rescheduled.
+            /*
+              The interval at which 'Next Execution Times' are examined, to potentially trigger RQ inserts.\
+              I recommend a value of no-more-than 60 seconds.  Otherwise you risk missing a Cron Datetime.
+            */
+            let app_config: Arc<AppConfig> = APP_CONFIG.load_full();
+            let scheduler_polling_interval: u64 = app_config.scheduler_polling_interval;
             let stopwatch: Instant = Instant::now();
-            if let Ok(mut unlocked_queue) = queue_counter_3.lock() {
-                // Successfully achieved a lock on the queue.
-                if let Ok(app_config) = &APP_CONFIG.lock() {
-                    // Successfully achieved a lock on the Application Configuration.
-                    scheduler::check_and_run_eligible_task_schedules(app_config, &mut *unlocked_queue);
+            if let Some(summary) = runtime_3.tick_promote_ready(&app_config, &mut backend, Utc::now()) {
+                log_cycle_summary(&summary);
+                daemon_status::DAEMON_STATUS.record_scheduler_pass(chrono::Utc::now(), &summary);
+                if let Ok(mut last_cycle) = ipc_stream::LAST_CYCLE_SUMMARY.lock() {
+                    *last_cycle = Some(summary);
                 }
             }
             let elapsed_seconds = stopwatch.elapsed().as_secs();  // time just spent working on RQ database.
             // I want this thread to execute at roughly the same interval.
             // Bu subtracting the Time Elapsed above, from the desired Wait Time, we know how much longer the thread should sleep.
-            thread::sleep(Duration::from_secs(scheduler_polling_interval - elapsed_seconds)); // wait N seconds before trying again.
+            if elapsed_seconds > scheduler_polling_interval {
+                warn!("Scheduler cycle took {} seconds, longer than 'scheduler_polling_interval' ({} seconds).  Consider raising it.",
+                      elapsed_seconds, scheduler_polling_interval);
+            }
+            thread::sleep(remaining_sleep(scheduler_polling_interval, elapsed_seconds)); // wait N seconds before trying again.
         }
     });
     if thread_handle_3.is_err() {
@@ -338,7 +603,7 @@ fn main() {
 
     println!("\nThis daemon performs the following functions:\n");
     println!("1. Performs the role of a Scheduler, enqueuing BTU Task Schedules in Python RQ whenever it's time to run them.");
-    println!("2. Performs a full-refresh of BTU Task Schedules every {} seconds.", full_refresh_internal_secs);    
+    println!("2. Performs a full-refresh of BTU Task Schedules every {} seconds.", APP_CONFIG.load().full_refresh_internal_secs);
     println!("3. Listens on Unix Domain Socket for requests from the Frappe BTU web application.\n");
 
     info!("Main Thread started");
@@ -346,35 +611,41 @@ fn main() {
     // TODO: Would be lovely if the main thread knew about the child threads status?
     // https://stackoverflow.com/questions/35883390/how-to-check-if-a-thread-has-finished-in-rust
 
-    // Immediately on startup, Scheduler daemon should populate its internal queue with all BTU Task Schedule identifiers.
-    let queue_counter_temp = Arc::clone(&internal_queue);
-    {
-        // Note: using an explicit scope here, to ensure the lock is dropped immediately afterwards, so new threads can take it.
-        let mut unlocked_queue = queue_counter_temp.lock().unwrap();
-
-        match queue_full_refill(&mut unlocked_queue) {
-            Ok(rows_added) => {
-                info!("Filled internal queue with {} Task Schedule identifiers.", rows_added);                
-            },
-            Err(error) => {
-                warn!("{}", error);
-                warn!("Unable to establish a connection Frappe MySQL database.");
-                // std::process::exit(1);    
-            }
+    // Immediately on startup, Scheduler daemon should schedule every BTU Task Schedule.
+    match runtime.full_refresh(&APP_CONFIG.load_full(), &mut LiveBackend, QueueSource::Startup) {
+        Ok(summary) => {
+            info!("Scheduled {} Task Schedules, and filled internal queue with {} Task Schedule identifiers.", summary.refreshed, summary.queued);
+        },
+        Err(error) => {
+            warn!("{}", error);
+            warn!("Unable to establish a connection Frappe MySQL database.");
+            // std::process::exit(1);    
         }
-        drop(unlocked_queue);
     }
 
     // The purpose of the main() thread = Unix Domain Socket server!
-    let listener: UnixListener = ipc_stream::create_socket_listener(&APP_CONFIG.lock().unwrap().socket_path);
+    let listener: UnixListener = {
+        let app_config: Arc<AppConfig> = APP_CONFIG.load_full();
+        match ipc_stream::create_socket_listener(&app_config.socket_path, app_config.create_socket_dir) {
+            Ok(listener) => listener,
+            Err(error) => {
+                error!("{}", error);
+                error!("Frappe Web App would be unable to send commands to the BTU Scheduler.\nEnding daemon now.");
+                std::process::exit(common::EXIT_SOCKET_SETUP);
+            }
+        }
+    };
     {
         // After creating the UDS file, Linux requires we change the file permissions:
-        // NOTE: Wrapping in a smaller namespace, so APP_CONFIG is automatically unlocked.
-        let unlocked_app_config: &AppConfig = &APP_CONFIG.lock().unwrap();
-        match ipc_stream::update_socket_file_permissions(&unlocked_app_config.socket_path, &unlocked_app_config.socket_file_group_owner) {
+        let app_config: Arc<AppConfig> = APP_CONFIG.load_full();
+        match ipc_stream::update_socket_file_permissions(&app_config.socket_path, &app_config.socket_file_group_owner) {
             Ok(_) => {
                 trace!("Successfully updated Unix Domain Socket file's permissions.");
             },
+            Err(error) if btu_scheduler::platform::PlatformInfo::detect().in_container == Some(true) => {
+                // Containers commonly lack the group, or the right to chown; the web server is then expected to share the container's user.
+                warn!("Failed to modify Unix Domain Socket file's permissions ({}).  Continuing, because the daemon is running in a container.", error);
+            },
             Err(error) => {
                 error!("\nERROR: Failed to modify Unix Domain Socket file's permissions:\n    {}", error);
                 error!("Frappe Web App would be unable to send commands to the BTU Scheduler.\nEnding daemon now.");
@@ -383,15 +654,25 @@ fn main() {
         }
     }
 
+    // Optionally, a read-only HTML status page on its own thread.
+    status_page::spawn_status_page(&APP_CONFIG.load_full(), Arc::clone(&internal_queue));
+
+    // Optionally, tell systemd the daemon is ready; and keep its watchdog satisfied while every worker thread checks in.
+    systemd::start(&APP_CONFIG.load_full());
+
+    // Reload the configuration whenever the daemon receives SIGHUP.
+    spawn_reload_on_hangup(hangup);
+
     for stream in listener.incoming() {
         let queue_counter_main = Arc::clone(&internal_queue);
+        let app_config_main: Arc<AppConfig> = APP_CONFIG.load_full();  // cheap; does not block the scheduling threads.
         match stream {
             Ok(unwrapped_stream) => {
                 let handler_result = thread::Builder::new().name("Unix_Socket_Handler".to_string()).spawn(move || {
                     // Call a function to handle whatever request is being made by a remote Client.
                     let request_result = ipc_stream::handle_client_request(unwrapped_stream, 
                                                                            queue_counter_main,
-                                                                           &APP_CONFIG.lock().unwrap());
+                                                                           app_config_main);
                     if let Err(error_message) = request_result {
                         error!("Error while handling Unix client stream: {}", error_message);
                     }
@@ -0,0 +1,89 @@
+/* daemon_status.rs */
+
+#![forbid(unsafe_code)]
+
+/*
+    What the worker threads last did, for the socket's 'status' request; the Frappe web UI polls it for a health widget.
+
+    Thread #2 (and the startup refresh) records each successful full refresh; Thread #3 records each scheduler pass, and how many
+    jobs it enqueued.  Atomics, rather than a lock: the socket handler never waits behind a worker thread.  Connection attempts
+    to Redis and MariaDB are recorded by the library, wherever a connection is opened (see btu_scheduler::metrics).
+*/
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use serde::Serialize;
+
+use btu_scheduler::metrics::{self, Backend, ConnectionAttempt};
+use btu_scheduler::scheduler::CycleSummary;
+
+/// Statistics published by the worker threads.  Times are Unix seconds; 0 until the first.
+pub struct DaemonStatus {
+    last_full_refresh_unix: AtomicI64,
+    last_scheduler_pass_unix: AtomicI64,
+    jobs_enqueued: AtomicU64,
+}
+
+pub static DAEMON_STATUS: DaemonStatus = DaemonStatus::new();
+
+impl DaemonStatus {
+
+    pub const fn new() -> Self {
+        DaemonStatus {
+            last_full_refresh_unix: AtomicI64::new(0),
+            last_scheduler_pass_unix: AtomicI64::new(0),
+            jobs_enqueued: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_full_refresh(&self, at: DateTime<Utc>) {
+        self.last_full_refresh_unix.store(at.timestamp(), Ordering::Relaxed);
+    }
+
+    /// Thread #3 finished a pass; add the jobs it enqueued.
+    pub fn record_scheduler_pass(&self, at: DateTime<Utc>, summary: &CycleSummary) {
+        self.last_scheduler_pass_unix.store(at.timestamp(), Ordering::Relaxed);
+        self.jobs_enqueued.fetch_add(summary.enqueued as u64, Ordering::Relaxed);
+    }
+
+    /// The reply to a 'status' request.  'queue_length' is None if the Internal Queue's lock could not be taken.
+    pub fn report(&self, started_at: DateTime<Utc>, now: DateTime<Utc>, queue_length: Option<usize>) -> StatusReport {
+        StatusReport {
+            daemon_version: btu_scheduler::get_package_version().to_owned(),
+            uptime_secs: (now - started_at).num_seconds(),
+            internal_queue_length: queue_length,
+            last_full_refresh: unix_to_rfc3339(self.last_full_refresh_unix.load(Ordering::Relaxed)),
+            last_scheduler_pass: unix_to_rfc3339(self.last_scheduler_pass_unix.load(Ordering::Relaxed)),
+            jobs_enqueued_since_start: self.jobs_enqueued.load(Ordering::Relaxed),
+            redis: metrics::last_connection_attempt(Backend::Redis),
+            mysql: metrics::last_connection_attempt(Backend::Mysql),
+        }
+    }
+}
+
+impl Default for DaemonStatus {
+    fn default() -> Self {
+        DaemonStatus::new()
+    }
+}
+
+/// The daemon's health, as JSON.  Times are RFC 3339, UTC; null until the first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusReport {
+    pub daemon_version: String,
+    pub uptime_secs: i64,
+    pub internal_queue_length: Option<usize>,
+    pub last_full_refresh: Option<String>,
+    pub last_scheduler_pass: Option<String>,
+    pub jobs_enqueued_since_start: u64,
+    pub redis: Option<ConnectionAttempt>,  // the most recent connection attempt; null before the first
+    pub mysql: Option<ConnectionAttempt>,
+}
+
+fn unix_to_rfc3339(unix: i64) -> Option<String> {
+    if unix == 0 {
+        return None;
+    }
+    Utc.timestamp_opt(unix, 0).single().map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
@@ -0,0 +1,68 @@
+/*
+    Optional error reporting to Sentry.
+
+    This module only exists when the daemon is built with '--features sentry', and does nothing unless the configuration
+    file also contains 'sentry_dsn'.  Events are sent by the Sentry client's own background thread; a slow or unreachable
+    Sentry server never blocks (or fails) the scheduler's threads.
+
+    Further Reading: https://docs.sentry.io/platforms/rust/
+*/
+
+use std::borrow::Cow;
+use std::thread;
+
+use sentry::integrations::tracing::{EventMapping, SentryLayer};
+use tracing::{warn, Subscriber};
+use tracing_subscriber::filter::{Filtered, LevelFilter};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use btu_scheduler::config::AppConfig;
+
+/// Start the Sentry client, and install its panic hook.  Returns None when 'sentry_dsn' is missing or invalid.
+/// The returned guard flushes queued events when dropped, so it must be held until the daemon exits.
+pub fn init(app_config: &AppConfig) -> Option<sentry::ClientInitGuard> {
+    let dsn: sentry::types::Dsn = match app_config.sentry_dsn.as_deref()?.parse() {
+        Ok(dsn) => dsn,
+        Err(error) => {
+            warn!("Ignoring configuration value 'sentry_dsn'; it is not a valid Sentry DSN: {}", error);
+            return None;
+        }
+    };
+    let guard = sentry::init(client_options(app_config, dsn));  // includes the default integrations, such as the panic hook
+    if !guard.is_enabled() {
+        warn!("Unable to start the Sentry client; errors will not be reported to Sentry.");
+        return None;
+    }
+    Some(guard)
+}
+
+/// The Sentry client settings for this daemon.
+pub fn client_options(app_config: &AppConfig, dsn: sentry::types::Dsn) -> sentry::ClientOptions {
+    sentry::ClientOptions {
+        dsn: Some(dsn),
+        release: Some(Cow::Borrowed(btu_scheduler::get_package_version())),
+        environment: app_config.environment_name.clone().map(Cow::Owned),
+        ..Default::default()
+    }
+}
+
+/// A tracing layer that forwards ERROR-level events to Sentry, tagged with the thread name, environment, and version.
+/// The level filter means events at other levels never reach the Sentry layer at all.
+pub fn tracing_layer<S>(app_config: &AppConfig) -> Filtered<SentryLayer<S>, LevelFilter, S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let environment_name: String = app_config.environment_name.clone().unwrap_or_default();
+    let version: String = btu_scheduler::get_package_version().to_string();
+    sentry::integrations::tracing::layer()
+        .event_mapper(move |event, context| {
+            let mut sentry_event = sentry::integrations::tracing::event_from_event(event, context);
+            let thread_name: String = thread::current().name().unwrap_or("(unnamed)").to_owned();
+            sentry_event.tags.insert("thread".to_owned(), thread_name);
+            sentry_event.tags.insert("environment_name".to_owned(), environment_name.clone());
+            sentry_event.tags.insert("version".to_owned(), version.clone());
+            EventMapping::Event(sentry_event)
+        })
+        .with_filter(LevelFilter::ERROR)
+}
@@ -4,36 +4,103 @@
 
 // This module handles Inter-process Communication with the colocated Frappe Web Server.
 
-use std::{collections::VecDeque, io::{Read, Write},
+use std::{collections::HashMap,
+          io::{Read, Write},
           os::unix::net::{UnixStream, UnixListener},
-          sync::{Arc, Mutex}};
+          sync::{Arc, Condvar, Mutex},
+          time::Instant};
 
+use btu_scheduler::btu_cron::CronClassification;
+use btu_scheduler::errors::RescheduleError;
+use btu_scheduler::ids::{CorrelationId, TaskScheduleId};
+use btu_scheduler::next_run_override::{reschedule_by_operator, RescheduleTarget};
+use btu_scheduler::errors::FrameError;
+use btu_scheduler::scheduler::{CycleSummary, RQScheduledTask, UnschedulableMarker, VecRQScheduledTask};
+use btu_scheduler::socket_frame::{self, ReceivedMessage};
 use camino::Utf8PathBuf;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use tracing::{trace, debug, info, warn, error, span, Level};
 use crate::config;
+use crate::daemon_status::DAEMON_STATUS;
+use crate::status_page::DAEMON_STARTED_AT;
+use btu_scheduler::schedule_queue::{QueueEntry, QueueSource, ScheduleQueue};
+use crate::rq;
 use crate::scheduler::rq_cancel_scheduled_task;
 
 #[derive(Serialize, Deserialize, Debug)]
-struct FrappeClientMessage {
-    request_type: String,
-    request_content: Option<String>
+pub struct FrappeClientMessage {
+    pub request_type: String,
+    pub request_content: Option<String>,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,  // When a client retries with the same key, the original response is replayed.
+    #[serde(default)]
+    pub correlation_id: Option<String>,  // Attached to the log lines, events, and RQ Job that follow.  Generated when missing.
 }
 
+/// Why the Unix Domain Socket could not be set up.  Each names the path involved; the io::Error carries the errno.
+#[derive(Debug)]
+pub enum SocketSetupError {
+    MissingDirectory { path: Utf8PathBuf },
+    CreateDirectory { path: Utf8PathBuf, source: std::io::Error },
+    RemoveStale { path: Utf8PathBuf, source: std::io::Error },
+    Bind { path: Utf8PathBuf, source: std::io::Error },
+    SelfConnect { path: Utf8PathBuf, source: std::io::Error },
+}
+
+impl std::fmt::Display for SocketSetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SocketSetupError::MissingDirectory { path } =>
+                write!(f, "Directory '{}' for the socket file does not exist, and 'create_socket_dir' is false.", path),
+            SocketSetupError::CreateDirectory { path, source } =>
+                write!(f, "Cannot create directory '{}' for the socket file: {}", path, source),
+            SocketSetupError::RemoveStale { path, source } =>
+                write!(f, "Cannot remove preexisting socket file '{}': {}", path, source),
+            SocketSetupError::Bind { path, source } =>
+                write!(f, "Cannot bind Unix Domain Socket '{}': {}", path, source),
+            SocketSetupError::SelfConnect { path, source } =>
+                write!(f, "Bound Unix Domain Socket '{}', but cannot connect to it (is SELinux or AppArmor denying access?): {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for SocketSetupError {}
+
 /**
-Create a UnixListener using a string slice, where the slice is a path to a Unix Domain Socket file.
+Create a UnixListener using a string slice, where the slice is a path to a Unix Domain Socket file.\
+Missing parent directories are created (mode 755) when 'create_dir' is true.  Before returning, the daemon connects
+to its own socket once: a security policy may allow the bind, yet block every client.
 */
-pub fn create_socket_listener(socket_file_path: &str) -> UnixListener {
+pub fn create_socket_listener(socket_file_path: &str, create_dir: bool) -> Result<UnixListener, SocketSetupError> {
+
+    use std::os::unix::fs::DirBuilderExt;
 
     let file_as_path: Utf8PathBuf = socket_file_path.into();
+    if let Some(parent) = file_as_path.parent().filter(|parent| !parent.as_str().is_empty() && !parent.exists()) {
+        if !create_dir {
+            return Err(SocketSetupError::MissingDirectory { path: parent.to_owned() });
+        }
+        std::fs::DirBuilder::new().recursive(true).mode(0o755).create(parent)
+            .map_err(|source| SocketSetupError::CreateDirectory { path: parent.to_owned(), source })?;
+        info!("Created directory '{}' for the Unix Domain Socket.", parent);
+    }
     if file_as_path.exists() {
         // Delete any pre-existing socket file:
         std::fs::remove_file(&file_as_path) // Pass a reference, so we don't lose ownership.
-            .expect(&format!("ERROR: On deamon startup, could not remove preexisting socket file '{}'", file_as_path));
+            .map_err(|source| SocketSetupError::RemoveStale { path: file_as_path.clone(), source })?;
     }
-    let listener = UnixListener::bind(&file_as_path).unwrap();
+    let listener = UnixListener::bind(&file_as_path)
+        .map_err(|source| SocketSetupError::Bind { path: file_as_path.clone(), source })?;
+
+    // Connect once, then accept that connection right away, so the request loop never sees it.
+    let _probe: UnixStream = UnixStream::connect(&file_as_path)
+        .map_err(|source| SocketSetupError::SelfConnect { path: file_as_path.clone(), source })?;
+    listener.accept()
+        .map_err(|source| SocketSetupError::SelfConnect { path: file_as_path.clone(), source })?;
+
     info!("Listening for inbound traffic on Unix Domain Socket '{}'", file_as_path);
-    return listener;
+    Ok(listener)
 }
 
 /** 
@@ -80,129 +147,927 @@ pub fn update_socket_file_permissions(socket_file_path: &str, unix_group_name: &
 }
 
 
-pub fn handle_client_request(mut stream: UnixStream, 
-                             queue: Arc<Mutex<VecDeque<std::string::String>>>,
-                             app_config: &config::AppConfig) -> Result<String,std::io::Error> {
+/**
+  The version of the Unix Domain Socket protocol spoken by this daemon.\
+  Version 2 introduced the 'capabilities' handshake, and structured JSON errors for unknown requests.\
+  Version 3 introduced the optional 'idempotency_key'.\
+  Version 4 prefixes every message, in both directions, with its length (see btu_scheduler::socket_frame).
+*/
+pub const PROTOCOL_VERSION: u32 = 4;
 
-    /*
-        Part One:  Read bytes from a socket Client.
+/// How long a client may take to send its whole message.
+pub const SOCKET_READ_TIMEOUT_SECS: u64 = 5;
+
+/// Everything a request handler might need, besides the client's message.
+pub struct RequestContext {
+    pub queue: Arc<Mutex<ScheduleQueue>>,
+    pub app_config: Arc<config::AppConfig>,
+}
+
+/**
+  The result of handling one client request.\
+  'reply' is written back over the socket (if any), while 'result' is returned upward to the daemon for logging.
+*/
+pub struct RequestOutcome {
+    pub reply: Option<String>,
+    pub result: Result<String, std::io::Error>,
+}
+
+impl RequestOutcome {
+    fn replied(reply: String, summary: String) -> Self {
+        RequestOutcome { reply: Some(reply), result: Ok(summary) }
+    }
+    fn failed(reply: Option<String>, error_message: String) -> Self {
+        RequestOutcome {
+            reply,
+            result: Err(std::io::Error::other(error_message))
+        }
+    }
+}
+
+type RequestHandler = fn(&FrappeClientMessage, &RequestContext) -> RequestOutcome;
+
+/**
+  Every 'request_type' understood by the daemon, and the function that handles it.\
+  The 'capabilities' request reads this same table, so its answer cannot drift from reality.
+*/
+pub static REQUEST_HANDLERS: &[(&str, RequestHandler)] = &[
+    ("ping", handle_ping),
+    ("capabilities", handle_capabilities),
+    ("create_task_schedule", handle_create_task_schedule),
+    ("cancel_task_schedule", handle_cancel_task_schedule),
+    ("reschedule_next_run", handle_reschedule_next_run),
+    ("recent_logs", handle_recent_logs),
+    ("query_task_schedules", handle_query_task_schedules),
+    ("show_scheduled", handle_show_scheduled),
+    ("health", handle_health),
+    ("status", handle_status),
+    ("list_internal_queue", handle_list_internal_queue),
+    ("clear_internal_queue", handle_clear_internal_queue),
+    ("get_config", handle_get_config),
+    ("reload_config", handle_reload_config),
+];
 
-        Developers take note: there are MANY wrong ways to implement the code below.  None of which will create compiler errors.
+/// The most entries returned by a 'list_internal_queue' request.  The reply always includes the total length.
+pub const INTERNAL_QUEUE_LIST_LIMIT: usize = 1000;
 
-        * Reading too few bytes.  For example, create buffer as Vec::new() instead of a fixed length.
-        * Storing extra, empty bytes.  For example, by creating buffer as vec![0; 512]; or [0; 4096];
-        * Using 'stream.read_to_string()' or 'stream.read_to_end()'.  These expect an EOF that will never arrive, so the client Times Out.
+/// The summary of Thread #3's most recent cycle; None until the first cycle finishes.
+pub static LAST_CYCLE_SUMMARY: Lazy<Mutex<Option<CycleSummary>>> = Lazy::new(|| Mutex::new(None));
 
-        For the moment, I'm knowingly doing a Wrong Thing, because I don't have time to build the Right Thing.
-        1.  I'm creating a vector of 1k bytes.
-        2.  I'm reading what Python sends me.  (NOTE: If you try read_to_end() Python never thinks you finished reading, and times out.)
-        3.  The end of the 1k bytes are filled with 0's
-        4.  I strip them out.
-        5.  I now have a perfectly formed JSON string, which can be matched to a FrappeClientMessage struct.
+/// Returns the names of every request type in the registry.
+pub fn supported_request_types() -> Vec<&'static str> {
+    REQUEST_HANDLERS.iter().map(|(name, _)| *name).collect()
+}
 
-        TODO:
-        * Create a vector with capacity.
-        * Read only the bytes that are sent.
-        * Reply smartly to Python so it doesn't Time Out.
-    */
 
-    let mut buffer = [0; 1024];
-    stream.read(&mut buffer)?;
-    // dbg!("Buffer has length: {}", buffer.len());
-    let mut buffer_as_string = match std::str::from_utf8(&buffer) {
-        Ok(v) => v,
-        Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+pub fn handle_client_request(stream: UnixStream,
+                             queue: Arc<Mutex<ScheduleQueue>>,
+                             app_config: Arc<config::AppConfig>) -> Result<String,std::io::Error> {
+
+    let rate_per_min: u32 = app_config.socket_rate_limit_per_min.unwrap_or(DEFAULT_SOCKET_RATE_LIMIT_PER_MIN);
+    let idempotency_store = RedisIdempotencyStore { app_config: Arc::clone(&app_config) };
+    let context = RequestContext { queue, app_config };
+    handle_guarded_request(stream, &REQUEST_GUARD, rate_per_min,
+                           |client_message| dispatch_idempotent_request(client_message, &context, &idempotency_store))
+}
+
+/**
+  Read one message from a socket client, pass it through the RequestGuard to 'work', and write the reply.\
+  Separate from handle_client_request(), so the guard can be exercised with any work function.
+*/
+pub fn handle_guarded_request<F>(mut stream: UnixStream,
+                                 guard: &RequestGuard,
+                                 rate_per_min: u32,
+                                 work: F) -> Result<String,std::io::Error>
+    where F: FnOnce(&FrappeClientMessage) -> RequestOutcome {
+
+    // Part 1: Read the client's message.  A client that stalls mid-message is dropped after SOCKET_READ_TIMEOUT_SECS.
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(SOCKET_READ_TIMEOUT_SECS)))?;
+    let message: ReceivedMessage = match socket_frame::read_message(&mut stream, socket_frame::MAX_FRAME_BYTES) {
+        Ok(message) => message,
+        Err(FrameError::TooLarge { length, max_bytes }) => {
+            let error_string: String = format!("Client message of {} bytes exceeds the limit of {} bytes.", length, max_bytes);
+            error!("{}", error_string);
+            let reply = serde_json::json!({ "error": "message_too_large", "message": error_string, "max_bytes": max_bytes });
+            write_reply(&mut stream, &reply.to_string(), true);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, error_string));
+        },
+        Err(FrameError::Io { source }) => {
+            error!("Error while reading client message: {}", source);
+            return Err(source);
+        }
     };
-    buffer_as_string = buffer_as_string.trim_matches(char::from(0));  // remove all the training zero's
+    if !message.framed {
+        debug!("Client sent a message without a length prefix (protocol version 3 or older); replying the same way.");
+    }
 
     // Part 2: Response varies with request:
-    let client_message: Result<FrappeClientMessage, serde_json::Error> = serde_json::from_str(&buffer_as_string);
+    let client_message: Result<FrappeClientMessage, serde_json::Error> = serde_json::from_slice(&message.payload);
+
+    // Action and Response varies depending on the 'request_type'
+    let mut client_message: FrappeClientMessage = match client_message {
+        Ok(client_message) => client_message,
+        // If message from socket client cannot be coerced into a FrappeClientMessage, return an error String.
+        Err(error) => {
+            let error_string: String = error.to_string();
+            error!("Error while parsing client message: {}", &error_string);
+            return Err(std::io::Error::other(error_string));
+        }
+    };
+    // Every log line from here on carries the correlation ID; handlers read the same ID from the message.
+    let correlation_id: CorrelationId = CorrelationId::from_client(client_message.correlation_id.as_deref());
+    client_message.correlation_id = Some(correlation_id.as_str().to_owned());
+    let _span = span!(Level::INFO, "socket_request", correlation_id = %correlation_id).entered();
+    let outcome: RequestOutcome = guard.run(&client_message, rate_per_min, || work(&client_message));
 
-    // If message from socket client cannot be coerced into a FrappeClientMessage:
-    if client_message.is_err() {
-        let error_string: String = client_message.unwrap_err().to_string();
-        error!("Error while parsing client message: {}", &error_string);
-        let new_error = std::io::Error::new(std::io::ErrorKind::Other, error_string);
-        return Err(new_error);  // if cannot coerce into FrappeClientMessage, return an error String.
+    // Reply back to the Unix Domain Socket client:
+    if let Some(reply) = outcome.reply {
+        write_reply(&mut stream, &reply, message.framed);
     }
+    outcome.result
+}
 
-    // Action and Response varies depending on the 'request_type'
-    let client_message = client_message.unwrap();  // overshadow the original variable with the unwrapped contents.
-    match client_message.request_type.as_str() {
-        "ping" => {
-            info!("Frappe Web Server sent a 'ping' request ...");
-            let mut stream_out = stream.try_clone()?;
-            stream_out.write_all("pong".as_bytes()).expect("Failed to 'write_all'");
-            info!("...replied back with 'pong'");
-            return Ok("Replied to client's 'ping' with a 'pong'".to_owned())
-        },
-        "create_task_schedule" => {
-            // This request must have arrive with a 2nd argument: 'request_content'
-            if client_message.request_content.is_none() {
-                let new_error = std::io::Error::new(std::io::ErrorKind::Other, "Request 'build_task_schedule' missing required argument 'request_content'");
-                return Err(new_error);
+/**
+  Write a reply to the socket client.\
+  Clients with a short timeout may disconnect before the reply is written.  By then the request's work (queueing, cancelling)
+  is done, so a failed write is only logged; it does not change the request's outcome.  Rust ignores SIGPIPE, so a closed
+  socket is an error here, never a signal.  The reply is framed like the request was.
+*/
+fn write_reply(stream: &mut UnixStream, reply: &str, framed: bool) {
+    let written = if framed {
+        socket_frame::write_frame(stream, reply.as_bytes())
+    } else {
+        stream.write_all(reply.as_bytes()).and_then(|_| stream.flush())
+    };
+    if let Err(error) = written {
+        warn!("Client disconnected before reply could be written: {}", error);
+    }
+}
+
+/// Find the handler for a message's 'request_type' in the registry, and call it.
+pub fn dispatch_request(client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+
+    match REQUEST_HANDLERS.iter().find(|(name, _)| *name == client_message.request_type.as_str()) {
+        Some((_, handler)) => handler(client_message, context),
+        None => handle_unknown_request(client_message)
+    }
+}
+
+
+/*
+    Idempotency
+
+    The Frappe web app retries socket requests on timeout.  If the first reply was lost after the work was done,
+    a retry must not do the work twice.  Clients may send an 'idempotency_key'; successful responses are remembered
+    for that key, and replayed to any request arriving with the same key.
+
+    The key is reserved before the work is done (in Redis, with SET NX), so two requests with the same key that arrive
+    together cannot both do it: the second waits for the first's response, and replays it.  A request that fails
+    releases its reservation, so that a retry is processed.
+*/
+
+pub use btu_scheduler::redis_keys::IDEMPOTENCY_KEY_PREFIX;
+/// How long a stored response is replayed, unless 'idempotency_ttl_secs' is configured.
+pub const DEFAULT_IDEMPOTENCY_TTL_SECS: u64 = 600;
+/// How long a reservation lasts without a response; after a crash, the key is then free again.
+pub const IDEMPOTENCY_PENDING_TTL_SECS: u64 = 60;
+/// How long a request waits for another request, with the same key, to finish.
+pub const IDEMPOTENCY_WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+const IDEMPOTENCY_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// A response remembered for an idempotency key; or, while 'pending', the reservation of a request still being processed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredResponse {
+    pub request_type: String,
+    pub reply: Option<String>,
+    pub stored_at: i64,  // Unix Time
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pending: bool,
+}
+
+/// The outcome of trying to reserve an idempotency key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reservation {
+    Reserved,                // this request holds the key, and should do the work
+    Taken(StoredResponse),   // another request holds the key: its response, or its pending reservation
+    Unavailable,             // the store cannot be used; process the request normally
+}
+
+/// Somewhere to remember responses.  Every method is best-effort: failures behave like "nothing stored".
+pub trait IdempotencyStore {
+    /// Reserve the key for a request, unless another request already holds it.  Atomic.
+    fn reserve(&self, idempotency_key: &str, request_type: &str) -> Reservation;
+    fn fetch(&self, idempotency_key: &str) -> Option<StoredResponse>;
+    /// Replace the reservation with the response.
+    fn store(&self, idempotency_key: &str, response: &StoredResponse);
+    /// Give up the reservation; the request failed, and may be retried.
+    fn release(&self, idempotency_key: &str);
+}
+
+/// The daemon's store: one Redis string per key, 'btu_scheduler:idempotency:<key>', which expires after the TTL.
+pub struct RedisIdempotencyStore {
+    pub app_config: Arc<config::AppConfig>,
+}
+
+impl RedisIdempotencyStore {
+    fn ttl_secs(&self) -> u64 {
+        self.app_config.idempotency_ttl_secs.unwrap_or(DEFAULT_IDEMPOTENCY_TTL_SECS)
+    }
+}
+
+pub fn idempotency_redis_key(idempotency_key: &str) -> String {
+    format!("{}{}", IDEMPOTENCY_KEY_PREFIX, idempotency_key)
+}
+
+impl IdempotencyStore for RedisIdempotencyStore {
+
+    fn reserve(&self, idempotency_key: &str, request_type: &str) -> Reservation {
+        let Some(mut redis_conn) = rq::get_redis_connection(&self.app_config, false) else {
+            return Reservation::Unavailable;
+        };
+        let reservation = StoredResponse {
+            request_type: request_type.to_owned(),
+            reply: None,
+            stored_at: chrono::Utc::now().timestamp(),
+            pending: true,
+        };
+        let Ok(serialized) = serde_json::to_string(&reservation) else {
+            return Reservation::Unavailable;
+        };
+        // SET NX answers OK when the key was set, and nil when it already existed.
+        let result: Result<Option<String>, redis::RedisError> = redis::cmd("SET").arg(idempotency_redis_key(idempotency_key)).arg(serialized)
+            .arg("NX").arg("EX").arg(IDEMPOTENCY_PENDING_TTL_SECS.min(self.ttl_secs()).max(1))
+            .query(&mut redis_conn);
+        match result {
+            Ok(Some(_)) => Reservation::Reserved,
+            Ok(None) => match self.fetch(idempotency_key) {
+                Some(stored) => Reservation::Taken(stored),
+                None => Reservation::Unavailable,  // expired or released since; rare enough to just process the request
+            },
+            Err(error) => {
+                debug!("Unable to reserve idempotency key '{}': {}", idempotency_key, error);
+                Reservation::Unavailable
             }
-            let task_schedule_id = client_message.request_content.unwrap();
-            info!("Frappe Web Server requesting Task Schedule '{}' be processed for Python RQ.  Adding this to the Scheduler's internal queue.", task_schedule_id);
+        }
+    }
+
+    fn fetch(&self, idempotency_key: &str) -> Option<StoredResponse> {
+        use redis::Commands;
+
+        let mut redis_conn = rq::get_redis_connection(&self.app_config, false)?;
+        let stored: Option<String> = redis_conn.get(idempotency_redis_key(idempotency_key)).ok()?;
+        serde_json::from_str(&stored?).ok()
+    }
+
+    fn store(&self, idempotency_key: &str, response: &StoredResponse) {
+        use redis::Commands;
+
+        let Some(mut redis_conn) = rq::get_redis_connection(&self.app_config, false) else {
+            debug!("Redis unavailable; response for idempotency key '{}' was not stored.", idempotency_key);
+            return;
+        };
+        let Ok(serialized) = serde_json::to_string(response) else {
+            return;
+        };
+        let result: Result<(), redis::RedisError> = redis_conn.set_ex(idempotency_redis_key(idempotency_key), serialized, self.ttl_secs() as usize);
+        if let Err(error) = result {
+            warn!("Unable to store response for idempotency key '{}': {}", idempotency_key, error);
+        }
+    }
+
+    fn release(&self, idempotency_key: &str) {
+        use redis::Commands;
+
+        if let Some(mut redis_conn) = rq::get_redis_connection(&self.app_config, false) {
+            let _: Result<u32, redis::RedisError> = redis_conn.del(idempotency_redis_key(idempotency_key));
+        }
+    }
+}
+
+/**
+  Wraps dispatch_request() with idempotency.  Requests without an 'idempotency_key' are dispatched normally.\
+  Only successful outcomes are stored; a failed request may be retried.
+*/
+pub fn dispatch_idempotent_request(client_message: &FrappeClientMessage,
+                                   context: &RequestContext,
+                                   store: &dyn IdempotencyStore) -> RequestOutcome {
+    run_idempotent(client_message, store, || dispatch_request(client_message, context))
+}
+
+/// Run 'work' for a request at most once per idempotency key.  See the notes above.
+pub fn run_idempotent<F>(client_message: &FrappeClientMessage, store: &dyn IdempotencyStore, work: F) -> RequestOutcome
+    where F: FnOnce() -> RequestOutcome {
+
+    let Some(idempotency_key) = client_message.idempotency_key.as_deref().filter(|key| !key.is_empty()) else {
+        return work();
+    };
+
+    let waiting_since: Instant = Instant::now();
+    let reserved: bool = loop {
+        match store.reserve(idempotency_key, &client_message.request_type) {
+            Reservation::Reserved => break true,
+            Reservation::Unavailable => break false,
+            Reservation::Taken(stored) if stored.request_type != client_message.request_type => {
+                warn!("Idempotency key '{}' was previously used for a '{}' request; processing this '{}' request normally.",
+                      idempotency_key, stored.request_type, client_message.request_type);
+                break false;
+            },
+            Reservation::Taken(stored) if !stored.pending => {
+                info!("Replaying stored response for '{}' request with idempotency key '{}'.", stored.request_type, idempotency_key);
+                return RequestOutcome {
+                    reply: stored.reply,
+                    result: Ok(format!("Replayed stored response for idempotency key '{}'.", idempotency_key))
+                };
+            },
+            Reservation::Taken(_) if waiting_since.elapsed() >= IDEMPOTENCY_WAIT => {
+                let error_message: String = format!("A '{}' request with idempotency key '{}' is still being processed; try again later.",
+                                                    client_message.request_type, idempotency_key);
+                let reply: String = serde_json::json!({ "error": "in_progress", "message": error_message }).to_string();
+                return RequestOutcome::failed(Some(reply), error_message);
+            },
+            // Another request with this key is being processed; wait for its response (or for it to give up the key).
+            Reservation::Taken(_) => std::thread::sleep(IDEMPOTENCY_POLL_INTERVAL),
+        }
+    };
+
+    let outcome: RequestOutcome = work();
+    if !reserved {
+        return outcome;
+    }
+    if outcome.result.is_ok() {
+        store.store(idempotency_key, &StoredResponse {
+            request_type: client_message.request_type.clone(),
+            reply: outcome.reply.clone(),
+            stored_at: chrono::Utc::now().timestamp(),
+            pending: false,
+        });
+    }
+    else {
+        store.release(idempotency_key);
+    }
+    outcome
+}
+
+/*
+    Rate limiting and coalescing
+
+    A misbehaving client may send the same request in a tight loop.  Requests are fingerprinted by their 'request_type'
+    and 'request_content' (correlation IDs and idempotency keys are ignored).  Each fingerprint has a token bucket,
+    holding 'socket_rate_limit_per_min' tokens and refilled at that rate; once empty, the client is told when to retry,
+    and the work is not done.  While a request is being processed, an identical request waits for the first to finish,
+    and shares its reply; it does not use a token.  Buckets are kept in memory, for at most REQUEST_GUARD_CAPACITY
+    fingerprints; the least recently used is forgotten first.
+*/
+
+/// Identical socket requests allowed per minute, unless 'socket_rate_limit_per_min' is configured.
+pub const DEFAULT_SOCKET_RATE_LIMIT_PER_MIN: u32 = 30;
+/// The number of request fingerprints whose token buckets are remembered.
+pub const REQUEST_GUARD_CAPACITY: usize = 1024;
+
+/// The daemon's guard, shared by every socket handler thread.
+pub static REQUEST_GUARD: Lazy<RequestGuard> = Lazy::new(|| RequestGuard::new(REQUEST_GUARD_CAPACITY));
+
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// The reply and result of a request, kept so that identical requests waiting on it can share them.
+#[derive(Clone)]
+struct SharedOutcome {
+    reply: Option<String>,
+    result: Result<String, String>,
+}
+
+/// A request being processed.  Waiting requests sleep on the Condvar until 'outcome' is filled.
+#[derive(Default)]
+struct InFlight {
+    outcome: Mutex<Option<SharedOutcome>>,
+    finished: Condvar,
+}
+
+#[derive(Default)]
+struct GuardState {
+    buckets: HashMap<String, TokenBucket>,
+    in_flight: HashMap<String, Arc<InFlight>>,
+}
+
+/// Rate limits and coalesces identical socket requests.  See the notes above.
+pub struct RequestGuard {
+    capacity: usize,
+    state: Mutex<GuardState>,
+}
 
-            // Wait until last possible moment to obtain lock on internal queue.  Drop immediately when done.
-            if let Ok(mut unlocked_queue) = queue.lock() {
-                unlocked_queue.push_back(task_schedule_id.clone());  // VecDequeue takes ownership forever; need to clone here to continue using 'task_schedule_id'
+/// What to do with a request, decided while holding the guard's lock.
+enum Admission {
+    Run(Arc<InFlight>),
+    Wait(Arc<InFlight>),
+    RateLimited { retry_after_secs: u64 },
+}
+
+impl RequestGuard {
+
+    pub fn new(capacity: usize) -> Self {
+        RequestGuard { capacity: capacity.max(1), state: Mutex::new(GuardState::default()) }
+    }
+
+    /// Requests are identical when their type and content are.
+    pub fn fingerprint(client_message: &FrappeClientMessage) -> String {
+        format!("{}\u{0}{}", client_message.request_type, client_message.request_content.as_deref().unwrap_or(""))
+    }
+
+    /// The number of fingerprints with a token bucket.  Never more than the guard's capacity.
+    pub fn tracked_fingerprints(&self) -> usize {
+        self.state.lock().unwrap().buckets.len()
+    }
+
+    /**
+      Run 'work' for a request, unless an identical request is already running (its outcome is shared), or the
+      request's token bucket is empty (a 'rate_limited' reply is returned).  A 'rate_per_min' of 0 disables rate limiting.
+    */
+    pub fn run<F>(&self, client_message: &FrappeClientMessage, rate_per_min: u32, work: F) -> RequestOutcome
+        where F: FnOnce() -> RequestOutcome {
+
+        let fingerprint: String = Self::fingerprint(client_message);
+        match self.admit(&fingerprint, rate_per_min, Instant::now()) {
+            Admission::RateLimited { retry_after_secs } => rate_limited_outcome(client_message, retry_after_secs),
+            Admission::Wait(in_flight) => {
+                let mut outcome = in_flight.outcome.lock().unwrap();
+                while outcome.is_none() {
+                    outcome = in_flight.finished.wait(outcome).unwrap();
+                }
+                let shared: SharedOutcome = outcome.clone().unwrap();
+                debug!("Shared the outcome of an identical '{}' request that was already being processed.", client_message.request_type);
+                match shared.result {
+                    Ok(summary) => RequestOutcome { reply: shared.reply, result: Ok(summary) },
+                    Err(error_message) => RequestOutcome::failed(shared.reply, error_message),
+                }
+            },
+            Admission::Run(in_flight) => {
+                // Even if 'work' panics, the waiting requests must be released.
+                let mut finish = FinishInFlight { guard: self, fingerprint: &fingerprint, in_flight: &in_flight, outcome: None };
+                let outcome: RequestOutcome = work();
+                finish.outcome = Some(SharedOutcome {
+                    reply: outcome.reply.clone(),
+                    result: outcome.result.as_ref().map(String::clone).map_err(|error| error.to_string()),
+                });
+                outcome
             }
-            else {
-                let new_error = std::io::Error::new(std::io::ErrorKind::Other, "Error in function 'handle_client_request' while attempting to unlock internal queue.");
-                return Err(new_error);
+        }
+    }
+
+    fn admit(&self, fingerprint: &str, rate_per_min: u32, now: Instant) -> Admission {
+        let mut state = self.state.lock().unwrap();
+        if let Some(in_flight) = state.in_flight.get(fingerprint) {
+            return Admission::Wait(Arc::clone(in_flight));
+        }
+        if rate_per_min > 0 {
+            let capacity: f64 = f64::from(rate_per_min);
+            let tokens_per_sec: f64 = capacity / 60.0;
+            if !state.buckets.contains_key(fingerprint) && state.buckets.len() >= self.capacity {
+                // Forget the least recently used fingerprint.  The bucket map is small, so a scan is cheap enough.
+                let oldest: Option<String> = state.buckets.iter()
+                    .min_by_key(|(_, bucket)| bucket.refilled_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    state.buckets.remove(&oldest);
+                }
             }
-            // Reply back to Unix Domain Socket client:
-            let mut stream_out = stream.try_clone()?;
-            stream_out.write_all(format!("BTU Scheduler now re-processing Task Schedule {} in Python RQ.",task_schedule_id)
-                .as_bytes()).expect("Failed to 'write_all'");
-            return Ok("Replied successfully to UDS client's 'build_task_schedule' request.".to_owned())
-        },
-        "cancel_task_schedule" => {
-            // This request must have arrive with a 2nd argument: 'request_content', which is the Task Schedule ID.
-            if client_message.request_content.is_none() {
-                let new_error = std::io::Error::new(std::io::ErrorKind::Other, "Request 'cancel_task_schedule' missing required argument 'request_content'");
-                return Err(new_error);
+            let bucket = state.buckets.entry(fingerprint.to_owned())
+                .or_insert(TokenBucket { tokens: capacity, refilled_at: now });
+            let elapsed_secs: f64 = now.saturating_duration_since(bucket.refilled_at).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed_secs * tokens_per_sec).min(capacity);
+            bucket.refilled_at = now;
+            if bucket.tokens < 1.0 {
+                let retry_after_secs: u64 = ((1.0 - bucket.tokens) / tokens_per_sec).ceil() as u64;
+                return Admission::RateLimited { retry_after_secs: retry_after_secs.max(1) };
             }
-            let task_schedule_id = client_message.request_content.unwrap();
-            info!("Frappe Web Server requesting Task Schedule '{}' be cancelled in Python RQ.", task_schedule_id);
-
-            let mut stream_out = stream.try_clone()?;
-            // Try to cancel, and reply back to the UDS Client:
-            match rq_cancel_scheduled_task(app_config, &task_schedule_id) {
-                Ok(_) => {
-                    let okay_message: String = format!("Successfully cancelled BTU Task Schedule {} in Python RQ.",task_schedule_id);
-                    info!("{}", okay_message);
-                    stream_out.write_all(okay_message.as_bytes()).expect("Failed to 'write_all'");
-
-                    // Before finishing, log the Tasks that are still known to the BTU:
-                    crate::scheduler::rq_print_scheduled_tasks(&app_config, false);      
-                    return Ok(okay_message)
-                },
-                Err(error_message) => {
-                    stream_out.write_all(error_message.as_bytes()).expect("Failed to 'write_all'");
-                    let new_error = std::io::Error::new(std::io::ErrorKind::Other, error_message);
-                    return Err(new_error);
-                }
+            bucket.tokens -= 1.0;
+        }
+        let in_flight: Arc<InFlight> = Arc::new(InFlight::default());
+        state.in_flight.insert(fingerprint.to_owned(), Arc::clone(&in_flight));
+        Admission::Run(in_flight)
+    }
+}
+
+/// Publishes a running request's outcome to those waiting on it, and removes it from the in-flight requests; when dropped.
+struct FinishInFlight<'a> {
+    guard: &'a RequestGuard,
+    fingerprint: &'a str,
+    in_flight: &'a InFlight,
+    outcome: Option<SharedOutcome>,
+}
+
+impl Drop for FinishInFlight<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.guard.state.lock() {
+            state.in_flight.remove(self.fingerprint);
+        }
+        let outcome: SharedOutcome = self.outcome.take().unwrap_or_else(|| SharedOutcome {
+            reply: None,
+            result: Err("An identical request failed while being processed.".to_owned()),
+        });
+        if let Ok(mut slot) = self.in_flight.outcome.lock() {
+            *slot = Some(outcome);
+        }
+        self.in_flight.finished.notify_all();
+    }
+}
+
+fn rate_limited_outcome(client_message: &FrappeClientMessage, retry_after_secs: u64) -> RequestOutcome {
+    let error_string: String = format!("Rate limited: too many identical '{}' requests; retry after {} seconds.",
+                                       client_message.request_type, retry_after_secs);
+    warn!("{}", error_string);
+    let reply = serde_json::json!({
+        "error": "rate_limited",
+        "request_type": client_message.request_type,
+        "message": error_string,
+        "retry_after_secs": retry_after_secs,
+    });
+    RequestOutcome::failed(Some(reply.to_string()), error_string)
+}
+
+fn handle_ping(_client_message: &FrappeClientMessage, _context: &RequestContext) -> RequestOutcome {
+    info!("Frappe Web Server sent a 'ping' request ...");
+    info!("...replied back with 'pong'");
+    RequestOutcome::replied("pong".to_owned(), "Replied to client's 'ping' with a 'pong'".to_owned())
+}
+
+/// Reply with the protocol version, daemon version, and supported request types; so clients can gate features.
+fn handle_capabilities(_client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+    info!("Frappe Web Server sent a 'capabilities' request.");
+    let identity = btu_scheduler::logging::InstanceIdentity::from_config(&context.app_config);
+    let reply = serde_json::json!({
+        "protocol_version": PROTOCOL_VERSION,
+        "daemon_version": btu_scheduler::get_package_version(),
+        "request_types": supported_request_types(),
+        "framing": "length_prefix_u32_be",
+        "max_message_bytes": socket_frame::MAX_FRAME_BYTES,
+        "environment": identity.environment_name,
+        "instance_id": identity.instance_id,
+    });
+    RequestOutcome::replied(reply.to_string(), "Replied to client's 'capabilities' request.".to_owned())
+}
+
+fn handle_create_task_schedule(client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+
+    // This request must have arrive with a 2nd argument: 'request_content'
+    let Some(request_content) = client_message.request_content.as_deref() else {
+        return RequestOutcome::failed(None, "Request 'create_task_schedule' missing required argument 'request_content'".to_owned());
+    };
+    let task_schedule_id: TaskScheduleId = match TaskScheduleId::new(request_content) {
+        Ok(task_schedule_id) => task_schedule_id,
+        Err(error) => return RequestOutcome::failed(None, format!("Request 'create_task_schedule' has an invalid 'request_content': {}", error)),
+    };
+    let correlation_id: CorrelationId = CorrelationId::from_client(client_message.correlation_id.as_deref());
+    info!("Frappe Web Server requesting Task Schedule '{}' be processed for Python RQ (correlation ID '{}').  Adding this to the Scheduler's internal queue.",
+          task_schedule_id, correlation_id);
+
+    // Wait until last possible moment to obtain lock on internal queue.  Drop immediately when done.
+    if let Ok(mut unlocked_queue) = context.queue.lock() {
+        // High priority: the user just edited this schedule, and should not wait behind a full refresh.
+        let entry = QueueEntry::new(task_schedule_id.clone(), QueueSource::Socket).with_correlation_id(correlation_id.clone());
+        if !unlocked_queue.push_priority(entry) {  // the queue takes ownership forever; need to clone here to continue using 'task_schedule_id'
+            info!("Task Schedule '{}' was already waiting in the internal queue at high priority; it is processed once, without this correlation ID.", task_schedule_id);
+        }
+    }
+    else {
+        return RequestOutcome::failed(None, "Error in function 'handle_create_task_schedule' while attempting to unlock internal queue.".to_owned());
+    }
+    RequestOutcome::replied(
+        format!("BTU Scheduler now re-processing Task Schedule {} in Python RQ (correlation ID {}).", task_schedule_id, correlation_id),
+        "Replied successfully to UDS client's 'create_task_schedule' request.".to_owned()
+    )
+}
+
+fn handle_cancel_task_schedule(client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+
+    // This request must have arrive with a 2nd argument: 'request_content', which is the Task Schedule ID.
+    let Some(request_content) = client_message.request_content.as_deref() else {
+        return RequestOutcome::failed(None, "Request 'cancel_task_schedule' missing required argument 'request_content'".to_owned());
+    };
+    let task_schedule_id: TaskScheduleId = match TaskScheduleId::new(request_content) {
+        Ok(task_schedule_id) => task_schedule_id,
+        Err(error) => return RequestOutcome::failed(None, format!("Request 'cancel_task_schedule' has an invalid 'request_content': {}", error)),
+    };
+    info!("Frappe Web Server requesting Task Schedule '{}' be cancelled in Python RQ.", task_schedule_id);
+
+    // Try to cancel, and reply back to the UDS Client:
+    match rq_cancel_scheduled_task(&context.app_config, &task_schedule_id) {
+        Ok(outcome) => {
+            let okay_message: String = format!("Successfully cancelled BTU Task Schedule {} in Python RQ; removed {} upcoming runs and {} auxiliary keys.",
+                                               task_schedule_id, outcome.removed_runs, outcome.removed_auxiliary);
+            info!("{}", okay_message);
+            // Before finishing, log the Tasks that are still known to the BTU:
+            if let Err(error) = crate::scheduler::rq_print_scheduled_tasks(&context.app_config, false) {
+                warn!("Unable to list the scheduled Task Schedules: {}", error);
             }
+            RequestOutcome::replied(okay_message.clone(), okay_message)
         },
+        Err(error_message) => {
+            RequestOutcome::failed(Some(error_message.clone()), error_message)
+        }
+    }
+}
 
-        _ => {
-            // No match for the 'request_type'
-            let error_string: String =  format!("Client message has an unhandled 'request_type': {}", client_message.request_type);
-            let mut stream_out = stream.try_clone()?;
-            // 1. Return an message over the UDS to the client:
-            stream_out.write_all(error_string.as_bytes()).expect("Failed to 'write_all'"); // Return this error to the caller
-            // 2. Print the same error message to stdout
-            error!("{}", error_string);
-            // 3. Return the error upward
-            let new_error = std::io::Error::new(std::io::ErrorKind::Other, error_string);
-            return Err(new_error);
+/// The 'request_content' of a 'reschedule_next_run' request.  Exactly one of 'until' and 'by' is required.
+#[derive(Deserialize, Debug)]
+pub struct RescheduleRequest {
+    pub task_schedule_id: String,
+    #[serde(default)]
+    pub until: Option<String>,  // RFC 3339, such as "2024-07-01T04:00:00Z"
+    #[serde(default)]
+    pub by: Option<String>,     // relative to the current next run, such as "2h" or "-30m"
+}
+
+impl RescheduleRequest {
+    pub fn target(&self) -> Result<RescheduleTarget, String> {
+        match (self.until.as_deref(), self.by.as_deref()) {
+            (Some(until), None) => RescheduleTarget::parse_until(until).map_err(|error| error.to_string()),
+            (None, Some(by)) => RescheduleTarget::parse_by(by).map_err(|error| error.to_string()),
+            _ => Err("exactly one of 'until' and 'by' is required".to_owned()),
+        }
+    }
+}
+
+/**
+  Move a Task Schedule's next run (a "snooze", or a "run early").  'request_content' is JSON, such as
+  '{"task_schedule_id": "TS-000042", "by": "2h"}'.  Replies with JSON naming the previous and new next run.
+*/
+fn handle_reschedule_next_run(client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+
+    let Some(request_content) = client_message.request_content.as_deref() else {
+        return RequestOutcome::failed(None, "Request 'reschedule_next_run' missing required argument 'request_content'".to_owned());
+    };
+    let parsed: Result<(TaskScheduleId, RescheduleTarget), String> = serde_json::from_str::<RescheduleRequest>(request_content)
+        .map_err(|error| error.to_string())
+        .and_then(|request| {
+            let task_schedule_id = TaskScheduleId::new(&request.task_schedule_id).map_err(|error| error.to_string())?;
+            Ok((task_schedule_id, request.target()?))
+        });
+    let (task_schedule_id, target) = match parsed {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            let error_message = format!("Request 'reschedule_next_run' has an invalid 'request_content': {}", error);
+            return RequestOutcome::failed(Some(error_message.clone()), error_message);
+        }
+    };
+    info!("Frappe Web Server requesting the next run of Task Schedule '{}' be moved.", task_schedule_id);
+    let Some(mut redis_conn) = rq::get_redis_connection(&context.app_config, false) else {
+        let error_message = RescheduleError::NoConnection.to_string();
+        return RequestOutcome::failed(Some(error_message.clone()), error_message);
+    };
+    match reschedule_by_operator(&context.app_config, &mut redis_conn, &task_schedule_id, target) {
+        Ok(rescheduled) => {
+            let reply = serde_json::json!({
+                "task_schedule_id": task_schedule_id,
+                "previous_run": rescheduled.previous.to_rfc3339(),
+                "next_run": rescheduled.next.to_rfc3339(),
+            });
+            RequestOutcome::replied(reply.to_string(), format!("Moved the next run of Task Schedule {} to {}.", task_schedule_id, rescheduled.next.to_rfc3339()))
+        },
+        Err(error) => {
+            let error_message = format!("Cannot move the next run of Task Schedule {}: {}", task_schedule_id, error);
+            RequestOutcome::failed(Some(error_message.clone()), error_message)
+        }
+    }
+}
+
+/// Reply with the newest N log events as JSON.  N is read from 'request_content'; when missing, the entire history is returned.
+fn handle_recent_logs(client_message: &FrappeClientMessage, _context: &RequestContext) -> RequestOutcome {
+
+    let history = &crate::logging::LOG_HISTORY;
+    let number: usize = match client_message.request_content.as_deref() {
+        None => history.capacity(),
+        Some(content) => match content.trim().parse::<usize>() {
+            Ok(value) => value.min(history.capacity()),
+            Err(_) => {
+                let error_message = format!("Request 'recent_logs' expects a number in 'request_content', but found '{}'", content);
+                return RequestOutcome::failed(Some(error_message.clone()), error_message);
+            }
+        }
+    };
+    let entries = history.newest(number);
+    let reply: String = serde_json::to_string(&entries).unwrap_or_else(|_| "[]".to_owned());
+    RequestOutcome::replied(reply, format!("Replied to client's 'recent_logs' request with {} entries.", entries.len()))
+}
+
+/// Reply with how long until each Task Schedule next runs.  'request_content' is one or more comma-separated Task Schedule IDs.
+fn handle_query_task_schedules(client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+
+    let Some(content) = client_message.request_content.as_deref() else {
+        return RequestOutcome::failed(None, "Request 'query_task_schedules' missing required argument 'request_content'".to_owned());
+    };
+    let task_schedules: Vec<serde_json::Value> = content.split(',')
+        .map(str::trim)
+        .filter(|task_schedule_id| !task_schedule_id.is_empty())
+        .map(|task_schedule_id| {
+            task_schedule_next_run_json(task_schedule_id,
+                                        &crate::scheduler::time_until_next_run(&context.app_config, task_schedule_id),
+                                        crate::scheduler::read_unschedulable_marker(&context.app_config, task_schedule_id).as_ref(),
+                                        crate::scheduler::read_cron_classification(&context.app_config, task_schedule_id).as_ref())
+        })
+        .collect();
+    let summary: String = format!("Replied to client's 'query_task_schedules' request with {} Task Schedules.", task_schedules.len());
+    RequestOutcome::replied(serde_json::json!({ "task_schedules": task_schedules }).to_string(), summary)
+}
+
+/// Every Next Execution Time waiting in Redis, soonest first, as a JSON array.  Empty when Redis cannot be read.
+fn handle_show_scheduled(_client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+
+    let scheduled: Vec<RQScheduledTask> = crate::scheduler::rq_get_scheduled_tasks(&context.app_config)
+        .unwrap_or_else(|error| {
+            warn!("Unable to read the scheduled Task Schedules: {}", error);
+            VecRQScheduledTask::new()
+        })
+        .sort_by_next_datetime().into();
+    match serde_json::to_string(&scheduled) {
+        Ok(reply) => {
+            let summary: String = format!("Replied to client's 'show_scheduled' request with {} scheduled Task Schedules.", scheduled.len());
+            RequestOutcome::replied(reply, summary)
+        },
+        Err(error) => RequestOutcome::failed(None, format!("Unable to serialize the scheduled Task Schedules to JSON: {}", error))
+    }
+}
+
+/**
+  One Task Schedule's entry in the 'query_task_schedules' reply.\
+  'next_run_in_secs' is negative when the next run is past due, and null when nothing is scheduled (or on error).\
+  A Task Schedule whose cron expression yields no Next Execution Time is flagged with 'unschedulable' and 'unschedulable_reason'.\
+  A cron expression's 'cron' entry has its normalized 7-element form, and any seconds or year that Frappe's UI cannot show.
+*/
+pub fn task_schedule_next_run_json(task_schedule_id: &str,
+                                   time_until_next_run: &Result<Option<chrono::Duration>, btu_scheduler::errors::SchedulerError>,
+                                   unschedulable: Option<&UnschedulableMarker>,
+                                   cron: Option<&CronClassification>) -> serde_json::Value {
+    let mut entry: serde_json::Value = match time_until_next_run {
+        Ok(duration) => serde_json::json!({
+            "task_schedule_id": task_schedule_id,
+            "next_run_in_secs": duration.map(|duration| duration.num_seconds()),
+        }),
+        Err(error) => serde_json::json!({
+            "task_schedule_id": task_schedule_id,
+            "next_run_in_secs": null,
+            "error": error.to_string(),
+        }),
+    };
+    if let Some(marker) = unschedulable {
+        entry["unschedulable"] = serde_json::Value::Bool(true);
+        entry["unschedulable_reason"] = serde_json::Value::String(marker.reason.clone());
+    }
+    if let Some(cron) = cron {
+        entry["cron"] = serde_json::to_value(cron).unwrap_or_default();
+    }
+    entry
+}
+
+/// Reply with the daemon's version and identity, a summary of the scheduler's last cycle ('last_cycle' is null before the first),
+/// and the number of RQ Jobs in Redis.
+fn handle_health(_client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+    let identity = btu_scheduler::logging::InstanceIdentity::from_config(&context.app_config);
+    let last_cycle: Option<CycleSummary> = LAST_CYCLE_SUMMARY.lock().ok().and_then(|summary| summary.clone());
+    // Counted with SCAN, so job-key growth can be graphed.  Null when Redis cannot be read.
+    let rq_job_count: Option<u64> = btu_scheduler::rq::count_jobs(&context.app_config)
+        .map_err(|error| debug!("Unable to count RQ Jobs for the 'health' request: {}", error))
+        .ok();
+    // Seconds since BTU last enqueued a job on each queue.  Null when Redis cannot be read.
+    let queue_last_enqueue_age_secs = rq::get_redis_connection(&context.app_config, false)
+        .and_then(|mut redis_conn| btu_scheduler::queue_stats::last_enqueue_ages(&mut redis_conn, chrono::Utc::now())
+            .map_err(|error| debug!("Unable to read last enqueue times for the 'health' request: {}", error))
+            .ok());
+    let reply = serde_json::json!({
+        "daemon_version": btu_scheduler::get_package_version(),
+        "environment": identity.environment_name,
+        "instance_id": identity.instance_id,
+        "last_cycle": last_cycle,
+        "rq_job_count": rq_job_count,
+        "queue_last_enqueue_age_secs": queue_last_enqueue_age_secs,
+        "sql_queries": btu_scheduler::metrics::query_stats_snapshot(),
+        "mysql": btu_scheduler::validate_sql_credentials(&context.app_config),
+        "platform": btu_scheduler::platform::PlatformInfo::detect(),
+    });
+    RequestOutcome::replied(reply.to_string(), "Replied to client's 'health' request.".to_owned())
+}
+
+/// Reply with what the worker threads last did, and whether Redis and MariaDB could last be reached; see daemon_status.
+/// Unlike 'health', nothing is queried, so it is cheap enough to poll.
+fn handle_status(_client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+    let queue_length: Option<usize> = context.queue.lock().ok().map(|unlocked_queue| unlocked_queue.len());
+    let report = DAEMON_STATUS.report(*DAEMON_STARTED_AT, chrono::Utc::now(), queue_length);
+    match serde_json::to_string(&report) {
+        Ok(reply) => RequestOutcome::replied(reply, "Replied to client's 'status' request.".to_owned()),
+        Err(error) => RequestOutcome::failed(None, format!("Unable to serialize the daemon's status: {}", error)),
+    }
+}
+
+/// Reply with the entries pending in the Internal Queue (at most INTERNAL_QUEUE_LIST_LIMIT), plus its total length.
+fn handle_list_internal_queue(_client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+
+    // Copy under the lock, then release it before serializing.  Thread #1 waits on this same lock.
+    let (entries, total, high_priority_len) = match context.queue.lock() {
+        Ok(unlocked_queue) => (unlocked_queue.snapshot(INTERNAL_QUEUE_LIST_LIMIT), unlocked_queue.len(), unlocked_queue.high_priority_len()),
+        Err(_) => return RequestOutcome::failed(None, "Error in function 'handle_list_internal_queue' while attempting to unlock internal queue.".to_owned())
+    };
+    let summary: String = format!("Replied to client's 'list_internal_queue' request with {} of {} entries.", entries.len(), total);
+    RequestOutcome::replied(internal_queue_json(&entries, total, high_priority_len).to_string(), summary)
+}
+
+/**
+  The 'list_internal_queue' reply.\
+  'entries' are in processing order; the first 'high_priority_len' of the whole queue are high priority.
+*/
+pub fn internal_queue_json(entries: &[QueueEntry], total: usize, high_priority_len: usize) -> serde_json::Value {
+    let entries: Vec<serde_json::Value> = entries.iter().enumerate().map(|(index, entry)| serde_json::json!({
+        "schedule_id": entry.schedule_id,
+        "source": entry.source.to_string(),
+        "priority": if index < high_priority_len { "high" } else { "normal" },
+        "enqueued_at": entry.enqueued_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        "attempt": entry.attempt,
+        "not_before": entry.not_before.map(|not_before| not_before.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+    })).collect();
+    serde_json::json!({
+        "total": total,
+        "truncated": entries.len() < total,
+        "entries": entries,
+    })
+}
+
+/// Discard every entry in the Internal Queue; for when a bad bulk import flooded it.  'request_content' must be "confirm".
+fn handle_clear_internal_queue(client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+
+    if client_message.request_content.as_deref().map(str::trim) != Some("confirm") {
+        let error_message = "Request 'clear_internal_queue' discards every pending entry; send 'confirm' in 'request_content' to proceed.".to_owned();
+        return RequestOutcome::failed(Some(error_message.clone()), error_message);
+    }
+    /*  Post-run requeues are kept.  Each is a Task Schedule that just ran, and has no Next Execution Time in Redis until
+        Thread #1 writes one; discarding it would skip that schedule's runs until the next full refresh.
+    */
+    let (discarded, kept): (usize, usize) = match context.queue.lock() {
+        Ok(mut unlocked_queue) => (unlocked_queue.clear_except(QueueSource::PostRunRequeue), unlocked_queue.len()),
+        Err(_) => return RequestOutcome::failed(None, "Error in function 'handle_clear_internal_queue' while attempting to unlock internal queue.".to_owned())
+    };
+    warn!("A client cleared the internal queue: {} entries were discarded, and will return with the next full refresh.  {} post-run requeues were kept.",
+          discarded, kept);
+    RequestOutcome::replied(serde_json::json!({ "discarded": discarded, "kept": kept }).to_string(),
+                            format!("Replied to client's 'clear_internal_queue' request; discarded {} entries, and kept {}.", discarded, kept))
+}
+
+/// Reply with the daemon's live configuration, secrets redacted.  It may differ from the file on disk, if that was edited since it was last read.
+fn handle_get_config(_client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+    info!("Frappe Web Server sent a 'get_config' request.");
+    RequestOutcome::replied(daemon_config_json(&context.app_config).to_string(), "Replied to client's 'get_config' request.".to_owned())
+}
+
+/**
+  The 'get_config' reply: the redacted configuration, and where it came from.\
+  'config_file' is null unless read from a file; 'environment_variables' lists the BTU_ variables read, when read from the environment.
+*/
+pub fn daemon_config_json(app_config: &config::AppConfig) -> serde_json::Value {
+    let source: &config::ConfigSource = app_config.source();
+    serde_json::json!({
+        "config_source": source.kind(),
+        "config_file": match source { config::ConfigSource::File(path) => Some(path.as_str()), _ => None },
+        "environment_variables": match source { config::ConfigSource::Environment(names) => names.as_slice(), _ => &[] },
+        "config": app_config.redacted_json(),
+    })
+}
+
+/// Read the configuration file again, and replace the running configuration if the file is valid.  The same as sending SIGHUP.
+fn handle_reload_config(_client_message: &FrappeClientMessage, context: &RequestContext) -> RequestOutcome {
+    info!("Frappe Web Server sent a 'reload_config' request.");
+    match crate::reload_app_config(&context.app_config) {
+        Ok(reload) => RequestOutcome::replied(config_reload_json(&reload).to_string(),
+                                              format!("Replied to client's 'reload_config' request; {} settings changed.", reload.changed.len())),
+        Err(error) => {
+            let reply = serde_json::json!({ "error": "invalid_config", "message": error.to_string() });
+            RequestOutcome::failed(Some(reply.to_string()), format!("Request 'reload_config' failed: {}", error))
         }
     }
 }
 
+/// The 'reload_config' reply: which settings took effect, and which wait for a restart.
+pub fn config_reload_json(reload: &config::ConfigReload) -> serde_json::Value {
+    serde_json::json!({
+        "reloaded": true,
+        "changed": reload.changed,
+        "restart_required": reload.restart_required,
+    })
+}
+
+/// No match for the 'request_type'.  Reply with a structured error, so clients can tell this apart from other failures.
+fn handle_unknown_request(client_message: &FrappeClientMessage) -> RequestOutcome {
+
+    let error_string: String =  format!("Client message has an unhandled 'request_type': {}", client_message.request_type);
+    error!("{}", error_string);
+    let reply = serde_json::json!({
+        "error": "unknown_request_type",
+        "request_type": client_message.request_type,
+        "message": error_string,
+        "protocol_version": PROTOCOL_VERSION,
+    });
+    RequestOutcome::failed(Some(reply.to_string()), error_string)
+}
+
 /*
     Known-to-be-good function for reading the Unix Domain Socket client data.
 
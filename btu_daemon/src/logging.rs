@@ -13,9 +13,15 @@
     4:  Error:  Designates very serious errors.
 */
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use chrono::{SecondsFormat, Utc};
-use tracing::Subscriber;
+use btu_scheduler::logging::InstanceIdentity;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::RwLock;
+use serde::Serialize;
+use tracing::{Level, Subscriber};
 use tracing_subscriber::Layer;
 
 
@@ -37,19 +43,136 @@ where
         event.record(&mut visitor);
 
         // Output the event in JSON
-        let output = serde_json::json!({
-			"timestamp":  Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
-            // "target": event.metadata().target(),
-            "name": event.metadata().name(),
-            "level": format!("{:?}", event.metadata().level()),
-            "fields": fields,
-        });
+        let output = format_log_event(event.metadata().name(), event.metadata().level(), fields, INSTANCE_IDENTITY.get());
         println!("{}", serde_json::to_string_pretty(&output).unwrap());
 
         // TODO: Send an email on certain Levels (e.g. email when WARN or ERROR)
     }
 }
 
+/// Identifies this daemon in every log line.  Set once during startup, after the configuration is read.
+pub static INSTANCE_IDENTITY: OnceCell<InstanceIdentity> = OnceCell::new();
+
+/// The JSON written for one event.  The 'environment' is omitted when none is configured, rather than written as null.
+pub fn format_log_event(name: &str, level: &Level, fields: BTreeMap<String, serde_json::Value>,
+                        identity: Option<&InstanceIdentity>) -> serde_json::Value {
+    let mut output = serde_json::json!({
+        "timestamp":  Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        // "target": event.metadata().target(),
+        "name": name,
+        "level": format!("{:?}", level),
+        "fields": fields,
+    });
+    if let Some(identity) = identity {
+        if let Some(environment_name) = &identity.environment_name {
+            output["environment"] = serde_json::json!(environment_name);
+        }
+        output["instance"] = serde_json::json!(identity.instance_id);
+    }
+    output
+}
+
+/// Default number of events kept by the in-memory log history.
+pub const DEFAULT_LOG_HISTORY_SIZE: usize = 500;
+
+/// One log event, as remembered by the in-memory log history.
+#[derive(Clone, Debug, Serialize)]
+pub struct LogEntry {
+    pub timestamp: String,
+    pub level: String,
+    pub thread_name: String,
+    pub message: String,
+}
+
+/**
+  A bounded history of recent log events, so the Frappe "Scheduler Log" page can display them without SSH access.\
+  When full, the oldest entry is discarded.
+*/
+pub struct LogHistory {
+    entries: RwLock<VecDeque<LogEntry>>,
+    capacity: AtomicUsize,
+}
+
+impl LogHistory {
+
+    pub fn new(capacity: usize) -> Self {
+        LogHistory {
+            entries: RwLock::new(VecDeque::with_capacity(capacity)),
+            capacity: AtomicUsize::new(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        let mut entries = self.entries.write();
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    /// Remember a new entry.  If the history is contended for too long, the entry is dropped; logging must stay cheap.
+    pub fn push(&self, entry: LogEntry) {
+        let capacity = self.capacity();
+        if capacity == 0 {
+            return;
+        }
+        if let Some(mut entries) = self.entries.try_write_for(Duration::from_millis(5)) {
+            while entries.len() >= capacity {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+    }
+
+    /// Returns up to 'number' of the newest entries, oldest first.
+    pub fn newest(&self, number: usize) -> Vec<LogEntry> {
+        let entries = self.entries.read();
+        let skip = entries.len().saturating_sub(number);
+        entries.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// The daemon's log history.  Resized from the configuration ('log_history_size') during startup.
+pub static LOG_HISTORY: Lazy<LogHistory> = Lazy::new(|| LogHistory::new(DEFAULT_LOG_HISTORY_SIZE));
+
+/// A tracing Layer that copies INFO-and-above events into LOG_HISTORY.
+pub struct LogHistoryLayer;
+
+impl<S> Layer<S> for LogHistoryLayer
+where
+    S: tracing::Subscriber,
+{
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        // Level ordering in tracing: TRACE is the "largest", ERROR the "smallest".
+        if *event.metadata().level() > Level::INFO {
+            return;
+        }
+        let mut fields = BTreeMap::new();
+        let mut visitor = JsonVisitor(&mut fields);
+        event.record(&mut visitor);
+
+        let message: String = match fields.remove("message") {
+            Some(serde_json::Value::String(value)) => value,
+            Some(other) => other.to_string(),
+            None => serde_json::Value::from(serde_json::Map::from_iter(fields)).to_string(),
+        };
+        LOG_HISTORY.push(LogEntry {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            level: event.metadata().level().to_string(),
+            thread_name: std::thread::current().name().unwrap_or("unnamed").to_owned(),
+            message,
+        });
+    }
+}
+
 // Again, I find this Visitor Pattern.  :thinking:
 struct JsonVisitor<'a>(&'a mut BTreeMap<String, serde_json::Value>);
 
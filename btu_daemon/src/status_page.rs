@@ -0,0 +1,267 @@
+/* status_page.rs */
+
+#![forbid(unsafe_code)]
+
+/*
+    An optional, read-only HTML status page, for spot checks without the Frappe UI.
+
+    Served over a hand-rolled HTTP/1.0 responder on a std TcpListener; there is one page, with no assets, and no way to change
+    anything.  Enabled by 'status_page_listen_addr'.  Only loopback addresses are accepted, unless 'status_page_allow_remote' is set.
+*/
+
+use std::{io::{Read, Write},
+          net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+          sync::{Arc, Mutex},
+          time::Duration};
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use tracing::{debug, info, warn, error};
+
+use btu_scheduler::config::AppConfig;
+use btu_scheduler::rq;
+use btu_scheduler::schedule_queue::ScheduleQueue;
+use btu_scheduler::scheduler::{self, CycleSummary};
+use crate::logging::{LogEntry, LOG_HISTORY};
+
+/// When the daemon started; forced early in main(), so the page can show its uptime.
+pub static DAEMON_STARTED_AT: Lazy<DateTime<Utc>> = Lazy::new(Utc::now);
+
+/// How many upcoming Task Schedules, and recent warnings and errors, the page shows.
+pub const STATUS_PAGE_ROWS: usize = 20;
+
+/// Why the status page could not be set up.
+#[derive(Debug)]
+pub enum StatusPageError {
+    InvalidAddress { address: String },
+    NotLoopback { address: String },
+    Bind { address: String, source: std::io::Error },
+}
+
+impl std::fmt::Display for StatusPageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatusPageError::InvalidAddress { address } =>
+                write!(f, "'status_page_listen_addr' = '{}' is not an address and port, such as '127.0.0.1:8975'.", address),
+            StatusPageError::NotLoopback { address } =>
+                write!(f, "Refusing to serve the status page on '{}', which is not a loopback address.  Set 'status_page_allow_remote' to allow it.", address),
+            StatusPageError::Bind { address, source } =>
+                write!(f, "Cannot serve the status page on '{}': {}", address, source),
+        }
+    }
+}
+
+impl std::error::Error for StatusPageError {}
+
+/// Bind the status page's listener.  Returns None when 'status_page_listen_addr' is not configured.
+pub fn bind_status_page(app_config: &AppConfig) -> Result<Option<TcpListener>, StatusPageError> {
+    let Some(address) = app_config.status_page_listen_addr.as_deref() else {
+        return Ok(None);
+    };
+    let socket_addresses: Vec<SocketAddr> = address.to_socket_addrs()
+        .map_err(|_| StatusPageError::InvalidAddress { address: address.to_owned() })?
+        .collect();
+    if socket_addresses.is_empty() {
+        return Err(StatusPageError::InvalidAddress { address: address.to_owned() });
+    }
+    if !app_config.status_page_allow_remote && !socket_addresses.iter().all(|socket_address| socket_address.ip().is_loopback()) {
+        return Err(StatusPageError::NotLoopback { address: address.to_owned() });
+    }
+    TcpListener::bind(socket_addresses.as_slice())
+        .map(Some)
+        .map_err(|source| StatusPageError::Bind { address: address.to_owned(), source })
+}
+
+/// Everything the page shows, gathered at the moment of the request.
+#[derive(Debug, Clone)]
+pub struct StatusSnapshot {
+    pub version: String,
+    pub environment: String,
+    pub uptime_secs: i64,
+    pub redis_connected: bool,
+    pub mysql_connected: bool,
+    pub queue_length: Option<usize>,  // None if the Internal Queue's lock could not be taken
+    pub upcoming: Vec<(String, String)>,  // (Task Schedule ID, Next Execution Time in local time)
+    pub last_cycle: Option<CycleSummary>,
+    pub recent_problems: Vec<LogEntry>,  // WARN and ERROR entries from the in-memory log history, newest last
+}
+
+impl StatusSnapshot {
+
+    pub fn collect(app_config: &AppConfig, queue: &Mutex<ScheduleQueue>) -> Self {
+        let redis_connected: bool = redis::Client::open(rq::redis_connection_info(app_config))
+            .and_then(|client| client.get_connection_with_timeout(Duration::from_secs(2)))
+            .is_ok();
+        let upcoming: Vec<(String, String)> = match (redis_connected, app_config.tz()) {
+            (true, Ok(time_zone)) => scheduler::rq_get_scheduled_tasks(app_config).unwrap_or_default().sort_by_next_datetime().iter()
+                .take(STATUS_PAGE_ROWS)
+                .map(|task| (task.task_schedule_id.to_string(), task.next_datetime_utc.with_timezone(&time_zone).format("%Y-%m-%d %H:%M:%S %Z").to_string()))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let recent_problems: Vec<LogEntry> = LOG_HISTORY.newest(usize::MAX).into_iter()
+            .filter(|entry| entry.level == "WARN" || entry.level == "ERROR")
+            .collect();
+        StatusSnapshot {
+            version: btu_scheduler::get_package_version().to_owned(),
+            environment: app_config.environment_name.clone().unwrap_or_default(),
+            uptime_secs: (Utc::now() - *DAEMON_STARTED_AT).num_seconds(),
+            redis_connected,
+            mysql_connected: btu_scheduler::validate_sql_credentials(app_config).is_ok(),
+            queue_length: queue.lock().ok().map(|unlocked_queue| unlocked_queue.len()),
+            upcoming,
+            last_cycle: crate::ipc_stream::LAST_CYCLE_SUMMARY.lock().ok().and_then(|summary| summary.clone()),
+            recent_problems: recent_problems[recent_problems.len().saturating_sub(STATUS_PAGE_ROWS)..].to_vec(),
+        }
+    }
+}
+
+/// A duration such as "2d 3h 14m", or "45s" when under a minute.
+pub fn format_uptime(seconds: i64) -> String {
+    let seconds: i64 = seconds.max(0);
+    let (days, hours, minutes) = (seconds / 86_400, seconds % 86_400 / 3600, seconds % 3600 / 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m", minutes),
+        (0, _, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h {}m", days, hours, minutes),
+    }
+}
+
+/// Escape text for HTML element content and attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// The status page, as HTML.
+pub fn render_status_page(snapshot: &StatusSnapshot) -> String {
+    let connected = |ok: bool| if ok { r#"<span class="ok">connected</span>"# } else { r#"<span class="bad">unreachable</span>"# };
+    let upcoming: String = if snapshot.upcoming.is_empty() {
+        "<p>None.</p>".to_owned()
+    } else {
+        let rows: Vec<String> = snapshot.upcoming.iter()
+            .map(|(schedule_id, local_time)| format!("<tr><td>{}</td><td>{}</td></tr>", escape_html(schedule_id), escape_html(local_time)))
+            .collect();
+        format!("<table>\n<tr><th>Task Schedule</th><th>Next run (local time)</th></tr>\n{}\n</table>", rows.join("\n"))
+    };
+    let problems: String = if snapshot.recent_problems.is_empty() {
+        "<p>None.</p>".to_owned()
+    } else {
+        let rows: Vec<String> = snapshot.recent_problems.iter().map(|entry| {
+            let class: &str = if entry.level == "ERROR" { "bad" } else { "warn" };
+            format!(r#"<tr><td>{}</td><td class="{}">{}</td><td>{}</td></tr>"#,
+                    escape_html(&entry.timestamp), class, escape_html(&entry.level), escape_html(&entry.message))
+        }).collect();
+        format!("<table>\n{}\n</table>", rows.join("\n"))
+    };
+    // The page's only template.  No assets: the style is inline, and the browser reloads the page every 30 seconds.
+    format!(r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="30">
+<title>BTU Scheduler status</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; margin-bottom: 1.5em; }}
+th, td {{ text-align: left; padding: 0.2em 1em 0.2em 0; }}
+.ok {{ color: #1a7f37; }} .bad {{ color: #cf222e; }} .warn {{ color: #9a6700; }}
+</style>
+</head>
+<body>
+<h1>BTU Scheduler</h1>
+<table>
+<tr><th>Version</th><td>{version}</td></tr>
+<tr><th>Environment</th><td>{environment}</td></tr>
+<tr><th>Uptime</th><td>{uptime}</td></tr>
+<tr><th>Redis</th><td>{redis}</td></tr>
+<tr><th>MariaDB</th><td>{mysql}</td></tr>
+<tr><th>Internal queue</th><td>{queue}</td></tr>
+<tr><th>Last cycle</th><td>{last_cycle}</td></tr>
+</table>
+<h2>Next scheduled tasks</h2>
+{upcoming}
+<h2>Recent warnings and errors</h2>
+{problems}
+<p><small>Read-only.  Refreshes every 30 seconds.  Generated {generated_at}.</small></p>
+</body>
+</html>
+"#,
+        version = escape_html(&snapshot.version),
+        environment = escape_html(&snapshot.environment),
+        uptime = format_uptime(snapshot.uptime_secs),
+        redis = connected(snapshot.redis_connected),
+        mysql = connected(snapshot.mysql_connected),
+        queue = snapshot.queue_length.map_or("(busy)".to_owned(), |length| format!("{} pending", length)),
+        last_cycle = snapshot.last_cycle.as_ref().map_or("(none yet)".to_owned(), |summary| escape_html(&summary.to_string())),
+        upcoming = upcoming,
+        problems = problems,
+        generated_at = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+/// Answer one HTTP request.  Only 'GET /' is served; nothing on this page can change the scheduler.
+pub fn handle_status_request<F: Fn() -> String>(mut stream: TcpStream, render: &F) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let mut buffer = [0; 2048];
+    let length: usize = stream.read(&mut buffer)?;
+    let request = String::from_utf8_lossy(&buffer[..length]);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (status, content_type, body): (&str, &str, String) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/")) | (Some("GET"), Some("/index.html")) => ("200 OK", "text/html; charset=utf-8", render()),
+        (Some("GET"), _) => ("404 Not Found", "text/plain; charset=utf-8", "Not found.\n".to_owned()),
+        _ => ("405 Method Not Allowed", "text/plain; charset=utf-8", "The status page is read-only.\n".to_owned()),
+    };
+    let response: String = format!("HTTP/1.0 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAllow: GET\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+                                   status, content_type, body.len(), body);
+    stream.write_all(response.as_bytes())
+}
+
+/// Serve the status page until the process ends.  Requests are answered one at a time; the page is for people, not for load.
+pub fn serve_status_page<F: Fn() -> String>(listener: TcpListener, render: F) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(error) = handle_status_request(stream, &render) {
+                    debug!("Status page: error while answering a request: {}", error);
+                }
+            },
+            Err(error) => warn!("Status page: cannot accept a connection: {}", error),
+        }
+    }
+}
+
+/// Bind and serve the page on its own thread, when configured.  A problem is logged; the scheduler runs on regardless.
+pub fn spawn_status_page(app_config: &AppConfig, queue: Arc<Mutex<ScheduleQueue>>) {
+    let listener: TcpListener = match bind_status_page(app_config) {
+        Ok(Some(listener)) => listener,
+        Ok(None) => return,
+        Err(error) => {
+            error!("{}", error);
+            return;
+        }
+    };
+    if let Ok(address) = listener.local_addr() {
+        info!("Serving the read-only status page at http://{}/", address);
+    }
+    let spawned = std::thread::Builder::new().name("Status_Page".to_string()).spawn(move || {
+        serve_status_page(listener, || {
+            let app_config: Arc<AppConfig> = crate::APP_CONFIG.load_full();
+            render_status_page(&StatusSnapshot::collect(&app_config, &queue))
+        });
+    });
+    if let Err(error) = spawned {
+        error!("Cannot spawn thread 'Status_Page': {}", error);
+    }
+}
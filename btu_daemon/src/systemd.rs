@@ -0,0 +1,172 @@
+/* systemd.rs */
+
+#![forbid(unsafe_code)]
+
+/*
+    Optional integration with systemd, for a unit with 'Type=notify' and (optionally) 'WatchdogSec='.  Enabled by 'systemd_notify'.
+
+    Once the three worker threads are running and the Unix Domain Socket is bound, the daemon sends READY=1 to $NOTIFY_SOCKET.
+    When systemd also asks for a watchdog ($WATCHDOG_USEC), a supervisor thread sends WATCHDOG=1 at half that interval, but only
+    while every worker thread has checked in recently.  A worker thread that died, or is stuck (for example, on a hung Redis),
+    stops the pings; systemd then restarts the daemon.
+*/
+
+use std::{ffi::OsStr,
+          os::unix::ffi::OsStrExt,
+          path::Path,
+          sync::atomic::{AtomicU64, Ordering},
+          time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use nix::sys::socket::{sendto, socket, AddressFamily, MsgFlags, SockAddr, SockFlag, SockType, UnixAddr};
+use tracing::{debug, info, warn, error};
+
+use btu_scheduler::config::{self, AppConfig};
+
+/// When one worker thread last checked in, as Unix seconds.  0 until its first check-in.
+pub struct ThreadHeartbeat {
+    pub thread_name: &'static str,
+    last_unix: AtomicU64,
+}
+
+impl ThreadHeartbeat {
+
+    pub const fn new(thread_name: &'static str) -> Self {
+        ThreadHeartbeat { thread_name, last_unix: AtomicU64::new(0) }
+    }
+
+    /// Record that the thread is alive, now.
+    pub fn beat(&self) {
+        self.beat_at(now_unix());
+    }
+
+    pub fn beat_at(&self, unix: u64) {
+        self.last_unix.store(unix, Ordering::Relaxed);
+    }
+
+    pub fn last_unix(&self) -> u64 {
+        self.last_unix.load(Ordering::Relaxed)
+    }
+}
+
+/// Each worker thread checks in once per loop.
+pub static INTERNAL_QUEUE_HEARTBEAT: ThreadHeartbeat = ThreadHeartbeat::new("1_Internal_Queue");
+pub static AUTO_REFILL_HEARTBEAT: ThreadHeartbeat = ThreadHeartbeat::new("2_Auto_Refill");
+pub static SCHEDULER_HEARTBEAT: ThreadHeartbeat = ThreadHeartbeat::new("3_Scheduler");
+
+pub fn worker_heartbeats() -> [&'static ThreadHeartbeat; 3] {
+    [&INTERNAL_QUEUE_HEARTBEAT, &AUTO_REFILL_HEARTBEAT, &SCHEDULER_HEARTBEAT]
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
+
+/// The threads whose last check-in was more than 'stall_secs' before 'now_unix'.
+pub fn stalled_threads(heartbeats: &[&ThreadHeartbeat], now_unix: u64, stall_secs: u64) -> Vec<&'static str> {
+    heartbeats.iter()
+        .filter(|heartbeat| now_unix.saturating_sub(heartbeat.last_unix()) > stall_secs)
+        .map(|heartbeat| heartbeat.thread_name)
+        .collect()
+}
+
+/// 'systemd_thread_stall_secs', but never less than two of Thread #3's polling intervals; it sleeps that long between check-ins.
+pub fn thread_stall_secs(app_config: &AppConfig) -> u64 {
+    app_config.systemd_thread_stall_secs.unwrap_or(config::DEFAULT_THREAD_STALL_SECS)
+        .max(app_config.scheduler_polling_interval.saturating_mul(2))
+}
+
+/**
+  How often to send WATCHDOG=1: half of $WATCHDOG_USEC, as sd_watchdog_enabled(3) recommends.\
+  None when systemd did not ask for a watchdog, or when $WATCHDOG_PID names another process.
+*/
+pub fn watchdog_interval(watchdog_usec: Option<&str>, watchdog_pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if let Some(pid) = watchdog_pid {
+        if pid.trim().parse::<u32>().ok() != Some(own_pid) {
+            return None;
+        }
+    }
+    let usec: u64 = watchdog_usec?.trim().parse().ok().filter(|usec| *usec > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// Send one datagram, such as "READY=1", to a notification socket.  A leading '@' names a socket in the abstract namespace.
+pub fn send_to_notify_socket(socket_path: &OsStr, state: &str) -> nix::Result<()> {
+    let address: UnixAddr = match socket_path.as_bytes().strip_prefix(b"@") {
+        Some(name) => UnixAddr::new_abstract(name)?,
+        None => UnixAddr::new(Path::new(socket_path))?,
+    };
+    let fd = socket(AddressFamily::Unix, SockType::Datagram, SockFlag::SOCK_CLOEXEC, None)?;
+    let result = sendto(fd, state.as_bytes(), &SockAddr::Unix(address), MsgFlags::empty());
+    let _ = nix::unistd::close(fd);
+    result.map(|_| ())
+}
+
+/// Tell systemd something.  Ok(false) when $NOTIFY_SOCKET is not set; the daemon was not started by systemd with 'Type=notify'.
+pub fn notify(state: &str) -> nix::Result<bool> {
+    let Some(socket_path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    send_to_notify_socket(&socket_path, state)?;
+    Ok(true)
+}
+
+/**
+  Called once the worker threads are running, and the Unix Domain Socket is bound.  Does nothing unless 'systemd_notify' is set.\
+  Sends READY=1; then, if systemd asked for a watchdog, starts the thread that pings it.
+*/
+pub fn start(app_config: &AppConfig) {
+    if !app_config.systemd_notify {
+        return;
+    }
+    match notify("READY=1") {
+        Ok(true) => info!("Notified systemd that the daemon is ready."),
+        Ok(false) => {
+            warn!("'systemd_notify' is set, but $NOTIFY_SOCKET is not.  Is the daemon running under systemd, with 'Type=notify'?");
+            return;
+        },
+        Err(error) => {
+            error!("Unable to notify systemd that the daemon is ready: {}", error);
+            return;
+        }
+    }
+
+    let watchdog_usec: Option<String> = std::env::var("WATCHDOG_USEC").ok();
+    let watchdog_pid: Option<String> = std::env::var("WATCHDOG_PID").ok();
+    let Some(interval) = watchdog_interval(watchdog_usec.as_deref(), watchdog_pid.as_deref(), std::process::id()) else {
+        info!("systemd did not ask for a watchdog ('WatchdogSec='); only readiness is reported.");
+        return;
+    };
+    info!("Pinging systemd's watchdog every {} ms, while every thread has checked in within {} seconds.",
+          interval.as_millis(), thread_stall_secs(app_config));
+    let spawned = std::thread::Builder::new().name("4_Systemd_Watchdog".to_string()).spawn(move || {
+        supervise(interval);
+    });
+    if let Err(error) = spawned {
+        error!("Cannot spawn thread '4_Systemd_Watchdog': {}", error);
+    }
+}
+
+/// Ping the watchdog while every worker thread is alive.  Once one stalls, stop; systemd restarts the daemon after 'WatchdogSec='.
+fn supervise(interval: Duration) {
+    let mut was_stalled: bool = false;
+    loop {
+        // Read each time; a reloaded configuration may have a new polling interval.
+        let stall_secs: u64 = thread_stall_secs(&crate::APP_CONFIG.load());
+        let stalled: Vec<&str> = stalled_threads(&worker_heartbeats(), now_unix(), stall_secs);
+        if stalled.is_empty() {
+            if was_stalled {
+                info!("Every thread has checked in again; resuming pings to systemd's watchdog.");
+            }
+            match notify("WATCHDOG=1") {
+                Ok(_) => debug!("Pinged systemd's watchdog."),
+                Err(error) => warn!("Unable to ping systemd's watchdog: {}", error),
+            }
+        }
+        else if !was_stalled {
+            error!("Thread(s) {} have not checked in for more than {} seconds.  No longer pinging systemd's watchdog, so systemd will restart the daemon.",
+                   stalled.join(", "), stall_secs);
+        }
+        was_stalled = !stalled.is_empty();
+        std::thread::sleep(interval);
+    }
+}
@@ -0,0 +1,1146 @@
+/*
+	NOTE: To run tests and display STDOUT, type the following in the shell:
+	
+		cargo test -- --nocapture
+
+*/
+
+#[cfg(test)]
+mod tests {
+
+	use std::sync::{Arc, Mutex};
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	use arc_swap::ArcSwap;
+	use btu_scheduler::config::AppConfig;
+	use btu_scheduler::schedule_queue::{QueueEntry, QueueSource, ScheduleQueue};
+	use btu_scheduler::socket_frame;
+	use crate::ipc_stream::{self, FrappeClientMessage, RequestContext, RequestOutcome};
+
+	// A minimal configuration; nothing here connects to a real database.
+	static SAMPLE_TOML: &str = r#"
+		environment_name = "Unit Test"
+		full_refresh_internal_secs = 900
+		scheduler_polling_interval = 60
+		time_zone_string = "America/Los_Angeles"
+		tracing_level = "INFO"
+		startup_without_database_connections = true
+		email_when_queuing = false
+		mysql_user = "root"
+		mysql_password = "password"
+		mysql_host = "127.0.0.1"
+		mysql_port = 3306
+		mysql_database = "test_db"
+		rq_host = "127.0.0.1"
+		rq_port = 11000
+		socket_path = "/tmp/btu_scheduler_test.sock"
+		socket_file_group_owner = "frappe"
+		webserver_ip = "127.0.0.1"
+		webserver_port = 8000
+		webserver_token = "token abc:def"
+	"#;
+
+	pub fn sample_app_config() -> AppConfig {
+		AppConfig::new_from_toml_string(SAMPLE_TOML).unwrap()
+	}
+
+	/**
+     * A slow socket handler holding the configuration must not delay the scheduling threads that read it.
+     */
+	#[test]
+	fn test_config_readers_not_blocked_by_slow_handler() {
+
+		let shared_config: Arc<ArcSwap<AppConfig>> = Arc::new(ArcSwap::from_pointee(sample_app_config()));
+		const READERS: usize = 8;
+		const READS_PER_READER: usize = 1000;
+
+		// Simulate a slow request (for example, a Redis scan during 'cancel_task_schedule') that holds the configuration,
+		// until every reader has finished.  No clock is involved: were the readers blocked by it, they would never finish.
+		let holding = Arc::new(std::sync::Barrier::new(READERS + 1));
+		let (release, released) = std::sync::mpsc::channel::<()>();
+		let handler_config = Arc::clone(&shared_config);
+		let handler_holding = Arc::clone(&holding);
+		let slow_handler = thread::spawn(move || {
+			let app_config: Arc<AppConfig> = handler_config.load_full();
+			handler_holding.wait();
+			released.recv().unwrap();
+			app_config.rq_port
+		});
+
+		// Meanwhile, several "scheduling cycles" read the configuration concurrently; the last one also reloads it.
+		let reads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+		let mut readers = Vec::new();
+		for reader_index in 0..READERS {
+			let reader_config = Arc::clone(&shared_config);
+			let reader_holding = Arc::clone(&holding);
+			let reader_reads = Arc::clone(&reads);
+			readers.push(thread::spawn(move || {
+				reader_holding.wait();
+				for _ in 0..READS_PER_READER {
+					let app_config = reader_config.load();
+					assert_eq!(app_config.scheduler_polling_interval, 60);
+					reader_reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+				}
+				if reader_index == READERS - 1 {
+					let mut reloaded: AppConfig = sample_app_config();
+					reloaded.rq_port = 11001;
+					reader_config.store(Arc::new(reloaded));
+				}
+			}));
+		}
+
+		for reader in readers {
+			reader.join().unwrap();
+		}
+		assert_eq!(reads.load(std::sync::atomic::Ordering::SeqCst), READERS * READS_PER_READER);
+		// The handler kept the configuration it started with; new readers see the reloaded one.
+		release.send(()).unwrap();
+		assert_eq!(slow_handler.join().unwrap(), 11000);
+		assert_eq!(shared_config.load().rq_port, 11001);
+	}
+
+	fn sample_request_context() -> RequestContext {
+		RequestContext {
+			queue: Arc::new(Mutex::new(ScheduleQueue::new())),
+			app_config: Arc::new(sample_app_config()),
+		}
+	}
+
+	fn send(request_type: &str, request_content: Option<&str>) -> RequestOutcome {
+		let message = FrappeClientMessage {
+			request_type: request_type.to_owned(),
+			request_content: request_content.map(|value| value.to_owned()),
+			idempotency_key: None,
+			correlation_id: None,
+		};
+		ipc_stream::dispatch_request(&message, &sample_request_context())
+	}
+
+	fn is_unknown_request_reply(outcome: &RequestOutcome) -> bool {
+		match &outcome.reply {
+			Some(reply) => {
+				match serde_json::from_str::<serde_json::Value>(reply) {
+					Ok(json) => json["error"] == "unknown_request_type",
+					Err(_) => false
+				}
+			},
+			None => false
+		}
+	}
+
+	/**
+     * Every request type in the registry must reach its own handler, rather than falling through to 'unknown'.
+     */
+	#[test]
+	fn test_every_registered_handler_is_reachable() {
+		for request_type in ipc_stream::supported_request_types() {
+			// Without 'request_content', handlers that require it return their own error; no database is touched.
+			let outcome = send(request_type, None);
+			assert!(!is_unknown_request_reply(&outcome), "Request type '{}' was not dispatched to a handler.", request_type);
+		}
+	}
+
+	/// A configuration that was not read from a file (or the environment) cannot be reloaded; the reply says why.
+	#[test]
+	fn test_reload_config_rejects_text_configuration() {
+		let outcome = send("reload_config", None);
+		assert!(outcome.result.is_err());
+		let reply: serde_json::Value = serde_json::from_str(&outcome.reply.unwrap()).unwrap();
+		assert_eq!(reply["error"], "invalid_config");
+		assert!(reply["message"].as_str().unwrap().contains("cannot be read again"), "{}", reply);
+	}
+
+	#[test]
+	fn test_get_config_redacts_secrets() {
+		let outcome = send("get_config", None);
+		assert!(outcome.result.is_ok());
+		let reply: String = outcome.reply.unwrap();
+		assert!(!reply.contains("token abc:def") && !reply.contains("\"password\""), "{}", reply);
+
+		let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+		assert_eq!(reply["config"]["webserver_token"], btu_scheduler::config::REDACTED);
+		assert_eq!(reply["config"]["environment_name"], "Unit Test");
+		assert_eq!(reply["config_source"], "text");
+		assert!(reply["config_file"].is_null());
+		assert_eq!(reply["environment_variables"], serde_json::json!([]));
+	}
+
+	#[test]
+	fn test_capabilities_lists_registry() {
+		let outcome = send("capabilities", None);
+		assert!(outcome.result.is_ok());
+		let reply: serde_json::Value = serde_json::from_str(&outcome.reply.unwrap()).unwrap();
+		assert_eq!(reply["protocol_version"], ipc_stream::PROTOCOL_VERSION);
+		assert_eq!(reply["daemon_version"], btu_scheduler::get_package_version());
+
+		let listed: Vec<&str> = reply["request_types"].as_array().unwrap().iter().map(|each| each.as_str().unwrap()).collect();
+		assert_eq!(listed, ipc_stream::supported_request_types());
+		assert!(listed.contains(&"capabilities"));
+	}
+
+	#[test]
+	fn test_unknown_request_type_is_structured_error() {
+		let outcome = send("no_such_request", Some("foo"));
+		assert!(outcome.result.is_err());
+		assert!(is_unknown_request_reply(&outcome));
+		let reply: serde_json::Value = serde_json::from_str(&outcome.reply.unwrap()).unwrap();
+		assert_eq!(reply["request_type"], "no_such_request");
+	}
+
+	#[test]
+	fn test_create_task_schedule_pushes_internal_queue() {
+		let context = sample_request_context();
+		let message = FrappeClientMessage {
+			request_type: "create_task_schedule".to_owned(),
+			request_content: Some("TS-000123".to_owned()),
+			idempotency_key: None,
+			correlation_id: None,
+		};
+		let outcome = ipc_stream::dispatch_request(&message, &context);
+		assert!(outcome.result.is_ok());
+		let entry = context.queue.lock().unwrap().pop_front().unwrap();
+		assert_eq!(entry.schedule_id, "TS-000123");
+		assert_eq!(entry.source, QueueSource::Socket);
+	}
+
+	/// A schedule submitted over the socket jumps ahead of the full-refresh backlog.
+	#[test]
+	fn test_create_task_schedule_is_high_priority() {
+		let context = sample_request_context();
+		{
+			let mut queue = context.queue.lock().unwrap();
+			queue.push_back(QueueEntry::new("TS-000001".into(), QueueSource::FullRefresh));
+			queue.push_back(QueueEntry::new("TS-000002".into(), QueueSource::FullRefresh));
+		}
+		let message = FrappeClientMessage {
+			request_type: "create_task_schedule".to_owned(),
+			request_content: Some("TS-000002".to_owned()),
+			idempotency_key: None,
+			correlation_id: None,
+		};
+		assert!(ipc_stream::dispatch_request(&message, &context).result.is_ok());
+
+		let mut queue = context.queue.lock().unwrap();
+		let next = queue.pop_front().unwrap();
+		assert_eq!((next.schedule_id.as_str(), next.source), ("TS-000002", QueueSource::Socket));
+		assert_eq!(queue.pop_front().unwrap().schedule_id, "TS-000001");
+		assert!(queue.is_empty());
+	}
+
+	/// An in-memory IdempotencyStore, so the wrapper can be tested without Redis.
+	#[derive(Default)]
+	struct MemoryIdempotencyStore {
+		responses: Mutex<std::collections::HashMap<String, ipc_stream::StoredResponse>>,
+	}
+
+	impl ipc_stream::IdempotencyStore for MemoryIdempotencyStore {
+		fn reserve(&self, idempotency_key: &str, request_type: &str) -> ipc_stream::Reservation {
+			let mut responses = self.responses.lock().unwrap();
+			if let Some(stored) = responses.get(idempotency_key) {
+				return ipc_stream::Reservation::Taken(stored.clone());
+			}
+			let reservation = ipc_stream::StoredResponse { request_type: request_type.to_owned(), reply: None, stored_at: 0, pending: true };
+			responses.insert(idempotency_key.to_owned(), reservation);
+			ipc_stream::Reservation::Reserved
+		}
+		fn fetch(&self, idempotency_key: &str) -> Option<ipc_stream::StoredResponse> {
+			self.responses.lock().unwrap().get(idempotency_key).cloned()
+		}
+		fn store(&self, idempotency_key: &str, response: &ipc_stream::StoredResponse) {
+			self.responses.lock().unwrap().insert(idempotency_key.to_owned(), response.clone());
+		}
+		fn release(&self, idempotency_key: &str) {
+			self.responses.lock().unwrap().remove(idempotency_key);
+		}
+	}
+
+	/// A retried request with the same idempotency key gets the original reply, and the work is done only once.
+	#[test]
+	fn test_idempotency_key_prevents_double_processing() {
+		let context = sample_request_context();
+		let store = MemoryIdempotencyStore::default();
+		let message = |key: Option<&str>| FrappeClientMessage {
+			request_type: "create_task_schedule".to_owned(),
+			request_content: Some("TS-000123".to_owned()),
+			idempotency_key: key.map(|value| value.to_owned()),
+			correlation_id: None,
+		};
+
+		// Thread #1 pops each entry between requests; otherwise the queue's own de-duplication would hide a repeat.
+		let pop_count = || std::iter::from_fn(|| context.queue.lock().unwrap().pop_front()).count();
+
+		let first = ipc_stream::dispatch_idempotent_request(&message(Some("retry-1")), &context, &store);
+		assert_eq!(pop_count(), 1);
+		let second = ipc_stream::dispatch_idempotent_request(&message(Some("retry-1")), &context, &store);
+		assert!(first.result.is_ok() && second.result.is_ok());
+		assert_eq!(first.reply, second.reply);
+		assert_eq!(pop_count(), 0);
+
+		// A different key, or no key at all, is processed normally.
+		ipc_stream::dispatch_idempotent_request(&message(Some("retry-2")), &context, &store);
+		assert_eq!(pop_count(), 1);
+		ipc_stream::dispatch_idempotent_request(&message(None), &context, &store);
+		assert_eq!(pop_count(), 1);
+	}
+
+	/// Failed requests are not remembered, so the client's retry is processed.
+	#[test]
+	fn test_idempotency_ignores_failed_outcomes() {
+		let context = sample_request_context();
+		let store = MemoryIdempotencyStore::default();
+		let message = FrappeClientMessage {
+			request_type: "create_task_schedule".to_owned(),
+			request_content: None,
+			idempotency_key: Some("retry-1".to_owned()),
+			correlation_id: None,
+		};
+		assert!(ipc_stream::dispatch_idempotent_request(&message, &context, &store).result.is_err());
+		assert!(ipc_stream::IdempotencyStore::fetch(&store, "retry-1").is_none());
+	}
+
+	/// Two requests with the same idempotency key, arriving together: the work is done once, and both get its reply.
+	#[test]
+	fn test_idempotency_concurrent_requests() {
+		let store = MemoryIdempotencyStore::default();
+		let work_count = std::sync::atomic::AtomicUsize::new(0);
+		let message = FrappeClientMessage {
+			request_type: "create_task_schedule".to_owned(),
+			request_content: Some("TS-000123".to_owned()),
+			idempotency_key: Some("retry-1".to_owned()),
+			correlation_id: None,
+		};
+		let barrier = std::sync::Barrier::new(2);
+		let replies: Vec<Option<String>> = thread::scope(|scope| {
+			let handles: Vec<_> = (0..2).map(|_| scope.spawn(|| {
+				barrier.wait();
+				ipc_stream::run_idempotent(&message, &store, || {
+					work_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+					thread::sleep(Duration::from_millis(200));
+					RequestOutcome { reply: Some("done".to_owned()), result: Ok("done".to_owned()) }
+				}).reply
+			})).collect();
+			handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+		});
+		assert_eq!(work_count.load(std::sync::atomic::Ordering::SeqCst), 1);
+		assert_eq!(replies, vec![Some("done".to_owned()), Some("done".to_owned())]);
+	}
+
+	/// With Redis unreachable, the daemon's store quietly does nothing, and requests are processed normally.
+	#[test]
+	fn test_idempotency_without_redis() {
+		let mut app_config = sample_app_config();
+		app_config.rq_port = 1;
+		let context = RequestContext {
+			queue: Arc::new(Mutex::new(ScheduleQueue::new())),
+			app_config: Arc::new(app_config),
+		};
+		let store = ipc_stream::RedisIdempotencyStore { app_config: Arc::clone(&context.app_config) };
+		let message = FrappeClientMessage {
+			request_type: "create_task_schedule".to_owned(),
+			request_content: Some("TS-000123".to_owned()),
+			idempotency_key: Some("retry-1".to_owned()),
+			correlation_id: None,
+		};
+		assert!(ipc_stream::dispatch_idempotent_request(&message, &context, &store).result.is_ok());
+		assert_eq!(context.queue.lock().unwrap().pop_front().map(|entry| entry.schedule_id), Some("TS-000123".into()));
+		assert!(ipc_stream::dispatch_idempotent_request(&message, &context, &store).result.is_ok());
+		assert_eq!(context.queue.lock().unwrap().pop_front().map(|entry| entry.schedule_id), Some("TS-000123".into()));
+	}
+
+	/// A blank or over-long Task Schedule ID is rejected before it reaches the internal queue (or Redis).
+	#[test]
+	fn test_socket_rejects_invalid_task_schedule_ids() {
+		let context = RequestContext {
+			queue: Arc::new(Mutex::new(ScheduleQueue::new())),
+			app_config: Arc::new(sample_app_config()),
+		};
+		for request_type in ["create_task_schedule", "cancel_task_schedule"] {
+			for request_content in ["   ", &"X".repeat(141)] {
+				let message = FrappeClientMessage {
+					request_type: request_type.to_owned(),
+					request_content: Some(request_content.to_owned()),
+					idempotency_key: None,
+					correlation_id: None,
+				};
+				let error = ipc_stream::dispatch_request(&message, &context).result.unwrap_err();
+				assert!(error.to_string().contains("BTU Task Schedule ID"), "{}: {}", request_type, error);
+			}
+		}
+		assert!(context.queue.lock().unwrap().is_empty());
+
+		// Surrounding whitespace is trimmed, rather than becoming part of the ID.
+		let message = FrappeClientMessage {
+			request_type: "create_task_schedule".to_owned(),
+			request_content: Some(" TS-000123\n".to_owned()),
+			idempotency_key: None,
+			correlation_id: None,
+		};
+		assert!(ipc_stream::dispatch_request(&message, &context).result.is_ok());
+		assert_eq!(context.queue.lock().unwrap().pop_front().map(|entry| entry.schedule_id), Some("TS-000123".into()));
+	}
+
+	/// Events emitted through the LogHistoryLayer, from several named threads, are returned by 'recent_logs'.
+	#[test]
+	fn test_recent_logs_captures_named_threads() {
+		use tracing_subscriber::{Registry, prelude::__tracing_subscriber_SubscriberExt};
+		use crate::logging::LogHistoryLayer;
+
+		let thread_names = ["1_Internal_Queue", "2_Auto_Refill", "3_Scheduler"];
+		let mut handles = Vec::new();
+		for thread_name in thread_names {
+			handles.push(thread::Builder::new().name(thread_name.to_owned()).spawn(move || {
+				let subscriber = Registry::default().with(LogHistoryLayer);
+				tracing::subscriber::with_default(subscriber, || {
+					tracing::info!("recent_logs test event from {}", thread_name);
+					tracing::debug!("recent_logs test event that is too verbose to keep");
+				});
+			}).unwrap());
+		}
+		for handle in handles {
+			handle.join().unwrap();
+		}
+
+		let outcome = send("recent_logs", Some("500"));
+		assert!(outcome.result.is_ok());
+		let entries: Vec<serde_json::Value> = serde_json::from_str(&outcome.reply.unwrap()).unwrap();
+		for thread_name in thread_names {
+			let found = entries.iter().any(|entry| {
+				entry["thread_name"] == thread_name
+				&& entry["level"] == "INFO"
+				&& entry["message"] == format!("recent_logs test event from {}", thread_name).as_str()
+			});
+			assert!(found, "No log entry from thread '{}'", thread_name);
+		}
+		assert!(!entries.iter().any(|entry| entry["message"] == "recent_logs test event that is too verbose to keep"));
+	}
+
+	#[test]
+	fn test_log_history_is_bounded() {
+		use crate::logging::{LogEntry, LogHistory};
+
+		let history = LogHistory::new(3);
+		for index in 0..5 {
+			history.push(LogEntry {
+				timestamp: String::new(),
+				level: "INFO".to_owned(),
+				thread_name: "main".to_owned(),
+				message: format!("event {}", index),
+			});
+		}
+		let newest: Vec<String> = history.newest(10).into_iter().map(|entry| entry.message).collect();
+		assert_eq!(newest, vec!["event 2", "event 3", "event 4"]);
+		assert_eq!(history.newest(1)[0].message, "event 4");
+	}
+
+	#[test]
+	fn test_recent_logs_rejects_non_numeric_content() {
+		let outcome = send("recent_logs", Some("lots"));
+		assert!(outcome.result.is_err());
+	}
+
+	#[test]
+	fn test_reschedule_next_run_rejects_invalid_content() {
+		for request_content in [
+			"TS-0001",
+			r#"{"task_schedule_id": "TS-0001"}"#,
+			r#"{"task_schedule_id": "TS-0001", "until": "2024-07-01T04:00:00Z", "by": "2h"}"#,
+			r#"{"task_schedule_id": "TS-0001", "until": "tomorrow"}"#,
+			r#"{"task_schedule_id": "TS-0001", "by": "soon"}"#,
+			r#"{"task_schedule_id": " ", "by": "2h"}"#,
+		] {
+			let outcome = send("reschedule_next_run", Some(request_content));
+			let error = outcome.result.unwrap_err().to_string();
+			assert!(error.contains("invalid 'request_content'"), "{}: {}", request_content, error);
+			assert_eq!(outcome.reply.as_deref(), Some(error.as_str()));
+		}
+		// Valid content reaches Redis, which is not running here.
+		let error = send("reschedule_next_run", Some(r#"{"task_schedule_id": "TS-0001", "by": "-30m"}"#)).result.unwrap_err();
+		assert!(!error.to_string().contains("invalid 'request_content'"), "{}", error);
+	}
+
+	#[test]
+	fn test_query_task_schedules_json() {
+		use btu_scheduler::errors::SchedulerError;
+
+		use btu_scheduler::scheduler::UnschedulableMarker;
+
+		let past_due = ipc_stream::task_schedule_next_run_json("TS-1", &Ok(Some(chrono::Duration::seconds(-90))), None, None);
+		assert_eq!(past_due, serde_json::json!({ "task_schedule_id": "TS-1", "next_run_in_secs": -90 }));
+
+		let nothing_scheduled = ipc_stream::task_schedule_next_run_json("TS-2", &Ok(None), None, None);
+		assert!(nothing_scheduled["next_run_in_secs"].is_null());
+		assert!(nothing_scheduled.get("unschedulable").is_none());
+
+		let unknown = ipc_stream::task_schedule_next_run_json("TS-3",
+			&Err(SchedulerError::UnknownTaskSchedule { task_schedule_id: "TS-3".to_owned() }), None, None);
+		assert!(unknown["next_run_in_secs"].is_null());
+		assert!(unknown["error"].as_str().unwrap().contains("TS-3"));
+
+		let marker = UnschedulableMarker {
+			task_schedule_id: "TS-4".into(),
+			cron_string: "0 0 12 * * * 2019".to_owned(),
+			cron_timezone: "UTC".to_owned(),
+			reason: "no upcoming dates".to_owned(),
+			detected_at: "2022-12-02T08:00:00Z".to_owned(),
+		};
+		let unschedulable = ipc_stream::task_schedule_next_run_json("TS-4", &Ok(None), Some(&marker), None);
+		assert_eq!(unschedulable["unschedulable"], true);
+		assert_eq!(unschedulable["unschedulable_reason"], "no upcoming dates");
+		assert!(unschedulable.get("cron").is_none());
+
+		// The cron expression's normalized form, and whatever Frappe's UI cannot show.
+		let classification = btu_scheduler::btu_cron::classify_cron("30 0 9 * * * 2030").unwrap();
+		let with_cron = ipc_stream::task_schedule_next_run_json("TS-5", &Ok(None), None, Some(&classification));
+		assert_eq!(with_cron["cron"], serde_json::json!({
+			"elements": 7, "cron7": "30 0 9 * * * 2030", "seconds": "30", "year": "2030", "frappe_compatible": false
+		}));
+	}
+
+	/// A cycle that overruns the polling interval must not underflow; Thread #3 still pauses briefly.
+	#[test]
+	fn test_remaining_sleep() {
+		use std::time::Duration;
+		use crate::{remaining_sleep, MIN_CYCLE_SLEEP};
+
+		assert_eq!(remaining_sleep(60, 0), Duration::from_secs(60));
+		assert_eq!(remaining_sleep(60, 45), Duration::from_secs(15));
+		assert_eq!(remaining_sleep(60, 60), MIN_CYCLE_SLEEP);
+		assert_eq!(remaining_sleep(60, 95), MIN_CYCLE_SLEEP);
+		assert_eq!(remaining_sleep(0, 0), MIN_CYCLE_SLEEP);
+	}
+
+	#[test]
+	fn test_systemd_watchdog() {
+		use std::os::unix::net::UnixDatagram;
+		use crate::systemd::{self, ThreadHeartbeat};
+
+		// Half of WATCHDOG_USEC; nothing when systemd asked another process, or did not ask at all.
+		assert_eq!(systemd::watchdog_interval(Some("120000000"), None, 42), Some(Duration::from_secs(60)));
+		assert_eq!(systemd::watchdog_interval(Some("120000000"), Some("42"), 42), Some(Duration::from_secs(60)));
+		assert_eq!(systemd::watchdog_interval(Some("120000000"), Some("43"), 42), None);
+		assert_eq!(systemd::watchdog_interval(None, None, 42), None);
+		assert_eq!(systemd::watchdog_interval(Some("0"), None, 42), None);
+
+		// A thread that has not checked in for longer than the limit (or ever) is stalled.
+		let queue = ThreadHeartbeat::new("1_Internal_Queue");
+		let scheduler = ThreadHeartbeat::new("3_Scheduler");
+		queue.beat_at(1_000);
+		assert_eq!(systemd::stalled_threads(&[&queue, &scheduler], 1_100, 300), vec!["3_Scheduler"]);
+		scheduler.beat_at(1_050);
+		assert!(systemd::stalled_threads(&[&queue, &scheduler], 1_300, 300).is_empty());
+		assert_eq!(systemd::stalled_threads(&[&queue, &scheduler], 1_301, 300), vec!["1_Internal_Queue"]);
+
+		// The limit never undercuts Thread #3's sleep between cycles.
+		let mut app_config: AppConfig = sample_app_config();
+		assert!(!app_config.systemd_notify);
+		assert_eq!(systemd::thread_stall_secs(&app_config), 300);
+		app_config.scheduler_polling_interval = 600;
+		assert_eq!(systemd::thread_stall_secs(&app_config), 1_200);
+
+		// Notifications are single datagrams on the socket systemd names.
+		let socket_path = std::env::temp_dir().join(format!("btu_notify_test_{}.sock", std::process::id()));
+		let _ = std::fs::remove_file(&socket_path);
+		let receiver = UnixDatagram::bind(&socket_path).unwrap();
+		systemd::send_to_notify_socket(socket_path.as_os_str(), "READY=1").unwrap();
+		let mut buffer = [0; 64];
+		let length: usize = receiver.recv(&mut buffer).unwrap();
+		assert_eq!(&buffer[..length], b"READY=1");
+		let _ = std::fs::remove_file(&socket_path);
+	}
+
+	#[test]
+	fn test_show_scheduled_json() {
+		use btu_scheduler::scheduler::{RQScheduledTask, TSIK};
+
+		// Each entry round-trips through the JSON the reply is made of.
+		let scheduled: Vec<RQScheduledTask> = vec![RQScheduledTask::from_tsik(TSIK::from("TS-1|1669964400")),
+		                                           RQScheduledTask::from_tsik(TSIK::from("TS-2|1669968000"))];
+		let json: String = serde_json::to_string(&scheduled).unwrap();
+		assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), serde_json::json!([
+			{ "task_schedule_id": "TS-1", "next_datetime_unix": 1669964400, "next_datetime_utc": "2022-12-02T07:00:00Z" },
+			{ "task_schedule_id": "TS-2", "next_datetime_unix": 1669968000, "next_datetime_utc": "2022-12-02T08:00:00Z" },
+		]));
+		assert_eq!(serde_json::from_str::<Vec<RQScheduledTask>>(&json).unwrap(), scheduled);
+
+		// Without Redis, the reply is an empty array.
+		let outcome = send("show_scheduled", None);
+		assert!(outcome.result.is_ok());
+		assert_eq!(outcome.reply.as_deref(), Some("[]"));
+	}
+
+	#[test]
+	fn test_log_lines_identify_the_instance() {
+		use btu_scheduler::logging::InstanceIdentity;
+		use crate::logging::format_log_event;
+
+		let fields: std::collections::BTreeMap<String, serde_json::Value> = [("message".to_owned(), serde_json::json!("hello"))].into();
+		let named = InstanceIdentity::new(Some("Production".to_owned()), "web1", "/tmp/btu_scheduler.sock");
+		let line: String = format_log_event("event", &tracing::Level::INFO, fields.clone(), Some(&named)).to_string();
+		assert!(line.contains(r#""environment":"Production""#), "{}", line);
+		assert!(line.contains(&format!(r#""instance":"{}""#, named.instance_id)), "{}", line);
+
+		let unnamed = InstanceIdentity::new(None, "web1", "/tmp/btu_scheduler.sock");
+		let line: String = format_log_event("event", &tracing::Level::INFO, fields, Some(&unnamed)).to_string();
+		assert!(!line.contains("environment"), "{}", line);
+		assert!(line.contains(r#""instance":"web1-"#), "{}", line);
+
+		// Two daemons on one host are told apart by their socket paths.
+		assert_ne!(named.instance_id, InstanceIdentity::new(None, "web1", "/tmp/other.sock").instance_id);
+		assert_eq!(named.instance_id.len(), "web1-".len() + 4);
+	}
+
+	/// A socket request, end to end: the client's message goes in one side of a socket pair, and the reply comes out.
+	fn send_over_socket(queue: &Arc<Mutex<ScheduleQueue>>, message: &str) -> (Result<String, std::io::Error>, String) {
+		use std::os::unix::net::UnixStream;
+
+		let (mut client, server) = UnixStream::pair().unwrap();
+		socket_frame::write_frame(&mut client, message.as_bytes()).unwrap();
+		let result = ipc_stream::handle_client_request(server, Arc::clone(queue), Arc::new(sample_app_config()));
+		let reply: Vec<u8> = socket_frame::read_frame(&mut client, socket_frame::MAX_REPLY_BYTES).unwrap();
+		(result, String::from_utf8(reply).unwrap())
+	}
+
+	#[test]
+	fn test_list_and_clear_internal_queue() {
+		let queue = Arc::new(Mutex::new(ScheduleQueue::new()));
+		{
+			let mut unlocked_queue = queue.lock().unwrap();
+			for index in 0..(ipc_stream::INTERNAL_QUEUE_LIST_LIMIT + 5) {
+				unlocked_queue.push_back(QueueEntry::new(format!("TS-{:04}", index).into(), QueueSource::FullRefresh));
+			}
+			unlocked_queue.push_priority(QueueEntry::new("TS-URGENT".into(), QueueSource::Socket));
+		}
+
+		let (result, reply) = send_over_socket(&queue, r#"{"request_type": "list_internal_queue"}"#);
+		assert!(result.is_ok());
+		let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+		assert_eq!(reply["total"], ipc_stream::INTERNAL_QUEUE_LIST_LIMIT + 6);
+		assert_eq!(reply["truncated"], true);
+		let entries = reply["entries"].as_array().unwrap();
+		assert_eq!(entries.len(), ipc_stream::INTERNAL_QUEUE_LIST_LIMIT);
+		assert_eq!((entries[0]["schedule_id"].as_str(), entries[0]["source"].as_str(), entries[0]["priority"].as_str()),
+		           (Some("TS-URGENT"), Some("socket"), Some("high")));
+		assert_eq!((entries[1]["schedule_id"].as_str(), entries[1]["priority"].as_str()), (Some("TS-0000"), Some("normal")));
+		assert!(entries[1]["enqueued_at"].as_str().unwrap().ends_with('Z'));
+		assert_eq!(queue.lock().unwrap().len(), ipc_stream::INTERNAL_QUEUE_LIST_LIMIT + 6, "listing must not consume entries");
+
+		// Clearing requires confirmation.
+		let (result, _) = send_over_socket(&queue, r#"{"request_type": "clear_internal_queue"}"#);
+		assert!(result.is_err());
+		assert_eq!(queue.lock().unwrap().len(), ipc_stream::INTERNAL_QUEUE_LIST_LIMIT + 6);
+
+		// A Task Schedule that just ran is kept: until Thread #1 writes its next run, it has none in Redis.
+		queue.lock().unwrap().push_back(QueueEntry::new("TS-JUST-RAN".into(), QueueSource::PostRunRequeue));
+		let (result, reply) = send_over_socket(&queue, r#"{"request_type": "clear_internal_queue", "request_content": "confirm"}"#);
+		assert!(result.is_ok());
+		let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+		assert_eq!((reply["discarded"].as_u64(), reply["kept"].as_u64()), (Some(ipc_stream::INTERNAL_QUEUE_LIST_LIMIT as u64 + 6), Some(1)));
+		assert_eq!(queue.lock().unwrap().pop_front().map(|entry| entry.schedule_id), Some("TS-JUST-RAN".into()));
+		assert!(queue.lock().unwrap().is_empty());
+
+		let (_, reply) = send_over_socket(&queue, r#"{"request_type": "list_internal_queue"}"#);
+		let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+		assert_eq!((reply["total"].as_u64(), reply["truncated"].as_bool()), (Some(0), Some(false)));
+	}
+
+	/// Send one message through a RequestGuard over a socket pair; 'work' stands in for the request handlers.
+	fn send_guarded<F>(guard: &ipc_stream::RequestGuard, rate_per_min: u32, message: &str, work: F) -> String
+		where F: FnOnce(&FrappeClientMessage) -> RequestOutcome {
+		use std::os::unix::net::UnixStream;
+
+		let (mut client, server) = UnixStream::pair().unwrap();
+		socket_frame::write_frame(&mut client, message.as_bytes()).unwrap();
+		let _ = ipc_stream::handle_guarded_request(server, guard, rate_per_min, work);
+		String::from_utf8(socket_frame::read_frame(&mut client, socket_frame::MAX_REPLY_BYTES).unwrap()).unwrap()
+	}
+
+	fn counted_work(counter: &std::sync::atomic::AtomicUsize) -> RequestOutcome {
+		counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+		RequestOutcome { reply: Some("cancelled".to_owned()), result: Ok("cancelled".to_owned()) }
+	}
+
+	/// A client repeating one request in a tight loop gets 'rate_limited' replies, once the bucket is empty.
+	#[test]
+	fn test_socket_rate_limit() {
+		use std::sync::atomic::{AtomicUsize, Ordering};
+
+		let guard = ipc_stream::RequestGuard::new(ipc_stream::REQUEST_GUARD_CAPACITY);
+		let counter = AtomicUsize::new(0);
+		let cancel = r#"{"request_type": "cancel_task_schedule", "request_content": "TS-0001"}"#;
+		let replies: Vec<String> = (0..200).map(|_| send_guarded(&guard, 30, cancel, |_| counted_work(&counter))).collect();
+		// The bucket starts full, and refills by one token every two seconds.
+		let runs: usize = counter.load(Ordering::SeqCst);
+		assert!((30..=31).contains(&runs), "work ran {} times", runs);
+		assert_eq!(replies.iter().filter(|reply| *reply == "cancelled").count(), runs);
+
+		let limited: serde_json::Value = serde_json::from_str(replies.last().unwrap()).unwrap();
+		assert_eq!(limited["error"], "rate_limited");
+		assert_eq!(limited["request_type"], "cancel_task_schedule");
+		assert!((1..=2).contains(&limited["retry_after_secs"].as_u64().unwrap()), "{}", limited);
+
+		// A different request has its own bucket.  A different correlation ID does not make a request different.
+		let other = r#"{"request_type": "cancel_task_schedule", "request_content": "TS-0002"}"#;
+		assert_eq!(send_guarded(&guard, 30, other, |_| counted_work(&counter)), "cancelled");
+		let same = r#"{"request_type": "cancel_task_schedule", "request_content": "TS-0001", "correlation_id": "retry-1"}"#;
+		assert!(send_guarded(&guard, 30, same, |_| counted_work(&counter)).contains("rate_limited"));
+
+		// A rate of 0 disables the limit.
+		let unlimited = ipc_stream::RequestGuard::new(ipc_stream::REQUEST_GUARD_CAPACITY);
+		counter.store(0, Ordering::SeqCst);
+		for _ in 0..100 {
+			send_guarded(&unlimited, 0, cancel, |_| counted_work(&counter));
+		}
+		assert_eq!(counter.load(Ordering::SeqCst), 100);
+	}
+
+	/// Identical requests arriving while the first is processed wait for it, and share its reply.
+	#[test]
+	fn test_socket_identical_requests_coalesce() {
+		use std::sync::atomic::{AtomicUsize, Ordering};
+		use std::sync::Barrier;
+
+		let guard = Arc::new(ipc_stream::RequestGuard::new(ipc_stream::REQUEST_GUARD_CAPACITY));
+		let counter = Arc::new(AtomicUsize::new(0));
+		let barrier = Arc::new(Barrier::new(12));
+		let handles: Vec<_> = (0..12).map(|_| {
+			let (guard, counter, barrier) = (Arc::clone(&guard), Arc::clone(&counter), Arc::clone(&barrier));
+			thread::spawn(move || {
+				barrier.wait();
+				send_guarded(&guard, 1000, r#"{"request_type": "cancel_task_schedule", "request_content": "TS-0001"}"#, |_| {
+					thread::sleep(Duration::from_millis(500));  // a slow scan of the RQ sorted set
+					counted_work(&counter)
+				})
+			})
+		}).collect();
+		let replies: Vec<String> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+		assert_eq!(counter.load(Ordering::SeqCst), 1);
+		assert!(replies.iter().all(|reply| reply == "cancelled"), "{:?}", replies);
+		// Once finished, the next identical request does the work again.
+		assert_eq!(send_guarded(&guard, 1000, r#"{"request_type": "cancel_task_schedule", "request_content": "TS-0001"}"#,
+		                        |_| counted_work(&counter)), "cancelled");
+		assert_eq!(counter.load(Ordering::SeqCst), 2);
+	}
+
+	/// The guard remembers a bounded number of fingerprints, forgetting the least recently used.
+	#[test]
+	fn test_request_guard_is_bounded() {
+		let guard = ipc_stream::RequestGuard::new(4);
+		let work = || RequestOutcome { reply: None, result: Ok(String::new()) };
+		let message = |content: &str| FrappeClientMessage {
+			request_type: "create_task_schedule".to_owned(),
+			request_content: Some(content.to_owned()),
+			idempotency_key: None,
+			correlation_id: None,
+		};
+		// Use up the first fingerprint's single token.
+		assert!(guard.run(&message("TS-0000"), 1, work).result.is_ok());
+		assert!(guard.run(&message("TS-0000"), 1, work).result.is_err());
+		for index in 1..10 {
+			assert!(guard.run(&message(&format!("TS-{:04}", index)), 1, work).result.is_ok());
+		}
+		assert_eq!(guard.tracked_fingerprints(), 4);
+		// Forgotten, so its bucket starts full again.
+		assert!(guard.run(&message("TS-0000"), 1, work).result.is_ok());
+	}
+
+	/// A message may arrive in several writes; the daemon waits for all of it, and frames its reply.
+	#[test]
+	fn test_socket_message_split_across_writes() {
+		use std::io::{Read, Write};
+		use std::os::unix::net::UnixStream;
+
+		let frame: Vec<u8> = socket_frame::encode_frame(br#"{"request_type": "ping", "request_content": null}"#);
+		let (mut client, server) = UnixStream::pair().unwrap();
+		let writer = thread::spawn(move || {
+			for part in [&frame[..2], &frame[2..9], &frame[9..]] {
+				client.write_all(part).unwrap();
+				thread::sleep(Duration::from_millis(20));
+			}
+			client
+		});
+		let guard = ipc_stream::RequestGuard::new(ipc_stream::REQUEST_GUARD_CAPACITY);
+		let result = ipc_stream::handle_guarded_request(server, &guard, 30, |message| {
+			assert_eq!(message.request_type, "ping");
+			RequestOutcome { reply: Some("pong".to_owned()), result: Ok("pong".to_owned()) }
+		});
+		assert_eq!(result.unwrap(), "pong");
+		let mut client = writer.join().unwrap();
+		assert_eq!(socket_frame::read_frame(&mut client, socket_frame::MAX_REPLY_BYTES).unwrap(), b"pong");
+
+		// A client older than protocol version 4 sends bare JSON, also in pieces; it is answered without a length.
+		let (mut client, server) = UnixStream::pair().unwrap();
+		let writer = thread::spawn(move || {
+			client.write_all(br#"{"request_type": "pi"#).unwrap();
+			thread::sleep(Duration::from_millis(20));
+			client.write_all(br#"ng"}"#).unwrap();
+			client
+		});
+		let result = ipc_stream::handle_guarded_request(server, &guard, 30, |_| {
+			RequestOutcome { reply: Some("pong".to_owned()), result: Ok("pong".to_owned()) }
+		});
+		assert!(result.is_ok());
+		let mut reply = String::new();
+		writer.join().unwrap().read_to_string(&mut reply).unwrap();
+		assert_eq!(reply, "pong");
+	}
+
+	/// A message longer than the cap is refused, before it is read, with a structured error.
+	#[test]
+	fn test_socket_message_exceeding_cap() {
+		use std::io::Write;
+		use std::os::unix::net::UnixStream;
+
+		let (mut client, server) = UnixStream::pair().unwrap();
+		let length: u32 = socket_frame::MAX_FRAME_BYTES as u32 + 1;
+		client.write_all(&length.to_be_bytes()).unwrap();  // no payload follows; the daemon must not wait for it
+		let guard = ipc_stream::RequestGuard::new(ipc_stream::REQUEST_GUARD_CAPACITY);
+		let result = ipc_stream::handle_guarded_request(server, &guard, 30, |_| panic!("an oversized message must not be handled"));
+		assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+		let reply: serde_json::Value = serde_json::from_slice(&socket_frame::read_frame(&mut client, socket_frame::MAX_REPLY_BYTES).unwrap()).unwrap();
+		assert_eq!(reply["error"], "message_too_large");
+		assert_eq!(reply["max_bytes"], socket_frame::MAX_FRAME_BYTES);
+
+		// A client that stops mid-message is dropped after the read timeout, instead of holding the handler thread.
+		let (mut client, mut server) = UnixStream::pair().unwrap();
+		client.write_all(&socket_frame::encode_frame(br#"{"request_type": "ping"}"#)[..10]).unwrap();
+		server.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+		let result = socket_frame::read_message(&mut server, socket_frame::MAX_FRAME_BYTES);
+		assert!(matches!(result, Err(btu_scheduler::errors::FrameError::Io { .. })), "{:?}", result);
+	}
+
+	#[test]
+	fn test_socket_client_disconnects_before_reply() {
+		use std::io::Write;
+		use std::os::unix::net::UnixStream;
+
+		let queue = Arc::new(Mutex::new(ScheduleQueue::new()));
+		queue.lock().unwrap().push_back(QueueEntry::new("TS-0001".into(), QueueSource::FullRefresh));
+
+		// The client sends its request, then hangs up without waiting for the reply.
+		let (mut client, server) = UnixStream::pair().unwrap();
+		client.write_all(br#"{"request_type": "clear_internal_queue", "request_content": "confirm"}"#).unwrap();
+		drop(client);
+
+		let result = ipc_stream::handle_client_request(server, Arc::clone(&queue), Arc::new(sample_app_config()));
+		assert!(result.unwrap().contains("discarded 1 entries"));
+		assert!(queue.lock().unwrap().is_empty());
+	}
+
+	/// A 'create_task_schedule' request's correlation ID (or one generated for it) is stored with its internal queue entry.
+	#[test]
+	fn test_create_request_correlation_id() {
+		use std::io::{Read, Write};
+		use std::os::unix::net::UnixStream;
+
+		let queue = Arc::new(Mutex::new(ScheduleQueue::new()));
+		let request = |json: &[u8]| {
+			let (mut client, server) = UnixStream::pair().unwrap();
+			client.write_all(json).unwrap();
+			ipc_stream::handle_client_request(server, Arc::clone(&queue), Arc::new(sample_app_config())).unwrap();
+			let mut reply = String::new();
+			client.read_to_string(&mut reply).unwrap();
+			reply
+		};
+
+		let reply: String = request(br#"{"request_type": "create_task_schedule", "request_content": "TS-0001", "correlation_id": "click-42"}"#);
+		assert_eq!(reply, "BTU Scheduler now re-processing Task Schedule TS-0001 in Python RQ (correlation ID click-42).");
+		let entry = queue.lock().unwrap().pop_front().unwrap();
+		assert_eq!(entry.correlation_id.unwrap(), "click-42");
+
+		// Without one, the daemon generates an ID at the socket, and tells the client.
+		let reply: String = request(br#"{"request_type": "create_task_schedule", "request_content": "TS-0002"}"#);
+		let entry = queue.lock().unwrap().pop_front().unwrap();
+		let generated = entry.correlation_id.expect("a generated correlation ID");
+		assert_eq!(generated.as_str().len(), 12);
+		assert!(reply.ends_with(&format!("(correlation ID {}).", generated)), "{}", reply);
+
+		// Entries from other sources have none.
+		assert!(QueueEntry::new("TS-0003".into(), QueueSource::FullRefresh).correlation_id.is_none());
+	}
+
+	#[test]
+	fn test_health_reports_last_cycle() {
+		let reply: serde_json::Value = serde_json::from_str(&send("health", None).reply.unwrap()).unwrap();
+		assert_eq!(reply["daemon_version"], btu_scheduler::get_package_version());
+		assert!(reply.get("last_cycle").is_some());
+		// The test configuration's Redis is unreachable: the count is unknown, not zero.
+		assert!(reply["rq_job_count"].is_null());
+		assert!(reply["queue_last_enqueue_age_secs"].is_null());
+		// Neither is MariaDB's.
+		assert_eq!(reply["mysql"]["status"], "connection_refused");
+
+		let summary = btu_scheduler::scheduler::CycleSummary::new(chrono::Utc::now(), 1669968000);
+		*ipc_stream::LAST_CYCLE_SUMMARY.lock().unwrap() = Some(summary);
+		let reply: serde_json::Value = serde_json::from_str(&send("health", None).reply.unwrap()).unwrap();
+		assert_eq!(reply["last_cycle"]["cutoff_unix"], 1669968000);
+		assert_eq!(reply["last_cycle"]["eligible"], 0);
+	}
+
+	#[test]
+	fn test_status_reports_thread_statistics() {
+		use chrono::TimeZone;
+		use btu_scheduler::metrics::{record_connection_attempt, Backend};
+		use btu_scheduler::scheduler::CycleSummary;
+		use crate::daemon_status::DaemonStatus;
+
+		let started_at = chrono::Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 0).unwrap();
+		let status = DaemonStatus::new();
+		let report = status.report(started_at, started_at + chrono::Duration::seconds(90), Some(4));
+		assert_eq!((report.uptime_secs, report.internal_queue_length), (90, Some(4)));
+		assert_eq!((report.last_full_refresh, report.last_scheduler_pass, report.jobs_enqueued_since_start), (None, None, 0));
+
+		// Thread #2 refreshes; Thread #3 makes two passes, and the enqueued jobs add up.
+		status.record_full_refresh(started_at + chrono::Duration::seconds(5));
+		let mut summary = CycleSummary::new(started_at, 1669968000);
+		summary.enqueued = 3;
+		status.record_scheduler_pass(started_at + chrono::Duration::seconds(60), &summary);
+		status.record_scheduler_pass(started_at + chrono::Duration::seconds(120), &summary);
+		let report = status.report(started_at, started_at + chrono::Duration::seconds(121), None);
+		assert_eq!(report.last_full_refresh.as_deref(), Some("2022-12-02T08:00:05Z"));
+		assert_eq!(report.last_scheduler_pass.as_deref(), Some("2022-12-02T08:02:00Z"));
+		assert_eq!(report.jobs_enqueued_since_start, 6);
+
+		// Over the socket.  Nothing in the test configuration is reachable.
+		record_connection_attempt(Backend::Mysql, false);
+		let reply: serde_json::Value = serde_json::from_str(&send("status", None).reply.unwrap()).unwrap();
+		assert_eq!(reply["daemon_version"], btu_scheduler::get_package_version());
+		assert_eq!(reply["internal_queue_length"], 0);
+		assert_eq!(reply["mysql"]["succeeded"], false);
+		assert!(reply["uptime_secs"].is_i64());
+		assert!(reply.get("last_full_refresh").is_some() && reply.get("redis").is_some());
+	}
+
+	#[test]
+	fn test_refill_entries_route_back_to_their_site() {
+		use btu_scheduler::config::split_schedule_id;
+		use crate::refill_entry;
+
+		let two_sites: String = format!("{}{}", SAMPLE_TOML, r#"
+			[[site]]
+			name = "hr.example.com"
+			mysql_user = "hr"
+			mysql_password = "secret"
+			mysql_database = "hr_db"
+			webserver_token = "token hr:456"
+		"#);
+		let app_config = AppConfig::new_from_toml_string(&two_sites).unwrap();
+		let entries: Vec<QueueEntry> = app_config.site_configs().iter()
+			.map(|site_config| refill_entry(site_config, "TS-0001", Some("2022-12-01 10:00:00.000000"), "0 * * * *", 0, QueueSource::FullRefresh))
+			.collect();
+		let ids: Vec<&str> = entries.iter().map(|entry| entry.schedule_id.as_str()).collect();
+		assert_eq!(ids, vec!["TS-0001", "hr.example.com::TS-0001"]);
+		assert_eq!(entries[0].fingerprint, entries[1].fingerprint);
+
+		// Thread #1 reads each entry from its own site.
+		let mut queue = ScheduleQueue::default();
+		for entry in entries {
+			assert!(queue.push_back(entry));
+		}
+		let mut tokens: Vec<String> = Vec::new();
+		while let Some(entry) = queue.pop_front() {
+			let (site, task_schedule_id) = split_schedule_id(entry.schedule_id.as_str());
+			assert_eq!(task_schedule_id, "TS-0001");
+			tokens.push(app_config.for_site(site).unwrap().webserver_token);
+		}
+		assert_eq!(tokens, vec!["token abc:def", "token hr:456"]);
+	}
+
+	/// A fresh directory under the system's temp directory, unique to one test.
+	fn temp_directory(test_name: &str) -> std::path::PathBuf {
+		let directory = std::env::temp_dir().join(format!("btu_test_{}_{}", test_name, std::process::id()));
+		let _ = std::fs::remove_dir_all(&directory);
+		std::fs::create_dir_all(&directory).unwrap();
+		directory
+	}
+
+	#[test]
+	fn test_socket_listener_creates_parent_directories() {
+		use std::os::unix::fs::PermissionsExt;
+		use ipc_stream::{create_socket_listener, SocketSetupError};
+
+		let root = temp_directory("socket_parents");
+		let socket_path = root.join("run/btu_scheduler/btu.sock");
+		let socket_path: &str = socket_path.to_str().unwrap();
+
+		// With 'create_socket_dir' off, a missing directory is an error that names it.
+		match create_socket_listener(socket_path, false) {
+			Err(SocketSetupError::MissingDirectory { path }) => assert!(path.as_str().ends_with("run/btu_scheduler")),
+			other => panic!("expected MissingDirectory, not {:?}", other.map(|_| ())),
+		}
+
+		let listener = create_socket_listener(socket_path, true).unwrap();
+		let mode: u32 = std::fs::metadata(root.join("run/btu_scheduler")).unwrap().permissions().mode();
+		assert_eq!(mode & 0o777, 0o755);
+
+		// The self-connection was already accepted; the next client is a real one.
+		listener.set_nonblocking(true).unwrap();
+		assert!(listener.accept().is_err());
+		drop(listener);
+
+		// A stale socket file from a previous run is replaced.
+		assert!(create_socket_listener(socket_path, true).is_ok());
+		let _ = std::fs::remove_dir_all(&root);
+	}
+
+	#[test]
+	fn test_socket_listener_reports_unusable_directories() {
+		use std::os::unix::fs::PermissionsExt;
+		use ipc_stream::{create_socket_listener, SocketSetupError};
+
+		let root = temp_directory("socket_failures");
+
+		// A regular file where a directory should be.
+		std::fs::write(root.join("not_a_directory"), b"").unwrap();
+		let socket_path = root.join("not_a_directory/btu/btu.sock");
+		let error = create_socket_listener(socket_path.to_str().unwrap(), true).map(|_| ()).unwrap_err();
+		assert!(matches!(error, SocketSetupError::CreateDirectory { .. }), "{:?}", error);
+		assert!(error.to_string().contains("not_a_directory/btu"), "{}", error);
+		assert!(error.to_string().contains("os error"), "{}", error);
+
+		// A read-only directory.  Root ignores permissions, so this half only runs as an ordinary user.
+		if !nix::unistd::geteuid().is_root() {
+			let read_only = root.join("read_only");
+			std::fs::create_dir(&read_only).unwrap();
+			std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+			let error = create_socket_listener(read_only.join("btu/btu.sock").to_str().unwrap(), true).map(|_| ()).unwrap_err();
+			assert!(matches!(error, SocketSetupError::CreateDirectory { .. }), "{:?}", error);
+			assert!(error.to_string().contains("Permission denied"), "{}", error);
+			std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o755)).unwrap();
+		}
+		let _ = std::fs::remove_dir_all(&root);
+	}
+
+	/// Collects envelopes in memory, instead of sending them to a Sentry server.
+	#[cfg(feature = "sentry")]
+	struct MockTransport(Arc<Mutex<Vec<sentry::Envelope>>>);
+
+	#[cfg(feature = "sentry")]
+	impl sentry::Transport for MockTransport {
+		fn send_envelope(&self, envelope: sentry::Envelope) {
+			self.0.lock().unwrap().push(envelope);
+		}
+	}
+
+	#[cfg(feature = "sentry")]
+	#[test]
+	fn test_sentry_layer_forwards_error_events() {
+		use tracing_subscriber::prelude::*;
+		use crate::error_reporting::{client_options, tracing_layer};
+
+		let app_config = sample_app_config();
+		let envelopes: Arc<Mutex<Vec<sentry::Envelope>>> = Arc::new(Mutex::new(Vec::new()));
+		let mut options = client_options(&app_config, "https://public@sentry.example.com/1".parse().unwrap());
+		options.transport = Some(Arc::new(Arc::new(MockTransport(Arc::clone(&envelopes)))));  // an Arc<Transport> is its own factory
+		let client = Arc::new(sentry::Client::from(options));
+		let hub = Arc::new(sentry::Hub::new(Some(Arc::clone(&client)), Arc::new(sentry::Scope::default())));
+
+		let layer_config = app_config.clone();
+		thread::Builder::new().name("1_Internal_Queue".to_owned()).spawn(move || {
+			sentry::Hub::run(hub, || {
+				let subscriber = tracing_subscriber::registry().with(tracing_layer(&layer_config));
+				tracing::subscriber::with_default(subscriber, || {
+					tracing::warn!("Not forwarded.");
+					tracing::error!("Synthetic error for Sentry.");
+				});
+			});
+		}).unwrap().join().unwrap();
+		client.flush(Some(Duration::from_secs(1)));
+
+		let envelopes = envelopes.lock().unwrap();
+		let events: Vec<&sentry::protocol::Event> = envelopes.iter().filter_map(|envelope| envelope.event()).collect();
+		assert_eq!(events.len(), 1, "only the ERROR event is forwarded");
+		let event = events[0];
+		assert_eq!(event.message.as_deref(), Some("Synthetic error for Sentry."));
+		assert_eq!(event.tags.get("thread").map(String::as_str), Some("1_Internal_Queue"));
+		assert_eq!(event.tags.get("environment_name").map(String::as_str), Some("Unit Test"));
+		assert_eq!(event.tags.get("version").map(String::as_str), Some(btu_scheduler::get_package_version()));
+		assert_eq!(event.environment.as_deref(), Some("Unit Test"));
+	}
+
+	/// Fetch a path from the status page with a bare HTTP request; returns the whole response.
+	fn http_request(address: std::net::SocketAddr, method: &str, path: &str) -> String {
+		use std::io::{Read, Write};
+		let mut stream = std::net::TcpStream::connect(address).unwrap();
+		stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+		stream.write_all(format!("{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", method, path).as_bytes()).unwrap();
+		let mut response = String::new();
+		stream.read_to_string(&mut response).unwrap();
+		response
+	}
+
+	#[test]
+	fn test_status_page() {
+		use crate::status_page::{bind_status_page, render_status_page, serve_status_page, StatusPageError, StatusSnapshot};
+
+		// Nothing is served unless configured; and never on a non-loopback address, unless remote access is allowed.
+		let mut app_config = sample_app_config();
+		assert!(bind_status_page(&app_config).unwrap().is_none());
+		app_config.status_page_listen_addr = Some("0.0.0.0:0".to_owned());
+		assert!(matches!(bind_status_page(&app_config), Err(StatusPageError::NotLoopback { .. })));
+		app_config.status_page_listen_addr = Some("no port".to_owned());
+		assert!(matches!(bind_status_page(&app_config), Err(StatusPageError::InvalidAddress { .. })));
+
+		app_config.status_page_listen_addr = Some("127.0.0.1:0".to_owned());
+		let listener = bind_status_page(&app_config).unwrap().unwrap();
+		let address = listener.local_addr().unwrap();
+		let queue: Arc<Mutex<ScheduleQueue>> = Arc::new(Mutex::new(ScheduleQueue::new()));
+		queue.lock().unwrap().push_back(QueueEntry::new("TS-0001".into(), QueueSource::FullRefresh));
+		queue.lock().unwrap().push_back(QueueEntry::new("TS-0002".into(), QueueSource::FullRefresh));
+		let page_queue = Arc::clone(&queue);
+		thread::spawn(move || serve_status_page(listener, move || render_status_page(&StatusSnapshot::collect(&app_config, &page_queue))));
+
+		let response: String = http_request(address, "GET", "/");
+		assert!(response.starts_with("HTTP/1.0 200 OK\r\n"), "{}", response);
+		assert!(response.contains("Content-Type: text/html; charset=utf-8"));
+		for expected in [
+			r#"<meta http-equiv="refresh" content="30">"#,
+			&format!("<tr><th>Version</th><td>{}</td></tr>", btu_scheduler::get_package_version()),
+			"<tr><th>Environment</th><td>Unit Test</td></tr>",
+			r#"<tr><th>Redis</th><td><span class="bad">unreachable</span></td></tr>"#,
+			"<tr><th>Internal queue</th><td>2 pending</td></tr>",
+			"<h2>Next scheduled tasks</h2>",
+			"<h2>Recent warnings and errors</h2>",
+		] {
+			assert!(response.contains(expected), "missing '{}' in:\n{}", expected, response);
+		}
+
+		// Read-only: no other method, and no other page.
+		assert!(http_request(address, "POST", "/").starts_with("HTTP/1.0 405 Method Not Allowed\r\n"));
+		assert!(http_request(address, "GET", "/clear_internal_queue").starts_with("HTTP/1.0 404 Not Found\r\n"));
+		assert_eq!(queue.lock().unwrap().len(), 2);
+	}
+
+	#[test]
+	fn test_status_page_rendering() {
+		use crate::logging::LogEntry;
+		use crate::status_page::{format_uptime, render_status_page, StatusSnapshot};
+
+		let snapshot = StatusSnapshot {
+			version: "0.3.8".to_owned(),
+			environment: "Production".to_owned(),
+			uptime_secs: 2 * 86_400 + 3 * 3600 + 14 * 60 + 5,
+			redis_connected: true,
+			mysql_connected: false,
+			queue_length: None,
+			upcoming: vec![("TS-0001".to_owned(), "2023-01-13 09:00:00 PST".to_owned())],
+			last_cycle: None,
+			recent_problems: vec![LogEntry {
+				timestamp: "2023-01-13T17:00:00Z".to_owned(), level: "ERROR".to_owned(), thread_name: "3_Scheduler".to_owned(),
+				message: "Task <b>TS-0002</b> & friends".to_owned(),
+			}],
+		};
+		let page: String = render_status_page(&snapshot);
+		assert!(page.contains("<tr><th>Uptime</th><td>2d 3h 14m</td></tr>"));
+		assert!(page.contains(r#"<tr><th>Redis</th><td><span class="ok">connected</span></td></tr>"#));
+		assert!(page.contains(r#"<tr><th>MariaDB</th><td><span class="bad">unreachable</span></td></tr>"#));
+		assert!(page.contains("<tr><th>Internal queue</th><td>(busy)</td></tr>"));
+		assert!(page.contains("<tr><th>Last cycle</th><td>(none yet)</td></tr>"));
+		assert!(page.contains("<tr><td>TS-0001</td><td>2023-01-13 09:00:00 PST</td></tr>"));
+		// Log messages are escaped.
+		assert!(page.contains("<td>Task &lt;b&gt;TS-0002&lt;/b&gt; &amp; friends</td>"), "{}", page);
+
+		assert_eq!((format_uptime(45), format_uptime(125), format_uptime(3 * 3600 + 60), format_uptime(-5)),
+		           ("45s".to_owned(), "2m".to_owned(), "3h 1m".to_owned(), "0s".to_owned()));
+	}
+}
@@ -0,0 +1,36 @@
+// list_tasks.rs
+
+/*
+	Example of using 'btu_scheduler' as a library, from outside the crate.
+
+	Lists every submitted BTU Task, along with the Python function it calls.
+
+		cargo run --example list_tasks -- /etc/btu_scheduler/btu_scheduler.toml
+*/
+
+use btu_scheduler::config::AppConfig;
+use btu_scheduler::task::{BtuTask, read_enabled_tasks};
+
+fn main() {
+
+	let config_path: Option<String> = std::env::args().nth(1);
+	let app_config: AppConfig = match AppConfig::new_from_toml_file(config_path.as_deref()) {
+		Ok(result) => result,
+		Err(error) => {
+			eprintln!("Error while creating AppConfig from TOML configuration file.\n{}", error);
+			std::process::exit(1);
+		}
+	};
+
+	let tasks: Vec<BtuTask> = match read_enabled_tasks(&app_config) {
+		Ok(result) => result,
+		Err(error) => {
+			eprintln!("Error while reading BTU Tasks from the MariaDB database: {}", error);
+			std::process::exit(1);
+		}
+	};
+
+	for task in tasks.iter() {
+		println!("{} : {} -> {}", task.task_key, task.description_short(), task.path_to_function());
+	}
+}
@@ -1,14 +1,16 @@
 
+use std::collections::HashSet;
 use std::str::FromStr;
 
 use cron::Schedule;
-use chrono::{DateTime, TimeZone, Utc, NaiveDateTime}; // See also: Local, TimeZone
+use chrono::{DateTime, LocalResult, TimeZone, Utc, NaiveDate, NaiveDateTime}; // See also: Local, TimeZone
 use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
 use tracing::{trace, debug, info, warn, error, span, Level};
 
 use crate::errors::CronError;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct CronStruct {
 	second: Option<String>,
 	minute: Option<String>,
@@ -34,6 +36,88 @@ impl CronStruct {
 			self.year.as_ref().unwrap_or(&wildcard_string)
 		)
 	}
+
+	/// Both the day of the month and the day of the week are restricted (neither is '*' nor '?').
+	fn restricts_both_days(&self) -> bool {
+		let restricted = |element: &Option<String>| element.as_deref().map_or(false, |element| element != "?");
+		restricted(&self.day_of_month) && restricted(&self.day_of_week)
+	}
+}
+
+/// How to read a cron expression that restricts both the day of the month and the day of the week, such as "0 9 13 * Fri".
+/// The 'cron' crate requires both to match: only Friday the 13th.  Vixie cron runs when either matches: every 13th, and every Friday.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DomDowSemantics {
+	#[default]
+	And,
+	Or
+}
+
+impl std::fmt::Display for DomDowSemantics {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			DomDowSemantics::And => write!(f, "and"),
+			DomDowSemantics::Or => write!(f, "or")
+		}
+	}
+}
+
+/// Explains how a cron expression that restricts both days is read, with its next runs, so a person can verify the intent.
+pub fn dom_dow_notice(cron_expression_string: &str, semantics: DomDowSemantics, upcoming: &[DateTime<Tz>]) -> String {
+	let interpretation: &str = match semantics {
+		DomDowSemantics::And => "only when BOTH match (Vixie cron runs when EITHER matches)",
+		DomDowSemantics::Or => "when EITHER matches (as Vixie cron does)"
+	};
+	let runs: Vec<String> = upcoming.iter().map(|runtime| runtime.format("%a %Y-%m-%d %H:%M %Z").to_string()).collect();
+	format!("Cron '{}' restricts both the day of the month and the day of the week.  BTU runs it {}, because \
+	         cron_dom_dow_semantics = \"{}\".  Next {} runs: {}",
+	        cron_expression_string, interpretation, semantics, runs.len(), if runs.is_empty() { "(none)".to_owned() } else { runs.join(", ") })
+}
+
+/// True when a cron expression restricts both the day of the month and the day of the week, so DomDowSemantics matters.
+pub fn restricts_day_of_month_and_week(cron_expression_string: &str) -> bool {
+	CronStruct::from_str(cron_expression_string).map_or(false, |cron_struct| cron_struct.restricts_both_days())
+}
+
+/// How a cron expression was written, compared to what Frappe's 5-element cron field can store.  Frappe implies a seconds
+/// element of '0' and a year element of '*'.  Any other seconds or year is honored by the scheduler, but invisible in Frappe's UI.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CronClassification {
+	pub elements: usize,          // 5, 6, or 7, as written
+	pub cron7: String,            // the normalized 7-element form the scheduler reads
+	pub seconds: Option<String>,  // the seconds element, when other than '0'
+	pub year: Option<String>,     // the year element, when other than '*'
+	pub frappe_compatible: bool,  // neither seconds nor year is present
+}
+
+impl CronClassification {
+	/// Why the expression cannot be shown faithfully in Frappe; None when it can.
+	pub fn notice(&self) -> Option<String> {
+		let mut parts: Vec<String> = Vec::new();
+		if let Some(seconds) = &self.seconds {
+			parts.push(format!("seconds '{}'", seconds));
+		}
+		if let Some(year) = &self.year {
+			parts.push(format!("year '{}'", year));
+		}
+		if parts.is_empty() {
+			return None;
+		}
+		Some(format!("Cron has {} elements ('{}'): its {} {} honored by the scheduler, but invisible in Frappe's 5-element cron field.",
+		             self.elements, self.cron7, parts.join(" and "), if parts.len() == 1 { "is" } else { "are" }))
+	}
+}
+
+/// Classify a cron expression of 5, 6, or 7 elements.  A repeat interval ("PT2H") is not a cron expression, and is an error.
+pub fn classify_cron(cron_expression_string: &str) -> Result<CronClassification, CronError> {
+	let elements: usize = cron_expression_string.split_whitespace().count();
+	let cron7: String = cron_str_to_cron_str7(cron_expression_string)?.split_whitespace().collect::<Vec<&str>>().join(" ");
+	let vector_cron7: Vec<&str> = cron7.split(' ').collect();
+	let seconds: Option<String> = Some(vector_cron7[0]).filter(|seconds| *seconds != "0").map(str::to_owned);
+	let year: Option<String> = Some(vector_cron7[6]).filter(|year| *year != "*").map(str::to_owned);
+	let frappe_compatible: bool = seconds.is_none() && year.is_none();
+	Ok(CronClassification { elements, cron7, seconds, year, frappe_compatible })
 }
 
 impl FromStr for CronStruct {
@@ -100,17 +184,14 @@ pub fn cron_str_to_cron_str7 (cron_expression_string: &str) -> Result<String, Cr
 pub fn tz_cron_to_utc_datetimes(cron_expression_string: &str,
 	                            cron_timezone: Tz,
 								from_utc_datetime: Option<DateTime<Utc>>,
-	                            number_of_results: &usize) -> Result<Vec<DateTime<Utc>>, CronError> {
+	                            number_of_results: &usize,
+	                            semantics: DomDowSemantics) -> Result<Vec<DateTime<Utc>>, CronError> {
 	/*
 		Given a cron string and Time Zone, what are the next set of UTC Datetime values?
 		Documentation: https://docs.rs/cron/0.9.0/cron
 	*/
 
-	/* NOTE 1:  This is a VERY simplistic implementation.
-	            What is truly required is something that handles Daylight Savings and time shifts.
-				But it's good enough for today.
-
-	   NOTE 2:  Rather than returning a Vector of UTC Datetimes, it would be -better- to return an Iterator.
+	/* NOTE:  Rather than returning a Vector of UTC Datetimes, it would be -better- to return an Iterator.
 				However, I don't know how to do that with Rust (yet).  One step at a time.
 	*/
 	let this_cronstruct: CronStruct;
@@ -123,68 +204,606 @@ pub fn tz_cron_to_utc_datetimes(cron_expression_string: &str,
 		}
 	}
 
-	let schedule = Schedule::from_str(&this_cronstruct.to_string()).unwrap();  // Schedule requires a 7-element cron expression.
-
-	/* 	The initial results below will be UTC datetimes.  Because that is what Schedule outputs.
+	// With "or" semantics, merge the runs of the day-of-month schedule with the runs of the day-of-week schedule.
+	if semantics == DomDowSemantics::Or && this_cronstruct.restricts_both_days() {
+		let by_day_of_month = CronStruct { day_of_week: None, ..this_cronstruct.clone() };
+		let by_day_of_week = CronStruct { day_of_month: None, ..this_cronstruct };
+		let mut result: Vec<DateTime<Utc>> = cronstruct_to_utc_datetimes(&by_day_of_month, cron_timezone, from_utc_datetime, number_of_results)?;
+		result.extend(cronstruct_to_utc_datetimes(&by_day_of_week, cron_timezone, from_utc_datetime, number_of_results)?);
+		result.sort_unstable();
+		result.dedup();
+		result.truncate(*number_of_results);
+		return Ok(result)
+	}
+	cronstruct_to_utc_datetimes(&this_cronstruct, cron_timezone, from_utc_datetime, number_of_results)
+}
 
-		Example 1:
-			* The current local time in Pacific is 09:01am (1701 UTC)
-			* Your cron schedule is simple: It has a cadence of 30 minutes, with no specific Day or Month
-			* The schedule will return a datetime value = 1730 UTC
-			* This value is correct, as-is.
+fn cronstruct_to_utc_datetimes(this_cronstruct: &CronStruct,
+                               cron_timezone: Tz,
+                               from_utc_datetime: Option<DateTime<Utc>>,
+                               number_of_results: &usize) -> Result<Vec<DateTime<Utc>>, CronError> {
 
-		1. Strip the time zone component, so the UTC DateTime becomes a Naive Datetime.
-		2. Change to Local Times by applying the function argument `cron_timezone`
-		   At this point, it's as-if Schedule created Local times in the first place.
-		3. Finally, shift the DateTime to UTC, in preparation for integration with RQ.
-
-		Yes, this will completely break during Daylight Savings.  For today, it's 80/20.
-	*/
+	// Schedule requires a 7-element cron expression.  It also rejects values the CronStruct accepted, such as day-of-week 0.
+	let schedule = Schedule::from_str(&this_cronstruct.to_string()).map_err(|_error| CronError::InvalidExpression)?;
 
 	/*
 		Scenario #1: If the hour part of Cron is the entire range of hours (*), then accept the Schedule as-is.
 	                 There is no need to recalculate Date Time values.
+		The same is true when the cron is already in UTC (for example, with 'cron_strings_are_utc').
 	*/
-	if this_cronstruct.hour.is_none() {
-		let mut result: Vec<DateTime<Utc>> = Vec::new();
-		for utc_datetime in schedule.after(&from_utc_datetime.unwrap_or(Utc::now())).take(*number_of_results) {
-			result.push(utc_datetime);
+	let from_utc_datetime: DateTime<Utc> = from_utc_datetime.unwrap_or_else(Utc::now);
+	if this_cronstruct.hour.is_none() || cron_timezone == Tz::UTC {
+		return Ok(schedule.after(&from_utc_datetime).take(*number_of_results).collect())
+	}
+
+	/*
+		Scenario #2: The cron requires a specific Time Of Day, in the local time zone.
+
+		The 'cron' crate only knows time zones, not wall clocks.  So iterate the Schedule over *naive* local times (disguised
+		as UTC), starting from the local time of 'from_utc_datetime', and convert each occurrence with the rules of the day
+		it falls on.  An 07:00 New York run is 12:00 UTC in winter, and 11:00 UTC in summer.
+
+		Daylight Saving Time changes:
+		* Spring forward: a local time inside the gap (02:30 on the second Sunday of March, in the US) does not exist; skip it.
+		* Fall back: a local time in the repeated hour (01:30 on the first Sunday of November) happens twice; run at the first.
+	*/
+	let from_local: DateTime<Utc> = Utc.from_utc_datetime(&from_utc_datetime.with_timezone(&cron_timezone).naive_local());
+	Ok(schedule.after(&from_local)
+		.filter_map(|local_as_utc| local_to_utc(cron_timezone, &local_as_utc.naive_utc()))
+		.filter(|utc_datetime| *utc_datetime > from_utc_datetime)  // in the repeated hour, the first of two times may already be past
+		.take(*number_of_results)
+		.collect())
+}  // end of function
+
+
+/**
+  Converts a naive local time into UTC, with the rules of the time zone on that date.
+  Returns None for a local time that does not exist (the Spring gap), and the earliest instant for one that happens twice.
+*/
+fn local_to_utc(cron_timezone: Tz, local_datetime: &NaiveDateTime) -> Option<DateTime<Utc>> {
+	match cron_timezone.from_local_datetime(local_datetime) {
+		LocalResult::Single(tz_aware) => Some(tz_aware.with_timezone(&Utc)),
+		LocalResult::Ambiguous(earliest, _latest) => Some(earliest.with_timezone(&Utc)),
+		LocalResult::None => {
+			debug!("Skipping local time {} because it does not exist in time zone {}.", local_datetime, cron_timezone);
+			None
 		}
-		return Ok(result)
 	}
+}
 
-	let mut result: Vec<DateTime<Utc>> = Vec::new();
-	// Scenario #2: If the cron requires a specific Time Of Day ---> we have to adjust for UTC.
-	
-	// What is the offset between UTC and Local Time Zone?
-	// local_offset = self.timezone.utcoffset(datetime.now(self.timezone))
-	// local_offset_hours = int(local_offset.total_seconds() / 3600)  # offset in second / second in an hour
 
-	// use argument 'from_utc_datetime', otherwise the current UTC datetime.
+/**
+  Returns the first candidate that does not fall on a holiday.  Holidays are compared with the candidate's
+  date in the cron's time zone; a 23:30 Pacific run is on the Pacific date, even though it is the next day in UTC.
+  Returns None if every candidate is a holiday.
+*/
+pub fn first_non_holiday(candidates: &[DateTime<Utc>], cron_timezone: Tz, holidays: &HashSet<NaiveDate>) -> Option<DateTime<Utc>> {
+	for candidate in candidates {
+		let local_date: NaiveDate = candidate.with_timezone(&cron_timezone).date_naive();
+		if !holidays.contains(&local_date) {
+			return Some(*candidate);
+		}
+		info!("Skipping execution at {} because {} is a holiday.", candidate.with_timezone(&cron_timezone), local_date);
+	}
+	None
+}
 
-	let actual_utc = Utc::now();
-	// let foo_naive_datetime: NaiveDateTime = NaiveDateTime::from_timestamp(actual_utc.timestamp(), 0);
-	// let foo_tz_aware = cron_timezone.from_local_datetime(&foo_naive_datetime).unwrap();
-	// let adjusted_utc: DateTime<Utc> = DateTime::<Utc>::from_utc(foo_tz_aware.naive_utc(), Utc);
+/**
+  A pseudo-random offset in [0, max_jitter_secs], derived from the Task Schedule ID.\
+  Uses stable_hash(), so a schedule keeps its offset across restarts and upgrades.
+*/
+pub fn jitter_offset_secs(task_schedule_id: &str, max_jitter_secs: u32) -> u32 {
+	if max_jitter_secs == 0 {
+		return 0;
+	}
+	(crate::stable_hash(task_schedule_id) % (u64::from(max_jitter_secs) + 1)) as u32
+}
 
-	for utc_datetime in schedule.after(&from_utc_datetime.unwrap_or(actual_utc)).take(*number_of_results) {
+/**
+  Delay an occurrence by a jitter offset.  The offset is capped at half the gap to the following occurrence (if known),
+  so a jittered run never passes, or crowds, the next one.  Returns the new time and the offset actually applied.
+*/
+pub fn apply_jitter(occurrence: DateTime<Utc>, following: Option<DateTime<Utc>>, offset_secs: u32) -> (DateTime<Utc>, i64) {
+	let mut applied: i64 = i64::from(offset_secs);
+	if let Some(following) = following {
+		applied = applied.min((following - occurrence).num_seconds().max(0) / 2);
+	}
+	(occurrence + chrono::Duration::seconds(applied), applied)
+}
 
-		// This logic acquire the exact same Hour:Minute, but in local time.
-		let naive_datetime: NaiveDateTime = NaiveDateTime::from_timestamp_opt(utc_datetime.timestamp(), 0).unwrap();
-		let tz_aware = cron_timezone.from_local_datetime(&naive_datetime).unwrap();
-		let new_utc_datetime: DateTime<Utc> = DateTime::<Utc>::from_utc(tz_aware.naive_utc(), Utc);
+/*
+	Repeat intervals are an alternative to cron, for Task Schedules such as "every 2 hours".  The BTU app stores them as
+	an ISO-8601 duration ("PT2H", "P1DT12H", "P2W"), or as a simple phrase ("every 30 minutes", "every hour", "15 minutes").
+	Only weeks, days, hours, minutes, and seconds are accepted.  Years and months have no fixed length, so they are rejected.
+*/
 
-		//use chrono::Datelike;
-		//if new_utc_datetime.date().day() != utc_datetime.date().day() {
-		// info!("Original and new 'utc_datetime' fall on different days ({} vs {})", utc_datetime, new_utc_datetime);
-		//}
+/// Parse a repeat interval.  The interval must be longer than zero.
+pub fn parse_repeat_interval(text: &str) -> Result<chrono::Duration, CronError> {
+	let invalid = || CronError::InvalidInterval { text: text.to_owned() };
+	let trimmed: String = text.trim().to_ascii_uppercase();
+	let seconds: i64 = if trimmed.starts_with('P') {
+		parse_iso8601_duration_secs(&trimmed).ok_or_else(invalid)?
+	}
+	else {
+		parse_interval_phrase_secs(&trimmed.to_ascii_lowercase()).ok_or_else(invalid)?
+	};
+	if seconds <= 0 {
+		return Err(invalid());
+	}
+	Ok(chrono::Duration::seconds(seconds))
+}
 
-		result.push(new_utc_datetime);
+/// "P[nW][nD][T[nH][nM][nS]]", already uppercase.  None if anything else is present.
+fn parse_iso8601_duration_secs(text: &str) -> Option<i64> {
+	let body: &str = text.strip_prefix('P')?;
+	let (date_part, time_part) = match body.split_once('T') {
+		Some((_, "")) => return None,  // a 'T' must be followed by something
+		Some((date_part, time_part)) => (date_part, Some(time_part)),
+		None => (body, None),
+	};
+	let date_secs: i64 = sum_designators(date_part, &[('W', 604800), ('D', 86400)])?;
+	let time_secs: i64 = time_part.map_or(Some(0), |time_part| sum_designators(time_part, &[('H', 3600), ('M', 60), ('S', 1)]))?;
+	if date_part.is_empty() && time_part.is_none() {
+		return None;  // a bare "P"
 	}
-	Ok(result)
+	date_secs.checked_add(time_secs)
+}
 
-}  // end of function
+/// Sum "<number><designator>" pairs, where designators must appear in the given order, at most once each.
+fn sum_designators(text: &str, designators: &[(char, i64)]) -> Option<i64> {
+	let mut total: i64 = 0;
+	let mut remaining: &str = text;
+	let mut allowed: &[(char, i64)] = designators;
+	while !remaining.is_empty() {
+		let digits_end: usize = remaining.find(|c: char| !c.is_ascii_digit())?;
+		if digits_end == 0 {
+			return None;
+		}
+		let amount: i64 = remaining[..digits_end].parse().ok()?;
+		let designator: char = remaining[digits_end..].chars().next()?;
+		let position: usize = allowed.iter().position(|(candidate, _)| *candidate == designator)?;
+		total = total.checked_add(amount.checked_mul(allowed[position].1)?)?;
+		allowed = &allowed[position + 1..];
+		remaining = &remaining[digits_end + designator.len_utf8()..];
+	}
+	Some(total)
+}
+
+/// "[every] [n] <unit>", already lowercase.  Units may be singular or plural: second, minute (or min), hour, day, week.
+fn parse_interval_phrase_secs(text: &str) -> Option<i64> {
+	let words: Vec<&str> = text.split_whitespace().collect();
+	let words: &[&str] = words.strip_prefix(&["every"]).unwrap_or(&words);
+	let (amount, unit): (i64, &str) = match words {
+		[unit] => (1, unit),
+		[amount, unit] => (amount.parse().ok()?, unit),
+		_ => return None,
+	};
+	let unit_secs: i64 = match unit.strip_suffix('s').unwrap_or(unit) {
+		"second" | "sec" => 1,
+		"minute" | "min" => 60,
+		"hour" => 3600,
+		"day" => 86400,
+		"week" => 604800,
+		_ => return None,
+	};
+	amount.checked_mul(unit_secs)
+}
+
+/// The next 'number_of_results' runs of a repeat interval, counting from 'anchor' (which is not itself included).
+pub fn interval_runtimes(anchor: DateTime<Utc>, interval: chrono::Duration, number_of_results: usize) -> Vec<DateTime<Utc>> {
+	(1..=number_of_results)
+		.map_while(|multiple| {
+			let offset_secs: i64 = interval.num_seconds().checked_mul(i64::try_from(multiple).ok()?)?;
+			anchor.checked_add_signed(chrono::Duration::try_seconds(offset_secs)?)
+		})
+		.collect()
+}
+
+/// A repeat interval in words, using the largest unit that divides it evenly (example: "every 90 minutes").
+pub fn describe_interval(interval: chrono::Duration) -> String {
+	let seconds: i64 = interval.num_seconds();
+	let (amount, unit) = [(604800, "week"), (86400, "day"), (3600, "hour"), (60, "minute")].iter()
+		.find(|(unit_secs, _)| seconds % unit_secs == 0)
+		.map_or((seconds, "second"), |(unit_secs, unit)| (seconds / unit_secs, *unit));
+	if amount == 1 {
+		format!("every {}", unit)
+	} else {
+		format!("every {} {}s", amount, unit)
+	}
+}
+
+/*
+	Describing cron expressions in English.
+
+	Each field of the normalized 7-element expression is parsed into parts (single values, ranges, and steps).  Fields using
+	syntax beyond that (such as 'L' or '#') are echoed as written.  Day-of-week numbers follow the 'cron' crate: Sunday is 1.
+*/
+
+const MONTH_NAMES: [&str; 12] = ["January", "February", "March", "April", "May", "June",
+                                 "July", "August", "September", "October", "November", "December"];
+const WEEKDAY_NAMES: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CronUnit {
+	Second,
+	Minute,
+	Hour,
+	DayOfMonth,
+	Month,
+	DayOfWeek,
+	Year
+}
+
+impl CronUnit {
+
+	fn noun(self) -> &'static str {
+		match self {
+			CronUnit::Second => "second",
+			CronUnit::Minute => "minute",
+			CronUnit::Hour => "hour",
+			CronUnit::DayOfMonth => "day",
+			CronUnit::Month => "month",
+			CronUnit::DayOfWeek => "day of the week",
+			CronUnit::Year => "year"
+		}
+	}
+
+	/// Seconds, minutes, and hours are counted ("every 15 minutes"); dates are ordinal ("every 2nd day").
+	fn is_time_of_day(self) -> bool {
+		matches!(self, CronUnit::Second | CronUnit::Minute | CronUnit::Hour)
+	}
 
+	fn value_from_text(self, text: &str) -> Option<u32> {
+		if let Ok(number) = text.parse::<u32>() {
+			return Some(number)
+		}
+		let names: &[&str] = match self {
+			CronUnit::Month => &MONTH_NAMES,
+			CronUnit::DayOfWeek => &WEEKDAY_NAMES,
+			_ => return None
+		};
+		let lowercase: String = text.to_lowercase();
+		names.iter()
+			.position(|name| lowercase.len() >= 3 && name.to_lowercase().starts_with(&lowercase))
+			.and_then(|index| u32::try_from(index + 1).ok())
+	}
+
+	fn value_text(self, value: u32) -> String {
+		let name = |names: &[&'static str]| (value as usize).checked_sub(1).and_then(|index| names.get(index))
+			.map_or_else(|| value.to_string(), |name| (*name).to_owned());
+		match self {
+			CronUnit::Month => name(&MONTH_NAMES),
+			CronUnit::DayOfWeek => name(&WEEKDAY_NAMES),
+			CronUnit::DayOfMonth => ordinal_number(value),
+			_ => value.to_string()
+		}
+	}
+}
+
+/// One comma-separated element of a cron field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CronFieldPart {
+	Single(u32),
+	Range(u32, u32),
+	Step { start: Option<u32>, end: Option<u32>, step: u32 }
+}
+
+struct CronField {
+	unit: CronUnit,
+	raw: String,
+	parts: Option<Vec<CronFieldPart>>,  // empty for a wildcard; None when the syntax is too complex to describe
+}
+
+impl CronField {
+
+	fn new(unit: CronUnit, raw: Option<&str>) -> Self {
+		let raw: &str = raw.unwrap_or("*");
+		let parts: Option<Vec<CronFieldPart>> = if raw == "*" || raw == "?" {
+			Some(Vec::new())
+		} else {
+			raw.split(',').map(|element| CronField::parse_part(unit, element)).collect()
+		};
+		CronField { unit, raw: raw.to_owned(), parts }
+	}
+
+	fn parse_part(unit: CronUnit, element: &str) -> Option<CronFieldPart> {
+		let range = |text: &str| -> Option<(u32, u32)> {
+			let (first, last) = text.split_once('-')?;
+			Some((unit.value_from_text(first)?, unit.value_from_text(last)?))
+		};
+		if let Some((base, step)) = element.split_once('/') {
+			let step: u32 = step.parse().ok().filter(|step| *step > 0)?;
+			let (start, end) = match base {
+				"*" => (None, None),
+				_ if base.contains('-') => range(base).map(|(first, last)| (Some(first), Some(last)))?,
+				_ => (Some(unit.value_from_text(base)?), None)
+			};
+			return Some(CronFieldPart::Step { start, end, step })
+		}
+		if element.contains('-') {
+			return range(element).map(|(first, last)| CronFieldPart::Range(first, last))
+		}
+		unit.value_from_text(element).map(CronFieldPart::Single)
+	}
+
+	fn is_any(&self) -> bool {
+		self.parts.as_ref().map_or(false, |parts| parts.is_empty())
+	}
+
+	/// The values, when the field is nothing but single values.
+	fn singles(&self) -> Option<Vec<u32>> {
+		let parts: &Vec<CronFieldPart> = self.parts.as_ref().filter(|parts| !parts.is_empty())?;
+		let mut values: Vec<u32> = parts.iter()
+			.map(|part| match part { CronFieldPart::Single(value) => Some(*value), _ => None })
+			.collect::<Option<Vec<u32>>>()?;
+		values.sort_unstable();
+		values.dedup();
+		Some(values)
+	}
+
+	/// Every value the field names, when it has only single values and ranges.
+	fn expanded_values(&self) -> Option<Vec<u32>> {
+		let parts: &Vec<CronFieldPart> = self.parts.as_ref().filter(|parts| !parts.is_empty())?;
+		let mut values: Vec<u32> = Vec::new();
+		for part in parts {
+			match *part {
+				CronFieldPart::Single(value) => values.push(value),
+				CronFieldPart::Range(first, last) => values.extend(first..=last),
+				CronFieldPart::Step { .. } => return None
+			}
+		}
+		values.sort_unstable();
+		values.dedup();
+		Some(values)
+	}
+
+	/// The field is a single step expression, such as '*/15'.
+	fn is_single_step(&self) -> bool {
+		matches!(self.parts.as_deref(), Some([CronFieldPart::Step { .. }]))
+	}
+
+	fn has_steps(&self) -> bool {
+		self.parts.as_ref().map_or(false, |parts| parts.iter().any(|part| matches!(part, CronFieldPart::Step { .. })))
+	}
+
+	fn part_text(&self, part: &CronFieldPart) -> String {
+		let unit: CronUnit = self.unit;
+		match *part {
+			CronFieldPart::Single(value) => unit.value_text(value),
+			CronFieldPart::Range(first, last) => format!("{} through {}", unit.value_text(first), unit.value_text(last)),
+			CronFieldPart::Step { start, end, step } => {
+				let every: String = match (step, unit.is_time_of_day()) {
+					(1, _) => format!("every {}", unit.noun()),
+					(_, true) => format!("every {} {}s", step, unit.noun()),
+					(_, false) => format!("every {} {}", ordinal_number(step), unit.noun())
+				};
+				match (start, end) {
+					(Some(first), Some(last)) => format!("{} from {} through {}", every, unit.value_text(first), unit.value_text(last)),
+					(Some(first), None) if !(unit.is_time_of_day() && first == 0) => format!("{} starting at {}", every, unit.value_text(first)),
+					_ => every
+				}
+			}
+		}
+	}
+
+	/// The parts as an English list, such as "Monday through Friday and Sunday".  None when the field could not be parsed.
+	fn list_text(&self) -> Option<String> {
+		self.parts.as_ref().map(|parts| english_list(parts.iter().map(|part| self.part_text(part)).collect()))
+	}
+
+	/// The field exactly as written, for syntax that is not described.
+	fn echo(&self) -> String {
+		format!("{} '{}'", self.unit.noun(), self.raw)
+	}
+
+	/// "every 15 minutes", "at minutes 0 through 10", or "during hours 9 through 17".
+	fn phrase(&self, preposition: &str) -> String {
+		match (self.is_any(), self.is_single_step(), self.list_text()) {
+			(true, _, _) => format!("every {}", self.unit.noun()),
+			(false, true, Some(text)) => text,
+			(false, false, Some(text)) => {
+				let plural: &str = if self.singles().map_or(false, |values| values.len() == 1) { "" } else { "s" };
+				format!("{} {}{} {}", preposition, self.unit.noun(), plural, text)
+			},
+			(false, _, None) => format!("{} {}", preposition, self.echo())
+		}
+	}
+}
+
+/// A number as an English ordinal: 1st, 2nd, 3rd, 4th, 11th, 22nd.
+fn ordinal_number(number: u32) -> String {
+	let suffix: &str = match (number % 10, number % 100) {
+		(_, 11..=13) => "th",
+		(1, _) => "st",
+		(2, _) => "nd",
+		(3, _) => "rd",
+		_ => "th"
+	};
+	format!("{}{}", number, suffix)
+}
+
+/// "a", "a and b", or "a, b, and c".
+fn english_list(mut items: Vec<String>) -> String {
+	match items.len() {
+		0 | 1 => items.pop().unwrap_or_default(),
+		2 => format!("{} and {}", items[0], items[1]),
+		_ => {
+			let last: String = items.pop().unwrap_or_default();
+			format!("{}, and {}", items.join(", "), last)
+		}
+	}
+}
+
+/// The time-of-day part of a description, and whether it is a list of specific times ("09:30 and 14:30").
+fn describe_time_of_day(second: &CronField, minute: &CronField, hour: &CronField) -> (String, bool) {
+
+	let on_the_minute: bool = second.singles().map_or(false, |seconds| seconds == [0]);
+
+	// A handful of specific times, such as "09:30 and 17:30".
+	if let (Some(seconds), Some(minutes), Some(hours)) = (second.singles(), minute.singles(), hour.singles()) {
+		if seconds.len() * minutes.len() * hours.len() <= 6 {
+			let times: Vec<String> = hours.iter()
+				.flat_map(|hour| minutes.iter().map(move |minute| (*hour, *minute)))
+				.flat_map(|(hour, minute)| seconds.iter().map(move |second| match on_the_minute {
+					true => format!("{:02}:{:02}", hour, minute),
+					false => format!("{:02}:{:02}:{:02}", hour, minute, second)
+				}))
+				.collect();
+			return (english_list(times), true)
+		}
+	}
+
+	let mut phrases: Vec<String> = Vec::new();
+	if !on_the_minute {
+		phrases.push(second.phrase("at"));
+		if minute.is_any() && hour.is_any() {
+			return (phrases.join(", "), false)
+		}
+	}
+	match minute.singles() {
+		// Minutes past the hour, such as "every 2 hours at :00 and :30".
+		Some(minutes) => {
+			let past_the_hour: String = format!("at {}", english_list(minutes.iter().map(|minute| format!(":{:02}", minute)).collect()));
+			if hour.is_any() || hour.is_single_step() {
+				phrases.push(format!("{} {}", hour.phrase("during"), past_the_hour));
+			} else {
+				phrases.push(format!("{} {}", past_the_hour, hour.phrase("during")));
+			}
+		},
+		None => {
+			phrases.push(minute.phrase("at"));
+			if !hour.is_any() {
+				phrases.push(hour.phrase("during"));
+			}
+		}
+	}
+	(phrases.join(", "), false)
+}
+
+/// The calendar part of a description, such as "every weekday" or "on the 1st of every month".  Also returns a qualifier
+/// that belongs after the time of day, such as ", if it falls on a Friday" or ", in 2023".
+fn describe_days(day_of_month: &CronField, month: &CronField, day_of_week: &CronField, year: &CronField,
+                 semantics: DomDowSemantics) -> (String, String) {
+
+	let months: String = if month.is_any() {
+		"every month".to_owned()
+	} else if month.has_steps() {
+		month.phrase("in")
+	} else {
+		month.list_text().unwrap_or_else(|| month.echo())
+	};
+	// Weekdays without "every", such as "weekday" or "Monday and Friday".
+	let weekdays: Option<String> = match day_of_week.expanded_values() {
+		Some(days) if days == [2, 3, 4, 5, 6] => Some("weekday".to_owned()),
+		_ if day_of_week.has_steps() => None,
+		_ => day_of_week.list_text()
+	};
+	let days_of_month: String = if day_of_month.is_single_step() {
+		day_of_month.phrase("on")
+	} else {
+		day_of_month.list_text().map_or_else(|| format!("on {}", day_of_month.echo()), |text| format!("on the {}", text))
+	};
+
+	let mut qualifier: String = String::new();
+	let description: String = match (day_of_month.is_any(), day_of_week.is_any()) {
+		(true, true) if month.is_any() => "every day".to_owned(),
+		(true, true) => format!("every day in {}", months),
+		(true, false) => {
+			let every_weekday: String = weekdays.map_or_else(|| format!("on {}", day_of_week.echo()), |days| format!("every {}", days));
+			if month.is_any() { every_weekday } else { format!("{} in {}", every_weekday, months) }
+		},
+		(false, true) => format!("{} of {}", days_of_month, months),
+		(false, false) if semantics == DomDowSemantics::Or => {
+			let every_weekday: String = weekdays.map_or_else(|| format!("on {}", day_of_week.echo()), |days| format!("every {}", days));
+			format!("{} of {} and {}", days_of_month, months, every_weekday)
+		},
+		// By default, both the day of the month and the day of the week must match.
+		(false, false) => {
+			qualifier.push_str(&format!(", if it falls on a {}", weekdays.unwrap_or_else(|| day_of_week.echo())));
+			format!("{} of {}", days_of_month, months)
+		}
+	};
+	if !year.is_any() {
+		qualifier.push_str(&format!(", in {}", year.list_text().unwrap_or_else(|| year.echo())));
+	}
+	(description, qualifier)
+}
+
+/// Describe a cron expression in English, such as "every weekday at 09:30" for "30 9 * * Mon-Fri".
+/// The time zone is not part of the expression; callers append it.  Returns an error if the 'cron' crate rejects the expression.
+pub fn describe_cron(cron: &str) -> Result<String, CronError> {
+	describe_cron_with_semantics(cron, DomDowSemantics::And)
+}
+
+/// Like describe_cron(), for a cron expression read with the given day-of-month/day-of-week semantics.
+pub fn describe_cron_with_semantics(cron: &str, semantics: DomDowSemantics) -> Result<String, CronError> {
+
+	let cron_expression_string: String = cron_str_to_cron_str7(cron)?;
+	Schedule::from_str(&cron_expression_string).map_err(|_| CronError::InvalidExpression)?;
+	let cron_struct: CronStruct = CronStruct::from_str(&cron_expression_string)?;
+	let field = |unit: CronUnit, raw: &Option<String>| CronField::new(unit, raw.as_deref());
+
+	let (time_of_day, specific_times) = describe_time_of_day(
+		&field(CronUnit::Second, &cron_struct.second),
+		&field(CronUnit::Minute, &cron_struct.minute),
+		&field(CronUnit::Hour, &cron_struct.hour)
+	);
+	let (days, qualifier) = describe_days(
+		&field(CronUnit::DayOfMonth, &cron_struct.day_of_month),
+		&field(CronUnit::Month, &cron_struct.month),
+		&field(CronUnit::DayOfWeek, &cron_struct.day_of_week),
+		&field(CronUnit::Year, &cron_struct.year),
+		semantics
+	);
+	Ok(match (specific_times, days.as_str()) {
+		(true, _) => format!("{} at {}{}", days, time_of_day, qualifier),
+		(false, "every day") => format!("{}{}", time_of_day, qualifier),
+		(false, _) => format!("{}, {}{}", time_of_day, days, qualifier)
+	})
+}
+
+/// How schedules are labelled when 'cron_strings_are_utc' is true, in place of a Task Schedule's own time zone.
+pub static UTC_ONLY_LABEL: &str = "UTC (cron_strings_are_utc)";
+
+/// The time zone a Task Schedule's cron expression is read in.  With 'cron_strings_are_utc', always UTC.
+pub fn effective_cron_timezone(stored_timezone: Tz, cron_strings_are_utc: bool) -> Tz {
+	if cron_strings_are_utc { Tz::UTC } else { stored_timezone }
+}
+
+/// The time zone shown beside a schedule's description.
+pub fn cron_timezone_label(stored_timezone: &str, cron_strings_are_utc: bool) -> &str {
+	if cron_strings_are_utc { UTC_ONLY_LABEL } else { stored_timezone }
+}
+
+/// A Task Schedule's cron expression (with its time zone) or repeat interval in English.  Falls back to the expression as written.
+pub fn describe_schedule(expression: &str, timezone: &str, semantics: DomDowSemantics) -> String {
+	match describe_cron_with_semantics(expression, semantics) {
+		Ok(description) => format!("{} {}", description, timezone),
+		Err(_) => parse_repeat_interval(expression).map_or_else(|_| format!("'{}' {}", expression, timezone), describe_interval)
+	}
+}
+
+/**
+  Which upcoming occurrences to store for a Task Schedule.  Normally just the first.  But when occurrences are closer together
+  than the scheduler's polling interval, one stored run per poll would lose the rest.  So store every occurrence within two
+  polling intervals of the first, bounded by 'max_occurrences'.  Expects 'occurrences' in ascending order.
+*/
+pub fn occurrences_within_polling_horizon(occurrences: &[DateTime<Utc>], polling_interval_secs: u64, max_occurrences: usize) -> Vec<DateTime<Utc>> {
+	let Some(first) = occurrences.first() else {
+		return Vec::new();
+	};
+	let polling_interval_secs: i64 = i64::try_from(polling_interval_secs).unwrap_or(i64::MAX / 2);
+	let is_high_frequency: bool = occurrences.get(1).map_or(false, |second| (*second - *first).num_seconds() < polling_interval_secs);
+	if !is_high_frequency {
+		return vec![*first];
+	}
+	let horizon: DateTime<Utc> = *first + chrono::Duration::seconds(2 * polling_interval_secs);
+	occurrences.iter()
+		.take_while(|occurrence| **occurrence <= horizon)
+		.take(max_occurrences.max(1))
+		.copied()
+		.collect()
+}
 
 pub fn future_foo(cron_expression_string: &str, _cron_timezone: Tz, _number_of_results: usize) -> () {
 
@@ -0,0 +1,226 @@
+// circuit_breaker.rs
+
+/*
+	A Task Schedule whose runs always fail (for example, its Python function was deleted, so the pickle fetch fails) would
+	otherwise be retried forever, and report the same error on every occurrence.
+
+	With 'max_consecutive_failures', each failed run increments the counter 'btu_scheduler:failure_count:<id>', and each
+	successful run deletes it.  When the counter reaches the maximum, the circuit "opens": the Task Schedule is added to the
+	set 'btu_scheduler:circuit_open', its upcoming runs are removed, and Thread #1 and Thread #3 pass it over.
+
+	The circuit closes when an operator runs 'btu circuit --reset <ID>', or when the counter expires after
+	'circuit_breaker_cooldown_secs'.  The next full refresh then schedules it again.
+*/
+
+use redis::{Commands, RedisError};
+use tracing::{error, info, warn};
+
+#[cfg(feature = "email-feat")]
+use crate::email::{self, make_email_body_preamble};
+
+use crate::config::AppConfig;
+use crate::events::{self, EventKind, SchedulerEvent};
+use crate::ids::TaskScheduleId;
+
+pub use crate::redis_keys::{RQ_KEY_CIRCUIT_OPEN, RQ_KEY_FAILURE_COUNT_PREFIX, failure_count_key};
+
+/// What a run's outcome did to a Task Schedule's circuit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitTransition {
+	Disabled,                  // 'max_consecutive_failures' is 0
+	Counted { failures: u32 },  // failed, but fewer times in a row than the maximum
+	Opened { failures: u32 },   // just reached the maximum; worth a notification
+	StillOpen,                 // failed again, while already open
+	Reset,                     // succeeded after earlier failures
+	Unchanged,                 // succeeded, with no earlier failures
+}
+
+/// Where failure counters and open circuits are kept.  In the daemon, that is Redis.
+pub trait CircuitStore {
+	fn increment_failures(&mut self, key: &str) -> u32;
+	fn read_failures(&mut self, key: &str) -> Option<u32>;
+	fn remove_failures(&mut self, key: &str) -> bool;
+	fn expire_failures(&mut self, key: &str, secs: u64);
+	fn add_open(&mut self, task_schedule_id: &str) -> bool;
+	fn remove_open(&mut self, task_schedule_id: &str) -> bool;
+	fn is_member_open(&mut self, task_schedule_id: &str) -> bool;
+	fn open_members(&mut self) -> Vec<String>;
+}
+
+impl CircuitStore for redis::Connection {
+	fn increment_failures(&mut self, key: &str) -> u32 {
+		self.incr(key, 1).unwrap_or_else(|error: RedisError| {
+			error!("Unable to increment Redis key '{}': {}", key, error);
+			0
+		})
+	}
+
+	fn read_failures(&mut self, key: &str) -> Option<u32> {
+		self.get(key).unwrap_or_else(|error: RedisError| {
+			warn!("Unable to read Redis key '{}': {}", key, error);
+			None
+		})
+	}
+
+	fn remove_failures(&mut self, key: &str) -> bool {
+		self.del::<_, u32>(key).map_or_else(|error| {
+			error!("Unable to delete Redis key '{}': {}", key, error);
+			false
+		}, |removed| removed > 0)
+	}
+
+	fn expire_failures(&mut self, key: &str, secs: u64) {
+		let secs: usize = usize::try_from(secs).unwrap_or(usize::MAX);
+		if let Err(error) = self.expire::<_, ()>(key, secs) {
+			error!("Unable to set the expiration of Redis key '{}': {}", key, error);
+		}
+	}
+
+	fn add_open(&mut self, task_schedule_id: &str) -> bool {
+		self.sadd::<_, _, u32>(RQ_KEY_CIRCUIT_OPEN, task_schedule_id).map_or_else(|error| {
+			error!("Unable to add '{}' to Redis set '{}': {}", task_schedule_id, RQ_KEY_CIRCUIT_OPEN, error);
+			false
+		}, |added| added > 0)
+	}
+
+	fn remove_open(&mut self, task_schedule_id: &str) -> bool {
+		self.srem::<_, _, u32>(RQ_KEY_CIRCUIT_OPEN, task_schedule_id).map_or_else(|error| {
+			error!("Unable to remove '{}' from Redis set '{}': {}", task_schedule_id, RQ_KEY_CIRCUIT_OPEN, error);
+			false
+		}, |removed| removed > 0)
+	}
+
+	fn is_member_open(&mut self, task_schedule_id: &str) -> bool {
+		self.sismember(RQ_KEY_CIRCUIT_OPEN, task_schedule_id).unwrap_or_else(|error: RedisError| {
+			warn!("Unable to read Redis set '{}': {}", RQ_KEY_CIRCUIT_OPEN, error);
+			false
+		})
+	}
+
+	fn open_members(&mut self) -> Vec<String> {
+		self.smembers(RQ_KEY_CIRCUIT_OPEN).unwrap_or_else(|error: RedisError| {
+			warn!("Unable to read Redis set '{}': {}", RQ_KEY_CIRCUIT_OPEN, error);
+			Vec::new()
+		})
+	}
+}
+
+/// Count a failed run.  At 'max_failures' in a row, open the circuit; its counter then expires after 'cooldown_secs', if any.
+pub fn record_failure<S: CircuitStore>(store: &mut S, task_schedule_id: &str, max_failures: u32, cooldown_secs: Option<u64>) -> CircuitTransition {
+	if max_failures == 0 {
+		return CircuitTransition::Disabled;
+	}
+	let key: String = failure_count_key(task_schedule_id);
+	let failures: u32 = store.increment_failures(&key);
+	if failures < max_failures {
+		return CircuitTransition::Counted { failures };
+	}
+	if !store.add_open(task_schedule_id) {
+		return CircuitTransition::StillOpen;
+	}
+	if let Some(cooldown_secs) = cooldown_secs {
+		store.expire_failures(&key, cooldown_secs);
+	}
+	CircuitTransition::Opened { failures }
+}
+
+/// Count a successful run: the failures are no longer consecutive.
+pub fn record_success<S: CircuitStore>(store: &mut S, task_schedule_id: &str) -> CircuitTransition {
+	let removed: bool = store.remove_failures(&failure_count_key(task_schedule_id));
+	if store.remove_open(task_schedule_id) || removed {
+		return CircuitTransition::Reset;
+	}
+	CircuitTransition::Unchanged
+}
+
+/// Is the Task Schedule's circuit open?  Once its counter has expired (the cool-down elapsed), the circuit is closed here.
+pub fn is_open<S: CircuitStore>(store: &mut S, task_schedule_id: &str) -> bool {
+	if !store.is_member_open(task_schedule_id) {
+		return false;
+	}
+	if store.read_failures(&failure_count_key(task_schedule_id)).is_some() {
+		return true;
+	}
+	if store.remove_open(task_schedule_id) {
+		info!("Task Schedule {}: the circuit breaker's cool-down has elapsed; it will be scheduled again.", task_schedule_id);
+	}
+	false
+}
+
+/// Close a Task Schedule's circuit, and forget its failures.  False if there was nothing to reset.
+pub fn reset<S: CircuitStore>(store: &mut S, task_schedule_id: &str) -> bool {
+	let removed: bool = store.remove_failures(&failure_count_key(task_schedule_id));
+	store.remove_open(task_schedule_id) || removed
+}
+
+/// A Task Schedule whose circuit is open, and its count of consecutive failures (None once the counter has expired).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenCircuit {
+	pub task_schedule_id: TaskScheduleId,
+	pub failures: Option<u32>,
+}
+
+/// Every open circuit, ordered by Task Schedule ID.
+pub fn open_circuits<S: CircuitStore>(store: &mut S) -> Vec<OpenCircuit> {
+	let mut members: Vec<String> = store.open_members();
+	members.sort();
+	members.into_iter().map(|member| {
+		let failures: Option<u32> = store.read_failures(&failure_count_key(&member));
+		OpenCircuit { task_schedule_id: TaskScheduleId::from(member), failures }
+	}).collect()
+}
+
+/// An operator's reset ('btu circuit --reset'), recorded as an event.  False if there was nothing to reset.
+pub fn reset_by_operator(app_config: &AppConfig, redis_conn: &mut redis::Connection, schedule_key: &TaskScheduleId) -> bool {
+	if !reset(redis_conn, schedule_key.as_str()) {
+		return false;
+	}
+	info!("Task Schedule {}: the circuit breaker was reset by an operator.", schedule_key);
+	events::record_event(app_config, redis_conn, SchedulerEvent::new(EventKind::Reset).schedule(schedule_key));
+	true
+}
+
+/// Thread #3's bookkeeping after each run.  'failure' is the reason the run failed, or None if it succeeded.
+pub fn record_run_outcome(app_config: &AppConfig, redis_conn: &mut redis::Connection, schedule_key: &TaskScheduleId, failure: Option<&str>) {
+	let Some(reason) = failure else {
+		if record_success(redis_conn, schedule_key.as_str()) == CircuitTransition::Reset {
+			info!("Task Schedule {} ran successfully; its count of consecutive failures is reset.", schedule_key);
+		}
+		return;
+	};
+	let max_failures: u32 = app_config.max_consecutive_failures.unwrap_or_default();
+	match record_failure(redis_conn, schedule_key.as_str(), max_failures, app_config.circuit_breaker_cooldown_secs) {
+		CircuitTransition::Opened { failures } => {
+			let reopens: String = match app_config.circuit_breaker_cooldown_secs {
+				Some(secs) => format!("after {} seconds, or when reset with 'btu circuit --reset {}'", secs, schedule_key),
+				None => format!("when reset with 'btu circuit --reset {}'", schedule_key),
+			};
+			let message: String = format!("Task Schedule {} failed {} times in a row, and will not be scheduled again until {}.  Last error: {}",
+			                              schedule_key, failures, reopens, reason);
+			error!("{}", message);
+			// Only its upcoming runs: the failure counter and the open circuit must stay.
+			if let Err(error) = crate::scheduler::remove_scheduled_runs(redis_conn, schedule_key) {
+				warn!("Unable to remove the upcoming runs of Task Schedule {}: {}", schedule_key, error);
+			}
+			events::record_event(app_config, redis_conn, SchedulerEvent::new(EventKind::Tripped).schedule(schedule_key)
+				.reason(format!("{} consecutive failures; last error: {}", failures, reason)));
+			notify_opened(app_config, &message);
+		},
+		CircuitTransition::Counted { failures } => {
+			warn!("Task Schedule {} has failed {} of {} times in a row.", schedule_key, failures, max_failures);
+		},
+		_ => {}
+	}
+}
+
+/// Email the recipients (when email is configured) about a circuit that just opened.
+#[allow(unused_variables)]
+fn notify_opened(app_config: &AppConfig, message: &str) {
+	#[cfg(feature = "email-feat")]  // Only compile this code when email feature is enabled:
+	if app_config.email_addresses.as_ref().map_or(false, |addresses| !addresses.is_empty()) {
+		let body: String = format!("{}\n{}", make_email_body_preamble(app_config), message);
+		if let Err(error) = email::send_email(app_config, "BTU stopped scheduling a failing Task Schedule", &body) {
+			error!("Error while attempting to send an email: {:?}", error);
+		}
+	}
+}
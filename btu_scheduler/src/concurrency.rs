@@ -0,0 +1,130 @@
+// concurrency.rs
+
+/*
+	A BTU Task with 'max_concurrent' may have at most that many RQ Jobs running (or waiting for a worker) at once, across
+	every Task Schedule and queue.  A warehouse sync that locks tables, for example.
+
+	Each enqueued instance is recorded in the sorted set 'btu_scheduler:running:<task>': the member is the RQ Job ID, and
+	the score is when its slot is released at the latest (Unix time: enqueued, plus the job's timeout).  The key itself
+	expires with its latest instance.  Workers never release a slot; instead, before each run, the recorded instances are
+	reconciled with their jobs' hashes:
+
+	* A job that ended (finished, failed, stopped, canceled), or whose hash is gone, releases its slot at once.
+	* A started job whose timeout was extended keeps its slot until its start time plus the new timeout.
+	* Otherwise, a slot is released when its recorded time passes; even by a job that never left the queue.
+*/
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use redis::{Commands, RedisError};
+use tracing::debug;
+
+use crate::ids::RqJobId;
+use crate::rq;
+
+pub use crate::redis_keys::{RQ_KEY_RUNNING_PREFIX, running_key};
+
+/// One recorded instance of a Task: its RQ Job, and when its slot is released at the latest (Unix time).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunningInstance {
+	pub job_id: RqJobId,
+	pub expires_at: i64,
+}
+
+/// Why a recorded instance no longer occupies a slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReleaseReason {
+	Ended,    // finished, failed, stopped, or canceled
+	Deleted,  // the job's hash no longer exists
+	Expired,  // its time passed
+}
+
+/// What a job's hash says about a recorded instance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstanceState {
+	Active { expires_at: i64 },
+	Released(ReleaseReason),
+}
+
+/// Classify one recorded instance from its 'rq:job:<id>' hash (empty when the key no longer exists).
+pub fn classify_instance(job_hash: &HashMap<String, Vec<u8>>, recorded_expires_at: i64, now: DateTime<Utc>) -> InstanceState {
+	if job_hash.is_empty() {
+		return InstanceState::Released(ReleaseReason::Deleted);
+	}
+	let status: String = rq::hashmap_value_to_optstring(job_hash, "status").unwrap_or_default();
+	if matches!(status.as_str(), "finished" | "failed" | "stopped" | "canceled") {
+		return InstanceState::Released(ReleaseReason::Ended);
+	}
+	let mut expires_at: i64 = recorded_expires_at;
+	if status == "started" {
+		let timeout_secs: Option<i64> = rq::hashmap_value_to_optstring(job_hash, "timeout")
+			.and_then(|timeout| timeout.trim().parse::<f64>().ok())
+			.map(|timeout| timeout as i64);
+		match (rq::hashmap_value_to_utcdatetime(job_hash, "started_at"), timeout_secs) {
+			// RQ lets a job with timeout -1 run forever; it holds its slot for as long as it runs.
+			(_, Some(timeout_secs)) if timeout_secs < 0 => expires_at = expires_at.max(now.timestamp() + 1),
+			(Some(started_at), Some(timeout_secs)) => expires_at = expires_at.max(started_at.timestamp() + timeout_secs),
+			_ => {}
+		}
+	}
+	if expires_at <= now.timestamp() {
+		return InstanceState::Released(ReleaseReason::Expired);
+	}
+	InstanceState::Active { expires_at }
+}
+
+/// The recorded instances that still occupy a slot (with their possibly extended times), and those released.
+pub fn reconcile<F>(recorded: Vec<RunningInstance>, mut read_job_hash: F, now: DateTime<Utc>) -> (Vec<RunningInstance>, Vec<(RqJobId, ReleaseReason)>)
+	where F: FnMut(&RqJobId) -> HashMap<String, Vec<u8>> {
+	let mut active: Vec<RunningInstance> = Vec::new();
+	let mut released: Vec<(RqJobId, ReleaseReason)> = Vec::new();
+	for instance in recorded {
+		match classify_instance(&read_job_hash(&instance.job_id), instance.expires_at, now) {
+			InstanceState::Active { expires_at } => active.push(RunningInstance { job_id: instance.job_id, expires_at }),
+			InstanceState::Released(reason) => released.push((instance.job_id, reason)),
+		}
+	}
+	(active, released)
+}
+
+/// Reconcile a Task's recorded instances with Redis, and return how many still occupy a slot.
+pub fn running_instances(redis_conn: &mut redis::Connection, key: &str, now: DateTime<Utc>) -> Result<usize, RedisError> {
+	let recorded: Vec<(String, i64)> = redis_conn.zrange_withscores(key, 0, -1)?;
+	if recorded.is_empty() {
+		return Ok(0);
+	}
+	let recorded: Vec<RunningInstance> = recorded.into_iter()
+		.map(|(job_id, expires_at)| RunningInstance { job_id: RqJobId::from(job_id), expires_at })
+		.collect();
+	let previous: HashMap<RqJobId, i64> = recorded.iter().map(|instance| (instance.job_id.clone(), instance.expires_at)).collect();
+	let (active, released) = reconcile(recorded, |job_id| {
+		redis_conn.hgetall(rq::job_key(job_id.as_str())).unwrap_or_default()
+	}, now);
+
+	let mut pipeline = redis::pipe();
+	for (job_id, reason) in &released {
+		debug!("Releasing RQ Job '{}' from '{}' ({:?}).", job_id, key, reason);
+		pipeline.zrem(key, job_id.as_str()).ignore();
+	}
+	for instance in active.iter().filter(|instance| previous.get(&instance.job_id) != Some(&instance.expires_at)) {
+		debug!("RQ Job '{}' in '{}' is still running past its recorded time; its slot is held until {}.", instance.job_id, key, instance.expires_at);
+		pipeline.zadd(key, instance.job_id.as_str(), instance.expires_at).ignore();
+	}
+	if let Some(latest) = active.iter().map(|instance| instance.expires_at).max() {
+		pipeline.expire_at(key, usize::try_from(latest).unwrap_or_default()).ignore();
+	}
+	pipeline.query::<()>(redis_conn)?;
+	Ok(active.len())
+}
+
+/// Record a newly enqueued instance of a Task.  Its slot is held for at most 'timeout_secs' from now.
+pub fn record_instance(redis_conn: &mut redis::Connection, key: &str, job_id: &RqJobId, timeout_secs: u32, now: DateTime<Utc>) -> Result<(), RedisError> {
+	let expires_at: i64 = now.timestamp() + i64::from(timeout_secs);
+	let latest: Vec<(String, i64)> = redis_conn.zrevrange_withscores(key, 0, 0)?;
+	let key_expires_at: i64 = latest.first().map_or(expires_at, |(_, score)| expires_at.max(*score));
+	redis::pipe().atomic()
+		.zadd(key, job_id.as_str(), expires_at).ignore()
+		.expire_at(key, usize::try_from(key_expires_at).unwrap_or_default()).ignore()
+		.query(redis_conn)
+}
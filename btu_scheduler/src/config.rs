@@ -6,20 +6,35 @@
 */
 
 use std::{fmt, fs};
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use camino::Utf8PathBuf;
 
+use chrono::NaiveDate;
 use chrono_tz::Tz;
+#[cfg(feature = "sql")]
 use mysql::{Opts, Pool};
 use serde::{Deserialize, Serialize};
 use tracing::Level;
 use tracing_subscriber::filter;
 
-use crate::config::error::ConfigError;
+use crate::btu_cron::DomDowSemantics;
+pub use crate::config::error::ConfigError;
+use crate::errors::EmailConfigError;
+use crate::ids::TaskScheduleId;
 use crate::logging::{LevelWrapper, LevelFilterWrapper};
 use tracing::{trace, debug, info, warn, error, span};
 
-static CONFIG_FILE_PATH: &'static str = "/etc/btu_scheduler/btu_scheduler.toml";
+pub static CONFIG_FILE_PATH: &str = "/etc/btu_scheduler/btu_scheduler.toml";
+/// The socket path suggested for system-wide installs.  In user mode, a configuration with this path uses a per-user socket instead.
+pub static SOCKET_FILE_PATH: &str = "/tmp/btu_scheduler.sock";
+
+/// The schema version written by 'btu config-upgrade'.  Increment when AppConfig gains, loses, or renames a setting.
+///   1: the original configuration, before 'environment_name', 'tracing_level', and 'startup_without_database_connections'
+///   2: before the email settings
+///   3: before 'sites', and the optional settings added with them (circuit breaker, job watchdog, status page, systemd, ...)
+///   4: the current shape
+pub const CONFIG_VERSION: u32 = 4;
 
 mod error {
 
@@ -29,48 +44,236 @@ mod error {
 
 	#[derive(ThisError, Debug)]
 	pub enum ConfigError {
-		#[error("Could not deserialize TOML into a Rust object.\n    {source:?}")]
+		#[error("Could not deserialize TOML into a Rust object.\n    {source:?}\n    (Files from older versions of BTU can be converted with 'btu config-upgrade'.)")]
 		ConfigLoad {
 			#[from] 
 			source: TomlError,
 		},
 		#[error("Cannot find the TOML configuration file on disk.")]
-		MissingConfigFile
+		MissingConfigFile,
+		#[error("The configuration is missing required settings: {}", missing.join(", "))]
+		MissingSettings {
+			missing: Vec<&'static str>
+		},
+		#[error("Invalid [[site]] '{site}' in the configuration: {reason}")]
+		InvalidSite {
+			site: String,
+			reason: &'static str
+		},
+		#[error("The environment variables do not form a valid configuration.{}", environment_problems(missing, invalid))]
+		Environment {
+			missing: Vec<String>,
+			invalid: Vec<String>  // "BTU_NAME: reason"
+		},
+		#[error("Cannot read the dotenv file '{path}': {source}")]
+		DotEnvFile {
+			path: String,
+			source: std::io::Error
+		},
+		#[error("Cannot read the TOML configuration file '{path}': {source}")]
+		ReadFile {
+			path: String,
+			source: std::io::Error
+		},
+		#[error("The configuration has invalid settings:{}", problems.iter().map(|problem| format!("\n    {}", problem)).collect::<String>())]
+		InvalidSettings {
+			problems: Vec<String>
+		},
+		#[error("A configuration read from {config_source} cannot be read again.")]
+		NotReloadable {
+			config_source: &'static str
+		}
+	}
+
+	fn environment_problems(missing: &[String], invalid: &[String]) -> String {
+		let mut problems = String::new();
+		if !missing.is_empty() {
+			problems.push_str(&format!("\n    Missing: {}", missing.join(", ")));
+		}
+		for problem in invalid {
+			problems.push_str(&format!("\n    Invalid: {}", problem));
+		}
+		problems
+	}
+}
+
+/// Separates a site name from a Task Schedule ID, in keys shared by every site (Internal Queue entries, TSIKs, and markers).
+pub const SITE_SEPARATOR: &str = "::";
+
+/// One Frappe site, on a bench that hosts several (DNS multi-tenancy).  Each site has its own BTU app, MariaDB database,
+/// and API token.  Settings left out here are inherited from the top-level configuration.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SiteConfig {
+	pub name: String,  // Identifies the site in Redis keys and socket requests.  Letters, digits, '.', '-', and '_' only.
+	pub mysql_user: String,
+	pub mysql_password: String,
+	pub mysql_host: Option<String>,
+	pub mysql_port: Option<u32>,
+	pub mysql_database: String,
+	pub webserver_ip: Option<String>,
+	pub webserver_port: Option<u16>,
+	pub webserver_use_tls: Option<bool>,
+	pub webserver_host_header: Option<String>,  // Usually the site's domain name, when the web server is shared.
+	pub webserver_token: String,
+}
+
+/// A Task Schedule ID, prefixed with its site's name.  The default (top-level) site's IDs are not prefixed.
+pub fn qualify_schedule_id(site: Option<&str>, task_schedule_id: &str) -> TaskScheduleId {
+	match site {
+		Some(site) => TaskScheduleId::from(format!("{}{}{}", site, SITE_SEPARATOR, task_schedule_id)),
+		None => TaskScheduleId::from(task_schedule_id)
+	}
+}
+
+/// The reverse of qualify_schedule_id(): the site name (None for the default site), and the Task Schedule ID.
+pub fn split_schedule_id(qualified_id: &str) -> (Option<&str>, &str) {
+	match qualified_id.split_once(SITE_SEPARATOR) {
+		Some((site, task_schedule_id)) => (Some(site), task_schedule_id),
+		None => (None, qualified_id)
+	}
+}
+
+/// Where an AppConfig was read from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ConfigSource {
+	#[default]
+	Text,  // a TOML string, such as in tests
+	File(Utf8PathBuf),
+	Environment(Vec<String>),  // the BTU_ variables that were read
+}
+
+impl fmt::Display for ConfigSource {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ConfigSource::Text => write!(f, "TOML text"),
+			ConfigSource::File(path) => write!(f, "{}", path),
+			ConfigSource::Environment(_) => write!(f, "environment variables"),
+		}
+	}
+}
+
+impl ConfigSource {
+	pub fn kind(&self) -> &'static str {
+		match self {
+			ConfigSource::Text => "text",
+			ConfigSource::File(_) => "file",
+			ConfigSource::Environment(_) => "environment",
+		}
 	}
 }
 
-#[derive(Deserialize, Serialize)]
+/**
+  Settings the daemon reads only once, as it starts: its identity, error reporting, the Unix Domain Socket, the status page,
+  and systemd readiness.  A reload keeps their running values, and reports any change as needing a restart.
+*/
+pub static RESTART_REQUIRED_SETTINGS: &[&str] = &[
+	"environment_name", "sentry_dsn", "socket_path", "socket_file_group_owner", "create_socket_dir",
+	"status_page_listen_addr", "status_page_allow_remote", "systemd_notify",
+];
+
+/// A configuration read again while the daemon runs, and how it differs from the running one.
+pub struct ConfigReload {
+	pub app_config: AppConfig,  // the new configuration, with the running values of RESTART_REQUIRED_SETTINGS
+	pub changed: Vec<String>,  // settings whose new values take effect now
+	pub restart_required: Vec<String>,  // settings that changed, but keep their running values until a restart
+}
+
+/// Replaces secrets in displayed or serialized configuration.
+pub static REDACTED: &str = "********";
+
+/// Default for 'systemd_thread_stall_secs'.
+pub const DEFAULT_THREAD_STALL_SECS: u64 = 300;
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct AppConfig {
 
+	pub config_version: Option<u32>,  // Schema version of this file (see CONFIG_VERSION).  Missing in files older than 'btu config-upgrade'.
 	pub environment_name: Option<String>,
 	pub full_refresh_internal_secs: u32,
 	pub time_zone_string: String,
-	pub tracing_level: LevelFilterWrapper,
+	#[serde(default = "default_tracing_level")]
+	pub tracing_level: LevelFilterWrapper,  // default INFO
+	#[serde(default)]
 	pub startup_without_database_connections: bool,
+	pub log_history_size: Option<usize>,  // Number of recent log events the daemon keeps in memory (default 500)
+	pub idempotency_ttl_secs: Option<u64>,  // How long socket responses are replayed for a repeated 'idempotency_key' (default 600)
+	pub socket_rate_limit_per_min: Option<u32>,  // Identical socket requests allowed per minute, before replying 'rate_limited' (default 30; 0 disables)
+	pub queue_stats_retention_days: Option<u32>,  // Days each daily hash of per-queue enqueue counts is kept (default 90)
+	pub task_run_history_size: Option<usize>,  // Runs kept per Task Schedule in 'btu_scheduler:task_run_history:<ID>' (default 50; 0 keeps none)
+	pub task_run_history_ttl_days: Option<u32>,  // Days a Task Schedule's run history is kept after its last run, such as once it is deleted (default 30)
+	pub sql_retry_backoff_secs: Option<u32>,  // Seconds Thread #1 waits before reading again, when MariaDB is unreachable (default 15)
+	pub slow_query_threshold_ms: Option<u64>,  // Warn when a single SQL query takes longer than this (default 2000)
+	pub sentry_dsn: Option<String>,  // Report daemon panics and errors to this Sentry project.  Requires the daemon's 'sentry' cargo feature.
 
 	pub email_address_from: Option<String>,
 	pub email_host_name: Option<String>,
 	pub email_host_port: Option<i16>,
+	pub email_security: Option<String>,  // One of "tls", "starttls", or "none".  When missing, chosen by port.
 	pub email_account_name: Option<String>,
 	pub email_account_password: Option<String>,
 
 	pub email_addresses: Option<Vec<String>>,
 	pub email_on_level: Option<LevelWrapper>,  // A wrapper around Level, because the tracing crate doesn't implement Serialize and Deserialize.
+	#[serde(default)]
 	pub email_when_queuing: bool,
 	mysql_user: String,
 	mysql_password: String,
-	mysql_host: String,
-	mysql_port: Option<u32>,
-	mysql_database: String,
+	pub(crate) mysql_host: String,
+	pub(crate) mysql_port: Option<u32>,
+	pub(crate) mysql_database: String,
 	pub rq_host: String,
 	pub rq_port: u32,
+	pub rq_password: Option<String>,  // For a Redis server with 'requirepass'
+	pub rq_database: Option<u32>,  // The logical Redis database that Python RQ uses (default 0)
+	#[serde(default)]
+	pub compress_job_data: bool,  // Store RQ Job 'data' zlib-compressed.  Requires patched Python RQ workers.
+	pub include_queues: Option<Vec<String>>,  // Only schedule Task Schedules on these queues.  Exact names, or prefixes like "stg_*"
+	pub exclude_queues: Option<Vec<String>>,  // Never schedule Task Schedules on these queues.  Same syntax as above.
+	pub allowed_function_prefixes: Option<Vec<String>>,  // Only enqueue BTU Tasks whose Python function starts with one of these (e.g. "btu.")
+	pub holiday_dates: Option<Vec<String>>,  // ISO dates (2022-12-25) when opted-in Task Schedules do not run
+	pub holiday_source: Option<String>,  // Name of a Frappe 'Holiday List', whose dates are added to 'holiday_dates'
+	#[serde(default)]
+	pub schedule_jitter_secs: u32,  // Spread simultaneous Task Schedules by up to this many seconds (default 0)
+	#[serde(default)]
+	pub cron_dom_dow_semantics: DomDowSemantics,  // "and" (default): a cron restricting both day of month and day of week needs both to match.  "or": either.
+	#[serde(default)]
+	pub cron_strings_are_utc: bool,  // When true, every cron expression is read as UTC; each Task Schedule's time zone is ignored (default false)
+	#[serde(default)]
+	pub event_stream_enabled: bool,  // When true, scheduler decisions are appended to the Redis stream 'btu_scheduler:events' (default false)
+	#[serde(default)]
+	pub job_watchdog_enabled: bool,  // When true, the daemon reports enqueued jobs still running past their timeout (default false)
+	pub job_watchdog_grace_secs: Option<u32>,  // Seconds allowed beyond a job's timeout, before the watchdog reports it (default 300)
+	#[serde(default)]
+	pub kill_overrunning_jobs: bool,  // When true, the watchdog also moves overrunning jobs to RQ's failed state (default false)
+	pub stale_job_max_age_hours: Option<u32>,  // BTU-created RQ Jobs never picked up by a worker are deleted after this many hours (default 48)
+	pub stale_job_cleanup_dry_run: Option<bool>,  // When true (the default), stale RQ Jobs are only reported; not deleted
+	pub max_consecutive_failures: Option<u32>,  // Stop scheduling a Task Schedule after this many failed runs in a row (default 0, never)
+	pub circuit_breaker_cooldown_secs: Option<u64>,  // Schedule it again after this many seconds (default: only when reset with 'btu circuit')
+	pub failed_run_retry_secs: Option<u64>,  // A run that failed (such as when the web server did not answer) is tried again, for up to this many seconds after its time (default 3600; 0 never)
+	pub redis_memory_warn_percent: Option<u8>,  // Warn when Redis uses this percentage of its 'maxmemory' (default 90; 0 never warns)
 	pub scheduler_polling_interval: u64,
 	pub socket_path: String,  // Dev Note: The level of effort to make this a PathBuf or Utf8PathBuf, and incorporate with MutexGuard: just too much!
 	pub socket_file_group_owner: String,
+	#[serde(default = "default_create_socket_dir")]
+	pub create_socket_dir: bool,  // Create the socket file's directory (mode 755) if it is missing (default true)
+	pub status_page_listen_addr: Option<String>,  // Serve a read-only HTML status page on this address, such as "127.0.0.1:8975" (default: none)
+	#[serde(default)]
+	pub status_page_allow_remote: bool,  // Allow 'status_page_listen_addr' to be a non-loopback address (default false)
+	#[serde(default)]
+	pub systemd_notify: bool,  // Notify systemd ('Type=notify') when ready, and ping its watchdog while every thread is alive (default false)
+	pub systemd_thread_stall_secs: Option<u64>,  // With 'systemd_notify', stop pinging the watchdog when a thread has not checked in for this long (default 300)
 	pub webserver_ip: String,
     pub webserver_port: u16,
+	pub webserver_use_tls: Option<bool>,  // Connect with https (true) or http (false).  By default, https only when the port is 443.
 	pub webserver_host_header: Option<String>,
-    pub webserver_token: String
+    pub webserver_token: String,
+	pub webserver_timeout_secs: Option<u64>,  // Give up on a request to the Frappe web server after this many seconds (default 30)
+	#[serde(skip)]
+	current_site: Option<String>,  // Set by for_site().  None for the top-level (default) site.
+	#[serde(skip)]
+	source: ConfigSource,  // Set by new_from_toml_file() and new_from_env_vars()
+	#[serde(rename = "site")]
+	pub sites: Option<Vec<SiteConfig>>,  // Additional Frappe sites, as a [[site]] array.  Must remain the last field; TOML writes tables last.
 }
 
 impl AppConfig {
@@ -83,8 +286,9 @@ impl AppConfig {
 		
 			One reason this is possible?  The TOML specification has the concepts of strings, integers, and nulls.  :)
 		*/
-		match toml::from_str(&any_string) {
+		match toml::from_str::<AppConfig>(any_string) {
 			Ok(app_config) => {
+				app_config.validate_sites()?;
 				Ok(app_config)
 			},
 			Err(error) => {
@@ -107,30 +311,95 @@ impl AppConfig {
 		if ! file_path.exists() {
 			// Originally I intended to create a default configuration.  
 			// But this requires elevating to root and restarting the app.  And either way, the user needs to manually key in
-			// values for MySQL and Redis credentials.  So better to just print and exit.
+			// values for MySQL and Redis credentials.  So better to just print, and let the caller exit.
 			println!("\nError: Configuration file '{}' does not exist.", file_path);
-			AppConfig::print_default_config_exit(&file_path);
+			AppConfig::print_default_config(&file_path);
+			return Err(ConfigError::MissingConfigFile);
 		}
 
-		let file_contents: String = fs::read_to_string(file_path)
+		let file_contents: String = fs::read_to_string(&file_path)
 			.expect("Something went wrong while reading the TOML file.");
 
-		AppConfig::new_from_toml_string(&file_contents)
+		let mut app_config: AppConfig = AppConfig::new_from_toml_string(&file_contents)?;
+		app_config.source = ConfigSource::File(file_path);
+		Ok(app_config)
+	}
+
+	/// Build a configuration from BTU_ environment variables, after loading a '.env' file from the working directory (if any).
+	pub fn new_from_env() -> Result<AppConfig, ConfigError> {
+		load_dotenv_file(Path::new(".env"))?;
+		AppConfig::new_from_env_vars(std::env::vars())
+	}
+
+	/// Build a configuration from (name, value) pairs of environment variables.  Every missing or invalid variable is reported together.
+	pub fn new_from_env_vars<I: IntoIterator<Item = (String, String)>>(variables: I) -> Result<AppConfig, ConfigError> {
+		let variables: std::collections::HashMap<String, String> = variables.into_iter().collect();
+		let mut table = toml::value::Table::new();
+		let mut invalid: Vec<String> = Vec::new();
+		for (setting, kind) in ENV_SETTINGS.iter() {
+			let name: String = env_variable_name(setting);
+			let Some(text) = variables.get(&name) else {
+				continue
+			};
+			// Deserializing each setting alone names the variable in any error, such as a port above 65535.
+			let value: Result<toml::Value, String> = env_value_to_toml(*kind, text).and_then(|value| {
+				let single = toml::Value::Table(std::iter::once((setting.to_string(), value.clone())).collect());
+				single.try_into::<PartialAppConfig>().map(|_| value).map_err(|error| error.to_string())
+			});
+			match value {
+				Ok(value) => {
+					table.insert(setting.to_string(), value);
+				},
+				Err(reason) => invalid.push(format!("{}: {}", name, reason))
+			}
+		}
+		let partial: PartialAppConfig = toml::Value::Table(table).try_into()?;
+		let missing: Vec<String> = partial.missing_required_settings().iter()
+			.map(|setting| env_variable_name(setting))
+			.filter(|name| !variables.contains_key(name))  // present but invalid
+			.collect();
+		if !missing.is_empty() || !invalid.is_empty() {
+			return Err(ConfigError::Environment { missing, invalid });
+		}
+		let mut app_config: AppConfig = partial.into_app_config()?;
+		let mut settings: Vec<String> = ENV_SETTINGS.iter().map(|(setting, _)| env_variable_name(setting))
+			.filter(|name| variables.contains_key(name))
+			.collect();
+		settings.sort();
+		app_config.source = ConfigSource::Environment(settings);
+		Ok(app_config)
 	}
 
 	pub fn print_default_config_exit(file_path: &Utf8PathBuf) -> () {
+		AppConfig::print_default_config(file_path);
+		std::process::exit(1);
+	}
+
+	/// Log an example configuration, to help someone create their missing TOML file.
+	pub fn print_default_config(file_path: &Utf8PathBuf) {
 		error!("\nError: No configuration file was found at path: {}", file_path);
 		error!("You will need to create a configuration file manually.");
 		error!("Below is an example of the file's contents:\n");
 		let default_config = AppConfig {
+			config_version: Some(CONFIG_VERSION),
 			environment_name: Some("Development".to_string()),
 			full_refresh_internal_secs: 180,
 			time_zone_string: "UTC".to_string(),
 			tracing_level: LevelFilterWrapper::new(filter::LevelFilter::INFO),
 			startup_without_database_connections: false,
+			log_history_size: Some(500),
+			idempotency_ttl_secs: Some(600),
+			socket_rate_limit_per_min: Some(30),
+			queue_stats_retention_days: Some(90),
+			task_run_history_size: Some(crate::run_history::DEFAULT_TASK_RUN_HISTORY_SIZE),
+			task_run_history_ttl_days: Some(crate::run_history::DEFAULT_TASK_RUN_HISTORY_TTL_DAYS),
+			sql_retry_backoff_secs: Some(15),
+			slow_query_threshold_ms: Some(crate::metrics::DEFAULT_SLOW_QUERY_THRESHOLD_MS),
+			sentry_dsn: None,
 			email_address_from: None,
 			email_host_name: None,
 			email_host_port: None,
+			email_security: None,
 			email_account_name: None,
 			email_account_password: None,
 			email_addresses: None,
@@ -143,17 +412,331 @@ impl AppConfig {
 			mysql_database: "bar".to_string(),
 			rq_host: "127.0.0.1".to_string(),
 			rq_port: 11000,
+			rq_password: None,
+			rq_database: None,
+			compress_job_data: false,
+			include_queues: None,
+			exclude_queues: None,
+			allowed_function_prefixes: None,
+			holiday_dates: None,
+			holiday_source: None,
+			schedule_jitter_secs: 0,
+			cron_dom_dow_semantics: DomDowSemantics::And,
+			cron_strings_are_utc: false,
+			event_stream_enabled: false,
+			job_watchdog_enabled: false,
+			job_watchdog_grace_secs: Some(crate::watchdog::DEFAULT_WATCHDOG_GRACE_SECS),
+			kill_overrunning_jobs: false,
+			stale_job_max_age_hours: Some(48),
+			stale_job_cleanup_dry_run: Some(true),
+			max_consecutive_failures: Some(0),
+			circuit_breaker_cooldown_secs: None,
+			failed_run_retry_secs: Some(crate::scheduler::DEFAULT_FAILED_RUN_RETRY_SECS),
+			redis_memory_warn_percent: Some(crate::rq::DEFAULT_REDIS_MEMORY_WARN_PERCENT),
 			scheduler_polling_interval: 60,
-			socket_path: "/tmp/btu_scheduler.sock".to_string(),
+			socket_path: SOCKET_FILE_PATH.to_string(),
 			socket_file_group_owner: "frappe_group".to_string(),
+			create_socket_dir: true,
+			status_page_listen_addr: None,
+			status_page_allow_remote: false,
+			systemd_notify: false,
+			systemd_thread_stall_secs: Some(DEFAULT_THREAD_STALL_SECS),
             webserver_ip: "127.0.0.1".to_string(),
             webserver_port: 8000,
+			webserver_use_tls: None,
 			webserver_host_header: Some("mysubdomain.domain.com".to_string()),
-            webserver_token: "token: abcd1234".to_string()
+            webserver_token: "token: abcd1234".to_string(),
+			webserver_timeout_secs: Some(crate::frappe_client::DEFAULT_WEBSERVER_TIMEOUT_SECS),
+			current_site: None,
+			source: ConfigSource::Text,
+			sites: None,
 		};
 		let toml_string = toml::to_string(&default_config).unwrap();
 		warn!("{}", toml_string);
-		std::process::exit(1);
+	}
+
+	/// Site names must be usable in Redis keys, and unique.
+	pub fn validate_sites(&self) -> Result<(), ConfigError> {
+		let sites: &[SiteConfig] = self.sites.as_deref().unwrap_or_default();
+		for (index, site) in sites.iter().enumerate() {
+			let invalid = |reason: &'static str| ConfigError::InvalidSite { site: site.name.clone(), reason };
+			if site.name.is_empty() || !site.name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_')) {
+				return Err(invalid("'name' must be letters, digits, '.', '-', or '_'"));
+			}
+			if sites[..index].iter().any(|earlier| earlier.name == site.name) {
+				return Err(invalid("another [[site]] has the same 'name'"));
+			}
+		}
+		Ok(())
+	}
+
+	/// Where this configuration was read from.
+	pub fn source(&self) -> &ConfigSource {
+		&self.source
+	}
+
+	/// A copy with every secret (passwords, tokens, and the Sentry DSN) replaced by REDACTED, including those of each [[site]].
+	/// Both the Display impl and redacted_json() start here, so they cannot disagree about what is secret.
+	pub fn redacted(&self) -> AppConfig {
+		let redact = |secret: &mut String| *secret = REDACTED.to_owned();
+		let mut redacted: AppConfig = self.clone();
+		redact(&mut redacted.mysql_password);
+		redact(&mut redacted.webserver_token);
+		for secret in [&mut redacted.rq_password, &mut redacted.email_account_password, &mut redacted.sentry_dsn].into_iter().flatten() {
+			redact(secret);
+		}
+		for site in redacted.sites.iter_mut().flatten() {
+			redact(&mut site.mysql_password);
+			redact(&mut site.webserver_token);
+		}
+		redacted
+	}
+
+	/// The configuration as JSON, with secrets redacted; for showing the live configuration to support.
+	pub fn redacted_json(&self) -> serde_json::Value {
+		serde_json::to_value(self.redacted()).unwrap_or(serde_json::Value::Null)
+	}
+
+	/// Read this configuration again from where it came from: the same file, or the environment.
+	pub fn read_again(&self) -> Result<AppConfig, ConfigError> {
+		let mut app_config: AppConfig = match &self.source {
+			ConfigSource::File(path) => {
+				let file_contents: String = fs::read_to_string(path)
+					.map_err(|source| ConfigError::ReadFile { path: path.to_string(), source })?;
+				AppConfig::new_from_toml_string(&file_contents)?
+			},
+			ConfigSource::Environment(_) => AppConfig::new_from_env()?,
+			ConfigSource::Text => return Err(ConfigError::NotReloadable { config_source: "TOML text" }),
+		};
+		app_config.source = self.source.clone();
+		Ok(app_config)
+	}
+
+	/// Checks that deserializing cannot make: the time zone is known, ports are within 1 to 65535, and intervals are not zero.
+	pub fn validate_settings(&self) -> Result<(), ConfigError> {
+		let mut problems: Vec<String> = Vec::new();
+		if let Err(error) = self.tz() {
+			problems.push(format!("time_zone_string: '{}' is not a known time zone ({})", self.time_zone_string, error));
+		}
+		let mut check_port = |setting: String, port: Option<i64>| {
+			if let Some(port) = port.filter(|port| !(1..=65535).contains(port)) {
+				problems.push(format!("{}: {} is not a port number between 1 and 65535", setting, port));
+			}
+		};
+		check_port("rq_port".to_owned(), Some(i64::from(self.rq_port)));
+		check_port("mysql_port".to_owned(), self.mysql_port.map(i64::from));
+		check_port("webserver_port".to_owned(), Some(i64::from(self.webserver_port)));
+		check_port("email_host_port".to_owned(), self.email_host_port.map(i64::from));
+		for site in self.sites.as_deref().unwrap_or_default() {
+			check_port(format!("site '{}' mysql_port", site.name), site.mysql_port.map(i64::from));
+			check_port(format!("site '{}' webserver_port", site.name), site.webserver_port.map(i64::from));
+		}
+		if self.scheduler_polling_interval == 0 {
+			problems.push("scheduler_polling_interval: must be at least 1 second".to_owned());
+		}
+		if self.full_refresh_internal_secs == 0 {
+			problems.push("full_refresh_internal_secs: must be at least 1 second".to_owned());
+		}
+		if problems.is_empty() { Ok(()) } else { Err(ConfigError::InvalidSettings { problems }) }
+	}
+
+	/// Validate 'new_config', read again while the daemon runs with this configuration, and prepare it to replace this one.
+	/// RESTART_REQUIRED_SETTINGS keep their running values.  An invalid 'new_config' is an Err; this configuration stays in effect.
+	pub fn prepare_reload(&self, mut new_config: AppConfig) -> Result<ConfigReload, ConfigError> {
+		new_config.validate_settings()?;
+		let running = serde_json::to_value(self).unwrap_or_default();
+		let reread = serde_json::to_value(&new_config).unwrap_or_default();
+		let (Some(running), Some(reread)) = (running.as_object(), reread.as_object()) else {
+			return Ok(ConfigReload { app_config: new_config, changed: Vec::new(), restart_required: Vec::new() });
+		};
+		let mut settings: Vec<&String> = running.keys().chain(reread.keys()).collect();
+		settings.sort();
+		settings.dedup();
+		let (restart_required, changed): (Vec<String>, Vec<String>) = settings.into_iter()
+			.filter(|setting| running.get(*setting) != reread.get(*setting))
+			.cloned()
+			.partition(|setting| RESTART_REQUIRED_SETTINGS.contains(&setting.as_str()));
+
+		new_config.environment_name = self.environment_name.clone();
+		new_config.sentry_dsn = self.sentry_dsn.clone();
+		new_config.socket_path = self.socket_path.clone();
+		new_config.socket_file_group_owner = self.socket_file_group_owner.clone();
+		new_config.create_socket_dir = self.create_socket_dir;
+		new_config.status_page_listen_addr = self.status_page_listen_addr.clone();
+		new_config.status_page_allow_remote = self.status_page_allow_remote;
+		new_config.systemd_notify = self.systemd_notify;
+		Ok(ConfigReload { app_config: new_config, changed, restart_required })
+	}
+
+	/// The site this configuration connects to.  None for the top-level (default) site.
+	pub fn site_name(&self) -> Option<&str> {
+		self.current_site.as_deref()
+	}
+
+	/// A Task Schedule ID of this configuration's site, as it appears in the Internal Queue and Redis.
+	pub fn qualify_schedule_id(&self, task_schedule_id: &str) -> TaskScheduleId {
+		qualify_schedule_id(self.site_name(), task_schedule_id)
+	}
+
+	/// The configuration for one site: the top-level settings, with that site's database and web server.
+	/// None means the default site.  Returns None if no [[site]] has that name.
+	pub fn for_site(&self, site: Option<&str>) -> Option<AppConfig> {
+		let Some(site) = site else {
+			let mut default_config: AppConfig = self.clone();
+			default_config.current_site = None;
+			return Some(default_config);
+		};
+		let site: &SiteConfig = self.sites.as_deref().unwrap_or_default().iter().find(|candidate| candidate.name == site)?;
+		let mut site_config: AppConfig = self.clone();
+		site_config.current_site = Some(site.name.clone());
+		site_config.mysql_user = site.mysql_user.clone();
+		site_config.mysql_password = site.mysql_password.clone();
+		site_config.mysql_host = site.mysql_host.clone().unwrap_or_else(|| self.mysql_host.clone());
+		site_config.mysql_port = site.mysql_port.or(self.mysql_port);
+		site_config.mysql_database = site.mysql_database.clone();
+		site_config.webserver_ip = site.webserver_ip.clone().unwrap_or_else(|| self.webserver_ip.clone());
+		site_config.webserver_port = site.webserver_port.unwrap_or(self.webserver_port);
+		site_config.webserver_use_tls = site.webserver_use_tls.or(self.webserver_use_tls);
+		site_config.webserver_host_header = site.webserver_host_header.clone();
+		site_config.webserver_token = site.webserver_token.clone();
+		Some(site_config)
+	}
+
+	/// The configuration of every site: the default site first, then each [[site]] in order.
+	pub fn site_configs(&self) -> Vec<AppConfig> {
+		std::iter::once(None)
+			.chain(self.sites.as_deref().unwrap_or_default().iter().map(|site| Some(site.name.as_str())))
+			.filter_map(|site| self.for_site(site))
+			.collect()
+	}
+
+	/// SQL queries slower than this are logged as warnings.
+	pub fn slow_query_threshold(&self) -> std::time::Duration {
+		std::time::Duration::from_millis(self.slow_query_threshold_ms.unwrap_or(crate::metrics::DEFAULT_SLOW_QUERY_THRESHOLD_MS))
+	}
+
+	/// Returns true if a queue name passes the optional 'include_queues' and 'exclude_queues' filters.
+	pub fn is_queue_allowed(&self, queue_name: &str) -> bool {
+		if let Some(include_queues) = &self.include_queues {
+			if !include_queues.iter().any(|pattern| queue_pattern_matches(pattern, queue_name)) {
+				return false;
+			}
+		}
+		if let Some(exclude_queues) = &self.exclude_queues {
+			if exclude_queues.iter().any(|pattern| queue_pattern_matches(pattern, queue_name)) {
+				return false;
+			}
+		}
+		true
+	}
+
+	/// Returns true if a Python function path passes the optional 'allowed_function_prefixes' policy.  An absent or empty list allows everything.
+	pub fn is_function_allowed(&self, path_to_function: &str) -> bool {
+		match &self.allowed_function_prefixes {
+			Some(prefixes) if !prefixes.is_empty() => prefixes.iter().any(|prefix| path_to_function.starts_with(prefix.as_str())),
+			_ => true
+		}
+	}
+
+	/// Translates the queue filters into an SQL fragment (beginning with " AND") plus its positional parameters.
+	/// The fragment expects the queue column to be named 'queue_name'.
+	pub fn queue_filter_sql(&self) -> (String, Vec<String>) {
+		let mut sql_fragment = String::new();
+		let mut parameters: Vec<String> = Vec::new();
+
+		if let Some(include_queues) = self.include_queues.as_ref().filter(|patterns| !patterns.is_empty()) {
+			let clauses: Vec<&str> = include_queues.iter().map(|_| "queue_name LIKE ?").collect();
+			sql_fragment.push_str(&format!(" AND ({})", clauses.join(" OR ")));
+			parameters.extend(include_queues.iter().map(|pattern| queue_pattern_to_like(pattern)));
+		}
+		else if self.include_queues.is_some() {
+			// An empty include list matches nothing.
+			sql_fragment.push_str(" AND 1 = 0");
+		}
+		if let Some(exclude_queues) = self.exclude_queues.as_ref().filter(|patterns| !patterns.is_empty()) {
+			let clauses: Vec<&str> = exclude_queues.iter().map(|_| "queue_name LIKE ?").collect();
+			sql_fragment.push_str(&format!(" AND NOT ({})", clauses.join(" OR ")));
+			parameters.extend(exclude_queues.iter().map(|pattern| queue_pattern_to_like(pattern)));
+		}
+		(sql_fragment, parameters)
+	}
+
+	/// Email settings are all-or-nothing.  Either none are configured (email is disabled), or the sender, host, and recipients are all present.
+	/// An account name and password are required together, and may only be omitted when 'email_security' is "none" (a local relay).
+	pub fn validate_email_settings(&self) -> Result<(), EmailConfigError> {
+		let account_given: bool = self.email_account_name.is_some() || self.email_account_password.is_some();
+		let anything_given: bool = self.email_address_from.is_some() || self.email_host_name.is_some() || account_given
+			|| self.email_addresses.as_ref().map_or(false, |addresses| !addresses.is_empty());
+		if !anything_given {
+			return Ok(());
+		}
+
+		let security: EmailSecurity = self.email_security()?;
+		let mut missing: Vec<&'static str> = Vec::new();
+		if self.email_address_from.is_none() {
+			missing.push("email_address_from");
+		}
+		if self.email_host_name.is_none() {
+			missing.push("email_host_name");
+		}
+		if self.email_addresses.as_ref().map_or(true, |addresses| addresses.is_empty()) {
+			missing.push("email_addresses");
+		}
+		if account_given || security != EmailSecurity::None {
+			if self.email_account_name.is_none() {
+				missing.push("email_account_name");
+			}
+			if self.email_account_password.is_none() {
+				missing.push("email_account_password");
+			}
+		}
+		if !missing.is_empty() {
+			return Err(EmailConfigError::MissingSettings { missing });
+		}
+		Ok(())
+	}
+
+	/// The SMTP security mode: from 'email_security' if configured, otherwise chosen by 'email_host_port'.
+	pub fn email_security(&self) -> Result<EmailSecurity, EmailConfigError> {
+		match &self.email_security {
+			Some(value) => value.parse(),
+			None => {
+				let port: Option<u16> = self.email_host_port.and_then(|port| u16::try_from(port).ok());
+				Ok(EmailSecurity::default_for_port(port))
+			}
+		}
+	}
+
+	/// The valid dates in 'holiday_dates'.  Invalid entries are logged and ignored.
+	pub fn configured_holiday_dates(&self) -> HashSet<NaiveDate> {
+		let mut result: HashSet<NaiveDate> = HashSet::new();
+		for date_string in self.holiday_dates.iter().flatten() {
+			match NaiveDate::parse_from_str(date_string.trim(), "%Y-%m-%d") {
+				Ok(date) => {
+					result.insert(date);
+				},
+				Err(_) => {
+					warn!("Ignoring holiday date '{}'; expected the format YYYY-MM-DD.", date_string);
+				}
+			}
+		}
+		result
+	}
+
+	/// The full URL of a path on the Frappe web server, such as "/api/method/btu.btu_api.endpoints.test_ping".
+	/// Every call to the web server builds its URL here; see frappe_client::base_url() for the scheme and port rules.
+	pub fn frappe_url(&self, path: &str) -> String {
+		let (base_url, _) = crate::frappe_client::base_url(&self.webserver_ip, self.webserver_port, self.webserver_use_tls);
+		format!("{}{}", base_url, path)
+	}
+
+	/// The time zone for showing times to people.  When 'time_zone_string' is not a known time zone, logs a warning and
+	/// returns UTC, so a typo does not stop listings and logs that work perfectly well in UTC.
+	pub fn tz_or_utc(&self) -> chrono_tz::Tz {
+		self.tz().unwrap_or_else(|error| {
+			warn!("Time zone '{}' is not valid ({}); showing times in UTC instead.", self.time_zone_string, error);
+			chrono_tz::UTC
+		})
 	}
 
 	pub fn tz(&self) -> Result<chrono_tz::Tz, chrono_tz::ParseError> {
@@ -170,9 +753,550 @@ impl AppConfig {
 
 }
 
+fn default_create_socket_dir() -> bool {
+	true
+}
+
+fn default_tracing_level() -> LevelFilterWrapper {
+	LevelFilterWrapper::new(filter::LevelFilter::INFO)
+}
+
+/// A permissive view of a configuration file from any generation of AppConfig: every setting is optional.
+/// Used by 'btu config-upgrade' to read old files that no longer deserialize as AppConfig.
+#[derive(Deserialize, Default)]
+pub struct PartialAppConfig {
+	pub config_version: Option<u32>,
+	pub environment_name: Option<String>,
+	pub full_refresh_internal_secs: Option<u32>,
+	pub time_zone_string: Option<String>,
+	pub tracing_level: Option<LevelFilterWrapper>,
+	pub startup_without_database_connections: Option<bool>,
+	pub log_history_size: Option<usize>,
+	pub idempotency_ttl_secs: Option<u64>,
+	pub socket_rate_limit_per_min: Option<u32>,
+	pub queue_stats_retention_days: Option<u32>,
+	pub task_run_history_size: Option<usize>,
+	pub task_run_history_ttl_days: Option<u32>,
+	pub sql_retry_backoff_secs: Option<u32>,
+	pub slow_query_threshold_ms: Option<u64>,
+	pub sentry_dsn: Option<String>,
+	pub email_address_from: Option<String>,
+	pub email_host_name: Option<String>,
+	pub email_host_port: Option<i16>,
+	pub email_security: Option<String>,
+	pub email_account_name: Option<String>,
+	pub email_account_password: Option<String>,
+	pub email_addresses: Option<Vec<String>>,
+	pub email_on_level: Option<LevelWrapper>,
+	pub email_when_queuing: Option<bool>,
+	pub mysql_user: Option<String>,
+	pub mysql_password: Option<String>,
+	pub mysql_host: Option<String>,
+	pub mysql_port: Option<u32>,
+	pub mysql_database: Option<String>,
+	pub rq_host: Option<String>,
+	pub rq_port: Option<u32>,
+	pub rq_password: Option<String>,
+	pub rq_database: Option<u32>,
+	pub compress_job_data: Option<bool>,
+	pub include_queues: Option<Vec<String>>,
+	pub exclude_queues: Option<Vec<String>>,
+	pub allowed_function_prefixes: Option<Vec<String>>,
+	pub holiday_dates: Option<Vec<String>>,
+	pub holiday_source: Option<String>,
+	pub schedule_jitter_secs: Option<u32>,
+	pub cron_dom_dow_semantics: Option<DomDowSemantics>,
+	pub cron_strings_are_utc: Option<bool>,
+	pub event_stream_enabled: Option<bool>,
+	pub job_watchdog_enabled: Option<bool>,
+	pub job_watchdog_grace_secs: Option<u32>,
+	pub kill_overrunning_jobs: Option<bool>,
+	pub stale_job_max_age_hours: Option<u32>,
+	pub stale_job_cleanup_dry_run: Option<bool>,
+	pub max_consecutive_failures: Option<u32>,
+	pub circuit_breaker_cooldown_secs: Option<u64>,
+	pub failed_run_retry_secs: Option<u64>,
+	pub redis_memory_warn_percent: Option<u8>,
+	pub scheduler_polling_interval: Option<u64>,
+	pub socket_path: Option<String>,
+	pub socket_file_group_owner: Option<String>,
+	pub create_socket_dir: Option<bool>,
+	pub status_page_listen_addr: Option<String>,
+	pub status_page_allow_remote: Option<bool>,
+	pub systemd_notify: Option<bool>,
+	pub systemd_thread_stall_secs: Option<u64>,
+	pub webserver_ip: Option<String>,
+	pub webserver_port: Option<u16>,
+	pub webserver_use_tls: Option<bool>,
+	pub webserver_host_header: Option<String>,
+	pub webserver_token: Option<String>,
+	pub webserver_timeout_secs: Option<u64>,
+	#[serde(rename = "site")]
+	pub sites: Option<Vec<SiteConfig>>,
+}
+
+impl PartialAppConfig {
+
+	/// The settings that have no sensible default, and are absent here.
+	pub fn missing_required_settings(&self) -> Vec<&'static str> {
+		let required: [(&'static str, bool); 14] = [
+			("full_refresh_internal_secs", self.full_refresh_internal_secs.is_some()),
+			("time_zone_string", self.time_zone_string.is_some()),
+			("mysql_user", self.mysql_user.is_some()),
+			("mysql_password", self.mysql_password.is_some()),
+			("mysql_host", self.mysql_host.is_some()),
+			("mysql_database", self.mysql_database.is_some()),
+			("rq_host", self.rq_host.is_some()),
+			("rq_port", self.rq_port.is_some()),
+			("scheduler_polling_interval", self.scheduler_polling_interval.is_some()),
+			("socket_path", self.socket_path.is_some()),
+			("socket_file_group_owner", self.socket_file_group_owner.is_some()),
+			("webserver_ip", self.webserver_ip.is_some()),
+			("webserver_port", self.webserver_port.is_some()),
+			("webserver_token", self.webserver_token.is_some()),
+		];
+		required.iter().filter(|(_, present)| !present).map(|(name, _)| *name).collect()
+	}
+
+	/// Fill defaults for the optional settings, and stamp the current CONFIG_VERSION.
+	pub fn into_app_config(self) -> Result<AppConfig, ConfigError> {
+		let missing: Vec<&'static str> = self.missing_required_settings();
+		if !missing.is_empty() {
+			return Err(ConfigError::MissingSettings { missing });
+		}
+		// Every required setting is present, so unwrap_or_default() below never supplies a value.
+		let app_config = AppConfig {
+			config_version: Some(CONFIG_VERSION),
+			environment_name: self.environment_name,
+			full_refresh_internal_secs: self.full_refresh_internal_secs.unwrap_or_default(),
+			time_zone_string: self.time_zone_string.unwrap_or_default(),
+			tracing_level: self.tracing_level.unwrap_or_else(default_tracing_level),
+			startup_without_database_connections: self.startup_without_database_connections.unwrap_or(false),
+			log_history_size: self.log_history_size,
+			idempotency_ttl_secs: self.idempotency_ttl_secs,
+			socket_rate_limit_per_min: self.socket_rate_limit_per_min,
+			queue_stats_retention_days: self.queue_stats_retention_days,
+			task_run_history_size: self.task_run_history_size,
+			task_run_history_ttl_days: self.task_run_history_ttl_days,
+			sql_retry_backoff_secs: self.sql_retry_backoff_secs,
+			slow_query_threshold_ms: self.slow_query_threshold_ms,
+			sentry_dsn: self.sentry_dsn,
+			email_address_from: self.email_address_from,
+			email_host_name: self.email_host_name,
+			email_host_port: self.email_host_port,
+			email_security: self.email_security,
+			email_account_name: self.email_account_name,
+			email_account_password: self.email_account_password,
+			email_addresses: self.email_addresses,
+			email_on_level: self.email_on_level,
+			email_when_queuing: self.email_when_queuing.unwrap_or(false),
+			mysql_user: self.mysql_user.unwrap_or_default(),
+			mysql_password: self.mysql_password.unwrap_or_default(),
+			mysql_host: self.mysql_host.unwrap_or_default(),
+			mysql_port: self.mysql_port,
+			mysql_database: self.mysql_database.unwrap_or_default(),
+			rq_host: self.rq_host.unwrap_or_default(),
+			rq_port: self.rq_port.unwrap_or_default(),
+			rq_password: self.rq_password,
+			rq_database: self.rq_database,
+			compress_job_data: self.compress_job_data.unwrap_or(false),
+			include_queues: self.include_queues,
+			exclude_queues: self.exclude_queues,
+			allowed_function_prefixes: self.allowed_function_prefixes,
+			holiday_dates: self.holiday_dates,
+			holiday_source: self.holiday_source,
+			schedule_jitter_secs: self.schedule_jitter_secs.unwrap_or(0),
+			cron_dom_dow_semantics: self.cron_dom_dow_semantics.unwrap_or_default(),
+			cron_strings_are_utc: self.cron_strings_are_utc.unwrap_or(false),
+			event_stream_enabled: self.event_stream_enabled.unwrap_or(false),
+			job_watchdog_enabled: self.job_watchdog_enabled.unwrap_or(false),
+			job_watchdog_grace_secs: self.job_watchdog_grace_secs,
+			kill_overrunning_jobs: self.kill_overrunning_jobs.unwrap_or(false),
+			stale_job_max_age_hours: self.stale_job_max_age_hours,
+			stale_job_cleanup_dry_run: self.stale_job_cleanup_dry_run,
+			max_consecutive_failures: self.max_consecutive_failures,
+			circuit_breaker_cooldown_secs: self.circuit_breaker_cooldown_secs,
+			failed_run_retry_secs: self.failed_run_retry_secs,
+			redis_memory_warn_percent: self.redis_memory_warn_percent,
+			scheduler_polling_interval: self.scheduler_polling_interval.unwrap_or_default(),
+			socket_path: self.socket_path.unwrap_or_default(),
+			socket_file_group_owner: self.socket_file_group_owner.unwrap_or_default(),
+			create_socket_dir: self.create_socket_dir.unwrap_or(true),
+			status_page_listen_addr: self.status_page_listen_addr,
+			status_page_allow_remote: self.status_page_allow_remote.unwrap_or(false),
+			systemd_notify: self.systemd_notify.unwrap_or(false),
+			systemd_thread_stall_secs: self.systemd_thread_stall_secs,
+			webserver_ip: self.webserver_ip.unwrap_or_default(),
+			webserver_port: self.webserver_port.unwrap_or_default(),
+			webserver_use_tls: self.webserver_use_tls,
+			webserver_host_header: self.webserver_host_header,
+			webserver_token: self.webserver_token.unwrap_or_default(),
+			webserver_timeout_secs: self.webserver_timeout_secs,
+			current_site: None,
+			source: ConfigSource::Text,
+			sites: self.sites,
+		};
+		app_config.validate_sites()?;
+		Ok(app_config)
+	}
+}
+
+/*
+	Configuration from environment variables.
+
+	For containers and CI pipelines without a TOML file.  Each setting is read from its name, uppercased, with the prefix
+	'BTU_' (for example, BTU_MYSQL_HOST).  Lists are comma-separated.  [[site]] entries cannot be set this way.
+*/
+
+/// The prefix of environment variables read by AppConfig::new_from_env().
+pub const ENV_PREFIX: &str = "BTU_";
+
+/// How the text of an environment variable becomes a TOML value.
+#[derive(Clone, Copy, Debug)]
+enum EnvValueKind {
+	Text,
+	Integer,
+	Boolean,
+	List
+}
+
+static ENV_SETTINGS: [(&str, EnvValueKind); 66] = [
+	("config_version", EnvValueKind::Integer),
+	("environment_name", EnvValueKind::Text),
+	("full_refresh_internal_secs", EnvValueKind::Integer),
+	("time_zone_string", EnvValueKind::Text),
+	("tracing_level", EnvValueKind::Text),
+	("startup_without_database_connections", EnvValueKind::Boolean),
+	("log_history_size", EnvValueKind::Integer),
+	("idempotency_ttl_secs", EnvValueKind::Integer),
+	("socket_rate_limit_per_min", EnvValueKind::Integer),
+	("queue_stats_retention_days", EnvValueKind::Integer),
+	("task_run_history_size", EnvValueKind::Integer),
+	("task_run_history_ttl_days", EnvValueKind::Integer),
+	("sql_retry_backoff_secs", EnvValueKind::Integer),
+	("slow_query_threshold_ms", EnvValueKind::Integer),
+	("sentry_dsn", EnvValueKind::Text),
+	("email_address_from", EnvValueKind::Text),
+	("email_host_name", EnvValueKind::Text),
+	("email_host_port", EnvValueKind::Integer),
+	("email_security", EnvValueKind::Text),
+	("email_account_name", EnvValueKind::Text),
+	("email_account_password", EnvValueKind::Text),
+	("email_addresses", EnvValueKind::List),
+	("email_on_level", EnvValueKind::Text),
+	("email_when_queuing", EnvValueKind::Boolean),
+	("mysql_user", EnvValueKind::Text),
+	("mysql_password", EnvValueKind::Text),
+	("mysql_host", EnvValueKind::Text),
+	("mysql_port", EnvValueKind::Integer),
+	("mysql_database", EnvValueKind::Text),
+	("rq_host", EnvValueKind::Text),
+	("rq_port", EnvValueKind::Integer),
+	("rq_password", EnvValueKind::Text),
+	("rq_database", EnvValueKind::Integer),
+	("compress_job_data", EnvValueKind::Boolean),
+	("include_queues", EnvValueKind::List),
+	("exclude_queues", EnvValueKind::List),
+	("allowed_function_prefixes", EnvValueKind::List),
+	("holiday_dates", EnvValueKind::List),
+	("holiday_source", EnvValueKind::Text),
+	("schedule_jitter_secs", EnvValueKind::Integer),
+	("cron_dom_dow_semantics", EnvValueKind::Text),
+	("cron_strings_are_utc", EnvValueKind::Boolean),
+	("event_stream_enabled", EnvValueKind::Boolean),
+	("job_watchdog_enabled", EnvValueKind::Boolean),
+	("job_watchdog_grace_secs", EnvValueKind::Integer),
+	("kill_overrunning_jobs", EnvValueKind::Boolean),
+	("stale_job_max_age_hours", EnvValueKind::Integer),
+	("stale_job_cleanup_dry_run", EnvValueKind::Boolean),
+	("max_consecutive_failures", EnvValueKind::Integer),
+	("circuit_breaker_cooldown_secs", EnvValueKind::Integer),
+	("failed_run_retry_secs", EnvValueKind::Integer),
+	("redis_memory_warn_percent", EnvValueKind::Integer),
+	("scheduler_polling_interval", EnvValueKind::Integer),
+	("socket_path", EnvValueKind::Text),
+	("socket_file_group_owner", EnvValueKind::Text),
+	("create_socket_dir", EnvValueKind::Boolean),
+	("status_page_listen_addr", EnvValueKind::Text),
+	("status_page_allow_remote", EnvValueKind::Boolean),
+	("systemd_notify", EnvValueKind::Boolean),
+	("systemd_thread_stall_secs", EnvValueKind::Integer),
+	("webserver_ip", EnvValueKind::Text),
+	("webserver_port", EnvValueKind::Integer),
+	("webserver_use_tls", EnvValueKind::Boolean),
+	("webserver_host_header", EnvValueKind::Text),
+	("webserver_token", EnvValueKind::Text),
+	("webserver_timeout_secs", EnvValueKind::Integer),
+];
+
+/// The environment variable for a setting, such as "BTU_MYSQL_HOST" for "mysql_host".
+pub fn env_variable_name(setting: &str) -> String {
+	format!("{}{}", ENV_PREFIX, setting.to_uppercase())
+}
+
+fn env_value_to_toml(kind: EnvValueKind, text: &str) -> Result<toml::Value, String> {
+	match kind {
+		EnvValueKind::Text => Ok(toml::Value::String(text.to_owned())),
+		EnvValueKind::Integer => text.trim().parse::<i64>()
+			.map(toml::Value::Integer)
+			.map_err(|_| format!("expected a whole number, found '{}'", text)),
+		EnvValueKind::Boolean => match text.trim().to_lowercase().as_str() {
+			"true" | "1" | "yes" | "on" => Ok(toml::Value::Boolean(true)),
+			"false" | "0" | "no" | "off" => Ok(toml::Value::Boolean(false)),
+			_ => Err(format!("expected true or false, found '{}'", text))
+		},
+		EnvValueKind::List => Ok(toml::Value::Array(
+			text.split(',').map(str::trim).filter(|item| !item.is_empty()).map(|item| toml::Value::String(item.to_owned())).collect()
+		))
+	}
+}
+
+/// Parse the contents of a dotenv file: one KEY=VALUE per line.  Blank lines and '#' comments are ignored, as is a leading
+/// 'export'.  Values may be wrapped in single or double quotes; unquoted values end at a ' #' comment.
+pub fn parse_dotenv(contents: &str) -> Vec<(String, String)> {
+	contents.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.filter_map(|line| {
+			let line: &str = line.strip_prefix("export ").map_or(line, str::trim_start);
+			let (key, value) = line.split_once('=')?;
+			let key: &str = key.trim();
+			if key.is_empty() || key.contains(char::is_whitespace) {
+				return None
+			}
+			let value: &str = value.trim();
+			let quoted: Option<&str> = ['"', '\''].iter()
+				.find_map(|quote| value.strip_prefix(*quote).and_then(|rest| rest.strip_suffix(*quote)));
+			let value: &str = match quoted {
+				Some(inner) => inner,
+				None => value.split_once(" #").map_or(value, |(before, _)| before.trim_end())
+			};
+			Some((key.to_owned(), value.to_owned()))
+		})
+		.collect()
+}
+
+/// Set the variables from a dotenv file, unless they are already set.  A missing file is not an error.  Returns how many were set.
+pub fn load_dotenv_file(path: &Path) -> Result<usize, ConfigError> {
+	if !path.exists() {
+		return Ok(0)
+	}
+	let contents: String = fs::read_to_string(path)
+		.map_err(|source| ConfigError::DotEnvFile { path: path.display().to_string(), source })?;
+	let mut variables_set: usize = 0;
+	for (key, value) in parse_dotenv(&contents) {
+		if std::env::var_os(&key).is_none() {
+			std::env::set_var(&key, value);
+			variables_set += 1;
+		}
+	}
+	Ok(variables_set)
+}
+
+/*
+	User mode.
+
+	A developer running BTU as themselves cannot write to /etc/btu_scheduler, and on a shared machine, one socket in /tmp
+	collides with everyone else's.  In user mode, the configuration file and socket follow the XDG Base Directory conventions.
+*/
+
+/// Where the configuration file and socket are, and why.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedPaths {
+	pub config_file: Utf8PathBuf,
+	pub socket_path: Option<String>,  // user mode only; replaces the shared SOCKET_FILE_PATH
+	pub user_mode: bool,
+	pub reason: &'static str,
+}
+
+impl ResolvedPaths {
+
+	/// In user mode, move a configuration that uses the shared default socket to the per-user socket.  Returns true if it changed.
+	pub fn apply_socket_path(&self, app_config: &mut AppConfig) -> bool {
+		match &self.socket_path {
+			Some(socket_path) if app_config.socket_path == SOCKET_FILE_PATH => {
+				app_config.socket_path = socket_path.clone();
+				true
+			},
+			_ => false
+		}
+	}
+}
+
+impl fmt::Display for ResolvedPaths {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let mode: &str = if self.user_mode { "user mode" } else { "system mode" };
+		write!(f, "{} ({}): configuration file '{}'", mode, self.reason, self.config_file)?;
+		if let Some(socket_path) = &self.socket_path {
+			write!(f, ", socket '{}'", socket_path)?;
+		}
+		Ok(())
+	}
+}
+
+/// Choose the configuration file and socket for the CLI or daemon.  An explicit '--config' path always wins.
+/// User mode applies with '--user', or automatically when the system configuration file cannot be read and $HOME is set:
+///   * configuration: $XDG_CONFIG_HOME/btu_scheduler/btu_scheduler.toml ($XDG_CONFIG_HOME defaults to $HOME/.config)
+///   * socket: $XDG_RUNTIME_DIR/btu_scheduler.sock (without $XDG_RUNTIME_DIR, a per-user file in the temporary directory)
+pub fn resolve_paths(cli_config: Option<&str>, user_mode: bool) -> ResolvedPaths {
+	let system_config_readable: bool = fs::File::open(CONFIG_FILE_PATH).is_ok();
+	resolve_paths_from(cli_config, user_mode, system_config_readable, |name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+/// resolve_paths(), with the environment and the system file's readability supplied by the caller.
+pub fn resolve_paths_from<F: Fn(&str) -> Option<String>>(cli_config: Option<&str>, user_mode: bool,
+                                                          system_config_readable: bool, env_var: F) -> ResolvedPaths {
+	let home: Option<String> = env_var("HOME");
+	let (user_mode, reason): (bool, &'static str) = match (user_mode, cli_config) {
+		(true, _) => (true, "--user"),
+		(false, Some(_)) => (false, "--config"),
+		(false, None) if !system_config_readable && home.is_some() => (true, "the system configuration file is not readable"),
+		(false, None) => (false, "default")
+	};
+	if !user_mode {
+		let config_file: Utf8PathBuf = cli_config.unwrap_or(CONFIG_FILE_PATH).into();
+		return ResolvedPaths { config_file, socket_path: None, user_mode, reason }
+	}
+
+	let config_home: Option<Utf8PathBuf> = env_var("XDG_CONFIG_HOME").map(Utf8PathBuf::from)
+		.or_else(|| home.as_ref().map(|home| Utf8PathBuf::from(home).join(".config")));
+	let config_file: Utf8PathBuf = match (cli_config, config_home) {
+		(Some(cli_config), _) => cli_config.into(),
+		(None, Some(config_home)) => config_home.join("btu_scheduler").join("btu_scheduler.toml"),
+		(None, None) => CONFIG_FILE_PATH.into()  // '--user' without $HOME or $XDG_CONFIG_HOME
+	};
+	let socket_path: String = match env_var("XDG_RUNTIME_DIR") {
+		Some(runtime_dir) => Utf8PathBuf::from(runtime_dir).join("btu_scheduler.sock").to_string(),
+		None => {
+			let user: String = env_var("USER").unwrap_or_else(|| "user".to_owned());
+			std::env::temp_dir().join(format!("btu_scheduler-{}.sock", user)).display().to_string()
+		}
+	};
+	ResolvedPaths { config_file, socket_path: Some(socket_path), user_mode, reason }
+}
+
+/// The result of upgrading a configuration file to CONFIG_VERSION.
+pub struct ConfigUpgrade {
+	pub from_version: Option<u32>,
+	pub app_config: AppConfig,
+	pub added: Vec<(String, String)>,  // settings filled with a default: (name, value as TOML)
+	pub unrecognized: Vec<String>,  // settings in the old file that AppConfig does not know; they are not written
+}
+
+impl ConfigUpgrade {
+	pub fn to_toml_string(&self) -> Result<String, toml::ser::Error> {
+		toml::to_string(&self.app_config)
+	}
+}
+
+impl fmt::Display for ConfigUpgrade {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let from_version: String = self.from_version.map(|version| version.to_string()).unwrap_or("(unversioned)".to_owned());
+		writeln!(f, "Upgraded configuration from version {} to {}.", from_version, CONFIG_VERSION)?;
+		for (name, value) in &self.added {
+			writeln!(f, "  + {} = {}", name, value)?;
+		}
+		for name in &self.unrecognized {
+			writeln!(f, "  - {} (not a BTU setting; dropped)", name)?;
+		}
+		Ok(())
+	}
+}
+
+/// Read a configuration file of any generation, and produce a current one.  Reports which settings were added or dropped.
+pub fn upgrade_config_toml(old_toml: &str) -> Result<ConfigUpgrade, ConfigError> {
+	let old_table: toml::value::Table = toml::from_str(old_toml)?;
+	let partial: PartialAppConfig = toml::from_str(old_toml)?;
+	let from_version: Option<u32> = partial.config_version;
+	let app_config: AppConfig = partial.into_app_config()?;
+
+	// Compare the keys before and after, rather than tracking each default by hand.
+	let new_table: toml::value::Table = toml::Value::try_from(&app_config)
+		.ok()
+		.and_then(|value| value.as_table().cloned())
+		.unwrap_or_default();
+	let added: Vec<(String, String)> = new_table.iter()
+		.filter(|(name, _)| !old_table.contains_key(*name))
+		.map(|(name, value)| (name.to_owned(), value.to_string()))
+		.collect();
+	let unrecognized: Vec<String> = old_table.keys()
+		.filter(|name| !new_table.contains_key(*name))
+		.cloned()
+		.collect();
+
+	Ok(ConfigUpgrade { from_version, app_config, added, unrecognized })
+}
+
+/// How to secure the connection to the SMTP server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailSecurity {
+	Tls,       // implicit TLS, usually port 465
+	StartTls,  // plaintext upgraded with STARTTLS, usually port 587
+	None,      // no encryption; only sensible for a relay on localhost
+}
+
+impl EmailSecurity {
+	pub fn default_for_port(port: Option<u16>) -> Self {
+		match port {
+			Some(587) => EmailSecurity::StartTls,
+			Some(25) => EmailSecurity::None,
+			_ => EmailSecurity::Tls,
+		}
+	}
+
+	pub fn default_port(&self) -> u16 {
+		match self {
+			EmailSecurity::Tls => 465,
+			EmailSecurity::StartTls => 587,
+			EmailSecurity::None => 25,
+		}
+	}
+}
+
+impl std::str::FromStr for EmailSecurity {
+	type Err = EmailConfigError;
+
+	fn from_str(any_string: &str) -> Result<Self, Self::Err> {
+		match any_string.trim().to_lowercase().as_str() {
+			"tls" => Ok(EmailSecurity::Tls),
+			"starttls" => Ok(EmailSecurity::StartTls),
+			"none" => Ok(EmailSecurity::None),
+			_ => Err(EmailConfigError::InvalidSecurity { found: any_string.to_owned() })
+		}
+	}
+}
+
+/// Does a queue name match a filter pattern?  Patterns are exact names, or a prefix followed by '*' (example: "stg_*")
+pub fn queue_pattern_matches(pattern: &str, queue_name: &str) -> bool {
+	match pattern.strip_suffix('*') {
+		Some(prefix) => queue_name.starts_with(prefix),
+		None => queue_name == pattern
+	}
+}
+
+/// Convert a queue filter pattern into an SQL LIKE pattern, escaping the characters LIKE treats specially.
+pub fn queue_pattern_to_like(pattern: &str) -> String {
+	let (literal, is_prefix) = match pattern.strip_suffix('*') {
+		Some(prefix) => (prefix, true),
+		None => (pattern, false)
+	};
+	let mut result: String = escape_like(literal);
+	if is_prefix {
+		result.push('%');
+	}
+	result
+}
+
+/// Escape the characters SQL LIKE treats specially, so 'text' only matches itself.
+pub fn escape_like(text: &str) -> String {
+	text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
 impl fmt::Display for AppConfig {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let redacted: AppConfig = self.redacted();
 		write!(f, "BTU Application Configuration ({}):\n
+* Config Version: {}
 * MySQL Username: {}
 * MySQL Password: {}
 * MySQL Host: {}.{:?}
@@ -180,36 +1304,99 @@ impl fmt::Display for AppConfig {
 * Path to Socket File: {}
 * RQ Host: {}
 * RQ Port: {}
+* RQ Database: {}
+* Compress Job Data: {}
+* Include Queues: {}
+* Exclude Queues: {}
+* Allowed Function Prefixes: {}
+* Holiday Dates: {}
+* Holiday Source: {}
+* Schedule Jitter (seconds): {}
+* Cron Day-of-Month/Day-of-Week Semantics: {}
+* Cron Strings Are UTC: {}
+* Event Stream Enabled: {}
+* Job Watchdog: {}
+* Circuit Breaker: {}
+* Redis Memory Warning: {}
+* Sentry Error Reporting: {}
 * Unix Domain Socket Path: {}
 * Socket File Group Owner: {}
+* Status Page: {}
+* Systemd Notify: {}
 * Scheduler Polling Interval: {}
 * Seconds Between Refresh: {}
 * Web Server IP: {},
 * Web Server Port: {},
+* Web Server TLS: {},
 * Web Server Host Header: {:?},
 * Web Server Token: {},
+* Web Server Timeout (seconds): {},
+* Additional Sites: {}
 ",
-			CONFIG_FILE_PATH,
+			self.source,
+			self.config_version.map(|version| version.to_string()).unwrap_or("(unversioned)".to_owned()),
 			self.mysql_user,
-			"********",
+			redacted.mysql_password,
 			self.mysql_host,
 			self.mysql_port.unwrap_or(3306),
 			self.mysql_database,
 			self.socket_path,
 			self.rq_host,
 			self.rq_port,
+			self.rq_database.unwrap_or(0),
+			self.compress_job_data,
+			self.include_queues.as_ref().map(|queues| queues.join(", ")).unwrap_or("(all)".to_owned()),
+			self.exclude_queues.as_ref().map(|queues| queues.join(", ")).unwrap_or("(none)".to_owned()),
+			self.allowed_function_prefixes.as_ref().map(|prefixes| prefixes.join(", ")).unwrap_or("(all)".to_owned()),
+			self.holiday_dates.as_ref().map(|dates| dates.join(", ")).unwrap_or("(none)".to_owned()),
+			self.holiday_source.as_deref().unwrap_or("(none)"),
+			self.schedule_jitter_secs,
+			self.cron_dom_dow_semantics,
+			self.cron_strings_are_utc,
+			self.event_stream_enabled,
+			if !self.job_watchdog_enabled { "(disabled)".to_owned() } else {
+				format!("grace {} seconds{}", self.job_watchdog_grace_secs.unwrap_or(crate::watchdog::DEFAULT_WATCHDOG_GRACE_SECS),
+				        if self.kill_overrunning_jobs { "; overrunning jobs are moved to the failed state" } else { "" })
+			},
+			match self.max_consecutive_failures.unwrap_or_default() {
+				0 => "(disabled)".to_owned(),
+				max => format!("after {} consecutive failures{}", max,
+				               self.circuit_breaker_cooldown_secs.map(|secs| format!("; cool-down {} seconds", secs)).unwrap_or_default()),
+			},
+			match self.redis_memory_warn_percent.unwrap_or(crate::rq::DEFAULT_REDIS_MEMORY_WARN_PERCENT) {
+				0 => "(disabled)".to_owned(),
+				percent => format!("at {}% of 'maxmemory'", percent),
+			},
+			if self.sentry_dsn.is_some() { "(configured)" } else { "(none)" },  // the DSN contains the project's key
 			self.socket_path,
 			self.socket_file_group_owner,
+			match &self.status_page_listen_addr {
+				Some(address) => {
+					let url: String = format!("http://{}/", address);
+					if self.status_page_allow_remote { format!("{}; remote access allowed", url) } else { url }
+				},
+				None => "(disabled)".to_owned(),
+			},
+			if !self.systemd_notify { "(disabled)".to_owned() } else {
+				format!("watchdog stops when a thread has not checked in for {} seconds",
+				        self.systemd_thread_stall_secs.unwrap_or(DEFAULT_THREAD_STALL_SECS))
+			},
 			self.scheduler_polling_interval,
 			self.full_refresh_internal_secs,
 			self.webserver_ip,
 			self.webserver_port,
+			self.webserver_use_tls.map_or("(by port)".to_owned(), |use_tls| use_tls.to_string()),
 			self.webserver_host_header,
-			self.webserver_token
+			redacted.webserver_token,
+			self.webserver_timeout_secs.unwrap_or(crate::frappe_client::DEFAULT_WEBSERVER_TIMEOUT_SECS),
+			self.sites.as_ref().filter(|sites| !sites.is_empty())
+				.map(|sites| sites.iter().map(|site| site.name.as_str()).collect::<Vec<&str>>().join(", "))
+				.unwrap_or("(none)".to_owned())
 		)
 	}
 }
 
+#[cfg(feature = "sql")]
 pub fn get_mysql_conn(config: &AppConfig) -> Result<mysql::PooledConn, mysql::error::Error> {
 	/* The purpose of this function is to:
 		1. Create a formatted URL of MySQL connection arguments.
@@ -222,11 +1409,14 @@ pub fn get_mysql_conn(config: &AppConfig) -> Result<mysql::PooledConn, mysql::er
 		port=config.mysql_port.unwrap_or(3306),  // default port for MySQL databases.
 		database=config.mysql_database);
 
-	let opts = mysql::Opts::from_url(&url)?;
-	let pool = mysql::Pool::new(opts)?;
-	pool.get_conn()
+	let result = mysql::Opts::from_url(&url).map_err(mysql::Error::from)
+		.and_then(mysql::Pool::new)
+		.and_then(|pool| pool.get_conn());
+	crate::metrics::record_connection_attempt(crate::metrics::Backend::Mysql, result.is_ok());
+	result
 }
 
+#[cfg(feature = "sql")]
 pub fn get_mysql_pool(config: &AppConfig) -> Result<mysql::Pool, mysql::error::Error> {
 	/* The purpose of this function is to:
 		1. Create a formatted URL of MySQL connection arguments.
@@ -0,0 +1,95 @@
+// config_check.rs
+
+/*
+	"Is my configuration right?"  Better answered by 'btu show-config-problems' than by the daemon panicking at 2am.
+
+	check_config() tries each thing the daemon depends on, using the configuration as written: the time zone, MariaDB,
+	Redis, the Frappe web server, and the Unix Domain Socket's directory and group.  Each check is one finding, pass or
+	fail, carrying the underlying error message.  Nothing is written anywhere.
+*/
+
+use std::path::Path;
+
+use crate::config::AppConfig;
+use crate::diagnose::{DiagnosticFinding, Severity};
+use crate::frappe_client::FrappeClient;
+
+/// The Frappe endpoint that answers when the web server, and the BTU App, are both working.
+pub const TEST_PING_PATH: &str = "/api/method/btu.btu_api.endpoints.test_ping";
+
+/// Every live check of the configuration, in the order the daemon needs them.
+pub fn check_config(app_config: &AppConfig) -> Vec<DiagnosticFinding> {
+	vec![
+		check_time_zone(app_config),
+		check_mariadb(app_config),
+		check_redis(app_config),
+		check_frappe(app_config),
+		check_socket_directory(&app_config.socket_path),
+		check_socket_group(&app_config.socket_file_group_owner),
+	]
+}
+
+fn check_time_zone(app_config: &AppConfig) -> DiagnosticFinding {
+	match app_config.tz() {
+		Ok(_) => DiagnosticFinding::new("time_zone_string", Severity::Pass, format!("'{}' is a known time zone.", app_config.time_zone_string)),
+		Err(error) => DiagnosticFinding::new("time_zone_string", Severity::Fail, format!("'{}' is not a known time zone: {}", app_config.time_zone_string, error)),
+	}
+}
+
+#[cfg(feature = "sql")]
+fn check_mariadb(app_config: &AppConfig) -> DiagnosticFinding {
+	let report: crate::SqlValidationReport = crate::validate_sql_credentials(app_config);
+	if report.is_ok() {
+		return DiagnosticFinding::new("mariadb", Severity::Pass, report.message);
+	}
+	DiagnosticFinding::new("mariadb", Severity::Fail, report.describe())
+}
+
+#[cfg(not(feature = "sql"))]
+fn check_mariadb(_app_config: &AppConfig) -> DiagnosticFinding {
+	DiagnosticFinding::new("mariadb", Severity::Warn, "Not checked; built without SQL support.")
+}
+
+fn check_redis(app_config: &AppConfig) -> DiagnosticFinding {
+	// Connect directly (not with rq::get_redis_connection()), to keep the error message.
+	match crate::rq::open_redis_connection(app_config) {
+		Ok(_) => DiagnosticFinding::new("redis", Severity::Pass, format!("Connected to {}:{}.", app_config.rq_host, app_config.rq_port)),
+		Err(error) if error.kind() == redis::ErrorKind::AuthenticationFailed => DiagnosticFinding::new("redis", Severity::Fail,
+		                                     crate::rq::describe_connection_error(app_config, &error)),
+		Err(error) => DiagnosticFinding::new("redis", Severity::Fail,
+		                                     format!("Unable to connect to {}:{}: {}", app_config.rq_host, app_config.rq_port, error)),
+	}
+}
+
+fn check_frappe(app_config: &AppConfig) -> DiagnosticFinding {
+	let client = FrappeClient::new(app_config);
+	match client.get_json(TEST_PING_PATH) {
+		Ok(reply) => match reply["message"].as_str() {
+			Some(message) => DiagnosticFinding::new("frappe", Severity::Pass, format!("'test_ping' answered '{}'.", message)),
+			None => DiagnosticFinding::new("frappe", Severity::Fail, format!("'test_ping' answered without a string 'message': {}", reply)),
+		},
+		Err(error) => DiagnosticFinding::new("frappe", Severity::Fail, error.to_string()),
+	}
+}
+
+fn check_socket_directory(socket_path: &str) -> DiagnosticFinding {
+	let Some(directory) = Path::new(socket_path).parent().filter(|directory| !directory.as_os_str().is_empty()) else {
+		return DiagnosticFinding::new("socket_path", Severity::Fail, format!("'{}' is not an absolute path to a file.", socket_path));
+	};
+	if directory.is_dir() {
+		DiagnosticFinding::new("socket_path", Severity::Pass, format!("Directory '{}' exists.", directory.display()))
+	}
+	else {
+		DiagnosticFinding::new("socket_path", Severity::Fail, format!("Directory '{}' does not exist.", directory.display()))
+	}
+}
+
+fn check_socket_group(group_name: &str) -> DiagnosticFinding {
+	match nix::unistd::Group::from_name(group_name) {
+		Ok(Some(group)) => DiagnosticFinding::new("socket_file_group_owner", Severity::Pass,
+		                                          format!("Linux group '{}' exists (gid {}).", group_name, group.gid)),
+		Ok(None) => DiagnosticFinding::new("socket_file_group_owner", Severity::Fail, format!("There is no Linux group named '{}'.", group_name)),
+		Err(error) => DiagnosticFinding::new("socket_file_group_owner", Severity::Fail,
+		                                     format!("Unable to look up Linux group '{}': {}", group_name, error)),
+	}
+}
@@ -0,0 +1,76 @@
+// dependency.rs
+
+/*
+	A Task Schedule may depend on another ('depends_on_schedule'): "run the export only after the import finishes".
+
+	Whenever BTU enqueues a Task Schedule's RQ Job, its ID is recorded in the hash 'btu_scheduler:last_job_ids'.  When a
+	dependent schedule runs, its new job depends on the parent schedule's most recent job, using RQ's own 'depends_on'
+	structures (see RQJob::save_deferred_pipeline).  RQ's worker enqueues the dependent job when the parent job finishes.
+
+	* Parent job still pending (queued, started, deferred, scheduled):  the new job is deferred.
+	* Parent job finished:  there is nothing to wait for; the new job is enqueued at once, as Python RQ does.
+	* Parent job failed, stopped, or canceled:  deferred as well, as Python RQ does; it runs only if the parent is retried and finishes.
+	* No recent parent job (never run, expired, or deleted):  the new job is enqueued at once, with a warning.
+*/
+
+use std::collections::HashMap;
+
+use redis::{Commands, RedisError};
+
+use crate::ids::{RqJobId, TaskScheduleId};
+use crate::rq;
+
+pub use crate::redis_keys::RQ_KEY_LAST_JOB_IDS;
+
+/// A Task Schedule's most recent RQ Job, with its hash (empty when the key no longer exists).
+pub type LastJob = (RqJobId, HashMap<String, Vec<u8>>);
+
+/// How a dependent Task Schedule's new RQ Job is saved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DependencyPlan {
+	Defer { parent_job_id: RqJobId, warning: Option<String> },  // saved as deferred; RQ enqueues it after the parent job
+	EnqueueNow { warning: Option<String> },                     // enqueued as usual
+}
+
+/// Decide how to save a job of 'task_schedule_id', which depends on 'parent_schedule_id'.
+/// 'parent_job' is the parent schedule's most recent RQ Job; None when none was recorded.
+pub fn plan(task_schedule_id: &str, parent_schedule_id: &str, parent_job: Option<LastJob>) -> DependencyPlan {
+	let Some((parent_job_id, parent_hash)) = parent_job else {
+		return DependencyPlan::EnqueueNow {
+			warning: Some(format!("Task Schedule {} depends on Task Schedule {}, which has no recent RQ Job; enqueued without waiting.",
+			                      task_schedule_id, parent_schedule_id))
+		};
+	};
+	if parent_hash.is_empty() {
+		return DependencyPlan::EnqueueNow {
+			warning: Some(format!("Task Schedule {} depends on Task Schedule {}, whose most recent RQ Job {} no longer exists; enqueued without waiting.",
+			                      task_schedule_id, parent_schedule_id, parent_job_id))
+		};
+	}
+	let status: String = rq::hashmap_value_to_optstring(&parent_hash, "status").unwrap_or_default();
+	match status.as_str() {
+		"finished" => DependencyPlan::EnqueueNow { warning: None },
+		"failed" | "stopped" | "canceled" => DependencyPlan::Defer {
+			warning: Some(format!("Task Schedule {} depends on Task Schedule {}, whose most recent RQ Job {} is '{}'; \
+			                       the new job stays deferred, unless that job is retried and finishes.",
+			                      task_schedule_id, parent_schedule_id, parent_job_id, status)),
+			parent_job_id,
+		},
+		_ => DependencyPlan::Defer { parent_job_id, warning: None },
+	}
+}
+
+/// Remember the RQ Job just enqueued (or deferred) for a Task Schedule.
+pub fn record_last_job(redis_conn: &mut redis::Connection, task_schedule_id: &TaskScheduleId, job_id: &RqJobId) -> Result<(), RedisError> {
+	redis_conn.hset(RQ_KEY_LAST_JOB_IDS, task_schedule_id.as_str(), job_id.as_str())
+}
+
+/// The most recent RQ Job of a Task Schedule, with its hash; None when none was recorded.
+pub fn read_last_job(redis_conn: &mut redis::Connection, task_schedule_id: &TaskScheduleId) -> Result<Option<LastJob>, RedisError> {
+	let job_id: Option<String> = redis_conn.hget(RQ_KEY_LAST_JOB_IDS, task_schedule_id.as_str())?;
+	let Some(job_id) = job_id else {
+		return Ok(None);
+	};
+	let job_hash: HashMap<String, Vec<u8>> = redis_conn.hgetall(rq::job_key(&job_id))?;
+	Ok(Some((RqJobId::from(job_id), job_hash)))
+}
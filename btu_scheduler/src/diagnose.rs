@@ -0,0 +1,321 @@
+// diagnose.rs
+
+/*
+	"Why is my schedule not running?"  Support's most common question, answered in one report.
+
+	gather() collects every signal about one Task Schedule: MariaDB (the schedule, its Task, the function policy), the cron
+	expression and its next runs, and Redis (its TSIK, markers, last RQ Job, recent events, and live workers).  The caller
+	adds the daemon's last cycle, which only the socket can tell.  diagnose() then turns those facts into findings, each
+	pass, warn, or fail, with a one-line explanation.  diagnose() reads nothing itself, so every scenario can be tested.
+*/
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::btu_cron::CronClassification;
+use crate::events::{EventKind, StreamedEvent};
+use crate::ids::{RqJobId, TaskId, TaskScheduleId};
+use crate::scheduler::UnschedulableMarker;
+use crate::task::TaskStatus;
+use crate::timeutil::humanize_duration;
+
+/// How many of a Task Schedule's next runs are shown.
+pub const NEXT_RUNS_SHOWN: usize = 3;
+/// How many of a Task Schedule's recent events are shown.
+pub const RECENT_EVENTS_SHOWN: usize = 5;
+/// How many events are read from the stream, to find a Task Schedule's recent ones.
+pub const EVENTS_SEARCHED: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+	Pass,
+	Warn,
+	Fail,
+}
+
+impl std::fmt::Display for Severity {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Severity::Pass => write!(f, "PASS"),
+			Severity::Warn => write!(f, "WARN"),
+			Severity::Fail => write!(f, "FAIL"),
+		}
+	}
+}
+
+/// One line of the report.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DiagnosticFinding {
+	pub check: &'static str,
+	pub severity: Severity,
+	pub explanation: String,
+}
+
+impl DiagnosticFinding {
+	pub(crate) fn new(check: &'static str, severity: Severity, explanation: impl Into<String>) -> Self {
+		DiagnosticFinding { check, severity, explanation: explanation.into() }
+	}
+}
+
+impl std::fmt::Display for DiagnosticFinding {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "[{}] {}: {}", self.severity, self.check, self.explanation)
+	}
+}
+
+/// What MariaDB says about the Task Schedule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScheduleFacts {
+	pub enabled: bool,
+	pub task: TaskId,
+	pub queue_name: String,
+	pub expression: String,                               // the cron expression, or the repeat interval
+	pub description: String,                              // the expression in English
+	pub cron: Option<CronClassification>,                 // None for a repeat interval
+	pub next_runs: Result<Vec<DateTime<Utc>>, String>,    // an error for an invalid expression
+}
+
+/// What MariaDB says about the Task Schedule's BTU Task.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaskFacts {
+	pub status: TaskStatus,
+	pub path_to_function: String,
+	pub policy: Result<(), String>,  // 'allowed_function_prefixes'
+}
+
+/// What Redis says about the Task Schedule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedisFacts {
+	pub next_tsik: Option<DateTime<Utc>>,               // the soonest stored Next Execution Time
+	pub circuit_open: bool,
+	pub unschedulable: Option<UnschedulableMarker>,
+	pub next_run_override: Option<DateTime<Utc>>,
+	pub last_job: Option<(RqJobId, Option<String>)>,    // the most recent RQ Job, with its status (None when its hash is gone)
+	pub recent_events: Vec<StreamedEvent>,              // this schedule's, oldest first
+	pub live_workers: Option<bool>,                     // on the schedule's queue; None when the queue is unknown
+}
+
+/// Everything known about one Task Schedule.  Err holds why a source could not be read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiagnosticInputs {
+	pub task_schedule_id: TaskScheduleId,
+	pub schedule: Result<Option<ScheduleFacts>, String>,
+	pub task: Option<Result<Option<TaskFacts>, String>>,       // None when the schedule could not be read
+	pub redis: Result<RedisFacts, String>,
+	pub daemon_last_cycle: Result<Option<DateTime<Utc>>, String>,  // when the daemon's last cycle started; None before its first
+	pub polling_interval_secs: u64,
+}
+
+fn format_time(datetime: DateTime<Utc>) -> String {
+	datetime.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Turn the facts into findings, in a fixed order.
+pub fn diagnose(inputs: &DiagnosticInputs, now: DateTime<Utc>) -> Vec<DiagnosticFinding> {
+	use Severity::{Fail, Pass, Warn};
+
+	let mut findings: Vec<DiagnosticFinding> = Vec::new();
+	let id: &TaskScheduleId = &inputs.task_schedule_id;
+	let polling_secs: i64 = inputs.polling_interval_secs.max(1) as i64;
+
+	// MariaDB: the Task Schedule, and its Task.
+	let schedule: Option<&ScheduleFacts> = match &inputs.schedule {
+		Err(error) => {
+			findings.push(DiagnosticFinding::new("schedule", Fail, format!("Unable to read Task Schedule {} from MariaDB: {}", id, error)));
+			None
+		},
+		Ok(None) => {
+			findings.push(DiagnosticFinding::new("schedule", Fail, format!("Task Schedule {} does not exist in MariaDB.", id)));
+			None
+		},
+		Ok(Some(schedule)) if !schedule.enabled => {
+			findings.push(DiagnosticFinding::new("schedule", Fail, format!("Task Schedule {} is disabled; BTU neither schedules nor runs it.", id)));
+			Some(schedule)
+		},
+		Ok(Some(schedule)) => {
+			findings.push(DiagnosticFinding::new("schedule", Pass, format!("Task Schedule {} exists, and is enabled (queue '{}').", id, schedule.queue_name)));
+			Some(schedule)
+		},
+	};
+	if let (Some(schedule), Some(task)) = (schedule, &inputs.task) {
+		match task {
+			Err(error) => findings.push(DiagnosticFinding::new("task", Fail, format!("Unable to read BTU Task {}: {}", schedule.task, error))),
+			Ok(None) => findings.push(DiagnosticFinding::new("task", Fail, format!("BTU Task {} does not exist.", schedule.task))),
+			Ok(Some(task)) => {
+				findings.push(match (task.status.rejection_reason(), task.status.warning()) {
+					(Some(reason), _) => DiagnosticFinding::new("task", Fail, format!("BTU Task {}: {}.", schedule.task, reason)),
+					(None, Some(warning)) => DiagnosticFinding::new("task", Warn, format!("BTU Task {}: {}.", schedule.task, warning)),
+					(None, None) => DiagnosticFinding::new("task", Pass, format!("BTU Task {} exists, and is submitted.", schedule.task)),
+				});
+				findings.push(match &task.policy {
+					Ok(()) => DiagnosticFinding::new("function policy", Pass, format!("'{}' is allowed.", task.path_to_function)),
+					Err(error) => DiagnosticFinding::new("function policy", Fail, error.clone()),
+				});
+			}
+		}
+	}
+
+	// The cron expression (or repeat interval), and its next runs.
+	if let Some(schedule) = schedule {
+		findings.push(match &schedule.next_runs {
+			Err(error) => DiagnosticFinding::new("cron", Fail, format!("'{}' is not valid: {}", schedule.expression, error)),
+			Ok(next_runs) if next_runs.is_empty() => {
+				DiagnosticFinding::new("cron", Fail, format!("'{}' ({}) yields no upcoming run.", schedule.expression, schedule.description))
+			},
+			Ok(next_runs) => {
+				let normalized: String = schedule.cron.as_ref().map(|cron| format!(", read as '{}'", cron.cron7)).unwrap_or_default();
+				let runs: Vec<String> = next_runs.iter().take(NEXT_RUNS_SHOWN).map(|run| format_time(*run)).collect();
+				DiagnosticFinding::new("cron", Pass, format!("'{}'{} runs {}.  Next: {}", schedule.expression, normalized, schedule.description, runs.join(", ")))
+			},
+		});
+		if let Some(notice) = schedule.cron.as_ref().and_then(CronClassification::notice) {
+			findings.push(DiagnosticFinding::new("cron elements", Warn, notice));
+		}
+	}
+
+	// Redis.
+	match &inputs.redis {
+		Err(error) => findings.push(DiagnosticFinding::new("redis", Fail, format!("Unable to read Redis: {}", error))),
+		Ok(redis) => {
+			let enabled: bool = schedule.map_or(false, |schedule| schedule.enabled);
+			findings.push(match redis.next_tsik {
+				Some(next_run) if (now - next_run).num_seconds() > 2 * polling_secs => DiagnosticFinding::new("next run", Warn,
+					format!("Scheduled for {}, which is {} past due; is the daemon running its cycles?", format_time(next_run), humanize_duration(now - next_run))),
+				Some(next_run) => DiagnosticFinding::new("next run", Pass, format!("Scheduled in Redis for {}.", format_time(next_run))),
+				None if enabled => DiagnosticFinding::new("next run", Fail,
+					"Nothing is scheduled in Redis.  The daemon adds it on its next full refresh, or on a 'create_task_schedule' request."),
+				None => DiagnosticFinding::new("next run", Pass, "Nothing is scheduled in Redis, as expected."),
+			});
+			findings.push(if redis.circuit_open {
+				DiagnosticFinding::new("circuit breaker", Fail, "Open: it failed too many times in a row.  Close it with 'btu circuit --reset'.")
+			} else {
+				DiagnosticFinding::new("circuit breaker", Pass, "Closed.")
+			});
+			findings.push(match &redis.unschedulable {
+				Some(marker) => DiagnosticFinding::new("unschedulable", Fail,
+					format!("Flagged since {}: {}", marker.detected_at, marker.reason)),
+				None => DiagnosticFinding::new("unschedulable", Pass, "Not flagged."),
+			});
+			if let Some(next_run) = redis.next_run_override {
+				findings.push(DiagnosticFinding::new("next-run override", Warn, format!("An operator moved its next run to {}.", format_time(next_run))));
+			}
+			findings.push(match &redis.last_job {
+				None => DiagnosticFinding::new("last run", Warn, "No RQ Job has been recorded for it."),
+				Some((job_id, None)) => DiagnosticFinding::new("last run", Pass, format!("RQ Job {} has since expired from Redis.", job_id)),
+				Some((job_id, Some(status))) if matches!(status.as_str(), "failed" | "stopped" | "canceled") => {
+					DiagnosticFinding::new("last run", Warn, format!("RQ Job {} is '{}'; see 'btu show-job {}'.", job_id, status, job_id))
+				},
+				Some((job_id, Some(status))) => DiagnosticFinding::new("last run", Pass, format!("RQ Job {} is '{}'.", job_id, status)),
+			});
+			let recent: Vec<&StreamedEvent> = redis.recent_events.iter().rev().take(RECENT_EVENTS_SHOWN).collect();
+			findings.push(match recent.first() {
+				None => DiagnosticFinding::new("history", Pass, "No recent events (they are recorded when 'event_stream_enabled' is true)."),
+				Some(latest) => {
+					let lines: Vec<String> = recent.iter().map(|event| {
+						let reason: String = event.event.reason.as_ref().map(|reason| format!(" ({})", reason)).unwrap_or_default();
+						format!("{} {}{}", event.event.at, format!("{:?}", event.event.event).to_lowercase(), reason)
+					}).collect();
+					let severity: Severity = if matches!(latest.event.event, EventKind::Failed | EventKind::Skipped | EventKind::Tripped) { Warn } else { Pass };
+					DiagnosticFinding::new("history", severity, format!("Most recent first: {}", lines.join("; ")))
+				}
+			});
+			if let Some(schedule) = schedule {
+				findings.push(match redis.live_workers {
+					Some(true) => DiagnosticFinding::new("workers", Pass, format!("A live worker listens to queue '{}'.", schedule.queue_name)),
+					Some(false) | None => DiagnosticFinding::new("workers", Fail, format!("No live worker listens to queue '{}'.", schedule.queue_name)),
+				});
+			}
+		}
+	}
+
+	// The daemon.
+	findings.push(match inputs.daemon_last_cycle {
+		Err(ref error) => DiagnosticFinding::new("daemon", Fail, format!("Unable to reach the daemon: {}", error)),
+		Ok(None) => DiagnosticFinding::new("daemon", Warn, "The daemon is running, but has not finished a scheduling cycle yet."),
+		Ok(Some(started_at)) if (now - started_at).num_seconds() > 3 * polling_secs => DiagnosticFinding::new("daemon", Warn,
+			format!("The daemon's last cycle started {} ago; it runs every {} seconds.", humanize_duration(now - started_at), polling_secs)),
+		Ok(Some(started_at)) => DiagnosticFinding::new("daemon", Pass, format!("The daemon's last cycle started {} ago.", humanize_duration(now - started_at))),
+	});
+	findings
+}
+
+/// True when any finding failed.
+pub fn any_failed(findings: &[DiagnosticFinding]) -> bool {
+	findings.iter().any(|finding| finding.severity == Severity::Fail)
+}
+
+#[cfg(feature = "sql")]
+/**
+  Read every fact about one Task Schedule from MariaDB and Redis.  The ID may be qualified by its site ("site::TS-0001").\
+  'daemon_last_cycle' comes from the daemon's 'health' reply, which only a socket client can ask for.
+*/
+pub fn gather(app_config: &crate::config::AppConfig, task_schedule_id: &TaskScheduleId, daemon_last_cycle: Result<Option<DateTime<Utc>>, String>,
+              now: DateTime<Utc>) -> DiagnosticInputs {
+	use crate::{circuit_breaker, config, dependency, next_run_override, rq, scheduler, task, task_schedule};
+
+	let (site, unqualified_id) = config::split_schedule_id(task_schedule_id.as_str());
+	let site_config: Option<config::AppConfig> = app_config.for_site(site);
+	let schedule: Result<Option<task_schedule::BtuTaskSchedule>, String> = match &site_config {
+		Some(site_config) => task_schedule::try_read_btu_task_schedule(site_config, &TaskScheduleId::from(unqualified_id)).map_err(|error| error.to_string()),
+		None => Err(format!("site '{}' is not in the configuration", site.unwrap_or_default())),
+	};
+	let task: Option<Result<Option<TaskFacts>, String>> = match (&schedule, &site_config) {
+		(Ok(Some(schedule)), Some(site_config)) => Some(task::read_task_preflight(site_config, schedule.task())
+			.map(|preflight| preflight.map(|preflight| TaskFacts {
+				status: preflight.status,
+				policy: task::check_function_policy(site_config, schedule.task().as_str(), &preflight.path_to_function).map_err(|error| error.to_string()),
+				path_to_function: preflight.path_to_function,
+			}))
+			.map_err(|error| error.to_string())),
+		_ => None,
+	};
+	let schedule_facts: Result<Option<ScheduleFacts>, String> = schedule.map(|schedule| schedule.map(|schedule| {
+		let next_runs: Result<Vec<DateTime<Utc>>, String> = match schedule.repeat_interval() {
+			Some(Err(error)) => Err(error.to_string()),
+			_ => schedule.next_runtimes(&Some(now), &NEXT_RUNS_SHOWN, app_config.cron_dom_dow_semantics)
+				.ok_or_else(|| "unable to compute its next runs".to_owned()),
+		};
+		ScheduleFacts {
+			enabled: schedule.enabled,
+			task: schedule.task().clone(),
+			queue_name: schedule.queue_name().to_owned(),
+			expression: schedule.repeat_interval.clone().filter(|_| schedule.cron_string.trim().is_empty()).unwrap_or_else(|| schedule.cron_string.clone()),
+			description: schedule.describe_schedule(app_config.cron_dom_dow_semantics),
+			cron: scheduler::cron_classification(&schedule),
+			next_runs,
+		}
+	}));
+
+	let redis: Result<RedisFacts, String> = match rq::get_redis_connection(app_config, false) {
+		None => Err("unable to establish a connection".to_owned()),
+		Some(mut redis_conn) => {
+			let queue_name: Option<String> = schedule_facts.as_ref().ok().and_then(Option::as_ref).map(|schedule| schedule.queue_name.clone());
+			let last_job: Option<(RqJobId, Option<String>)> = dependency::read_last_job(&mut redis_conn, task_schedule_id).ok().flatten()
+				.map(|(job_id, job_hash)| {
+					let status: Option<String> = (!job_hash.is_empty()).then(|| rq::hashmap_value_to_optstring(&job_hash, "status").unwrap_or_default());
+					(job_id, status)
+				});
+			let recent_events: Vec<StreamedEvent> = scheduler::read_events(app_config, EVENTS_SEARCHED, None).unwrap_or_default().into_iter()
+				.filter(|event| event.event.schedule_id.as_ref() == Some(task_schedule_id))
+				.collect();
+			Ok(RedisFacts {
+				next_tsik: scheduler::soonest_scheduled_run(app_config, task_schedule_id.as_str()),
+				circuit_open: circuit_breaker::is_open(&mut redis_conn, task_schedule_id.as_str()),
+				unschedulable: scheduler::read_unschedulable_marker(app_config, task_schedule_id.as_str()),
+				next_run_override: next_run_override::active_override(&mut redis_conn, task_schedule_id.as_str(), now),
+				last_job,
+				recent_events,
+				live_workers: queue_name.map(|queue_name| rq::queue_has_live_workers(&mut redis_conn, &queue_name)),
+			})
+		}
+	};
+	DiagnosticInputs {
+		task_schedule_id: task_schedule_id.clone(),
+		schedule: schedule_facts,
+		task,
+		redis,
+		daemon_last_cycle,
+		polling_interval_secs: app_config.scheduler_polling_interval,
+	}
+}
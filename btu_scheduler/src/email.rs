@@ -8,7 +8,8 @@ use lettre::{transport::smtp::authentication::Credentials, Message, SmtpTranspor
 // use lettre::smtp::response::Response;
 // use lettre_email::{Email, EmailBuilder};
 use tracing::{trace, debug, info, warn, error, span, Level};
-use crate::config::AppConfig;
+use crate::config::{AppConfig, EmailSecurity};
+use crate::errors::EmailConfigError;
 
 
 #[derive(Clone, Debug)]
@@ -23,72 +24,70 @@ pub fn send_email(app_config: &AppConfig, subject: &str, body: &str) -> AHResult
     
     let mailer = make_mailer_from_config(app_config)?;
 
-    // Need to create a semi-colon separated string of To Email addresses.
-    let mut to_addresses = String::new();
-    for each in app_config.email_addresses.as_ref().unwrap() {
-        to_addresses += &format!("{};", each);
-    }
-
-    // This seems very silly, looping over the entire set of functions.
-    // But Lettre 0.10 seems a step backwards, and I don't have time to fix
-
-	let btu_email = BTUEmail {
-        from: app_config.email_address_from.as_ref().unwrap().to_owned(),
-        to: app_config.email_addresses.as_ref().unwrap().to_vec(),
+    // make_mailer_from_config() validated these settings already.
+    let btu_email = BTUEmail {
+        from: app_config.email_address_from.clone().unwrap_or_default(),
+        to: app_config.email_addresses.clone().unwrap_or_default(),
         subject: subject.to_owned(),
         body: body.to_owned()
     };
 
-    // Add multiple To Address, if required.
-    for each_recipient in btu_email.to {
-
-        let this_body = body;
-        // Create an Email Builder.
+    // Send a separate email to each recipient.
+    for each_recipient in &btu_email.to {
         let email: Message = Message::builder()
-        .from(btu_email.from.parse().unwrap())  // parse the String into a Mailbox
-        .to(each_recipient.parse().unwrap())
-        .subject(&btu_email.subject)
-        .body(this_body.to_owned())
-        .unwrap();
-
-        match mailer.send(&email) {
-            Ok(_) => {
-                println!("Email sent successfully!");
-            }
-            Err(e) => panic!("Could not send email: {:?}", e),
-        }
-    
+            .from(btu_email.from.parse().context("Invalid 'email_address_from'")?)  // parse the String into a Mailbox
+            .to(each_recipient.parse().with_context(|| format!("Invalid recipient email address '{}'", each_recipient))?)
+            .subject(&btu_email.subject)
+            .body(btu_email.body.clone())?;
+
+        mailer.send(&email).with_context(|| format!("Could not send email to '{}'", each_recipient))?;
+        debug!("Email sent successfully to '{}'.", each_recipient);
     }
 
     Ok(())
 }
 
 
-pub fn make_mailer_from_config(app_config: &AppConfig) -> AHResult<SmtpTransport> {
-
-    if app_config.email_host_name.is_none() {
-        panic!("Configuration file is missing an Email Host Name.");
-    }
-    if app_config.email_account_name.is_none() {
-        panic!("Configuration file is missing an Email Account Name.");
-    }
-    if app_config.email_account_password.is_none() {
-        panic!("Configuration file is missing an Email Password.");
-    }
+/// Everything needed to build an SMTP transport.  Kept separate from the transport, so it can be tested without sending mail.
+#[derive(Debug, PartialEq)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub security: EmailSecurity,
+    pub credentials: Option<(String, String)>,  // account name, password
+}
 
-    let this_email_account: String = app_config.email_account_name.as_ref().unwrap().clone();
-    let this_email_password: String = app_config.email_account_password.as_ref().unwrap().clone();
-    let this_email_host: String = app_config.email_account_password.as_ref().unwrap().clone();
+pub fn smtp_settings_from_config(app_config: &AppConfig) -> Result<SmtpSettings, EmailConfigError> {
 
-    let creds = Credentials::new(this_email_account, this_email_password);
+    app_config.validate_email_settings()?;
+    let Some(host) = app_config.email_host_name.clone() else {
+        return Err(EmailConfigError::MissingSettings { missing: vec!["email_host_name"] });
+    };
+    let security: EmailSecurity = app_config.email_security()?;
+    let port: u16 = match app_config.email_host_port {
+        Some(port) => u16::try_from(port).map_err(|_| EmailConfigError::InvalidPort { found: port })?,
+        None => security.default_port()
+    };
+    let credentials = match (&app_config.email_account_name, &app_config.email_account_password) {
+        (Some(account), Some(password)) => Some((account.clone(), password.clone())),
+        _ => None
+    };
+    Ok(SmtpSettings { host, port, security, credentials })
+}
 
-    // Open a remote connection to mail server.
-    let mailer = SmtpTransport::relay(&this_email_host)
-        .unwrap()
-        .credentials(creds)
-        .build();
+pub fn make_mailer_from_config(app_config: &AppConfig) -> AHResult<SmtpTransport> {
 
-    Ok(mailer)
+    let settings: SmtpSettings = smtp_settings_from_config(app_config)?;
+    let builder = match settings.security {
+        EmailSecurity::Tls => SmtpTransport::relay(&settings.host)?,
+        EmailSecurity::StartTls => SmtpTransport::starttls_relay(&settings.host)?,
+        EmailSecurity::None => SmtpTransport::builder_dangerous(&settings.host),
+    };
+    let mut builder = builder.port(settings.port);
+    if let Some((account, password)) = settings.credentials {
+        builder = builder.credentials(Credentials::new(account, password));
+    }
+    Ok(builder.build())
 }
 
 /*
@@ -104,10 +103,10 @@ fn make_transport(mail_host: &str, mail_username: String, mail_password: String)
 
 pub fn make_email_body_preamble(app_config: &AppConfig) -> String {
     
-    let preamble: String = format!("{}<br>{}<br>{}<br>",
-        "Hi, I am the BTU scheduler daemon.",
-        format!("The current time is {} (UTC).", Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)),
-        format!("My environment is named: {}", app_config.environment_name.as_ref().unwrap_or(&"Not Specified".to_owned()))
+    let preamble: String = format!("Hi, I am the BTU scheduler daemon.<br>The current time is {} (UTC).<br>I am running as {}.<br>Platform: {}.<br>",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        crate::logging::InstanceIdentity::from_config(app_config),
+        crate::platform::PlatformInfo::detect()
     );
 
     preamble
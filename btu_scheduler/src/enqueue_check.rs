@@ -0,0 +1,108 @@
+// enqueue_check.rs
+
+/*
+	An 'rpush' that returns OK does not prove a worker can run the job.  Under Redis 'maxmemory' eviction, the job's hash
+	can vanish between the save and the push; the worker then pops an ID whose hash does not exist, and errors.
+
+	So after enqueuing, the scheduler verifies both halves: the job hash exists, and the job's ID is on its queue.  On a
+	mismatch, the job is saved and enqueued once more, and verified again.  Either way, the outcome is logged and
+	written to the event stream.
+*/
+
+use redis::{Commands, RedisError};
+
+use crate::ids::RqJobId;
+use crate::rq;
+
+/// What was found in Redis, just after a job was enqueued.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EnqueueCheck {
+	pub hash_exists: bool,  // 'rq:job:<ID>' exists
+	pub on_queue: bool,     // the ID is an element of 'rq:queue:<queue name>'
+}
+
+impl EnqueueCheck {
+	pub fn is_complete(&self) -> bool {
+		self.hash_exists && self.on_queue
+	}
+}
+
+impl std::fmt::Display for EnqueueCheck {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match (self.hash_exists, self.on_queue) {
+			(true, true) => write!(f, "the job hash exists, and the job is on its queue"),
+			(false, true) => write!(f, "the job is on its queue, but its hash does not exist (evicted?)"),
+			(true, false) => write!(f, "the job hash exists, but the job is not on its queue"),
+			(false, false) => write!(f, "neither the job hash nor its queue entry exists"),
+		}
+	}
+}
+
+/// The result of verifying an enqueued job.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verification {
+	Verified,                                          // found as expected, the first time
+	Repaired { first: EnqueueCheck },                  // found incomplete, then saved and enqueued again successfully
+	Failed { first: EnqueueCheck, retry: String },     // still incomplete (or the retry failed); 'retry' says why
+}
+
+/// Where enqueued jobs are checked.  In the daemon, that is Redis.
+pub trait EnqueuedJobStore {
+	fn job_hash_exists(&mut self, job_id: &RqJobId) -> Result<bool, RedisError>;
+	fn queue_contains(&mut self, queue_name: &str, job_id: &RqJobId) -> Result<bool, RedisError>;
+	/// Remove every occurrence of the ID from the queue, so a retry does not enqueue it twice.
+	fn remove_from_queue(&mut self, queue_name: &str, job_id: &RqJobId) -> Result<(), RedisError>;
+}
+
+impl EnqueuedJobStore for redis::Connection {
+	fn job_hash_exists(&mut self, job_id: &RqJobId) -> Result<bool, RedisError> {
+		self.exists(rq::job_key(job_id.as_str()))
+	}
+
+	fn queue_contains(&mut self, queue_name: &str, job_id: &RqJobId) -> Result<bool, RedisError> {
+		let queue_key: String = queue_key(queue_name);
+		// LPOS requires Redis 6.0.6; older servers answer with an error, and the whole list is read instead.
+		match redis::cmd("LPOS").arg(&queue_key).arg(job_id.as_str()).query::<Option<i64>>(self) {
+			Ok(position) => Ok(position.is_some()),
+			Err(_) => {
+				let members: Vec<String> = self.lrange(&queue_key, 0, -1)?;
+				Ok(members.iter().any(|member| member == job_id.as_str()))
+			}
+		}
+	}
+
+	fn remove_from_queue(&mut self, queue_name: &str, job_id: &RqJobId) -> Result<(), RedisError> {
+		self.lrem(queue_key(queue_name), 0, job_id.as_str())
+	}
+}
+
+fn queue_key(queue_name: &str) -> String {
+	format!("rq:queue:{}", queue_name)
+}
+
+/// Check both halves of an enqueued job.
+pub fn check_enqueued<S: EnqueuedJobStore>(store: &mut S, queue_name: &str, job_id: &RqJobId) -> Result<EnqueueCheck, RedisError> {
+	Ok(EnqueueCheck {
+		hash_exists: store.job_hash_exists(job_id)?,
+		on_queue: store.queue_contains(queue_name, job_id)?,
+	})
+}
+
+/// Verify an enqueued job.  When incomplete, remove its queue entry and call 'save_and_enqueue' once, then check again.
+pub fn verify_enqueued<S, F>(store: &mut S, queue_name: &str, job_id: &RqJobId, save_and_enqueue: F) -> Result<Verification, RedisError>
+	where S: EnqueuedJobStore, F: FnOnce(&mut S) -> Result<(), String> {
+	let first: EnqueueCheck = check_enqueued(store, queue_name, job_id)?;
+	if first.is_complete() {
+		return Ok(Verification::Verified);
+	}
+	store.remove_from_queue(queue_name, job_id)?;
+	if let Err(error) = save_and_enqueue(store) {
+		return Ok(Verification::Failed { first, retry: error });
+	}
+	let second: EnqueueCheck = check_enqueued(store, queue_name, job_id)?;
+	if second.is_complete() {
+		Ok(Verification::Repaired { first })
+	} else {
+		Ok(Verification::Failed { first, retry: second.to_string() })
+	}
+}
@@ -21,7 +21,11 @@ pub enum CronError {
 		found: usize
 	},
 	#[error("Invalid cron expression; could not transform into a CronStruct.")]
-	InvalidExpression
+	InvalidExpression,
+	#[error("Invalid repeat interval '{text}'; expected an ISO-8601 duration such as 'PT2H', or a phrase such as 'every 30 minutes'.")]
+	InvalidInterval {
+		text: String
+	}
 }
 
 #[derive(ThisError, Debug, PartialEq)]
@@ -30,22 +34,225 @@ pub enum StringError {
 	MissingDelimiter,
 }
 
+#[derive(ThisError, Debug, PartialEq)]
+pub enum FrappeBoolError {
+	#[error("Cannot interpret '{found}' as a boolean (expected one of 0, 1, true, false).")]
+	InvalidValue {
+		found: String
+	},
+	#[error("Expected a boolean, but the SQL column is NULL.")]
+	Null
+}
+
 #[derive(ThisError, Debug, PartialEq)]
 pub enum RQError {
 	#[error("No idea what happened here.")]
 	Unknown {
 		#[from]
 		source: redis::RedisError,
+	},
+	#[error("Unable to establish a connection to Redis.")]
+	NoConnection,
+	#[error("Failed to save RQ Job '{job_key}' to Redis; nothing was written: {source}")]
+	SaveFailed {
+		job_key: String,
+		source: redis::RedisError,
+	},
+	#[error("Failed to write to Redis key '{key}': {source}")]
+	WriteFailed {
+		key: String,
+		source: redis::RedisError,
+	},
+	#[error("Failed to read Redis key '{key}': {source}")]
+	ReadFailed {
+		key: String,
+		source: redis::RedisError,
+	},
+	#[error("Redis SCAN for keys matching '{pattern}' failed: {source}")]
+	ScanFailed {
+		pattern: String,
+		source: redis::RedisError,
+	}
+}
+
+#[derive(ThisError, Debug, Clone, PartialEq)]
+pub enum DurationArgError {
+	#[error("Invalid duration '{text}'; expected a number of seconds, or a number followed by s, m, h, or d (such as '90s', '15m', '2h', '1d').")]
+	Invalid {
+		text: String
+	},
+	#[error("Duration '{text}' is too large.")]
+	TooLarge {
+		text: String
+	}
+}
+
+#[derive(ThisError, Debug, PartialEq)]
+pub enum RescheduleError {
+	#[error("Task Schedule '{task_schedule_id}' has no upcoming run in Redis to move.")]
+	NothingScheduled {
+		task_schedule_id: String
+	},
+	#[error("Cannot move the next run to {requested}; that is not in the future.")]
+	NotInFuture {
+		requested: String
+	},
+	#[error("Cannot move the next run to {requested}; that is more than {max_days} days away.")]
+	TooFarAhead {
+		requested: String,
+		max_days: i64
+	},
+	#[error("Invalid time '{text}'; expected RFC 3339, such as '2024-07-01T04:00:00Z'.")]
+	InvalidTime {
+		text: String
+	},
+	#[error("Invalid offset: {source}")]
+	InvalidOffset {
+		#[from]
+		source: DurationArgError,
+	},
+	#[error("Unable to establish a connection to Redis.")]
+	NoConnection,
+	#[error("Failed to move the next run in Redis: {source}")]
+	Redis {
+		#[from]
+		source: redis::RedisError,
+	}
+}
+
+#[derive(ThisError, Debug, Clone, PartialEq)]
+pub enum SchedulerError {
+	#[error("Cannot find a BTU Task Schedule with identifier '{task_schedule_id}'.")]
+	UnknownTaskSchedule {
+		task_schedule_id: String
+	},
+	#[error("Error while reading BTU Task Schedule '{task_schedule_id}' from SQL: {message}")]
+	Sql {
+		task_schedule_id: String,
+		message: String
+	},
+	#[error("Cannot connect to MariaDB to read BTU Task Schedule '{task_schedule_id}': {message}")]
+	SqlUnavailable {
+		task_schedule_id: String,
+		message: String
+	}
+}
+
+#[derive(ThisError, Debug, PartialEq)]
+pub enum FrappeApiError {
+	#[error("Frappe raised {exc_type} while fetching BTU Task '{task_id}' (HTTP {status}): {exception}")]
+	Exception {
+		task_id: String,
+		status: u16,
+		exc_type: String,
+		exception: String  // only the first line; the full traceback is in Frappe's logs
+	},
+	#[error("Unexpected response from Frappe while fetching BTU Task '{task_id}' (HTTP {status}): {detail}")]
+	UnexpectedResponse {
+		task_id: String,
+		status: u16,
+		detail: String
+	},
+	#[error("Cannot reach Frappe while fetching BTU Task '{task_id}': {message}")]
+	Transport {
+		task_id: String,
+		message: String
+	}
+}
+
+#[derive(ThisError, Debug, PartialEq)]
+pub enum FrappeHttpError {
+	#[error("No response from the Frappe web server at {url} within {secs} seconds.")]
+	Timeout {
+		url: String,
+		secs: u64
+	},
+	#[error("Cannot reach the Frappe web server at {url}: {message}")]
+	Transport {
+		url: String,
+		message: String
+	},
+	#[error("Frappe web server replied with HTTP status {status} for URL {url}")]
+	Status {
+		url: String,
+		status: u16,
+		body: String
+	},
+	#[error("Unexpected response from the Frappe web server at {url}: {message}")]
+	InvalidBody {
+		url: String,
+		message: String
+	}
+}
+
+#[derive(ThisError, Debug)]
+pub enum SmokeTestError {
+	#[error("Unable to fetch the test function from the Frappe web server: {source}")]
+	Fetch {
+		#[from]
+		source: FrappeHttpError
+	},
+	#[error("Unable to save the test job: {source}")]
+	Save {
+		#[from]
+		source: RQError
+	},
+	#[error("Unable to enqueue the test job: {source}")]
+	Enqueue {
+		source: std::io::Error
+	}
+}
+
+#[derive(ThisError, Debug, PartialEq)]
+pub enum PolicyError {
+	#[error("BTU Task '{task_key}' is blocked by policy: function '{path_to_function}' does not match 'allowed_function_prefixes'.")]
+	FunctionNotAllowed {
+		task_key: String,
+		path_to_function: String
+	}
+}
+
+#[derive(ThisError, Debug, Clone, PartialEq)]
+pub enum IdentifierError {
+	#[error("A {kind} cannot be empty.")]
+	Empty {
+		kind: &'static str
+	},
+	#[error("A {kind} cannot be longer than {max_length} characters (found {length}).")]
+	TooLong {
+		kind: &'static str,
+		length: usize,
+		max_length: usize
+	}
+}
+
+#[derive(ThisError, Debug)]
+pub enum FrameError {
+	#[error("Socket message of {length} bytes exceeds the limit of {max_bytes} bytes.")]
+	TooLarge {
+		length: usize,
+		max_bytes: usize
+	},
+	#[error("Unable to read the socket message: {source}")]
+	Io {
+		#[from]
+		source: std::io::Error
 	}
 }
 
 // Email Errors
 #[derive(ThisError, Debug, PartialEq)]
 pub enum EmailConfigError {
-	#[error("Cron expression has the wrong number of elements (should be one of 5, 6, or 7).")]
-	WrongQtyOfElements {
-		found: usize
+	#[error("Email settings are incomplete.  Missing: {}", missing.join(", "))]
+	MissingSettings {
+		missing: Vec<&'static str>
 	},
-	#[error("Invalid cron expression; could not transform into a CronStruct.")]
-	InvalidExpression
+	#[error("Cannot interpret '{found}' as 'email_security' (expected one of tls, starttls, none).")]
+	InvalidSecurity {
+		found: String
+	},
+	#[error("Invalid 'email_host_port' value: {found}")]
+	InvalidPort {
+		found: i16
+	}
 }
@@ -0,0 +1,149 @@
+// events.rs
+
+/*
+	An optional changefeed of scheduler decisions, for consumers (such as analytics) that should not have to scrape logs.
+
+	With 'event_stream_enabled', every decision is appended as compact JSON to the Redis stream 'btu_scheduler:events':
+	a Next Execution Time was stored, a job was enqueued, a run was skipped or failed, a schedule was cancelled, or a stale
+	job was pruned.  Redis trims the stream to roughly EVENT_STREAM_MAXLEN entries.
+
+	Writes are best-effort.  A failed write is logged, and never changes the outcome of the decision it describes.
+*/
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use redis::streams::{StreamId, StreamMaxlen};
+use redis::{Commands, RedisError};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::config::AppConfig;
+use crate::ids::{CorrelationId, RqJobId, TaskScheduleId};
+
+pub use crate::redis_keys::EVENT_STREAM_KEY;
+/// The approximate number of entries kept in the stream ("XADD ... MAXLEN ~").
+pub const EVENT_STREAM_MAXLEN: usize = 10_000;
+/// Each stream entry has a single field, holding the event as JSON.
+pub static EVENT_FIELD: &str = "event";
+
+/// What the scheduler decided.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+	Scheduled,  // a Next Execution Time was stored in Redis
+	Enqueued,   // an RQ Job was enqueued for immediate execution
+	Skipped,    // a run (or the schedule itself) was passed over; see 'reason'
+	Failed,     // a run could not be scheduled or enqueued; see 'reason'
+	Cancelled,  // a Task Schedule's upcoming runs were removed from Redis
+	Pruned,     // a stale RQ Job was deleted
+	Overran,    // a started RQ Job ran past its timeout plus the watchdog's grace margin
+	Tripped,    // a Task Schedule failed too many times in a row, and is no longer scheduled (its circuit is open)
+	Reset,      // an operator closed a Task Schedule's open circuit
+	Rescheduled,  // an operator moved a Task Schedule's next run ('btu snooze')
+}
+
+/// One scheduler decision, as written to the event stream.  Empty fields are omitted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchedulerEvent {
+	pub event: EventKind,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub schedule_id: Option<TaskScheduleId>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub job_id: Option<RqJobId>,
+	pub at: String,  // RFC 3339, UTC; when the decision was made
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub run_at: Option<String>,  // RFC 3339, UTC; the Next Execution Time the decision concerns
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub reason: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub correlation_id: Option<CorrelationId>,  // the socket request that led to this decision, if any
+}
+
+impl SchedulerEvent {
+	pub fn new(event: EventKind) -> Self {
+		SchedulerEvent {
+			event,
+			schedule_id: None,
+			job_id: None,
+			at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+			run_at: None,
+			reason: None,
+			correlation_id: None,
+		}
+	}
+
+	pub fn schedule(mut self, schedule_id: &TaskScheduleId) -> Self {
+		self.schedule_id = Some(schedule_id.clone());
+		self
+	}
+
+	pub fn job(mut self, job_id: &RqJobId) -> Self {
+		self.job_id = Some(job_id.clone());
+		self
+	}
+
+	pub fn run_at(mut self, run_at: DateTime<Utc>) -> Self {
+		self.run_at = Some(run_at.to_rfc3339_opts(SecondsFormat::Secs, true));
+		self
+	}
+
+	pub fn reason(mut self, reason: impl Into<String>) -> Self {
+		self.reason = Some(reason.into());
+		self
+	}
+
+	pub fn correlation(mut self, correlation_id: Option<&CorrelationId>) -> Self {
+		self.correlation_id = correlation_id.cloned();
+		self
+	}
+}
+
+/// An event read back from the stream, with its stream entry ID.  Serializes as a single flat JSON object.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StreamedEvent {
+	pub id: String,
+	#[serde(flatten)]
+	pub event: SchedulerEvent,
+}
+
+impl StreamedEvent {
+	/// None when the entry has no 'event' field, or its JSON is not a SchedulerEvent.
+	pub fn from_stream_id(entry: &StreamId) -> Option<Self> {
+		let json: String = entry.get(EVENT_FIELD)?;
+		match serde_json::from_str(&json) {
+			Ok(event) => Some(StreamedEvent { id: entry.id.clone(), event }),
+			Err(error) => {
+				debug!("Ignoring entry '{}' of '{}': {}", entry.id, EVENT_STREAM_KEY, error);
+				None
+			}
+		}
+	}
+}
+
+/// Where events are appended.  In the daemon, that is the Redis stream.
+pub trait EventSink {
+	fn append_event(&mut self, json: &str) -> Result<(), RedisError>;
+}
+
+impl EventSink for redis::Connection {
+	fn append_event(&mut self, json: &str) -> Result<(), RedisError> {
+		self.xadd_maxlen::<_, _, _, _, String>(EVENT_STREAM_KEY, StreamMaxlen::Approx(EVENT_STREAM_MAXLEN), "*", &[(EVENT_FIELD, json)])
+			.map(|_| ())
+	}
+}
+
+/// Append an event to the stream, when 'event_stream_enabled' is true.  Best-effort: a failure is only logged.
+pub fn record_event<S: EventSink>(app_config: &AppConfig, sink: &mut S, event: SchedulerEvent) {
+	if !app_config.event_stream_enabled {
+		return;
+	}
+	let json: String = match serde_json::to_string(&event) {
+		Ok(json) => json,
+		Err(error) => {
+			warn!("Unable to serialize a scheduler event: {}", error);
+			return;
+		}
+	};
+	if let Err(error) = sink.append_event(&json) {
+		warn!("Unable to append to Redis stream '{}' (the event is dropped): {}", EVENT_STREAM_KEY, error);
+	}
+}
@@ -0,0 +1,193 @@
+// frappe_client.rs
+
+/*
+	Every HTTP request to the Frappe web server goes through a FrappeClient.
+
+	The client owns the base URL (see base_url() below), the API token, the optional "Host" header needed
+	when Frappe runs behind gunicorn in DNS multi-tenancy mode, and a timeout.  Without the timeout, a hung web server
+	would block the calling thread forever; in the daemon, that is the thread running Tasks.
+*/
+
+use std::io::Read;
+use std::time::Duration;
+
+use serde_json::Value as SerdeJsonValue;
+
+use crate::config::AppConfig;
+use crate::errors::FrappeHttpError;
+
+/// Used when 'webserver_timeout_secs' is not configured.
+pub const DEFAULT_WEBSERVER_TIMEOUT_SECS: u64 = 30;
+
+/// The largest response body read from the web server, in bytes.
+const MAX_BODY_BYTES: u64 = 10_000_000;
+
+/// The web server's base URL, and a warning about any settings it ignored.
+///
+/// A 'webserver_ip' containing "://" is already a full URL, and is used as-is; the port and TLS settings are ignored.
+/// Otherwise it is an IP address or hostname, and the scheme is decided by 'webserver_use_tls' when configured, else by
+/// the port: https for 443, http for any other.  The port is omitted when it is the scheme's default.
+pub fn base_url(webserver_ip: &str, webserver_port: u16, webserver_use_tls: Option<bool>) -> (String, Option<String>) {
+	let webserver_ip: &str = webserver_ip.trim();
+	if webserver_ip.contains("://") {
+		let warning: String = format!("'webserver_ip' is a full URL ({}); 'webserver_port' and 'webserver_use_tls' are ignored.", webserver_ip);
+		return (webserver_ip.trim_end_matches('/').to_owned(), Some(warning));
+	}
+	let use_tls: bool = webserver_use_tls.unwrap_or(webserver_port == 443);
+	let url: String = match (use_tls, webserver_port) {
+		(true, 443) => format!("https://{}", webserver_ip),
+		(false, 80) => format!("http://{}", webserver_ip),
+		(true, port) => format!("https://{}:{}", webserver_ip, port),
+		(false, port) => format!("http://{}:{}", webserver_ip, port),
+	};
+	(url, None)
+}
+
+/// A response from the web server, whatever its HTTP status.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrappeResponse {
+	pub url: String,
+	pub status: u16,
+	pub content_length: Option<usize>,
+	pub body: Vec<u8>,
+}
+
+impl FrappeResponse {
+	fn is_success(&self) -> bool {
+		(200..300).contains(&self.status)
+	}
+
+	/// The response as an error, unless its HTTP status is 2xx.
+	fn require_success(self) -> Result<Self, FrappeHttpError> {
+		if self.is_success() {
+			return Ok(self);
+		}
+		Err(FrappeHttpError::Status {
+			url: self.url,
+			status: self.status,
+			body: String::from_utf8_lossy(&self.body).into_owned()
+		})
+	}
+
+	fn into_json(self) -> Result<SerdeJsonValue, FrappeHttpError> {
+		serde_json::from_slice(&self.body)
+			.map_err(|error| FrappeHttpError::InvalidBody { url: self.url, message: format!("not valid JSON ({})", error) })
+	}
+}
+
+pub struct FrappeClient {
+	agent: ureq::Agent,
+	base_url: String,
+	token: String,
+	host_header: Option<String>,
+	timeout: Duration,
+}
+
+impl FrappeClient {
+
+	/// A client for the web server of the configuration's site.
+	pub fn new(app_config: &AppConfig) -> Self {
+		// The daemon warns about ignored settings once, at startup.
+		let base_url: String = app_config.frappe_url("");
+		let timeout: Duration = Duration::from_secs(app_config.webserver_timeout_secs.unwrap_or(DEFAULT_WEBSERVER_TIMEOUT_SECS));
+		let agent: ureq::Agent = ureq::AgentBuilder::new()
+			.timeout_connect(timeout)
+			.timeout(timeout)
+			.build();
+		FrappeClient {
+			agent,
+			base_url,
+			token: app_config.webserver_token.clone(),
+			host_header: app_config.webserver_host_header.clone(),
+			timeout
+		}
+	}
+
+	/// The full URL for a path such as "/api/method/btu.btu_api.endpoints.test_ping".
+	pub fn url(&self, path: &str) -> String {
+		format!("{}{}", self.base_url, path)
+	}
+
+	pub fn timeout(&self) -> Duration {
+		self.timeout
+	}
+
+	/// GET a path, and parse the response body as JSON.  Any HTTP status other than 2xx is an error.
+	pub fn get_json(&self, path: &str) -> Result<SerdeJsonValue, FrappeHttpError> {
+		self.send("GET", path, "application/json", None)?.require_success()?.into_json()
+	}
+
+	/// POST a JSON body to a path, and parse the response body as JSON.  Any HTTP status other than 2xx is an error.
+	pub fn post_json(&self, path: &str, body: &SerdeJsonValue) -> Result<SerdeJsonValue, FrappeHttpError> {
+		self.send("POST", path, "application/json", Some(body))?.require_success()?.into_json()
+	}
+
+	/// GET a path as raw bytes.  The body must match the response's 'content-length' header.
+	pub fn get_bytes(&self, path: &str) -> Result<Vec<u8>, FrappeHttpError> {
+		let response: FrappeResponse = self.send("GET", path, "application/octet-stream", None)?.require_success()?;
+		let expected: usize = response.content_length.ok_or_else(|| FrappeHttpError::InvalidBody {
+			url: response.url.clone(),
+			message: "missing a valid 'content-length' header".to_owned()
+		})?;
+		if response.body.len() != expected {
+			return Err(FrappeHttpError::InvalidBody {
+				url: response.url,
+				message: format!("expected {} bytes, but received {}", expected, response.body.len())
+			});
+		}
+		Ok(response.body)
+	}
+
+	/// Send a request with an optional JSON body, and read the whole response; whatever its HTTP status, because
+	/// Frappe describes its exceptions in the bodies of 4xx and 5xx responses.
+	pub fn send(&self, method: &str, path: &str, content_type: &str, body: Option<&SerdeJsonValue>) -> Result<FrappeResponse, FrappeHttpError> {
+		let url: String = self.url(path);
+		let mut request: ureq::Request = self.agent.request(method, &url)
+			.set("Authorization", &self.token)
+			.set("Content-Type", content_type);
+		// If Frappe is running via gunicorn, in DNS Multi-tenancy mode, then we have to pass a "Host" header.
+		if let Some(host_header) = &self.host_header {
+			request = request.set("Host", host_header);
+		}
+
+		let result: Result<ureq::Response, ureq::Error> = match body {
+			Some(body) => request.send_json(body),
+			None => request.call()
+		};
+		let response: ureq::Response = match result {
+			Ok(response) => response,
+			Err(ureq::Error::Status(_code, response)) => response,
+			Err(ureq::Error::Transport(transport)) => {
+				if is_timeout(&transport) {
+					return Err(FrappeHttpError::Timeout { url, secs: self.timeout.as_secs() });
+				}
+				return Err(FrappeHttpError::Transport { url, message: transport.to_string() });
+			}
+		};
+
+		let status: u16 = response.status();
+		let content_length: Option<usize> = response.header("content-length").and_then(|value| value.parse::<usize>().ok());
+		let mut bytes: Vec<u8> = Vec::with_capacity(content_length.unwrap_or_default().min(MAX_BODY_BYTES as usize));
+		if let Err(error) = response.into_reader().take(MAX_BODY_BYTES).read_to_end(&mut bytes) {
+			if matches!(error.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) {
+				return Err(FrappeHttpError::Timeout { url, secs: self.timeout.as_secs() });
+			}
+			return Err(FrappeHttpError::InvalidBody { url, message: format!("cannot read the body ({})", error) });
+		}
+		Ok(FrappeResponse { url, status, content_length, body: bytes })
+	}
+}
+
+/// Did the request fail because the web server was too slow?  ureq reports this as an I/O error, somewhere in the chain.
+fn is_timeout(transport: &ureq::Transport) -> bool {
+	let mut source: Option<&(dyn std::error::Error + 'static)> = std::error::Error::source(transport);
+	while let Some(error) = source {
+		if let Some(io_error) = error.downcast_ref::<std::io::Error>() {
+			if matches!(io_error.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) {
+				return true;
+			}
+		}
+		source = error.source();
+	}
+	false
+}
@@ -0,0 +1,132 @@
+// ids.rs
+
+/*
+	Typed identifiers.
+
+	A BTU Task ID, a BTU Task Schedule ID, and an RQ Job ID are all strings.  Wrapping each in its own type means
+	passing one where another is expected fails to compile, instead of failing at runtime with a confusing SQL lookup.
+
+	Values read from MySQL or Redis are converted with From, unchecked.  Values typed by a person (CLI arguments,
+	socket requests) should go through new(), which rejects empty and over-long identifiers.
+*/
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::errors::IdentifierError;
+
+/// Frappe's 'name' columns are varchar(140).  Nothing longer can be the primary key of a document.
+pub const MAX_IDENTIFIER_LENGTH: usize = 140;
+
+macro_rules! typed_identifier {
+	($(#[$attribute:meta])* $name:ident, $kind:literal) => {
+
+		$(#[$attribute])*
+		#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+		#[serde(transparent)]
+		pub struct $name(String);
+
+		impl $name {
+
+			/// Build from user input: leading and trailing whitespace is removed, and the result must be non-empty and at most
+			/// MAX_IDENTIFIER_LENGTH characters.
+			pub fn new(value: &str) -> Result<Self, IdentifierError> {
+				let trimmed: &str = value.trim();
+				let length: usize = trimmed.chars().count();
+				if length == 0 {
+					return Err(IdentifierError::Empty { kind: $kind });
+				}
+				if length > MAX_IDENTIFIER_LENGTH {
+					return Err(IdentifierError::TooLong { kind: $kind, length, max_length: MAX_IDENTIFIER_LENGTH });
+				}
+				Ok($name(trimmed.to_owned()))
+			}
+
+			pub fn as_str(&self) -> &str {
+				&self.0
+			}
+
+			pub fn into_string(self) -> String {
+				self.0
+			}
+		}
+
+		impl fmt::Display for $name {
+			fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				write!(f, "{}", self.0)
+			}
+		}
+
+		// Hash and Eq both come from the inner String, so a HashSet of identifiers can be searched with a &str.
+		impl std::borrow::Borrow<str> for $name {
+			fn borrow(&self) -> &str {
+				&self.0
+			}
+		}
+
+		impl AsRef<str> for $name {
+			fn as_ref(&self) -> &str {
+				&self.0
+			}
+		}
+
+		impl From<String> for $name {
+			fn from(value: String) -> Self {
+				$name(value)
+			}
+		}
+
+		impl From<&str> for $name {
+			fn from(value: &str) -> Self {
+				$name(value.to_owned())
+			}
+		}
+
+		impl PartialEq<str> for $name {
+			fn eq(&self, other: &str) -> bool {
+				self.0 == other
+			}
+		}
+
+		impl PartialEq<&str> for $name {
+			fn eq(&self, other: &&str) -> bool {
+				self.0 == *other
+			}
+		}
+	};
+}
+
+typed_identifier!(
+	/// The primary key ('name') of a BTU Task.
+	TaskId, "BTU Task ID"
+);
+
+typed_identifier!(
+	/// The primary key ('name') of a BTU Task Schedule.  On a multi-site bench, the scheduler's internal IDs are also
+	/// qualified by the site's name (see config::qualify_schedule_id).
+	TaskScheduleId, "BTU Task Schedule ID"
+);
+
+typed_identifier!(
+	/// An RQ Job's UUID, without the 'rq:job:' prefix of its Redis key.
+	RqJobId, "RQ Job ID"
+);
+
+typed_identifier!(
+	/// Ties one socket request to the log lines, scheduler events, and RQ Job that follow from it.  Chosen by the client,
+	/// or generated by the daemon when the client sends none.
+	CorrelationId, "correlation ID"
+);
+
+impl CorrelationId {
+
+	/// A new random ID: the first 12 hexadecimal digits of a UUID.
+	pub fn generate() -> Self {
+		CorrelationId(Uuid::new_v4().to_simple().to_string()[..12].to_owned())
+	}
+
+	/// The client's ID when it is valid (see new()); otherwise a generated one.
+	pub fn from_client(value: Option<&str>) -> Self {
+		value.and_then(|value| CorrelationId::new(value).ok()).unwrap_or_else(CorrelationId::generate)
+	}
+}
@@ -0,0 +1,123 @@
+// janitor.rs
+
+/*
+	When BTU saves an RQ Job, but the enqueue fails (or no worker ever existed), the 'rq:job:*' hash lingers forever
+	with no status.  Python RQ's own TTLs do not apply to jobs that were never enqueued.
+
+	This module finds those abandoned jobs, and (unless running in dry-run mode) deletes them.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Duration, Utc};
+use redis::{Commands, RedisError};
+use tracing::{debug, info, warn};
+
+use crate::config::AppConfig;
+use crate::events::{self, EventKind, SchedulerEvent};
+use crate::rq;
+
+/// Default for 'stale_job_max_age_hours'.
+pub const DEFAULT_STALE_JOB_MAX_AGE_HOURS: u32 = 48;
+
+/// Total number of stale RQ Jobs deleted since the process started.
+pub static STALE_JOBS_DELETED_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// What one cleanup run found, and did.
+#[derive(Debug, Default)]
+pub struct StaleJobReport {
+	pub jobs_scanned: usize,
+	pub stale_job_keys: Vec<String>,
+	pub jobs_deleted: usize,
+	pub dry_run: bool,
+}
+
+/**
+  Is this RQ Job hash an abandoned BTU job?  All of the following must be true:
+
+  * Its description matches a BTU Task's description (so jobs created by other applications are never touched).
+  * It has no status, or status 'queued'.  A worker never started it.
+  * It was created more than 'max_age' before 'now'.
+*/
+pub fn is_stale_btu_job(job_hash: &HashMap<String, Vec<u8>>,
+                        btu_task_descriptions: &HashSet<String>,
+                        now: DateTime<Utc>,
+                        max_age: Duration) -> bool {
+
+	let Some(description) = rq::hashmap_value_to_optstring(job_hash, "description") else {
+		return false;
+	};
+	if !btu_task_descriptions.contains(&description) {
+		return false;
+	}
+	let status: String = rq::hashmap_value_to_optstring(job_hash, "status").unwrap_or_default();
+	if !(status.is_empty() || status == "queued") {
+		return false;
+	}
+	match rq::hashmap_value_to_utcdatetime(job_hash, "created_at") {
+		Some(created_at) => now - created_at > max_age,
+		None => false  // Without a creation time, there's no way to know its age.  Leave it alone.
+	}
+}
+
+/// Scan every RQ Job, and delete the abandoned BTU jobs.  With 'stale_job_cleanup_dry_run' (the default), only report them.
+#[cfg(feature = "sql")]
+pub fn remove_stale_jobs(app_config: &AppConfig) -> Result<StaleJobReport, std::io::Error> {
+
+	let btu_task_descriptions: HashSet<String> = crate::task::read_task_descriptions(app_config)
+		.map_err(|error| std::io::Error::new(std::io::ErrorKind::NotConnected, format!("Unable to read BTU Tasks from MariaDB: {}", error)))?;
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Unable to establish connection to Redis."));
+	};
+	let redis_error = |error: RedisError| std::io::Error::new(std::io::ErrorKind::Other, format!("Redis error while removing stale jobs: {}", error));
+
+	let max_age = Duration::hours(app_config.stale_job_max_age_hours.unwrap_or(DEFAULT_STALE_JOB_MAX_AGE_HOURS).into());
+	let now: DateTime<Utc> = Utc::now();
+	let mut report = StaleJobReport {
+		dry_run: app_config.stale_job_cleanup_dry_run.unwrap_or(true),
+		..Default::default()
+	};
+
+	for job_key in rq::scan_job_keys(&mut redis_conn).map_err(redis_error)? {
+		report.jobs_scanned += 1;
+		let job_hash: HashMap<String, Vec<u8>> = match redis_conn.hgetall(&job_key) {
+			Ok(value) => value,
+			Err(error) => {
+				debug!("Skipping '{}' during stale job cleanup: {}", job_key, error);
+				continue;
+			}
+		};
+		if is_stale_btu_job(&job_hash, &btu_task_descriptions, now, max_age) {
+			report.stale_job_keys.push(job_key);
+		}
+	}
+
+	if report.dry_run {
+		for job_key in &report.stale_job_keys {
+			info!("Dry run: would delete stale RQ Job '{}'.", job_key);
+		}
+	}
+	else if !report.stale_job_keys.is_empty() {
+		let deleted: usize = redis_conn.del(&report.stale_job_keys).map_err(redis_error)?;
+		report.jobs_deleted = deleted;
+		STALE_JOBS_DELETED_TOTAL.fetch_add(deleted as u64, Ordering::Relaxed);
+		for job_id in report.stale_job_keys.iter().filter_map(|job_key| rq::job_id_from_key(job_key)) {
+			events::record_event(app_config, &mut redis_conn, SchedulerEvent::new(EventKind::Pruned).job(&job_id)
+				.reason(format!("never started by a worker, and older than {} hours", max_age.num_hours())));
+		}
+	}
+
+	if report.stale_job_keys.is_empty() {
+		debug!("Stale job cleanup: scanned {} RQ Jobs; none are stale.", report.jobs_scanned);
+	}
+	else if report.dry_run {
+		warn!("Stale job cleanup (dry run): scanned {} RQ Jobs; {} are stale and would be deleted.",
+		      report.jobs_scanned, report.stale_job_keys.len());
+	}
+	else {
+		info!("Stale job cleanup: scanned {} RQ Jobs; deleted {}.  Total deleted since startup: {}",
+		      report.jobs_scanned, report.jobs_deleted, STALE_JOBS_DELETED_TOTAL.load(Ordering::Relaxed));
+	}
+	Ok(report)
+}
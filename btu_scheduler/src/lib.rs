@@ -4,17 +4,44 @@
 
 // Main library 'btu_scheduler'
 // These modules are located in adjacent files.
+use std::io::Read;
+#[cfg(feature = "sql")]
 use mysql::PooledConn;
+#[cfg(feature = "sql")]
 use mysql::prelude::Queryable;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 // The following declarations are critical, and determine what Modules are part of this crate.
 pub mod btu_cron;
+pub mod circuit_breaker;
+pub mod concurrency;
 pub mod config;
+pub mod config_check;
+pub mod dependency;
+pub mod diagnose;
+pub mod enqueue_check;
 pub mod errors;
+pub mod events;
+pub mod frappe_client;
+pub mod ids;
+pub mod janitor;
 pub mod logging;
+pub mod metrics;
+pub mod next_run_override;
+pub mod pickle;
+pub mod redis_keys;
+pub mod platform;
+pub mod queue_stats;
+pub mod repeat_count;
 pub mod rq;
+pub mod run_history;
+pub mod runtime;
+pub mod schedule_queue;
 pub mod scheduler;
+pub mod socket_frame;
+pub mod timeutil;
+pub mod watchdog;
 
 // To use this feature, build like this:  `cargo build --features email-feat`
 #[cfg(feature = "email-feat")]
@@ -22,11 +49,105 @@ pub mod email;
 
 mod tests;
 use crate::config::AppConfig;
+use crate::errors::{FrappeApiError, SmokeTestError};
+use crate::ids::{RqJobId, TaskId, TaskScheduleId};
 
-// This is the response from an HTTP call to Frappe REST API.
+// This is the response from an HTTP call to Frappe REST API.  When the endpoint raises, Frappe replies with the
+// exception's details instead of a 'message'; sometimes with HTTP 200, depending on its configuration.
 #[derive(Deserialize, Debug)]
-struct FrappeApiMessage {
-	message: Vec<u8>
+#[serde(untagged)]
+enum FrappeApiMessage {
+	Success {
+		message: Vec<u8>
+	},
+	Error {
+		exc_type: Option<String>,
+		exception: Option<String>,
+		#[serde(rename = "_server_messages")]
+		server_messages: Option<String>
+	}
+}
+
+/// Frappe's '_server_messages' is a JSON list of JSON-encoded objects.  Returns the first one's 'message'.
+fn first_server_message(server_messages: &str) -> Option<String> {
+	let encoded: Vec<String> = serde_json::from_str(server_messages).ok()?;
+	let first: serde_json::Value = serde_json::from_str(encoded.first()?).ok()?;
+	first.get("message")?.as_str().map(str::to_owned)
+}
+
+/// Interpret the body of a response from Frappe's 'get_pickled_task' endpoint, whatever its HTTP status.
+fn parse_frappe_api_response(task_id: &str, status: u16, body: &[u8]) -> Result<Vec<u8>, FrappeApiError> {
+	let unexpected = |detail: String| FrappeApiError::UnexpectedResponse { task_id: task_id.to_owned(), status, detail };
+	let parsed: FrappeApiMessage = serde_json::from_slice(body)
+		.map_err(|error| unexpected(format!("the body is not JSON ({})", error)))?;
+	match parsed {
+		FrappeApiMessage::Success { message } if (200..300).contains(&status) => Ok(message),
+		FrappeApiMessage::Success { .. } => Err(unexpected("a pickled function, but with an error status".to_owned())),
+		FrappeApiMessage::Error { exc_type: None, exception: None, server_messages } => {
+			let detail: Option<String> = server_messages.as_deref().and_then(first_server_message);
+			Err(unexpected(detail.unwrap_or_else(|| "no 'message', and no exception details".to_owned())))
+		}
+		FrappeApiMessage::Error { exc_type, exception, .. } => {
+			Err(FrappeApiError::Exception {
+				task_id: task_id.to_owned(),
+				status,
+				exc_type: exc_type.unwrap_or_else(|| "an exception".to_owned()),
+				exception: exception.as_deref().and_then(|text| text.lines().next()).unwrap_or("").to_owned(),
+			})
+		}
+	}
+}
+
+/// Frappe stores checkboxes as integers (0 or 1), but other integrations have sent "1", "true", and so on.
+/// This newtype converts any of those into a real boolean, at the boundary where data enters the BTU.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrappeBool(pub bool);
+
+impl std::str::FromStr for FrappeBool {
+	type Err = crate::errors::FrappeBoolError;
+
+	fn from_str(any_string: &str) -> Result<Self, Self::Err> {
+		match any_string.trim().to_lowercase().as_str() {
+			"1" | "true" => Ok(FrappeBool(true)),
+			"0" | "false" => Ok(FrappeBool(false)),
+			_ => Err(crate::errors::FrappeBoolError::InvalidValue { found: any_string.to_owned() })
+		}
+	}
+}
+
+#[cfg(feature = "sql")]
+impl TryFrom<&mysql::Value> for FrappeBool {
+	type Error = crate::errors::FrappeBoolError;
+
+	fn try_from(sql_value: &mysql::Value) -> Result<Self, Self::Error> {
+		match sql_value {
+			mysql::Value::NULL => Err(crate::errors::FrappeBoolError::Null),
+			mysql::Value::Int(0) => Ok(FrappeBool(false)),
+			mysql::Value::Int(1) => Ok(FrappeBool(true)),
+			mysql::Value::UInt(0) => Ok(FrappeBool(false)),
+			mysql::Value::UInt(1) => Ok(FrappeBool(true)),
+			mysql::Value::Bytes(bytes) => String::from_utf8_lossy(bytes).parse(),
+			other => Err(crate::errors::FrappeBoolError::InvalidValue { found: format!("{:?}", other) })
+		}
+	}
+}
+
+#[cfg(feature = "sql")]
+impl FrappeBool {
+	/// Convert an SQL column to a boolean.  Anything that is not clearly true (including NULL) becomes false, with a warning.
+	pub fn from_sql_or_false(sql_value: Option<&mysql::Value>, description: &str) -> bool {
+		match sql_value.map(FrappeBool::try_from) {
+			Some(Ok(FrappeBool(value))) => value,
+			Some(Err(error)) => {
+				warn!("Treating {} as false: {}", description, error);
+				false
+			},
+			None => {
+				warn!("Treating {} as false: the SQL column is missing.", description);
+				false
+			}
+		}
+	}
 }
 
 pub fn get_package_version() -> &'static str {
@@ -35,34 +156,173 @@ pub fn get_package_version() -> &'static str {
     VERSION
 }
 
+/// A 64-bit FNV-1a hash.  Unlike Rust's DefaultHasher, the output is guaranteed never to change between Rust releases.
+pub fn stable_hash(text: &str) -> u64 {
+	let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+	for byte in text.bytes() {
+		hash ^= u64::from(byte);
+		hash = hash.wrapping_mul(0x0100_0000_01b3);
+	}
+	hash
+}
+
 
 pub mod task {
 	
 	use std::fmt;
+	#[cfg(feature = "sql")]
 	use mysql::prelude::Queryable;
+	#[cfg(feature = "sql")]
 	use mysql::PooledConn;
 	use tracing::{trace, debug, info, warn, error, span, Level};
 	use crate::config::{self, AppConfig};
+	use crate::errors::{FrappeApiError, PolicyError};
+	use crate::ids::TaskId;
+	use crate::metrics::{timed_query, QueryKind};
 	use crate::rq::RQJob;
 	
+	/// One BTU Task, by name.  The name is always a bound parameter; Frappe names may contain quotes and backslashes.
+	pub static TASK_BY_NAME_SQL: &str = "SELECT name AS task_key, desc_short, desc_long,
+		arguments, function_string AS path_to_function, max_task_duration, docstatus, is_transient
+		FROM `tabBTU Task` WHERE name = ? LIMIT 1";
+
+	#[derive(Clone, Debug)]
 	pub struct BtuTask {
-		pub task_key: String,
+		pub task_key: TaskId,
 		desc_short: String,
 		desc_long: String,
 		arguments: Option<String>,
 		path_to_function: String,	// example:  btu.manual_tests.ping_with_wait
 		pub max_task_duration: u32,  // example:  600
+		pub status: TaskStatus,
+	}
+
+	/// The state of a BTU Task in Frappe, from its 'docstatus' and 'is_transient' columns.
+	#[derive(Clone, Copy, Debug, PartialEq)]
+	pub enum TaskStatus {
+		Draft,        // docstatus 0
+		Submitted,    // docstatus 1
+		Cancelled,    // docstatus 2
+		Transient,    // submitted, but meant for one-off runs
+		Unknown(i64), // any other docstatus
+	}
+
+	impl TaskStatus {
+		pub fn from_sql(docstatus: i64, is_transient: bool) -> Self {
+			match (docstatus, is_transient) {
+				(2, _) => TaskStatus::Cancelled,
+				(0, _) => TaskStatus::Draft,
+				(1, true) => TaskStatus::Transient,
+				(1, false) => TaskStatus::Submitted,
+				(other, _) => TaskStatus::Unknown(other),
+			}
+		}
+
+		/// Why a Task with this status must not be sent to RQ; or None if it may be.
+		pub fn rejection_reason(&self) -> Option<&'static str> {
+			match self {
+				TaskStatus::Cancelled => Some("task is cancelled in Frappe"),
+				_ => None
+			}
+		}
+
+		/// A concern worth logging, for a Task that may still be sent to RQ.
+		pub fn warning(&self) -> Option<&'static str> {
+			match self {
+				TaskStatus::Draft => Some("task is a draft in Frappe (not submitted)"),
+				TaskStatus::Transient => Some("task is transient in Frappe (intended for one-off runs)"),
+				TaskStatus::Unknown(_) => Some("task has an unrecognized 'docstatus' in Frappe"),
+				_ => None
+			}
+		}
+	}
+
+	impl fmt::Display for TaskStatus {
+		fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+			match self {
+				TaskStatus::Draft => write!(f, "Draft"),
+				TaskStatus::Submitted => write!(f, "Submitted"),
+				TaskStatus::Cancelled => write!(f, "Cancelled"),
+				TaskStatus::Transient => write!(f, "Transient"),
+				TaskStatus::Unknown(docstatus) => write!(f, "Unknown (docstatus {})", docstatus),
+			}
+		}
+	}
+
+	#[cfg(feature = "sql")]
+	/// Map the 'docstatus' and 'is_transient' columns (at the given indexes) of a SQL row.
+	fn task_status_from_row(row: &mysql::Row, docstatus_index: usize, is_transient_index: usize) -> TaskStatus {
+		let docstatus: i64 = row.get_opt(docstatus_index).unwrap_or(Ok(0)).unwrap_or(0);
+		let is_transient: bool = crate::FrappeBool::from_sql_or_false(row.as_ref(is_transient_index), "column 'is_transient' of BTU Task");
+		TaskStatus::from_sql(docstatus, is_transient)
+	}
+
+	/// What must be known about a BTU Task before it is sent to RQ.
+	#[derive(Clone, Debug, PartialEq)]
+	pub struct TaskPreflight {
+		pub status: TaskStatus,
+		pub path_to_function: String,
+	}
+
+	#[cfg(feature = "sql")]
+	/// The status and Python function of one BTU Task; or None if the Task does not exist.
+	pub fn read_task_preflight(app_config: &AppConfig, task_key: &TaskId) -> Result<Option<TaskPreflight>, mysql::Error> {
+
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config)?;
+		let query_syntax = "SELECT docstatus, is_transient, function_string FROM `tabBTU Task` WHERE name = ?";
+		let row: Option<mysql::Row> = timed_query(QueryKind::Task, app_config.slow_query_threshold(), || {
+			sql_conn.exec_first(query_syntax, (task_key.as_str(),))
+		})?;
+		Ok(row.map(|row| TaskPreflight {
+			status: task_status_from_row(&row, 0, 1),
+			path_to_function: row.get_opt(2).unwrap_or(Ok("".to_owned())).unwrap_or("".to_owned()),
+		}))
+	}
+
+	#[cfg(feature = "sql")]
+	/// The most instances of a BTU Task that may run at once ('max_concurrent'), across every Task Schedule and queue.
+	/// None when there is no limit: the column is empty or zero, or missing from an older BTU App.
+	pub fn read_task_max_concurrent(app_config: &AppConfig, task_key: &TaskId) -> Option<u32> {
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config).ok()?;
+		let query_syntax = "SELECT max_concurrent FROM `tabBTU Task` WHERE name = ?";
+		let result: Result<Option<Option<u32>>, mysql::Error> = timed_query(QueryKind::Task, app_config.slow_query_threshold(), || {
+			sql_conn.exec_first(query_syntax, (task_key.as_str(),))
+		});
+		match result {
+			Ok(max_concurrent) => max_concurrent.flatten().filter(|max_concurrent| *max_concurrent > 0),
+			Err(error) => {
+				debug!("Unable to read 'max_concurrent' of BTU Task {}; assuming no limit: {}", task_key, error);
+				None
+			}
+		}
+	}
+
+	/// Enforce the 'allowed_function_prefixes' policy for a Task's Python function.
+	pub fn check_function_policy(app_config: &AppConfig, task_key: &str, path_to_function: &str) -> Result<(), PolicyError> {
+		if app_config.is_function_allowed(path_to_function) {
+			return Ok(());
+		}
+		Err(PolicyError::FunctionNotAllowed { task_key: task_key.to_owned(), path_to_function: path_to_function.to_owned() })
 	}
 
 	// TODO: Need to resolve SQL injection possibility.  Probably means crabbing some more Crates.
 	impl BtuTask {
 
-		pub fn new_from_mysql(task_key: &str, app_config: &AppConfig) -> Self {
-			let mut sql_conn: PooledConn = config::get_mysql_conn(app_config).unwrap();
+		/// Whether this Task's Python function passes the 'allowed_function_prefixes' policy.
+		pub fn check_function_policy(&self, app_config: &AppConfig) -> Result<(), PolicyError> {
+			check_function_policy(app_config, self.task_key.as_str(), &self.path_to_function)
+		}
 
-			let query_syntax = format!("SELECT name AS task_key, desc_short, desc_long,
-			arguments, function_string AS path_to_function,	max_task_duration 
-			FROM `tabBTU Task` WHERE name = '{}' LIMIT 1;", task_key);
+		#[cfg(feature = "sql")]
+		pub fn new_from_mysql(task_key: &TaskId, app_config: &AppConfig) -> Self {
+			Self::try_new_from_mysql(task_key, app_config).unwrap_or_else(|error| panic!("{}", error))
+		}
+
+		#[cfg(feature = "sql")]
+		/// Read a BTU Task from SQL.  An error of kind NotFound when no Task has this ID.
+		pub fn try_new_from_mysql(task_key: &TaskId, app_config: &AppConfig) -> Result<Self, std::io::Error> {
+			let sql_error = |error: mysql::Error| std::io::Error::new(std::io::ErrorKind::Other, format!("Unable to read BTU Task '{}' from SQL: {}", task_key, error));
+			let mut sql_conn: PooledConn = config::get_mysql_conn(app_config).map_err(sql_error)?;
 
 			// OPTION 1: Working 1 row at a time.
 			/*
@@ -92,37 +352,53 @@ pub mod task {
 				Option 2:  Using a map.
 				NOTE: The use of 'get_opt()' is necessary to handle SQL rows containing NULLs, instead of the expected datatype.
 			*/
-			let task: BtuTask = sql_conn.query_first(query_syntax).unwrap().map(|row: mysql::Row| {
+			let row: Option<mysql::Row> = timed_query(QueryKind::Task, app_config.slow_query_threshold(), || {
+				sql_conn.exec_first(TASK_BY_NAME_SQL, (task_key.as_str(),))
+			}).map_err(sql_error)?;
+			let task: BtuTask = row.map(|row: mysql::Row| {
 					BtuTask {
-						task_key: row.get(0).unwrap(),
+						task_key: TaskId::from(row.get::<String, _>(0).unwrap()),
 						desc_short: row.get_opt(1).unwrap_or(Ok("".to_owned())).unwrap_or("".to_owned()),
 						desc_long: row.get_opt(2).unwrap_or(Ok("".to_owned())).unwrap_or("".to_owned()),
 						arguments: row.get_opt(3).unwrap_or(Ok(None)).unwrap_or(None),
 						path_to_function:  row.get(4).unwrap_or("".to_owned()),
 						max_task_duration: row.get_opt(5).unwrap_or(Ok(600)).unwrap_or(600),
+						status: task_status_from_row(&row, 6, 7),
 					}
-				}).unwrap();
+				}).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("There is no BTU Task '{}'.", task_key)))?;
 			info!("{}", task);
-			task
+			Ok(task)
 		}
 
 		/// Create an RQ Job struct from a BTU Task Schedule struct.
-		pub fn to_rq_job(&self, app_config: &AppConfig) -> RQJob {
+		pub fn to_rq_job(&self, app_config: &AppConfig) -> Result<RQJob, FrappeApiError> {
 
 			let mut new_job: RQJob = RQJob::new_with_defaults();
 			new_job.description = self.desc_short.clone();
-			match crate::get_pickled_function_from_web(&self.task_key, None, app_config) {
-				Ok(byte_result) => {
-					new_job.data = byte_result;
-				}
-				Err(error_message) => {
-					panic!("Error while requesting pickled Python function:\n{}", error_message);
-				}
-			}
+			new_job.data = crate::get_pickled_function_from_web(&self.task_key, None, None, app_config)?;
 			new_job.timeout = self.max_task_duration;
-			new_job
+			Ok(new_job)
 		}
 
+		/// Short description of the Task, as entered in Frappe.
+		pub fn description_short(&self) -> &str {
+			&self.desc_short
+		}
+
+		/// Long description of the Task, as entered in Frappe.
+		pub fn description_long(&self) -> &str {
+			&self.desc_long
+		}
+
+		/// Optional arguments passed to the Python function, if any.
+		pub fn arguments(&self) -> Option<&str> {
+			self.arguments.as_deref()
+		}
+
+		/// Dotted path to the Python function (example:  btu.manual_tests.ping_with_wait)
+		pub fn path_to_function(&self) -> &str {
+			&self.path_to_function
+		}
 
 	}
 
@@ -134,52 +410,109 @@ pub mod task {
 						desc_long: {}\n\
 						arguments: {:?}\n\
 						path_to_function: {}\n\
-						max_task_duration: {}",
+						max_task_duration: {}\n\
+						status: {}",
 				self.task_key, self.desc_short,
-				self.desc_long, self.arguments, self.path_to_function, self.max_task_duration
+				self.desc_long, self.arguments, self.path_to_function, self.max_task_duration, self.status
 			)
 		}
 	}
 
-	pub fn print_enabled_tasks(app_config: &AppConfig, to_stdout: bool) -> () {
+	#[cfg(feature = "sql")]
+	/// Read every submitted, non-transient BTU Task from the MariaDB database.
+	pub fn read_enabled_tasks(app_config: &AppConfig) -> Result<Vec<BtuTask>, mysql::Error> {
+
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config)?;
+		let query_syntax = "SELECT name AS task_key, desc_short, desc_long,
+			arguments, function_string AS path_to_function, max_task_duration, docstatus, is_transient
+			FROM `tabBTU Task` WHERE docstatus = 1 AND is_transient = 0 ORDER BY name";
+
+		timed_query(QueryKind::Task, app_config.slow_query_threshold(), || {
+			sql_conn.query_map(query_syntax, |row: mysql::Row| {
+				BtuTask {
+					task_key: TaskId::from(row.get::<String, _>(0).unwrap()),
+					desc_short: row.get_opt(1).unwrap_or(Ok("".to_owned())).unwrap_or("".to_owned()),
+					desc_long: row.get_opt(2).unwrap_or(Ok("".to_owned())).unwrap_or("".to_owned()),
+					arguments: row.get_opt(3).unwrap_or(Ok(None)).unwrap_or(None),
+					path_to_function:  row.get(4).unwrap_or("".to_owned()),
+					max_task_duration: row.get_opt(5).unwrap_or(Ok(600)).unwrap_or(600),
+					status: task_status_from_row(&row, 6, 7),
+				}
+			})
+		})
+	}
 
-		let mut sql_conn: PooledConn;
-		match config::get_mysql_conn(app_config) {
-			Ok(_conn) => {
-				sql_conn = _conn;
-			},
-			Err(err) => {
-				error!("Error while attempting to get connection in 'query_task_summary' : {}", err);
-				return ()
-			}
-		}
+	#[cfg(feature = "sql")]
+	/// The short description of every BTU Task.  RQ Jobs created by BTU use these as their 'description'.
+	pub fn read_task_descriptions(app_config: &AppConfig) -> Result<std::collections::HashSet<String>, mysql::Error> {
 
-		let query_syntax = "SELECT name, desc_short	FROM `tabBTU Task` WHERE docstatus = 1 AND is_transient = 0";
-		let task_vector: Vec<(String,String)> = sql_conn.query_map(query_syntax, |row: mysql::Row| {
-			(row.get(0).unwrap(), row.get(1).unwrap())
-		}).unwrap();
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config)?;
+		let descriptions: Vec<Option<String>> = sql_conn.query("SELECT DISTINCT desc_short FROM `tabBTU Task`")?;
+		Ok(descriptions.into_iter().flatten().filter(|description| !description.is_empty()).collect())
+	}
 
-		// TODO: Create a new macro that combines info! and println!, or warn! and println, etc.
-		// Something like echo!(level, message, to_stdout) ?
-		if task_vector.len() > 0 {
-			for task in task_vector {
-				if to_stdout {
-					println!("Task {} : {}", task.0, task.1);
-				}
-				else {
-					info!("Task {} : {}", task.0, task.1);
-				}
-			}
+	/// One enabled BTU Task, as listed by 'btu list-tasks'.
+	#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+	pub struct TaskSummary {
+		pub task_key: TaskId,
+		pub desc_short: String,
+	}
+
+	/// Which enabled Tasks to list.  Tasks are always ordered by name, so pages are stable.
+	#[derive(Clone, Debug, Default, PartialEq)]
+	pub struct TaskListOptions {
+		pub limit: Option<usize>,
+		pub offset: usize,
+		pub filter: Option<String>,  // a substring of the Task's name or short description
+	}
+
+	/// A page of enabled Tasks, with the number of Tasks matching the filter (on every page).
+	#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
+	pub struct TaskPage {
+		pub tasks: Vec<TaskSummary>,
+		pub total: usize,
+	}
+
+	impl crate::metrics::RowCount for TaskPage {
+		fn row_count(&self) -> usize {
+			self.tasks.len()
 		}
-		else {
-			if to_stdout { 
-				println!("No BTU Tasks are defined in the MariaDB database.");
-			}
-			else {
-				warn!("No BTU Tasks are defined in the MariaDB database.");
-			}
+	}
 
+	/// The SQL for one page of enabled Tasks, the SQL counting every match, and their shared parameters.
+	/// The filter is always passed as a parameter; 'limit' and 'offset' are numbers, and are written into the SQL.
+	pub fn task_list_queries(options: &TaskListOptions) -> (String, String, Vec<String>) {
+		let mut where_clause: String = "docstatus = 1 AND is_transient = 0".to_owned();
+		let mut parameters: Vec<String> = Vec::new();
+		if let Some(filter) = options.filter.as_deref().filter(|filter| !filter.is_empty()) {
+			where_clause.push_str(" AND (name LIKE ? OR desc_short LIKE ?)");
+			let pattern: String = format!("%{}%", config::escape_like(filter));
+			parameters = vec![pattern.clone(), pattern];
 		}
+		let mut page_query: String = format!("SELECT name, IFNULL(desc_short, '') FROM `tabBTU Task` WHERE {} ORDER BY name", where_clause);
+		match (options.limit, options.offset) {
+			(Some(limit), 0) => page_query.push_str(&format!(" LIMIT {}", limit)),
+			(Some(limit), offset) => page_query.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset)),
+			(None, 0) => {},
+			(None, offset) => page_query.push_str(&format!(" LIMIT 18446744073709551615 OFFSET {}", offset)),  // MariaDB requires a LIMIT with OFFSET
+		}
+		let count_query: String = format!("SELECT COUNT(*) FROM `tabBTU Task` WHERE {}", where_clause);
+		(page_query, count_query, parameters)
+	}
+
+	#[cfg(feature = "sql")]
+	/// One page of enabled (submitted, non-transient) BTU Tasks, ordered by name.
+	pub fn list_enabled_tasks(app_config: &AppConfig, options: &TaskListOptions) -> Result<TaskPage, mysql::Error> {
+
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config)?;
+		let (page_query, count_query, parameters) = task_list_queries(options);
+		timed_query(QueryKind::Task, app_config.slow_query_threshold(), || {
+			let tasks: Vec<TaskSummary> = sql_conn.exec_map(page_query.as_str(), parameters.clone(), |(name, desc_short): (String, String)| {
+				TaskSummary { task_key: TaskId::from(name), desc_short }
+			})?;
+			let total: Option<u64> = sql_conn.exec_first(count_query.as_str(), parameters.clone())?;
+			Ok(TaskPage { tasks, total: total.unwrap_or_default() as usize })
+		})
 	}
 }  // end of task module.
 
@@ -189,11 +522,16 @@ pub mod task_schedule {
 	use anyhow::anyhow as anyhow_macro;
 	use chrono::{DateTime, Utc};
 	use chrono_tz::Tz;
+	#[cfg(feature = "sql")]
 	use mysql::PooledConn;
+	#[cfg(feature = "sql")]
 	use mysql::prelude::Queryable;
 	use tracing::{trace, debug, info, warn, error, span, Level};
 	use crate::btu_cron;
 	use crate::config::{self, AppConfig};
+	use crate::errors::{CronError, SchedulerError};
+	use crate::ids::{TaskId, TaskScheduleId};
+	use crate::metrics::{timed_query, QueryKind};
 	use crate::rq::RQJob;
 	use crate::task::BtuTask;
 	use crate::scheduler::RQScheduledTask;
@@ -219,19 +557,24 @@ pub mod task_schedule {
 	// Deliberately excluding SQL columns that don't matter for this program.
 	#[derive(Debug, Clone)]
 	pub struct BtuTaskSchedule {
-		pub id: String,
-		task: String,
+		pub id: TaskScheduleId,
+		task: TaskId,
 		task_description: String,
-		pub enabled: u8,
+		pub enabled: bool,
 		queue_name: String,
 		redis_job_id: Option<String>,  // Using Option here, because it's quite possible for BTU App to create a schedule, but not populate this!
 		argument_overrides: Option<String>,  // MUST use Option here, if the result is at all Nullable.
 		schedule_description: String,
 		pub cron_string: String,
-		pub cron_timezone: chrono_tz::Tz
+		pub cron_timezone: chrono_tz::Tz,
+		pub skip_holidays: bool,  // when true, occurrences on holidays are skipped
+		pub repeat_interval: Option<String>,  // an alternative to cron, such as "PT2H" or "every 30 minutes"; used when 'cron_string' is empty
+		pub repeat: Option<u32>,  // run this many times, then stop; empty or 0 means forever
+		utc_only: bool  // read with 'cron_strings_are_utc'; 'cron_timezone' is then UTC, regardless of the stored time zone
 	}
 
 	impl BtuTaskSchedule {
+		#[cfg(feature = "sql")]
 		/**
 			Create a new BtuTask struct by reading from the MariaDB database.
 		*/
@@ -240,13 +583,50 @@ pub mod task_schedule {
 			task
 		}
 
+		/// The BTU Task this Task Schedule runs.
+		pub fn task(&self) -> &TaskId {
+			&self.task
+		}
+
+		/// The name of the RQ queue this Task Schedule's jobs are placed on.
+		pub fn queue_name(&self) -> &str {
+			&self.queue_name
+		}
+
+		/// The RQ queue this Task Schedule's jobs are actually placed on: 'queue_name', or 'default' when that is blank or invalid.
+		pub fn rq_queue_name(&self) -> &str {
+			crate::rq::queue_name_or_default(&self.queue_name, &format!("Task Schedule {}", self.id))
+		}
+
+		#[cfg(feature = "sql")]
 		/// Create an RQ Job struct from a BTU Task Schedule struct.
 		pub fn to_rq_job(&self, app_config: &AppConfig) -> Result<RQJob, anyhow::Error> {
 
 			let mut new_job: RQJob = RQJob::new_with_defaults();
 			new_job.description = self.task_description.clone();
+			new_job.set_origin(self.rq_queue_name());
+
+			// Check the Task's status first; a cancelled Task would otherwise fail later with a confusing error from Frappe.
+			// Then check the function policy, before the web server is asked for anything.
+			match crate::task::read_task_preflight(app_config, &self.task) {
+				Ok(Some(preflight)) => {
+					if let Some(reason) = preflight.status.rejection_reason() {
+						return Err(anyhow_macro!("Task Schedule {} cannot run BTU Task {}: {}", self.id, self.task, reason));
+					}
+					if let Some(warning) = preflight.status.warning() {
+						warn!("Task Schedule {} runs BTU Task {}, but the {}.", self.id, self.task, warning);
+					}
+					crate::task::check_function_policy(app_config, self.task.as_str(), &preflight.path_to_function)?;
+				}
+				Ok(None) => {
+					return Err(anyhow_macro!("Task Schedule {} cannot run BTU Task {}: task does not exist in Frappe", self.id, self.task));
+				}
+				Err(mysql_error) => {
+					return Err(anyhow_macro!("Error while reading the status of BTU Task {}: {}", self.task, mysql_error));
+				}
+			}
 
-			match crate::get_pickled_function_from_web(&self.task, Some(&self.id), app_config) {
+			match crate::get_pickled_function_from_web(&self.task, Some(&self.id), self.argument_overrides(), app_config) {
 				Ok(byte_result) => {
 					new_job.data = byte_result;
 				}
@@ -255,20 +635,59 @@ pub mod task_schedule {
 					return Err::<RQJob, anyhow::Error>(anyhow_macro!("Error while requesting pickled Python function:\n{}", error_message));
 				}
 			}
+			// Also visible when the job is inspected, such as with 'btu show-job'.
+			if let Some(argument_overrides) = self.argument_overrides() {
+				new_job.set_argument_overrides(argument_overrides);
+			}
 			Ok(new_job)
 		}
 
+		/// The keyword argument overrides (JSON) entered on the Task Schedule; None when blank.
+		pub fn argument_overrides(&self) -> Option<&str> {
+			self.argument_overrides.as_deref().map(str::trim).filter(|overrides| !overrides.is_empty())
+		}
+
+		/// The repeat interval, when this Task Schedule has one instead of a cron expression.  Err if it cannot be parsed.
+		pub fn repeat_interval(&self) -> Option<Result<chrono::Duration, CronError>> {
+			if !self.cron_string.trim().is_empty() {
+				return None;
+			}
+			let text: &str = self.repeat_interval.as_deref().map(str::trim).filter(|text| !text.is_empty())?;
+			Some(btu_cron::parse_repeat_interval(text))
+		}
+
+		/// The cron expression, or the repeat interval when there is no cron expression.
+		pub fn schedule_expression(&self) -> &str {
+			match self.repeat_interval.as_deref() {
+				Some(interval) if self.cron_string.trim().is_empty() => interval,
+				_ => &self.cron_string
+			}
+		}
+
+		/// The schedule in English, such as "every weekday at 09:30 America/New_York".
+		pub fn describe_schedule(&self, semantics: btu_cron::DomDowSemantics) -> String {
+			btu_cron::describe_schedule(self.schedule_expression(), btu_cron::cron_timezone_label(self.cron_timezone.name(), self.utc_only), semantics)
+		}
+
 		/**
 			Return on optional Vector of UTC Datetimes, which are the next execution times for this Task Schedule.
 		 */
-		pub fn next_runtimes(&self, from_utc_datetime: &Option<DateTime<Utc>>, number_results: &usize) -> Option<Vec<DateTime<Utc>>> {
-
-			let next_runtimes = btu_cron::tz_cron_to_utc_datetimes(
-				&self.cron_string,
-				self.cron_timezone,
-				*from_utc_datetime,
-				number_results
-			);
+		pub fn next_runtimes(&self, from_utc_datetime: &Option<DateTime<Utc>>, number_results: &usize,
+		                     semantics: btu_cron::DomDowSemantics) -> Option<Vec<DateTime<Utc>>> {
+
+			// For a repeat interval, these are 'from_utc_datetime' (or now) plus 1, 2, 3... intervals.
+			let next_runtimes = match self.repeat_interval() {
+				Some(interval) => interval.map(|interval| {
+					btu_cron::interval_runtimes(from_utc_datetime.unwrap_or_else(Utc::now), interval, *number_results)
+				}),
+				None => btu_cron::tz_cron_to_utc_datetimes(
+					&self.cron_string,
+					self.cron_timezone,
+					*from_utc_datetime,
+					number_results,
+					semantics
+				)
+			};
 
 			if next_runtimes.is_err() {
 				error!("Cannot calculate 'Next Execution Time' values for Task Schedule {}", &self.id);
@@ -285,163 +704,434 @@ pub mod task_schedule {
 		}
 	}
 
+	#[cfg(feature = "sql")]
+	/// The identifiers of every BTU Task Schedule in the MariaDB database; enabled or not.
+	pub fn read_task_schedule_ids(app_config: &AppConfig) -> Result<Vec<String>, mysql::Error> {
+
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config)?;
+		sql_conn.query("SELECT name FROM `tabBTU Task Schedule` ORDER BY name")
+	}
+
+	#[cfg(feature = "sql")]
+	/// The identifiers of every enabled BTU Task Schedule, whatever its queue.
+	pub fn read_enabled_task_schedule_ids(app_config: &AppConfig) -> Result<Vec<String>, mysql::Error> {
+
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config)?;
+		sql_conn.query("SELECT name FROM `tabBTU Task Schedule` WHERE enabled = 1 ORDER BY name")
+	}
+
+	#[cfg(feature = "sql")]
+	/// The dates in a Frappe 'Holiday List'.
+	pub fn read_holiday_dates(app_config: &AppConfig, holiday_list: &str) -> Result<Vec<chrono::NaiveDate>, mysql::Error> {
+
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config)?;
+		let query_syntax = "SELECT DATE_FORMAT(holiday_date, '%Y-%m-%d') FROM `tabHoliday`
+			WHERE parenttype = 'Holiday List' AND parent = ?";
+		let date_strings: Vec<String> = sql_conn.exec(query_syntax, (holiday_list,))?;
+		Ok(date_strings.iter().filter_map(|date_string| chrono::NaiveDate::parse_from_str(date_string, "%Y-%m-%d").ok()).collect())
+	}
+
+	#[cfg(feature = "sql")]
 	/** Given a Task Schedule identifier (string), connect to MySQL, query the table,
 	    and return a new instance of struct 'BtuTaskSchedule'.
 	*/
-	pub fn read_btu_task_schedule(app_config: &config::AppConfig, task_schedule_id: &str) -> Option<BtuTaskSchedule> {
-
-		// Can finally does this as of Rust 1.65
-		let Ok(mut sql_conn) = config::get_mysql_conn(&app_config) else {
-			println!("func 1 returned None");
-			return None;
-		};
+	// A missing row is UnknownTaskSchedule.  When MariaDB cannot be reached (for example, while it restarts), the error
+	// is SqlUnavailable, and the caller should try again later.  Any other SQL failure is Sql.
+	pub fn read_btu_task_schedule(app_config: &config::AppConfig, task_schedule_id: &TaskScheduleId) -> Result<BtuTaskSchedule, SchedulerError> {
+
+		match try_read_btu_task_schedule(app_config, task_schedule_id) {
+			Ok(Some(btu_task_schedule)) => Ok(btu_task_schedule),
+			Ok(None) => Err(SchedulerError::UnknownTaskSchedule { task_schedule_id: task_schedule_id.to_string() }),
+			Err(mysql_error) => Err(sql_read_error(task_schedule_id, &mysql_error)),
+		}
+	}
 
-		// let mut sql_conn: PooledConn = config::get_mysql_conn(&app_config).unwrap();  // create a connection to the MariaDB database.
+	#[cfg(feature = "sql")]
+	/// A SQL failure while reading a Task Schedule, as a SchedulerError: SqlUnavailable when MariaDB could not be reached.
+	pub fn sql_read_error(task_schedule_id: &TaskScheduleId, mysql_error: &mysql::Error) -> SchedulerError {
+		let task_schedule_id: String = task_schedule_id.to_string();
+		let message: String = mysql_error.to_string();
+		match crate::classify_sql_error(mysql_error) {
+			crate::SqlValidationStatus::ConnectionRefused => SchedulerError::SqlUnavailable { task_schedule_id, message },
+			_ => SchedulerError::Sql { task_schedule_id, message },
+		}
+	}
 
-		// 2. Run query, and map result into a new Result<Option<BtuTaskSchedule>>
-		//    TODO: Investigate resolving SQL injection.  Probably means finding a helpful 3rd party crate.
-		let query_syntax = format!("SELECT TaskSchedule.name, TaskSchedule.task, TaskSchedule.task_description,
+	/// Task Schedules with the site's cron time zone, in the columns task_schedule_from_row() reads; then the WHERE clause.
+	macro_rules! select_task_schedules {
+		($where_clause:literal) => {
+			concat!("SELECT TaskSchedule.name, TaskSchedule.task, TaskSchedule.task_description,
 		TaskSchedule.enabled, TaskSchedule.queue_name, TaskSchedule.redis_job_id, TaskSchedule.argument_overrides,
-		TaskSchedule.schedule_description, TaskSchedule.cron_string, Configuration.value AS cron_time_zone
+		TaskSchedule.schedule_description, IFNULL(TaskSchedule.cron_string, ''), Configuration.value AS cron_time_zone
 
 		FROM `tabBTU Task Schedule` AS TaskSchedule
 
 		INNER JOIN `tabSingles`	AS Configuration
 		ON Configuration.doctype = 'BTU Configuration'
 		AND Configuration.`field` = 'cron_time_zone'
-		
-		WHERE TaskSchedule.name = '{}' LIMIT 1;", task_schedule_id);
 
-		/* TODO: exec_map appears entirely broken.
-			thread '<unnamed>' panicked at 'Could not retrieve alloc::string::String from Value', 
-			/home/sysop/.cargo/registry/src/github.com-1ecc6299db9ec823/mysql_common-0.27.5/src/value/convert/mod.rs:175:23
+		WHERE ", $where_clause)
+		};
+	}
+
+	/// One Task Schedule, by name, with the site's cron time zone.  The name is always a bound parameter.
+	pub static TASK_SCHEDULE_BY_NAME_SQL: &str = select_task_schedules!("TaskSchedule.name = ? LIMIT 1");
+
+	/// Every enabled Task Schedule, for the full refresh.  The queue filter ('include_queues', 'exclude_queues') is appended.
+	pub static ENABLED_TASK_SCHEDULES_SQL: &str = select_task_schedules!("TaskSchedule.enabled = 1");
+
+	#[cfg(feature = "sql")]
+	/// Like read_btu_task_schedule(), but distinguishes a missing Task Schedule (Ok(None)) from a SQL failure (Err).
+	pub fn try_read_btu_task_schedule(app_config: &config::AppConfig, task_schedule_id: &TaskScheduleId) -> Result<Option<BtuTaskSchedule>, mysql::Error> {
+
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config)?;
+
+		// Run query, and map result into a new Result<Option<BtuTaskSchedule>>
+		/* A prepared statement returns integer columns as integers, not text.  Reading 'enabled' as a String once panicked
+		   here ("Could not retrieve alloc::string::String from Value"); integer columns are now read with FrappeBool.
 		*/
+		let mut task_schedules: Vec<BtuTaskSchedule> = timed_query(QueryKind::TaskSchedule, app_config.slow_query_threshold(), || sql_conn
+			.exec_map(TASK_SCHEDULE_BY_NAME_SQL, (task_schedule_id.as_str(),), task_schedule_from_row))?;
+		if !task_schedules.is_empty() {
+			read_optional_columns(&mut sql_conn, app_config, "TaskSchedule.name = ?", &[task_schedule_id.to_string()], &mut task_schedules);
+		}
 
-		// TODO: Error handling if the query fails.
-		let result_task_schedules: Result<Vec<BtuTaskSchedule>, mysql::Error> = sql_conn
-			.query_map(query_syntax, |row: mysql::Row| {
-				BtuTaskSchedule {
-					id:  row.get(0).unwrap(),
-					task:row.get(1).unwrap(),
-					task_description: row.get(2).unwrap(),
-					enabled:  row.get(3).unwrap(),
-					queue_name:  row.get(4).unwrap(),
-					redis_job_id:  row.get(5).unwrap(),
-					argument_overrides: row.get(6).unwrap(),
-					schedule_description:row.get(7).unwrap(),
-					cron_string:  row.get(8).unwrap(),
-					cron_timezone: row.get::<String, _>(9).unwrap().parse().unwrap()
-				}
-			});
+  		// The SQL query returns 0 or 1 rows.
+		Ok(task_schedules.into_iter().next().map(|task_schedule| apply_cron_strings_are_utc(app_config, task_schedule)))
+	}
+
+	#[cfg(feature = "sql")]
+	/// Every enabled Task Schedule on queues that pass the queue filter, ordered by name, in a single SQL query.  The full
+	/// refresh reads these, rather than one query per Task Schedule.
+	pub fn read_many_btu_task_schedules(app_config: &config::AppConfig) -> Result<Vec<BtuTaskSchedule>, mysql::Error> {
+
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config)?;
+		let (queue_filter, queue_parameters) = app_config.queue_filter_sql();
+		let query_syntax: String = format!("{}{} ORDER BY TaskSchedule.name", ENABLED_TASK_SCHEDULES_SQL, queue_filter);
+		let mut task_schedules: Vec<BtuTaskSchedule> = timed_query(QueryKind::FullRefill, app_config.slow_query_threshold(), || sql_conn
+			.exec_map(query_syntax, queue_parameters.clone(), task_schedule_from_row))?;
+		read_optional_columns(&mut sql_conn, app_config, &format!("TaskSchedule.enabled = 1{}", queue_filter), &queue_parameters, &mut task_schedules);
+		Ok(task_schedules.into_iter().map(|task_schedule| apply_cron_strings_are_utc(app_config, task_schedule)).collect())
+	}
+
+	#[cfg(feature = "sql")]
+	/// One row of select_task_schedules!().
+	fn task_schedule_from_row(row: mysql::Row) -> BtuTaskSchedule {
+		BtuTaskSchedule {
+			id: TaskScheduleId::from(row.get::<String, _>(0).unwrap()),
+			task: TaskId::from(row.get::<String, _>(1).unwrap()),
+			task_description: row.get(2).unwrap(),
+			enabled: crate::FrappeBool::from_sql_or_false(row.as_ref(3), "column 'enabled' of BTU Task Schedule"),
+			queue_name:  row.get(4).unwrap(),
+			redis_job_id:  row.get(5).unwrap(),
+			argument_overrides: row.get(6).unwrap(),
+			schedule_description:row.get(7).unwrap(),
+			cron_string:  row.get(8).unwrap(),
+			cron_timezone: row.get::<String, _>(9).unwrap().parse().unwrap(),
+			// Optional columns; see read_optional_columns().
+			skip_holidays: false,
+			repeat_interval: None,
+			repeat: None,
+			utc_only: false
+		}
+	}
+
+	/// Columns of BTU Task Schedule that older BTU Apps do not have, and their values for one Task Schedule.
+	/// They are not in select_task_schedules!(), because one missing column would fail every read.
+	#[derive(Clone, Debug, Default, PartialEq, Eq)]
+	pub struct OptionalColumns {
+		pub skip_holidays: bool,
+		pub repeat_interval: Option<String>,
+		pub repeat: Option<u32>,
+	}
+
+	impl OptionalColumns {
+		pub fn apply_to(self, task_schedule: &mut BtuTaskSchedule) {
+			task_schedule.skip_holidays = self.skip_holidays;
+			task_schedule.repeat_interval = self.repeat_interval;
+			task_schedule.repeat = self.repeat;
+		}
+	}
 
-		let task_schedules: Vec<BtuTaskSchedule>;  // uninitialized until match below -->
-		match result_task_schedules {
-			Ok(result) => {
-				task_schedules = result;
+	#[cfg(feature = "sql")]
+	/// The rows of one optional column's query.  None when it failed; every Task Schedule then keeps the column's default.
+	/// A column missing from an older BTU App (ER_BAD_FIELD_ERROR) is expected, and only logged at debug level.
+	pub fn optional_column_rows<T>(column: &str, result: Result<Vec<(String, T)>, mysql::Error>) -> Option<Vec<(String, T)>> {
+		match result {
+			Ok(rows) => Some(rows),
+			Err(mysql::Error::MySqlError(server_error)) if server_error.code == 1054 => {
+				debug!("Column '{}' of BTU Task Schedule does not exist (an older BTU App); using its default.", column);
+				None
+			},
+			Err(error) => {
+				warn!("Unable to read column '{}' of BTU Task Schedule; using its default: {}", column, error);
+				None
 			}
-			Err(mysql_error) => {
-				error!("MySQL Error encountered in read_btu_task_schedule(): {:?}", mysql_error);
-				return None;
+		}
+	}
+
+	#[cfg(feature = "sql")]
+	/// Read each optional column of the Task Schedules matching 'where_clause', with its own query, into 'task_schedules'.
+	fn read_optional_columns(sql_conn: &mut PooledConn, app_config: &AppConfig, where_clause: &str, parameters: &[String],
+	                         task_schedules: &mut [BtuTaskSchedule]) {
+		let mut columns: std::collections::HashMap<String, OptionalColumns> = std::collections::HashMap::new();
+		let query_syntax = |column: &str| format!("SELECT TaskSchedule.name, TaskSchedule.`{}` FROM `tabBTU Task Schedule` AS TaskSchedule WHERE {}",
+		                                          column, where_clause);
+
+		let result = timed_query(QueryKind::TaskSchedule, app_config.slow_query_threshold(), || {
+			sql_conn.exec_map(query_syntax("skip_holidays"), parameters.to_vec(), |row: mysql::Row| {
+				(row.get::<String, _>(0).unwrap(), crate::FrappeBool::from_sql_or_false(row.as_ref(1), "column 'skip_holidays' of BTU Task Schedule"))
+			})
+		});
+		for (name, skip_holidays) in optional_column_rows("skip_holidays", result).unwrap_or_default() {
+			columns.entry(name).or_default().skip_holidays = skip_holidays;
+		}
+
+		let result = timed_query(QueryKind::TaskSchedule, app_config.slow_query_threshold(), || {
+			sql_conn.exec::<(String, Option<String>), _, _>(query_syntax("repeat_interval"), parameters.to_vec())
+		});
+		for (name, repeat_interval) in optional_column_rows("repeat_interval", result).unwrap_or_default() {
+			columns.entry(name).or_default().repeat_interval = repeat_interval;
+		}
+
+		let result = timed_query(QueryKind::TaskSchedule, app_config.slow_query_threshold(), || {
+			sql_conn.exec::<(String, Option<i64>), _, _>(query_syntax("repeat"), parameters.to_vec())
+		});
+		for (name, repeat) in optional_column_rows("repeat", result).unwrap_or_default() {
+			columns.entry(name).or_default().repeat = repeat.and_then(|repeat| u32::try_from(repeat).ok());
+		}
+
+		for task_schedule in task_schedules.iter_mut() {
+			columns.remove(task_schedule.id.as_str()).unwrap_or_default().apply_to(task_schedule);
+		}
+	}
+
+	#[cfg(feature = "sql")]
+	/// The Task Schedule whose most recent RQ Job must finish before this one's jobs run ('depends_on_schedule').
+	/// None when there is no dependency: the column is empty, or missing from an older BTU App.
+	pub fn read_task_schedule_depends_on(app_config: &AppConfig, task_schedule_id: &TaskScheduleId) -> Option<TaskScheduleId> {
+		let mut sql_conn: PooledConn = config::get_mysql_conn(app_config).ok()?;
+		let query_syntax = "SELECT depends_on_schedule FROM `tabBTU Task Schedule` WHERE name = ?";
+		let result: Result<Option<Option<String>>, mysql::Error> = timed_query(QueryKind::TaskSchedule, app_config.slow_query_threshold(), || {
+			sql_conn.exec_first(query_syntax, (task_schedule_id.as_str(),))
+		});
+		match result {
+			Ok(depends_on) => depends_on.flatten().filter(|depends_on| !depends_on.trim().is_empty()).map(TaskScheduleId::from),
+			Err(error) => {
+				debug!("Unable to read 'depends_on_schedule' of Task Schedule {}; assuming no dependency: {}", task_schedule_id, error);
+				None
 			}
 		}
+	}
 
-  		// The SQL query returns 0 or 1 rows.  The syntax below uses 'next()' to fetch the first element in the Vector.
-		if let Some(btu_task_schedule) =  task_schedules.iter().next() {
-			Some(btu_task_schedule.to_owned())
-		} else {
-			// No results returned from SQL query.
-			error!("Cannot find a record in 'tabBTU Task Schedule' with primary key '{}'", task_schedule_id);
-			None
-		}       
+	#[cfg(feature = "sql")]
+	/// With 'cron_strings_are_utc', a Task Schedule's cron expression is read as UTC, whatever time zone is stored for it.
+	fn apply_cron_strings_are_utc(app_config: &config::AppConfig, mut task_schedule: BtuTaskSchedule) -> BtuTaskSchedule {
+		if !app_config.cron_strings_are_utc {
+			return task_schedule;
+		}
+		if task_schedule.cron_timezone != chrono_tz::UTC {
+			debug!("Task Schedule {}: ignoring time zone '{}', because 'cron_strings_are_utc' is true.", task_schedule.id, task_schedule.cron_timezone);
+		}
+		task_schedule.cron_timezone = btu_cron::effective_cron_timezone(task_schedule.cron_timezone, true);
+		task_schedule.utc_only = true;
+		task_schedule
 	}
 }
 
 
+/// The JSON body sent to Frappe's 'get_pickled_task' endpoint.  With 'argument_overrides', Frappe bakes those keyword arguments into the pickle.
+pub fn pickled_task_request_body(task_id: &TaskId, task_schedule_id: Option<&TaskScheduleId>, argument_overrides: Option<&str>) -> serde_json::Value {
+	serde_json::json!({
+		"task_id": task_id,
+		"task_schedule_id": task_schedule_id,
+		"argument_overrides": argument_overrides
+	})
+}
+
 /// Call ERPNext REST API and acquire pickled Python function as bytes.
-fn get_pickled_function_from_web(task_id: &str, task_schedule_id: Option<&str>, app_config: &AppConfig) -> Result<Vec<u8>, String> {
+fn get_pickled_function_from_web(task_id: &TaskId, task_schedule_id: Option<&TaskScheduleId>, argument_overrides: Option<&str>,
+                                 app_config: &AppConfig) -> Result<Vec<u8>, FrappeApiError> {
+
+	let client = frappe_client::FrappeClient::new(app_config);
+	let request_body = pickled_task_request_body(task_id, task_schedule_id, argument_overrides);
+	// Using json, because that's what we're sending 'task_id' as.  Frappe's exceptions arrive as 4xx and 5xx responses.
+	let response = client.send("GET", "/api/method/btu.btu_api.endpoints.get_pickled_task", "application/json", Some(&request_body))
+		.map_err(|error| FrappeApiError::Transport { task_id: task_id.to_string(), message: error.to_string() })?;
+	parse_frappe_api_response(task_id.as_str(), response.status, &response.body)
+}
 
-	let url: String;
-    if app_config.webserver_port == 443 {
-		url = format!("https://{}/api/method/btu.btu_api.endpoints.get_pickled_task",
-		app_config.webserver_ip);
-    }
-    else {
-		url = format!("http://{}:{}/api/method/btu.btu_api.endpoints.get_pickled_task",
-		app_config.webserver_ip, app_config.webserver_port);
-    }
 
-	let mut request = ureq::get(&url)
-		.set("Authorization", &app_config.webserver_token)
-		.set("Content-Type", "application/json");  // Using json, because that's what we're sending 'task_id' as below.
+/// The outcome of validate_sql_credentials(); what went wrong, when something did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SqlValidationStatus {
+	Ok,
+	ConnectionRefused,  // Nothing answered at 'mysql_host' and 'mysql_port', or the connection timed out.
+	AuthFailed,  // The server rejected 'mysql_user' and 'mysql_password', or their access to the database.
+	DatabaseMissing,  // There is no database named 'mysql_database'.
+	BtuTablesMissing,  // The database exists, but the BTU app's tables do not.
+	QueryFailed,  // Anything else.
+}
 
-		// If Frappe is running via gunicorn, in DNS Multi-tenancy mode, then we have to pass a "Host" header.
-    if app_config.webserver_host_header.is_some() {
-        request = request.set("Host", &app_config.webserver_host_header.as_ref().unwrap());
-    }
+/// The SQL tables read by the daemon, which exist once the BTU Frappe app is installed on a site.
+pub static REQUIRED_BTU_TABLES: [&str; 2] = ["tabBTU Task", "tabBTU Task Schedule"];
+
+/// What validate_sql_credentials() found; serialized in the daemon's 'health' reply.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SqlValidationReport {
+	pub status: SqlValidationStatus,
+	pub message: String,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub missing_tables: Vec<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub hint: Option<String>,
+}
 
-	let wrapped_response = request
-		.send_json(ureq::json!({
-			"task_id": task_id,
-			"task_schedule_id": task_schedule_id
-		}));
+impl SqlValidationReport {
 
-	if wrapped_response.is_err() {
-		return Err(format!("Error in response: {:?}", wrapped_response.err()));
+	pub fn is_ok(&self) -> bool {
+		self.status == SqlValidationStatus::Ok
 	}
 
-	let web_server_resp = wrapped_response.unwrap()
-	;
-	assert!(web_server_resp.has("Content-Length"));
-	let _response_length = web_server_resp.header("Content-Length")
-		.and_then(|s| s.parse::<usize>().ok()).unwrap();
+	fn failed(status: SqlValidationStatus, message: String, app_config: &AppConfig) -> Self {
+		let hint: Option<String> = match status {
+			SqlValidationStatus::ConnectionRefused =>
+				Some(format!("Is MariaDB running, and listening on {}:{}?", app_config.mysql_host, app_config.mysql_port.unwrap_or(3306))),
+			SqlValidationStatus::AuthFailed =>
+				Some("Check 'mysql_user' and 'mysql_password' in the configuration; Frappe's are in the site's 'site_config.json'.".to_owned()),
+			SqlValidationStatus::DatabaseMissing =>
+				Some(format!("Check 'mysql_database' ('{}'); it should be the site's 'db_name'.", app_config.mysql_database)),
+			SqlValidationStatus::BtuTablesMissing =>
+				Some("The BTU Frappe app may not be installed on this site.  Try 'bench --site <site> install-app btu'.".to_owned()),
+			SqlValidationStatus::Ok | SqlValidationStatus::QueryFailed => None,
+		};
+		SqlValidationReport { status, message, missing_tables: Vec::new(), hint }
+	}
 
-	// Store the response in a FrappeApiMessage struct.
-	let response_json: FrappeApiMessage = web_server_resp.into_json().unwrap();
-	let bytes: Vec<u8> = response_json.message;
-	return Ok(bytes);
+	/// The message, followed by the hint when there is one.
+	pub fn describe(&self) -> String {
+		match &self.hint {
+			Some(hint) => format!("{}  {}", self.message, hint),
+			None => self.message.clone(),
+		}
+	}
 }
 
+#[cfg(feature = "sql")]
+/// Which failure a MySQL error represents.  Server errors are told apart by their MariaDB error codes.
+pub fn classify_sql_error(error: &mysql::Error) -> SqlValidationStatus {
+	use mysql::{DriverError, Error};
+
+	match error {
+		Error::IoError(_) => SqlValidationStatus::ConnectionRefused,
+		Error::DriverError(DriverError::ConnectTimeout | DriverError::CouldNotConnect(_) | DriverError::Timeout) =>
+			SqlValidationStatus::ConnectionRefused,
+		// ER_DBACCESS_DENIED_ERROR, ER_ACCESS_DENIED_ERROR, ER_TABLEACCESS_DENIED_ERROR
+		Error::MySqlError(server_error) if matches!(server_error.code, 1044 | 1045 | 1142) => SqlValidationStatus::AuthFailed,
+		// ER_BAD_DB_ERROR
+		Error::MySqlError(server_error) if server_error.code == 1049 => SqlValidationStatus::DatabaseMissing,
+		// ER_NO_SUCH_TABLE
+		Error::MySqlError(server_error) if server_error.code == 1146 => SqlValidationStatus::BtuTablesMissing,
+		_ => SqlValidationStatus::QueryFailed,
+	}
+}
 
+#[cfg(feature = "sql")]
 /**
+  Validates the SQL connection by performing a simple query against SQL table 'tabDocType', then checks that the
+  BTU app's tables exist.  Each kind of failure is reported with a hint about what to check.
+*/
+pub fn validate_sql_credentials(app_config: &config::AppConfig) -> SqlValidationReport {
+
+	let failed_with = |error: mysql::Error| SqlValidationReport::failed(classify_sql_error(&error), error.to_string(), app_config);
+
+	let mut sql_conn: PooledConn = match config::get_mysql_conn(app_config) {
+		Ok(sql_conn) => sql_conn,
+		Err(error) => return failed_with(error)
+	};
+
+	let number_of_doctypes: Option<u64> = match sql_conn.query_first("SELECT count(*) FROM tabDocType;") {
+		Ok(result_option) => result_option,
+		Err(error) => return failed_with(error)
+	};
+	if number_of_doctypes.unwrap_or(0) == 0 {
+		// An unlikely condition, but worth checking.
+		return SqlValidationReport::failed(SqlValidationStatus::QueryFailed, "Query of DocType table returned 0 rows.".to_owned(), app_config);
+	}
+
+	// information_schema answers without an error, whether or not the tables exist.
+	let query_string: String = format!("SELECT table_name FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name IN ({});",
+	                                   REQUIRED_BTU_TABLES.iter().map(|table| format!("'{}'", table)).collect::<Vec<String>>().join(", "));
+	let found_tables: Vec<String> = match sql_conn.query(query_string) {
+		Ok(found_tables) => found_tables,
+		Err(error) => return failed_with(error)
+	};
+	let missing_tables: Vec<String> = REQUIRED_BTU_TABLES.iter()
+		.filter(|table| !found_tables.iter().any(|found| found.eq_ignore_ascii_case(table)))
+		.map(|table| (*table).to_owned())
+		.collect();
+	if !missing_tables.is_empty() {
+		let message: String = format!("Database '{}' has no table {}.", app_config.mysql_database,
+		                              missing_tables.iter().map(|table| format!("`{}`", table)).collect::<Vec<String>>().join(" or "));
+		let mut report = SqlValidationReport::failed(SqlValidationStatus::BtuTablesMissing, message, app_config);
+		report.missing_tables = missing_tables;
+		return report;
+	}
 
-  Validates the SQL connection by performing a simple query against SQL table 'tabDocType'
+	SqlValidationReport {
+		status: SqlValidationStatus::Ok,
+		message: format!("Connected to database '{}'; found the BTU tables.", app_config.mysql_database),
+		missing_tables: Vec::new(),
+		hint: None,
+	}
+}
+
+/// The Frappe endpoint whose bytes are the pickled test function run by worker_smoke_test().
+pub static SMOKE_TEST_ENDPOINT: &str = "/api/method/btu.btu_api.endpoints.test_function_ping_now_bytes";
+
+/// What worker_smoke_test() observed.  'round_trip' runs from enqueuing the test job until its final status (or the timeout).
+#[derive(Debug)]
+pub struct WorkerSmokeTest {
+	pub job_id: RqJobId,
+	pub queue_name: String,
+	pub outcome: rq::JobOutcome,
+	pub round_trip: std::time::Duration,
+}
+
+/**
+  Prove that a Python RQ worker runs BTU's jobs: fetch a trivial pickled function from the Frappe web server, enqueue it on
+  'queue_name', and wait up to 'timeout' for a worker to finish it.  The test job is deleted afterwards, whatever happened.
 */
-pub fn validate_sql_credentials(app_config: &config::AppConfig) -> Result<(), std::io::Error> {
-
-	let sql_conn: Result<PooledConn, mysql::Error> = config::get_mysql_conn(&app_config);
-	if sql_conn.is_err() {
-		let sql_error_string: String = sql_conn.err().unwrap().to_string();
-		let io_error = std::io::Error::new(std::io::ErrorKind::Other, sql_error_string);
-		return Err(io_error);
-	}
-	let mut sql_conn: PooledConn = sql_conn.unwrap();  // create a connection to the MariaDB database.
-
-	// 2. Run query, and map result into a new Result<Option<BtuTaskSchedule>>
-	//    TODO: Investigate resolving SQL injection.  Probably means finding a helpful 3rd party crate.
-	let query_string: &'static str = "SELECT count(*) FROM tabDocType;";
-
-	let query_result: Result<Option<u64>, mysql::Error> = sql_conn.query_first(query_string);
-	match query_result {
-		Ok(result_option) => {
-			if result_option.is_none() {
-				// Return an Error if there are no results.
-				let io_error = std::io::Error::new(std::io::ErrorKind::Other, format!("Query of DocType table returned no results."));
-				return Err(io_error);				
-			}
-			let number_of_doctypes: u64 = result_option.unwrap();
-			if number_of_doctypes == 0 {
-				// Return an Error if SQL table `tabDocType` has no zero rows (unlikely condition, but worth checking)
-				let io_error = std::io::Error::new(std::io::ErrorKind::Other, format!("Query of DocType table returned 0 rows."));
-				return Err(io_error);
-			}
-		},
-		Err(error) => {
-			let io_error = std::io::Error::new(std::io::ErrorKind::Other, error);
-			return Err(io_error);
+pub fn worker_smoke_test(app_config: &AppConfig, queue_name: &str, timeout: std::time::Duration) -> Result<WorkerSmokeTest, SmokeTestError> {
+	let mut rq_job: rq::RQJob = rq::RQJob::new_with_defaults();
+	rq_job.description = "BTU worker smoke test".to_owned();
+	rq_job.data = frappe_client::FrappeClient::new(app_config).get_bytes(SMOKE_TEST_ENDPOINT)?;
+	rq_job.set_origin(queue_name);
+	rq_job.timeout = u32::try_from(timeout.as_secs()).unwrap_or(u32::MAX).max(1);
+	rq_job.save_to_redis(app_config)?;
+
+	let started = std::time::Instant::now();
+	let outcome: rq::JobOutcome = match rq::enqueue_job_immediate(app_config, &rq_job.job_key_short, true) {
+		Ok(_) => rq::wait_for_job_completion(app_config, &rq_job.job_key_short, timeout),
+		Err(source) => {
+			delete_smoke_test_job(app_config, &rq_job.job_key_short, queue_name);
+			return Err(SmokeTestError::Enqueue { source });
 		}
-	}
+	};
+	let round_trip: std::time::Duration = started.elapsed();
+	delete_smoke_test_job(app_config, &rq_job.job_key_short, queue_name);
+	Ok(WorkerSmokeTest { job_id: rq_job.job_key_short, queue_name: queue_name.to_owned(), outcome, round_trip })
+}
 
-	Ok(())
+/// Best-effort: a test job left behind after a timeout would otherwise still be run by the next worker.
+fn delete_smoke_test_job(app_config: &AppConfig, job_id: &RqJobId, queue_name: &str) {
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return;
+	};
+	if let Err(error) = rq::delete_job(&mut redis_conn, job_id, queue_name) {
+		warn!("Unable to delete the smoke test job '{}': {}", job_id, error);
+	}
 }
 
 #[allow(dead_code)]
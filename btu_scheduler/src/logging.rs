@@ -23,12 +23,12 @@
 */
 
 use std::fmt;
-use serde::ser::SerializeTuple;
 use serde::{Serialize, Serializer, Deserialize, Deserializer};
 use serde::de::{self, Visitor};
 use tracing::Level;
 use tracing_subscriber::filter::LevelFilter;
 
+#[derive(Clone)]
 pub struct LevelWrapper ( pub tracing::Level );  // tuple struct: See article https://rust-unofficial.github.io/patterns/patterns/behavioural/newtype.html
 
 impl LevelWrapper {
@@ -59,7 +59,7 @@ impl<'de> Visitor<'de> for LevelWrapperVisitor {
 			"INFO" => LevelWrapper(Level::INFO),
 			"WARN" => LevelWrapper(Level::WARN),
 			"ERROR" => LevelWrapper(Level::ERROR),
-			_ => panic!("Unrecognized level value: {}", value),
+			_ => return Err(E::custom(format!("unrecognized level value '{}'; expected TRACE, DEBUG, INFO, WARN, or ERROR", value))),
 		};
         Ok(result_level)
     }
@@ -69,9 +69,8 @@ impl Serialize for LevelWrapper {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 		where S: Serializer
 	{
-		let mut tup = serializer.serialize_tuple(1)?;
-		tup.serialize_element(&self.0.to_string())?;  // Unsure if this is reasonable, but converting the Level to a string seems the easiest approach to Serialization.
-		tup.end()
+		// Written the same way it is read ("INFO"), so a serialized configuration loads again.
+		serializer.serialize_str(&self.0.to_string().to_uppercase())
 	}
 }
 
@@ -85,6 +84,7 @@ impl<'a> Deserialize<'a> for LevelWrapper {
 
 // Next, implement Serialize and Deserial for tracing_level: filter::LevelFilter
 
+#[derive(Clone)]
 pub struct LevelFilterWrapper ( pub LevelFilter);  // tuple struct: See article https://rust-unofficial.github.io/patterns/patterns/behavioural/newtype.html
 
 impl LevelFilterWrapper {
@@ -115,7 +115,7 @@ impl<'de> Visitor<'de> for LevelFilterWrapperVisitor {
 			"INFO" => LevelFilterWrapper(LevelFilter::INFO),
 			"WARN" => LevelFilterWrapper(LevelFilter::WARN),
 			"ERROR" => LevelFilterWrapper(LevelFilter::ERROR),
-			_ => panic!("Unrecognized level value: {}", value),
+			_ => return Err(E::custom(format!("unrecognized level value '{}'; expected TRACE, DEBUG, INFO, WARN, or ERROR", value))),
 		};
         Ok(result_level)
     }
@@ -125,9 +125,8 @@ impl Serialize for LevelFilterWrapper {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 		where S: Serializer
 	{
-		let mut tup = serializer.serialize_tuple(1)?;
-		tup.serialize_element(&self.0.to_string())?;  // Unsure if this is reasonable, but converting the Level to a string seems the easiest approach to Serialization.
-		tup.end()
+		// Written the same way it is read ("INFO"), so a serialized configuration loads again.
+		serializer.serialize_str(&self.0.to_string().to_uppercase())
 	}
 }
 
@@ -138,3 +137,48 @@ impl<'a> Deserialize<'a> for LevelFilterWrapper {
 		deserializer.deserialize_str(LevelFilterWrapperVisitor)
 	}
 }
+
+
+/**
+  Identifies one BTU daemon, when logs from several are aggregated in one place.\
+  The instance id is the hostname, plus the last 4 hex digits of a hash of the socket path; so two daemons on one host differ.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstanceIdentity {
+	pub environment_name: Option<String>,
+	pub instance_id: String,
+}
+
+impl InstanceIdentity {
+
+	pub fn new(environment_name: Option<String>, hostname: &str, socket_path: &str) -> Self {
+		let socket_hash: String = format!("{:016x}", crate::stable_hash(socket_path));
+		InstanceIdentity {
+			environment_name,
+			instance_id: format!("{}-{}", hostname, &socket_hash[12..]),
+		}
+	}
+
+	pub fn from_config(app_config: &crate::config::AppConfig) -> Self {
+		Self::new(app_config.environment_name.clone(), &read_hostname(), &app_config.socket_path)
+	}
+}
+
+impl fmt::Display for InstanceIdentity {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.environment_name {
+			Some(environment_name) => write!(f, "environment '{}' (instance {})", environment_name, self.instance_id),
+			None => write!(f, "instance {}", self.instance_id),
+		}
+	}
+}
+
+/// The name of this host, or "unknown-host" if it cannot be read.
+pub fn read_hostname() -> String {
+	std::fs::read_to_string("/proc/sys/kernel/hostname")
+		.or_else(|_| std::fs::read_to_string("/etc/hostname"))
+		.map(|hostname| hostname.trim().to_owned())
+		.ok()
+		.filter(|hostname| !hostname.is_empty())
+		.unwrap_or_else(|| "unknown-host".to_owned())
+}
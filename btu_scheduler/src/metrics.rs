@@ -0,0 +1,169 @@
+/* Dev Notes:
+
+  * Statistics live in a process-wide static, so every thread (and the socket's 'health' request) sees the same numbers.
+  * Durations are kept in whole milliseconds.  Anything finer is noise, next to a network round-trip to MariaDB.
+  * The outcome of the most recent connection attempt to Redis, and to MariaDB, is kept the same way; for the 'status' request.
+
+*/
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use tracing::{debug, warn};
+
+/// Warn when a single SQL query takes longer than this, unless 'slow_query_threshold_ms' is configured.
+pub const DEFAULT_SLOW_QUERY_THRESHOLD_MS: u64 = 2000;
+
+/// The kinds of SQL query that are timed.  Each kind accumulates its own statistics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryKind {
+	Task,          // reading a BTU Task
+	TaskSchedule,  // reading a BTU Task Schedule, once per entry in the internal queue
+	FullRefill,    // reading every enabled Task Schedule (or just its ID) for the full refresh
+}
+
+const QUERY_KIND_COUNT: usize = 3;
+
+impl QueryKind {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			QueryKind::Task => "task",
+			QueryKind::TaskSchedule => "task_schedule",
+			QueryKind::FullRefill => "full_refill",
+		}
+	}
+
+	fn index(&self) -> usize {
+		match self {
+			QueryKind::Task => 0,
+			QueryKind::TaskSchedule => 1,
+			QueryKind::FullRefill => 2,
+		}
+	}
+
+	fn all() -> [QueryKind; QUERY_KIND_COUNT] {
+		[QueryKind::Task, QueryKind::TaskSchedule, QueryKind::FullRefill]
+	}
+}
+
+/// Accumulated timings for one kind of query, since the process started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct QueryStats {
+	pub count: u64,
+	pub total_ms: u64,
+	pub max_ms: u64,
+	pub slow: u64,  // how many exceeded the slow query threshold
+}
+
+impl QueryStats {
+	const fn new() -> Self {
+		QueryStats { count: 0, total_ms: 0, max_ms: 0, slow: 0 }
+	}
+
+	fn record(&mut self, elapsed_ms: u64, is_slow: bool) {
+		self.count += 1;
+		self.total_ms = self.total_ms.saturating_add(elapsed_ms);
+		self.max_ms = self.max_ms.max(elapsed_ms);
+		if is_slow {
+			self.slow += 1;
+		}
+	}
+}
+
+static QUERY_STATS: Mutex<[QueryStats; QUERY_KIND_COUNT]> = Mutex::new([QueryStats::new(); QUERY_KIND_COUNT]);
+
+/// Anything a query returns that can say how many rows it read.
+pub trait RowCount {
+	fn row_count(&self) -> usize;
+}
+
+impl<T> RowCount for Vec<T> {
+	fn row_count(&self) -> usize {
+		self.len()
+	}
+}
+
+impl<T> RowCount for Option<T> {
+	fn row_count(&self) -> usize {
+		usize::from(self.is_some())
+	}
+}
+
+impl RowCount for u32 {
+	fn row_count(&self) -> usize {
+		*self as usize
+	}
+}
+
+/// Run a query, and record how long it took.  A failed query is timed too; it is counted with zero rows.
+pub fn timed_query<T, E, F>(kind: QueryKind, slow_threshold: Duration, query: F) -> Result<T, E>
+	where T: RowCount, F: FnOnce() -> Result<T, E>
+{
+	let stopwatch = Instant::now();
+	let result: Result<T, E> = query();
+	let row_count: usize = result.as_ref().map_or(0, RowCount::row_count);
+	record_query(kind, stopwatch.elapsed(), row_count, slow_threshold);
+	result
+}
+
+/// Log one query's duration, and add it to the statistics.  Returns true if the query was slow.
+pub fn record_query(kind: QueryKind, elapsed: Duration, row_count: usize, slow_threshold: Duration) -> bool {
+	let elapsed_ms: u64 = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+	let is_slow: bool = elapsed > slow_threshold;
+	debug!(query_kind = kind.as_str(), duration_ms = elapsed_ms, rows = row_count, "SQL query finished.");
+	if is_slow {
+		warn!(query_kind = kind.as_str(), duration_ms = elapsed_ms, rows = row_count,
+		      "Slow SQL query: {} ms, over the threshold of {} ms.  The database may be under load.", elapsed_ms, slow_threshold.as_millis());
+	}
+	if let Ok(mut stats) = QUERY_STATS.lock() {
+		stats[kind.index()].record(elapsed_ms, is_slow);
+	}
+	is_slow
+}
+
+/// A copy of the statistics for every kind of query, keyed by name.
+pub fn query_stats_snapshot() -> BTreeMap<&'static str, QueryStats> {
+	let stats: [QueryStats; QUERY_KIND_COUNT] = QUERY_STATS.lock().map(|stats| *stats).unwrap_or_default();
+	QueryKind::all().iter().map(|kind| (kind.as_str(), stats[kind.index()])).collect()
+}
+
+/// The databases the daemon connects to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+	Redis,
+	Mysql,
+}
+
+/// How the most recent attempt to connect to a Backend went.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ConnectionAttempt {
+	pub succeeded: bool,
+	pub at: String,  // RFC 3339, UTC
+}
+
+/// Indexed by Backend::index().
+static LAST_CONNECTION_ATTEMPTS: Mutex<[Option<ConnectionAttempt>; 2]> = Mutex::new([None, None]);
+
+impl Backend {
+	fn index(&self) -> usize {
+		match self {
+			Backend::Redis => 0,
+			Backend::Mysql => 1,
+		}
+	}
+}
+
+/// Remember whether a connection to 'backend' was just established.
+pub fn record_connection_attempt(backend: Backend, succeeded: bool) {
+	if let Ok(mut attempts) = LAST_CONNECTION_ATTEMPTS.lock() {
+		attempts[backend.index()] = Some(ConnectionAttempt { succeeded, at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true) });
+	}
+}
+
+/// The most recent attempt to connect to 'backend'; None if the process has not tried yet.
+pub fn last_connection_attempt(backend: Backend) -> Option<ConnectionAttempt> {
+	LAST_CONNECTION_ATTEMPTS.lock().ok().and_then(|attempts| attempts[backend.index()].clone())
+}
@@ -0,0 +1,180 @@
+// next_run_override.rs
+
+/*
+	Operators sometimes want to delay a Task Schedule's next run (a maintenance window), or pull it forward, without
+	editing its cron expression.  'btu snooze' and the socket request 'reschedule_next_run' do this.
+
+	The schedule's upcoming TSIKs are replaced by a single TSIK at the requested time, and that time is remembered in the
+	hash 'btu_scheduler:next_run_override'.  While the override is unexpired, Thread #1 leaves the schedule alone; so a full
+	refresh does not put the cron-computed time back.  Once Thread #3 runs the overridden TSIK, the override is removed,
+	and the post-run requeue schedules the next run from the cron expression, as usual.
+*/
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use redis::{Commands, RedisError};
+use tracing::{info, warn};
+
+use crate::config::AppConfig;
+use crate::errors::RescheduleError;
+use crate::events::{self, EventKind, SchedulerEvent};
+use crate::ids::TaskScheduleId;
+use crate::timeutil;
+use crate::redis_keys::{self, RQ_KEY_SCHEDULED_TASKS};
+
+pub use crate::redis_keys::RQ_KEY_NEXT_RUN_OVERRIDE;
+
+/// A next run may be moved at most this far into the future.
+pub const MAX_OVERRIDE_DAYS: i64 = 7;
+
+/// Where the requested next run is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RescheduleTarget {
+	At(DateTime<Utc>),  // an absolute time
+	By(Duration),       // relative to the currently scheduled next run; negative to run early
+}
+
+impl RescheduleTarget {
+
+	/// An RFC 3339 time, such as "2024-07-01T04:00:00Z".
+	pub fn parse_until(text: &str) -> Result<Self, RescheduleError> {
+		DateTime::parse_from_rfc3339(text.trim())
+			.map(|until| RescheduleTarget::At(until.with_timezone(&Utc)))
+			.map_err(|_| RescheduleError::InvalidTime { text: text.to_owned() })
+	}
+
+	/// An offset such as "2h" or "-30m" (see timeutil::parse_duration_arg).
+	pub fn parse_by(text: &str) -> Result<Self, RescheduleError> {
+		Ok(RescheduleTarget::By(timeutil::parse_duration_arg(text)?))
+	}
+}
+
+/// The result of moving a Task Schedule's next run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rescheduled {
+	pub previous: DateTime<Utc>,  // the earliest run that was replaced
+	pub next: DateTime<Utc>,
+	pub replaced: usize,          // how many upcoming runs were replaced by the one
+}
+
+/// Where upcoming runs and overrides are kept.  In the daemon, that is Redis.
+pub trait OverrideStore {
+	/// The schedule's TSIKs, and their scores.
+	fn schedule_runs(&mut self, task_schedule_id: &str) -> Result<Vec<(String, i64)>, RedisError>;
+	/// In one transaction: remove 'replaced', add the TSIK 'next', and remember 'next_unix' as the override.
+	fn replace_runs(&mut self, task_schedule_id: &str, replaced: &[String], next: &str, next_unix: i64) -> Result<(), RedisError>;
+	fn read_override(&mut self, task_schedule_id: &str) -> Option<i64>;
+	fn remove_override(&mut self, task_schedule_id: &str) -> bool;
+	fn run_score(&mut self, tsik: &str) -> Option<i64>;
+	fn add_run(&mut self, tsik: &str, unix: i64);
+}
+
+impl OverrideStore for redis::Connection {
+	fn schedule_runs(&mut self, task_schedule_id: &str) -> Result<Vec<(String, i64)>, RedisError> {
+		let prefix: String = redis_keys::tsik_prefix(task_schedule_id);
+		let members: Vec<(String, i64)> = self.zrange_withscores(RQ_KEY_SCHEDULED_TASKS, 0, -1)?;
+		Ok(members.into_iter().filter(|(tsik, _)| tsik.starts_with(&prefix)).collect())
+	}
+
+	fn replace_runs(&mut self, task_schedule_id: &str, replaced: &[String], next: &str, next_unix: i64) -> Result<(), RedisError> {
+		let mut pipeline = redis::pipe();
+		pipeline.atomic();
+		for tsik in replaced {
+			pipeline.zrem(RQ_KEY_SCHEDULED_TASKS, tsik).ignore();
+		}
+		pipeline.zadd(RQ_KEY_SCHEDULED_TASKS, next, next_unix).ignore()
+			.hset(RQ_KEY_NEXT_RUN_OVERRIDE, task_schedule_id, next_unix).ignore()
+			.query(self)
+	}
+
+	fn read_override(&mut self, task_schedule_id: &str) -> Option<i64> {
+		self.hget(RQ_KEY_NEXT_RUN_OVERRIDE, task_schedule_id).unwrap_or_else(|error: RedisError| {
+			warn!("Unable to read Redis hash '{}': {}", RQ_KEY_NEXT_RUN_OVERRIDE, error);
+			None
+		})
+	}
+
+	fn remove_override(&mut self, task_schedule_id: &str) -> bool {
+		self.hdel::<_, _, u32>(RQ_KEY_NEXT_RUN_OVERRIDE, task_schedule_id).map_or_else(|error| {
+			warn!("Unable to remove '{}' from Redis hash '{}': {}", task_schedule_id, RQ_KEY_NEXT_RUN_OVERRIDE, error);
+			false
+		}, |removed| removed > 0)
+	}
+
+	fn run_score(&mut self, tsik: &str) -> Option<i64> {
+		self.zscore(RQ_KEY_SCHEDULED_TASKS, tsik).unwrap_or_else(|error: RedisError| {
+			warn!("Unable to read the score of '{}' in '{}': {}", tsik, RQ_KEY_SCHEDULED_TASKS, error);
+			None
+		})
+	}
+
+	fn add_run(&mut self, tsik: &str, unix: i64) {
+		if let Err(error) = self.zadd::<_, _, _, u32>(RQ_KEY_SCHEDULED_TASKS, tsik, unix) {
+			warn!("Unable to add '{}' to '{}': {}", tsik, RQ_KEY_SCHEDULED_TASKS, error);
+		}
+	}
+}
+
+fn utc_from_unix(unix: i64) -> DateTime<Utc> {
+	Utc.timestamp_opt(unix, 0).single().unwrap_or_else(Utc::now)
+}
+
+/// Replace a Task Schedule's upcoming runs with a single run at the target time.  That time must be in the future, and at
+/// most MAX_OVERRIDE_DAYS away.
+pub fn reschedule_next_run<S: OverrideStore>(store: &mut S, task_schedule_id: &str, target: RescheduleTarget, now: DateTime<Utc>)
+	-> Result<Rescheduled, RescheduleError> {
+	let runs: Vec<(String, i64)> = store.schedule_runs(task_schedule_id)?;
+	let Some(previous_unix) = runs.iter().map(|(_, unix)| *unix).min() else {
+		return Err(RescheduleError::NothingScheduled { task_schedule_id: task_schedule_id.to_owned() });
+	};
+	let previous: DateTime<Utc> = utc_from_unix(previous_unix);
+	let next: DateTime<Utc> = match target {
+		RescheduleTarget::At(at) => at,
+		RescheduleTarget::By(offset) => previous + offset,
+	};
+	if next <= now {
+		return Err(RescheduleError::NotInFuture { requested: next.to_rfc3339() });
+	}
+	if next > now + Duration::days(MAX_OVERRIDE_DAYS) {
+		return Err(RescheduleError::TooFarAhead { requested: next.to_rfc3339(), max_days: MAX_OVERRIDE_DAYS });
+	}
+	let replaced: Vec<String> = runs.into_iter().map(|(tsik, _)| tsik).collect();
+	let tsik: String = redis_keys::tsik(task_schedule_id, next.timestamp());
+	store.replace_runs(task_schedule_id, &replaced, &tsik, next.timestamp())?;
+	Ok(Rescheduled { previous, next, replaced: replaced.len() })
+}
+
+/// The overridden next run, while it is unexpired.  Thread #1 must then leave the schedule's runs alone.  An override whose
+/// run already happened (or was removed) is forgotten here.
+pub fn active_override<S: OverrideStore>(store: &mut S, task_schedule_id: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+	let next_unix: i64 = store.read_override(task_schedule_id)?;
+	let tsik: String = redis_keys::tsik(task_schedule_id, next_unix);
+	if store.run_score(&tsik).is_some() {
+		return Some(utc_from_unix(next_unix));
+	}
+	if next_unix > now.timestamp() {
+		// Still ahead, but its TSIK is gone (an import, perhaps).  Put it back.
+		store.add_run(&tsik, next_unix);
+		return Some(utc_from_unix(next_unix));
+	}
+	store.remove_override(task_schedule_id);
+	None
+}
+
+/// After Thread #3 runs a TSIK: if it was the overridden run, forget the override, so cron scheduling resumes.
+pub fn complete_override<S: OverrideStore>(store: &mut S, task_schedule_id: &str, run_unix: i64) -> bool {
+	if store.read_override(task_schedule_id) != Some(run_unix) {
+		return false;
+	}
+	store.remove_override(task_schedule_id)
+}
+
+/// An operator's request ('btu snooze', or the socket request 'reschedule_next_run'), recorded as an event.
+pub fn reschedule_by_operator(app_config: &AppConfig, redis_conn: &mut redis::Connection, schedule_key: &TaskScheduleId, target: RescheduleTarget)
+	-> Result<Rescheduled, RescheduleError> {
+	let rescheduled: Rescheduled = reschedule_next_run(redis_conn, schedule_key.as_str(), target, Utc::now())?;
+	info!("Task Schedule {}: the next run was moved from {} to {} by an operator; cron scheduling resumes after that run.",
+	      schedule_key, rescheduled.previous.to_rfc3339(), rescheduled.next.to_rfc3339());
+	events::record_event(app_config, redis_conn, SchedulerEvent::new(EventKind::Rescheduled).schedule(schedule_key).run_at(rescheduled.next)
+		.reason(format!("moved from {}", rescheduled.previous.to_rfc3339())));
+	Ok(rescheduled)
+}
@@ -0,0 +1,207 @@
+// pickle.rs
+
+/*
+	A read-only subset of Python's pickle format: the plain values that RQ Jobs carry in 'data' and 'meta'.
+
+	RQ pickles each job's call as the tuple (func_name, instance, args, kwargs).  When the arguments are plain values (None,
+	booleans, numbers, strings, bytes, tuples, lists, and dicts), unpickle() can read them back, for display.  Pickles of
+	Python objects (classes, sets, datetimes) need code that only Python has; those return None.  Nothing here ever writes a
+	pickle, so nothing here can create a job a worker would misread.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// A Python value read from a pickle.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PickleValue {
+	None,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	Text(String),
+	Bytes(Vec<u8>),
+	Tuple(Vec<PickleValue>),
+	List(Vec<PickleValue>),
+	Dict(Vec<(PickleValue, PickleValue)>),  // in insertion order, like a Python dict
+}
+
+/// Written as Python's repr() would write it.
+impl fmt::Display for PickleValue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PickleValue::None => write!(f, "None"),
+			PickleValue::Bool(true) => write!(f, "True"),
+			PickleValue::Bool(false) => write!(f, "False"),
+			PickleValue::Int(number) => write!(f, "{}", number),
+			PickleValue::Float(number) => write!(f, "{:?}", number),
+			PickleValue::Text(text) => write!(f, "'{}'", text.replace('\\', "\\\\").replace('\'', "\\'").replace('\n', "\\n")),
+			PickleValue::Bytes(bytes) => {
+				write!(f, "b'")?;
+				for byte in bytes {
+					match byte {
+						b'\\' | b'\'' => write!(f, "\\{}", *byte as char)?,
+						0x20..=0x7e => write!(f, "{}", *byte as char)?,
+						_ => write!(f, "\\x{:02x}", byte)?,
+					}
+				}
+				write!(f, "'")
+			},
+			PickleValue::Tuple(items) if items.len() == 1 => write!(f, "({},)", items[0]),
+			PickleValue::Tuple(items) => write!(f, "({})", join(items)),
+			PickleValue::List(items) => write!(f, "[{}]", join(items)),
+			PickleValue::Dict(entries) => {
+				let entries: Vec<String> = entries.iter().map(|(key, value)| format!("{}: {}", key, value)).collect();
+				write!(f, "{{{}}}", entries.join(", "))
+			}
+		}
+	}
+}
+
+/// Each value's repr, separated by commas.
+pub fn join(values: &[PickleValue]) -> String {
+	values.iter().map(ToString::to_string).collect::<Vec<String>>().join(", ")
+}
+
+enum Item { Mark, Value(PickleValue) }
+
+fn take<'a>(bytes: &'a [u8], position: &mut usize, length: usize) -> Option<&'a [u8]> {
+	let taken: &[u8] = bytes.get(*position..position.checked_add(length)?)?;
+	*position += length;
+	Some(taken)
+}
+
+fn take_array<const N: usize>(bytes: &[u8], position: &mut usize) -> Option<[u8; N]> {
+	take(bytes, position, N)?.try_into().ok()
+}
+
+fn text(bytes: &[u8]) -> Option<PickleValue> {
+	String::from_utf8(bytes.to_vec()).ok().map(PickleValue::Text)
+}
+
+/// The values above the topmost mark, which is removed.
+fn pop_to_mark(stack: &mut Vec<Item>) -> Option<Vec<PickleValue>> {
+	let mark: usize = stack.iter().rposition(|item| matches!(item, Item::Mark))?;
+	let items: Vec<Item> = stack.split_off(mark + 1);
+	stack.pop();
+	items.into_iter().map(|item| match item { Item::Value(value) => Some(value), Item::Mark => None }).collect()
+}
+
+fn pop_values(stack: &mut Vec<Item>, count: usize) -> Option<Vec<PickleValue>> {
+	let floor: usize = stack.len().checked_sub(count)?;
+	stack.split_off(floor).into_iter().map(|item| match item { Item::Value(value) => Some(value), Item::Mark => None }).collect()
+}
+
+/// Add values to the list (APPEND, APPENDS) or key-value pairs to the dict (SETITEM, SETITEMS) on top of the stack.
+fn extend_top(stack: &mut [Item], values: Vec<PickleValue>) -> Option<()> {
+	match stack.last_mut()? {
+		Item::Value(PickleValue::List(list)) => list.extend(values),
+		Item::Value(PickleValue::Dict(dict)) => {
+			if values.len() % 2 != 0 {
+				return None;
+			}
+			let mut values = values.into_iter();
+			while let (Some(key), Some(value)) = (values.next(), values.next()) {
+				dict.push((key, value));
+			}
+		},
+		_ => return None
+	}
+	Some(())
+}
+
+/**
+  Read a pickle of plain values (protocols 2 through 5).  None when the pickle is malformed, or holds anything else.\
+  Memoized values are remembered as they were when memoized; pickles of plain values only refer back to strings and bytes.
+*/
+pub fn unpickle(bytes: &[u8]) -> Option<PickleValue> {
+	let mut stack: Vec<Item> = Vec::new();
+	let mut memo: HashMap<u32, PickleValue> = HashMap::new();
+	let mut position: usize = 0;
+	loop {
+		let opcode: u8 = *take(bytes, &mut position, 1)?.first()?;
+		let value: PickleValue = match opcode {
+			0x80 => { take(bytes, &mut position, 1)?; continue },  // PROTO
+			0x95 => { take(bytes, &mut position, 8)?; continue },  // FRAME
+			b'(' => { stack.push(Item::Mark); continue },
+			0x94 | b'q' | b'r' => {  // MEMOIZE, BINPUT, LONG_BINPUT
+				let index: u32 = match opcode {
+					0x94 => memo.len() as u32,
+					b'q' => u32::from(take_array::<1>(bytes, &mut position)?[0]),
+					_ => u32::from_le_bytes(take_array(bytes, &mut position)?),
+				};
+				let Some(Item::Value(top)) = stack.last() else {
+					return None;
+				};
+				memo.insert(index, top.clone());
+				continue
+			},
+			b'h' => memo.get(&u32::from(take_array::<1>(bytes, &mut position)?[0]))?.clone(),  // BINGET
+			b'j' => memo.get(&u32::from_le_bytes(take_array(bytes, &mut position)?))?.clone(),  // LONG_BINGET
+			b'N' => PickleValue::None,
+			0x88 => PickleValue::Bool(true),   // NEWTRUE
+			0x89 => PickleValue::Bool(false),  // NEWFALSE
+			b'K' => PickleValue::Int(i64::from(take_array::<1>(bytes, &mut position)?[0])),  // BININT1
+			b'M' => PickleValue::Int(i64::from(u16::from_le_bytes(take_array(bytes, &mut position)?))),  // BININT2
+			b'J' => PickleValue::Int(i64::from(i32::from_le_bytes(take_array(bytes, &mut position)?))),  // BININT
+			0x8a => {  // LONG1: a little-endian, two's complement integer; only those that fit in an i64
+				let length: usize = take_array::<1>(bytes, &mut position)?[0] as usize;
+				let digits: &[u8] = take(bytes, &mut position, length)?;
+				if length > 8 {
+					return None;
+				}
+				let fill: u8 = if digits.last().map_or(false, |last| last & 0x80 != 0) { 0xff } else { 0 };
+				let mut buffer = [fill; 8];
+				buffer[..length].copy_from_slice(digits);
+				PickleValue::Int(i64::from_le_bytes(buffer))
+			},
+			b'G' => PickleValue::Float(f64::from_be_bytes(take_array(bytes, &mut position)?)),  // BINFLOAT
+			0x8c => {  // SHORT_BINUNICODE
+				let length: usize = take_array::<1>(bytes, &mut position)?[0] as usize;
+				text(take(bytes, &mut position, length)?)?
+			},
+			b'X' => {  // BINUNICODE
+				let length: usize = u32::from_le_bytes(take_array(bytes, &mut position)?) as usize;
+				text(take(bytes, &mut position, length)?)?
+			},
+			0x8d => {  // BINUNICODE8
+				let length: usize = usize::try_from(u64::from_le_bytes(take_array(bytes, &mut position)?)).ok()?;
+				text(take(bytes, &mut position, length)?)?
+			},
+			b'C' => {  // SHORT_BINBYTES
+				let length: usize = take_array::<1>(bytes, &mut position)?[0] as usize;
+				PickleValue::Bytes(take(bytes, &mut position, length)?.to_vec())
+			},
+			b'B' => {  // BINBYTES
+				let length: usize = u32::from_le_bytes(take_array(bytes, &mut position)?) as usize;
+				PickleValue::Bytes(take(bytes, &mut position, length)?.to_vec())
+			},
+			b')' => PickleValue::Tuple(Vec::new()),
+			b']' => PickleValue::List(Vec::new()),
+			b'}' => PickleValue::Dict(Vec::new()),
+			b't' => PickleValue::Tuple(pop_to_mark(&mut stack)?),
+			0x85 => PickleValue::Tuple(pop_values(&mut stack, 1)?),  // TUPLE1
+			0x86 => PickleValue::Tuple(pop_values(&mut stack, 2)?),  // TUPLE2
+			0x87 => PickleValue::Tuple(pop_values(&mut stack, 3)?),  // TUPLE3
+			b'a' | b's' => {  // APPEND, SETITEM
+				let count: usize = if opcode == b'a' { 1 } else { 2 };
+				let values: Vec<PickleValue> = pop_values(&mut stack, count)?;
+				extend_top(&mut stack, values)?;
+				continue
+			},
+			b'e' | b'u' => {  // APPENDS, SETITEMS
+				let values: Vec<PickleValue> = pop_to_mark(&mut stack)?;
+				extend_top(&mut stack, values)?;
+				continue
+			},
+			b'.' => {  // STOP
+				return match (stack.pop(), stack.is_empty()) {
+					(Some(Item::Value(value)), true) => Some(value),
+					_ => None
+				};
+			},
+			_ => return None
+		};
+		stack.push(Item::Value(value));
+	}
+}
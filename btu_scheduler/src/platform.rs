@@ -0,0 +1,116 @@
+/* Dev Notes:
+
+  * Every probe is best-effort.  A file that cannot be read, or does not look as expected, leaves its field as None.
+  * The parsing functions take file contents (not paths), so they can be tested against captured files.
+
+*/
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+/// Facts about the machine and process the BTU is running on.  Useful in bug reports, and for choosing defaults.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PlatformInfo {
+	pub os_release: Option<String>,  // 'PRETTY_NAME' from /etc/os-release (example: "Debian GNU/Linux 11 (bullseye)")
+	pub libc: String,  // the C library this binary was built against: "glibc", "musl", or "unknown"
+	pub under_systemd: bool,  // started by systemd as a service ('INVOCATION_ID' is set)
+	pub in_container: Option<bool>,
+	pub container_runtime: Option<String>,  // when recognized (example: "docker")
+	pub total_memory_kb: Option<u64>,
+}
+
+impl PlatformInfo {
+
+	pub fn detect() -> Self {
+		let cgroup: Option<String> = std::fs::read_to_string("/proc/1/cgroup").ok();
+		let container_runtime: Option<String> = if Path::new("/.dockerenv").exists() {
+			Some("docker".to_owned())
+		}
+		else if Path::new("/run/.containerenv").exists() {
+			Some("podman".to_owned())
+		}
+		else {
+			cgroup.as_deref().and_then(container_runtime_from_cgroup)
+		};
+		let in_container: Option<bool> = if container_runtime.is_some() {
+			Some(true)
+		}
+		else {
+			// Without /proc/1/cgroup there is no telling.  Nor with a private cgroup namespace, where PID 1 sees only the root ("0::/").
+			cgroup.as_deref().filter(|contents| contents.trim() != "0::/").map(|_| false)
+		};
+
+		PlatformInfo {
+			os_release: std::fs::read_to_string("/etc/os-release").ok().as_deref().and_then(parse_os_release_name),
+			libc: libc_flavor().to_owned(),
+			under_systemd: std::env::var_os("INVOCATION_ID").is_some(),
+			in_container,
+			container_runtime,
+			total_memory_kb: std::fs::read_to_string("/proc/meminfo").ok().as_deref().and_then(parse_meminfo_total_kb),
+		}
+	}
+}
+
+impl fmt::Display for PlatformInfo {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}, {}", self.os_release.as_deref().unwrap_or("unknown Linux distribution"), self.libc)?;
+		if self.under_systemd {
+			write!(f, ", under systemd")?;
+		}
+		match (self.in_container, &self.container_runtime) {
+			(Some(true), Some(runtime)) => write!(f, ", in a {} container", runtime)?,
+			(Some(true), None) => write!(f, ", in a container")?,
+			_ => {}
+		}
+		if let Some(total_memory_kb) = self.total_memory_kb {
+			write!(f, ", {} MiB memory", total_memory_kb / 1024)?;
+		}
+		Ok(())
+	}
+}
+
+/// The C library this binary was compiled against.
+pub fn libc_flavor() -> &'static str {
+	if cfg!(target_env = "musl") {
+		"musl"
+	}
+	else if cfg!(target_env = "gnu") {
+		"glibc"
+	}
+	else {
+		"unknown"
+	}
+}
+
+/// The 'PRETTY_NAME' (or failing that, 'NAME') from the contents of /etc/os-release.
+pub fn parse_os_release_name(contents: &str) -> Option<String> {
+	let value_of = |key: &str| -> Option<String> {
+		contents.lines()
+			.filter_map(|line| line.trim().strip_prefix(key)?.strip_prefix('='))
+			.map(|value| value.trim().trim_matches('"').trim_matches('\'').to_owned())
+			.find(|value| !value.is_empty())
+	};
+	value_of("PRETTY_NAME").or_else(|| value_of("NAME"))
+}
+
+/// The 'MemTotal' line from the contents of /proc/meminfo, in kB.
+pub fn parse_meminfo_total_kb(contents: &str) -> Option<u64> {
+	let line: &str = contents.lines().find(|line| line.starts_with("MemTotal:"))?;
+	line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Recognize a container runtime from the contents of /proc/1/cgroup.  None if PID 1 does not look containerized.
+pub fn container_runtime_from_cgroup(contents: &str) -> Option<String> {
+	const MARKERS: [(&str, &str); 5] = [
+		("/docker", "docker"),
+		("/kubepods", "kubernetes"),
+		("/libpod", "podman"),
+		("/containerd", "containerd"),
+		("/lxc", "lxc"),
+	];
+	MARKERS.iter()
+		.find(|(marker, _)| contents.lines().any(|line| line.contains(marker)))
+		.map(|(_, runtime)| (*runtime).to_owned())
+}
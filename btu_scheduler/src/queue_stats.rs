@@ -0,0 +1,137 @@
+// queue_stats.rs
+
+/*
+	Per-queue accounting, for capacity planning.
+
+	Each time BTU pushes a job onto an RQ queue, it increments the queue's field in a hash for that UTC day
+	('btu_scheduler:queue_stats:<YYYY-MM-DD>'), and records the time in 'btu_scheduler:queue_last_enqueue'.
+	Daily hashes expire after 'queue_stats_retention_days'.  Writes are best-effort: a failure is logged, and never
+	prevents a job from being enqueued.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use redis::{Commands, RedisError};
+use tracing::warn;
+
+use crate::config::AppConfig;
+use crate::redis_keys::queue_stats_key;
+pub use crate::redis_keys::RQ_KEY_QUEUE_LAST_ENQUEUE;
+
+/// Default for 'queue_stats_retention_days'.
+pub const DEFAULT_QUEUE_STATS_RETENTION_DAYS: u32 = 90;
+
+/// The Redis commands that account for one job enqueued on 'queue_name'.
+pub fn record_enqueue_pipeline(queue_name: &str, now: DateTime<Utc>, retention_days: u32) -> redis::Pipeline {
+	let daily_key: String = queue_stats_key(now.date_naive());
+	let mut pipeline = redis::pipe();
+	pipeline
+		.hincr(&daily_key, queue_name, 1).ignore()
+		// Refreshed on every write, so a day's hash lives 'retention_days' past its last enqueue.
+		.expire(&daily_key, retention_days.max(1) as usize * 86_400).ignore()
+		.hset(RQ_KEY_QUEUE_LAST_ENQUEUE, queue_name, now.to_rfc3339_opts(SecondsFormat::Secs, true)).ignore();
+	pipeline
+}
+
+/// Account for one job enqueued on 'queue_name'.  A failure is only logged.
+pub fn record_enqueue(app_config: &AppConfig, redis_conn: &mut redis::Connection, queue_name: &str, now: DateTime<Utc>) {
+	let retention_days: u32 = app_config.queue_stats_retention_days.unwrap_or(DEFAULT_QUEUE_STATS_RETENTION_DAYS);
+	let result: Result<(), RedisError> = record_enqueue_pipeline(queue_name, now, retention_days).query(redis_conn);
+	if let Err(error) = result {
+		warn!("Unable to record queue statistics for queue '{}': {}", queue_name, error);
+	}
+}
+
+/// One queue's enqueues over the report's days.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueueStats {
+	pub queue_name: String,
+	pub total: u64,
+	pub per_day: BTreeMap<NaiveDate, u64>,  // only the days with enqueues
+	pub last_enqueue: Option<DateTime<Utc>>,
+}
+
+/// Enqueues per queue, over the 'days' UTC days ending 'to' (inclusive).
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueueStatsReport {
+	pub days: u32,
+	pub from: NaiveDate,
+	pub to: NaiveDate,
+	pub queues: Vec<QueueStats>,  // by name
+	pub total: u64,
+}
+
+impl QueueStatsReport {
+	/// Average enqueues per day for one queue, over every day of the report (including days without enqueues).
+	pub fn daily_average(&self, queue: &QueueStats) -> f64 {
+		queue.total as f64 / f64::from(self.days.max(1))
+	}
+}
+
+/// The dates of the 'days' UTC days ending 'to', oldest first.
+pub fn report_dates(to: NaiveDate, days: u32) -> Vec<NaiveDate> {
+	(0..i64::from(days.max(1))).rev().map(|offset| to - Duration::days(offset)).collect()
+}
+
+fn queue_entry<'a>(queues: &'a mut BTreeMap<String, QueueStats>, queue_name: &str) -> &'a mut QueueStats {
+	queues.entry(queue_name.to_owned()).or_insert_with(|| QueueStats {
+		queue_name: queue_name.to_owned(), total: 0, per_day: BTreeMap::new(), last_enqueue: None
+	})
+}
+
+/**
+  Combine the daily hashes into a report.  'buckets' holds each day's counts (missing days are simply absent), and
+  'last_enqueue' the RFC 3339 times from RQ_KEY_QUEUE_LAST_ENQUEUE.  A queue appears when it has enqueues in the report's
+  days, or a last enqueue time.  Counts that are not numbers are ignored.
+*/
+pub fn aggregate(to: NaiveDate, days: u32, buckets: &[(NaiveDate, HashMap<String, String>)], last_enqueue: &HashMap<String, String>) -> QueueStatsReport {
+	let dates: Vec<NaiveDate> = report_dates(to, days);
+	let mut queues: BTreeMap<String, QueueStats> = BTreeMap::new();
+	for (date, counts) in buckets.iter().filter(|(date, _)| dates.contains(date)) {
+		for (queue_name, count) in counts {
+			let Ok(count) = count.parse::<u64>() else { continue };
+			let queue: &mut QueueStats = queue_entry(&mut queues, queue_name);
+			queue.total += count;
+			*queue.per_day.entry(*date).or_insert(0) += count;
+		}
+	}
+	for (queue_name, timestamp) in last_enqueue {
+		if let Ok(timestamp) = DateTime::parse_from_rfc3339(timestamp) {
+			queue_entry(&mut queues, queue_name).last_enqueue = Some(timestamp.with_timezone(&Utc));
+		}
+	}
+	let queues: Vec<QueueStats> = queues.into_values().collect();
+	QueueStatsReport {
+		days: dates.len() as u32,
+		from: dates[0],
+		to,
+		total: queues.iter().map(|queue| queue.total).sum(),
+		queues,
+	}
+}
+
+/// Read the daily hashes for the 'days' UTC days ending today, and aggregate them.
+pub fn read_report(redis_conn: &mut redis::Connection, days: u32, now: DateTime<Utc>) -> Result<QueueStatsReport, RedisError> {
+	let today: NaiveDate = now.date_naive();
+	let mut pipeline = redis::pipe();
+	let dates: Vec<NaiveDate> = report_dates(today, days);
+	for date in &dates {
+		pipeline.hgetall(queue_stats_key(*date));
+	}
+	let counts: Vec<HashMap<String, String>> = pipeline.query(redis_conn)?;
+	let buckets: Vec<(NaiveDate, HashMap<String, String>)> = dates.into_iter().zip(counts).collect();
+	let last_enqueue: HashMap<String, String> = redis_conn.hgetall(RQ_KEY_QUEUE_LAST_ENQUEUE)?;
+	Ok(aggregate(today, days, &buckets, &last_enqueue))
+}
+
+/// Seconds since BTU last enqueued a job on each queue.
+pub fn last_enqueue_ages(redis_conn: &mut redis::Connection, now: DateTime<Utc>) -> Result<BTreeMap<String, i64>, RedisError> {
+	let last_enqueue: HashMap<String, String> = redis_conn.hgetall(RQ_KEY_QUEUE_LAST_ENQUEUE)?;
+	Ok(last_enqueue.into_iter()
+		.filter_map(|(queue_name, timestamp)| {
+			let timestamp: DateTime<Utc> = DateTime::parse_from_rfc3339(&timestamp).ok()?.with_timezone(&Utc);
+			Some((queue_name, (now - timestamp).num_seconds()))
+		})
+		.collect())
+}
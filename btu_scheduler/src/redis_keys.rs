@@ -0,0 +1,180 @@
+// redis_keys.rs
+
+/*
+	Every Redis key BTU writes is named here, and nowhere else.  Each family of keys is listed in KEY_FAMILIES, with how it
+	relates to a Task Schedule.  Cancelling a Task Schedule then removes everything the scheduler created for it; a new
+	family added here cannot be forgotten, and a unit test rejects any "btu_scheduler:" key named outside this module.
+*/
+
+use redis::RedisError;
+
+/// Every Redis key owned by BTU begins with this prefix.  Keys belonging to Python RQ ("rq:*") are never exported or imported.
+pub static BTU_KEY_PREFIX: &str = "btu_scheduler:";
+
+/// Sorted set of TSIKs ('<Task Schedule ID>|<Unix time>'), scored by their Next Execution Time.
+pub static RQ_KEY_SCHEDULED_TASKS: &str = "btu_scheduler:task_execution_times";
+/// Hash of Task Schedule ID to the correlation ID of the socket request whose RQ Job has not been created yet.
+pub static RQ_KEY_CORRELATION_IDS: &str = "btu_scheduler:correlation_ids";
+/// An enabled Task Schedule whose cron expression yields no Next Execution Time is flagged with this key, plus its ID.
+pub static RQ_KEY_UNSCHEDULABLE_PREFIX: &str = "btu_scheduler:unschedulable:";
+/// Counts a Task Schedule's consecutive failed runs, when followed by its ID.
+pub static RQ_KEY_FAILURE_COUNT_PREFIX: &str = "btu_scheduler:failure_count:";
+/// The set of Task Schedule IDs whose circuit is open.
+pub static RQ_KEY_CIRCUIT_OPEN: &str = "btu_scheduler:circuit_open";
+/// Maps a Task Schedule ID to the Unix time of its manually overridden next run.
+pub static RQ_KEY_NEXT_RUN_OVERRIDE: &str = "btu_scheduler:next_run_override";
+/// Hash of Task Schedule ID to the ID of the most recent RQ Job enqueued for it; read by schedules that depend on it.
+pub static RQ_KEY_LAST_JOB_IDS: &str = "btu_scheduler:last_job_ids";
+/// Hash of Task Schedule ID to the number of runs its 'repeat' count still allows.
+pub static RQ_KEY_TASK_REPEAT_COUNTS: &str = "btu_scheduler:task_repeat_counts";
+/// Hash of Task Schedule ID to the 'repeat' its remaining count (above) started from.
+pub static RQ_KEY_TASK_REPEAT_TOTALS: &str = "btu_scheduler:task_repeat_totals";
+/// Records the running instances of a BTU Task, when followed by its ID.
+pub static RQ_KEY_RUNNING_PREFIX: &str = "btu_scheduler:running:";
+/// The Redis hash of jobs BTU enqueued, and is watching: Job ID -> WatchedJob as JSON.
+pub static RQ_KEY_WATCHED_JOBS: &str = "btu_scheduler:watched_jobs";
+/// The Redis stream that receives scheduler events.
+pub static EVENT_STREAM_KEY: &str = "btu_scheduler:events";
+/// Followed by a socket request's idempotency key, its stored response (or its reservation, while it is processed).
+pub static IDEMPOTENCY_KEY_PREFIX: &str = "btu_scheduler:idempotency:";
+/// Followed by a UTC date ('YYYY-MM-DD'), a hash of queue name to the number of jobs BTU enqueued on it that day.
+pub static RQ_KEY_QUEUE_STATS_PREFIX: &str = "btu_scheduler:queue_stats:";
+/// Hash of queue name to the time (RFC 3339, UTC) BTU last enqueued a job on it.
+pub static RQ_KEY_QUEUE_LAST_ENQUEUE: &str = "btu_scheduler:queue_last_enqueue";
+/// Followed by a Task Schedule ID, a sorted set of its recent runs (RunRecords as JSON), scored by Unix time.
+pub static RQ_KEY_TASK_RUN_HISTORY_PREFIX: &str = "btu_scheduler:task_run_history:";
+
+/// How a family of keys relates to Task Schedules.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ownership {
+	Tsiks,          // members '<schedule ID>|<Unix time>' of this sorted set; a schedule's upcoming runs
+	KeyPrefix,      // '<prefix><schedule ID>' is the schedule's own key
+	HashField,      // the schedule's ID is a field of this hash
+	SetMember,      // the schedule's ID is a member of this set
+	PerTask,        // '<prefix><Task ID>'; shared by every schedule of a BTU Task
+	History,        // '<prefix><schedule ID>'; an audit trail, kept when the schedule is cancelled, until it expires
+	Shared,         // not tied to any one schedule
+}
+
+/// One family of BTU's Redis keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyFamily {
+	pub key: &'static str,  // the key; or, for KeyPrefix, PerTask and History, the prefix
+	pub ownership: Ownership,
+}
+
+/// Every family of keys BTU writes.
+pub static KEY_FAMILIES: &[KeyFamily] = &[
+	KeyFamily { key: RQ_KEY_SCHEDULED_TASKS, ownership: Ownership::Tsiks },
+	KeyFamily { key: RQ_KEY_CORRELATION_IDS, ownership: Ownership::HashField },
+	KeyFamily { key: RQ_KEY_UNSCHEDULABLE_PREFIX, ownership: Ownership::KeyPrefix },
+	KeyFamily { key: RQ_KEY_FAILURE_COUNT_PREFIX, ownership: Ownership::KeyPrefix },
+	KeyFamily { key: RQ_KEY_CIRCUIT_OPEN, ownership: Ownership::SetMember },
+	KeyFamily { key: RQ_KEY_NEXT_RUN_OVERRIDE, ownership: Ownership::HashField },
+	KeyFamily { key: RQ_KEY_LAST_JOB_IDS, ownership: Ownership::HashField },
+	KeyFamily { key: RQ_KEY_TASK_REPEAT_COUNTS, ownership: Ownership::HashField },
+	KeyFamily { key: RQ_KEY_TASK_REPEAT_TOTALS, ownership: Ownership::HashField },
+	KeyFamily { key: RQ_KEY_RUNNING_PREFIX, ownership: Ownership::PerTask },
+	KeyFamily { key: RQ_KEY_WATCHED_JOBS, ownership: Ownership::Shared },
+	KeyFamily { key: EVENT_STREAM_KEY, ownership: Ownership::Shared },
+	KeyFamily { key: IDEMPOTENCY_KEY_PREFIX, ownership: Ownership::Shared },
+	KeyFamily { key: RQ_KEY_QUEUE_STATS_PREFIX, ownership: Ownership::Shared },
+	KeyFamily { key: RQ_KEY_QUEUE_LAST_ENQUEUE, ownership: Ownership::Shared },
+	KeyFamily { key: RQ_KEY_TASK_RUN_HISTORY_PREFIX, ownership: Ownership::History },
+];
+
+/// A TSIK (Task Scheduled Instance Key): the member of RQ_KEY_SCHEDULED_TASKS for one run of a Task Schedule.
+pub fn tsik(task_schedule_id: &str, next_unix: i64) -> String {
+	format!("{}{}", tsik_prefix(task_schedule_id), next_unix)
+}
+
+/// What every TSIK of a Task Schedule begins with.
+pub fn tsik_prefix(task_schedule_id: &str) -> String {
+	format!("{}|", task_schedule_id)
+}
+
+/// 'text' with Redis glob metacharacters ('*', '?', '[', ']', '\\') escaped, so a MATCH pattern matches it literally.
+/// Frappe names may contain any of them.
+pub fn escape_glob(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for character in text.chars() {
+		if matches!(character, '*' | '?' | '[' | ']' | '\\') {
+			escaped.push('\\');
+		}
+		escaped.push(character);
+	}
+	escaped
+}
+
+/// The ZSCAN MATCH pattern for every TSIK of a Task Schedule.
+pub fn tsik_match_pattern(task_schedule_id: &str) -> String {
+	format!("{}*", escape_glob(&tsik_prefix(task_schedule_id)))
+}
+
+pub fn unschedulable_key(task_schedule_id: &str) -> String {
+	format!("{}{}", RQ_KEY_UNSCHEDULABLE_PREFIX, task_schedule_id)
+}
+
+pub fn failure_count_key(task_schedule_id: &str) -> String {
+	format!("{}{}", RQ_KEY_FAILURE_COUNT_PREFIX, task_schedule_id)
+}
+
+pub fn task_run_history_key(task_schedule_id: &str) -> String {
+	format!("{}{}", RQ_KEY_TASK_RUN_HISTORY_PREFIX, task_schedule_id)
+}
+
+/// The daily hash of enqueue counts per queue.
+pub fn queue_stats_key(date: chrono::NaiveDate) -> String {
+	format!("{}{}", RQ_KEY_QUEUE_STATS_PREFIX, date.format("%Y-%m-%d"))
+}
+
+/// The sorted set of a Task's running instances.  On a multi-site bench, the Task ID is prefixed with its site's name.
+pub fn running_key(site: Option<&str>, task_key: &str) -> String {
+	match site {
+		Some(site) => format!("{}{}{}{}", RQ_KEY_RUNNING_PREFIX, site, crate::config::SITE_SEPARATOR, task_key),
+		None => format!("{}{}", RQ_KEY_RUNNING_PREFIX, task_key),
+	}
+}
+
+/// Something in Redis that belongs to one Task Schedule, besides its TSIKs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScheduleKey {
+	Key(String),
+	HashField { key: &'static str, field: String },
+	SetMember { key: &'static str, member: String },
+}
+
+impl std::fmt::Display for ScheduleKey {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ScheduleKey::Key(key) => write!(f, "{}", key),
+			ScheduleKey::HashField { key, field } => write!(f, "{} (field '{}')", key, field),
+			ScheduleKey::SetMember { key, member } => write!(f, "{} (member '{}')", key, member),
+		}
+	}
+}
+
+/// Everything besides its TSIKs that the scheduler may have created for a Task Schedule.
+pub fn schedule_keys(task_schedule_id: &str) -> Vec<ScheduleKey> {
+	KEY_FAMILIES.iter().filter_map(|family| match family.ownership {
+		Ownership::KeyPrefix => Some(ScheduleKey::Key(format!("{}{}", family.key, task_schedule_id))),
+		Ownership::HashField => Some(ScheduleKey::HashField { key: family.key, field: task_schedule_id.to_owned() }),
+		Ownership::SetMember => Some(ScheduleKey::SetMember { key: family.key, member: task_schedule_id.to_owned() }),
+		Ownership::Tsiks | Ownership::PerTask | Ownership::Shared | Ownership::History => None,
+	}).collect()
+}
+
+/// Delete everything schedule_keys() names for a Task Schedule, in one transaction.  Returns how many existed.
+pub fn delete_schedule_keys(redis_conn: &mut redis::Connection, task_schedule_id: &str) -> Result<usize, RedisError> {
+	let mut pipeline = redis::pipe();
+	pipeline.atomic();
+	for schedule_key in schedule_keys(task_schedule_id) {
+		match schedule_key {
+			ScheduleKey::Key(key) => pipeline.del(key),
+			ScheduleKey::HashField { key, field } => pipeline.hdel(key, field),
+			ScheduleKey::SetMember { key, member } => pipeline.srem(key, member),
+		};
+	}
+	let removed: Vec<usize> = pipeline.query(redis_conn)?;
+	Ok(removed.iter().sum())
+}
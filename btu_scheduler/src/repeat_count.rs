@@ -0,0 +1,141 @@
+// repeat_count.rs
+
+/*
+	A Task Schedule with a 'repeat' count runs that many times, and then stops; like the 'repeat' argument of rq-scheduler.
+
+	The remaining count is kept in the hash 'btu_scheduler:task_repeat_counts', keyed by Task Schedule ID.  It starts at
+	'repeat' the first time a run is enqueued, and each successfully enqueued run decrements it.  Once it reaches 0, Thread #3
+	no longer pushes the schedule back onto the Internal Queue, and removes any upcoming runs still in Redis; Thread #1 and
+	the full refresh no longer schedule it.  A failed run does not count.
+
+	A 'repeat' that is empty (NULL), or 0, means forever; that is how Frappe stores an Int field left blank.  Cancelling the
+	Task Schedule (which BTU App does when it is disabled) deletes the count, so re-enabling it starts over.
+
+	The 'repeat' a count started from is kept beside it, in 'btu_scheduler:task_repeat_totals'.  When the column is edited,
+	the old count no longer applies: the schedule is not exhausted, and its next run starts the count over from the new value.
+*/
+
+use redis::{Commands, RedisError};
+use tracing::{error, info, warn};
+
+use crate::ids::TaskScheduleId;
+use crate::schedule_queue::{QueueEntry, QueueSource, ScheduleQueue};
+
+pub use crate::redis_keys::{RQ_KEY_TASK_REPEAT_COUNTS, RQ_KEY_TASK_REPEAT_TOTALS};
+
+/// What counting one run did to a Task Schedule's repeat count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepeatTransition {
+	Unlimited,                     // no 'repeat'; runs forever
+	Remaining { remaining: u32 },  // counted; this many runs are left
+	Exhausted,                     // that was the last run
+}
+
+/// A Task Schedule's stored repeat count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepeatCount {
+	pub remaining: u32,
+	pub repeat: Option<u32>,  // the 'repeat' the count started from; None for counts stored before it was recorded
+}
+
+/// Where remaining repeat counts are kept.  In the daemon, that is Redis.
+pub trait RepeatStore {
+	fn read_count(&mut self, task_schedule_id: &str) -> Option<RepeatCount>;
+	/// Start the count at 'repeat' if there is none yet, or it started from another 'repeat'; then decrement it, and
+	/// return what remains.
+	fn count_down(&mut self, task_schedule_id: &str, repeat: u32) -> u32;
+	/// Remove the Task Schedule's upcoming runs; returns how many there were.
+	fn remove_upcoming_runs(&mut self, task_schedule_id: &str) -> usize;
+}
+
+impl RepeatStore for redis::Connection {
+	fn read_count(&mut self, task_schedule_id: &str) -> Option<RepeatCount> {
+		let result: Result<(Option<u32>, Option<u32>), RedisError> = redis::pipe()
+			.hget(RQ_KEY_TASK_REPEAT_COUNTS, task_schedule_id)
+			.hget(RQ_KEY_TASK_REPEAT_TOTALS, task_schedule_id)
+			.query(self);
+		match result {
+			Ok((remaining, repeat)) => remaining.map(|remaining| RepeatCount { remaining, repeat }),
+			Err(error) => {
+				warn!("Unable to read field '{}' of Redis hash '{}': {}", task_schedule_id, RQ_KEY_TASK_REPEAT_COUNTS, error);
+				None
+			}
+		}
+	}
+
+	fn count_down(&mut self, task_schedule_id: &str, repeat: u32) -> u32 {
+		// WATCH both hashes, so the count cannot change between reading it, and starting it over.
+		let result: Result<(i64,), RedisError> = redis::transaction(self, &[RQ_KEY_TASK_REPEAT_COUNTS, RQ_KEY_TASK_REPEAT_TOTALS], |con, pipe| {
+			let (remaining, counted_from): (Option<u32>, Option<u32>) = redis::pipe()
+				.hget(RQ_KEY_TASK_REPEAT_COUNTS, task_schedule_id)
+				.hget(RQ_KEY_TASK_REPEAT_TOTALS, task_schedule_id)
+				.query(con)?;
+			let current: Option<RepeatCount> = remaining.map(|remaining| RepeatCount { remaining, repeat: counted_from });
+			if remaining_runs(current, repeat).is_none() {
+				pipe.hset(RQ_KEY_TASK_REPEAT_COUNTS, task_schedule_id, repeat).ignore();
+			}
+			pipe.hset(RQ_KEY_TASK_REPEAT_TOTALS, task_schedule_id, repeat).ignore()
+				.hincr(RQ_KEY_TASK_REPEAT_COUNTS, task_schedule_id, -1)
+				.query(con)
+		});
+		match result {
+			Ok((remaining,)) => u32::try_from(remaining).unwrap_or(0),
+			Err(error) => {
+				// Not counted; better one run too many than a schedule stopped early.
+				error!("Unable to decrement field '{}' of Redis hash '{}': {}", task_schedule_id, RQ_KEY_TASK_REPEAT_COUNTS, error);
+				repeat
+			}
+		}
+	}
+
+	fn remove_upcoming_runs(&mut self, task_schedule_id: &str) -> usize {
+		crate::scheduler::remove_scheduled_runs(self, &TaskScheduleId::from(task_schedule_id)).unwrap_or_else(|error| {
+			error!("Unable to remove the upcoming runs of Task Schedule {}: {}", task_schedule_id, error);
+			0
+		})
+	}
+}
+
+/// The number of runs a 'repeat' column allows, or None for forever.
+pub fn effective_repeat(repeat: Option<u32>) -> Option<u32> {
+	repeat.filter(|repeat| *repeat > 0)
+}
+
+/// Count one successfully enqueued run.
+pub fn record_run<S: RepeatStore>(store: &mut S, task_schedule_id: &str, repeat: Option<u32>) -> RepeatTransition {
+	let Some(repeat) = effective_repeat(repeat) else {
+		return RepeatTransition::Unlimited;
+	};
+	match store.count_down(task_schedule_id, repeat) {
+		0 => RepeatTransition::Exhausted,
+		remaining => RepeatTransition::Remaining { remaining },
+	}
+}
+
+/// The runs left of a stored count, if it still applies to 'repeat': None when there is no count, or it started from another 'repeat'.
+pub fn remaining_runs(count: Option<RepeatCount>, repeat: u32) -> Option<u32> {
+	count.filter(|count| count.repeat.map_or(true, |counted_from| counted_from == repeat)).map(|count| count.remaining)
+}
+
+/// Has the Task Schedule already run every time its 'repeat' allows?
+pub fn is_exhausted<S: RepeatStore>(store: &mut S, task_schedule_id: &str, repeat: Option<u32>) -> bool {
+	effective_repeat(repeat).map_or(false, |repeat| remaining_runs(store.read_count(task_schedule_id), repeat) == Some(0))
+}
+
+/**
+  Thread #3's step after enqueuing a run: count it, then push the Task Schedule back onto the Internal Queue for its next run.\
+  After its last run, the schedule is not pushed back, and its remaining upcoming runs are removed.
+*/
+pub fn count_run_and_requeue<S: RepeatStore>(store: &mut S, internal_queue: &mut ScheduleQueue, schedule_key: &TaskScheduleId,
+                                             repeat: Option<u32>) -> RepeatTransition {
+	let transition: RepeatTransition = record_run(store, schedule_key.as_str(), repeat);
+	if transition == RepeatTransition::Exhausted {
+		let removed: usize = store.remove_upcoming_runs(schedule_key.as_str());
+		info!("Task Schedule {} has run all {} times of its 'repeat', and will not be scheduled again ({} upcoming run(s) removed).",
+		      schedule_key, repeat.unwrap_or_default(), removed);
+	}
+	else {
+		internal_queue.push_priority(QueueEntry::new(schedule_key.clone(), QueueSource::PostRunRequeue));
+	}
+	transition
+}
@@ -9,6 +9,7 @@ and not cross over into how the BTU works.
 
 use std::fmt;
 use std::collections::HashMap;
+use std::io::{Read, Write};
 
 use chrono::{DateTime, Utc};
 use redis::{Commands, RedisError};
@@ -16,13 +17,30 @@ use uuid::Uuid;
 use tracing::{trace, debug, info, warn, error, span, Level};
 
 use crate::config::AppConfig;
+use crate::errors::RQError;
+use crate::ids::{CorrelationId, RqJobId};
+use crate::pickle::PickleValue;
 
 static RQ_JOB_PREFIX: &str = "rq:job";
 
-#[derive(Debug)]
+/// Hash field marking an RQ Job whose 'data' was compressed by BTU.  Patched workers check this before unpickling.
+pub static COMPRESSION_MARKER_FIELD: &str = "btu_compressed";
+pub static COMPRESSION_MARKER_ZLIB: &str = "zlib";
+/// Payloads smaller than this are never compressed; the savings would be negligible.
+pub const COMPRESSION_THRESHOLD_BYTES: usize = 1024;
+/// Hash field holding the correlation ID of the socket request that led to this RQ Job.  RQ ignores it.
+pub static CORRELATION_ID_FIELD: &str = "btu_correlation_id";
+
+/// Key of the RQ Job's 'meta' dict holding the Task Schedule's 'argument_overrides' (the raw JSON text entered in Frappe).
+pub static ARGUMENT_OVERRIDES_META_KEY: &str = "btu_argument_overrides";
+
+/// The queue RQ Jobs are placed on, unless something names another.
+pub static DEFAULT_QUEUE_NAME: &str = "default";
+
+#[derive(Clone, Debug)]
 pub struct RQJob {
 	pub job_key: String,
-	pub job_key_short: String,
+	pub job_key_short: RqJobId,
 	created_at: DateTime<Utc>,
 	pub data: Vec<u8>,
 	pub description: String,
@@ -37,8 +55,15 @@ pub struct RQJob {
 	status: Option<String>,  // not initially populated
 	pub timeout: u32,
 	worker_name: String,
+	pub extra_fields: HashMap<String, Vec<u8>>,  // hash fields not modeled above (e.g. added by newer RQ versions); written back verbatim
 }
 
+/// The rq:job hash fields that RQJob models explicitly.  Anything else is kept in 'extra_fields'.
+pub static RQ_JOB_MODELED_FIELDS: &[&str] = &[
+	"status", "worker_name", "ended_at", "result_ttl", "enqueued_at", "last_heartbeat", "origin",
+	"description", "started_at", "created_at", "timeout", "data", "meta", "exc_info",
+];
+
 fn option_string_to_owned(element: &Option<String>) -> String {
 	// Awkward, but makes for cleaner syntax in 'save_to_redis()' below.
 	match element {
@@ -59,7 +84,7 @@ impl RQJob {
 		let uuid_string: String = Uuid::new_v4().to_hyphenated().to_string();
 		RQJob {
 			job_key: format!("{}:{}", RQ_JOB_PREFIX, uuid_string),  // str(uuid4())
-			job_key_short: uuid_string,
+			job_key_short: RqJobId::from(uuid_string),
 			created_at: chrono::offset::Utc::now(),
 			description: "".to_owned(),
 			data: Vec::new(),
@@ -68,22 +93,72 @@ impl RQJob {
 			exc_info: None,
 			last_heartbeat: chrono::offset::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
 			meta: None,
-			origin: "default".to_owned(),  // begin with the queue named 'default'
+			origin: DEFAULT_QUEUE_NAME.to_owned(),  // begin with the queue named 'default'
 			result_ttl: None,
 			started_at: None,
 			status: None,
 			timeout: 3600,  // default of 3600 seconds (1 hour)
 			worker_name: "".to_owned(),
+			extra_fields: HashMap::new(),
 		}
 	}
 
+	/// Record the socket request that led to this job; saved as the hash field CORRELATION_ID_FIELD.
+	pub fn set_correlation_id(&mut self, correlation_id: &CorrelationId) {
+		self.extra_fields.insert(CORRELATION_ID_FIELD.to_owned(), correlation_id.as_str().as_bytes().to_vec());
+	}
+
+	/// The RQ queue the job is pushed onto by enqueue_job_immediate().
+	pub fn set_origin(&mut self, queue_name: &str) {
+		self.origin = queue_name.to_owned();
+	}
+
+	pub fn correlation_id(&self) -> Option<CorrelationId> {
+		self.extra_fields.get(CORRELATION_ID_FIELD).map(|value| CorrelationId::from(String::from_utf8_lossy(value).into_owned()))
+	}
+
+	/// Record a Task Schedule's 'argument_overrides' in 'meta', as the pickled dict {ARGUMENT_OVERRIDES_META_KEY: overrides}.
+	/// RQ workers unpickle 'meta', so it cannot hold the raw string.
+	pub fn set_argument_overrides(&mut self, argument_overrides: &str) {
+		self.meta = Some(pickle_string_dict(&[(ARGUMENT_OVERRIDES_META_KEY, argument_overrides)]));
+	}
+
+	/// What 'data' will run, such as "btu.manual_tests.ping_with_wait(10)"; or its length, when it cannot be read.
+	pub fn describe_data(&self) -> String {
+		describe_job_data(&self.data).unwrap_or_else(|| format!("<bytes> with length {}", self.data.len()))
+	}
+
+	/// The 'argument_overrides' recorded in 'meta', if any.
+	pub fn argument_overrides(&self) -> Option<String> {
+		let entries: Vec<(String, String)> = unpickle_string_dict(self.meta.as_deref()?)?;
+		entries.into_iter().find(|(key, _)| key == ARGUMENT_OVERRIDES_META_KEY).map(|(_, value)| value)
+	}
+
 	/// Save the RQ struct to the Redis database.
-	pub fn save_to_redis(&self, app_config: &AppConfig) -> () {
-		// This function was a lot more work than expected.  Even though I'm takig a reference to the struct,
-		// I have to explicitely clone() all Strings.  And for Option<String>, explicitely as_ref()
-		let mut redis_conn = get_redis_connection(app_config, true).expect("Unable to establish a connection to Redis.");
+	/// The writes are one MULTI/EXEC transaction, so the Job's hash appears fully formed or not at all.  If the connection
+	/// drops before EXEC, Redis discards the queued commands; a worker can never pick up a Job with missing 'data'.
+	pub fn save_to_redis(&self, app_config: &AppConfig) -> Result<(), RQError> {
+		let mut redis_conn = get_redis_connection(app_config, false).ok_or(RQError::NoConnection)?;
+		self.save_pipeline(app_config.compress_job_data).query::<()>(&mut redis_conn)
+			.map_err(|source| RQError::SaveFailed { job_key: self.job_key.clone(), source })
+	}
 
-		let values: Vec<(&'static str, String)> =  vec![
+	/// The single atomic pipeline that save_to_redis() sends.
+	pub fn save_pipeline(&self, compression_enabled: bool) -> redis::Pipeline {
+		let fields: Vec<(String, Vec<u8>)> = self.to_redis_fields(compression_enabled);
+		let mut pipeline = redis::pipe();
+		pipeline.atomic().hset_multiple(&self.job_key, &fields).ignore();
+		if !fields.iter().any(|(name, _)| name == COMPRESSION_MARKER_FIELD) {
+			// If this job was previously saved compressed, the stale marker must not survive.
+			pipeline.hdel(&self.job_key, COMPRESSION_MARKER_FIELD).ignore();
+		}
+		pipeline
+	}
+
+	/// The hash fields written by save_to_redis(), including any 'extra_fields' exactly as they were read.
+	pub fn to_redis_fields(&self, compression_enabled: bool) -> Vec<(String, Vec<u8>)> {
+
+		let mut fields: Vec<(String, Vec<u8>)> = vec![
 			( "status", option_string_to_owned(&self.status) ),
 			( "worker_name", self.worker_name.clone() ),
 			( "ended_at", option_string_to_owned(&self.ended_at)),
@@ -95,15 +170,185 @@ impl RQJob {
 			( "started_at",  option_string_to_owned(&self.started_at) ),
 			( "created_at", utc_to_rq_string(self.created_at) ),
 			( "timeout", self.timeout.to_string() )
-		];
+		].into_iter().map(|(name, value)| (name.to_owned(), value.into_bytes())).collect();
 
-		// When using hset_multiple, the values must all be of the same Type.
-		// In the case below, an Array of Tuples, where the Tuple is (&str, &String)
-		let _: () = redis_conn.hset_multiple(&self.job_key, &values).expect("Failed to execute HSET.");
-		let _: () = redis_conn.hset(&self.job_key, "data", &self.data).expect("failed to execute HSET");
-		if self.meta.is_some() {
-			let _: () = redis_conn.hset(&self.job_key, "meta", &self.meta.as_ref().unwrap()).expect("failed to execute HSET");
+		match compress_job_data_if_enabled(&self.data, compression_enabled) {
+			Some(compressed) => {
+				fields.push(("data".to_owned(), compressed));
+				fields.push((COMPRESSION_MARKER_FIELD.to_owned(), COMPRESSION_MARKER_ZLIB.as_bytes().to_vec()));
+			},
+			None => {
+				fields.push(("data".to_owned(), self.data.clone()));
+			}
+		}
+		if let Some(meta) = &self.meta {
+			fields.push(("meta".to_owned(), meta.clone()));
+		}
+		for (name, value) in &self.extra_fields {
+			fields.push((name.clone(), value.clone()));
+		}
+		fields
+	}
+}
+
+
+/*
+	Just enough of Python's pickle format for a dict of strings, such as an RQ Job's 'meta'.
+	pickle_string_dict() writes protocol 2, which every Python 3 reads.  unpickle_string_dict() reads what Python itself
+	writes for such a dict (protocols 2 through 5, with framing and memoization), using the reader in pickle.rs.
+*/
+
+/// Pickle a dict whose keys and values are all strings.
+pub fn pickle_string_dict(entries: &[(&str, &str)]) -> Vec<u8> {
+	let mut bytes: Vec<u8> = vec![0x80, 2, b'}'];  // PROTO 2, EMPTY_DICT
+	for (key, value) in entries {
+		for text in [key, value] {
+			bytes.push(b'X');  // BINUNICODE: 4-byte little-endian length, then UTF-8
+			bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+			bytes.extend_from_slice(text.as_bytes());
 		}
+		bytes.push(b's');  // SETITEM
+	}
+	bytes.push(b'.');  // STOP
+	bytes
+}
+
+/// Unpickle a dict whose keys and values are all strings.  None for any other pickle.
+pub fn unpickle_string_dict(bytes: &[u8]) -> Option<Vec<(String, String)>> {
+	let PickleValue::Dict(entries) = crate::pickle::unpickle(bytes)? else {
+		return None;
+	};
+	entries.into_iter().map(|entry| match entry {
+		(PickleValue::Text(key), PickleValue::Text(value)) => Some((key, value)),
+		_ => None
+	}).collect()
+}
+
+/**
+  What an RQ Job's 'data' will run, written as a Python call, such as "btu.manual_tests.ping_with_wait(10, site='erp')".\
+  RQ stores a zlib-compressed pickle of (func_name, instance, args, kwargs); uncompressed data is read too.  None when the
+  data is not such a pickle, or its arguments are not plain values.
+*/
+pub fn describe_job_data(data: &[u8]) -> Option<String> {
+	let decompressed: Option<Vec<u8>> = decompress_job_data(data).ok();
+	let call: PickleValue = crate::pickle::unpickle(decompressed.as_deref().unwrap_or(data))?;
+	let PickleValue::Tuple(call) = call else {
+		return None;
+	};
+	match call.as_slice() {
+		[PickleValue::Text(func_name), PickleValue::None, PickleValue::Tuple(args) | PickleValue::List(args), PickleValue::Dict(kwargs)] => {
+			let mut arguments: Vec<String> = args.iter().map(ToString::to_string).collect();
+			for (key, value) in kwargs {
+				match key {
+					PickleValue::Text(name) => arguments.push(format!("{}={}", name, value)),
+					_ => return None
+				}
+			}
+			Some(format!("{}({})", func_name, arguments.join(", ")))
+		},
+		_ => None
+	}
+}
+
+/// Compress an RQ Job's data with zlib.
+pub fn compress_job_data(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+	let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+	encoder.write_all(data)?;
+	encoder.finish()
+}
+
+/// Reverse of compress_job_data().
+pub fn decompress_job_data(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+	let mut decoder = flate2::read::ZlibDecoder::new(data);
+	let mut result: Vec<u8> = Vec::new();
+	decoder.read_to_end(&mut result)?;
+	Ok(result)
+}
+
+/**
+  Returns the compressed payload, or None if the data should be stored as-is.
+  Data is left alone when compression is disabled, when the payload is under COMPRESSION_THRESHOLD_BYTES,
+  or when compressing fails.
+*/
+pub fn compress_job_data_if_enabled(data: &[u8], compression_enabled: bool) -> Option<Vec<u8>> {
+	if !compression_enabled {
+		return None;
+	}
+	if data.len() < COMPRESSION_THRESHOLD_BYTES {
+		debug!("Job data is only {} bytes; skipping compression.", data.len());
+		return None;
+	}
+	match compress_job_data(data) {
+		Ok(compressed) => {
+			debug!("Compressed job data from {} bytes to {} bytes.", data.len(), compressed.len());
+			Some(compressed)
+		},
+		Err(error) => {
+			warn!("Unable to compress job data; storing it uncompressed instead: {}", error);
+			None
+		}
+	}
+}
+
+
+/*
+	Read-only accessors, so that other crates can inspect an RQJob without parsing its Display output.
+*/
+impl RQJob {
+
+	/// When the job was created (UTC).
+	pub fn created_at(&self) -> DateTime<Utc> {
+		self.created_at
+	}
+
+	/// When a worker finished the job, if it has finished.
+	pub fn ended_at(&self) -> Option<&str> {
+		self.ended_at.as_deref()
+	}
+
+	/// When the job was pushed onto a queue, if it has been.
+	pub fn enqueued_at(&self) -> Option<&str> {
+		self.enqueued_at.as_deref()
+	}
+
+	/// Exception information recorded by the worker, if the job failed.
+	pub fn exc_info(&self) -> Option<&str> {
+		self.exc_info.as_deref()
+	}
+
+	/// The last heartbeat written for this job.
+	pub fn last_heartbeat(&self) -> &str {
+		&self.last_heartbeat
+	}
+
+	/// The pickled 'meta' bytes, if any.
+	pub fn meta(&self) -> Option<&[u8]> {
+		self.meta.as_deref()
+	}
+
+	/// The name of the RQ queue this job belongs to.
+	pub fn origin(&self) -> &str {
+		&self.origin
+	}
+
+	/// How long RQ keeps the job's result, if specified.
+	pub fn result_ttl(&self) -> Option<&str> {
+		self.result_ttl.as_deref()
+	}
+
+	/// When a worker started the job, if it has started.
+	pub fn started_at(&self) -> Option<&str> {
+		self.started_at.as_deref()
+	}
+
+	/// The RQ status string (queued, started, finished, failed, ...), if populated.
+	pub fn status(&self) -> Option<&str> {
+		self.status.as_deref()
+	}
+
+	/// The name of the worker that picked up this job, if any.
+	pub fn worker_name(&self) -> &str {
+		&self.worker_name
 	}
 }
 
@@ -126,27 +371,77 @@ impl fmt::Display for RQJob {
 		write!(f,  "job_key: {}\n\
 					job_key_short: {}\n\
 					created_at: {}\n\
-					data: <bytes> with length {}\n\
+					data: {}\n\
 					description: {}\n\
 					ended_at: {:?}\n\
 					enqueued_at: {:?}\n\
 					last_heartbeat: {}\n\
 					origin: {}\n\
 					meta: <bytes> with length {}\n\
+					argument_overrides: {}\n\
 					result_ttl: {:?}\n\
 					started_at: {:?}\n\
 					status: {:#?}\n\
 					timeout: {}\n\
-					worker_name: {}
+					worker_name: {}\n\
+					correlation_id: {}\n\
+					extra_fields: {}
 			",
-			self.job_key, self.job_key_short,  self.created_at, self.data.len(), 
+			self.job_key, self.job_key_short,  self.created_at, self.describe_data(),
 			self.description, self.ended_at, self.enqueued_at,
-			self.last_heartbeat, self.origin, meta_length, self.result_ttl,  
-			self.started_at, self.status, self.timeout, self.worker_name
+			self.last_heartbeat, self.origin, meta_length,
+			self.argument_overrides().unwrap_or("(none)".to_owned()), self.result_ttl,
+			self.started_at, self.status, self.timeout, self.worker_name,
+			self.correlation_id().map_or("(none)".to_owned(), |correlation_id| correlation_id.into_string()),
+			extra_field_names(&self.extra_fields)  // names only; the values may be binary
 		)
 	}
 }
 
+/// As JSON, for 'btu show-job --json': the same fields as Display.  'data' and 'meta' are pickled bytes, so only their
+/// lengths are written, with 'data_summary' naming what the Job runs; of 'extra_fields', only the names.
+impl serde::Serialize for RQJob {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		where S: serde::Serializer
+	{
+		use serde::ser::SerializeStruct;
+		let mut extra_fields: Vec<&str> = self.extra_fields.keys().map(String::as_str).collect();
+		extra_fields.sort_unstable();
+		let mut state = serializer.serialize_struct("RQJob", 20)?;
+		state.serialize_field("job_key", &self.job_key)?;
+		state.serialize_field("job_key_short", &self.job_key_short)?;
+		state.serialize_field("created_at", &utc_to_rq_string(self.created_at))?;
+		state.serialize_field("data_length", &self.data.len())?;
+		state.serialize_field("data_summary", &self.describe_data())?;
+		state.serialize_field("description", &self.description)?;
+		state.serialize_field("ended_at", &self.ended_at)?;
+		state.serialize_field("enqueued_at", &self.enqueued_at)?;
+		state.serialize_field("exc_info", &self.exc_info)?;
+		state.serialize_field("last_heartbeat", &self.last_heartbeat)?;
+		state.serialize_field("origin", &self.origin)?;
+		state.serialize_field("meta_length", &self.meta.as_ref().map_or(0, Vec::len))?;
+		state.serialize_field("argument_overrides", &self.argument_overrides())?;
+		state.serialize_field("result_ttl", &self.result_ttl)?;
+		state.serialize_field("started_at", &self.started_at)?;
+		state.serialize_field("status", &self.status)?;
+		state.serialize_field("timeout", &self.timeout)?;
+		state.serialize_field("worker_name", &self.worker_name)?;
+		state.serialize_field("correlation_id", &self.correlation_id())?;
+		state.serialize_field("extra_fields", &extra_fields)?;
+		state.end()
+	}
+}
+
+
+fn extra_field_names(extra_fields: &HashMap<String, Vec<u8>>) -> String {
+	if extra_fields.is_empty() {
+		return "(none)".to_owned();
+	}
+	let mut names: Vec<&str> = extra_fields.keys().map(String::as_str).collect();
+	names.sort_unstable();
+	names.join(", ")
+}
+
 
 fn bytes_to_hex_string(bytes: &Vec<u8>) -> String {
 
@@ -157,33 +452,277 @@ fn bytes_to_hex_string(bytes: &Vec<u8>) -> String {
 }
 
 
-pub fn enqueue_job_immediate(app_config: &AppConfig, job_id: &str) -> Result<String, std::io::Error> {
+/// The result of enqueue_job_immediate().  The job was enqueued, but 'warnings' may describe something an administrator should check.
+#[derive(Debug, PartialEq)]
+pub struct EnqueueOutcome {
+	pub message: String,
+	pub warnings: Vec<String>,
+}
+
+impl std::fmt::Display for EnqueueOutcome {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.message)
+	}
+}
+
+/**
+  Push an existing RQ Job onto its 'origin' queue.
+
+  If that queue is not already in 'rq:queues', and no live worker listens to it, the job would sit in a queue that
+  nothing serves (often a typo in the queue name).  The job is still enqueued, but the outcome carries a warning;
+  unless 'create_queue' is true, which acknowledges that a new queue is intended.
+*/
+pub fn enqueue_job_immediate(app_config: &AppConfig, job_id: &RqJobId, create_queue: bool) -> Result<EnqueueOutcome, std::io::Error> {
 
 	let mut redis_conn = get_redis_connection(app_config, true).expect("Unable to establish a connection to Redis.");
 	let job = read_job_by_id(app_config, job_id)?;
 
-	// 1. Add the queue name to 'rq:queues'.
+	// 1. Before changing anything, check whether this queue is already known to RQ.
 	let queue_key: String = format!("rq:queue:{}", job.origin);
-	let some_result: Result<u32, RedisError> = redis_conn.sadd("rq:queues", &queue_key);
+	let queue_known: bool = redis_conn.sismember(RQ_KEY_QUEUES, &queue_key).unwrap_or(false);
+	let has_live_workers: bool = !queue_known && queue_has_live_workers(&mut redis_conn, &job.origin);  // only scan workers when it matters
+	let warnings: Vec<String> = unserved_queue_warning(&job.origin, queue_known, has_live_workers, create_queue).into_iter().collect();
+
+	// 2. Add the queue name to 'rq:queues'.
+	let some_result: Result<u32, RedisError> = redis_conn.sadd(RQ_KEY_QUEUES, &queue_key);
 	if some_result.is_err() {
 		return Err(std::io::Error::new(std::io::ErrorKind::Other, some_result.unwrap_err()));
 	}
 
-	// 2. Push the job onto the queue.
+	// 3. Mark the job as queued, and push it onto the queue, in one transaction.  Python RQ does the same in Queue.enqueue_job();
+	//    without these fields, rq-dashboard shows the job as "unknown", and some RQ 1.x workers fail computing the job's age.
 	// NOTE: The return value of 'rpush' is an integer, representing the length of the List, after the completion of the push operation.
-	let push_result: Result<u32, RedisError> = redis_conn.rpush(&queue_key, job_id);
+	let push_result: Result<(u32,), RedisError> = redis::pipe()
+		.atomic()
+		.hset_multiple(&job.job_key, &enqueue_fields(Utc::now())).ignore()
+		.rpush(&queue_key, job_id.as_str())
+		.query(&mut redis_conn);
 	match push_result {
-		Ok(foo) => {
-			return Ok(format!("Enqueued job '{}' for immediate execution. Length of list after 'rpush' operation: {}", job_id, foo))
+		Ok((list_length,)) => {
+			crate::queue_stats::record_enqueue(app_config, &mut redis_conn, &job.origin, Utc::now());
+			let message = format!("Enqueued job '{}' for immediate execution. Length of list after 'rpush' operation: {}", job_id, list_length);
+			Ok(EnqueueOutcome { message, warnings })
 		}
 		Err(bar) => {
-			return Err(std::io::Error::new(std::io::ErrorKind::Other, bar));
+			Err(std::io::Error::new(std::io::ErrorKind::Other, bar))
+		}
+	}
+}
+
+/// The Redis keys that save_to_redis() and enqueue_job_immediate() write for a job, at time 'now'.
+pub fn keys_written_by_enqueue(job: &RQJob, now: DateTime<Utc>) -> Vec<String> {
+	vec![
+		job.job_key.clone(),
+		RQ_KEY_QUEUES.to_owned(),
+		format!("rq:queue:{}", job.origin),
+		crate::redis_keys::queue_stats_key(now.date_naive()),
+		crate::redis_keys::RQ_KEY_QUEUE_LAST_ENQUEUE.to_owned(),
+	]
+}
+
+/*
+	Job dependencies ('depends_on'), in Python RQ's format (RQ 1.8 and later).  A job whose parent has not finished is
+	not pushed onto its queue.  Instead, in Queue.setup_dependencies() and Job.register_dependency(), RQ:
+
+	* writes 'dependency_ids' (a JSON list) and 'dependency_id' (the first, for older RQ) to the job's hash, with status 'deferred';
+	* adds the job to its queue's DeferredJobRegistry, 'rq:deferred:<queue>', scored by the current Unix time;
+	* adds the job's ID to the parent's set 'rq:job:<parent>:dependents', and the parent's ID to 'rq:job:<job>:dependencies'.
+
+	When the parent finishes, the worker reads 'rq:job:<parent>:dependents', removes each dependent from the registry, and
+	enqueues it.  A parent that fails leaves its dependents deferred.
+*/
+
+/// The set of jobs waiting for 'job_id' to finish.
+pub fn dependents_key(job_id: &str) -> String {
+	format!("{}:{}:dependents", RQ_JOB_PREFIX, job_id)
+}
+
+/// The set of jobs that 'job_id' waits for.
+pub fn dependencies_key(job_id: &str) -> String {
+	format!("{}:{}:dependencies", RQ_JOB_PREFIX, job_id)
+}
+
+/// The DeferredJobRegistry of a queue.
+pub fn deferred_registry_key(queue_name: &str) -> String {
+	format!("rq:deferred:{}", queue_name)
+}
+
+/// The hash fields Python RQ writes on a job that depends on 'parent_id'.
+pub fn dependency_fields(parent_id: &RqJobId) -> [(&'static str, String); 3] {
+	[
+		("status", "deferred".to_owned()),
+		("dependency_id", parent_id.to_string()),
+		("dependency_ids", serde_json::json!([parent_id.as_str()]).to_string()),
+	]
+}
+
+impl RQJob {
+	/// The single atomic pipeline that saves this job as deferred until 'parent_id' finishes.  It is never pushed onto a queue.
+	pub fn save_deferred_pipeline(&self, parent_id: &RqJobId, compression_enabled: bool, now: DateTime<Utc>) -> redis::Pipeline {
+		let mut pipeline = self.save_pipeline(compression_enabled);
+		pipeline.hset_multiple(&self.job_key, &dependency_fields(parent_id)).ignore()
+			.zadd(deferred_registry_key(&self.origin), self.job_key_short.as_str(), now.timestamp()).ignore()
+			.sadd(dependents_key(parent_id.as_str()), self.job_key_short.as_str()).ignore()
+			.sadd(dependencies_key(self.job_key_short.as_str()), parent_id.as_str()).ignore();
+		pipeline
+	}
+
+	/// Save this job to Redis as deferred until 'parent_id' finishes; RQ's worker then enqueues it.
+	pub fn save_deferred_to_redis(&self, app_config: &AppConfig, parent_id: &RqJobId) -> Result<(), RQError> {
+		let mut redis_conn = get_redis_connection(app_config, false).ok_or(RQError::NoConnection)?;
+		self.save_deferred_pipeline(parent_id, app_config.compress_job_data, Utc::now()).query::<()>(&mut redis_conn)
+			.map_err(|source| RQError::SaveFailed { job_key: self.job_key.clone(), source })
+	}
+}
+
+/// The hash fields Python RQ sets on a job at the moment it is pushed onto a queue.
+pub fn enqueue_fields(now: DateTime<Utc>) -> [(&'static str, String); 2] {
+	[
+		("status", "queued".to_owned()),
+		("enqueued_at", utc_to_rq_string(now)),
+	]
+}
+
+/// True when a queue name is letters, digits, dashes, and underscores.  Anything else becomes part of a Redis key, and is
+/// almost certainly a typo.
+pub fn is_valid_queue_name(queue_name: &str) -> bool {
+	!queue_name.is_empty() && queue_name.chars().all(|character| character.is_ascii_alphanumeric() || character == '-' || character == '_')
+}
+
+/// The queue for 'owner's jobs: 'queue_name' when it is valid, otherwise (with a warning) the default queue.
+pub fn queue_name_or_default<'a>(queue_name: &'a str, owner: &str) -> &'a str {
+	let queue_name: &str = queue_name.trim();
+	if queue_name.is_empty() {
+		warn!("{} has no queue name; its jobs are placed on queue '{}'.", owner, DEFAULT_QUEUE_NAME);
+		return DEFAULT_QUEUE_NAME;
+	}
+	if !is_valid_queue_name(queue_name) {
+		warn!("{} has queue name '{}', which is not letters, digits, dashes, and underscores; its jobs are placed on queue '{}'.",
+		      owner, queue_name, DEFAULT_QUEUE_NAME);
+		return DEFAULT_QUEUE_NAME;
+	}
+	queue_name
+}
+
+/// A warning when a job is about to be placed on a queue that nothing serves, and the caller has not asked to create it.
+pub fn unserved_queue_warning(queue_name: &str, queue_known: bool, has_live_workers: bool, create_queue: bool) -> Option<String> {
+	if queue_known || has_live_workers || create_queue {
+		return None;
+	}
+	Some(format!("Queue '{}' did not exist in '{}', and no live worker listens to it.  Is the queue name a typo?  \
+	              (The job was enqueued anyway; use --create-queue to intentionally create a new queue.)", queue_name, RQ_KEY_QUEUES))
+}
+
+/// True if any worker registered in Redis lists this queue.  Each 'rq:worker:*' hash has a comma-separated 'queues' field.
+pub fn queue_has_live_workers(redis_conn: &mut redis::Connection, queue_name: &str) -> bool {
+
+	let worker_keys: Vec<String> = match redis_conn.scan_match::<_, String>(format!("{}*", RQ_WORKER_PREFIX)) {
+		Ok(keys) => keys.collect(),
+		Err(_) => return false
+	};
+	worker_keys.iter().any(|worker_key| {
+		let queues: Option<String> = redis_conn.hget(worker_key, "queues").unwrap_or(None);
+		queues.map_or(false, |queues| worker_serves_queue(&queues, queue_name))
+	})
+}
+
+/// Does a worker's comma-separated 'queues' field include this queue?
+pub fn worker_serves_queue(worker_queues: &str, queue_name: &str) -> bool {
+	worker_queues.split(',').any(|each| each.trim() == queue_name)
+}
+
+
+/// How often wait_for_job_completion() reads the job's hash.
+pub const JOB_POLL_INTERVAL_MS: u64 = 250;
+
+/// What a job's hash says about its progress.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JobProgress {
+	Missing,           // the hash does not exist
+	Pending(String),   // queued, started, deferred, or scheduled; empty before RQ sets a status
+	Finished,
+	Failed { status: String, exc_info: Option<String> },  // failed, stopped, or canceled
+}
+
+pub fn classify_job_progress(job_hash: &HashMap<String, Vec<u8>>) -> JobProgress {
+	if job_hash.is_empty() {
+		return JobProgress::Missing;
+	}
+	let status: String = hashmap_value_to_optstring(job_hash, "status").unwrap_or_default();
+	match status.as_str() {
+		"finished" => JobProgress::Finished,
+		"failed" | "stopped" | "canceled" => JobProgress::Failed { status, exc_info: hashmap_value_to_optstring(job_hash, "exc_info") },
+		_ => JobProgress::Pending(status),
+	}
+}
+
+/// How a job ended, as observed by wait_for_job_completion().  Durations are measured from the start of the wait.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobOutcome {
+	Finished { elapsed: std::time::Duration },
+	Failed { status: String, exc_info: Option<String>, elapsed: std::time::Duration },
+	Vanished { elapsed: std::time::Duration },  // the hash disappeared before a final status was seen (for example, 'result_ttl' 0)
+	TimedOut { last_status: Option<String>, waited: std::time::Duration },
+}
+
+/// Read a job's hash every JOB_POLL_INTERVAL_MS, until its status is final, or 'timeout' has passed.
+pub fn wait_for_job_completion(app_config: &AppConfig, job_id: &RqJobId, timeout: std::time::Duration) -> JobOutcome {
+	let key: String = job_key(job_id.as_str());
+	let mut redis_conn: Option<redis::Connection> = None;
+	poll_job_progress(|| {
+		if redis_conn.is_none() {
+			redis_conn = get_redis_connection(app_config, false);  // reconnect, if a previous read failed
+		}
+		let job_hash: Result<HashMap<String, Vec<u8>>, RedisError> = redis_conn.as_mut()?.hgetall(&key);
+		match job_hash {
+			Ok(job_hash) => Some(classify_job_progress(&job_hash)),
+			Err(error) => {
+				warn!("Unable to read '{}' while waiting for the job to complete: {}", key, error);
+				redis_conn = None;
+				None
+			}
+		}
+	}, timeout, std::time::Duration::from_millis(JOB_POLL_INTERVAL_MS))
+}
+
+/// The state machine behind wait_for_job_completion().  'read_progress' returns None when the hash could not be read.
+pub fn poll_job_progress<F>(mut read_progress: F, timeout: std::time::Duration, poll_interval: std::time::Duration) -> JobOutcome
+	where F: FnMut() -> Option<JobProgress> {
+	let started = std::time::Instant::now();
+	let mut last_status: Option<String> = None;
+	loop {
+		match read_progress() {
+			Some(JobProgress::Finished) => return JobOutcome::Finished { elapsed: started.elapsed() },
+			Some(JobProgress::Failed { status, exc_info }) => return JobOutcome::Failed { status, exc_info, elapsed: started.elapsed() },
+			Some(JobProgress::Missing) if last_status.is_some() => return JobOutcome::Vanished { elapsed: started.elapsed() },
+			Some(JobProgress::Pending(status)) => {
+				if last_status.as_deref() != Some(status.as_str()) {
+					debug!("Job status is now '{}'.", status);
+				}
+				last_status = Some(status);
+			},
+			Some(JobProgress::Missing) | None => {}
+		}
+		let elapsed: std::time::Duration = started.elapsed();
+		if elapsed >= timeout {
+			return JobOutcome::TimedOut { last_status, waited: elapsed };
 		}
+		std::thread::sleep(poll_interval.min(timeout - elapsed));
 	}
 }
 
+/// Delete a job's hash, and its ID from the queue and from RQ's finished and failed registries.
+pub fn delete_job(redis_conn: &mut redis::Connection, job_id: &RqJobId, queue_name: &str) -> Result<(), RedisError> {
+	redis::pipe().atomic()
+		.del(job_key(job_id.as_str())).ignore()
+		.lrem(format!("rq:queue:{}", queue_name), 0, job_id.as_str()).ignore()
+		.zrem(format!("rq:finished:{}", queue_name), job_id.as_str()).ignore()
+		.zrem(format!("rq:failed:{}", queue_name), job_id.as_str()).ignore()
+		.query(redis_conn)
+}
+
 
-pub fn exists_job_by_id(app_config: &AppConfig, job_id: &str) -> bool {
+pub fn exists_job_by_id(app_config: &AppConfig, job_id: &RqJobId) -> bool {
 	/*
 		Given a potential RQ Job ID, return a boolean True if it exists in the RQ database.
 	*/
@@ -207,42 +746,338 @@ pub fn exists_job_by_id(app_config: &AppConfig, job_id: &str) -> bool {
 }
 
 
+/**
+  Where and how to connect to Redis: the equivalent of "redis://:password@host:port/db".\
+  Built directly (rather than parsed from a URL), so a password needs no percent-encoding.
+*/
+pub fn redis_connection_info(app_config: &AppConfig) -> redis::ConnectionInfo {
+	redis::ConnectionInfo {
+		addr: redis::ConnectionAddr::Tcp(app_config.rq_host.clone(), app_config.rq_port as u16),
+		redis: redis::RedisConnectionInfo {
+			db: i64::from(app_config.rq_database.unwrap_or(0)),
+			username: None,
+			password: app_config.rq_password.clone(),
+		}
+	}
+}
+
+/// Connect to Redis, with the configured password and database.
+pub fn open_redis_connection(app_config: &AppConfig) -> Result<redis::Connection, redis::RedisError> {
+	redis::Client::open(redis_connection_info(app_config))?.get_connection()
+}
+
+/// Why a connection to Redis failed, for the log; a wrong password is told apart from an unreachable server.
+pub fn describe_connection_error(app_config: &AppConfig, error: &redis::RedisError) -> String {
+	let database: u32 = app_config.rq_database.unwrap_or(0);
+	match error.kind() {
+		redis::ErrorKind::AuthenticationFailed => format!("Redis authentication failed at host {}:{}; check 'rq_password'.",
+		                                                  app_config.rq_host, app_config.rq_port),
+		redis::ErrorKind::ResponseError if database != 0 => format!("Redis Server at host {}:{} refused to select database {}: {}",
+		                                                            app_config.rq_host, app_config.rq_port, database, error),
+		_ => format!("Unable to establish a connection to Redis Server at host {}:{}", app_config.rq_host, app_config.rq_port),
+	}
+}
+
 pub fn get_redis_connection(app_config: &AppConfig, panic_on_error: bool) -> Option<redis::Connection> {
 	// Returns a Redis Connection, or None.
-	let client: redis::Client = redis::Client::open(format!("redis://{}:{}/", app_config.rq_host, app_config.rq_port)).unwrap();
-	if let Ok(result) = client.get_connection() {
-		Some(result)
+	let result = open_redis_connection(app_config);
+	crate::metrics::record_connection_attempt(crate::metrics::Backend::Redis, result.is_ok());
+	match result {
+		Ok(result) => Some(result),
+		Err(error) => {
+			let message_string = describe_connection_error(app_config, &error);
+			if panic_on_error {
+				panic!("{}", message_string);
+			}
+			error!(message_string);
+			None
+		}
+	}
+}
+
+
+/// A hint for SCAN: how many keys Redis should examine per call.  Each call stays short, no matter how large the keyspace.
+pub const JOB_SCAN_COUNT_HINT: usize = 1000;
+
+/// Every RQ Job key, found using SCAN (never KEYS).  An error (rather than an empty list) when Redis is unreachable, or the SCAN fails.
+pub fn get_all_job_ids(app_config: &AppConfig) -> Result<Vec<String>, RQError> {
+	let mut redis_conn = get_redis_connection(app_config, false).ok_or(RQError::NoConnection)?;
+	scan_job_keys(&mut redis_conn).map_err(scan_failed)
+}
+
+/**
+  Up to 'limit' RQ Job keys, plus a boolean that is true when more keys exist beyond the limit.\
+  Because SCAN is incremental, Redis stops being asked for keys once the limit is reached.
+*/
+pub fn get_job_ids_page(app_config: &AppConfig, limit: usize) -> Result<(Vec<String>, bool), RQError> {
+	let mut redis_conn = get_redis_connection(app_config, false).ok_or(RQError::NoConnection)?;
+	let mut keys: Vec<String> = iter_job_keys(&mut redis_conn).map_err(scan_failed)?
+		.take(limit + 1)
+		.collect();
+	let has_more: bool = keys.len() > limit;
+	keys.truncate(limit);
+	Ok((keys, has_more))
+}
+
+/**
+  How many RQ Job keys exist, counted with SCAN; so Redis is never blocked, and nothing is held in memory.\
+  SCAN may return a key twice while keys are being added or removed, so under churn the count is approximate.
+*/
+pub fn count_jobs(app_config: &AppConfig) -> Result<u64, RQError> {
+	let mut redis_conn = get_redis_connection(app_config, false).ok_or(RQError::NoConnection)?;
+	let count: usize = iter_job_keys(&mut redis_conn).map_err(scan_failed)?.count();
+	Ok(count as u64)
+}
+
+fn scan_failed(source: RedisError) -> RQError {
+	RQError::ScanFailed { pattern: format!("{}:*", RQ_JOB_PREFIX), source }
+}
+
+/// Cursor-based SCAN for the keys of RQ Jobs.  Unlike KEYS, this never blocks Redis for the entire keyspace.
+/// Secondary keys (such as 'rq:job:<id>:dependents') are excluded.
+pub fn scan_job_keys(redis_conn: &mut redis::Connection) -> Result<Vec<String>, RedisError> {
+	let mut keys: Vec<String> = iter_job_keys(redis_conn)?.collect();
+	keys.sort();
+	keys.dedup();  // SCAN may return the same key more than once.
+	Ok(keys)
+}
+
+/// Lazily iterate RQ Job keys.  Each SCAN call is issued only when the previous batch has been consumed.
+pub fn iter_job_keys(redis_conn: &mut redis::Connection) -> Result<impl Iterator<Item = String> + '_, RedisError> {
+	let mut scan_command = redis::cmd("SCAN");
+	scan_command.cursor_arg(0)
+		.arg("MATCH").arg(format!("{}:*", RQ_JOB_PREFIX))
+		.arg("COUNT").arg(JOB_SCAN_COUNT_HINT);
+	let iter = scan_command.iter::<String>(redis_conn)?;
+	Ok(iter.filter(|key| is_primary_job_key(key)))
+}
+
+/// True for 'rq:job:<id>', but false for secondary keys like 'rq:job:<id>:dependents'.
+pub fn is_primary_job_key(key: &str) -> bool {
+	match key.strip_prefix(RQ_JOB_PREFIX).and_then(|rest| rest.strip_prefix(':')) {
+		Some(job_id) => !job_id.is_empty() && !job_id.contains(':'),
+		None => false
+	}
+}
+
+/// The primary key of an RQ Job: 'rq:job:<id>'.
+pub fn job_key(job_id: &str) -> String {
+	format!("{}:{}", RQ_JOB_PREFIX, job_id)
+}
+
+/// The Job ID in a primary job key: 'abc' for 'rq:job:abc'.
+pub fn job_id_from_key(key: &str) -> Option<RqJobId> {
+	if !is_primary_job_key(key) {
+		return None;
+	}
+	key.strip_prefix(RQ_JOB_PREFIX).and_then(|rest| rest.strip_prefix(':')).map(RqJobId::from)
+}
+
+/*
+	Sanity check: is 'rq_port' pointing at the queue Redis?
+
+	A common setup mistake is configuring Frappe's cache Redis (usually port 13000) instead of its queue Redis
+	(usually port 11000).  Connections succeed, but no worker ever sees the jobs.
+*/
+
+/// Key present in any Redis database that Python RQ has used.
+pub static RQ_KEY_QUEUES: &str = "rq:queues";
+/// Prefix of the keys that Python RQ workers register.
+pub static RQ_WORKER_PREFIX: &str = "rq:worker:";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SanityVerdict {
+	LooksLikeRq,    // RQ's own keys are present.
+	EmptyDatabase,  // A brand-new installation; nothing to judge yet.
+	Suspicious,     // Keys exist, but none of them belong to Python RQ.
+	Unreachable,
+}
+
+/// The result of sanity_check_rq_database().
+#[derive(Debug, PartialEq)]
+pub struct SanityReport {
+	pub verdict: SanityVerdict,
+	pub checked: Vec<String>,  // what was looked for
+	pub found: Vec<String>,    // keys found during the check
+}
+
+impl SanityReport {
+	/// A warning for the administrator, or None if nothing looks wrong.
+	pub fn warning_message(&self, app_config: &AppConfig) -> Option<String> {
+		if self.verdict != SanityVerdict::Suspicious {
+			return None;
+		}
+		Some(format!("Redis at {}:{} does not look like a Python RQ database.  Checked for: {}.  \
+		              Is 'rq_port' pointing at Frappe's cache Redis (often port 13000) instead of its queue Redis (often port 11000)?  \
+		              BTU will continue, but workers may never see its jobs.",
+		              app_config.rq_host, app_config.rq_port, self.checked.join("; ")))
+	}
+}
+
+/**
+  Classify a Redis database using a listing of keys found there.\
+  The listing only needs to contain 'rq:queues' and 'rq:worker:*' keys (if any exist), plus any one other key when the database is not empty.
+*/
+pub fn classify_rq_keys(keys: &[String]) -> SanityReport {
+	let checked: Vec<String> = vec![
+		format!("key '{}'", RQ_KEY_QUEUES),
+		format!("keys matching '{}*'", RQ_WORKER_PREFIX),
+	];
+	let found: Vec<String> = keys.iter()
+		.filter(|key| key.as_str() == RQ_KEY_QUEUES || key.starts_with(RQ_WORKER_PREFIX))
+		.cloned()
+		.collect();
+	let verdict = if keys.is_empty() {
+		SanityVerdict::EmptyDatabase
+	}
+	else if found.is_empty() {
+		SanityVerdict::Suspicious
 	}
 	else {
-		let message_string = format!("Unable to establish a connection to Redis Server at host {0}:{1}",
-		                             app_config.rq_host,
-		                             app_config.rq_port);
-		if panic_on_error == true {
-			panic!("{}", message_string);
+		SanityVerdict::LooksLikeRq
+	};
+	SanityReport { verdict, checked, found }
+}
+
+/// Look for signs that the configured Redis is the one Python RQ workers use.  Does not modify anything.
+pub fn sanity_check_rq_database(app_config: &AppConfig) -> SanityReport {
+
+	let Some(mut redis_conn) = get_redis_connection(app_config, false) else {
+		return SanityReport { verdict: SanityVerdict::Unreachable, checked: Vec::new(), found: Vec::new() };
+	};
+
+	let mut keys: Vec<String> = Vec::new();
+	if redis_conn.exists(RQ_KEY_QUEUES).unwrap_or(false) {
+		keys.push(RQ_KEY_QUEUES.to_owned());
+	}
+	if let Ok(mut workers) = redis_conn.scan_match::<_, String>(format!("{}*", RQ_WORKER_PREFIX)) {
+		keys.extend(workers.next());
+	}
+	if keys.is_empty() {
+		// Distinguish an empty database from one that holds something else.
+		if let Ok(Some(any_key)) = redis::cmd("RANDOMKEY").query::<Option<String>>(&mut redis_conn) {
+			keys.push(any_key);
 		}
-		error!(message_string);
-		None
 	}
+	classify_rq_keys(&keys)
 }
 
+/// Python RQ's set of registered worker keys.
+pub static RQ_KEY_WORKERS: &str = "rq:workers";
+/// Prefix of each queue's list of pending Job IDs: 'rq:queue:<name>'.
+pub static RQ_QUEUE_PREFIX: &str = "rq:queue:";
 
-pub fn get_all_job_ids(app_config: &AppConfig) -> Option<Vec<String>> {
+/// One RQ queue, as shown by 'btu list-queues'.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+pub struct QueueSummary {
+	pub queue_name: String,
+	pub pending_jobs: u64,       // length of the 'rq:queue:<name>' list
+	pub workers: Option<usize>,  // registered workers that listen to this queue; None when 'rq:workers' could not be read
+}
 
-	let redis_conn = get_redis_connection(app_config, true);
-	if redis_conn.is_none() {
-		warn!("get_all_job_ids() - Unable to esablish a Redis connection.");
-		return None
+/// The queue name in a member of 'rq:queues': 'default' for 'rq:queue:default'.
+pub fn queue_name_from_key(queue_key: &str) -> &str {
+	queue_key.strip_prefix(RQ_QUEUE_PREFIX).unwrap_or(queue_key)
+}
+
+/// Combine each queue's length with the 'queues' field of every registered worker.  Sorted by queue name.
+pub fn summarize_queues(queue_lengths: Vec<(String, u64)>, worker_queues: Option<&[String]>) -> Vec<QueueSummary> {
+	let mut result: Vec<QueueSummary> = queue_lengths.into_iter().map(|(queue_name, pending_jobs)| {
+		let workers: Option<usize> = worker_queues.map(|worker_queues| {
+			worker_queues.iter().filter(|queues| worker_serves_queue(queues, &queue_name)).count()
+		});
+		QueueSummary { queue_name, pending_jobs, workers }
+	}).collect();
+	result.sort_by(|a, b| a.queue_name.cmp(&b.queue_name));
+	result
+}
+
+/**
+  Every queue listed in 'rq:queues', with its number of pending jobs, and how many registered workers listen to it.\
+  Nothing is modified.  If the workers cannot be read, the queues are still returned, without worker counts.
+*/
+pub fn get_queue_summary(app_config: &AppConfig) -> Result<Vec<QueueSummary>, RQError> {
+	let mut redis_conn = get_redis_connection(app_config, false).ok_or(RQError::NoConnection)?;
+	let queue_keys: Vec<String> = redis_conn.smembers(RQ_KEY_QUEUES)
+		.map_err(|source| RQError::ReadFailed { key: RQ_KEY_QUEUES.to_owned(), source })?;
+
+	let mut queue_lengths: Vec<(String, u64)> = Vec::new();
+	for queue_key in &queue_keys {
+		let pending_jobs: u64 = redis_conn.llen(queue_key)
+			.map_err(|source| RQError::ReadFailed { key: queue_key.clone(), source })?;
+		queue_lengths.push((queue_name_from_key(queue_key).to_owned(), pending_jobs));
 	}
-	match redis_conn.unwrap().keys("rq:job:*") {
-		Ok(keys) => {
-			Some(keys)
-		},
-		Err(_) => {
+
+	let worker_queues: Option<Vec<String>> = match read_worker_queues(&mut redis_conn) {
+		Ok(worker_queues) => Some(worker_queues),
+		Err(error) => {
+			warn!("Unable to read the workers registered in '{}': {}", RQ_KEY_WORKERS, error);
 			None
 		}
+	};
+	Ok(summarize_queues(queue_lengths, worker_queues.as_deref()))
+}
+
+/// The comma-separated 'queues' field of each worker in 'rq:workers'.
+fn read_worker_queues(redis_conn: &mut redis::Connection) -> Result<Vec<String>, RedisError> {
+	let worker_keys: Vec<String> = redis_conn.smembers(RQ_KEY_WORKERS)?;
+	let mut result: Vec<String> = Vec::new();
+	for worker_key in worker_keys {
+		let queues: Option<String> = redis_conn.hget(&worker_key, "queues")?;
+		result.extend(queues);  // a worker that just exited may no longer have a hash
+	}
+	Ok(result)
+}
+
+/// Used when 'redis_memory_warn_percent' is not configured.
+pub const DEFAULT_REDIS_MEMORY_WARN_PERCENT: u8 = 90;
+
+/// Memory figures from the 'memory' section of Redis' INFO command.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RedisMemory {
+	pub used_memory: u64,
+	pub maxmemory: u64,  // 0 when Redis has no limit
+}
+
+impl RedisMemory {
+	/// Read 'used_memory' and 'maxmemory' from the text of 'INFO memory'.  None if either is missing.
+	pub fn from_info(info: &str) -> Option<Self> {
+		let field = |name: &str| info.lines()
+			.filter_map(|line| line.trim().split_once(':'))
+			.find(|(key, _)| *key == name)
+			.and_then(|(_, value)| value.trim().parse::<u64>().ok());
+		Some(RedisMemory { used_memory: field("used_memory")?, maxmemory: field("maxmemory")? })
+	}
+
+	/// A warning when memory use has reached 'warn_percent' of 'maxmemory'.  Never when there is no limit, or 'warn_percent' is 0.
+	pub fn pressure_warning(&self, warn_percent: u8) -> Option<String> {
+		if self.maxmemory == 0 || warn_percent == 0 {
+			return None;
+		}
+		let used_percent: u64 = self.used_memory.saturating_mul(100) / self.maxmemory;
+		if used_percent < u64::from(warn_percent) {
+			return None;
+		}
+		Some(format!("Redis is using {}% of its 'maxmemory' ({} of {} bytes).  When it is full, Redis may evict RQ job hashes, \
+		              or refuse new jobs, depending on its 'maxmemory-policy'.", used_percent, self.used_memory, self.maxmemory))
 	}
 }
 
+/// Read Redis' memory use, and warn when it is near 'maxmemory'.  Returns the warning, if any.
+pub fn check_memory_pressure(app_config: &AppConfig) -> Result<Option<String>, RedisError> {
+	let Some(mut redis_conn) = get_redis_connection(app_config, false) else {
+		return Ok(None);
+	};
+	let info: String = redis::cmd("INFO").arg("memory").query(&mut redis_conn)?;
+	let warning: Option<String> = RedisMemory::from_info(&info)
+		.and_then(|memory| memory.pressure_warning(app_config.redis_memory_warn_percent.unwrap_or(DEFAULT_REDIS_MEMORY_WARN_PERCENT)));
+	if let Some(warning) = &warning {
+		warn!("{}", warning);
+	}
+	Ok(warning)
+}
+
+
 /// Converting a Redis hashmap value into an owned Option String.
 pub fn hashmap_value_to_optstring(hashmap: &HashMap<String, Vec<u8>>, key: &str) -> Option<String> {
 	// NOTE: This function saves a ton of syntax in the library. 
@@ -278,11 +1113,11 @@ pub fn hashmap_value_to_utcdatetime(hashmap: &HashMap<String, Vec<u8>>, key: &st
 }
 
 
-pub fn read_job_by_id(app_config: &AppConfig, job_id: &str) -> Result<RQJob, std::io::Error> {
+pub fn read_job_by_id(app_config: &AppConfig, job_id: &RqJobId) -> Result<RQJob, std::io::Error> {
 
-	let redis_conn = get_redis_connection(app_config, true);
+	let redis_conn = get_redis_connection(app_config, false);
 	if redis_conn.is_none() {
-		return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Unable to establish connection to Redis.")));
+		return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Unable to establish connection to Redis."));
 	}
 	let mut redis_conn = redis_conn.unwrap();
 	let key: String = format!("{}:{}", RQ_JOB_PREFIX, job_id);
@@ -290,52 +1125,11 @@ pub fn read_job_by_id(app_config: &AppConfig, job_id: &str) -> Result<RQJob, std
 	let result: Result<HashMap<String, Vec<u8>>, RedisError> =  redis_conn.hgetall(&key); // reference to avoid a Move.
 	match result {
 		Ok(rq_hashmap) => {
-
 			if rq_hashmap.len() == 0 {
 				let message: String = format!("Job with key '{}' does not exist in the RQ database.", key);
-				return Err(std::io::Error::new(std::io::ErrorKind::Other, message));
-			}
-
-			// Kind of wonky: Asking if the length of the hashmap one of [11, 12, 13]?
-			if ! vec![11, 12, 13, 14].contains(&rq_hashmap.len()) {
-				let message: String = format!("Expected Redis to return a Hashmap with 11 to 13 keys, but found {} keys instead.",
-				                              rq_hashmap.len());
-				return Err(std::io::Error::new(std::io::ErrorKind::Other, message));											  
+				return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message));
 			}
-
-			let my_job: RQJob = RQJob {
-				job_key: key,
-				job_key_short: job_id.to_string(),
-				status: hashmap_value_to_optstring(&rq_hashmap, "status"),
-				data: rq_hashmap.get("data").unwrap().to_owned(),
-				exc_info: hashmap_value_to_optstring(&rq_hashmap, "exc_info"),
-				ended_at: hashmap_value_to_optstring(&rq_hashmap, "ended_at"),
-				result_ttl: hashmap_value_to_optstring(&rq_hashmap, "result_ttl"),
-				enqueued_at: hashmap_value_to_optstring(&rq_hashmap, "enqueued_at"),
-				last_heartbeat: String::from_utf8_lossy(rq_hashmap.get("last_heartbeat").unwrap()).to_string(),
-				origin: String::from_utf8_lossy(rq_hashmap.get("origin").unwrap()).to_string(),
-				description: String::from_utf8_lossy(rq_hashmap.get("description").unwrap()).to_string(),
-				meta: match rq_hashmap.get("meta") {
-					Some(value) => {
-						Some(value.to_owned())
-					},
-					_ => {
-						None
-					}
-				},
-				started_at: hashmap_value_to_optstring(&rq_hashmap, "started_at"),
-				created_at: hashmap_value_to_utcdatetime(&rq_hashmap, "created_at").unwrap(),
-				timeout: match rq_hashmap.get("timeout") {
-					Some(timeout_string) => {
-						redis_value_to_u32(timeout_string).unwrap()
-					},
-					None => {
-						600  // default value of 600 second timeout (5 minutes)
-					}
-				},			
-				worker_name: String::from_utf8_lossy(rq_hashmap.get("worker_name").unwrap()).to_string(),
-			};
-			return Ok(my_job)
+			rq_job_from_hashmap(job_id.as_str(), rq_hashmap)
 		},
 		Err(bar) => {
 			return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Redis HGETALL returned an error like this: {}", bar)));
@@ -343,6 +1137,67 @@ pub fn read_job_by_id(app_config: &AppConfig, job_id: &str) -> Result<RQJob, std
 	}
 }
 
+/// Build an RQJob from the contents of its rq:job hash.  Fields that RQJob does not model are kept in 'extra_fields'.
+pub fn rq_job_from_hashmap(job_id: &str, mut rq_hashmap: HashMap<String, Vec<u8>>) -> Result<RQJob, std::io::Error> {
+
+	let key: String = format!("{}:{}", RQ_JOB_PREFIX, job_id);
+	let is_compressed: bool = rq_hashmap.remove(COMPRESSION_MARKER_FIELD)
+		.map_or(false, |marker| marker.as_slice() == COMPRESSION_MARKER_ZLIB.as_bytes());
+	let extra_field_names: Vec<String> = rq_hashmap.keys()
+		.filter(|name| !RQ_JOB_MODELED_FIELDS.contains(&name.as_str()))
+		.cloned()
+		.collect();
+	let extra_fields: HashMap<String, Vec<u8>> = extra_field_names.into_iter()
+		.filter_map(|name| rq_hashmap.remove_entry(&name))
+		.collect();
+
+	// Kind of wonky: Asking if the number of modeled keys is one of [11, 12, 13, 14]?
+	let rq_key_count: usize = rq_hashmap.len();
+	if ! [11, 12, 13, 14].contains(&rq_key_count) {
+		let message: String = format!("Expected Redis to return a Hashmap with 11 to 14 keys, but found {} keys instead.",
+		                              rq_key_count);
+		return Err(std::io::Error::new(std::io::ErrorKind::Other, message));											  
+	}
+
+	let data: Vec<u8> = if is_compressed {
+		let compressed: &Vec<u8> = rq_hashmap.get("data").unwrap();
+		let decompressed: Vec<u8> = decompress_job_data(compressed)?;
+		debug!("Decompressed job data from {} bytes to {} bytes.", compressed.len(), decompressed.len());
+		decompressed
+	}
+	else {
+		rq_hashmap.get("data").unwrap().to_owned()
+	};
+
+	let my_job: RQJob = RQJob {
+		job_key: key,
+		job_key_short: RqJobId::from(job_id),
+		status: hashmap_value_to_optstring(&rq_hashmap, "status"),
+		data,
+		exc_info: hashmap_value_to_optstring(&rq_hashmap, "exc_info"),
+		ended_at: hashmap_value_to_optstring(&rq_hashmap, "ended_at"),
+		result_ttl: hashmap_value_to_optstring(&rq_hashmap, "result_ttl"),
+		enqueued_at: hashmap_value_to_optstring(&rq_hashmap, "enqueued_at"),
+		last_heartbeat: String::from_utf8_lossy(rq_hashmap.get("last_heartbeat").unwrap()).to_string(),
+		origin: String::from_utf8_lossy(rq_hashmap.get("origin").unwrap()).to_string(),
+		description: String::from_utf8_lossy(rq_hashmap.get("description").unwrap()).to_string(),
+		meta: rq_hashmap.get("meta").cloned(),
+		started_at: hashmap_value_to_optstring(&rq_hashmap, "started_at"),
+		created_at: hashmap_value_to_utcdatetime(&rq_hashmap, "created_at").unwrap(),
+		timeout: match rq_hashmap.get("timeout") {
+			Some(timeout_string) => {
+				redis_value_to_u32(timeout_string).unwrap()
+			},
+			None => {
+				600  // default value of 600 second timeout (5 minutes)
+			}
+		},			
+		worker_name: String::from_utf8_lossy(rq_hashmap.get("worker_name").unwrap()).to_string(),
+		extra_fields,
+	};
+	Ok(my_job)
+}
+
 
 /// Converts a Redis byte string to a signed 32-bit integer.
 pub fn redis_value_to_i32(redis_value: &Vec<u8>) -> Result<i32, &str> {
@@ -0,0 +1,148 @@
+// run_history.rs
+
+/*
+	"When did this Task Schedule actually last fire?"  Answered without grepping logs, or reading the Frappe database.
+
+	Each time Thread #3 handles one run of a Task Schedule, it adds a RunRecord to the schedule's sorted set
+	'btu_scheduler:task_run_history:<Task Schedule ID>', scored by the Unix time of the decision: when, the Next Execution
+	Time it concerned, the RQ Job ID (when a job was created), and the outcome.  Only the newest 'task_run_history_size'
+	entries are kept.  Unlike the scheduler's other keys, the history survives cancelling the Task Schedule; it is an audit
+	trail.  It expires 'task_run_history_ttl_days' after the last run it records, so a deleted schedule's history does not
+	stay in Redis forever.  Writes are best-effort: a failure is logged, and never changes the outcome of the run.
+*/
+
+use std::fmt;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use redis::{Commands, RedisError};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::config::AppConfig;
+use crate::ids::RqJobId;
+use crate::redis_keys::task_run_history_key;
+
+/// Default for 'task_run_history_size'.
+pub const DEFAULT_TASK_RUN_HISTORY_SIZE: usize = 50;
+/// Default for 'task_run_history_ttl_days'.
+pub const DEFAULT_TASK_RUN_HISTORY_TTL_DAYS: u32 = 30;
+
+/// What became of one run of a Task Schedule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+	Enqueued,          // an RQ Job was enqueued
+	Deferred,          // an RQ Job was saved, to wait for the Task Schedule it depends on
+	Skipped,           // passed over; see 'detail'
+	BlockedByPolicy,   // the Task's function does not match 'allowed_function_prefixes'
+	ScheduleDisabled,  // the Task Schedule is disabled in the Frappe database
+	FailedToBuild,     // the RQ Job could not be created, such as when the web server did not answer
+	Failed,            // reading the Task Schedule, or saving and enqueuing its RQ Job, failed
+}
+
+impl fmt::Display for RunOutcome {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let text: &str = match self {
+			RunOutcome::Enqueued => "enqueued",
+			RunOutcome::Deferred => "deferred",
+			RunOutcome::Skipped => "skipped",
+			RunOutcome::BlockedByPolicy => "blocked_by_policy",
+			RunOutcome::ScheduleDisabled => "schedule_disabled",
+			RunOutcome::FailedToBuild => "failed_to_build",
+			RunOutcome::Failed => "failed",
+		};
+		write!(f, "{}", text)
+	}
+}
+
+/// One entry of a Task Schedule's run history, stored as JSON.  Empty fields are omitted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+	pub at: String,  // RFC 3339, UTC; when the run was handled
+	pub run_at: String,  // RFC 3339, UTC; the Next Execution Time it was handled for
+	pub outcome: RunOutcome,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub job_id: Option<RqJobId>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub detail: Option<String>,
+}
+
+impl RunRecord {
+	pub fn new(outcome: RunOutcome, run_at: DateTime<Utc>) -> Self {
+		RunRecord {
+			at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+			run_at: run_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+			outcome,
+			job_id: None,
+			detail: None,
+		}
+	}
+
+	pub fn job(mut self, job_id: &RqJobId) -> Self {
+		self.job_id = Some(job_id.clone());
+		self
+	}
+
+	pub fn detail(mut self, detail: impl Into<String>) -> Self {
+		self.detail = Some(detail.into());
+		self
+	}
+
+	/// When the run was handled; None if 'at' was not written by BTU.
+	pub fn at_utc(&self) -> Option<DateTime<Utc>> {
+		DateTime::parse_from_rfc3339(&self.at).ok().map(|at| at.with_timezone(&Utc))
+	}
+
+	/// The Next Execution Time the run was handled for; None if 'run_at' was not written by BTU.
+	pub fn run_at_utc(&self) -> Option<DateTime<Utc>> {
+		DateTime::parse_from_rfc3339(&self.run_at).ok().map(|run_at| run_at.with_timezone(&Utc))
+	}
+}
+
+/// The Redis commands that add 'record' to a Task Schedule's history, drop all but the newest 'history_size' entries, and
+/// keep the history for 'ttl_days' more.
+pub fn record_run_pipeline(task_schedule_id: &str, record: &RunRecord, history_size: usize, ttl_days: u32) -> Result<redis::Pipeline, serde_json::Error> {
+	let key: String = task_run_history_key(task_schedule_id);
+	let score: i64 = record.at_utc().map_or_else(|| Utc::now().timestamp(), |at| at.timestamp());
+	let mut pipeline = redis::pipe();
+	pipeline
+		.zadd(&key, serde_json::to_string(record)?, score).ignore()
+		// Ranks run from the oldest (0); keep the last 'history_size'.
+		.zremrangebyrank(&key, 0, -(history_size as isize) - 1).ignore()
+		// Refreshed on every run; once the schedule stops running (or is deleted), its history expires.
+		.expire(&key, ttl_days.max(1) as usize * 86_400).ignore();
+	Ok(pipeline)
+}
+
+/// Add one run to a Task Schedule's history.  Does nothing when 'task_run_history_size' is 0.  A failure is only logged.
+pub fn record_run(app_config: &AppConfig, redis_conn: &mut redis::Connection, task_schedule_id: &str, record: RunRecord) {
+	let history_size: usize = app_config.task_run_history_size.unwrap_or(DEFAULT_TASK_RUN_HISTORY_SIZE);
+	if history_size == 0 {
+		return;
+	}
+	let ttl_days: u32 = app_config.task_run_history_ttl_days.unwrap_or(DEFAULT_TASK_RUN_HISTORY_TTL_DAYS);
+	let result: Result<(), String> = record_run_pipeline(task_schedule_id, &record, history_size, ttl_days)
+		.map_err(|error| error.to_string())
+		.and_then(|pipeline| pipeline.query(redis_conn).map_err(|error: RedisError| error.to_string()));
+	if let Err(error) = result {
+		warn!("Unable to add a '{}' run to the history of Task Schedule {}: {}", record.outcome, task_schedule_id, error);
+	}
+}
+
+/// A Task Schedule's run history, newest first.  Entries that are not a RunRecord are skipped.
+pub fn read_history(redis_conn: &mut redis::Connection, task_schedule_id: &str) -> Result<Vec<RunRecord>, RedisError> {
+	let key: String = task_run_history_key(task_schedule_id);
+	let members: Vec<String> = redis_conn.zrevrange(&key, 0, -1)?;
+	Ok(parse_history(&key, &members))
+}
+
+/// The RunRecords among 'members' of 'key', in the same order.
+pub fn parse_history(key: &str, members: &[String]) -> Vec<RunRecord> {
+	members.iter().filter_map(|member| match serde_json::from_str::<RunRecord>(member) {
+		Ok(record) => Some(record),
+		Err(error) => {
+			debug!("Ignoring a member of '{}': {}", key, error);
+			None
+		}
+	}).collect()
+}
@@ -0,0 +1,215 @@
+// runtime.rs
+
+/*
+	The daemon's scheduling work, one step ("tick") at a time, apart from its threads and its clocks.
+
+	Each of btu-daemon's worker threads is a loop around one of these ticks:
+	  * Thread #1:  tick_internal_queue() schedules the Task Schedule at the front of the Internal Queue.
+	  * Thread #2:  tick_full_refresh() and tick_maintenance() refresh every Task Schedule, and do housekeeping, when they are due.
+	  * Thread #3:  tick_promote_ready() enqueues the runs whose time has come.
+
+	The caller passes the time, the configuration, and a SchedulerBackend, which does the work against Redis and MariaDB.  The
+	daemon's backend uses the real databases; tests drive the ticks with a fake backend, and a fake clock.  The runtime owns the
+	Internal Queue that the threads share (socket requests push onto it too), and Thread #2's stopwatches.
+*/
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use tracing::{trace, debug, info, error, span, Level};
+
+use crate::config::{self, AppConfig};
+use crate::errors::{RQError, SchedulerError};
+use crate::ids::{CorrelationId, TaskScheduleId};
+use crate::schedule_queue::{QueueSource, ScheduleQueue};
+use crate::scheduler::{self, CycleSummary, WriteRetry};
+use crate::task_schedule::BtuTaskSchedule;
+
+/// How long Thread #1 waits after an empty Internal Queue, or after scheduling an entry.
+pub const INTERNAL_QUEUE_PAUSE: Duration = Duration::from_millis(1250);
+
+/// What one full refresh did, over every site.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RefreshSummary {
+	pub refreshed: u32,  // Task Schedules whose Next Execution Times were written in bulk
+	pub queued: u32,     // Task Schedule IDs added to the internal queue, for Thread #1 to schedule one at a time
+}
+
+/// Thread #2's housekeeping, each at its own interval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaintenanceTask {
+	StaleJobCleanup,   // remove RQ Jobs that BTU saved, but no worker ever picked up
+	JobWatchdog,       // check the jobs BTU enqueued for overruns
+	RedisMemoryCheck,  // compare Redis' memory use with its 'maxmemory'
+}
+
+impl MaintenanceTask {
+	pub const ALL: [MaintenanceTask; 3] = [MaintenanceTask::StaleJobCleanup, MaintenanceTask::JobWatchdog, MaintenanceTask::RedisMemoryCheck];
+
+	pub fn interval(self) -> Duration {
+		match self {
+			MaintenanceTask::StaleJobCleanup => Duration::from_secs(3600),
+			MaintenanceTask::JobWatchdog => Duration::from_secs(60),
+			MaintenanceTask::RedisMemoryCheck => Duration::from_secs(300),
+		}
+	}
+}
+
+/// Why Thread #1 could not schedule a Task Schedule.
+#[derive(Debug)]
+pub enum ScheduleTaskError {
+	Read(SchedulerError),                                           // the Task Schedule could not be read from MariaDB
+	Write { task_schedule: Box<BtuTaskSchedule>, error: RQError },  // its Next Execution Times could not be written to Redis
+}
+
+/// The work behind each tick.  In the daemon, against Redis and MariaDB.
+pub trait SchedulerBackend {
+	/// The score of a Task Schedule Instance Key in Redis, if it is there.
+	fn scheduled_score(&mut self, app_config: &AppConfig, tsik: &str) -> Option<i64>;
+	/// Read one Task Schedule, and write its Next Execution Times; returns the next one, if any.
+	fn schedule_task(&mut self, site_config: &AppConfig, task_schedule_id: &TaskScheduleId, correlation_id: Option<&CorrelationId>)
+		-> Result<Option<DateTime<Utc>>, ScheduleTaskError>;
+	/// Every attempt to write the Task Schedule failed; record that it is not scheduled.
+	fn abandon_schedule_write(&mut self, site_config: &AppConfig, task_schedule: &BtuTaskSchedule, error: &RQError);
+	/// Schedule every enabled Task Schedule again.  Whatever cannot be written in bulk is pushed onto 'queue' (locking it briefly).
+	fn full_refresh(&mut self, app_config: &AppConfig, queue: &Mutex<ScheduleQueue>, source: QueueSource) -> Result<RefreshSummary, String>;
+	/// Enqueue every run due at 'now'.  Each Task Schedule that ran is pushed back onto 'queue', for its next run.
+	fn run_eligible(&mut self, app_config: &AppConfig, queue: &mut ScheduleQueue, now: DateTime<Utc>) -> CycleSummary;
+	/// Do one housekeeping job; failures are only logged.
+	fn run_maintenance(&mut self, app_config: &AppConfig, task: MaintenanceTask);
+}
+
+/// When Thread #2 last did each of its jobs.
+struct Stopwatches {
+	full_refresh: Instant,
+	maintenance: [Instant; 3],  // in the order of MaintenanceTask::ALL
+}
+
+/// The state the daemon's threads share.  Each thread holds an Arc, and calls its own tick.
+pub struct SchedulerRuntime {
+	queue: Arc<Mutex<ScheduleQueue>>,
+	stopwatches: Mutex<Stopwatches>,
+}
+
+impl SchedulerRuntime {
+
+	/// A runtime with an empty Internal Queue.  The full refresh and housekeeping are first due an interval after 'now'.
+	pub fn new(now: Instant) -> Self {
+		SchedulerRuntime {
+			queue: Arc::new(Mutex::new(ScheduleQueue::new())),
+			stopwatches: Mutex::new(Stopwatches { full_refresh: now, maintenance: [now; 3] }),
+		}
+	}
+
+	/// The Internal Queue, for socket requests and the status page.
+	pub fn queue(&self) -> Arc<Mutex<ScheduleQueue>> {
+		Arc::clone(&self.queue)
+	}
+
+	/// Thread #1: schedule the Task Schedule at the front of the Internal Queue, and return how long to wait before the next tick.
+	/// When the write fails, the entry goes to the back of the queue, for a few more attempts, each after a longer wait.  When MariaDB is unreachable, it
+	/// stays at the front, and the wait is 'sql_retry_backoff_secs'.  An entry that needed no work is followed immediately.
+	pub fn tick_internal_queue(&self, app_config: &AppConfig, backend: &mut dyn SchedulerBackend, now: DateTime<Utc>) -> Duration {
+		let Ok(mut queue) = self.queue.lock() else {
+			error!("The internal queue's lock is poisoned; Thread 1 cannot schedule anything.");
+			return INTERNAL_QUEUE_PAUSE;
+		};
+		let Some(entry) = queue.pop_ready(now) else {
+			return INTERNAL_QUEUE_PAUSE;  // empty, or only retries waiting out their delay
+		};
+		let schedule_key: TaskScheduleId = entry.schedule_id.clone();  // BTU Task Schedule 'name'
+		// Entries from a socket request carry its correlation ID into every log line below.
+		let _span = entry.correlation_id.as_ref()
+			.map(|correlation_id| span!(Level::INFO, "queue_entry", correlation_id = %correlation_id).entered());
+		debug!("Thread 1: Processing Task Schedule '{}' (source: {}, waited {} ms in queue).",
+		       schedule_key, entry.source, entry.latency(now).num_milliseconds());
+
+		// Unchanged since last time, and its Next Execution Time is still waiting in Redis?  Nothing to do.
+		if queue.fingerprints.can_skip(&entry, now.timestamp(), |tsik| backend.scheduled_score(app_config, tsik)) {
+			trace!("Thread 1: Task Schedule '{}' is unchanged, and already scheduled; skipping.", schedule_key);
+			return Duration::ZERO;
+		}
+		// On a multi-site bench, the ID names its site ("site::TS-0001"); read from that site's database.
+		let (site, task_schedule_id) = config::split_schedule_id(schedule_key.as_str());
+		let Some(site_config) = app_config.for_site(site) else {
+			error!("Thread 1: Task Schedule '{}' belongs to site '{}', which is not in the configuration; discarding it.",
+			       schedule_key, site.unwrap_or_default());
+			return Duration::ZERO;
+		};
+
+		let mut pause: Duration = INTERNAL_QUEUE_PAUSE;
+		let mut next_runtime: Option<DateTime<Utc>> = None;
+		match backend.schedule_task(&site_config, &TaskScheduleId::from(task_schedule_id), entry.correlation_id.as_ref()) {
+			Ok(runtime) => next_runtime = runtime,
+			Err(ScheduleTaskError::Write { task_schedule, error }) => {
+				// Redis is briefly unavailable?  Try again shortly, rather than waiting for the next full refresh.
+				if scheduler::retry_schedule_write(&mut queue, &entry, &error, now) == WriteRetry::GaveUp {
+					backend.abandon_schedule_write(&site_config, &task_schedule, &error);
+				}
+			},
+			Err(ScheduleTaskError::Read(error)) => {
+				// MariaDB is unreachable?  Keep the entry at the front, and wait before reading again.
+				if scheduler::retry_schedule_read(&mut queue, &entry, &error) {
+					pause = scheduler::sql_retry_backoff(&site_config);
+				}
+			}
+		}
+		queue.fingerprints.remember(&entry, next_runtime.map(|runtime| runtime.timestamp()));
+		trace!("{} values remain in internal queue.", queue.len());
+		pause
+	}
+
+	/// Schedule every enabled Task Schedule again, now.  The Internal Queue is only locked to hand Thread #1 what could not be written.
+	pub fn full_refresh(&self, app_config: &AppConfig, backend: &mut dyn SchedulerBackend, source: QueueSource) -> Result<RefreshSummary, String> {
+		backend.full_refresh(app_config, &self.queue, source)
+	}
+
+	/// Thread #2: the full refresh, once more than 'full_refresh_internal_secs' have passed since the last one that succeeded.
+	/// Returns None when it is not yet due.  A failed refresh is tried again on the next tick.
+	pub fn tick_full_refresh(&self, app_config: &AppConfig, backend: &mut dyn SchedulerBackend, now: Instant) -> Option<Result<RefreshSummary, String>> {
+		let last_refresh: Instant = self.stopwatches.lock().ok()?.full_refresh;
+		let elapsed_seconds: u64 = now.saturating_duration_since(last_refresh).as_secs();
+		if elapsed_seconds <= u64::from(app_config.full_refresh_internal_secs) {
+			return None;
+		}
+		info!("{} seconds have elapsed.  It's time for a full-refresh of the Task Schedules in Redis!", elapsed_seconds);
+		let result = self.full_refresh(app_config, backend, QueueSource::FullRefresh);
+		match &result {
+			Ok(summary) => {
+				debug!("  * Refreshed {} Task Schedules in bulk, and added {} values to the internal FIFO queue.", summary.refreshed, summary.queued);
+				if let Ok(mut stopwatches) = self.stopwatches.lock() {
+					stopwatches.full_refresh = now;  // begin a new countdown
+				}
+			},
+			Err(error) => error!("Error while performing the full refresh! {}", error)
+		}
+		Some(result)
+	}
+
+	/// Thread #2: each housekeeping job whose interval has passed.  Returns the jobs that ran.
+	pub fn tick_maintenance(&self, app_config: &AppConfig, backend: &mut dyn SchedulerBackend, now: Instant) -> Vec<MaintenanceTask> {
+		let due: Vec<MaintenanceTask> = match self.stopwatches.lock() {
+			Ok(mut stopwatches) => MaintenanceTask::ALL.iter().zip(stopwatches.maintenance.iter_mut())
+				.filter(|(task, last_run)| now.saturating_duration_since(**last_run).as_secs() > task.interval().as_secs())
+				.map(|(task, last_run)| {
+					*last_run = now;
+					*task
+				})
+				.collect(),
+			Err(_) => Vec::new(),
+		};
+		for task in &due {
+			backend.run_maintenance(app_config, *task);
+		}
+		due
+	}
+
+	/// Thread #3: enqueue every run whose time is at or before 'now'.  Each Task Schedule that ran is back on the Internal Queue
+	/// afterwards, so Thread #1 writes its next run.  Returns None when the Internal Queue's lock could not be taken.
+	pub fn tick_promote_ready(&self, app_config: &AppConfig, backend: &mut dyn SchedulerBackend, now: DateTime<Utc>) -> Option<CycleSummary> {
+		// The lock is held for the whole pass, so that after a Task runs, it can be rescheduled.
+		let mut queue = self.queue.lock().ok()?;
+		Some(backend.run_eligible(app_config, &mut queue, now))
+	}
+}
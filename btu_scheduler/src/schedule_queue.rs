@@ -0,0 +1,306 @@
+// schedule_queue.rs
+
+/*
+	The daemon's Internal Queue: Task Schedule IDs waiting for their Next Execution Time to be written into Redis.
+
+	There are two priorities.  Requests from the Frappe web server (a user just edited a schedule) are high-priority,
+	and always processed before the normal-priority backlog created by a full refresh.
+
+	A Task Schedule ID is never pending twice.  Processing it once is enough, no matter how many times it was pushed.
+	The pending IDs are also kept in a HashSet, so rejecting a duplicate does not scan the queue; a full refresh pushes
+	every enabled schedule at once.
+
+	A full refresh also carries each schedule's fingerprint ('modified' timestamp, cron string, and a hash of the settings its
+	Next Execution Times depend on, such as the time zone and holidays).  When the fingerprint is unchanged since the schedule was last processed, and its Next Execution Time is still waiting in Redis, Thread #1 skips
+	the SQL read and the ZADD.
+*/
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use crate::ids::{CorrelationId, TaskScheduleId};
+
+/// What caused a Task Schedule to enter the Internal Queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueSource {
+	Socket,          // a request from the Frappe web server
+	FullRefresh,     // Thread #2's periodic refresh
+	PostRunRequeue,  // the Task Schedule just ran, and needs its next Execution Time
+	Startup,         // the daemon's initial refresh
+}
+
+impl fmt::Display for QueueSource {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let name = match self {
+			QueueSource::Socket => "socket",
+			QueueSource::FullRefresh => "full_refresh",
+			QueueSource::PostRunRequeue => "post_run_requeue",
+			QueueSource::Startup => "startup",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+/// What a full refresh reads about a Task Schedule, to tell whether it changed since it was last processed.
+/// Next Execution Times also depend on settings outside the row (time zones, jitter, holidays); 'context_hash' covers them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleFingerprint {
+	pub modified: String,  // Frappe's 'modified' column, as text
+	pub cron_hash: u64,
+	pub context_hash: u64,  // see context_hash()
+}
+
+impl ScheduleFingerprint {
+	pub fn new(modified: &str, cron_string: &str) -> Self {
+		ScheduleFingerprint { modified: modified.to_owned(), cron_hash: crate::stable_hash(cron_string.trim()), context_hash: 0 }
+	}
+
+	pub fn with_context(mut self, context_hash: u64) -> Self {
+		self.context_hash = context_hash;
+		self
+	}
+
+	/// A hash of what, besides its own row, decides a Task Schedule's Next Execution Times: the configuration (whose
+	/// 'config_generation' changes on every reload), the site's cron time zone, and the holidays.
+	pub fn context_hash(config_generation: u64, cron_time_zone: &str, holidays: &HashSet<NaiveDate>) -> u64 {
+		let mut sorted_holidays: Vec<&NaiveDate> = holidays.iter().collect();
+		sorted_holidays.sort();
+		crate::stable_hash(&format!("{}|{}|{:?}", config_generation, cron_time_zone, sorted_holidays))
+	}
+}
+
+/// How many times Thread #1 tries to write a Task Schedule's Next Execution Times to Redis, before giving up.
+pub const MAX_WRITE_ATTEMPTS: u32 = 5;
+/// The wait before the 2nd write attempt.  It doubles for each attempt after that, up to WRITE_RETRY_MAX_DELAY_SECS.
+pub const WRITE_RETRY_BASE_DELAY_SECS: i64 = 1;
+pub const WRITE_RETRY_MAX_DELAY_SECS: i64 = 30;
+
+/// How long Thread #1 waits before write attempt number 'attempt' (2 or more): 1, 2, 4, 8 seconds, and so on.
+pub fn write_retry_delay(attempt: u32) -> Duration {
+	let doublings: u32 = attempt.saturating_sub(2).min(16);
+	Duration::seconds((WRITE_RETRY_BASE_DELAY_SECS << doublings).min(WRITE_RETRY_MAX_DELAY_SECS))
+}
+
+/// One element of the Internal Queue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueEntry {
+	pub schedule_id: TaskScheduleId,
+	pub source: QueueSource,
+	pub enqueued_at: DateTime<Utc>,
+	pub fingerprint: Option<ScheduleFingerprint>,  // only known for entries from a full refresh
+	pub correlation_id: Option<CorrelationId>,  // only known for entries from a socket request
+	pub attempt: u32,  // 1 for the first try; more when writing to Redis failed, and the entry was pushed back
+	pub not_before: Option<DateTime<Utc>>,  // for a retry, when it may be tried again
+}
+
+impl QueueEntry {
+
+	pub fn new(schedule_id: TaskScheduleId, source: QueueSource) -> Self {
+		QueueEntry { schedule_id, source, enqueued_at: Utc::now(), fingerprint: None, correlation_id: None, attempt: 1, not_before: None }
+	}
+
+	pub fn with_correlation_id(mut self, correlation_id: CorrelationId) -> Self {
+		self.correlation_id = Some(correlation_id);
+		self
+	}
+
+	pub fn with_fingerprint(mut self, fingerprint: ScheduleFingerprint) -> Self {
+		self.fingerprint = Some(fingerprint);
+		self
+	}
+
+	/// A copy of this entry for its next attempt, not to be tried before write_retry_delay() from 'now'; or None, once
+	/// MAX_WRITE_ATTEMPTS have been made.  The copy keeps its original 'enqueued_at', so latency covers every attempt.
+	pub fn next_attempt(&self, now: DateTime<Utc>) -> Option<QueueEntry> {
+		(self.attempt < MAX_WRITE_ATTEMPTS).then(|| QueueEntry {
+			attempt: self.attempt + 1,
+			not_before: Some(now + write_retry_delay(self.attempt + 1)),
+			..self.clone()
+		})
+	}
+
+	/// Whether the entry may be processed at 'now'; false while a retry is waiting out its delay.
+	pub fn is_ready(&self, now: DateTime<Utc>) -> bool {
+		self.not_before.map_or(true, |not_before| not_before <= now)
+	}
+
+	/// How long the entry waited in the queue, as of 'now'.
+	pub fn latency(&self, now: DateTime<Utc>) -> Duration {
+		now - self.enqueued_at
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct ScheduleQueue {
+	high_priority: VecDeque<QueueEntry>,
+	normal_priority: VecDeque<QueueEntry>,
+	pending: HashSet<TaskScheduleId>,  // the schedule_id of every entry above
+	pub fingerprints: FingerprintCache,
+}
+
+impl ScheduleQueue {
+
+	pub fn new() -> Self {
+		ScheduleQueue::default()
+	}
+
+	/// Append at normal priority.  Returns false if the schedule was already pending (at either priority).
+	pub fn push_back(&mut self, entry: QueueEntry) -> bool {
+		if !self.pending.insert(entry.schedule_id.clone()) {
+			return false;
+		}
+		self.normal_priority.push_back(entry);
+		true
+	}
+
+	/// Append at high priority.  A pending normal-priority duplicate is removed, so the schedule is processed exactly once, sooner.
+	/// Returns false if the schedule was already pending at high priority.
+	pub fn push_priority(&mut self, entry: QueueEntry) -> bool {
+		if !self.pending.insert(entry.schedule_id.clone()) {
+			if self.high_priority.iter().any(|pending| pending.schedule_id == entry.schedule_id) {
+				return false;
+			}
+			self.normal_priority.retain(|pending| pending.schedule_id != entry.schedule_id);
+		}
+		self.high_priority.push_back(entry);
+		true
+	}
+
+	/// Put an entry back at the very front, ahead of every other entry; for one that could not be processed yet.
+	/// A pending duplicate (at either priority) is removed.
+	pub fn push_front(&mut self, entry: QueueEntry) {
+		if !self.pending.insert(entry.schedule_id.clone()) {
+			self.high_priority.retain(|pending| pending.schedule_id != entry.schedule_id);
+			self.normal_priority.retain(|pending| pending.schedule_id != entry.schedule_id);
+		}
+		self.high_priority.push_front(entry);
+	}
+
+	/// The next entry to process: high priority first, then normal priority; each in FIFO order.
+	pub fn pop_front(&mut self) -> Option<QueueEntry> {
+		let entry: QueueEntry = self.high_priority.pop_front().or_else(|| self.normal_priority.pop_front())?;
+		self.pending.remove(&entry.schedule_id);
+		Some(entry)
+	}
+
+	/// Like pop_front(), but skips retries still waiting out their delay.  They keep their place in the queue.
+	pub fn pop_ready(&mut self, now: DateTime<Utc>) -> Option<QueueEntry> {
+		let entry: QueueEntry = match self.high_priority.iter().position(|entry| entry.is_ready(now)) {
+			Some(index) => self.high_priority.remove(index)?,
+			None => {
+				let index: usize = self.normal_priority.iter().position(|entry| entry.is_ready(now))?;
+				self.normal_priority.remove(index)?
+			}
+		};
+		self.pending.remove(&entry.schedule_id);
+		Some(entry)
+	}
+
+	pub fn contains(&self, schedule_id: &str) -> bool {
+		self.pending.contains(schedule_id)
+	}
+
+	pub fn len(&self) -> usize {
+		self.high_priority.len() + self.normal_priority.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.high_priority.is_empty() && self.normal_priority.is_empty()
+	}
+
+	/// How many of the pending entries are high priority.  These come first in processing order.
+	pub fn high_priority_len(&self) -> usize {
+		self.high_priority.len()
+	}
+
+	/// A copy of (at most) the first 'limit' pending entries, in processing order.
+	pub fn snapshot(&self, limit: usize) -> Vec<QueueEntry> {
+		self.high_priority.iter().chain(self.normal_priority.iter()).take(limit).cloned().collect()
+	}
+
+	/// Discard every pending entry.  Returns how many were discarded.
+	pub fn clear(&mut self) -> usize {
+		let discarded: usize = self.len();
+		self.high_priority.clear();
+		self.normal_priority.clear();
+		self.pending.clear();
+		discarded
+	}
+
+	/// Discard every pending entry, except those from 'keep'.  Returns how many were discarded.
+	pub fn clear_except(&mut self, keep: QueueSource) -> usize {
+		let before: usize = self.len();
+		self.high_priority.retain(|entry| entry.source == keep);
+		self.normal_priority.retain(|entry| entry.source == keep);
+		let kept: HashSet<TaskScheduleId> = self.high_priority.iter().chain(self.normal_priority.iter())
+			.map(|entry| entry.schedule_id.clone())
+			.collect();
+		self.pending = kept;
+		before - self.len()
+	}
+}
+
+/// The fingerprint and stored Next Execution Time of each Task Schedule, as of when Thread #1 last processed it.
+#[derive(Debug, Default)]
+pub struct FingerprintCache {
+	entries: HashMap<TaskScheduleId, (ScheduleFingerprint, i64)>,
+	hits: usize,    // entries skipped since the counts were last taken
+	misses: usize,  // entries with a fingerprint that still had to be processed
+}
+
+impl FingerprintCache {
+
+	/// Can Thread #1 skip this entry?  Only when it came from a full refresh, its fingerprint matches the cache, and
+	/// 'stored_score' (a ZSCORE of the cached TSIK) says that Next Execution Time is still waiting in the future.
+	/// Socket requests and post-run requeues are never skipped.
+	pub fn can_skip<F>(&mut self, entry: &QueueEntry, now_unix: i64, stored_score: F) -> bool
+		where F: FnOnce(&str) -> Option<i64>
+	{
+		let Some(fingerprint) = &entry.fingerprint else {
+			return false;
+		};
+		let skip: bool = match self.entries.get(&entry.schedule_id) {
+			Some((cached, next_run_unix)) if cached == fingerprint && *next_run_unix > now_unix => {
+				let tsik: String = crate::redis_keys::tsik(entry.schedule_id.as_str(), *next_run_unix);
+				stored_score(&tsik).map_or(false, |score| score > now_unix)
+			},
+			_ => false
+		};
+		if skip {
+			self.hits += 1;
+		}
+		else {
+			self.misses += 1;
+		}
+		skip
+	}
+
+	/// Record the outcome of processing an entry.  'next_run_unix' is the first Next Execution Time written to Redis (if any).
+	pub fn remember(&mut self, entry: &QueueEntry, next_run_unix: Option<i64>) {
+		match (&entry.fingerprint, next_run_unix) {
+			(Some(fingerprint), Some(next_run_unix)) => {
+				self.entries.insert(entry.schedule_id.clone(), (fingerprint.clone(), next_run_unix));
+			},
+			(None, Some(next_run_unix)) if entry.source == QueueSource::PostRunRequeue => {
+				// The definition did not change; only the Next Execution Time moved on.
+				if let Some(cached) = self.entries.get_mut(&entry.schedule_id) {
+					cached.1 = next_run_unix;
+				}
+			},
+			_ => {
+				// Possibly edited (a socket request), or nothing was written: the next full refresh must process it.
+				self.entries.remove(&entry.schedule_id);
+			}
+		}
+	}
+
+	/// The hit and miss counts since the previous call.
+	pub fn take_counts(&mut self) -> (usize, usize) {
+		let counts = (self.hits, self.misses);
+		self.hits = 0;
+		self.misses = 0;
+		counts
+	}
+}
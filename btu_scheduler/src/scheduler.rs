@@ -1,11 +1,12 @@
 // scheduler.rs
 
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt;
 use anyhow::anyhow as anyhow_macro;
 use chrono::{DateTime, SecondsFormat, Utc}; // See also: DateTime, Local, TimeZone
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use redis::{self, Commands, RedisError};
+use serde::{Deserialize, Serialize};
 use tracing::{trace, debug, info, warn, error, span, Level};
 
 #[cfg(feature = "email-feat")]
@@ -13,25 +14,47 @@ use crate::email;
 #[cfg(feature = "email-feat")]
 use crate::email::{BTUEmail, make_email_body_preamble};
 
-use crate::{btu_cron, config, rq};
-use crate::task_schedule::{BtuTaskSchedule, read_btu_task_schedule};
+use crate::{btu_cron, circuit_breaker, concurrency, config, dependency, enqueue_check, next_run_override, queue_stats, redis_keys, repeat_count, rq, run_history, task, timeutil, watchdog};
+use crate::errors::{PolicyError, RQError, SchedulerError};
+use crate::events::{self, EventKind, SchedulerEvent, StreamedEvent};
+use crate::dependency::DependencyPlan;
+use crate::ids::{CorrelationId, RqJobId, TaskScheduleId};
+use crate::run_history::{RunOutcome, RunRecord};
+use crate::schedule_queue::{MAX_WRITE_ATTEMPTS, QueueEntry, QueueSource, ScheduleQueue, write_retry_delay};
+use crate::task_schedule::BtuTaskSchedule;
+#[cfg(feature = "sql")]
+use crate::task_schedule::{read_btu_task_schedule, read_task_schedule_depends_on};
 
 // static RQ_SCHEDULER_NAMESPACE_PREFIX: &'static str = "rq:scheduler_instance:";
 // static RQ_KEY_SCHEDULER: &'static str = "rq:scheduler";
 // static RQ_KEY_SCHEDULER_LOCK: &'static str = "rq:scheduler_lock";
-static RQ_KEY_SCHEDULED_TASKS: &'static str = "btu_scheduler:task_execution_times";
+pub use crate::redis_keys::{BTU_KEY_PREFIX, RQ_KEY_CORRELATION_IDS, RQ_KEY_SCHEDULED_TASKS, RQ_KEY_UNSCHEDULABLE_PREFIX, unschedulable_key};
+use crate::redis_keys::{RQ_KEY_CIRCUIT_OPEN, RQ_KEY_NEXT_RUN_OVERRIDE, RQ_KEY_TASK_REPEAT_COUNTS, RQ_KEY_TASK_REPEAT_TOTALS};
+/// How many upcoming occurrences to examine, when looking for one that is not a holiday.
+const HOLIDAY_SEARCH_HORIZON: usize = 100;
+/// The most TSIKs stored at once for a Task Schedule that recurs faster than the polling interval.
+pub const MAX_OCCURRENCES_PER_SCHEDULE: usize = 120;
 
 
 pub struct TSIK(String);
 
 impl TSIK {
 
-	pub fn task_schedule_id(&self) -> &str {
-		self.0.split("|").collect::<Vec<&str>>()[0]
+	pub fn task_schedule_id(&self) -> TaskScheduleId {
+		TaskScheduleId::from(self.0.split("|").collect::<Vec<&str>>()[0])
 	}
 	pub fn next_unix_datetime(&self) -> i64 {
 		self.0.split("|").collect::<Vec<&str>>()[1].parse::<i64>().unwrap()
 	}
+	/// True if the key looks like "TaskScheduleID|UnixTime".  The accessors above panic on anything else.
+	pub fn is_well_formed(&self) -> bool {
+		match self.0.split_once('|') {
+			Some((task_schedule_id, unix_time)) => {
+				!task_schedule_id.is_empty() && !unix_time.contains('|') && unix_time.parse::<i64>().map_or(false, |value| value > 0)
+			},
+			None => false
+		}
+	}
 }
 
 impl From<String> for TSIK {
@@ -48,7 +71,7 @@ impl From<&str> for TSIK {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct RQScheduledTask {
-	pub task_schedule_id: String,
+	pub task_schedule_id: TaskScheduleId,
 	pub next_datetime_unix: i64,
 	pub next_datetime_utc: DateTime<Utc>,
 }
@@ -56,7 +79,7 @@ pub struct RQScheduledTask {
 impl RQScheduledTask {
 
 	pub fn to_tsik(&self) -> String {
-		format!("{}|{}", self.task_schedule_id, self.next_datetime_unix)
+		redis_keys::tsik(self.task_schedule_id.as_str(), self.next_datetime_unix)
 	}		
 
 	pub fn from_tsik(tsik: TSIK) -> RQScheduledTask {
@@ -67,13 +90,48 @@ impl RQScheduledTask {
  		let next_utc: DateTime<Utc> = DateTime::from_utc(next_naive, Utc);
 		// Build a new struct
 		RQScheduledTask {
-			task_schedule_id: tsik.task_schedule_id().to_owned(),
+			task_schedule_id: tsik.task_schedule_id(),
 			next_datetime_unix: tsik.next_unix_datetime(),
 			next_datetime_utc: next_utc
 		}
 	}
 }
 
+/// As JSON: 'task_schedule_id', 'next_datetime_unix', and 'next_datetime_utc' (RFC 3339).
+impl Serialize for RQScheduledTask {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		where S: serde::Serializer
+	{
+		use serde::ser::SerializeStruct;
+		let mut state = serializer.serialize_struct("RQScheduledTask", 3)?;
+		state.serialize_field("task_schedule_id", &self.task_schedule_id)?;
+		state.serialize_field("next_datetime_unix", &self.next_datetime_unix)?;
+		state.serialize_field("next_datetime_utc", &self.next_datetime_utc.to_rfc3339_opts(SecondsFormat::Secs, true))?;
+		state.end()
+	}
+}
+
+impl<'de> Deserialize<'de> for RQScheduledTask {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+		where D: serde::Deserializer<'de>
+	{
+		// The Unix time is authoritative; 'next_datetime_utc' is the same moment, written for people.
+		#[derive(Deserialize)]
+		struct Fields {
+			task_schedule_id: TaskScheduleId,
+			next_datetime_unix: i64,
+		}
+		let fields: Fields = Fields::deserialize(deserializer)?;
+		let next_naive = NaiveDateTime::from_timestamp_opt(fields.next_datetime_unix, 0)
+			.ok_or_else(|| serde::de::Error::custom(format!("'next_datetime_unix' is out of range: {}", fields.next_datetime_unix)))?;
+		Ok(RQScheduledTask {
+			task_schedule_id: fields.task_schedule_id,
+			next_datetime_unix: fields.next_datetime_unix,
+			next_datetime_utc: DateTime::from_naive_utc_and_offset(next_naive, Utc)
+		})
+	}
+}
+
 impl std::fmt::Display for RQScheduledTask {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		write!(f, "{} at {}",
@@ -103,23 +161,26 @@ fn _from_tuple_to_rqscheduledtask(tuple: &(String, String)) -> Result<RQSchedule
 		The tuple argument consists of 2 Strings: JobId and Unix Timestamp.
 		Using this information, we can build an RQScheduledTask struct.
 		There is no reason to consume the tuple; so accepting it as a reference.
+		Redis normally returns integral scores as integers, but a score written by another tool may look like "1669939200.0"
 	*/
-	let timestamp: i64 = tuple.1.parse::<i64>().unwrap();  // coerce the second String into an i64, using "turbofish" syntax
-	let utc_datetime:  DateTime<Utc> = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap(), Utc);
-	/*
-	let local_time_zone: chrono_tz::Tz = match tuple.2.parse() {
-		Ok(v) => v,
-		Err(e) => {
-			let new_error = std::io::Error::new(std::io::ErrorKind::Other, "Could not match local time zone to chrono Tz.");
-			return Err(new_error)
+	let timestamp: i64 = match tuple.1.parse::<i64>() {
+		Ok(timestamp) => timestamp,
+		Err(_) => match tuple.1.parse::<f64>() {
+			Ok(score) if score.is_finite() => score as i64,
+			_ => {
+				let message = format!("Member '{}' has a score that is not a Unix Time: '{}'", tuple.0, tuple.1);
+				return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, message));
+			}
 		}
 	};
-	*/
+	let Some(utc_datetime) = DateTime::<Utc>::from_timestamp(timestamp, 0) else {
+		let message = format!("Member '{}' has a score outside the range of dates: {}", tuple.0, timestamp);
+		return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, message));
+	};
 	Ok(RQScheduledTask {
-		task_schedule_id: tuple.0.clone(),
+		task_schedule_id: TaskScheduleId::from(tuple.0.as_str()),
 		next_datetime_unix: timestamp,
 		next_datetime_utc: utc_datetime
-		// next_datetime_local: local_time_zone.from_utc_datetime(&utc_datetime.naive_utc())
 	})
 }
 
@@ -128,32 +189,53 @@ fn _from_tuple_to_rqscheduledtask(tuple: &(String, String)) -> Result<RQSchedule
 // the Trait or Type.  In this case, I don't the "From" or "FromIterator" traits, nor the "Vector" type.
 // But I wrap Vec<RQScheduledTask> in a Newtype, and I can do whatever I want with it.
 
-pub struct VecRQScheduledTask ( Vec<RQScheduledTask> );
+// The second element counts malformed members that were skipped, rather than aborting the whole listing.
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VecRQScheduledTask ( Vec<RQScheduledTask>, usize );
 
 impl VecRQScheduledTask {
 
-	fn new() -> Self {
-		let empty_vector: Vec<RQScheduledTask> = Vec::new();
-		VecRQScheduledTask(empty_vector)
+	pub fn new() -> Self {
+		VecRQScheduledTask::default()
 	}
 
-	fn len(&self) -> usize {
-		// Because this is just a 1-element tuple, "self.0" gets the inner Vector!
+	pub fn len(&self) -> usize {
+		// "self.0" gets the inner Vector!
 		self.0.len()
 	}
 
-	fn sort_by_id(self) -> VecRQScheduledTask {
-		// Consumes the current VecRQScheduledTask, and returns another that is sorted by Task Schedule ID.
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	/// How many malformed members were skipped during conversion.
+	pub fn skipped(&self) -> usize {
+		self.1
+	}
+
+	/// The scheduled tasks, in their current order.  For indexing and slicing.
+	pub fn as_slice(&self) -> &[RQScheduledTask] {
+		&self.0
+	}
+
+	pub fn iter(&self) -> std::slice::Iter<'_, RQScheduledTask> {
+		self.0.iter()
+	}
+
+	/// Consumes the current VecRQScheduledTask, and returns another that is sorted by Task Schedule ID.
+	pub fn sort_by_id(self) -> VecRQScheduledTask {
 		let mut result = self.0;
-		result.sort_by(|a, b| a.task_schedule_id.partial_cmp(&b.task_schedule_id).unwrap());
-		VecRQScheduledTask(result)
+		result.sort_by(|a, b| a.task_schedule_id.cmp(&b.task_schedule_id));
+		VecRQScheduledTask(result, self.1)
 	}
-	
-	fn sort_by_next_datetime(self) -> VecRQScheduledTask {
-		// Consumes the current VecRQScheduledTask, and returns another that is sorted by Task Schedule ID.
+
+	/// Consumes the current VecRQScheduledTask, and returns another that is sorted by Next Execution Time.
+	/// The sort is stable: tasks due at the same moment keep their relative order.
+	pub fn sort_by_next_datetime(self) -> VecRQScheduledTask {
 		let mut result = self.0;
-		result.sort_by(|a, b| a.next_datetime_unix.partial_cmp(&b.next_datetime_unix).unwrap());
-		VecRQScheduledTask(result)
+		result.sort_by_key(|task| task.next_datetime_unix);
+		VecRQScheduledTask(result, self.1)
 	}
 
 }
@@ -165,43 +247,49 @@ impl std::iter::FromIterator<Result<RQScheduledTask, std::io::Error>> for VecRQS
 		
 		let mut result: VecRQScheduledTask = VecRQScheduledTask::new();
 		for inner in iter {
-			result.0.push(inner.unwrap()); 
+			match inner {
+				Ok(task) => result.0.push(task),
+				Err(error) => {
+					debug!("Skipping a scheduled task: {}", error);
+					result.1 += 1;
+				}
+			}
 		}
 		result
 	}
 }
 
-// Create a 3rd struct which will contain a reference to your set of data.
-struct IterNewType<'a> {
-	inner: &'a VecRQScheduledTask,
-	// position used to know where you are in your iteration.
-	pos: usize,
+/// Convert (member, score) pairs directly, such as from a Redis 'zscan' iterator; without collecting them first.
+impl std::iter::FromIterator<(String, String)> for VecRQScheduledTask {
+
+	fn from_iter<T>(iter: T) -> VecRQScheduledTask
+	where T: IntoIterator<Item=(String, String)> {
+		iter.into_iter().map(|tuple| _from_tuple_to_rqscheduledtask(&tuple)).collect()
+	}
 }
 
-// Now you can just implement the `Iterator` trait on your `IterNewType` struct.
-impl<'a> Iterator for IterNewType<'a> {
+impl IntoIterator for VecRQScheduledTask {
+	type Item = RQScheduledTask;
+	type IntoIter = std::vec::IntoIter<RQScheduledTask>;
 
+	fn into_iter(self) -> Self::IntoIter {
+		self.0.into_iter()
+	}
+}
+
+impl<'a> IntoIterator for &'a VecRQScheduledTask {
 	type Item = &'a RQScheduledTask;
+	type IntoIter = std::slice::Iter<'a, RQScheduledTask>;
 
-	fn next(&mut self) -> Option<Self::Item> {
-		if self.pos >= self.inner.len() {
-			// No more data to read, so stop here.
-			None
-		} else {
-			// We increment the position of our iterator.
-			self.pos += 1;
-			// We return the current value pointed by our iterator.
-			self.inner.0.get(self.pos - 1)
-		}
+	fn into_iter(self) -> Self::IntoIter {
+		self.0.iter()
 	}
 }
 
-impl VecRQScheduledTask {
-	fn iter<'a>(&'a self) -> IterNewType<'a> {
-		IterNewType {
-			inner: self,
-			pos: 0,
-		}
+impl From<VecRQScheduledTask> for Vec<RQScheduledTask> {
+
+	fn from(tasks: VecRQScheduledTask) -> Self {
+		tasks.0
 	}
 }
 
@@ -209,19 +297,18 @@ impl From<Vec<(String,String)>> for VecRQScheduledTask {
 
 	fn from(vec_of_tuple: Vec<(String,String)>) -> Self {
 
-		if vec_of_tuple.len() == 0 {
-			// If passed an empty tuple, return an empty Vector of RQScheduledTask.
-			return VecRQScheduledTask::new();
-		}
-		let result = vec_of_tuple.iter().map(_from_tuple_to_rqscheduledtask).collect();
-		result
+		vec_of_tuple.into_iter().collect()
 	}
 }
 
 /**
 	This function writes a Task Schedules "Next Execution Time(s)" to the Redis Queue database.
 */ 
-pub fn add_task_schedule_to_rq(app_config: &config::AppConfig, task_schedule: &BtuTaskSchedule) -> () {
+/// Returns the first Next Execution Time written to Redis, or None if the schedule has no Next Execution Time.
+/// An error means Redis could not be written (such as during a failover), and the caller should try again.
+/// A 'correlation_id' (from a socket request) is handed on to the RQ Job of the schedule's next run.
+pub fn add_task_schedule_to_rq(app_config: &config::AppConfig, task_schedule: &BtuTaskSchedule, correlation_id: Option<&CorrelationId>)
+	-> Result<Option<DateTime<Utc>>, RQError> {
 	/*
 		Developer Notes:
 		
@@ -258,62 +345,67 @@ pub fn add_task_schedule_to_rq(app_config: &config::AppConfig, task_schedule: &B
 			I'm going to call this a TSIK (Task Scheduled Instance Key)
 	*/
 
-	/*
-		Notice the line below: Only retrieving the 1st value from the result vector.  Later, it might be helpful to fetch
-		multiple Next Execution Times, because of time zone shifts around Daylight Savings.
-	*/
-	let next_runtimes = task_schedule.next_runtimes(&None, &1);
-	if next_runtimes.is_none() {
-		return;
-	}
-	let rq_scheduled_task: RQScheduledTask = RQScheduledTask {
-		task_schedule_id: task_schedule.id.to_owned(),
-		next_datetime_unix: next_runtimes.as_ref().unwrap()[0].timestamp(),
-		next_datetime_utc: next_runtimes.as_ref().unwrap()[0]
+	// Once per scheduling, say what the UI cannot: which reading applies to a cron restricting both the day of the month and
+	// the day of the week ('cron_dom_dow_semantics'), and that seconds and years are honored though Frappe cannot show them.
+	log_schedule_notices(app_config, task_schedule);
+	// Every site of a multi-site bench shares one Redis, so TSIKs and markers carry the site's name ("site::TS-0001|1669968000").
+	let schedule_key: TaskScheduleId = app_config.qualify_schedule_id(task_schedule.id.as_str());
+	let next_runtimes: Vec<DateTime<Utc>> = next_runtimes_for_task_schedule(app_config, task_schedule);
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(RQError::NoConnection);  // If cannot connect to Redis, do not panic the thread.
 	};
-
-	// Establish connection to Redis, and perform a ZADD
-	// Someday, I can make this better, with RFC 3137, let-else statements
-	// https://github.com/rust-lang/rust/issues/87335
-	let redis_conn: Option<redis::Connection> = rq::get_redis_connection(app_config, false);
-	if redis_conn.is_none() {
-		return ();  // If cannot connect to Redis, do not panic the thread.  Instead, return an empty Vector.
+	if app_config.max_consecutive_failures.unwrap_or_default() > 0 && circuit_breaker::is_open(&mut redis_conn, schedule_key.as_str()) {
+		info!("Task Schedule {} is not scheduled: its circuit breaker is open, after too many consecutive failures.", schedule_key);
+		return Ok(None);
+	}
+	if repeat_count::is_exhausted(&mut redis_conn, schedule_key.as_str(), task_schedule.repeat) {
+		info!("Task Schedule {} is not scheduled: it has already run all {} times of its 'repeat'.", schedule_key, task_schedule.repeat.unwrap_or_default());
+		return Ok(None);
+	}
+	// An operator moved the next run ('btu snooze'); until that run happens, the cron-computed times must not return.
+	if let Some(next_runtime) = next_run_override::active_override(&mut redis_conn, schedule_key.as_str(), Utc::now()) {
+		info!("Task Schedule {} keeps its next run at {}, set by an operator; cron scheduling resumes after that run.",
+		      schedule_key, next_runtime.to_rfc3339());
+		return Ok(Some(next_runtime));
 	}
+	// A schedule without a Next Execution Time would silently vanish from Redis.  Instead it is marked 'unschedulable' (for the
+	// CLI and socket), until it computes again.
+	let marker: Option<UnschedulableMarker> = (next_runtimes.is_empty() && task_schedule.enabled)
+		.then(|| UnschedulableMarker::new(&schedule_key, task_schedule, unschedulable_reason(task_schedule, app_config.cron_dom_dow_semantics)));
+	let reason: Option<String> = marker.as_ref().map(|marker| marker.reason.clone());
+	if update_unschedulable_marker(&mut redis_conn, schedule_key.as_str(), marker) == MarkerTransition::Marked {
+		notify_unschedulable(app_config, task_schedule);
+		events::record_event(app_config, &mut redis_conn,
+		                     SchedulerEvent::new(EventKind::Skipped).schedule(&schedule_key).reason(reason.unwrap_or_default()));
+	}
+	let Some(next_runtime) = next_runtimes.first().copied() else {
+		return Ok(None);
+	};
+	// A schedule recurring faster than the polling interval gets enough TSIKs to cover two intervals.  TSIKs are deterministic,
+	// so each run re-adding the same upcoming occurrences is harmless.
+	let members: Vec<(i64, String)> = next_runtimes.iter().map(|runtime| {
+		let upcoming = RQScheduledTask { task_schedule_id: schedule_key.clone(), next_datetime_unix: runtime.timestamp(), next_datetime_utc: *runtime };
+		(upcoming.next_datetime_unix, upcoming.to_tsik())
+	}).collect();
 
-	let mut redis_conn: redis::Connection = redis_conn.unwrap();  // shadow the previous variable assignment
-	let some_result: Result<std::primitive::u32, RedisError> = redis_conn.zadd(
-		RQ_KEY_SCHEDULED_TASKS,
-		rq_scheduled_task.to_tsik(),
-		rq_scheduled_task.next_datetime_unix
-	);
+	// Perform a ZADD
+	let some_result: Result<std::primitive::u32, RedisError> = redis_conn.zadd_multiple(RQ_KEY_SCHEDULED_TASKS, &members);
 
 	match some_result {
-		Ok(_result) => {
-			trace!("Result from 'zadd' is Ok, with the following payload: {}", _result);
-			// Developer Note: I believe a result of 1 means Redis wrote a new record.
-			//                 A result of 0 means the record already existed, and no write was necessary.
-			let message1: &str = &format!("Task Schedule ID {} is being monitored for future execution.", task_schedule.id);
-			// If application configuration has a good Time Zone string, print Next Execution Time in local time...
-			if let Ok(timezone) = app_config.tz() {
-				let message2: &str = &format!("Next Execution Time ({}) for Task Schedule {} = {}", 
-											timezone, 
-											task_schedule.id, 
-											rq_scheduled_task.next_datetime_utc.with_timezone(&timezone).to_rfc2822());	
-				let message3: &str =  &format!("Next Execution Time (UTC) for Task Schedule {} = {}",
-					                           task_schedule.id,
-											   rq_scheduled_task.next_datetime_utc.to_rfc3339());
-				debug!(message1, message2, message3);
-			}
-			else {
-				// Otherwise, just print in UTC.	
-				let message3: &str =  &format!("Next Execution Time (UTC) for Task Schedule {} = {}",
-				                               task_schedule.id,
-											   rq_scheduled_task.next_datetime_utc.to_rfc3339());
-				debug!(message1, message3);
+		Ok(added) => {
+			trace!("Result from 'zadd' is Ok, with the following payload: {}", added);
+			// The correlation ID waits until Thread #3 creates the RQ Job of the next run.  Later runs follow from the schedule, not the request.
+			if let Some(correlation_id) = correlation_id {
+				if let Err(error) = redis_conn.hset::<_, _, _, ()>(RQ_KEY_CORRELATION_IDS, schedule_key.as_str(), correlation_id.as_str()) {
+					warn!("Unable to store correlation ID '{}' for Task Schedule {}: {}", correlation_id, schedule_key, error);
+				}
 			}
+			report_schedule_written(app_config, &mut redis_conn, task_schedule, &schedule_key, &next_runtimes, added, correlation_id);
+			Ok(Some(next_runtime))
 		},
 		Err(error) => {
-			error!("Result from redis 'zadd' is Err, with the following payload: {}", error);
+			// Reported by the caller, which decides whether to try again.
+			Err(RQError::WriteFailed { key: RQ_KEY_SCHEDULED_TASKS.to_owned(), source: error })
 		}
 	}
 	/*
@@ -322,7 +414,603 @@ pub fn add_task_schedule_to_rq(app_config: &config::AppConfig, task_schedule: &B
 		and the "Member" is the BTU Task Schedule identifier.
 		* We haven't created an RQ Jobs for this Task Schedule yet.
 	*/
-	()
+}
+
+/// The notices worth logging each time a Task Schedule is scheduled: ambiguous cron days, and fields Frappe cannot show.
+fn log_schedule_notices(app_config: &config::AppConfig, task_schedule: &BtuTaskSchedule) {
+	if let Some(notice) = dom_dow_notice(app_config, task_schedule) {
+		warn!("Task Schedule {}: {}", task_schedule.id, notice);
+	}
+	if let Some(notice) = cron_classification(task_schedule).and_then(|classification| classification.notice()) {
+		info!("Task Schedule {}: {}", task_schedule.id, notice);
+	}
+}
+
+/// After the TSIKs of a Task Schedule's 'next_runtimes' were written to Redis, 'added' of them new: record and log it.
+fn report_schedule_written(app_config: &config::AppConfig, redis_conn: &mut redis::Connection, task_schedule: &BtuTaskSchedule,
+                           schedule_key: &TaskScheduleId, next_runtimes: &[DateTime<Utc>], added: u32, correlation_id: Option<&CorrelationId>) {
+	let (next_runtime, member_count): (DateTime<Utc>, usize) = (next_runtimes[0], next_runtimes.len());
+	if added > 0 || correlation_id.is_some() {
+		events::record_event(app_config, redis_conn,
+		                     SchedulerEvent::new(EventKind::Scheduled).schedule(schedule_key).run_at(next_runtime).correlation(correlation_id));
+	}
+	if member_count > 1 && added as usize == member_count {
+		// Every member was new, so this schedule just started being monitored.  Say so once; not on every replenishment.
+		warn!("Task Schedule {} recurs more often than the scheduler polling interval ({} seconds).  {} upcoming runs are stored at a time, \
+		       but runs are only enqueued once per polling interval; in practice, the polling interval is the finest granularity.",
+		       task_schedule.id, app_config.scheduler_polling_interval, member_count);
+	}
+	// Developer Note: I believe a result of 1 means Redis wrote a new record.
+	//                 A result of 0 means the record already existed, and no write was necessary.
+	log_monitored_schedule(app_config, &task_schedule.id, &task_schedule.describe_schedule(app_config.cron_dom_dow_semantics),
+	                       next_runtime, correlation_id);
+}
+
+/// One Task Schedule's result from add_many_task_schedules_to_rq(): what add_task_schedule_to_rq() would have returned.
+#[derive(Debug)]
+pub struct BulkScheduleResult {
+	pub schedule_key: TaskScheduleId,  // qualified by its site (see config::qualify_schedule_id)
+	pub result: Result<Option<DateTime<Utc>>, RQError>,
+}
+
+/**
+  add_task_schedule_to_rq(), for every Task Schedule of a full refresh at once.  Every Next Execution Time is calculated first;
+  then all their TSIKs are written, and their unschedulable markers cleared, with one Redis pipeline: one round trip, rather
+  than one per Task Schedule.\
+  A schedule whose circuit breaker is open, whose next run an operator moved, that has run every time its 'repeat' allows,
+  or that computes no Next Execution Time, is handed to add_task_schedule_to_rq() instead.  Those are few, and their rules live there.\
+  An error means the pipeline was not written, and the caller should fall back to scheduling one Task Schedule at a time.
+*/
+pub fn add_many_task_schedules_to_rq(app_config: &config::AppConfig, schedules: &[BtuTaskSchedule]) -> Result<Vec<BulkScheduleResult>, RQError> {
+
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(RQError::NoConnection);
+	};
+	let circuit_open: HashSet<String> = if app_config.max_consecutive_failures.unwrap_or_default() > 0 {
+		redis_conn.smembers(RQ_KEY_CIRCUIT_OPEN).map_err(|source| RQError::ReadFailed { key: RQ_KEY_CIRCUIT_OPEN.to_owned(), source })?
+	} else {
+		HashSet::new()
+	};
+	let overridden: HashSet<String> = redis_conn.hkeys(RQ_KEY_NEXT_RUN_OVERRIDE)
+		.map_err(|source| RQError::ReadFailed { key: RQ_KEY_NEXT_RUN_OVERRIDE.to_owned(), source })?;
+	let repeat_counts: HashMap<String, u32> = redis_conn.hgetall(RQ_KEY_TASK_REPEAT_COUNTS)
+		.map_err(|source| RQError::ReadFailed { key: RQ_KEY_TASK_REPEAT_COUNTS.to_owned(), source })?;
+	let repeat_totals: HashMap<String, u32> = redis_conn.hgetall(RQ_KEY_TASK_REPEAT_TOTALS)
+		.map_err(|source| RQError::ReadFailed { key: RQ_KEY_TASK_REPEAT_TOTALS.to_owned(), source })?;
+
+	let mut results: Vec<BulkScheduleResult> = Vec::with_capacity(schedules.len());
+	let mut batch: Vec<(&BtuTaskSchedule, TaskScheduleId, Vec<DateTime<Utc>>)> = Vec::with_capacity(schedules.len());
+	for task_schedule in schedules {
+		let schedule_key: TaskScheduleId = app_config.qualify_schedule_id(task_schedule.id.as_str());
+		let next_runtimes: Vec<DateTime<Utc>> = next_runtimes_for_task_schedule(app_config, task_schedule);
+		let repeat_count: Option<repeat_count::RepeatCount> = repeat_counts.get(schedule_key.as_str())
+			.map(|remaining| repeat_count::RepeatCount { remaining: *remaining, repeat: repeat_totals.get(schedule_key.as_str()).copied() });
+		let repeats_exhausted: bool = repeat_count::effective_repeat(task_schedule.repeat)
+			.map_or(false, |repeat| repeat_count::remaining_runs(repeat_count, repeat) == Some(0));
+		if next_runtimes.is_empty() || circuit_open.contains(schedule_key.as_str()) || overridden.contains(schedule_key.as_str()) || repeats_exhausted {
+			let result = add_task_schedule_to_rq(app_config, task_schedule, None);
+			results.push(BulkScheduleResult { schedule_key, result });
+			continue;
+		}
+		log_schedule_notices(app_config, task_schedule);
+		batch.push((task_schedule, schedule_key, next_runtimes));
+	}
+	if batch.is_empty() {
+		return Ok(results);
+	}
+
+	let pipeline: redis::Pipeline = bulk_schedule_pipeline(batch.iter().map(|(_, schedule_key, next_runtimes)| (schedule_key, next_runtimes.as_slice())));
+	let replies: Vec<u32> = pipeline.query(&mut redis_conn)
+		.map_err(|source| RQError::WriteFailed { key: RQ_KEY_SCHEDULED_TASKS.to_owned(), source })?;
+	for ((task_schedule, schedule_key, next_runtimes), reply) in batch.into_iter().zip(replies.chunks(2)) {
+		let (added, unmarked): (u32, u32) = (reply[0], reply.get(1).copied().unwrap_or_default());
+		if unmarked > 0 {
+			info!("Task Schedule {} has a Next Execution Time again; it is no longer flagged as unschedulable.", schedule_key);
+		}
+		report_schedule_written(app_config, &mut redis_conn, task_schedule, &schedule_key, &next_runtimes, added, None);
+		results.push(BulkScheduleResult { schedule_key, result: Ok(Some(next_runtimes[0])) });
+	}
+	Ok(results)
+}
+
+/// The commands add_many_task_schedules_to_rq() sends: for each Task Schedule, a ZADD of its TSIKs, then a DEL of its
+/// unschedulable marker.  However many schedules there are, they are packed into one request; the replies come back in order.
+pub fn bulk_schedule_pipeline<'a>(batch: impl IntoIterator<Item = (&'a TaskScheduleId, &'a [DateTime<Utc>])>) -> redis::Pipeline {
+	let mut pipeline = redis::pipe();
+	for (schedule_key, next_runtimes) in batch {
+		let members: Vec<(i64, String)> = next_runtimes.iter()
+			.map(|runtime| (runtime.timestamp(), redis_keys::tsik(schedule_key.as_str(), runtime.timestamp())))
+			.collect();
+		pipeline.zadd_multiple(RQ_KEY_SCHEDULED_TASKS, &members).del(unschedulable_key(schedule_key.as_str()));
+	}
+	pipeline
+}
+
+/// What Thread #1 did with an entry whose Next Execution Times could not be written to Redis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteRetry {
+	Requeued { attempt: u32 },  // pushed to the back of the Internal Queue; 'attempt' is the number of the next try
+	GaveUp,                     // every attempt failed
+}
+
+/**
+  After add_task_schedule_to_rq() fails, push the entry to the back of the Internal Queue.  A brief Redis outage
+  (such as a failover) then delays the schedule by seconds, rather than losing it until the next full refresh.
+  Each retry waits longer than the last (see schedule_queue::write_retry_delay), so an outage is not hammered.
+  After MAX_WRITE_ATTEMPTS, give up; the caller should then call abandon_schedule_write().
+*/
+pub fn retry_schedule_write(queue: &mut ScheduleQueue, entry: &QueueEntry, error: &RQError, now: DateTime<Utc>) -> WriteRetry {
+	let Some(retry) = entry.next_attempt(now) else {
+		error!("Giving up on Task Schedule {} (source: {}): its Next Execution Times could not be written to Redis after {} attempts.  \
+		        The next full refresh will try again.  Last error: {}", entry.schedule_id, entry.source, entry.attempt, error);
+		return WriteRetry::GaveUp;
+	};
+	let attempt: u32 = retry.attempt;
+	warn!("Unable to write the Next Execution Times of Task Schedule {} to Redis (attempt {} of {}); trying again in {}s: {}",
+	      entry.schedule_id, entry.attempt, MAX_WRITE_ATTEMPTS, write_retry_delay(attempt).num_seconds(), error);
+	if !queue.push_back(retry) {
+		trace!("Task Schedule {} is already pending in the Internal Queue; it will be written then.", entry.schedule_id);
+	}
+	WriteRetry::Requeued { attempt }
+}
+
+/// Once every attempt has failed, flag the Task Schedule as unschedulable, and record a Failed event.
+/// Both are best-effort, because Redis may still be unavailable.  The marker clears once the schedule is written again.
+pub fn abandon_schedule_write(app_config: &config::AppConfig, task_schedule: &BtuTaskSchedule, error: &RQError) {
+	let schedule_key: TaskScheduleId = app_config.qualify_schedule_id(task_schedule.id.as_str());
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return;
+	};
+	let reason: String = format!("Its Next Execution Times could not be written to Redis after {} attempts: {}", MAX_WRITE_ATTEMPTS, error);
+	update_unschedulable_marker(&mut redis_conn, schedule_key.as_str(), Some(UnschedulableMarker::new(&schedule_key, task_schedule, reason.clone())));
+	events::record_event(app_config, &mut redis_conn, SchedulerEvent::new(EventKind::Failed).schedule(&schedule_key).reason(reason));
+}
+
+/// Default for 'sql_retry_backoff_secs'.
+pub const DEFAULT_SQL_RETRY_BACKOFF_SECS: u32 = 15;
+
+/// How long Thread #1 waits before reading again, after MariaDB was unreachable.
+pub fn sql_retry_backoff(app_config: &config::AppConfig) -> std::time::Duration {
+	std::time::Duration::from_secs(u64::from(app_config.sql_retry_backoff_secs.unwrap_or(DEFAULT_SQL_RETRY_BACKOFF_SECS)))
+}
+
+/**
+  After read_btu_task_schedule() fails, decide what happens to the entry.  When MariaDB could not be reached
+  (SqlUnavailable), push the entry back to the very front of the Internal Queue, and return true; the caller should then
+  wait before reading again.  Any other failure (such as a deleted Task Schedule) is logged, and the entry is dropped.
+*/
+pub fn retry_schedule_read(queue: &mut ScheduleQueue, entry: &QueueEntry, error: &SchedulerError) -> bool {
+	match error {
+		SchedulerError::SqlUnavailable { .. } => {
+			warn!("{}  Task Schedule {} (source: {}) stays at the front of the Internal Queue.", error, entry.schedule_id, entry.source);
+			queue.push_front(entry.clone());
+			true
+		},
+		_ => {
+			error!("Unable to read BTU Task Schedule {} (source: {}); discarding it.  {}\n(verify BTU Configuration has a Time Zone)",
+			       entry.schedule_id, entry.source, error);
+			false
+		}
+	}
+}
+
+/// The debug event written after a Task Schedule's Next Execution Times are stored in Redis.
+pub(crate) fn log_monitored_schedule(app_config: &config::AppConfig, task_schedule_id: &TaskScheduleId, description: &str,
+                                     next_datetime_utc: DateTime<Utc>, correlation_id: Option<&CorrelationId>) {
+	let correlation_id: Option<&str> = correlation_id.map(CorrelationId::as_str);
+	let message1: &str = &format!("Task Schedule ID {} is being monitored for future execution ({}).", task_schedule_id, description);
+	// If application configuration has a good Time Zone string, print Next Execution Time in local time...
+	if let Ok(timezone) = app_config.tz() {
+		let message2: &str = &format!("Next Execution Time ({}) for Task Schedule {} = {}", 
+									timezone, 
+									task_schedule_id, 
+									next_datetime_utc.with_timezone(&timezone).to_rfc2822());	
+		let message3: &str =  &format!("Next Execution Time (UTC) for Task Schedule {} = {}",
+			                           task_schedule_id,
+									   next_datetime_utc.to_rfc3339());
+		debug!(message1, message2, message3, correlation_id);
+	}
+	else {
+		// Otherwise, just print in UTC.	
+		let message3: &str =  &format!("Next Execution Time (UTC) for Task Schedule {} = {}",
+		                               task_schedule_id,
+									   next_datetime_utc.to_rfc3339());
+		debug!(message1, message3, correlation_id);
+	}
+}
+
+/// Take (read and remove) the correlation ID waiting for a Task Schedule's next RQ Job.
+fn take_correlation_id(redis_conn: &mut redis::Connection, schedule_key: &TaskScheduleId) -> Option<CorrelationId> {
+	let result: Result<(Option<String>, u32), RedisError> = redis::pipe().atomic()
+		.hget(RQ_KEY_CORRELATION_IDS, schedule_key.as_str())
+		.hdel(RQ_KEY_CORRELATION_IDS, schedule_key.as_str())
+		.query(redis_conn);
+	match result {
+		Ok((correlation_id, _)) => correlation_id.map(CorrelationId::from),
+		Err(error) => {
+			warn!("Unable to read the correlation ID for Task Schedule {}: {}", schedule_key, error);
+			None
+		}
+	}
+}
+
+/// Recorded (as JSON) in the Redis key 'btu_scheduler:unschedulable:<id>', while an enabled Task Schedule has no Next Execution Time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UnschedulableMarker {
+	pub task_schedule_id: TaskScheduleId,
+	pub cron_string: String,  // or the repeat interval, for a schedule without a cron expression
+	pub cron_timezone: String,
+	pub reason: String,
+	pub detected_at: String,  // RFC 3339, UTC; when the schedule was first found unschedulable
+}
+
+impl UnschedulableMarker {
+	/// 'schedule_key' is the Task Schedule ID, qualified by its site (see config::qualify_schedule_id).
+	pub fn new(schedule_key: &TaskScheduleId, task_schedule: &BtuTaskSchedule, reason: String) -> Self {
+		UnschedulableMarker {
+			task_schedule_id: schedule_key.clone(),
+			cron_string: task_schedule.schedule_expression().to_owned(),
+			cron_timezone: task_schedule.cron_timezone.to_string(),
+			reason,
+			detected_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+		}
+	}
+}
+
+/// What happened to a Task Schedule's unschedulable marker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkerTransition {
+	Marked,        // newly unschedulable; worth a notification
+	StillMarked,   // was already flagged (the reason is refreshed)
+	Cleared,       // computes successfully again
+	Unchanged,     // schedulable, and never flagged
+}
+
+/// Where unschedulable markers are kept.  In the daemon, that is Redis.
+pub trait MarkerStore {
+	fn read_marker(&mut self, key: &str) -> Option<String>;
+	fn write_marker(&mut self, key: &str, value: &str);
+	fn remove_marker(&mut self, key: &str) -> bool;
+}
+
+impl MarkerStore for redis::Connection {
+	fn read_marker(&mut self, key: &str) -> Option<String> {
+		self.get(key).unwrap_or_else(|error: RedisError| {
+			warn!("Unable to read Redis key '{}': {}", key, error);
+			None
+		})
+	}
+
+	fn write_marker(&mut self, key: &str, value: &str) {
+		if let Err(error) = self.set::<_, _, ()>(key, value) {
+			error!("Unable to write Redis key '{}': {}", key, error);
+		}
+	}
+
+	fn remove_marker(&mut self, key: &str) -> bool {
+		self.del::<_, u32>(key).map_or_else(|error| {
+			error!("Unable to delete Redis key '{}': {}", key, error);
+			false
+		}, |removed| removed > 0)
+	}
+}
+
+/// Write or clear a Task Schedule's unschedulable marker.  'marker' is None when the schedule computed a Next Execution Time.
+pub fn update_unschedulable_marker<S: MarkerStore>(store: &mut S, task_schedule_id: &str, marker: Option<UnschedulableMarker>) -> MarkerTransition {
+	let key: String = unschedulable_key(task_schedule_id);
+	let Some(mut marker) = marker else {
+		if store.remove_marker(&key) {
+			info!("Task Schedule {} has a Next Execution Time again; it is no longer flagged as unschedulable.", task_schedule_id);
+			return MarkerTransition::Cleared;
+		}
+		return MarkerTransition::Unchanged;
+	};
+	let previous: Option<UnschedulableMarker> = store.read_marker(&key).and_then(|json| serde_json::from_str(&json).ok());
+	if let Some(previous) = &previous {
+		marker.detected_at = previous.detected_at.clone();  // keep the time it was first found
+	}
+	store.write_marker(&key, &serde_json::to_string(&marker).unwrap_or_default());
+	if previous.is_some() {
+		return MarkerTransition::StillMarked;
+	}
+	error!("Task Schedule {} is enabled, but cron expression '{}' yields no Next Execution Time ({}).  It will not run until corrected.",
+	       task_schedule_id, marker.cron_string, marker.reason);
+	MarkerTransition::Marked
+}
+
+/// Why a Task Schedule computes no Next Execution Time.
+pub fn unschedulable_reason(task_schedule: &BtuTaskSchedule, semantics: btu_cron::DomDowSemantics) -> String {
+	if let Some(Err(error)) = task_schedule.repeat_interval() {
+		return error.to_string();
+	}
+	match btu_cron::tz_cron_to_utc_datetimes(&task_schedule.cron_string, task_schedule.cron_timezone, None, &1, semantics) {
+		Err(error) => error.to_string(),
+		Ok(runtimes) if runtimes.is_empty() => "The cron expression has no upcoming dates (a year in the past, or an impossible combination of days).".to_owned(),
+		Ok(_) => "Every upcoming date was skipped, or could not be calculated.".to_owned(),
+	}
+}
+
+/// Email the recipients (when email is configured) about a Task Schedule that just became unschedulable.
+#[allow(unused_variables)]
+fn notify_unschedulable(app_config: &config::AppConfig, task_schedule: &BtuTaskSchedule) {
+	#[cfg(feature = "email-feat")]  // Only compile this code when email feature is enabled:
+	if app_config.email_addresses.as_ref().map_or(false, |addresses| !addresses.is_empty()) {
+		let body: String = format!("{}\nBTU Task Schedule {} is enabled, but its cron expression '{}' yields no Next Execution Time.  \
+		                            It will not run until the schedule is corrected.",
+			make_email_body_preamble(app_config), task_schedule.id, task_schedule.schedule_expression());
+		if let Err(error) = email::send_email(app_config, "BTU cannot schedule a Task Schedule", &body) {
+			error!("Error while attempting to send an email: {:?}", error);
+		}
+	}
+}
+
+/// Every unschedulable marker in Redis, ordered by Task Schedule ID.
+pub fn read_unschedulable_markers(app_config: &config::AppConfig) -> Vec<UnschedulableMarker> {
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Vec::new();
+	};
+	let keys: Vec<String> = redis_conn.scan_match::<_, String>(format!("{}*", RQ_KEY_UNSCHEDULABLE_PREFIX))
+		.map(|keys| keys.collect())
+		.unwrap_or_default();
+	let mut markers: Vec<UnschedulableMarker> = keys.iter()
+		.filter_map(|key| redis_conn.read_marker(key))
+		.filter_map(|json| serde_json::from_str(&json).ok())
+		.collect();
+	markers.sort_by(|a, b| a.task_schedule_id.cmp(&b.task_schedule_id));
+	markers
+}
+
+/// The unschedulable marker for one Task Schedule, if it is flagged.
+pub fn read_unschedulable_marker(app_config: &config::AppConfig, task_schedule_id: &str) -> Option<UnschedulableMarker> {
+	let mut redis_conn: redis::Connection = rq::get_redis_connection(app_config, false)?;
+	redis_conn.read_marker(&unschedulable_key(task_schedule_id)).and_then(|json| serde_json::from_str(&json).ok())
+}
+
+/// The Task Schedules of 'site' (None for the default site) that have an unschedulable marker, but are no longer enabled.
+pub fn stale_unschedulable_markers(markers: &[UnschedulableMarker], site: Option<&str>, enabled_ids: &HashSet<String>) -> Vec<TaskScheduleId> {
+	markers.iter()
+		.filter(|marker| {
+			let (marker_site, task_schedule_id) = config::split_schedule_id(marker.task_schedule_id.as_str());
+			marker_site == site && !enabled_ids.contains(task_schedule_id)
+		})
+		.map(|marker| marker.task_schedule_id.clone())
+		.collect()
+}
+
+#[cfg(feature = "sql")]
+/// Delete the unschedulable markers of a site's Task Schedules that were disabled or deleted since they were flagged.
+/// The full refresh only visits enabled schedules, so nothing else would ever clear them.  Returns how many were deleted.
+pub fn prune_unschedulable_markers(app_config: &config::AppConfig) -> usize {
+	let markers: Vec<UnschedulableMarker> = read_unschedulable_markers(app_config);
+	if markers.is_empty() {
+		return 0;
+	}
+	let enabled_ids: HashSet<String> = match crate::task_schedule::read_enabled_task_schedule_ids(app_config) {
+		Ok(ids) => ids.into_iter().collect(),
+		Err(error) => {
+			warn!("Unable to read the enabled Task Schedules; unschedulable markers were not pruned: {}", error);
+			return 0;
+		}
+	};
+	let stale: Vec<TaskScheduleId> = stale_unschedulable_markers(&markers, app_config.site_name(), &enabled_ids);
+	if stale.is_empty() {
+		return 0;
+	}
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return 0;
+	};
+	let keys: Vec<String> = stale.iter().map(|schedule_key| unschedulable_key(schedule_key.as_str())).collect();
+	match redis_conn.del::<_, usize>(&keys) {
+		Ok(deleted) => {
+			info!("Removed the unschedulable markers of {} Task Schedules that are no longer enabled: {}.", deleted,
+			      stale.iter().map(|schedule_key| schedule_key.as_str()).collect::<Vec<&str>>().join(", "));
+			deleted
+		},
+		Err(error) => {
+			warn!("Unable to remove stale unschedulable markers: {}", error);
+			0
+		}
+	}
+}
+
+/// The score (Next Execution Time, as a Unix timestamp) of a TSIK in Redis.  None if the TSIK is absent, or Redis is unreachable.
+pub fn scheduled_score(app_config: &config::AppConfig, tsik: &str) -> Option<i64> {
+	let mut redis_conn: redis::Connection = rq::get_redis_connection(app_config, false)?;
+	let score: Result<Option<i64>, RedisError> = redis_conn.zscore(RQ_KEY_SCHEDULED_TASKS, tsik);
+	score.unwrap_or_else(|error| {
+		warn!("Unable to read the score of '{}' in '{}': {}", tsik, RQ_KEY_SCHEDULED_TASKS, error);
+		None
+	})
+}
+
+/// For a cron expression that restricts both the day of the month and the day of the week: how it is read, and its next 3 runs.
+pub fn dom_dow_notice(app_config: &config::AppConfig, task_schedule: &BtuTaskSchedule) -> Option<String> {
+	if task_schedule.repeat_interval().is_some() || !btu_cron::restricts_day_of_month_and_week(&task_schedule.cron_string) {
+		return None;
+	}
+	let upcoming: Vec<DateTime<chrono_tz::Tz>> = task_schedule.next_runtimes(&None, &3, app_config.cron_dom_dow_semantics)
+		.unwrap_or_default()
+		.iter()
+		.map(|runtime| runtime.with_timezone(&task_schedule.cron_timezone))
+		.collect();
+	Some(btu_cron::dom_dow_notice(&task_schedule.cron_string, app_config.cron_dom_dow_semantics, &upcoming))
+}
+
+/// How a Task Schedule's cron expression was written (5, 6, or 7 elements); None for a repeat interval, or an invalid expression.
+pub fn cron_classification(task_schedule: &BtuTaskSchedule) -> Option<btu_cron::CronClassification> {
+	if task_schedule.repeat_interval().is_some() {
+		return None;
+	}
+	btu_cron::classify_cron(&task_schedule.cron_string).ok()
+}
+
+/// The next time a Task Schedule should run, according to its cron definition (and holidays, when it skips them).
+pub fn next_runtime_for_task_schedule(app_config: &config::AppConfig, task_schedule: &BtuTaskSchedule) -> Option<DateTime<Utc>> {
+	/*
+		Usually only the 1st value is needed.  The 2nd is fetched so that jitter can be capped by the following occurrence.
+		Later, it might be helpful to fetch multiple Next Execution Times, because of time zone shifts around Daylight Savings.
+	*/
+	let number_candidates: usize = if task_schedule.skip_holidays { HOLIDAY_SEARCH_HORIZON } else { 2 };
+	let anchor: Option<DateTime<Utc>> = interval_anchor(app_config, task_schedule);
+	let candidates: Vec<DateTime<Utc>> = task_schedule.next_runtimes(&anchor, &number_candidates, app_config.cron_dom_dow_semantics)?;
+	let next_runtime: DateTime<Utc> = if task_schedule.skip_holidays {
+		let first_workday = btu_cron::first_non_holiday(&candidates, task_schedule.cron_timezone, &read_holidays(app_config));
+		if first_workday.is_none() {
+			warn!("Task Schedule {}: the next {} occurrences are all holidays.  Not scheduling it.", task_schedule.id, candidates.len());
+		}
+		first_workday?
+	}
+	else {
+		candidates[0]
+	};
+	// Repeat intervals count from when each schedule last ran, so they are spread out already.
+	if app_config.schedule_jitter_secs == 0 || task_schedule.repeat_interval().is_some() {
+		return Some(next_runtime);
+	}
+
+	// Spread Task Schedules that share a cron expression, so they do not all hit the web server and workers at once.
+	let following: Option<DateTime<Utc>> = candidates.iter().find(|candidate| **candidate > next_runtime).copied();
+	let offset_secs: u32 = btu_cron::jitter_offset_secs(task_schedule.id.as_str(), app_config.schedule_jitter_secs);
+	let (jittered, applied) = btu_cron::apply_jitter(next_runtime, following, offset_secs);
+	debug!("Task Schedule {}: applied a jitter offset of {} seconds (cron time {}, jittered time {}).",
+	       task_schedule.id, applied, next_runtime.to_rfc3339(), jittered.to_rfc3339());
+	Some(jittered)
+}
+
+/// Where a repeat interval counts from.  Usually that is now: Thread #1 sees the schedule just after it was created, edited,
+/// or ran.  But when a run is already waiting in Redis (a full refresh of an unchanged schedule), count from that run instead,
+/// so that every refresh does not push the next run further away.
+fn interval_anchor(app_config: &config::AppConfig, task_schedule: &BtuTaskSchedule) -> Option<DateTime<Utc>> {
+	let Some(Ok(interval)) = task_schedule.repeat_interval() else {
+		return None;
+	};
+	soonest_scheduled_run(app_config, app_config.qualify_schedule_id(task_schedule.id.as_str()).as_str())
+		.filter(|scheduled| *scheduled > Utc::now())
+		.map(|scheduled| scheduled - interval)
+}
+
+/// Every upcoming run to store for a Task Schedule: only the next one, unless the schedule recurs faster than the polling interval.
+pub fn next_runtimes_for_task_schedule(app_config: &config::AppConfig, task_schedule: &BtuTaskSchedule) -> Vec<DateTime<Utc>> {
+	let Some(first) = next_runtime_for_task_schedule(app_config, task_schedule) else {
+		return Vec::new();
+	};
+	// A repeat interval continues from the first run.  A cron expression does not need an anchor.
+	let from: Option<DateTime<Utc>> = task_schedule.repeat_interval().and_then(Result::ok).map(|interval| first - interval);
+	// Cheap check first: most schedules are nowhere near the polling interval.
+	let is_high_frequency: bool = task_schedule.next_runtimes(&from, &2, app_config.cron_dom_dow_semantics)
+		.filter(|pair| pair.len() > 1)
+		.map_or(false, |pair| ((pair[1] - pair[0]).num_seconds() as u64) < app_config.scheduler_polling_interval);
+	if !is_high_frequency {
+		return vec![first];
+	}
+	let Some(occurrences) = task_schedule.next_runtimes(&from, &MAX_OCCURRENCES_PER_SCHEDULE, app_config.cron_dom_dow_semantics) else {
+		return vec![first];
+	};
+	// Carry over any jitter or holiday adjustment already applied to the first run.
+	let shift: chrono::Duration = occurrences.iter().rev().find(|occurrence| **occurrence <= first)
+		.map_or(chrono::Duration::zero(), |occurrence| first - *occurrence);
+	let holidays: HashSet<NaiveDate> = if task_schedule.skip_holidays { read_holidays(app_config) } else { HashSet::new() };
+	let shifted: Vec<DateTime<Utc>> = occurrences.iter()
+		.map(|occurrence| *occurrence + shift)
+		.filter(|runtime| *runtime >= first)
+		.filter(|runtime| !holidays.contains(&runtime.with_timezone(&task_schedule.cron_timezone).date_naive()))
+		.collect();
+	btu_cron::occurrences_within_polling_horizon(&shifted, app_config.scheduler_polling_interval, MAX_OCCURRENCES_PER_SCHEDULE)
+}
+
+/// How long until a Task Schedule next runs.  Negative when the next run is already past due.
+///
+/// The Redis sorted set is checked first, because it is cheap.  When nothing is waiting there, the next run is
+/// computed from the SQL cron definition instead.  An unknown Task Schedule is an Err, while a known one with nothing
+/// to run (e.g. it is disabled) is Ok(None).
+#[cfg(feature = "sql")]
+pub fn time_until_next_run(app_config: &config::AppConfig, task_schedule_id: &str) -> Result<Option<chrono::Duration>, SchedulerError> {
+
+	let scheduled: Option<DateTime<Utc>> = soonest_scheduled_run(app_config, task_schedule_id);
+	resolve_time_until_next_run(scheduled, Utc::now(), || {
+		// A qualified ID ("site::TS-0001") is read from that site's database.
+		let (site, unqualified_id) = config::split_schedule_id(task_schedule_id);
+		let site_config: config::AppConfig = app_config.for_site(site)
+			.ok_or_else(|| SchedulerError::UnknownTaskSchedule { task_schedule_id: task_schedule_id.to_owned() })?;
+		match crate::task_schedule::try_read_btu_task_schedule(&site_config, &TaskScheduleId::from(unqualified_id)) {
+			Ok(Some(task_schedule)) if task_schedule.enabled => Ok(next_runtime_for_task_schedule(&site_config, &task_schedule)),
+			Ok(Some(_)) => Ok(None),  // disabled
+			Ok(None) => Err(SchedulerError::UnknownTaskSchedule { task_schedule_id: task_schedule_id.to_owned() }),
+			Err(mysql_error) => Err(SchedulerError::Sql { task_schedule_id: task_schedule_id.to_owned(), message: mysql_error.to_string() })
+		}
+	})
+}
+
+/// Read a Task Schedule from its site's database, and classify its cron expression.  None when unknown, or unreadable.
+#[cfg(feature = "sql")]
+pub fn read_cron_classification(app_config: &config::AppConfig, task_schedule_id: &str) -> Option<btu_cron::CronClassification> {
+	let (site, unqualified_id) = config::split_schedule_id(task_schedule_id);
+	let site_config: config::AppConfig = app_config.for_site(site)?;
+	match crate::task_schedule::try_read_btu_task_schedule(&site_config, &TaskScheduleId::from(unqualified_id)) {
+		Ok(task_schedule) => task_schedule.as_ref().and_then(cron_classification),
+		Err(error) => {
+			debug!("Unable to read Task Schedule {} to classify its cron expression: {}", task_schedule_id, error);
+			None
+		}
+	}
+}
+
+/// The logic of time_until_next_run(), with Redis and SQL supplied by the caller.  The SQL fallback is only called when Redis has nothing.
+pub fn resolve_time_until_next_run<F>(scheduled: Option<DateTime<Utc>>, now: DateTime<Utc>, sql_fallback: F)
+	-> Result<Option<chrono::Duration>, SchedulerError>
+where F: FnOnce() -> Result<Option<DateTime<Utc>>, SchedulerError> {
+
+	let next_run: Option<DateTime<Utc>> = match scheduled {
+		Some(next_run) => Some(next_run),
+		None => sql_fallback()?
+	};
+	Ok(next_run.map(|next_run| next_run - now))
+}
+
+/// The soonest execution time in Redis for one Task Schedule; None if nothing is waiting, or Redis cannot be reached.
+pub fn soonest_scheduled_run(app_config: &config::AppConfig, task_schedule_id: &str) -> Option<DateTime<Utc>> {
+
+	let mut redis_conn: redis::Connection = rq::get_redis_connection(app_config, false)?;
+	let members: Vec<(String, i64)> = redis_conn.zscan_match::<_, _, (String, i64)>(RQ_KEY_SCHEDULED_TASKS, redis_keys::tsik_match_pattern(task_schedule_id))
+		.ok()?
+		.collect();
+	soonest_run_in_members(&members, task_schedule_id)
+}
+
+/// Given sorted-set members and scores, the soonest execution time belonging to one Task Schedule.
+pub fn soonest_run_in_members(members: &[(String, i64)], task_schedule_id: &str) -> Option<DateTime<Utc>> {
+
+	members.iter()
+		.filter(|(member, _)| {
+			let tsik = TSIK::from(member.as_str());
+			tsik.is_well_formed() && tsik.task_schedule_id() == task_schedule_id
+		})
+		.map(|(member, score)| rq_scheduled_task_from_member(member, *score).0.next_datetime_utc)
+		.min()
+}
+
+/// Holidays from the configuration file, plus those in the configured Frappe 'Holiday List' (if any).
+pub fn read_holidays(app_config: &config::AppConfig) -> HashSet<NaiveDate> {
+
+	let mut holidays: HashSet<NaiveDate> = app_config.configured_holiday_dates();
+	if let Some(holiday_list) = &app_config.holiday_source {
+		holidays.extend(read_holiday_list(app_config, holiday_list));
+	}
+	holidays
+}
+
+#[cfg(feature = "sql")]
+fn read_holiday_list(app_config: &config::AppConfig, holiday_list: &str) -> Vec<NaiveDate> {
+	crate::task_schedule::read_holiday_dates(app_config, holiday_list).unwrap_or_else(|error| {
+		error!("Unable to read dates from Holiday List '{}': {}", holiday_list, error);
+		Vec::new()
+	})
+}
+
+#[cfg(not(feature = "sql"))]
+fn read_holiday_list(_app_config: &config::AppConfig, holiday_list: &str) -> Vec<NaiveDate> {
+	debug!("Ignoring Holiday List '{}'; this build has no SQL support.", holiday_list);
+	Vec::new()
 }
 
 fn fetch_task_schedules_ready_for_rq(app_config: &config::AppConfig, sched_before_unix_time: i64) -> Vec<RQScheduledTask> {
@@ -347,23 +1035,24 @@ fn fetch_task_schedules_ready_for_rq(app_config: &config::AppConfig, sched_befor
 
 	// TODO: As per Redis 6.2.0, the command 'zrangebyscore' is considered deprecated.
 	// Please prefer using the ZRANGE command with the BYSCORE argument in new code.
-	let redis_result: Result<Vec<String>, redis::RedisError> = redis_conn.zrangebyscore(RQ_KEY_SCHEDULED_TASKS, 0, sched_before_unix_time);
+	let redis_result: Result<Vec<(String, f64)>, redis::RedisError> = redis_conn.zrangebyscore_withscores(RQ_KEY_SCHEDULED_TASKS, 0, sched_before_unix_time);
 	if redis_result.is_err() {
 		return Vec::new();  // if nothing to enqueue, then return an empty Vector.
 	}
 
-	let zranges: Vec<String> = redis_result.unwrap();
+	let zranges: Vec<(String, f64)> = redis_result.unwrap();
 	if zranges.len() > 0 {
-		info!("Found {:?} Task Schedules that qualify for immediate execution.", zranges.len());
+		debug!("Found {:?} Task Schedules that qualify for immediate execution.", zranges.len());
 	}
 	// The strings in the vector are a concatenation:  Task Schedule ID, pipe character, Unix Time.
 	// Need to split off the trailing Unix Time, to obtain a list of Task Schedules.
-	// NOTE: The syntax below is -very- "Rusty" (imo): maps the values returned by an iterator, using a closure function.
-	let task_schedules_to_enqueue: Vec<RQScheduledTask> = zranges.iter().map(|x| -> RQScheduledTask {
-
-		// each 'x' represents a pipe separated string.
-		let tsik: TSIK = x.to_owned().into();
-		RQScheduledTask::from_tsik(tsik)
+	// The score was fetched alongside, because it -should- equal the trailing Unix Time.  If not, repair the member.
+	let task_schedules_to_enqueue: Vec<RQScheduledTask> = zranges.iter().map(|(member, score)| -> RQScheduledTask {
+		let (rq_scheduled_task, is_mismatched) = rq_scheduled_task_from_member(member, *score as i64);
+		if is_mismatched {
+			repair_mismatched_member(&mut redis_conn, member, &rq_scheduled_task);
+		}
+		rq_scheduled_task
 	}).collect::<Vec<_>>();
 
 	// Finally, return a Vector of Task Schedule identifiers:
@@ -372,19 +1061,133 @@ fn fetch_task_schedules_ready_for_rq(app_config: &config::AppConfig, sched_befor
 
 }
 
+/**
+ Given a sorted-set member (a TSIK) and its score, build an RQScheduledTask.
+
+ The score and the TSIK's embedded Unix Time should be identical.  When they differ by more than 1 second
+ (a bug, or someone editing with redis-cli), the score wins; because the score is what ZRANGEBYSCORE selects on.
+ The boolean is true when the member disagrees with its score, and should be rewritten.
+*/
+pub fn rq_scheduled_task_from_member(member: &str, score: i64) -> (RQScheduledTask, bool) {
+
+	let from_member: RQScheduledTask = RQScheduledTask::from_tsik(TSIK::from(member));
+	if (from_member.next_datetime_unix - score).abs() <= 1 {
+		return (from_member, false);
+	}
+	warn!("Task Schedule Instance Key '{}' embeds Unix Time {}, but its score is {}.  Using the score.",
+	      member, from_member.next_datetime_unix, score);
+	let corrected = RQScheduledTask {
+		task_schedule_id: from_member.task_schedule_id,
+		next_datetime_unix: score,
+		next_datetime_utc: DateTime::<Utc>::from_timestamp(score, 0).unwrap_or(from_member.next_datetime_utc),
+	};
+	(corrected, true)
+}
+
+/// Replace a sorted-set member whose embedded Unix Time disagrees with its score, using the corrected TSIK.
+fn repair_mismatched_member(redis_conn: &mut redis::Connection, stale_member: &str, corrected: &RQScheduledTask) {
+
+	let removed: Result<u32, RedisError> = redis_conn.zrem(RQ_KEY_SCHEDULED_TASKS, stale_member);
+	let added: Result<u32, RedisError> = redis_conn.zadd(RQ_KEY_SCHEDULED_TASKS, corrected.to_tsik(), corrected.next_datetime_unix);
+	match (removed, added) {
+		(Ok(_), Ok(_)) => {
+			warn!("Replaced member '{}' with '{}' in '{}'.", stale_member, corrected.to_tsik(), RQ_KEY_SCHEDULED_TASKS);
+		},
+		(Err(error), _) | (_, Err(error)) => {
+			error!("Unable to repair member '{}' in '{}': {}", stale_member, RQ_KEY_SCHEDULED_TASKS, error);
+		}
+	}
+}
+
+/// What happened to one eligible Task Schedule Instance, when it did not fail.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskRunOutcome {
+	Enqueued,
+	Reenqueued,       // enqueued, but only on the second attempt: verification found the first incomplete
+	Skipped,          // Redis unavailable, the Task Schedule is disabled, or the queue is filtered out by configuration
+	BlockedByPolicy,  // the Task's function does not match 'allowed_function_prefixes'
+}
+
+/// Counts from one cycle of check_and_run_eligible_task_schedules(), logged as a single event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CycleSummary {
+	pub started_at: String,  // RFC 3339, UTC
+	pub cutoff_unix: i64,    // Task Schedule Instances scheduled at or before this time were eligible
+	pub eligible: usize,
+	pub enqueued: usize,
+	pub reenqueued: usize,  // of those enqueued, how many needed a second attempt (see enqueue_check)
+	pub failed: usize,
+	pub skipped: usize,
+	pub blocked_by_policy: usize,
+	pub unchanged_schedules_skipped: usize,    // full-refresh entries Thread #1 skipped since the previous cycle (fingerprint cache hits)
+	pub changed_schedules_processed: usize,    // full-refresh entries it had to read and re-add (cache misses)
+	pub duration_ms: u64,
+}
+
+impl CycleSummary {
+
+	pub fn new(started_at: DateTime<Utc>, cutoff_unix: i64) -> Self {
+		CycleSummary {
+			started_at: started_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+			cutoff_unix,
+			eligible: 0,
+			enqueued: 0,
+			reenqueued: 0,
+			failed: 0,
+			skipped: 0,
+			blocked_by_policy: 0,
+			unchanged_schedules_skipped: 0,
+			changed_schedules_processed: 0,
+			duration_ms: 0,
+		}
+	}
+
+	/// Count the outcome of one eligible Task Schedule Instance.
+	pub fn record<E>(&mut self, outcome: &Result<TaskRunOutcome, E>) {
+		self.eligible += 1;
+		match outcome {
+			Ok(TaskRunOutcome::Enqueued) => self.enqueued += 1,
+			Ok(TaskRunOutcome::Reenqueued) => {
+				self.enqueued += 1;
+				self.reenqueued += 1;
+			},
+			Ok(TaskRunOutcome::Skipped) => self.skipped += 1,
+			Ok(TaskRunOutcome::BlockedByPolicy) => self.blocked_by_policy += 1,
+			Err(_) => self.failed += 1,
+		}
+	}
+}
+
+impl fmt::Display for CycleSummary {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Scheduler cycle at {} (cutoff {}): {} eligible, {} enqueued, {} failed, {} skipped, {} blocked by policy, in {} ms.  \
+		           Since the previous cycle, {} unchanged schedules were skipped and {} were processed.",
+			self.started_at, self.cutoff_unix, self.eligible, self.enqueued, self.failed, self.skipped, self.blocked_by_policy, self.duration_ms,
+			self.unchanged_schedules_skipped, self.changed_schedules_processed)?;
+		if self.reenqueued > 0 {
+			write!(f, "  {} jobs were enqueued a second time, after verification found the first attempt incomplete.", self.reenqueued)?;
+		}
+		Ok(())
+	}
+}
+
 /**
 	 Examine the Next Execution Time for all scheduled RQ Jobs (this information is stored in RQ as a Unix timestamps)
 	If the Next Execution Time is in the past?  Then place the RQ Job into the appropriate queue.  RQ and Workers take over from there.
 */
 
-pub fn check_and_run_eligible_task_schedules(app_config: &config::AppConfig, internal_queue: &mut VecDeque<String>) {
+#[cfg(feature = "sql")]
+pub fn check_and_run_eligible_task_schedules(app_config: &config::AppConfig, internal_queue: &mut ScheduleQueue, started_at: DateTime<Utc>) -> CycleSummary {
 	// Developer Note: This function is analgous to the 'rq-scheduler' Python function: 'Scheduler.enqueue_jobs()'
-	let task_schedule_instances: Vec<RQScheduledTask> = fetch_task_schedules_ready_for_rq(app_config, Utc::now().timestamp());
+	let stopwatch = std::time::Instant::now();
+	let mut summary = CycleSummary::new(started_at, started_at.timestamp());
+	let task_schedule_instances: Vec<RQScheduledTask> = fetch_task_schedules_ready_for_rq(app_config, summary.cutoff_unix);
 
 	for task_schedule_instance in task_schedule_instances.iter() {
-		info!("Time to make the donuts! (enqueuing Redis Job '{}' for immediate execution)", task_schedule_instance.task_schedule_id);
-		match run_immediate_scheduled_task(app_config, task_schedule_instance, internal_queue) {
-			Ok(_) => {
+		debug!("Time to make the donuts! (enqueuing Redis Job '{}' for immediate execution)", task_schedule_instance.task_schedule_id);
+		let outcome = run_immediate_scheduled_task(app_config, task_schedule_instance, internal_queue);
+		match &outcome {
+			Ok(TaskRunOutcome::Enqueued) | Ok(TaskRunOutcome::Reenqueued) => {
 				#[cfg(feature = "email-feat")]  // Only compile this code when email feature is enabled:
 				if app_config.email_when_queuing {
 					// Send emails that mention the Task was enqueued.  This is useful for debugging or building confidence in the BTU.
@@ -400,21 +1203,87 @@ pub fn check_and_run_eligible_task_schedules(app_config: &config::AppConfig, int
 					}
 				}
 			},
+			Ok(TaskRunOutcome::Skipped) | Ok(TaskRunOutcome::BlockedByPolicy) => {},
 			Err(err) => {
 				error!("Error while attempting to run Task Schedule {} : {}", task_schedule_instance.task_schedule_id, err);
 			}
 		}
+		// Count consecutive failures, for the circuit breaker.  Skipped runs neither fail nor succeed.
+		if app_config.max_consecutive_failures.unwrap_or_default() > 0 && matches!(outcome, Ok(TaskRunOutcome::Enqueued) | Ok(TaskRunOutcome::Reenqueued) | Err(_)) {
+			if let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) {
+				let failure: Option<String> = outcome.as_ref().err().map(ToString::to_string);
+				circuit_breaker::record_run_outcome(app_config, &mut redis_conn, &task_schedule_instance.task_schedule_id, failure.as_deref());
+			}
+		}
+		summary.record(&outcome);
 	}
+	(summary.unchanged_schedules_skipped, summary.changed_schedules_processed) = internal_queue.fingerprints.take_counts();
+	summary.duration_ms = u64::try_from(stopwatch.elapsed().as_millis()).unwrap_or(u64::MAX);
+	summary
+}
+
+/// Default for 'failed_run_retry_secs'.
+pub const DEFAULT_FAILED_RUN_RETRY_SECS: u64 = 3600;
+
+/// What retry_failed_run() did with a run that failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailedRunRetry {
+	Restored,  // its TSIK is back, with its original score; the next pass tries it again
+	TooLate,   // more than 'failed_run_retry_secs' after its time; not tried again
+	Disabled,  // 'failed_run_retry_secs' is 0
+	Lost,      // Redis refused the TSIK; only the schedule's next run remains
 }
 
+/// Where a failed run's TSIK is put back.  In the daemon, that is Redis.
+pub trait TsikStore {
+	fn restore_tsik(&mut self, tsik: &str, next_unix: i64) -> bool;
+}
+
+impl TsikStore for redis::Connection {
+	fn restore_tsik(&mut self, tsik: &str, next_unix: i64) -> bool {
+		self.zadd::<_, _, _, u32>(RQ_KEY_SCHEDULED_TASKS, tsik, next_unix).map_or_else(|error| {
+			error!("Unable to add '{}' back to Redis sorted set '{}': {}", tsik, RQ_KEY_SCHEDULED_TASKS, error);
+			false
+		}, |_| true)
+	}
+}
+
+/**
+  Thread #3 removes a run's TSIK before it creates the RQ Job.  When that fails for a reason that may pass (the web server
+  or MariaDB did not answer, or Redis refused the job), the TSIK is put back with its original score, so the next pass
+  tries the run again; otherwise the run would be lost.  Only within 'retry_secs' of the run's time, so that a long outage
+  does not end with a burst of stale runs.\
+  Either way, the Task Schedule is pushed back onto the Internal Queue, so its next run is calculated promptly.
+*/
+pub fn retry_failed_run<S: TsikStore>(store: &mut S, internal_queue: &mut ScheduleQueue, task_schedule_instance: &RQScheduledTask,
+                                      retry_secs: u64, now: DateTime<Utc>) -> FailedRunRetry {
+	internal_queue.push_priority(QueueEntry::new(task_schedule_instance.task_schedule_id.clone(), QueueSource::PostRunRequeue));
+	if retry_secs == 0 {
+		return FailedRunRetry::Disabled;
+	}
+	let late_secs: i64 = now.timestamp() - task_schedule_instance.next_datetime_unix;
+	if late_secs > i64::try_from(retry_secs).unwrap_or(i64::MAX) {
+		warn!("Task Schedule {}: the run at {} failed, and is more than {} seconds late; it will not be tried again.",
+		      task_schedule_instance.task_schedule_id, task_schedule_instance.next_datetime_utc.to_rfc3339(), retry_secs);
+		return FailedRunRetry::TooLate;
+	}
+	if !store.restore_tsik(&task_schedule_instance.to_tsik(), task_schedule_instance.next_datetime_unix) {
+		return FailedRunRetry::Lost;
+	}
+	info!("Task Schedule {}: the run at {} failed; it will be tried again on the next pass.",
+	      task_schedule_instance.task_schedule_id, task_schedule_instance.next_datetime_utc.to_rfc3339());
+	FailedRunRetry::Restored
+}
+
+#[cfg(feature = "sql")]
 pub fn run_immediate_scheduled_task(app_config: &config::AppConfig, 
 									task_schedule_instance: &RQScheduledTask,
-									internal_queue: &mut VecDeque<String>) -> Result<(), anyhow::Error> {
+									internal_queue: &mut ScheduleQueue) -> Result<TaskRunOutcome, anyhow::Error> {
 
-	// 0. First remove the Task from the Schedule (so it doesn't get executed twice)
+	// 0. First remove the Task from the Schedule (so it doesn't get executed twice).  If the run then fails, retry_failed_run() puts it back.
 	if rq::get_redis_connection(app_config, true).is_none() {
 		warn!("Early exit from run_immediate_scheduled_task(); cannot establish a connection to Redis database.");
-		return Ok(());  // If cannot connect to Redis, do not panic the thread.  Instead, return an empty Vector.
+		return Ok(TaskRunOutcome::Skipped);  // If cannot connect to Redis, do not panic the thread.
 	}
 	let mut redis_conn = rq::get_redis_connection(app_config, true).unwrap();
 	let redis_result: u32 = redis_conn.zrem(RQ_KEY_SCHEDULED_TASKS, task_schedule_instance.to_tsik())?;
@@ -422,114 +1291,378 @@ pub fn run_immediate_scheduled_task(app_config: &config::AppConfig,
 	if redis_result != 1 {
 		error!("Unable to remove Task Schedule Instance using 'zrem'.  Response from Redis = {}", redis_result);
 	}
-
-	// 1. Read the MariaDB database to construct a BTU Task Schedule struct.
-	let task_schedule = read_btu_task_schedule(app_config, &task_schedule_instance.task_schedule_id);
-	if task_schedule.is_none() {
-		return Err(anyhow_macro!("Unable to read Task Schedule from MariaDB database."));
+	// When this was a run moved by an operator, the schedule follows its cron expression again from here on.
+	if next_run_override::complete_override(&mut redis_conn, task_schedule_instance.task_schedule_id.as_str(), task_schedule_instance.next_datetime_unix) {
+		debug!("Task Schedule {}: running its overridden next run; its override is removed.", task_schedule_instance.task_schedule_id);
+	}
+	// Every event below concerns this run of this Task Schedule; and, for the first run after a socket request, that request.
+	let correlation_id: Option<CorrelationId> = take_correlation_id(&mut redis_conn, &task_schedule_instance.task_schedule_id);
+	let _span = correlation_id.as_ref().map(|correlation_id| span!(Level::INFO, "run", correlation_id = %correlation_id).entered());
+	let run_event = |kind: EventKind| {
+		SchedulerEvent::new(kind).schedule(&task_schedule_instance.task_schedule_id).run_at(task_schedule_instance.next_datetime_utc)
+			.correlation(correlation_id.as_ref())
+	};
+	let history_key: &str = task_schedule_instance.task_schedule_id.as_str();
+	let run_record = |outcome: RunOutcome| RunRecord::new(outcome, task_schedule_instance.next_datetime_utc);
+	// A run that failed for a reason that may pass is tried again on the next pass.
+	let retry_secs: u64 = app_config.failed_run_retry_secs.unwrap_or(DEFAULT_FAILED_RUN_RETRY_SECS);
+	let retry_later = |redis_conn: &mut redis::Connection, internal_queue: &mut ScheduleQueue| {
+		retry_failed_run(redis_conn, internal_queue, task_schedule_instance, retry_secs, Utc::now())
+	};
+	// 0b. Pass over a Task Schedule whose circuit breaker opened after this TSIK was stored.
+	if app_config.max_consecutive_failures.unwrap_or_default() > 0 && circuit_breaker::is_open(&mut redis_conn, task_schedule_instance.task_schedule_id.as_str()) {
+		debug!("Skipping Task Schedule {}; its circuit breaker is open.", task_schedule_instance.task_schedule_id);
+		events::record_event(app_config, &mut redis_conn, run_event(EventKind::Skipped).reason("circuit_open"));
+		run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Skipped).detail("circuit_open"));
+		return Ok(TaskRunOutcome::Skipped);
 	}
-	let task_schedule: BtuTaskSchedule = task_schedule.unwrap();  // shadow original variable.
+
+	// 1. Read the MariaDB database to construct a BTU Task Schedule struct.  On a multi-site bench, use the database (and later,
+	//    the web server) of the site named in the TSIK.
+	let (site, task_schedule_id) = config::split_schedule_id(task_schedule_instance.task_schedule_id.as_str());
+	let Some(site_config) = app_config.for_site(site) else {
+		let error = anyhow_macro!("Task Schedule {} belongs to site '{}', which is not in the configuration.",
+		                          task_schedule_instance.task_schedule_id, site.unwrap_or_default());
+		events::record_event(app_config, &mut redis_conn, run_event(EventKind::Failed).reason(error.to_string()));
+		run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Failed).detail(error.to_string()));
+		return Err(error);
+	};
+	let app_config: &config::AppConfig = &site_config;  // shadow the original variable, for every step below
+	let task_schedule: BtuTaskSchedule = match read_btu_task_schedule(app_config, &TaskScheduleId::from(task_schedule_id)) {
+		Ok(task_schedule) => task_schedule,
+		Err(error) => {
+			let reason: String = format!("Unable to read Task Schedule from MariaDB database: {}", error);
+			events::record_event(app_config, &mut redis_conn, run_event(EventKind::Failed).reason(reason.clone()));
+			run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Failed).detail(reason.clone()));
+			if !matches!(error, SchedulerError::UnknownTaskSchedule { .. }) {
+				retry_later(&mut redis_conn, internal_queue);
+			}
+			return Err(anyhow_macro!(reason));
+		}
+	};
 
 	// 2. Exit early if the Task Schedule is disabled (this should be a rare scenario, but definitely worth checking.)
-	if task_schedule.enabled == 0 {
+	if !task_schedule.enabled {
 		warn!("Task Schedule {} is disabled in SQL database; BTU will neither execute nor re-queue.", task_schedule.id);
-		return Err(anyhow_macro!("Task Schedule {} is disabled in SQL database; BTU will neither execute nor re-queue.", task_schedule.id));
+		events::record_event(app_config, &mut redis_conn, run_event(EventKind::Skipped).reason("disabled"));
+		run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::ScheduleDisabled));
+		return Ok(TaskRunOutcome::Skipped);  // not a failure: it must not count toward the circuit breaker
+	}
+	// 2b. Exit early if the queue is filtered out by configuration (the TSIK may predate a configuration change)
+	if !is_queue_allowed_for_task_schedule(app_config, task_schedule.id.as_str(), task_schedule.queue_name()) {
+		let reason: String = format!("queue '{}' is filtered out", task_schedule.queue_name());
+		events::record_event(app_config, &mut redis_conn, run_event(EventKind::Skipped).reason(reason.clone()));
+		run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Skipped).detail(reason));
+		return Ok(TaskRunOutcome::Skipped);
+	}
+	// 2c. Exit early if the Task Schedule has already run every time its 'repeat' allows; its last run removes its upcoming
+	//     runs, but one may have been written since.  Not re-queued.
+	if repeat_count::is_exhausted(&mut redis_conn, task_schedule_instance.task_schedule_id.as_str(), task_schedule.repeat) {
+		if let Err(error) = remove_scheduled_runs(&mut redis_conn, &task_schedule_instance.task_schedule_id) {
+			warn!("Unable to remove the upcoming runs of Task Schedule {}: {}", task_schedule.id, error);
+		}
+		let reason: String = format!("already ran all {} times of its 'repeat'", task_schedule.repeat.unwrap_or_default());
+		info!("Task Schedule {} was not enqueued: it {}.", task_schedule.id, reason);
+		events::record_event(app_config, &mut redis_conn, run_event(EventKind::Skipped).reason(reason.clone()));
+		run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Skipped).detail(reason));
+		return Ok(TaskRunOutcome::Skipped);
+	}
+	// 2d. Pass over this occurrence if the BTU Task already has 'max_concurrent' instances running, on any queue.
+	let max_concurrent: Option<u32> = task::read_task_max_concurrent(app_config, task_schedule.task());
+	let running_key: String = concurrency::running_key(app_config.site_name(), task_schedule.task().as_str());
+	if let Some(max_concurrent) = max_concurrent {
+		match concurrency::running_instances(&mut redis_conn, &running_key, Utc::now()) {
+			Ok(running) if running >= max_concurrent as usize => {
+				let reason: String = format!("concurrency limit: {} of {} instances of BTU Task {} are running",
+				                             running, max_concurrent, task_schedule.task());
+				warn!("Task Schedule {} was not enqueued ({}).", task_schedule.id, reason);
+				events::record_event(app_config, &mut redis_conn, run_event(EventKind::Skipped).reason(reason.clone()));
+				run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Skipped).detail(reason));
+				internal_queue.push_priority(QueueEntry::new(task_schedule_instance.task_schedule_id.clone(), QueueSource::PostRunRequeue));
+				return Ok(TaskRunOutcome::Skipped);
+			},
+			Ok(_) => {},
+			Err(error) => {
+				warn!("Unable to count the running instances of BTU Task {}; ignoring its concurrency limit: {}", task_schedule.task(), error);
+			}
+		}
 	}
 	// 3. Create an RQ Job from the BtuTask struct.
-	let rq_job: rq::RQJob = task_schedule.to_rq_job(app_config)?;
+	let mut rq_job: rq::RQJob = match task_schedule.to_rq_job(app_config) {
+		Ok(rq_job) => rq_job,
+		Err(error) => {
+			if let Some(policy_error) = error.downcast_ref::<PolicyError>() {
+				// Not re-queued: until the Task or the policy changes, every future run would be blocked too.
+				warn!("Task Schedule {} was not enqueued (blocked_by_policy): {}", task_schedule.id, policy_error);
+				events::record_event(app_config, &mut redis_conn, run_event(EventKind::Skipped).reason(format!("blocked_by_policy: {}", policy_error)));
+				run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::BlockedByPolicy).detail(policy_error.to_string()));
+				return Ok(TaskRunOutcome::BlockedByPolicy);
+			}
+			events::record_event(app_config, &mut redis_conn, run_event(EventKind::Failed).reason(error.to_string()));
+			run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::FailedToBuild).detail(error.to_string()));
+			retry_later(&mut redis_conn, internal_queue);
+			return Err(error);
+		}
+	};
+	if let Some(correlation_id) = &correlation_id {
+		rq_job.set_correlation_id(correlation_id);
+	}
 	debug!("Created an RQJob struct: {}", rq_job);
 
-	// 4. Save the new Job into Redis.
-	rq_job.save_to_redis(app_config);
+	// 3b. A Task Schedule that depends on another ('depends_on_schedule') waits for that schedule's most recent RQ Job.
+	//     The job is saved as deferred, with RQ's own dependency structures; RQ's worker enqueues it when the parent finishes.
+	let dependency_plan: Option<DependencyPlan> = read_task_schedule_depends_on(app_config, &task_schedule.id)
+		.map(|parent_schedule_id| {
+			let parent_key: TaskScheduleId = app_config.qualify_schedule_id(parent_schedule_id.as_str());
+			let parent_job = dependency::read_last_job(&mut redis_conn, &parent_key).unwrap_or_else(|error| {
+				warn!("Unable to read the most recent RQ Job of Task Schedule {}: {}", parent_key, error);
+				None
+			});
+			dependency::plan(task_schedule.id.as_str(), parent_key.as_str(), parent_job)
+		});
+	if let Some(DependencyPlan::Defer { warning: Some(warning), .. } | DependencyPlan::EnqueueNow { warning: Some(warning) }) = &dependency_plan {
+		warn!("{}", warning);
+	}
+	if let Some(DependencyPlan::Defer { parent_job_id, .. }) = &dependency_plan {
+		if let Err(error) = rq_job.save_deferred_to_redis(app_config, parent_job_id) {
+			events::record_event(app_config, &mut redis_conn, run_event(EventKind::Failed).reason(error.to_string()));
+			run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Failed).job(&rq_job.job_key_short).detail(error.to_string()));
+			retry_later(&mut redis_conn, internal_queue);
+			return Err(error.into());
+		}
+		info!("Task Schedule {}: RQ Job {} is deferred until RQ Job {} finishes.", task_schedule.id, rq_job.job_key_short, parent_job_id);
+		repeat_count::count_run_and_requeue(&mut redis_conn, internal_queue, &task_schedule_instance.task_schedule_id, task_schedule.repeat);
+		record_last_job(&mut redis_conn, &task_schedule_instance.task_schedule_id, &rq_job.job_key_short);
+		events::record_event(app_config, &mut redis_conn, run_event(EventKind::Enqueued).job(&rq_job.job_key_short)
+		                     .reason(format!("deferred until RQ Job {} finishes", parent_job_id)));
+		run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Deferred).job(&rq_job.job_key_short)
+		                        .detail(format!("until RQ Job {} finishes", parent_job_id)));
+		return Ok(TaskRunOutcome::Enqueued);
+	}
+
+	// 4. Save the new Job into Redis.  If that fails, nothing was written, and there is nothing to enqueue.
+	if let Err(error) = rq_job.save_to_redis(app_config) {
+		events::record_event(app_config, &mut redis_conn, run_event(EventKind::Failed).reason(error.to_string()));
+		run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Failed).job(&rq_job.job_key_short).detail(error.to_string()));
+		retry_later(&mut redis_conn, internal_queue);
+		return Err(error.into());
+	}
 
 	// 5. Enqueue that job for immediate execution.
-	match rq::enqueue_job_immediate(&app_config, &rq_job.job_key_short) {
-		Ok(ok_message) => {
-			info!("Successfully enqueued: {}", ok_message);
-		}
-		Err(err_message) => {
-			error!("Error while attempting to queue job for execution: {}", err_message);
+	let enqueue_result = rq::enqueue_job_immediate(app_config, &rq_job.job_key_short, false);
+	if let Ok(outcome) = &enqueue_result {
+		info!("Successfully enqueued: {}", outcome);
+		for warning in &outcome.warnings {
+			warn!("Task Schedule {}: {}", task_schedule.id, warning);
 		}
 	}
+	// 5b. Verify the job hash exists, and the job is on its queue.  Under 'maxmemory' eviction, 'rpush' can succeed after the
+	//     hash is already gone.  When either is missing, save and enqueue the job once more.
+	let queue_name: String = rq_job.origin().to_owned();
+	let verification: Option<Result<enqueue_check::Verification, RedisError>> = enqueue_result.as_ref().ok().map(|_| {
+		enqueue_check::verify_enqueued(&mut redis_conn, &queue_name, &rq_job.job_key_short, |_| {
+			rq_job.save_to_redis(app_config).map_err(|error| format!("unable to save the job again: {}", error))?;
+			rq::enqueue_job_immediate(app_config, &rq_job.job_key_short, false).map(|_| ())
+				.map_err(|error| format!("unable to enqueue the job again: {}", error))
+		})
+	});
 	/* 6. Recalculate the next Run Time.
 		  Easy enough; just push the Task Schedule ID back into the -Internal- Queue! 
 		  It will get processed automatically during the next thread cycle.
+		  High priority: until it's processed, this Task Schedule has no Next Execution Time at all.
+		  A run that was enqueued counts against the schedule's 'repeat'; after its last run, it is not pushed back.
+		  A run that failed is pushed back below, by retry_failed_run().
 	*/
-	internal_queue.push_back(task_schedule_instance.task_schedule_id.to_owned());
-	Ok(())
+	let enqueued: bool = enqueue_result.is_ok() && !matches!(verification, Some(Ok(enqueue_check::Verification::Failed { .. })));
+	if enqueued {
+		repeat_count::count_run_and_requeue(&mut redis_conn, internal_queue, &task_schedule_instance.task_schedule_id, task_schedule.repeat);
+	}
+	let mut outcome: TaskRunOutcome = TaskRunOutcome::Enqueued;
+	let mut enqueued_event: SchedulerEvent = run_event(EventKind::Enqueued).job(&rq_job.job_key_short);
+	let mut enqueued_record: RunRecord = run_record(RunOutcome::Enqueued).job(&rq_job.job_key_short);
+	match verification {
+		Some(Ok(enqueue_check::Verification::Verified)) | None => {},
+		Some(Ok(enqueue_check::Verification::Repaired { first })) => {
+			error!("Task Schedule {}: after enqueuing RQ Job {} on queue '{}', {}.  The job was saved and enqueued again, successfully.",
+			       task_schedule.id, rq_job.job_key_short, queue_name, first);
+			outcome = TaskRunOutcome::Reenqueued;
+			enqueued_event = enqueued_event.reason(format!("enqueued again: {}", first));
+			enqueued_record = enqueued_record.detail(format!("enqueued again: {}", first));
+		},
+		Some(Ok(enqueue_check::Verification::Failed { first, retry })) => {
+			let error = anyhow_macro!("After enqueuing RQ Job {} on queue '{}', {}; a second attempt failed too ({}).",
+			                          rq_job.job_key_short, queue_name, first, retry);
+			error!("Task Schedule {}: {}", task_schedule.id, error);
+			events::record_event(app_config, &mut redis_conn, run_event(EventKind::Failed).job(&rq_job.job_key_short).reason(error.to_string()));
+			run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Failed).job(&rq_job.job_key_short).detail(error.to_string()));
+			retry_later(&mut redis_conn, internal_queue);
+			return Err(error);
+		},
+		Some(Err(error)) => {
+			warn!("Task Schedule {}: unable to verify that RQ Job {} is on queue '{}': {}", task_schedule.id, rq_job.job_key_short, queue_name, error);
+		},
+	}
+	match enqueue_result {
+		Ok(_) => {
+			events::record_event(app_config, &mut redis_conn, enqueued_event);
+			run_history::record_run(app_config, &mut redis_conn, history_key, enqueued_record);
+			record_last_job(&mut redis_conn, &task_schedule_instance.task_schedule_id, &rq_job.job_key_short);
+			if max_concurrent.is_some() {
+				if let Err(error) = concurrency::record_instance(&mut redis_conn, &running_key, &rq_job.job_key_short, rq_job.timeout, Utc::now()) {
+					warn!("Unable to record RQ Job {} as a running instance of BTU Task {}: {}", rq_job.job_key_short, task_schedule.task(), error);
+				}
+			}
+			if app_config.job_watchdog_enabled {
+				watchdog::watch_job(&mut redis_conn, &watchdog::WatchedJob::new(&rq_job.job_key_short, &task_schedule_instance.task_schedule_id,
+				                                                                 &queue_name, rq_job.timeout, Utc::now()));
+			}
+			Ok(outcome)
+		},
+		Err(error) => {
+			let error = anyhow_macro!("Error while attempting to queue job for execution: {}", error);
+			events::record_event(app_config, &mut redis_conn, run_event(EventKind::Failed).job(&rq_job.job_key_short).reason(error.to_string()));
+			run_history::record_run(app_config, &mut redis_conn, history_key, run_record(RunOutcome::Failed).job(&rq_job.job_key_short).detail(error.to_string()));
+			retry_later(&mut redis_conn, internal_queue);
+			Err(error)
+		}
+	}
 }
 
-pub fn rq_get_scheduled_tasks(app_config: &config::AppConfig) -> VecRQScheduledTask {
-	/*
-		Call RQ and request the list of values in "btu_scheduler:job_execution_times"
-	*/
+/// Remember a Task Schedule's newest RQ Job, for the schedules that depend on it.  A failure is only logged.
+fn record_last_job(redis_conn: &mut redis::Connection, task_schedule_id: &TaskScheduleId, job_id: &RqJobId) {
+	if let Err(error) = dependency::record_last_job(redis_conn, task_schedule_id, job_id) {
+		warn!("Unable to record RQ Job {} as the most recent job of Task Schedule {}: {}", job_id, task_schedule_id, error);
+	}
+}
 
-	// Someday, I can make this better, with RFC 3137, let-else statements
-	// https://github.com/rust-lang/rust/issues/87335
-	let redis_conn: Option<redis::Connection> = rq::get_redis_connection(app_config, false);
-	if redis_conn.is_none() {
-		debug!("In lieu of a Redis Connection, returning an empty vector.");
-		return Vec::new().into();  // If cannot connect to Redis, do not panic the thread.  Instead, return an empty Vector.
+/// The final guard before enqueuing: is this Task Schedule's queue permitted by 'include_queues' and 'exclude_queues'?
+pub fn is_queue_allowed_for_task_schedule(app_config: &config::AppConfig, task_schedule_id: &str, queue_name: &str) -> bool {
+	if app_config.is_queue_allowed(queue_name) {
+		return true;
 	}
+	debug!("Skipping Task Schedule {}; queue '{}' is filtered out by 'include_queues' or 'exclude_queues'.", task_schedule_id, queue_name);
+	false
+}
 
-	let mut redis_conn: redis::Connection = redis_conn.unwrap();
-	let redis_result: Vec<(String, String)> = redis_conn.zscan(RQ_KEY_SCHEDULED_TASKS).unwrap().collect();  // vector of tuple
-	let number_results = redis_result.len();
-	let wrapped_result: VecRQScheduledTask = redis_result.into();
-	if number_results != wrapped_result.len() {
-		println!("Unexpected Error: Number values in Redis: {}.  Number values in VecRQScheduledTask: {}", number_results, wrapped_result.len());
+/// Every Next Execution Time in RQ_KEY_SCHEDULED_TASKS.  Malformed members are skipped, and counted.
+pub fn rq_get_scheduled_tasks(app_config: &config::AppConfig) -> Result<VecRQScheduledTask, RQError> {
+
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(RQError::NoConnection);
+	};
+	let wrapped_result: VecRQScheduledTask = redis_conn.zscan::<_, (String, String)>(RQ_KEY_SCHEDULED_TASKS)
+		.map_err(|source| RQError::ReadFailed { key: RQ_KEY_SCHEDULED_TASKS.to_owned(), source })?
+		.collect();  // converted as they arrive; no intermediate vector
+	if wrapped_result.skipped() > 0 {
+		warn!("Skipped {} malformed members of '{}'.  The remaining {} were read successfully.",
+		      wrapped_result.skipped(), RQ_KEY_SCHEDULED_TASKS, wrapped_result.len());
 	}
-	wrapped_result		
+	Ok(wrapped_result)
 }
 
 /**
 	Remove a Task Schedule from the Redis database, to prevent it from executing in the future.
 */	
-pub fn rq_cancel_scheduled_task(app_config: &config::AppConfig, task_schedule_id: &str) -> Result<String,String> {
-	
+/// What cancelling a Task Schedule removed from Redis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CancelOutcome {
+	pub removed_runs: usize,       // TSIKs
+	pub removed_auxiliary: usize,  // everything else the scheduler kept for it (see redis_keys::schedule_keys)
+}
+
+impl fmt::Display for CancelOutcome {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.removed_runs == 0 {
+			write!(f, "Scheduled Task not found in Redis Queue; removed {} auxiliary keys.", self.removed_auxiliary)
+		} else {
+			write!(f, "Scheduled Task successfully removed from Redis Queue; removed {} upcoming runs and {} auxiliary keys.",
+			       self.removed_runs, self.removed_auxiliary)
+		}
+	}
+}
+
+/// Remove a Task Schedule's upcoming runs (its TSIKs) from Redis, and return how many there were.
+pub fn remove_scheduled_runs(redis_conn: &mut redis::Connection, task_schedule_id: &TaskScheduleId) -> Result<usize, RedisError> {
 	// As of changes made May 21st 2022, the members in the Ordered Set 'btu_scheduler:task_execution_times'
 	// are not just Task Schedule ID's.  The Unix Time is a suffix.  Removing members now requires some "starts_with" logic.
+	let prefix: String = redis_keys::tsik_prefix(task_schedule_id.as_str());
+	let all_task_schedules: Vec<String> = redis_conn.zrange(RQ_KEY_SCHEDULED_TASKS, 0, -1)?;
+	let members: Vec<&String> = all_task_schedules.iter().filter(|each_row| each_row.starts_with(&prefix)).collect();
+	if members.is_empty() {
+		return Ok(0);
+	}
+	redis_conn.zrem(RQ_KEY_SCHEDULED_TASKS, members)  // ZREM returns how many members it removed
+}
 
-	// First, list all the keys using 'zrange btu_scheduler:task_execution_times 0 -1'
-	let mut redis_conn = rq::get_redis_connection(app_config, true).expect("Unable to establish a connection to Redis.");	
-	let all_task_schedules: redis::RedisResult<Vec<String>> = redis_conn.zrange(RQ_KEY_SCHEDULED_TASKS, 0, -1);
-	if all_task_schedules.is_err() {
-		return Err(all_task_schedules.err().unwrap().to_string());
+/// Cancel a Task Schedule: remove its upcoming runs, and every auxiliary key the scheduler created for it.
+pub fn rq_cancel_scheduled_task(app_config: &config::AppConfig, task_schedule_id: &TaskScheduleId) -> Result<CancelOutcome, String> {
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, true) else {
+		return Err("Unable to establish a connection to Redis.".to_owned());
+	};
+	let removed_runs: usize = remove_scheduled_runs(&mut redis_conn, task_schedule_id).map_err(|error| error.to_string())?;
+	let removed_auxiliary: usize = redis_keys::delete_schedule_keys(&mut redis_conn, task_schedule_id.as_str()).map_err(|error| error.to_string())?;
+	if removed_runs > 0 {
+		events::record_event(app_config, &mut redis_conn, SchedulerEvent::new(EventKind::Cancelled).schedule(task_schedule_id));
 	}
-	let mut removed: bool = false;
+	Ok(CancelOutcome { removed_runs, removed_auxiliary })
+}
 
-	for each_row in all_task_schedules.unwrap() {
-		if each_row.starts_with(task_schedule_id) {
-			let redis_result: redis::RedisResult<u64> = redis_conn.zrem(RQ_KEY_SCHEDULED_TASKS, each_row);
-			if redis_result.is_err() {
-				return Err(redis_result.err().unwrap().to_string());
-			}
-			if redis_result.unwrap() == 0 {
-				removed = true;
-			}
-			
+/// Scheduler events from the Redis stream 'btu_scheduler:events' (see the 'events' module), oldest first.
+/// Without 'since_id', the most recent 'count' events.  With 'since_id', up to 'count' events that came after that stream ID.
+pub fn read_events(app_config: &config::AppConfig, count: usize, since_id: Option<&str>) -> Result<Vec<StreamedEvent>, std::io::Error> {
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Unable to establish connection to Redis."));
+	};
+	let entries: Vec<redis::streams::StreamId> = match since_id {
+		Some(since_id) => {
+			let options = redis::streams::StreamReadOptions::default().count(count);
+			let reply: Option<redis::streams::StreamReadReply> = redis_conn.xread_options(&[events::EVENT_STREAM_KEY], &[since_id], &options)
+				.map_err(stream_read_error)?;
+			reply.map(|reply| reply.keys.into_iter().flat_map(|key| key.ids).collect()).unwrap_or_default()
+		},
+		None => {
+			let reply: redis::streams::StreamRangeReply = redis_conn.xrevrange_count(events::EVENT_STREAM_KEY, "+", "-", count)
+				.map_err(stream_read_error)?;
+			reply.ids.into_iter().rev().collect()
 		}
-		// info!("{}", each_row);
-	}
-	if removed {
-		return Ok("Scheduled Task successfully removed from Redis Queue.".to_owned());			
-	} else {
-		return Ok("Scheduled Task not found in Redis Queue.".to_owned());				
-	}
+	};
+	Ok(entries.iter().filter_map(StreamedEvent::from_stream_id).collect())
+}
+
+/// Like read_events() with 'since_id', but waits up to 'block' for new events to arrive.  "$" means "only events after now".
+pub fn wait_for_events(app_config: &config::AppConfig, count: usize, since_id: &str, block: std::time::Duration) -> Result<Vec<StreamedEvent>, std::io::Error> {
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Unable to establish connection to Redis."));
+	};
+	let block_ms: usize = usize::try_from(block.as_millis()).unwrap_or(usize::MAX).max(1);  // BLOCK 0 would wait forever
+	let options = redis::streams::StreamReadOptions::default().count(count).block(block_ms);
+	let reply: Option<redis::streams::StreamReadReply> = redis_conn.xread_options(&[events::EVENT_STREAM_KEY], &[since_id], &options)
+		.map_err(stream_read_error)?;
+	Ok(reply.map(|reply| reply.keys.iter().flat_map(|key| key.ids.iter().filter_map(StreamedEvent::from_stream_id)).collect())
+		.unwrap_or_default())
+}
+
+fn stream_read_error(error: RedisError) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::Other, format!("Unable to read Redis stream '{}': {}", events::EVENT_STREAM_KEY, error))
 }
 
 /**
-	Prints upcoming Task Schedules using the configured Time Zone.
+  Prints upcoming Task Schedules using the configured Time Zone (or UTC, when that is not valid), and how far away each is.
 */
-pub fn rq_print_scheduled_tasks(app_config: &config::AppConfig, to_stdout: bool) {
+pub fn rq_print_scheduled_tasks(app_config: &config::AppConfig, to_stdout: bool) -> Result<(), RQError> {
 
-	let tasks: VecRQScheduledTask = rq_get_scheduled_tasks(app_config);  // fetch all the scheduled tasks.
-	let local_time_zone: chrono_tz::Tz = app_config.tz().unwrap();  // get the time zone from the Application Configuration.
+	let tasks: VecRQScheduledTask = rq_get_scheduled_tasks(app_config)?;  // fetch all the scheduled tasks.
+	let local_time_zone: chrono_tz::Tz = app_config.tz_or_utc();
 
 	println!("There are {} BTU Tasks scheduled for automatic execution:", tasks.len());
-	for result in tasks.sort_by_id().iter() {
+	if tasks.skipped() > 0 {
+		println!("({} malformed entries in Redis were skipped.)", tasks.skipped());
+	}
+	for result in &tasks.sort_by_id() {
 		let next_datetime_local = result.next_datetime_utc.with_timezone(&local_time_zone);
-		let message: &str = &format!("Task Schedule {schedule} is scheduled to occur later at {time}", schedule=result.task_schedule_id, time=next_datetime_local);
+		let delta: String = timeutil::compact_instant(result.next_datetime_utc, Utc::now());
+		let message: &str = &format!("Task Schedule {schedule} is scheduled to occur later at {time} ({delta})",
+		                             schedule=result.task_schedule_id, time=next_datetime_local, delta=delta);
 		if to_stdout {
 			println!("    {}", message);
 		}
@@ -537,6 +1670,390 @@ pub fn rq_print_scheduled_tasks(app_config: &config::AppConfig, to_stdout: bool)
 			info!(message);	
 		}
 	};
+	let unschedulable: Vec<UnschedulableMarker> = read_unschedulable_markers(app_config);
+	if !unschedulable.is_empty() {
+		println!("There are {} enabled BTU Task Schedules that cannot be scheduled:", unschedulable.len());
+	}
+	for marker in &unschedulable {
+		let message: &str = &format!("[UNSCHEDULABLE] Task Schedule {} with cron '{}' ({}) since {}: {}",
+			marker.task_schedule_id, marker.cron_string, btu_cron::describe_schedule(&marker.cron_string, btu_cron::cron_timezone_label(&marker.cron_timezone, app_config.cron_strings_are_utc), app_config.cron_dom_dow_semantics),
+			marker.detected_at, marker.reason);
+		if to_stdout {
+			println!("    {}", message);
+		}
+		else {
+			warn!(message);
+		}
+	}
+	Ok(())
+}
+
+/*
+	Exporting and importing BTU's own Redis state.
+
+	Before risky Redis maintenance, an administrator can snapshot every 'btu_scheduler:*' key to a JSON file,
+	and restore it afterwards.  The RQ Jobs themselves are not included; the daemon recreates those on its next full refresh.
+*/
+
+/// The contents of a single BTU-owned Redis key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum SchedulerKeyValue {
+	SortedSet(Vec<(String, i64)>),  // (member, score), ordered by score
+	Hash(BTreeMap<String, String>),
+	List(Vec<String>),
+	String(String),
+	Set(BTreeSet<String>),
+	Stream(Vec<(String, BTreeMap<String, String>)>),  // (entry ID, fields), oldest first
+}
+
+impl SchedulerKeyValue {
+	/// True for a sorted set, hash, list, set, or stream without any items.
+	pub fn is_empty(&self) -> bool {
+		match self {
+			SchedulerKeyValue::SortedSet(members) => members.is_empty(),
+			SchedulerKeyValue::Hash(fields) => fields.is_empty(),
+			SchedulerKeyValue::List(items) => items.is_empty(),
+			SchedulerKeyValue::String(_) => false,
+			SchedulerKeyValue::Set(members) => members.is_empty(),
+			SchedulerKeyValue::Stream(entries) => entries.is_empty(),
+		}
+	}
+}
+
+/// A snapshot of all BTU-owned Redis keys, as written by 'btu export-state'.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SchedulerState {
+	pub btu_version: String,
+	pub exported_at: String,  // RFC 3339, UTC
+	pub keys: BTreeMap<String, SchedulerKeyValue>,
+	/// The time to live (PTTL, in milliseconds) of each key that had one when exported.  Import applies it again.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub ttls_ms: BTreeMap<String, i64>,
+}
+
+/// How many times import_state() merges, when BTU keys change underneath it, before giving up.
+const MAX_IMPORT_ATTEMPTS: u32 = 10;
+
+/// What import_state() did, and what it refused to do.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportReport {
+	pub keys_written: Vec<String>,
+	pub skipped: Vec<String>,  // one human-readable reason per skipped key or member
+}
+
+impl std::fmt::Display for ImportReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "Keys written: {}", self.keys_written.len())?;
+		for key in &self.keys_written {
+			writeln!(f, "    {}", key)?;
+		}
+		writeln!(f, "Skipped: {}", self.skipped.len())?;
+		for reason in &self.skipped {
+			writeln!(f, "    {}", reason)?;
+		}
+		Ok(())
+	}
+}
+
+/// Read every BTU-owned key from Redis.
+pub fn export_state(app_config: &config::AppConfig) -> Result<SchedulerState, std::io::Error> {
+
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Unable to establish connection to Redis."));
+	};
+	read_scheduler_state(&mut redis_conn)
+		.map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, format!("Error while reading BTU keys from Redis: {}", error)))
+}
+
+/// How many jobs BTU enqueued on each RQ queue, over the last 'days' UTC days (including today), and when it last did.
+pub fn get_queue_stats(app_config: &config::AppConfig, days: u32) -> Result<queue_stats::QueueStatsReport, std::io::Error> {
+
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Unable to establish connection to Redis."));
+	};
+	queue_stats::read_report(&mut redis_conn, days, Utc::now())
+		.map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, format!("Error while reading queue statistics from Redis: {}", error)))
+}
+
+/// The recent runs of a Task Schedule, newest first.  On a multi-site bench, 'task_schedule_id' is qualified by its site.
+pub fn get_task_run_history(app_config: &config::AppConfig, task_schedule_id: &str) -> Result<Vec<RunRecord>, std::io::Error> {
+
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Unable to establish connection to Redis."));
+	};
+	run_history::read_history(&mut redis_conn, task_schedule_id)
+		.map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, format!("Error while reading the run history from Redis: {}", error)))
+}
+
+/// Every entry of a Redis stream, with its fields as strings.
+fn read_stream_entries(redis_conn: &mut redis::Connection, key: &str) -> Result<Vec<(String, BTreeMap<String, String>)>, RedisError> {
+	let reply: redis::streams::StreamRangeReply = redis_conn.xrange_all(key)?;
+	reply.ids.into_iter().map(|entry| {
+		let fields = entry.map.iter()
+			.map(|(field, field_value)| Ok((field.clone(), redis::from_redis_value::<String>(field_value)?)))
+			.collect::<Result<BTreeMap<String, String>, RedisError>>()?;
+		Ok((entry.id, fields))
+	}).collect()
+}
+
+fn read_scheduler_state(redis_conn: &mut redis::Connection) -> Result<SchedulerState, RedisError> {
+
+	let mut key_names: Vec<String> = redis_conn.scan_match::<_, String>(format!("{}*", BTU_KEY_PREFIX))?.collect();
+	key_names.sort();
+	key_names.dedup();  // SCAN may return the same key more than once.
+
+	let mut keys: BTreeMap<String, SchedulerKeyValue> = BTreeMap::new();
+	let mut ttls_ms: BTreeMap<String, i64> = BTreeMap::new();
+	for key in key_names {
+		let key_type: String = redis::cmd("TYPE").arg(&key).query(redis_conn)?;
+		let value: SchedulerKeyValue = match key_type.as_str() {
+			"zset" => SchedulerKeyValue::SortedSet(redis_conn.zrange_withscores(&key, 0, -1)?),
+			"hash" => SchedulerKeyValue::Hash(redis_conn.hgetall(&key)?),
+			"list" => SchedulerKeyValue::List(redis_conn.lrange(&key, 0, -1)?),
+			"string" => SchedulerKeyValue::String(redis_conn.get(&key)?),
+			"set" => SchedulerKeyValue::Set(redis_conn.smembers(&key)?),
+			"stream" => SchedulerKeyValue::Stream(read_stream_entries(redis_conn, &key)?),
+			"none" => continue,  // expired between SCAN and TYPE
+			other => {
+				warn!("Not exporting Redis key '{}'; type '{}' is not supported.", key, other);
+				continue;
+			}
+		};
+		// -1 means the key never expires; -2, that it expired since it was read.
+		let ttl_ms: i64 = redis::cmd("PTTL").arg(&key).query(redis_conn)?;
+		if ttl_ms > 0 {
+			ttls_ms.insert(key.clone(), ttl_ms);
+		}
+		keys.insert(key, value);
+	}
+
+	Ok(SchedulerState {
+		btu_version: crate::get_package_version().to_owned(),
+		exported_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+		keys,
+		ttls_ms,
+	})
+}
+
+/**
+  Write a snapshot back into Redis.
+
+  * overwrite = true:  BTU's Redis state becomes exactly the (validated) snapshot.  BTU keys missing from the snapshot are deleted.
+  * overwrite = false: merge.  Nothing already in Redis is changed; only missing keys, members, and fields are added.
+
+  Every key written whole (all keys when overwriting; only missing ones when merging) gets back the time to live it had when
+  exported, so keys that expire (run history, failure counts, concurrency slots, ...) still do.
+
+  Keys outside the 'btu_scheduler:' namespace, malformed Task Schedule Instance Keys, and members whose Task Schedule
+  no longer exists in MariaDB are skipped, and listed in the returned report.
+
+  The keys are WATCHed, so a running daemon cannot change them between the read and the write.  If they keep changing,
+  the import gives up, with an error.
+*/
+#[cfg(feature = "sql")]
+pub fn import_state(app_config: &config::AppConfig, state: &SchedulerState, overwrite: bool) -> Result<ImportReport, std::io::Error> {
+
+	let known_task_schedules: HashSet<String> = crate::task_schedule::read_task_schedule_ids(app_config)
+		.map_err(|error| std::io::Error::new(std::io::ErrorKind::NotConnected, format!("Unable to read Task Schedules from MariaDB: {}", error)))?
+		.into_iter()
+		.collect();
+
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Unable to establish connection to Redis."));
+	};
+	let redis_error = |error: RedisError| std::io::Error::new(std::io::ErrorKind::Other, format!("Error while importing BTU keys into Redis: {}", error));
+
+	let (validated, validation_report) = validate_scheduler_state(state, &known_task_schedules);
+
+	// The daemon may write while the import runs.  Every key read (or written) is WATCHed before it is read for the merge;
+	// if any changes before the MULTI/EXEC, the transaction is discarded, and the merge is repeated.
+	let mut watched: HashSet<String> = HashSet::new();
+	let mut attempt: u32 = 0;
+	let report: ImportReport = loop {
+		attempt += 1;
+		if attempt > MAX_IMPORT_ATTEMPTS {
+			return Err(std::io::Error::new(std::io::ErrorKind::Other,
+			           "BTU keys kept changing during the import.  Stop the BTU daemon, and import again."));
+		}
+		let current: SchedulerState = read_scheduler_state(&mut redis_conn).map_err(redis_error)?;
+		let unwatched: Vec<&String> = current.keys.keys().chain(validated.keys.keys()).filter(|key| !watched.contains(*key)).collect();
+		if !unwatched.is_empty() {
+			// Read before being watched; watch, and read again.
+			redis::cmd("WATCH").arg(&unwatched).query::<()>(&mut redis_conn).map_err(redis_error)?;
+			watched.extend(unwatched.into_iter().cloned());
+			continue;
+		}
+		let mut report: ImportReport = validation_report.clone();
+		let additions: BTreeMap<String, SchedulerKeyValue> = merge_scheduler_keys(&current.keys, &validated.keys, overwrite, &mut report);
+		// A single MULTI/EXEC, so workers never observe a half-restored state.  EXEC answers nil if a watched key changed.
+		let executed: Option<()> = import_pipeline(&current.keys, &additions, &validated.ttls_ms, overwrite)
+			.query(&mut redis_conn).map_err(redis_error)?;
+		if executed.is_some() {
+			break report;
+		}
+		debug!("BTU keys changed during the import; merging again.");
+		watched.clear();  // EXEC unwatches every key
+	};
+
+	info!("Imported BTU scheduler state exported at {}: {} keys written, {} items skipped.",
+	      state.exported_at, report.keys_written.len(), report.skipped.len());
+	Ok(report)
+}
+
+/**
+  The MULTI/EXEC that writes merge_scheduler_keys()'s 'additions'.  When overwriting, every 'current' BTU key is deleted first.
+  When merging, nothing is deleted: additions to existing keys only add members and fields.  A key that did not exist
+  before gets its exported time to live from 'ttls_ms'; an existing key keeps its own.
+*/
+pub fn import_pipeline(current: &BTreeMap<String, SchedulerKeyValue>,
+                       additions: &BTreeMap<String, SchedulerKeyValue>,
+                       ttls_ms: &BTreeMap<String, i64>,
+                       overwrite: bool) -> redis::Pipeline {
+	let mut pipe = redis::pipe();
+	pipe.atomic();
+	if overwrite {
+		for key in current.keys().chain(additions.keys()) {
+			pipe.del(key).ignore();
+		}
+	}
+	for (key, value) in additions {
+		match value {
+			SchedulerKeyValue::SortedSet(members) => {
+				for (member, score) in members {
+					pipe.zadd(key, member, *score).ignore();
+				}
+			},
+			SchedulerKeyValue::Hash(fields) => {
+				for (field, field_value) in fields {
+					pipe.hset(key, field, field_value).ignore();
+				}
+			},
+			SchedulerKeyValue::List(items) => {
+				for item in items {
+					pipe.rpush(key, item).ignore();
+				}
+			},
+			SchedulerKeyValue::String(string_value) => {
+				pipe.set(key, string_value).ignore();
+			},
+			SchedulerKeyValue::Set(members) => {
+				for member in members {
+					pipe.sadd(key, member).ignore();
+				}
+			},
+			SchedulerKeyValue::Stream(entries) => {
+				// The original entry IDs are kept, so readers following the stream (btu events --follow) are not confused.
+				for (entry_id, fields) in entries {
+					pipe.cmd("XADD").arg(key).arg(entry_id).arg(fields).ignore();
+				}
+			}
+		}
+		let whole_key: bool = overwrite || !current.contains_key(key);
+		if let Some(ttl_ms) = ttls_ms.get(key).filter(|_| whole_key) {
+			pipe.cmd("PEXPIRE").arg(key).arg(*ttl_ms).ignore();
+		}
+	}
+	pipe
+}
+
+/// Remove anything from a snapshot that must not be imported.  Returns the remaining state, and a report of what was removed.
+pub fn validate_scheduler_state(state: &SchedulerState, known_task_schedules: &HashSet<String>) -> (SchedulerState, ImportReport) {
+
+	let mut report = ImportReport::default();
+	let mut keys: BTreeMap<String, SchedulerKeyValue> = BTreeMap::new();
+
+	for (key, value) in &state.keys {
+		if !key.starts_with(BTU_KEY_PREFIX) {
+			report.skipped.push(format!("Key '{}' is outside the '{}' namespace.", key, BTU_KEY_PREFIX));
+			continue;
+		}
+		if key != RQ_KEY_SCHEDULED_TASKS {
+			keys.insert(key.clone(), value.clone());
+			continue;
+		}
+		let SchedulerKeyValue::SortedSet(members) = value else {
+			report.skipped.push(format!("Key '{}' must be a sorted set.", key));
+			continue;
+		};
+		let mut valid_members: Vec<(String, i64)> = Vec::new();
+		for (member, score) in members {
+			let tsik = TSIK::from(member.as_str());
+			if !tsik.is_well_formed() {
+				report.skipped.push(format!("Member '{}' of '{}' is not a valid Task Schedule Instance Key.", member, key));
+			}
+			else if !known_task_schedules.contains(tsik.task_schedule_id().as_str()) {
+				report.skipped.push(format!("Member '{}' of '{}' refers to a Task Schedule that no longer exists.", member, key));
+			}
+			else {
+				valid_members.push((member.clone(), *score));
+			}
+		}
+		if !valid_members.is_empty() {
+			keys.insert(key.clone(), SchedulerKeyValue::SortedSet(valid_members));
+		}
+	}
+
+	let ttls_ms: BTreeMap<String, i64> = state.ttls_ms.iter()
+		.filter(|(key, ttl_ms)| keys.contains_key(*key) && **ttl_ms > 0)
+		.map(|(key, ttl_ms)| (key.clone(), *ttl_ms))
+		.collect();
+	let validated = SchedulerState {
+		btu_version: state.btu_version.clone(),
+		exported_at: state.exported_at.clone(),
+		keys,
+		ttls_ms,
+	};
+	(validated, report)
+}
+
+/**
+  What to write into Redis, given its 'current' BTU keys and the 'incoming' snapshot.  When overwriting, the whole snapshot.
+  When merging, only what is missing: new keys whole, and the members and fields absent from existing sorted sets, sets, and hashes.
+*/
+pub fn merge_scheduler_keys(current: &BTreeMap<String, SchedulerKeyValue>,
+                            incoming: &BTreeMap<String, SchedulerKeyValue>,
+                            overwrite: bool,
+                            report: &mut ImportReport) -> BTreeMap<String, SchedulerKeyValue> {
+	if overwrite {
+		report.keys_written.extend(incoming.keys().cloned());
+		return incoming.clone();
+	}
+
+	let mut additions: BTreeMap<String, SchedulerKeyValue> = BTreeMap::new();
+	for (key, incoming_value) in incoming {
+		let Some(current_value) = current.get(key) else {
+			additions.insert(key.clone(), incoming_value.clone());
+			report.keys_written.push(key.clone());
+			continue;
+		};
+		let missing: SchedulerKeyValue = match (current_value, incoming_value) {
+			(SchedulerKeyValue::SortedSet(current_members), SchedulerKeyValue::SortedSet(incoming_members)) => {
+				SchedulerKeyValue::SortedSet(incoming_members.iter()
+					.filter(|(member, _)| !current_members.iter().any(|(existing, _)| existing == member))
+					.cloned()
+					.collect())
+			},
+			(SchedulerKeyValue::Hash(current_fields), SchedulerKeyValue::Hash(incoming_fields)) => {
+				SchedulerKeyValue::Hash(incoming_fields.iter()
+					.filter(|(field, _)| !current_fields.contains_key(*field))
+					.map(|(field, field_value)| (field.clone(), field_value.clone()))
+					.collect())
+			},
+			(SchedulerKeyValue::Set(current_members), SchedulerKeyValue::Set(incoming_members)) => {
+				SchedulerKeyValue::Set(incoming_members.difference(current_members).cloned().collect())
+			},
+			_ => {
+				report.skipped.push(format!("Key '{}' already exists in Redis; use overwrite to replace it.", key));
+				continue;
+			}
+		};
+		if !missing.is_empty() {
+			additions.insert(key.clone(), missing);
+			report.keys_written.push(key.clone());
+		}
+	}
+	additions
 }
 
 /*
@@ -0,0 +1,96 @@
+// socket_frame.rs
+
+/*
+	Framing of messages on the daemon's Unix Domain Socket, in both directions.
+
+	Each message is a 4-byte, big-endian length, followed by that many bytes of UTF-8 (JSON, or a text reply).  The reader
+	loops until the whole frame has arrived, so a client may send it in any number of writes.  A frame longer than
+	the reader's limit (MAX_FRAME_BYTES, for requests) is refused before any of its payload is read.
+
+	Clients older than protocol version 4 send bare JSON, with no length.  Such a request starts with '{', which as a
+	length would be over 2 GB; it is read until it forms one complete JSON value, and is answered without a length.
+*/
+
+use std::io::{Read, Write};
+
+use crate::errors::FrameError;
+
+/// The bytes of a frame's length prefix.
+pub const FRAME_HEADER_BYTES: usize = 4;
+/// The longest request the daemon accepts.
+pub const MAX_FRAME_BYTES: usize = 64 * 1024;
+/// The longest reply a client should accept.  Replies listing every scheduled run can be far longer than any request.
+pub const MAX_REPLY_BYTES: usize = 16 * 1024 * 1024;
+
+/// A request read from a socket client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceivedMessage {
+	pub payload: Vec<u8>,
+	pub framed: bool,  // false for a bare JSON request, from a client older than protocol version 4; answer it the same way
+}
+
+/// The payload, preceded by its length.
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+	let mut frame: Vec<u8> = Vec::with_capacity(FRAME_HEADER_BYTES + payload.len());
+	frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+	frame.extend_from_slice(payload);
+	frame
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> std::io::Result<()> {
+	writer.write_all(&encode_frame(payload))?;
+	writer.flush()
+}
+
+/// Read one length-prefixed frame, however many reads it takes.
+pub fn read_frame<R: Read>(reader: &mut R, max_bytes: usize) -> Result<Vec<u8>, FrameError> {
+	let mut header = [0_u8; FRAME_HEADER_BYTES];
+	reader.read_exact(&mut header)?;
+	read_payload(reader, header, max_bytes)
+}
+
+/// Read one request: a frame, or (from an older client) bare JSON.
+pub fn read_message<R: Read>(reader: &mut R, max_bytes: usize) -> Result<ReceivedMessage, FrameError> {
+	let mut header = [0_u8; FRAME_HEADER_BYTES];
+	reader.read_exact(&mut header)?;
+	if header[0] == b'{' {
+		let payload: Vec<u8> = read_bare_json(reader, header.to_vec(), max_bytes)?;
+		return Ok(ReceivedMessage { payload, framed: false });
+	}
+	let payload: Vec<u8> = read_payload(reader, header, max_bytes)?;
+	Ok(ReceivedMessage { payload, framed: true })
+}
+
+fn read_payload<R: Read>(reader: &mut R, header: [u8; FRAME_HEADER_BYTES], max_bytes: usize) -> Result<Vec<u8>, FrameError> {
+	let length: usize = u32::from_be_bytes(header) as usize;
+	if length > max_bytes {
+		return Err(FrameError::TooLarge { length, max_bytes });
+	}
+	let mut payload: Vec<u8> = vec![0; length];
+	reader.read_exact(&mut payload)?;
+	Ok(payload)
+}
+
+/// Keep reading until the bytes form one complete JSON value.  At EOF, whatever arrived is returned, for the caller to reject.
+fn read_bare_json<R: Read>(reader: &mut R, mut buffer: Vec<u8>, max_bytes: usize) -> Result<Vec<u8>, FrameError> {
+	let mut chunk = [0_u8; 1024];
+	loop {
+		let mut values = serde_json::Deserializer::from_slice(&buffer).into_iter::<serde::de::IgnoredAny>();
+		match values.next() {
+			Some(Ok(_)) => {
+				buffer.truncate(values.byte_offset());
+				return Ok(buffer);
+			},
+			Some(Err(error)) if !error.is_eof() => return Ok(buffer),  // malformed; more bytes cannot help
+			_ => {}
+		}
+		if buffer.len() > max_bytes {
+			return Err(FrameError::TooLarge { length: buffer.len(), max_bytes });
+		}
+		let bytes_read: usize = reader.read(&mut chunk)?;
+		if bytes_read == 0 {
+			return Ok(buffer);
+		}
+		buffer.extend_from_slice(&chunk[..bytes_read]);
+	}
+}
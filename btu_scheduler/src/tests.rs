@@ -10,12 +10,82 @@ mod tests {
 	
 	use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 	use crate::btu_cron::cron_str_to_cron_str7;
-	use crate::btu_cron::{tz_cron_to_utc_datetimes};
+	use crate::btu_cron::{tz_cron_to_utc_datetimes, DomDowSemantics};
 	use crate::config::AppConfig;
 	use crate::scheduler::RQScheduledTask;
 
 	use tracing::info;
 	use tracing::field;
+
+	// A minimal configuration; nothing here connects to a real database.
+	static SAMPLE_TOML: &str = r#"
+		environment_name = "Unit Test"
+		full_refresh_internal_secs = 900
+		scheduler_polling_interval = 60
+		time_zone_string = "America/Los_Angeles"
+		tracing_level = "INFO"
+		startup_without_database_connections = true
+		email_when_queuing = false
+		mysql_user = "root"
+		mysql_password = "password"
+		mysql_host = "127.0.0.1"
+		mysql_port = 3306
+		mysql_database = "test_db"
+		rq_host = "127.0.0.1"
+		rq_port = 11000
+		socket_path = "/tmp/btu_scheduler_test.sock"
+		socket_file_group_owner = "frappe"
+		webserver_ip = "127.0.0.1"
+		webserver_port = 8000
+		webserver_token = "token abc:def"
+	"#;
+
+	fn sample_app_config() -> AppConfig {
+		AppConfig::new_from_toml_string(SAMPLE_TOML).unwrap()
+	}
+
+	// Captured configuration files from older BTU releases.  Version 1 predates 'environment_name', 'tracing_level',
+	// and 'startup_without_database_connections'; it also carries the README's 'name' key, which BTU never read.
+	static CONFIG_V1_TOML: &str = r#"
+		name = "BTU Scheduler Daemon"
+		full_refresh_internal_secs = 900
+		scheduler_polling_interval = 60
+		time_zone_string = "America/Los_Angeles"
+		mysql_user = "root"
+		mysql_password = "password"
+		mysql_host = "localhost"
+		mysql_port = 3306
+		mysql_database = "erpnext_db"
+		rq_host = "127.0.0.1"
+		rq_port = 11000
+		socket_path = "/tmp/btu_scheduler.sock"
+		socket_file_group_owner = "erpnext_group"
+		webserver_ip = "127.0.0.1"
+		webserver_port = 8000
+		webserver_token = "token abc:def"
+	"#;
+
+	// Version 2: before the email settings.
+	static CONFIG_V2_TOML: &str = r#"
+		environment_name = "DEV"
+		full_refresh_internal_secs = 900
+		scheduler_polling_interval = 60
+		time_zone_string = "America/Los_Angeles"
+		tracing_level = "DEBUG"
+		startup_without_database_connections = true
+		mysql_user = "root"
+		mysql_password = "password"
+		mysql_host = "localhost"
+		mysql_database = "erpnext_db"
+		rq_host = "127.0.0.1"
+		rq_port = 11000
+		socket_path = "/tmp/btu_scheduler.sock"
+		socket_file_group_owner = "erpnext_group"
+		webserver_ip = "127.0.0.1"
+		webserver_port = 8000
+		webserver_host_header = "erp.example.com"
+		webserver_token = "token abc:def"
+	"#;
 	
     #[test]
     fn test_cron7_fail() {
@@ -76,7 +146,7 @@ mod tests {
 		let vec_utc_calculated = tz_cron_to_utc_datetimes("0 */10 1 25 12 * 2021", 
 		                                                  local_timezone,
 														  Some(starting_at_utc_datetime),
-														  &number_of_results).unwrap();
+														  &number_of_results, DomDowSemantics::And).unwrap();
 
 		// There is an 8-hour difference between Los Angeles and UTC in December.
 		// Therefore, with the cron string above, the expected results begin at 9AM UTC.
@@ -101,7 +171,7 @@ mod tests {
 		let vec_utc_calculated = tz_cron_to_utc_datetimes("*/30 * * * *", 
 		                                                  local_timezone,
 														  Some(starting_at_utc_datetime),
-														  &number_of_results).unwrap();
+														  &number_of_results, DomDowSemantics::And).unwrap();
 
 		// There is an 8-hour difference between Los Angeles and UTC in December.
 		// Therefore, with the cron string above, the expected results begin at 9AM UTC.
@@ -127,7 +197,7 @@ mod tests {
 
 		// Create a new struct: RQScheduledTask
 		let expected = RQScheduledTask {
-			task_schedule_id: job_id.clone(),
+			task_schedule_id: job_id.as_str().into(),
 			next_datetime_unix: unix_timestamp,
 			next_datetime_utc: datetime_utc,
 		};
@@ -181,9 +251,3839 @@ mod tests {
 		let timezone_pacific = chrono_tz::America::Los_Angeles;
 		let starting_at_utc_datetime: DateTime<Utc> = Utc.with_ymd_and_hms(2021, 12, 25, 0, 0, 1).unwrap();
 
-		let _this_result = tz_cron_to_utc_datetimes(expression_string, timezone_pacific, Some(starting_at_utc_datetime), &12);
+		let _this_result = tz_cron_to_utc_datetimes(expression_string, timezone_pacific, Some(starting_at_utc_datetime), &12, DomDowSemantics::And);
 	}
   	
+	/// Frappe booleans arrive as integers, strings, or NULL.  All should convert without panicking.
+	#[test]
+	fn test_frappe_bool_conversions() {
+		use crate::FrappeBool;
+		use crate::errors::FrappeBoolError;
+
+		for (text, expected) in [("1", true), ("0", false), ("true", true), ("false", false), ("TRUE", true), (" 1 ", true)] {
+			assert_eq!(text.parse::<FrappeBool>(), Ok(FrappeBool(expected)), "string '{}'", text);
+		}
+		assert_eq!("yes".parse::<FrappeBool>(), Err(FrappeBoolError::InvalidValue { found: "yes".to_owned() }));
+	}
+
+	#[cfg(feature = "sql")]
+	#[test]
+	fn test_frappe_bool_sql_conversions() {
+		use crate::FrappeBool;
+		use crate::errors::FrappeBoolError;
+
+		assert_eq!(FrappeBool::try_from(&mysql::Value::Int(1)), Ok(FrappeBool(true)));
+		assert_eq!(FrappeBool::try_from(&mysql::Value::Int(0)), Ok(FrappeBool(false)));
+		assert_eq!(FrappeBool::try_from(&mysql::Value::UInt(1)), Ok(FrappeBool(true)));
+		assert_eq!(FrappeBool::try_from(&mysql::Value::Bytes(b"1".to_vec())), Ok(FrappeBool(true)));
+		assert_eq!(FrappeBool::try_from(&mysql::Value::Bytes(b"false".to_vec())), Ok(FrappeBool(false)));
+		assert!(FrappeBool::try_from(&mysql::Value::Int(2)).is_err());
+		assert_eq!(FrappeBool::try_from(&mysql::Value::NULL), Err(FrappeBoolError::Null));
+
+		// A NULL or unrecognized column means "disabled", with a warning instead of a panic.
+		assert!(!FrappeBool::from_sql_or_false(Some(&mysql::Value::NULL), "enabled"));
+		assert!(!FrappeBool::from_sql_or_false(Some(&mysql::Value::Bytes(b"maybe".to_vec())), "enabled"));
+		assert!(!FrappeBool::from_sql_or_false(None, "enabled"));
+		assert!(FrappeBool::from_sql_or_false(Some(&mysql::Value::Int(1)), "enabled"));
+	}
+
+	#[test]
+	fn test_queue_pattern_matching() {
+		use crate::config::{queue_pattern_matches, queue_pattern_to_like};
+
+		assert!(queue_pattern_matches("stg_*", "stg_default"));
+		assert!(queue_pattern_matches("stg_*", "stg_"));
+		assert!(!queue_pattern_matches("stg_*", "default"));
+		assert!(queue_pattern_matches("default", "default"));
+		assert!(!queue_pattern_matches("default", "default_long"));
+		assert!(queue_pattern_matches("*", "anything"));
+
+		// Underscores and percent signs are wildcards in SQL LIKE, so they must be escaped.
+		assert_eq!(queue_pattern_to_like("stg_*"), "stg\\_%");
+		assert_eq!(queue_pattern_to_like("default"), "default");
+		assert_eq!(queue_pattern_to_like("100%"), "100\\%");
+	}
+
+	#[test]
+	fn test_task_list_queries() {
+		use crate::task::{task_list_queries, TaskListOptions};
+
+		let select: &str = "SELECT name, IFNULL(desc_short, '') FROM `tabBTU Task` WHERE docstatus = 1 AND is_transient = 0";
+		let count: &str = "SELECT COUNT(*) FROM `tabBTU Task` WHERE docstatus = 1 AND is_transient = 0";
+		let filtered: &str = " AND (name LIKE ? OR desc_short LIKE ?)";
+		let queries = |limit: Option<usize>, offset: usize, filter: Option<&str>| {
+			task_list_queries(&TaskListOptions { limit, offset, filter: filter.map(str::to_owned) })
+		};
+
+		// Every page is ordered by name, so consecutive pages neither repeat nor skip Tasks.
+		assert_eq!(queries(None, 0, None), (format!("{} ORDER BY name", select), count.to_owned(), vec![]));
+		assert_eq!(queries(Some(50), 0, None).0, format!("{} ORDER BY name LIMIT 50", select));
+		assert_eq!(queries(Some(50), 100, None).0, format!("{} ORDER BY name LIMIT 50 OFFSET 100", select));
+		assert_eq!(queries(None, 100, None).0, format!("{} ORDER BY name LIMIT 18446744073709551615 OFFSET 100", select));
+
+		// The filter is a parameter, matched anywhere in the name or short description.  LIKE wildcards in it are literal.
+		let (page_query, count_query, parameters) = queries(Some(50), 100, Some("50%_off"));
+		assert_eq!(page_query, format!("{}{} ORDER BY name LIMIT 50 OFFSET 100", select, filtered));
+		assert_eq!(count_query, format!("{}{}", count, filtered));
+		assert_eq!(parameters, vec!["%50\\%\\_off%", "%50\\%\\_off%"]);
+		assert!(!page_query.contains("50%_off"));
+		assert_eq!(queries(None, 0, Some("")), queries(None, 0, None));  // an empty filter matches everything
+	}
+
+	#[test]
+	fn test_queue_filters_guard() {
+		use crate::scheduler::is_queue_allowed_for_task_schedule;
+
+		let mut app_config = sample_app_config();
+		assert!(is_queue_allowed_for_task_schedule(&app_config, "TS-1", "default"));
+		assert_eq!(app_config.queue_filter_sql(), (String::new(), Vec::new()));
+
+		app_config.include_queues = Some(vec!["stg_*".to_owned(), "reports".to_owned()]);
+		app_config.exclude_queues = Some(vec!["stg_slow".to_owned()]);
+		assert!(is_queue_allowed_for_task_schedule(&app_config, "TS-1", "stg_default"));
+		assert!(is_queue_allowed_for_task_schedule(&app_config, "TS-1", "reports"));
+		assert!(!is_queue_allowed_for_task_schedule(&app_config, "TS-1", "default"));
+		assert!(!is_queue_allowed_for_task_schedule(&app_config, "TS-1", "stg_slow"));
+
+		let (sql_fragment, parameters) = app_config.queue_filter_sql();
+		assert_eq!(sql_fragment, " AND (queue_name LIKE ? OR queue_name LIKE ?) AND NOT (queue_name LIKE ?)");
+		assert_eq!(parameters, vec!["stg\\_%", "reports", "stg\\_slow"]);
+	}
+
+	#[test]
+	fn test_function_prefix_policy() {
+		use crate::errors::PolicyError;
+		use crate::task::check_function_policy;
+
+		let mut app_config = sample_app_config();
+		assert!(app_config.is_function_allowed("anything.at.all"));  // absent list allows everything
+		app_config.allowed_function_prefixes = Some(Vec::new());
+		assert!(app_config.is_function_allowed("anything.at.all"));  // as does an empty one
+
+		app_config.allowed_function_prefixes = Some(vec!["btu.".to_owned(), "erpnext.accounts.".to_owned()]);
+		assert!(app_config.is_function_allowed("btu.manual_tests.ping_now"));
+		assert!(app_config.is_function_allowed("erpnext.accounts.utils.reconcile"));
+		assert!(!app_config.is_function_allowed("erpnext.stock.utils.repost"));
+		assert!(!app_config.is_function_allowed("btu_other.ping"));
+
+		assert_eq!(check_function_policy(&app_config, "TASK-1", "btu.manual_tests.ping_now"), Ok(()));
+		assert_eq!(check_function_policy(&app_config, "TASK-2", "frappe.utils.evil"),
+			Err(PolicyError::FunctionNotAllowed { task_key: "TASK-2".to_owned(), path_to_function: "frappe.utils.evil".to_owned() }));
+	}
+
+	/// A sorted-set member whose embedded Unix Time disagrees with its score is corrected to match the score.
+	#[test]
+	fn test_tsik_score_mismatch_prefers_score() {
+		use crate::scheduler::rq_scheduled_task_from_member;
+
+		// Consistent (or within 1 second): nothing to repair.
+		let (task, is_mismatched) = rq_scheduled_task_from_member("TS-000001|1638424800", 1638424800);
+		assert!(!is_mismatched);
+		assert_eq!(task.next_datetime_unix, 1638424800);
+		let (_, is_mismatched) = rq_scheduled_task_from_member("TS-000001|1638424800", 1638424801);
+		assert!(!is_mismatched);
+
+		// Embedded time is in the past, but the score is in the future: trust the score.
+		let (task, is_mismatched) = rq_scheduled_task_from_member("TS-000001|1638424800", 1638428400);
+		assert!(is_mismatched);
+		assert_eq!(task.task_schedule_id, "TS-000001");
+		assert_eq!(task.next_datetime_unix, 1638428400);
+		assert_eq!(task.next_datetime_utc.timestamp(), 1638428400);
+		assert_eq!(task.to_tsik(), "TS-000001|1638428400");
+	}
+
+	#[test]
+	fn test_job_data_compression_round_trip() {
+		use crate::rq::{compress_job_data, decompress_job_data, compress_job_data_if_enabled, COMPRESSION_THRESHOLD_BYTES};
+
+		// A large, repetitive payload; similar to a pickled function with its arguments.
+		let payload: Vec<u8> = b"cbtu.btu_api.endpoints\ntest_function_ping_now\n".repeat(500);
+		let compressed = compress_job_data(&payload).unwrap();
+		assert!(compressed.len() < payload.len());
+		assert_eq!(decompress_job_data(&compressed).unwrap(), payload);
+
+		// With the flag enabled, large payloads are compressed...
+		let compressed = compress_job_data_if_enabled(&payload, true).unwrap();
+		assert_eq!(decompress_job_data(&compressed).unwrap(), payload);
+		// ...but never when the flag is disabled.
+		assert!(compress_job_data_if_enabled(&payload, false).is_none());
+
+		// Payloads under the threshold are always stored as-is.
+		let small_payload: Vec<u8> = vec![7u8; COMPRESSION_THRESHOLD_BYTES - 1];
+		assert!(compress_job_data_if_enabled(&small_payload, true).is_none());
+		let threshold_payload: Vec<u8> = vec![7u8; COMPRESSION_THRESHOLD_BYTES];
+		assert!(compress_job_data_if_enabled(&threshold_payload, true).is_some());
+
+		// Garbage is an error, not a panic.
+		assert!(decompress_job_data(b"not zlib data").is_err());
+	}
+
+	fn sample_scheduler_state(keys: Vec<(&str, crate::scheduler::SchedulerKeyValue)>) -> crate::scheduler::SchedulerState {
+		crate::scheduler::SchedulerState {
+			btu_version: "0.3.8".to_owned(),
+			exported_at: "2022-12-02T06:00:00Z".to_owned(),
+			keys: keys.into_iter().map(|(key, value)| (key.to_owned(), value)).collect(),
+			ttls_ms: Default::default(),
+		}
+	}
+
+	/// Import never accepts foreign keys, malformed TSIKs, or members for deleted Task Schedules.
+	#[test]
+	fn test_scheduler_state_validation() {
+		use std::collections::HashSet;
+		use crate::scheduler::{validate_scheduler_state, SchedulerKeyValue};
+
+		let state = sample_scheduler_state(vec![
+			("btu_scheduler:task_execution_times", SchedulerKeyValue::SortedSet(vec![
+				("TS-000001|1638424800".to_owned(), 1638424800),
+				("TS-000002".to_owned(), 1638424800),
+				("TS-000003|soon".to_owned(), 1638424800),
+				("TS-DELETED|1638424800".to_owned(), 1638424800),
+			])),
+			("rq:job:abc", SchedulerKeyValue::String("pickled".to_owned())),
+			("btu_scheduler:last_run", SchedulerKeyValue::Hash([("TS-000001".to_owned(), "1638424800".to_owned())].into())),
+		]);
+		let known: HashSet<String> = ["TS-000001", "TS-000002", "TS-000003"].iter().map(|s| s.to_string()).collect();
+
+		let (validated, report) = validate_scheduler_state(&state, &known);
+		assert_eq!(report.skipped.len(), 4);
+		assert!(!validated.keys.contains_key("rq:job:abc"));
+		assert!(validated.keys.contains_key("btu_scheduler:last_run"));
+		assert_eq!(validated.keys["btu_scheduler:task_execution_times"],
+		           SchedulerKeyValue::SortedSet(vec![("TS-000001|1638424800".to_owned(), 1638424800)]));
+	}
+
+	#[test]
+	fn test_scheduler_state_overwrite_versus_merge() {
+		use crate::scheduler::{merge_scheduler_keys, ImportReport, SchedulerKeyValue};
+
+		let current = sample_scheduler_state(vec![
+			("btu_scheduler:task_execution_times", SchedulerKeyValue::SortedSet(vec![("TS-000002|1638428400".to_owned(), 1638428400)])),
+			("btu_scheduler:last_run", SchedulerKeyValue::Hash([("TS-000001".to_owned(), "100".to_owned())].into())),
+			("btu_scheduler:history", SchedulerKeyValue::List(vec!["current".to_owned()])),
+			("btu_scheduler:circuit_open", SchedulerKeyValue::Set(["TS-000001".to_owned()].into())),
+		]);
+		let incoming = sample_scheduler_state(vec![
+			("btu_scheduler:task_execution_times", SchedulerKeyValue::SortedSet(vec![("TS-000001|1638424800".to_owned(), 1638424800)])),
+			("btu_scheduler:last_run", SchedulerKeyValue::Hash([
+				("TS-000001".to_owned(), "200".to_owned()), ("TS-000002".to_owned(), "300".to_owned())
+			].into())),
+			("btu_scheduler:history", SchedulerKeyValue::List(vec!["incoming".to_owned()])),
+			("btu_scheduler:circuit_open", SchedulerKeyValue::Set(["TS-000001".to_owned(), "TS-000002".to_owned()].into())),
+		]);
+
+		// Overwrite: the result is exactly the snapshot.
+		let mut report = ImportReport::default();
+		let result = merge_scheduler_keys(&current.keys, &incoming.keys, true, &mut report);
+		assert_eq!(result, incoming.keys);
+		assert_eq!(report.keys_written.len(), 4);
+
+		// Merge: additions only; existing members, fields, and lists are untouched.
+		let mut report = ImportReport::default();
+		let result = merge_scheduler_keys(&current.keys, &incoming.keys, false, &mut report);
+		assert_eq!(result["btu_scheduler:task_execution_times"],
+		           SchedulerKeyValue::SortedSet(vec![("TS-000001|1638424800".to_owned(), 1638424800)]));
+		assert_eq!(result["btu_scheduler:last_run"], SchedulerKeyValue::Hash([("TS-000002".to_owned(), "300".to_owned())].into()));
+		assert!(!result.contains_key("btu_scheduler:history"));
+		assert_eq!(result["btu_scheduler:circuit_open"], SchedulerKeyValue::Set(["TS-000002".to_owned()].into()));
+		assert_eq!(report.skipped.len(), 1);
+		assert_eq!(report.keys_written.len(), 3);
+
+		// Merging the same snapshot again adds nothing.
+		let mut report = ImportReport::default();
+		assert!(merge_scheduler_keys(&incoming.keys, &incoming.keys, false, &mut report).is_empty());
+		assert!(report.keys_written.is_empty());
+	}
+
+	/// Merging never deletes a key, and only keys written whole get their exported time to live.
+	#[test]
+	fn test_scheduler_state_import_pipeline() {
+		use std::collections::BTreeMap;
+		use crate::scheduler::{import_pipeline, SchedulerKeyValue};
+
+		let current: BTreeMap<String, SchedulerKeyValue> =
+			[("btu_scheduler:last_run".to_owned(), SchedulerKeyValue::Hash([("TS-000001".to_owned(), "100".to_owned())].into()))].into();
+		let additions: BTreeMap<String, SchedulerKeyValue> = [
+			("btu_scheduler:last_run".to_owned(), SchedulerKeyValue::Hash([("TS-000002".to_owned(), "300".to_owned())].into())),
+			("btu_scheduler:failure_count:TS-000001".to_owned(), SchedulerKeyValue::String("2".to_owned())),
+		].into();
+		let ttls_ms: BTreeMap<String, i64> = [
+			("btu_scheduler:last_run".to_owned(), 5000),
+			("btu_scheduler:failure_count:TS-000001".to_owned(), 60000),
+		].into();
+
+		let merge_commands = String::from_utf8(import_pipeline(&current, &additions, &ttls_ms, false).get_packed_pipeline()).unwrap();
+		assert!(!merge_commands.contains("DEL\r\n"));
+		assert!(!merge_commands.contains("\r\n5000\r\n"));
+		assert!(merge_commands.contains("PEXPIRE\r\n$37\r\nbtu_scheduler:failure_count:TS-000001\r\n$5\r\n60000"));
+
+		let overwrite_commands = String::from_utf8(import_pipeline(&current, &additions, &ttls_ms, true).get_packed_pipeline()).unwrap();
+		assert!(overwrite_commands.contains("DEL\r\n"));
+		assert!(overwrite_commands.contains("\r\n5000\r\n"));
+	}
+
+	#[test]
+	fn test_scheduler_state_json_round_trip() {
+		use crate::scheduler::{SchedulerKeyValue, SchedulerState};
+
+		let state = sample_scheduler_state(vec![
+			("btu_scheduler:task_execution_times", SchedulerKeyValue::SortedSet(vec![("TS-000001|1638424800".to_owned(), 1638424800)])),
+			("btu_scheduler:history", SchedulerKeyValue::List(vec!["a".to_owned(), "b".to_owned()])),
+			("btu_scheduler:circuit_open", SchedulerKeyValue::Set(["TS-000001".to_owned()].into())),
+			("btu_scheduler:events", SchedulerKeyValue::Stream(vec![
+				("1669960800000-0".to_owned(), [("event".to_owned(), r#"{"event":"scheduled"}"#.to_owned())].into())
+			])),
+		]);
+		let as_json: String = serde_json::to_string(&state).unwrap();
+		assert!(as_json.contains(r#""type":"sorted_set""#));
+		assert!(as_json.contains(r#""type":"set""#) && as_json.contains(r#""type":"stream""#));
+		let from_json: SchedulerState = serde_json::from_str(&as_json).unwrap();
+		assert_eq!(from_json, state);
+	}
+
+	/// Values and time-to-live survive 'export-state' then 'import-state', whether overwriting or merging.
+	#[cfg(all(feature = "integration", feature = "sql"))]
+	#[test]
+	fn test_scheduler_state_export_import_live() {
+		use std::collections::{BTreeMap, BTreeSet};
+		use redis::Commands;
+		use crate::scheduler::{export_state, import_state, SchedulerKeyValue};
+
+		let app_config: AppConfig = AppConfig::new_from_env().expect("BTU_* environment variables describing a live installation");
+		let mut redis_conn = crate::rq::get_redis_connection(&app_config, false).unwrap();
+		let prefix: String = format!("btu_scheduler:export_test:{}", uuid::Uuid::new_v4());
+		let (string_key, hash_key, zset_key) = (format!("{}:count", prefix), format!("{}:hash", prefix), format!("{}:zset", prefix));
+		let set_key: String = format!("{}:set", prefix);
+		let pttl = |redis_conn: &mut redis::Connection, key: &str| -> i64 { redis::cmd("PTTL").arg(key).query(redis_conn).unwrap() };
+		let hash = |redis_conn: &mut redis::Connection| -> BTreeMap<String, String> { redis_conn.hgetall(&hash_key).unwrap() };
+
+		let _: () = redis::cmd("SET").arg(&string_key).arg("2").arg("PX").arg(600_000).query(&mut redis_conn).unwrap();
+		let _: () = redis_conn.hset_multiple(&hash_key, &[("f1", "v1"), ("f2", "v2")]).unwrap();
+		let _: () = redis::cmd("PEXPIRE").arg(&hash_key).arg(600_000).query(&mut redis_conn).unwrap();
+		let _: () = redis_conn.zadd_multiple(&zset_key, &[(1, "m1"), (2, "m2")]).unwrap();
+		let _: () = redis_conn.sadd(&set_key, &["TS-000001", "TS-000002"]).unwrap();
+
+		let state = export_state(&app_config).unwrap();
+		assert_eq!(state.keys[&string_key], SchedulerKeyValue::String("2".to_owned()));
+		assert_eq!(state.keys[&zset_key], SchedulerKeyValue::SortedSet(vec![("m1".to_owned(), 1), ("m2".to_owned(), 2)]));
+		assert!((1..=600_000).contains(&state.ttls_ms[&string_key]));
+		assert!((1..=600_000).contains(&state.ttls_ms[&hash_key]));
+		assert_eq!(state.keys[&set_key], SchedulerKeyValue::Set(["TS-000001".to_owned(), "TS-000002".to_owned()].into()));
+		assert!(!state.ttls_ms.contains_key(&zset_key));
+
+		// Overwrite: the keys come back exactly, and the expiring ones still expire.
+		let _: () = redis_conn.del(&[&string_key, &hash_key, &zset_key, &set_key]).unwrap();
+		import_state(&app_config, &state, true).unwrap();
+		let reexported = export_state(&app_config).unwrap();
+		for key in [&string_key, &hash_key, &zset_key, &set_key] {
+			assert_eq!(reexported.keys[key], state.keys[key]);
+		}
+		assert!((1..=600_000).contains(&pttl(&mut redis_conn, &string_key)));
+		assert!((1..=600_000).contains(&pttl(&mut redis_conn, &hash_key)));
+		assert_eq!(pttl(&mut redis_conn, &zset_key), -1);
+
+		// Merge: a missing key comes back with its TTL; an existing key keeps its values and its own TTL, gaining only missing fields.
+		let _: () = redis_conn.del(&string_key).unwrap();
+		let _: () = redis_conn.hdel(&hash_key, "f2").unwrap();
+		let _: () = redis_conn.srem(&set_key, "TS-000002").unwrap();
+		let _: () = redis_conn.hset(&hash_key, "f1", "changed").unwrap();
+		let _: () = redis::cmd("PEXPIRE").arg(&hash_key).arg(900_000).query(&mut redis_conn).unwrap();
+		let report = import_state(&app_config, &state, false).unwrap();
+		assert!(report.keys_written.contains(&string_key) && report.keys_written.contains(&hash_key));
+		assert!(!report.keys_written.contains(&zset_key));
+		assert_eq!(redis_conn.get::<_, String>(&string_key).unwrap(), "2");
+		assert!((1..=600_000).contains(&pttl(&mut redis_conn, &string_key)));
+		assert_eq!(hash(&mut redis_conn), [("f1".to_owned(), "changed".to_owned()), ("f2".to_owned(), "v2".to_owned())].into());
+		assert!(pttl(&mut redis_conn, &hash_key) > 600_000);
+		assert_eq!(redis_conn.smembers::<_, BTreeSet<String>>(&set_key).unwrap(), ["TS-000001".to_owned(), "TS-000002".to_owned()].into());
+
+		let _: () = redis_conn.del(&[&string_key, &hash_key, &zset_key, &set_key]).unwrap();
+	}
+
+	/// Holidays are judged by the date in the schedule's time zone, including around month and UTC-midnight boundaries.
+	#[test]
+	fn test_first_non_holiday() {
+		use std::collections::HashSet;
+		use chrono::TimeZone;
+		use crate::btu_cron::first_non_holiday;
+
+		let pacific = chrono_tz::America::Los_Angeles;
+		// Daily at 23:30 Pacific, beginning Nov 29th 2022.  In UTC, each of these is already the following day.
+		let candidates: Vec<DateTime<Utc>> = (29..=33).map(|offset| {
+			Utc.with_ymd_and_hms(2022, 11, 1, 7, 30, 0).unwrap() + chrono::Duration::days(offset)
+		}).collect();
+		assert_eq!(candidates[0].with_timezone(&pacific).date_naive(), NaiveDate::from_ymd_opt(2022, 11, 29).unwrap());
+
+		let date = |month: u32, day: u32| NaiveDate::from_ymd_opt(2022, month, day).unwrap();
+
+		// No holidays: the first candidate.
+		assert_eq!(first_non_holiday(&candidates, pacific, &HashSet::new()), Some(candidates[0]));
+
+		// Nov 29th and 30th are holidays; Dec 1st (Pacific) is not.
+		let holidays: HashSet<NaiveDate> = [date(11, 29), date(11, 30)].into();
+		assert_eq!(first_non_holiday(&candidates, pacific, &holidays), Some(candidates[2]));
+
+		// A holiday on the UTC date (Nov 30th) of the first candidate does not affect its Pacific date (Nov 29th).
+		let holidays: HashSet<NaiveDate> = [date(12, 1)].into();
+		assert_eq!(candidates[1].with_timezone(&pacific).date_naive(), date(11, 30));
+		assert_eq!(first_non_holiday(&candidates, pacific, &holidays), Some(candidates[0]));
+
+		// Every candidate is a holiday: give up.
+		let holidays: HashSet<NaiveDate> = [date(11, 29), date(11, 30), date(12, 1), date(12, 2), date(12, 3)].into();
+		assert_eq!(first_non_holiday(&candidates, pacific, &holidays), None);
+	}
+
+	#[test]
+	fn test_configured_holiday_dates() {
+		let mut app_config = sample_app_config();
+		assert!(app_config.configured_holiday_dates().is_empty());
+
+		app_config.holiday_dates = Some(vec!["2022-12-25".to_owned(), "Christmas".to_owned(), " 2023-01-01 ".to_owned()]);
+		let holidays = app_config.configured_holiday_dates();
+		assert_eq!(holidays.len(), 2);
+		assert!(holidays.contains(&NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()));
+	}
+
+	fn synthetic_job_hash(description: &str, status: Option<&str>, created_at: DateTime<Utc>) -> std::collections::HashMap<String, Vec<u8>> {
+		let mut job_hash = std::collections::HashMap::new();
+		job_hash.insert("description".to_owned(), description.as_bytes().to_vec());
+		job_hash.insert("created_at".to_owned(), crate::rq::utc_to_rq_string(created_at).into_bytes());
+		if let Some(status) = status {
+			job_hash.insert("status".to_owned(), status.as_bytes().to_vec());
+		}
+		job_hash
+	}
+
+	#[test]
+	fn test_stale_btu_job_classification() {
+		use std::collections::HashSet;
+		use chrono::{Duration, TimeZone};
+		use crate::janitor::is_stale_btu_job;
+
+		let now: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 10, 12, 0, 0).unwrap();
+		let old: DateTime<Utc> = now - Duration::hours(72);
+		let recent: DateTime<Utc> = now - Duration::hours(2);
+		let max_age = Duration::hours(48);
+		let descriptions: HashSet<String> = ["Nightly Invoice Sync".to_owned()].into();
+
+		// Old BTU jobs that no worker started are stale.
+		assert!(is_stale_btu_job(&synthetic_job_hash("Nightly Invoice Sync", None, old), &descriptions, now, max_age));
+		assert!(is_stale_btu_job(&synthetic_job_hash("Nightly Invoice Sync", Some(""), old), &descriptions, now, max_age));
+		assert!(is_stale_btu_job(&synthetic_job_hash("Nightly Invoice Sync", Some("queued"), old), &descriptions, now, max_age));
+
+		// Recent jobs, jobs a worker touched, and jobs belonging to other applications are not.
+		assert!(!is_stale_btu_job(&synthetic_job_hash("Nightly Invoice Sync", None, recent), &descriptions, now, max_age));
+		assert!(!is_stale_btu_job(&synthetic_job_hash("Nightly Invoice Sync", Some("finished"), old), &descriptions, now, max_age));
+		assert!(!is_stale_btu_job(&synthetic_job_hash("Nightly Invoice Sync", Some("failed"), old), &descriptions, now, max_age));
+		assert!(!is_stale_btu_job(&synthetic_job_hash("frappe.utils.background_jobs.execute_job", None, old), &descriptions, now, max_age));
+
+		// Without a parseable creation time, a job is never considered stale.
+		let mut job_hash = synthetic_job_hash("Nightly Invoice Sync", None, old);
+		job_hash.insert("created_at".to_owned(), b"yesterday".to_vec());
+		assert!(!is_stale_btu_job(&job_hash, &descriptions, now, max_age));
+	}
+
+	/// An RQ Job hash as a worker leaves it while running: status 'started', with 'started_at' and 'timeout'.
+	fn started_job_hash(started_at: DateTime<Utc>, timeout: &str) -> std::collections::HashMap<String, Vec<u8>> {
+		std::collections::HashMap::from([
+			("status".to_owned(), b"started".to_vec()),
+			("origin".to_owned(), b"default".to_vec()),
+			("started_at".to_owned(), crate::rq::utc_to_rq_string(started_at).into_bytes()),
+			("timeout".to_owned(), timeout.as_bytes().to_vec()),
+		])
+	}
+
+	#[test]
+	fn test_job_watchdog_classification() {
+		use std::collections::HashMap;
+		use chrono::{Duration, TimeZone};
+		use crate::watchdog::{classify_watched_job, failure_fields, job_is_started, started_registry_members_for_job, WatchVerdict, WatchedJob};
+
+		let now: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 1, 13, 12, 0, 0).unwrap();
+		let watched = WatchedJob::new(&"job-1".into(), &"TS-0001".into(), "default", 600, now - Duration::hours(3));
+		let grace: u32 = 300;
+		let started_ago = |minutes: i64, timeout: &str| classify_watched_job(&started_job_hash(now - Duration::minutes(minutes), timeout), &watched, now, grace);
+
+		// A 10 minute timeout, plus 5 minutes of grace.
+		assert_eq!(started_ago(1, "600"), WatchVerdict::Running);
+		assert_eq!(started_ago(15, "600"), WatchVerdict::Running);
+		assert_eq!(started_ago(16, "600"), WatchVerdict::Overran { elapsed_secs: 960, limit_secs: 900 });
+		assert_eq!(started_ago(180, "600.0"), WatchVerdict::Overran { elapsed_secs: 10800, limit_secs: 900 });  // some RQ versions write a float
+		assert_eq!(started_ago(180, "-1"), WatchVerdict::Unlimited);
+		assert_eq!(started_ago(16, "3600"), WatchVerdict::Running);  // the job's own timeout wins over the one recorded at enqueue
+
+		// Without a readable timeout, the one recorded when BTU enqueued the job applies.
+		let mut job_hash = started_job_hash(now - Duration::minutes(16), "");
+		assert_eq!(classify_watched_job(&job_hash, &watched, now, grace), WatchVerdict::Overran { elapsed_secs: 960, limit_secs: 900 });
+
+		// Older RQ versions may leave 'status' empty; a start time without an end time still counts as started.
+		job_hash.remove("status");
+		assert!(matches!(classify_watched_job(&job_hash, &watched, now, grace), WatchVerdict::Overran { .. }));
+		assert!(job_is_started(&job_hash));
+		job_hash.insert("ended_at".to_owned(), b"2023-01-13T11:59:00.000Z".to_vec());
+		assert_eq!(classify_watched_job(&job_hash, &watched, now, grace), WatchVerdict::Forget);
+		assert!(!job_is_started(&job_hash));
+
+		// Jobs not started yet are waiting; finished, failed, and deleted jobs are no longer watched.
+		for (status, verdict) in [("queued", WatchVerdict::Waiting), ("deferred", WatchVerdict::Waiting), ("finished", WatchVerdict::Forget),
+		                          ("failed", WatchVerdict::Forget), ("stopped", WatchVerdict::Forget)] {
+			let mut job_hash = started_job_hash(now - Duration::hours(2), "600");
+			job_hash.insert("status".to_owned(), status.as_bytes().to_vec());
+			assert_eq!(classify_watched_job(&job_hash, &watched, now, grace), verdict, "status '{}'", status);
+		}
+		assert_eq!(classify_watched_job(&HashMap::new(), &watched, now, grace), WatchVerdict::Forget);
+
+		// StartedJobRegistry members: the Job ID before RQ 2.0, and "<job_id>:<execution_id>" since.
+		let members: Vec<String> = ["job-1", "job-1:4f1c", "job-10", "job-10:9a2b", "other"].iter().map(|member| member.to_string()).collect();
+		assert_eq!(started_registry_members_for_job(&members, "job-1"), vec!["job-1", "job-1:4f1c"]);
+
+		let fields = failure_fields(now, 960, 900);
+		assert_eq!((fields[0].0, fields[0].1.as_str()), ("status", "failed"));
+		assert_eq!((fields[1].0, fields[1].1.as_str()), ("ended_at", "2023-01-13T12:00:00.000Z"));
+		assert!(fields[2].1.starts_with("BTU Scheduler timed out this job"));
+
+		// The watched entry round-trips through JSON; the overrun time is only written once it is reported.
+		let json: String = serde_json::to_string(&watched).unwrap();
+		assert!(!json.contains("overran_at"));
+		assert_eq!(serde_json::from_str::<WatchedJob>(&json).unwrap(), watched);
+	}
+
+	/// Writes synthetic started jobs, then fails them the way the watchdog does.  Needs a live Redis, configured by BTU_*
+	/// environment variables: `cargo test -p btu_scheduler --features integration -- test_fail_overrunning_job_live`
+	#[cfg(feature = "integration")]
+	#[test]
+	fn test_fail_overrunning_job_live() {
+		use std::collections::HashMap;
+		use chrono::Duration;
+		use redis::Commands;
+		use crate::ids::RqJobId;
+		use crate::watchdog::{fail_overrunning_job, failed_registry_key, started_registry_key, FailOutcome};
+
+		let app_config: AppConfig = AppConfig::new_from_env().expect("BTU_* environment variables describing a live installation");
+		let mut redis_conn = crate::rq::get_redis_connection(&app_config, false).unwrap();
+		let queue_name: String = format!("btu_watchdog_test_{}", uuid::Uuid::new_v4());
+		let (started_key, failed_key) = (started_registry_key(&queue_name), failed_registry_key(&queue_name));
+		let now: DateTime<Utc> = Utc::now();
+		let write_job = |redis_conn: &mut redis::Connection, job_id: &RqJobId, status: &str| {
+			let mut job_hash: HashMap<String, Vec<u8>> = started_job_hash(now - Duration::hours(2), "600");
+			job_hash.insert("status".to_owned(), status.as_bytes().to_vec());
+			job_hash.insert("origin".to_owned(), queue_name.as_bytes().to_vec());
+			let fields: Vec<(String, Vec<u8>)> = job_hash.into_iter().collect();
+			let _: () = redis_conn.hset_multiple(crate::rq::job_key(job_id.as_str()), &fields).unwrap();
+			let _: () = redis_conn.zadd(&started_key, format!("{}:exec-1", job_id), now.timestamp() + 60).unwrap();
+		};
+
+		// A job that is still running is failed: status, failed registry, and no longer in the started registry.
+		let running: RqJobId = RqJobId::from(uuid::Uuid::new_v4().to_string());
+		write_job(&mut redis_conn, &running, "started");
+		let outcome = fail_overrunning_job(&mut redis_conn, &running, &queue_name, now, 7200, 900).unwrap();
+		assert_eq!(outcome, FailOutcome::Failed);
+		let status: String = redis_conn.hget(crate::rq::job_key(running.as_str()), "status").unwrap();
+		assert_eq!(status, "failed");
+		assert!(redis_conn.zscore::<_, _, Option<i64>>(&failed_key, running.as_str()).unwrap().is_some());
+		assert!(redis_conn.zscore::<_, _, Option<i64>>(&started_key, format!("{}:exec-1", running)).unwrap().is_none());
+
+		// A job that finished after the watchdog's check is left alone.
+		let finished: RqJobId = RqJobId::from(uuid::Uuid::new_v4().to_string());
+		write_job(&mut redis_conn, &finished, "finished");
+		let outcome = fail_overrunning_job(&mut redis_conn, &finished, &queue_name, now, 7200, 900).unwrap();
+		assert_eq!(outcome, FailOutcome::NotStarted);
+		let status: String = redis_conn.hget(crate::rq::job_key(finished.as_str()), "status").unwrap();
+		assert_eq!(status, "finished");
+		assert!(redis_conn.zscore::<_, _, Option<i64>>(&failed_key, finished.as_str()).unwrap().is_none());
+
+		let _: Result<(), _> = redis_conn.del(&[crate::rq::job_key(running.as_str()), crate::rq::job_key(finished.as_str()), started_key, failed_key]);
+	}
+
+	#[test]
+	fn test_primary_job_keys() {
+		use crate::rq::is_primary_job_key;
+
+		assert!(is_primary_job_key("rq:job:11f83e81-83ea-4df2-aa7e-cd12d8dec779"));
+		assert!(!is_primary_job_key("rq:job:11f83e81-83ea-4df2-aa7e-cd12d8dec779:dependents"));
+		assert!(!is_primary_job_key("rq:job:"));
+		assert!(!is_primary_job_key("rq:jobs:abc"));
+		assert!(!is_primary_job_key("rq:queue:default"));
+	}
+
+	#[test]
+	fn test_rq_database_sanity_classification() {
+		use crate::rq::{classify_rq_keys, SanityVerdict};
+
+		let keys = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<String>>();
+
+		assert_eq!(classify_rq_keys(&keys(&[])).verdict, SanityVerdict::EmptyDatabase);
+		assert_eq!(classify_rq_keys(&keys(&["rq:queues"])).verdict, SanityVerdict::LooksLikeRq);
+		assert_eq!(classify_rq_keys(&keys(&["rq:worker:host.1234"])).verdict, SanityVerdict::LooksLikeRq);
+
+		// Frappe's cache Redis holds keys like these, and nothing belonging to Python RQ.
+		let report = classify_rq_keys(&keys(&["_1bd3e0294da19198|bootinfo"]));
+		assert_eq!(report.verdict, SanityVerdict::Suspicious);
+		assert!(report.found.is_empty());
+		let warning = report.warning_message(&sample_app_config()).unwrap();
+		assert!(warning.contains("rq:queues") && warning.contains("11000"));
+
+		assert!(classify_rq_keys(&keys(&["rq:queues"])).warning_message(&sample_app_config()).is_none());
+		assert!(classify_rq_keys(&keys(&[])).warning_message(&sample_app_config()).is_none());
+	}
+
+	#[test]
+	fn test_schedule_queue_priorities_and_dedup() {
+		use crate::schedule_queue::{QueueEntry, QueueSource, ScheduleQueue};
+
+		let refresh = |id: &str| QueueEntry::new(id.into(), QueueSource::FullRefresh);
+		let socket = |id: &str| QueueEntry::new(id.into(), QueueSource::Socket);
+
+		let mut queue = ScheduleQueue::new();
+		// Interleaved pushes from a full refresh (normal) and the socket (high).
+		assert!(queue.push_back(refresh("TS-1")));
+		assert!(queue.push_priority(socket("TS-A")));
+		assert!(queue.push_back(refresh("TS-2")));
+		assert!(queue.push_back(refresh("TS-3")));
+		assert!(queue.push_priority(socket("TS-B")));
+
+		// Duplicates (by schedule_id only) are not queued twice; a high-priority push promotes a pending normal-priority one.
+		assert!(!queue.push_back(refresh("TS-2")));
+		assert!(!queue.push_back(refresh("TS-A")));
+		assert!(queue.push_priority(socket("TS-3")));
+		assert!(!queue.push_priority(QueueEntry::new("TS-3".into(), QueueSource::PostRunRequeue)));
+		assert_eq!(queue.len(), 5);
+
+		let drained: Vec<(String, QueueSource)> = std::iter::from_fn(|| queue.pop_front())
+			.map(|entry| (entry.schedule_id.into_string(), entry.source))
+			.collect();
+		assert_eq!(drained, vec![
+			("TS-A".to_owned(), QueueSource::Socket),
+			("TS-B".to_owned(), QueueSource::Socket),
+			("TS-3".to_owned(), QueueSource::Socket),
+			("TS-1".to_owned(), QueueSource::FullRefresh),
+			("TS-2".to_owned(), QueueSource::FullRefresh),
+		]);
+		assert!(queue.is_empty());
+
+		// Once processed, an ID may be queued again.
+		assert!(queue.push_back(refresh("TS-1")));
+	}
+
+	#[test]
+	fn test_schedule_queue_pending_ids_stay_in_step() {
+		use crate::schedule_queue::{QueueEntry, QueueSource, ScheduleQueue};
+
+		let refresh = |id: &str| QueueEntry::new(id.into(), QueueSource::FullRefresh);
+		let mut queue = ScheduleQueue::new();
+
+		// A full refresh that runs while the previous one is still pending adds nothing, and keeps the original order.
+		let ids: Vec<String> = (1..=500).map(|number| format!("TS-{}", number)).collect();
+		assert_eq!(ids.iter().filter(|id| queue.push_back(refresh(id))).count(), 500);
+		assert_eq!(ids.iter().rev().filter(|id| queue.push_back(refresh(id))).count(), 0);
+		assert_eq!(queue.len(), 500);
+		assert_eq!(queue.pop_front().unwrap().schedule_id, "TS-1");
+		assert!(!queue.contains("TS-1"));
+		assert!(queue.contains("TS-500"));
+
+		// Putting an entry back at the front moves it, instead of copying it.
+		queue.push_front(refresh("TS-250"));
+		assert_eq!(queue.len(), 499);
+		assert_eq!(queue.pop_front().unwrap().schedule_id, "TS-250");
+		assert!(queue.push_back(refresh("TS-250")));
+
+		// After clear(), every ID may be queued again.
+		assert_eq!(queue.clear(), 499);
+		assert!(!queue.contains("TS-2"));
+		assert!(queue.push_priority(QueueEntry::new("TS-2".into(), QueueSource::Socket)));
+		assert!(!queue.push_back(refresh("TS-2")));
+		assert_eq!(queue.len(), 1);
+
+		// clear_except() keeps one source's entries, and they are still pending.
+		assert!(queue.push_back(QueueEntry::new("TS-RAN".into(), QueueSource::PostRunRequeue)));
+		assert_eq!(queue.clear_except(QueueSource::PostRunRequeue), 1);
+		assert!(queue.contains("TS-RAN") && !queue.contains("TS-2"));
+		assert!(!queue.push_back(refresh("TS-RAN")));
+		assert_eq!(queue.len(), 1);
+	}
+
+	#[test]
+	fn test_queue_entry_latency() {
+		use chrono::{Duration, TimeZone};
+		use crate::schedule_queue::{QueueEntry, QueueSource};
+
+		let mut entry = QueueEntry::new("TS-1".into(), QueueSource::PostRunRequeue);
+		entry.enqueued_at = Utc.with_ymd_and_hms(2022, 12, 1, 23, 59, 58).unwrap();
+		let now: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 2, 0, 0, 3).unwrap();
+		assert_eq!(entry.latency(now), Duration::seconds(5));
+		assert_eq!(entry.source.to_string(), "post_run_requeue");
+	}
+
+	#[test]
+	fn test_fingerprint_cache() {
+		use crate::schedule_queue::{FingerprintCache, QueueEntry, QueueSource, ScheduleFingerprint};
+
+		let now: i64 = 1669968000;
+		let next_run: i64 = now + 3600;
+		let refresh = |cron: &str| QueueEntry::new("TS-1".into(), QueueSource::FullRefresh)
+			.with_fingerprint(ScheduleFingerprint::new("2022-12-01 10:00:00.000000", cron));
+		let tsik_in_redis = |tsik: &str| if tsik == "TS-1|1669971600" { Some(1669971600) } else { None };
+
+		let mut cache = FingerprintCache::default();
+		// Never seen before: processed.
+		assert!(!cache.can_skip(&refresh("0 * * * *"), now, tsik_in_redis));
+		cache.remember(&refresh("0 * * * *"), Some(next_run));
+
+		// Unchanged schedule, TSIK still in the future: skipped.
+		assert!(cache.can_skip(&refresh("0 * * * *"), now, tsik_in_redis));
+		// Cron edit: processed.
+		assert!(!cache.can_skip(&refresh("30 * * * *"), now, tsik_in_redis));
+		// TSIK missing from Redis, despite an unchanged fingerprint: processed.
+		assert!(!cache.can_skip(&refresh("0 * * * *"), now, |_| None));
+		// TSIK is due (or past): processed.
+		assert!(!cache.can_skip(&refresh("0 * * * *"), next_run, tsik_in_redis));
+		// A configuration reload, or a new cron time zone: processed.
+		let no_holidays = std::collections::HashSet::new();
+		assert_ne!(ScheduleFingerprint::context_hash(0, "UTC", &no_holidays), ScheduleFingerprint::context_hash(1, "UTC", &no_holidays));
+		assert_ne!(ScheduleFingerprint::context_hash(0, "UTC", &no_holidays), ScheduleFingerprint::context_hash(0, "Europe/Paris", &no_holidays));
+		let reloaded = refresh("0 * * * *");
+		let reloaded = QueueEntry { fingerprint: reloaded.fingerprint.map(|fingerprint| fingerprint.with_context(1)), ..reloaded };
+		assert!(!cache.can_skip(&reloaded, now, tsik_in_redis));
+		assert_eq!(cache.take_counts(), (1, 5));
+		assert_eq!(cache.take_counts(), (0, 0));
+
+		// Socket requests bypass the cache, and invalidate it.
+		let socket = QueueEntry::new("TS-1".into(), QueueSource::Socket);
+		assert!(!cache.can_skip(&socket, now, tsik_in_redis));
+		assert_eq!(cache.take_counts(), (0, 0));
+		cache.remember(&socket, Some(next_run));
+		assert!(!cache.can_skip(&refresh("0 * * * *"), now, tsik_in_redis));
+	}
+
+	#[test]
+	fn test_compress_job_data_defaults_to_false() {
+		assert!(!sample_app_config().compress_job_data);
+	}
+
+
+	#[test]
+	fn test_email_settings_are_all_or_nothing() {
+		use crate::errors::EmailConfigError;
+
+		let mut app_config = sample_app_config();
+		assert_eq!(app_config.validate_email_settings(), Ok(()));
+
+		app_config.email_host_name = Some("smtp.example.com".to_owned());
+		assert_eq!(app_config.validate_email_settings(), Err(EmailConfigError::MissingSettings {
+			missing: vec!["email_address_from", "email_addresses", "email_account_name", "email_account_password"]
+		}));
+
+		app_config.email_address_from = Some("btu@example.com".to_owned());
+		app_config.email_addresses = Some(vec!["admin@example.com".to_owned()]);
+		app_config.email_security = Some("none".to_owned());
+		assert_eq!(app_config.validate_email_settings(), Ok(()));
+
+		app_config.email_security = Some("ssl".to_owned());
+		assert_eq!(app_config.validate_email_settings(), Err(EmailConfigError::InvalidSecurity { found: "ssl".to_owned() }));
+	}
+
+	#[cfg(feature = "email-feat")]
+	#[test]
+	fn test_smtp_settings_from_config() {
+		use crate::config::EmailSecurity;
+		use crate::email::smtp_settings_from_config;
+
+		let mut app_config = sample_app_config();
+		app_config.email_address_from = Some("btu@example.com".to_owned());
+		app_config.email_host_name = Some("smtp.example.com".to_owned());
+		app_config.email_account_name = Some("btu@example.com".to_owned());
+		app_config.email_account_password = Some("secret".to_owned());
+		app_config.email_addresses = Some(vec!["admin@example.com".to_owned()]);
+
+		// The host comes from 'email_host_name', never from the password.
+		let settings = smtp_settings_from_config(&app_config).unwrap();
+		assert_eq!(settings.host, "smtp.example.com");
+		assert_eq!((settings.port, settings.security), (465, EmailSecurity::Tls));
+
+		app_config.email_host_port = Some(587);
+		let settings = smtp_settings_from_config(&app_config).unwrap();
+		assert_eq!((settings.port, settings.security), (587, EmailSecurity::StartTls));
+
+		app_config.email_security = Some("tls".to_owned());
+		let settings = smtp_settings_from_config(&app_config).unwrap();
+		assert_eq!((settings.port, settings.security), (587, EmailSecurity::Tls));
+	}
+
+
+	#[test]
+	fn test_time_until_next_run() {
+		use chrono::{Duration, TimeZone};
+		use crate::errors::SchedulerError;
+		use crate::scheduler::{resolve_time_until_next_run, soonest_run_in_members};
+
+		let now: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 2, 0, 0, 0).unwrap();
+		let members: Vec<(String, i64)> = vec![
+			(format!("TS-1|{}", now.timestamp() + 3600), now.timestamp() + 3600),
+			(format!("TS-1|{}", now.timestamp() + 840), now.timestamp() + 840),
+			(format!("TS-10|{}", now.timestamp() + 60), now.timestamp() + 60),
+		];
+
+		// Redis hit: the soonest TSIK for this schedule wins, and SQL is never consulted.
+		let scheduled = soonest_run_in_members(&members, "TS-1");
+		let result = resolve_time_until_next_run(scheduled, now, || panic!("SQL should not be read when Redis has a TSIK"));
+		assert_eq!(result, Ok(Some(Duration::minutes(14))));
+
+		// No TSIK: fall back to the cron definition.
+		assert_eq!(soonest_run_in_members(&members, "TS-2"), None);
+		let result = resolve_time_until_next_run(None, now, || {
+			let timezone: chrono_tz::Tz = "UTC".parse().unwrap();
+			Ok(tz_cron_to_utc_datetimes("0 6 * * *", timezone, Some(now), &1, DomDowSemantics::And).unwrap().first().copied())
+		});
+		assert_eq!(result, Ok(Some(Duration::hours(6))));
+
+		// Past due: the duration is negative.
+		let result = resolve_time_until_next_run(Some(now - Duration::seconds(90)), now, || Ok(None));
+		assert_eq!(result, Ok(Some(Duration::seconds(-90))));
+
+		// Known but nothing scheduled, versus unknown.
+		assert_eq!(resolve_time_until_next_run(None, now, || Ok(None)), Ok(None));
+		let unknown = SchedulerError::UnknownTaskSchedule { task_schedule_id: "TS-9".to_owned() };
+		assert_eq!(resolve_time_until_next_run(None, now, || Err(unknown.clone())), Err(unknown));
+	}
+
+
+	#[test]
+	fn test_unserved_queue_warning() {
+		use crate::rq::{unserved_queue_warning, worker_serves_queue};
+
+		assert!(worker_serves_queue("default,long", "long"));
+		assert!(!worker_serves_queue("default,long", "lon"));
+		assert!(!worker_serves_queue("", "default"));
+
+		assert_eq!(unserved_queue_warning("short", true, false, false), None);   // already in 'rq:queues'
+		assert_eq!(unserved_queue_warning("short", false, true, false), None);   // a worker listens to it
+		assert_eq!(unserved_queue_warning("shrot", false, false, true), None);   // --create-queue
+		let warning = unserved_queue_warning("shrot", false, false, false).unwrap();
+		assert!(warning.contains("'shrot'") && warning.contains("--create-queue"), "{}", warning);
+
+		// 'btu list-queues' reads queue names from the members of 'rq:queues'.
+		assert_eq!(crate::rq::queue_name_from_key("rq:queue:default"), "default");
+		assert_eq!(crate::rq::queue_name_from_key("unexpected"), "unexpected");
+	}
+
+	#[test]
+	fn test_task_schedule_queue_routing() {
+		use crate::rq::{is_valid_queue_name, keys_written_by_enqueue, queue_name_or_default, RQJob};
+
+		assert!(is_valid_queue_name("long"));
+		assert!(is_valid_queue_name("high-priority_2"));
+		assert!(!is_valid_queue_name(""));
+		assert!(!is_valid_queue_name("long queue"));
+		assert!(!is_valid_queue_name("rq:queue:long"));
+
+		assert_eq!(queue_name_or_default(" long ", "Task Schedule TS-1"), "long");
+		assert_eq!(queue_name_or_default("", "Task Schedule TS-1"), "default");
+		assert_eq!(queue_name_or_default("long*", "Task Schedule TS-1"), "default");
+
+		// A job for a Task Schedule with queue 'long' is pushed onto 'rq:queue:long', not 'rq:queue:default'.
+		let mut rq_job: RQJob = RQJob::new_with_defaults();
+		rq_job.set_origin(queue_name_or_default("long", "Task Schedule TS-1"));
+		let keys: Vec<String> = keys_written_by_enqueue(&rq_job, Utc::now());
+		assert!(keys.contains(&"rq:queue:long".to_owned()), "{:?}", keys);
+		assert!(!keys.contains(&"rq:queue:default".to_owned()), "{:?}", keys);
+	}
+
+
+	#[test]
+	fn test_schedule_jitter() {
+		use chrono::{Duration, TimeZone};
+		use crate::btu_cron::{apply_jitter, jitter_offset_secs};
+
+		// Deterministic, and within [0, jitter].
+		assert_eq!(jitter_offset_secs("TS-000001", 300), jitter_offset_secs("TS-000001", 300));
+		assert!((0..=300).contains(&jitter_offset_secs("TS-000001", 300)));
+		let distinct: std::collections::HashSet<u32> = (1..=40).map(|index| jitter_offset_secs(&format!("TS-{:06}", index), 300)).collect();
+		assert!(distinct.len() > 20, "40 schedules should be spread out, but only {} offsets were distinct", distinct.len());
+
+		// The default of zero jitter produces exact times.
+		let occurrence: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 0).unwrap();
+		assert_eq!(jitter_offset_secs("TS-000001", 0), 0);
+		assert_eq!(apply_jitter(occurrence, None, 0), (occurrence, 0));
+
+		// Capped at half the gap to the following occurrence.
+		let following = occurrence + Duration::minutes(5);
+		assert_eq!(apply_jitter(occurrence, Some(following), 600), (occurrence + Duration::seconds(150), 150));
+		assert_eq!(apply_jitter(occurrence, Some(following), 45), (occurrence + Duration::seconds(45), 45));
+	}
+
+
+	#[test]
+	fn test_task_status_mapping() {
+		use crate::task::TaskStatus;
+
+		assert_eq!(TaskStatus::from_sql(0, false), TaskStatus::Draft);
+		assert_eq!(TaskStatus::from_sql(1, false), TaskStatus::Submitted);
+		assert_eq!(TaskStatus::from_sql(1, true), TaskStatus::Transient);
+		assert_eq!(TaskStatus::from_sql(2, false), TaskStatus::Cancelled);
+		assert_eq!(TaskStatus::from_sql(2, true), TaskStatus::Cancelled);
+		assert_eq!(TaskStatus::from_sql(7, false), TaskStatus::Unknown(7));
+
+		// Only cancelled Tasks are rejected; transient ones are valid for one-off runs, with a warning.
+		assert_eq!(TaskStatus::Cancelled.rejection_reason(), Some("task is cancelled in Frappe"));
+		assert_eq!(TaskStatus::Transient.rejection_reason(), None);
+		assert!(TaskStatus::Transient.warning().is_some());
+		assert_eq!(TaskStatus::Submitted.rejection_reason(), None);
+		assert_eq!(TaskStatus::Submitted.warning(), None);
+	}
+
+
+	#[test]
+	fn test_scheduled_task_conversion_skips_malformed_members() {
+		use crate::scheduler::VecRQScheduledTask;
+
+		let members: Vec<(String, String)> = vec![
+			("TS-1|1669939200".to_owned(), "1669939200".to_owned()),
+			("TS-2|1669939260".to_owned(), "not-a-number".to_owned()),
+			("TS-3|1669939320".to_owned(), "1669939320.0".to_owned()),
+			("TS-4|99999999999999999".to_owned(), "99999999999999999".to_owned()),  // beyond the range of dates
+		];
+		let converted: VecRQScheduledTask = members.into();
+		assert_eq!(converted.len(), 2);
+		assert_eq!(converted.skipped(), 2);
+	}
+
+	/// A plain-std benchmark: converting 10,000 members must stay fast, even in a debug build.
+	#[test]
+	fn test_scheduled_task_conversion_performance() {
+		use std::time::{Duration, Instant};
+		use crate::scheduler::VecRQScheduledTask;
+
+		let start_unix: i64 = 1669939200;
+		let members = (0..10_000_i64).map(|index| (format!("TS-{:06}|{}", index, start_unix + index), (start_unix + index).to_string()));
+		let started = Instant::now();
+		let converted: VecRQScheduledTask = members.collect();
+		let elapsed = started.elapsed();
+		assert_eq!((converted.len(), converted.skipped()), (10_000, 0));
+		assert!(elapsed < Duration::from_millis(500), "Converting 10,000 members took {:?}", elapsed);
+	}
+
+	#[test]
+	fn test_scheduled_task_collection_api() {
+		use crate::scheduler::{RQScheduledTask, VecRQScheduledTask};
+
+		let member = |id: &str, unix: i64| (id.to_owned(), unix.to_string());
+		let tasks: VecRQScheduledTask = vec![
+			member("TS-3", 1669939320), member("TS-1", 1669939260), member("TS-2", 1669939260), member("TS-0", 1669939200)
+		].into();
+		let ids = |tasks: &[RQScheduledTask]| -> Vec<String> { tasks.iter().map(|task| task.task_schedule_id.to_string()).collect() };
+
+		// Borrowed iteration, indexing, and slicing.
+		assert_eq!((&tasks).into_iter().count(), 4);
+		assert_eq!(tasks.iter().filter(|task| task.next_datetime_unix == 1669939260).count(), 2);
+		assert_eq!(tasks.as_slice()[0].task_schedule_id.as_str(), "TS-3");
+		assert_eq!(ids(&tasks.as_slice()[1..3]), vec!["TS-1", "TS-2"]);
+
+		let by_id: VecRQScheduledTask = tasks.clone().sort_by_id();
+		assert_eq!(ids(by_id.as_slice()), vec!["TS-0", "TS-1", "TS-2", "TS-3"]);
+		// Sorting by time is stable: TS-1 and TS-2 are due together, and keep their order.
+		assert_eq!(ids(tasks.clone().sort_by_next_datetime().as_slice()), vec!["TS-0", "TS-1", "TS-2", "TS-3"]);
+		let reversed: VecRQScheduledTask = vec![member("TS-2", 1669939260), member("TS-1", 1669939260)].into();
+		assert_eq!(ids(reversed.sort_by_next_datetime().as_slice()), vec!["TS-2", "TS-1"]);
+
+		// Owned iteration, and conversion back to a plain Vec.
+		let owned: Vec<RQScheduledTask> = tasks.clone().into_iter().collect();
+		assert_eq!(owned, Vec::<RQScheduledTask>::from(tasks));
+
+		let empty = VecRQScheduledTask::new();
+		assert!(empty.is_empty());
+		assert_eq!((empty.len(), empty.skipped(), empty.iter().next()), (0, 0, None));
+		assert!(Vec::<RQScheduledTask>::from(empty.sort_by_id()).is_empty());
+	}
+
+
+	#[test]
+	fn test_rq_job_preserves_unknown_fields() {
+		use std::collections::HashMap;
+		use crate::rq::rq_job_from_hashmap;
+
+		let mut hash: HashMap<String, Vec<u8>> = [
+			"status", "worker_name", "ended_at", "result_ttl", "enqueued_at", "origin", "description", "started_at", "timeout",
+		].iter().map(|name| (name.to_string(), b"".to_vec())).collect();
+		hash.insert("timeout".to_owned(), b"600".to_vec());
+		hash.insert("last_heartbeat".to_owned(), b"2022-12-02T00:00:00.000Z".to_vec());
+		hash.insert("created_at".to_owned(), b"2022-12-02T00:00:00.000Z".to_vec());
+		hash.insert("data".to_owned(), vec![0x80, 0x04, 0x95]);
+		hash.insert("retries_left".to_owned(), b"3".to_vec());
+		hash.insert("success_callback_name".to_owned(), vec![0x00, 0xFF, 0x80, 0x7F]);  // binary; not valid UTF-8
+
+		// Read, then "save" to a new hash: the unknown fields must survive byte-for-byte.
+		let job = rq_job_from_hashmap("abc", hash.clone()).unwrap();
+		let saved: HashMap<String, Vec<u8>> = job.to_redis_fields(false).into_iter().collect();
+		for unknown in ["retries_left", "success_callback_name"] {
+			assert_eq!(saved.get(unknown), hash.get(unknown), "field '{}'", unknown);
+		}
+		let reread = rq_job_from_hashmap("abc", saved).unwrap();
+		assert_eq!(reread.extra_fields, job.extra_fields);
+
+		// Display lists the names, never the (possibly binary) values.
+		let display: String = job.to_string();
+		assert!(display.contains("extra_fields: retries_left, success_callback_name"), "{}", display);
+	}
+
+	#[test]
+	fn test_describe_job_data() {
+		use crate::pickle::{unpickle, PickleValue};
+		use crate::rq::{compress_job_data, describe_job_data, RQJob};
+
+		// 'data' as RQ writes it: zlib.compress(pickle.dumps((func_name, instance, args, kwargs), protocol=pickle.HIGHEST_PROTOCOL)).
+		let fixture: &[u8] = include_bytes!("../testdata/rq_job_data_ping_with_wait.bin");
+		let mut job = RQJob::new_with_defaults();
+		job.data = fixture.to_vec();
+		let expected: &str = "btu.manual_tests.ping_with_wait(10, site='erp.example.com', verbose=True, ratio=0.5, tags=['a', 'b'], \
+		                      big=1099511627776, neg=-3)";
+		assert_eq!(job.describe_data(), expected);
+		assert!(job.to_string().contains(&format!("data: {}\n", expected)), "{}", job);
+
+		// Uncompressed data, and older protocols with BINPUT memoization.
+		let protocol_2: &[u8] = b"\x80\x02(X\x11\x00\x00\x00btu.tasks.nightlyq\x00N)}q\x01tq\x02.";
+		assert_eq!(describe_job_data(protocol_2).as_deref(), Some("btu.tasks.nightly()"));
+		assert_eq!(describe_job_data(&compress_job_data(protocol_2).unwrap()).as_deref(), Some("btu.tasks.nightly()"));
+
+		// An argument that is a Python object (here, a datetime.date) cannot be read; only the length is shown.
+		let with_object: &[u8] = b"\x80\x04\x957\x00\x00\x00\x00\x00\x00\x00(\x8c\x0cbtu.tasks.at\x94N\x8c\x08datetime\x94\x8c\x04date\x94\
+		                           \x93\x94C\x04\x07\xe8\x01\x02\x94\x85\x94R\x94\x85\x94}\x94t\x94.";
+		assert_eq!(describe_job_data(with_object), None);
+		job.data = with_object.to_vec();
+		assert_eq!(job.describe_data(), "<bytes> with length 66");
+		job.data = Vec::new();
+		assert_eq!(job.describe_data(), "<bytes> with length 0");
+
+		// Values are written as Python's repr() would write them.
+		assert_eq!(unpickle(b"\x80\x02X\x04\x00\x00\x00it's."), Some(PickleValue::Text("it's".to_owned())));
+		assert_eq!(PickleValue::Text("it's".to_owned()).to_string(), r"'it\'s'");
+		assert_eq!(PickleValue::Tuple(vec![PickleValue::Bytes(b"a\x00".to_vec())]).to_string(), r"(b'a\x00',)");
+		assert_eq!(unpickle(b"\x80\x02\x8a\x01\xff."), Some(PickleValue::Int(-1)));  // LONG1
+		assert_eq!(unpickle(b"\x80\x02N"), None);  // no STOP
+	}
+
+	#[test]
+	fn test_argument_overrides_reach_frappe_and_meta() {
+		use std::collections::HashMap;
+		use crate::rq::{pickle_string_dict, rq_job_from_hashmap, unpickle_string_dict, RQJob};
+
+		// The overrides are sent to Frappe alongside the Task and Task Schedule.
+		let body = crate::pickled_task_request_body(&"TASK-1".into(), Some(&"TS-1".into()), Some(r#"{"company": "ACME"}"#));
+		assert_eq!(body, serde_json::json!({ "task_id": "TASK-1", "task_schedule_id": "TS-1", "argument_overrides": r#"{"company": "ACME"}"# }));
+		assert!(crate::pickled_task_request_body(&"TASK-1".into(), None, None)["argument_overrides"].is_null());
+
+		// 'meta' is a pickled dict, because RQ workers unpickle it.  Python reads these bytes as {'a': 'é'}.
+		assert_eq!(pickle_string_dict(&[("a", "é")]), b"\x80\x02}X\x01\x00\x00\x00aX\x02\x00\x00\x00\xc3\xa9s.".to_vec());
+		// What Python writes, protocol 4: pickle.dumps({'btu_argument_overrides': '{"a": 1}'}, protocol=4)
+		let from_python: &[u8] = b"\x80\x04\x95(\x00\x00\x00\x00\x00\x00\x00}\x94\x8c\x16btu_argument_overrides\x94\x8c\x08{\"a\": 1}\x94s.";
+		assert_eq!(unpickle_string_dict(from_python), Some(vec![("btu_argument_overrides".to_owned(), r#"{"a": 1}"#.to_owned())]));
+		// ... and protocol 2, with SETITEMS: pickle.dumps({'a': 'b', 'c': 'd'}, protocol=2)
+		let setitems: &[u8] = b"\x80\x02}q\x00(X\x01\x00\x00\x00aq\x01X\x01\x00\x00\x00bq\x02X\x01\x00\x00\x00cq\x03X\x01\x00\x00\x00dq\x04u.";
+		assert_eq!(unpickle_string_dict(setitems), Some(vec![("a".to_owned(), "b".to_owned()), ("c".to_owned(), "d".to_owned())]));
+		// Anything else (here, {'n': 1}) is not read.
+		assert_eq!(unpickle_string_dict(b"\x80\x04\x95\n\x00\x00\x00\x00\x00\x00\x00}\x94\x8c\x01n\x94K\x01s."), None);
+		assert_eq!(unpickle_string_dict(b"\x80\x02}X\xff\x00\x00\x00a"), None);  // truncated
+
+		// The overrides survive a save and a read, byte for byte.
+		let mut job = RQJob::new_with_defaults();
+		assert_eq!(job.argument_overrides(), None);
+		job.set_argument_overrides(r#"{"company": "ACME"}"#);
+		let saved: HashMap<String, Vec<u8>> = job.to_redis_fields(false).into_iter().collect();
+		let reread = rq_job_from_hashmap(job.job_key_short.as_str(), saved).unwrap();
+		assert_eq!(reread.meta(), job.meta());
+		assert_eq!(reread.argument_overrides().as_deref(), Some(r#"{"company": "ACME"}"#));
+		assert!(reread.to_string().contains(r#"argument_overrides: {"company": "ACME"}"#));
+	}
+
+	/// Needs a live Redis, configured by BTU_* environment variables:
+	/// `cargo test -p btu_scheduler --features integration -- test_argument_overrides_round_trip_live`
+	#[cfg(feature = "integration")]
+	#[test]
+	fn test_argument_overrides_round_trip_live() {
+		let app_config: AppConfig = AppConfig::new_from_env().expect("BTU_* environment variables describing a live installation");
+		let mut job = crate::rq::RQJob::new_with_defaults();
+		job.set_argument_overrides(r#"{"company": "ACME"}"#);
+		job.save_to_redis(&app_config).unwrap();
+		let reread = crate::rq::read_job_by_id(&app_config, &job.job_key_short).unwrap();
+		let _: Result<(), _> = redis::Commands::del(&mut crate::rq::get_redis_connection(&app_config, false).unwrap(), &job.job_key);
+		assert_eq!(reread.meta(), job.meta());
+		assert_eq!(reread.argument_overrides().as_deref(), Some(r#"{"company": "ACME"}"#));
+	}
+
+
+	#[test]
+	fn test_typed_identifiers() {
+		use crate::errors::IdentifierError;
+		use crate::ids::{RqJobId, TaskId, TaskScheduleId, MAX_IDENTIFIER_LENGTH};
+
+		// User input is trimmed, and must be non-empty and no longer than a Frappe 'name'.
+		assert_eq!(TaskScheduleId::new("  TS-000123\n").unwrap(), "TS-000123");
+		assert_eq!(TaskId::new(" "), Err(IdentifierError::Empty { kind: "BTU Task ID" }));
+		let too_long: String = "é".repeat(MAX_IDENTIFIER_LENGTH + 1);  // counted in characters, not bytes
+		assert_eq!(RqJobId::new(&too_long), Err(IdentifierError::TooLong { kind: "RQ Job ID", length: 141, max_length: 140 }));
+		assert!(RqJobId::new(&too_long[2..]).is_ok());
+		assert_eq!(TaskId::new("").unwrap_err().to_string(), "A BTU Task ID cannot be empty.");
+
+		// Display, AsRef, and serde all see the bare string.
+		let task_schedule_id = TaskScheduleId::from("TS-000123");
+		assert_eq!(task_schedule_id.to_string(), "TS-000123");
+		assert_eq!(task_schedule_id.as_ref(), "TS-000123");
+		assert_eq!(serde_json::to_string(&task_schedule_id).unwrap(), "\"TS-000123\"");
+		assert_eq!(serde_json::from_str::<TaskScheduleId>("\"TS-000123\"").unwrap(), task_schedule_id);
+
+		// A TSIK's Task Schedule ID, and an RQJob's short key, are typed.
+		let tsik = crate::scheduler::TSIK::from("TS-000123|1669939200");
+		let from_tsik: TaskScheduleId = tsik.task_schedule_id();
+		assert_eq!(from_tsik, task_schedule_id);
+		let job = crate::rq::RQJob::new_with_defaults();
+		let job_id: &RqJobId = &job.job_key_short;
+		assert_eq!(job.job_key, format!("rq:job:{}", job_id));
+	}
+
+	/// True if 'value' looks like 'YYYY-MM-DDTHH:MM:SS[.fff|.ffffff]Z', which both Python RQ's utcparse() and fromisoformat() accept.
+	fn is_rq_datetime_shape(value: &str) -> bool {
+		let Some(body) = value.strip_suffix('Z') else { return false };
+		let (whole, fraction) = body.split_once('.').unwrap_or((body, "000"));
+		let digits_ok = |text: &str| text.bytes().all(|byte| byte.is_ascii_digit());
+		whole.len() == 19
+			&& whole.bytes().enumerate().all(|(index, byte)| match index {
+				4 | 7 => byte == b'-',
+				10 => byte == b'T',
+				13 | 16 => byte == b':',
+				_ => byte.is_ascii_digit(),
+			})
+			&& [3, 6].contains(&fraction.len()) && digits_ok(fraction)
+			&& NaiveDateTime::parse_from_str(body, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+	}
+
+	#[test]
+	fn test_enqueued_job_matches_python_rq_fields() {
+		use std::collections::HashMap;
+		use crate::rq::{enqueue_fields, RQJob};
+
+		// Captured with HGETALL from a job enqueued by Python RQ 1.10 (Queue.enqueue), data shortened.
+		let python_rq: &[(&str, &str)] = &[
+			("status", "queued"),
+			("created_at", "2022-12-02T08:00:00.123456Z"),
+			("enqueued_at", "2022-12-02T08:00:00.124002Z"),
+			("last_heartbeat", "2022-12-02T08:00:00.123461Z"),
+			("started_at", ""),
+			("ended_at", ""),
+			("origin", "default"),
+			("description", "frappe.utils.background_jobs.execute_job(...)"),
+			("timeout", "600"),
+			("worker_name", ""),
+			("data", "(zlib-compressed pickle)"),
+		];
+		let datetime_fields = ["created_at", "enqueued_at", "last_heartbeat", "started_at", "ended_at"];
+
+		let mut ours: HashMap<String, String> = RQJob::new_with_defaults().to_redis_fields(false).into_iter()
+			.map(|(name, value)| (name, String::from_utf8_lossy(&value).to_string()))
+			.collect();
+		for (name, value) in enqueue_fields(Utc::now()) {
+			ours.insert(name.to_owned(), value);
+		}
+
+		for (name, python_value) in python_rq {
+			let our_value: &String = ours.get(*name).unwrap_or_else(|| panic!("BTU does not write field '{}'", name));
+			if datetime_fields.contains(name) && !python_value.is_empty() {
+				// Python RQ has stamped this datetime by the time the job is queued, so BTU must as well.
+				assert!(is_rq_datetime_shape(python_value), "fixture '{}' = '{}'", name, python_value);
+				assert!(is_rq_datetime_shape(our_value), "'{}' = '{}' is not an RQ-compatible datetime", name, our_value);
+			}
+		}
+		assert_eq!(ours.get("status").map(String::as_str), Some("queued"));
+		assert!(!is_rq_datetime_shape("2022-12-02 08:00:00.123Z"));
+		assert!(!is_rq_datetime_shape("2022-12-02T08:00:00.12Z"));
+		assert!(!is_rq_datetime_shape("2022-12-02T08:00:00+00:00"));
+	}
+
+	#[test]
+	fn test_save_to_redis_is_one_transaction() {
+		use crate::errors::RQError;
+		use crate::rq::RQJob;
+
+		// Every write goes through one MULTI/EXEC block; if the connection drops before EXEC, Redis writes nothing.
+		let commands = |pipeline: &redis::Pipeline| -> Vec<String> {
+			String::from_utf8_lossy(&pipeline.get_packed_pipeline()).split("\r\n")
+				.filter(|part| ["MULTI", "EXEC", "HMSET", "HSET", "HDEL"].contains(part))
+				.map(str::to_owned)
+				.collect()
+		};
+		let mut job = RQJob::new_with_defaults();
+		assert_eq!(commands(&job.save_pipeline(false)), vec!["MULTI", "HMSET", "HDEL", "EXEC"]);
+
+		// A compressed Job keeps its marker, so there is nothing to delete.
+		job.data = vec![b'x'; 64 * 1024];
+		assert_eq!(commands(&job.save_pipeline(true)), vec!["MULTI", "HMSET", "EXEC"]);
+
+		// Without Redis, the error is returned to the caller (which then does not enqueue), rather than panicking.
+		let result = job.save_to_redis(&sample_app_config());
+		assert!(matches!(result, Err(RQError::NoConnection)), "{:?}", result);
+	}
+
+	/// The commands in a packed pipeline, each as its name and arguments.  MULTI and EXEC are left out.
+	fn pipeline_commands(pipeline: &redis::Pipeline) -> Vec<Vec<String>> {
+		let packed: String = String::from_utf8_lossy(&pipeline.get_packed_pipeline()).into_owned();
+		let mut parts = packed.split("\r\n");
+		let mut commands: Vec<Vec<String>> = Vec::new();
+		while let Some(header) = parts.next() {
+			let Some(count) = header.strip_prefix('*').and_then(|count| count.parse::<usize>().ok()) else { continue };
+			let command: Vec<String> = (0..count).map(|_| { parts.next(); parts.next().unwrap().to_owned() }).collect();
+			if !["MULTI", "EXEC"].contains(&command[0].as_str()) {
+				commands.push(command);
+			}
+		}
+		commands
+	}
+
+	#[test]
+	fn test_deferred_job_matches_python_rq() {
+		use std::collections::{BTreeMap, BTreeSet};
+		use chrono::TimeZone;
+		use crate::rq::RQJob;
+
+		/*
+			Python RQ 1.10, after Queue('default').enqueue(func, depends_on=parent) while the parent job is queued: the keys it
+			wrote for the dependency (HGETALL, ZRANGE WITHSCORES, SMEMBERS), with '<child>' and '<parent>' for the job IDs.
+			Hash fields without a bearing on dependencies are covered by test_enqueued_job_matches_python_rq_fields.
+		*/
+		let python_rq_hash: &[(&str, &str)] = &[
+			("status", "deferred"),
+			("dependency_id", "<parent>"),
+			("dependency_ids", r#"["<parent>"]"#),
+			("enqueued_at", ""),
+			("origin", "default"),
+		];
+		let python_rq_sets: &[(&str, &[&str])] = &[
+			("rq:job:<parent>:dependents", &["<child>"]),
+			("rq:job:<child>:dependencies", &["<parent>"]),
+		];
+		let python_rq_zsets: &[(&str, &str, &str)] = &[("rq:deferred:default", "<child>", "1669968000")];
+
+		let now: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 0).unwrap();
+		let parent: crate::ids::RqJobId = "0b9d3f5a-parent".into();
+		let job = RQJob::new_with_defaults();
+		let ids = |text: &str| text.replace("<child>", job.job_key_short.as_str()).replace("<parent>", parent.as_str());
+
+		// Apply our pipeline to an empty database, as Redis would.
+		let mut hashes: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+		let mut sets: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+		let mut zsets: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+		let mut pushed: Vec<String> = Vec::new();
+		for command in pipeline_commands(&job.save_deferred_pipeline(&parent, false, now)) {
+			match command[0].as_str() {
+				"HMSET" | "HSET" => for pair in command[2..].chunks(2) {
+					hashes.entry(command[1].clone()).or_default().insert(pair[0].clone(), pair[1].clone());
+				},
+				"HDEL" => { hashes.entry(command[1].clone()).or_default().remove(&command[2]); },
+				"SADD" => { sets.entry(command[1].clone()).or_default().extend(command[2..].iter().cloned()); },
+				"ZADD" => { zsets.entry(command[1].clone()).or_default().insert(command[3].clone(), command[2].clone()); },
+				"RPUSH" | "LPUSH" => pushed.push(command[1].clone()),
+				other => panic!("unexpected command {}", other),
+			}
+		}
+
+		let job_hash: &BTreeMap<String, String> = &hashes[&job.job_key];
+		for (name, python_value) in python_rq_hash {
+			assert_eq!(job_hash.get(*name), Some(&ids(python_value)), "hash field '{}'", name);
+		}
+		for (key, members) in python_rq_sets {
+			let members: BTreeSet<String> = members.iter().map(|member| ids(member)).collect();
+			assert_eq!(sets.get(&ids(key)), Some(&members), "set '{}'", key);
+		}
+		for (key, member, score) in python_rq_zsets {
+			assert_eq!(zsets.get(*key).and_then(|zset| zset.get(&ids(member))), Some(&score.to_string()), "sorted set '{}'", key);
+		}
+		assert_eq!((sets.len(), zsets.len(), hashes.len()), (2, 1, 1), "nothing else is written");
+		assert!(pushed.is_empty(), "a deferred job is never pushed onto its queue; RQ's worker does that");
+	}
+
+	#[test]
+	fn test_diagnose_scenarios() {
+		use chrono::{Duration, TimeZone};
+		use crate::diagnose::{any_failed, diagnose, DiagnosticFinding, DiagnosticInputs, RedisFacts, ScheduleFacts, Severity, TaskFacts};
+		use crate::events::{EventKind, SchedulerEvent, StreamedEvent};
+		use crate::task::TaskStatus;
+
+		let now: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 0).unwrap();
+		let healthy = DiagnosticInputs {
+			task_schedule_id: "TS-0001".into(),
+			schedule: Ok(Some(ScheduleFacts {
+				enabled: true,
+				task: "TASK-0001".into(),
+				queue_name: "default".to_owned(),
+				expression: "0 9 * * *".to_owned(),
+				description: "every day at 09:00 UTC".to_owned(),
+				cron: crate::btu_cron::classify_cron("0 9 * * *").ok(),
+				next_runs: Ok(vec![now + Duration::hours(1), now + Duration::hours(25), now + Duration::hours(49)]),
+			})),
+			task: Some(Ok(Some(TaskFacts { status: TaskStatus::Submitted, path_to_function: "btu.ping".to_owned(), policy: Ok(()) }))),
+			redis: Ok(RedisFacts {
+				next_tsik: Some(now + Duration::hours(1)),
+				circuit_open: false,
+				unschedulable: None,
+				next_run_override: None,
+				last_job: Some(("job-1".into(), Some("finished".to_owned()))),
+				recent_events: vec![],
+				live_workers: Some(true),
+			}),
+			daemon_last_cycle: Ok(Some(now - Duration::seconds(30))),
+			polling_interval_secs: 60,
+		};
+		let checks = |findings: &[DiagnosticFinding], severity: Severity| -> Vec<&'static str> {
+			findings.iter().filter(|finding| finding.severity == severity).map(|finding| finding.check).collect()
+		};
+
+		// 1. Everything is fine.
+		let findings: Vec<DiagnosticFinding> = diagnose(&healthy, now);
+		assert_eq!(findings.iter().map(|finding| finding.check).collect::<Vec<_>>(),
+		           vec!["schedule", "task", "function policy", "cron", "next run", "circuit breaker", "unschedulable", "last run", "history", "workers", "daemon"]);
+		assert!(checks(&findings, Severity::Fail).is_empty() && checks(&findings, Severity::Warn).is_empty(), "{:?}", findings);
+		assert!(!any_failed(&findings));
+		assert_eq!(findings[3].to_string(), "[PASS] cron: '0 9 * * *', read as '0 0 9 * * * *' runs every day at 09:00 UTC.  \
+		                                     Next: 2022-12-02T09:00:00Z, 2022-12-03T09:00:00Z, 2022-12-04T09:00:00Z");
+
+		// 2. Disabled: nothing in Redis is expected.
+		let mut disabled = healthy.clone();
+		if let Ok(Some(schedule)) = &mut disabled.schedule { schedule.enabled = false; }
+		if let Ok(redis) = &mut disabled.redis { redis.next_tsik = None; }
+		let findings = diagnose(&disabled, now);
+		assert_eq!(checks(&findings, Severity::Fail), vec!["schedule"]);
+		assert!(findings.iter().any(|finding| finding.check == "next run" && finding.explanation.contains("as expected")));
+
+		// 3. Nothing can be read: only the sources themselves are reported.
+		let unreachable = DiagnosticInputs {
+			schedule: Ok(None),
+			task: None,
+			redis: Err("unable to establish a connection".to_owned()),
+			daemon_last_cycle: Err("Cannot connect to the daemon's socket".to_owned()),
+			..healthy.clone()
+		};
+		let findings = diagnose(&unreachable, now);
+		assert_eq!(checks(&findings, Severity::Fail), vec!["schedule", "redis", "daemon"]);
+		assert_eq!(findings[0].explanation, "Task Schedule TS-0001 does not exist in MariaDB.");
+		assert!(any_failed(&findings));
+
+		// 4. Many things wrong at once.
+		let mut troubled = healthy.clone();
+		troubled.task = Some(Ok(Some(TaskFacts { status: TaskStatus::Draft, path_to_function: "os.system".to_owned(), policy: Err("not allowed".to_owned()) })));
+		if let Ok(Some(schedule)) = &mut troubled.schedule {
+			schedule.expression = "30 0 9 * * * *".to_owned();
+			schedule.cron = crate::btu_cron::classify_cron("30 0 9 * * * *").ok();
+		}
+		troubled.redis = Ok(RedisFacts {
+			next_tsik: Some(now - Duration::minutes(10)),
+			circuit_open: true,
+			unschedulable: None,
+			next_run_override: Some(now + Duration::hours(2)),
+			last_job: Some(("job-9".into(), Some("failed".to_owned()))),
+			recent_events: vec![StreamedEvent {
+				id: "1669968000000-0".to_owned(),
+				event: SchedulerEvent { at: "2022-12-02T07:59:00Z".to_owned(), ..SchedulerEvent::new(EventKind::Skipped).reason("circuit_open") },
+			}],
+			live_workers: Some(false),
+		});
+		troubled.daemon_last_cycle = Ok(Some(now - Duration::minutes(10)));
+		let findings = diagnose(&troubled, now);
+		assert_eq!(checks(&findings, Severity::Fail), vec!["function policy", "circuit breaker", "workers"]);
+		assert_eq!(checks(&findings, Severity::Warn), vec!["task", "cron elements", "next run", "next-run override", "last run", "history", "daemon"]);
+		let history: &DiagnosticFinding = findings.iter().find(|finding| finding.check == "history").unwrap();
+		assert_eq!(history.explanation, "Most recent first: 2022-12-02T07:59:00Z skipped (circuit_open)");
+	}
+
+	#[test]
+	fn test_dependency_plan() {
+		use std::collections::HashMap;
+		use crate::dependency::{plan, DependencyPlan};
+		use crate::ids::RqJobId;
+
+		let parent = |status: &str| -> Option<(RqJobId, HashMap<String, Vec<u8>>)> {
+			let mut hash: HashMap<String, Vec<u8>> = HashMap::new();
+			if !status.is_empty() {
+				hash.insert("status".to_owned(), status.as_bytes().to_vec());
+			}
+			Some(("job-1".into(), hash))
+		};
+		for status in ["queued", "started", "deferred", "scheduled"] {
+			assert_eq!(plan("TS-2", "TS-1", parent(status)), DependencyPlan::Defer { parent_job_id: "job-1".into(), warning: None }, "{}", status);
+		}
+		assert_eq!(plan("TS-2", "TS-1", parent("finished")), DependencyPlan::EnqueueNow { warning: None });
+
+		// Python RQ leaves a job deferred behind a failed parent; say so.
+		let DependencyPlan::Defer { warning: Some(warning), .. } = plan("TS-2", "TS-1", parent("failed")) else { panic!("expected a warning") };
+		assert!(warning.contains("is 'failed'"), "{}", warning);
+
+		// Nothing to wait for: enqueue as usual, with a warning.
+		let DependencyPlan::EnqueueNow { warning: Some(warning) } = plan("TS-2", "TS-1", None) else { panic!("expected a warning") };
+		assert_eq!(warning, "Task Schedule TS-2 depends on Task Schedule TS-1, which has no recent RQ Job; enqueued without waiting.");
+		let DependencyPlan::EnqueueNow { warning: Some(warning) } = plan("TS-2", "TS-1", parent("")) else { panic!("expected a warning") };
+		assert!(warning.contains("no longer exists"), "{}", warning);
+	}
+
+	#[test]
+	fn test_job_listing_without_redis() {
+		use crate::errors::RQError;
+
+		// An unreachable Redis is an error, never an empty list (or a count of zero).
+		let app_config: AppConfig = sample_app_config();
+		assert!(matches!(crate::rq::get_all_job_ids(&app_config), Err(RQError::NoConnection)));
+		assert!(matches!(crate::rq::get_job_ids_page(&app_config, 10), Err(RQError::NoConnection)));
+		assert!(matches!(crate::rq::count_jobs(&app_config), Err(RQError::NoConnection)));
+	}
+
+
+	#[test]
+	fn test_cycle_summary_counts() {
+		use chrono::TimeZone;
+		use crate::scheduler::{CycleSummary, TaskRunOutcome};
+
+		let started_at: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 0).unwrap();
+		let mut summary = CycleSummary::new(started_at, started_at.timestamp());
+		let outcomes: Vec<Result<TaskRunOutcome, String>> = vec![
+			Ok(TaskRunOutcome::Enqueued),
+			Ok(TaskRunOutcome::Enqueued),
+			Ok(TaskRunOutcome::Skipped),
+			Ok(TaskRunOutcome::BlockedByPolicy),
+			Err("task is cancelled in Frappe".to_owned()),
+		];
+		for outcome in &outcomes {
+			summary.record(outcome);
+		}
+		assert_eq!((summary.eligible, summary.enqueued, summary.skipped, summary.failed, summary.blocked_by_policy), (5, 2, 1, 1, 1));
+		assert_eq!(summary.started_at, "2022-12-02T08:00:00Z");
+		assert!(summary.to_string().contains("5 eligible, 2 enqueued, 1 failed, 1 skipped, 1 blocked by policy"), "{}", summary);
+
+		// A job enqueued on the second attempt counts as enqueued, too.
+		summary.record::<String>(&Ok(TaskRunOutcome::Reenqueued));
+		assert_eq!((summary.eligible, summary.enqueued, summary.reenqueued), (6, 3, 1));
+		assert!(summary.to_string().contains("1 jobs were enqueued a second time"), "{}", summary);
+	}
+
+	#[test]
+	fn test_parse_repeat_interval() {
+		use chrono::Duration;
+		use crate::btu_cron::parse_repeat_interval;
+
+		// ISO-8601 durations: weeks, days, hours, minutes, and seconds.
+		assert_eq!(parse_repeat_interval("PT2H").unwrap(), Duration::hours(2));
+		assert_eq!(parse_repeat_interval("PT30M").unwrap(), Duration::minutes(30));
+		assert_eq!(parse_repeat_interval("PT45S").unwrap(), Duration::seconds(45));
+		assert_eq!(parse_repeat_interval("P1D").unwrap(), Duration::days(1));
+		assert_eq!(parse_repeat_interval("P2W").unwrap(), Duration::weeks(2));
+		assert_eq!(parse_repeat_interval("P1DT12H").unwrap(), Duration::hours(36));
+		assert_eq!(parse_repeat_interval("PT1H30M15S").unwrap(), Duration::seconds(5415));
+		assert_eq!(parse_repeat_interval(" pt90m ").unwrap(), Duration::minutes(90));
+
+		// Simple phrases.
+		assert_eq!(parse_repeat_interval("every 2 hours").unwrap(), Duration::hours(2));
+		assert_eq!(parse_repeat_interval("Every 30 Minutes").unwrap(), Duration::minutes(30));
+		assert_eq!(parse_repeat_interval("every hour").unwrap(), Duration::hours(1));
+		assert_eq!(parse_repeat_interval("15 mins").unwrap(), Duration::minutes(15));
+		assert_eq!(parse_repeat_interval("every 1 week").unwrap(), Duration::weeks(1));
+		assert_eq!(parse_repeat_interval("day").unwrap(), Duration::days(1));
+
+		// Rejected: empty, zero, out of order, repeated, months and years, fractions, trailing garbage, and overflow.
+		for invalid in ["", "P", "PT", "PT0S", "every 0 minutes", "PT30M2H", "PT1H1H", "P1M", "P1Y", "PT1.5H", "PT2", "PT2HX", "2H",
+		                "every -5 minutes", "every two hours", "every 5 fortnights", "P99999999999999999W"] {
+			assert!(parse_repeat_interval(invalid).is_err(), "'{}' should be rejected", invalid);
+		}
+		assert!(parse_repeat_interval("P1M").unwrap_err().to_string().contains("'P1M'"));
+	}
+
+	#[test]
+	fn test_interval_runtimes() {
+		use chrono::{Duration, TimeZone};
+		use crate::btu_cron::{describe_interval, interval_runtimes};
+
+		let last_run: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 0).unwrap();
+		let runtimes = interval_runtimes(last_run, Duration::minutes(30), 3);
+		assert_eq!(runtimes, vec![
+			Utc.with_ymd_and_hms(2022, 12, 2, 8, 30, 0).unwrap(),
+			Utc.with_ymd_and_hms(2022, 12, 2, 9, 0, 0).unwrap(),
+			Utc.with_ymd_and_hms(2022, 12, 2, 9, 30, 0).unwrap(),
+		]);
+		assert!(interval_runtimes(last_run, Duration::hours(1), 0).is_empty());
+
+		assert_eq!(describe_interval(Duration::hours(2)), "every 2 hours");
+		assert_eq!(describe_interval(Duration::minutes(90)), "every 90 minutes");
+		assert_eq!(describe_interval(Duration::days(7)), "every week");
+		assert_eq!(describe_interval(Duration::seconds(45)), "every 45 seconds");
+	}
+
+	// Unschedulable markers, kept in a map instead of Redis.
+	impl crate::scheduler::MarkerStore for std::collections::HashMap<String, String> {
+		fn read_marker(&mut self, key: &str) -> Option<String> {
+			self.get(key).cloned()
+		}
+		fn write_marker(&mut self, key: &str, value: &str) {
+			self.insert(key.to_owned(), value.to_owned());
+		}
+		fn remove_marker(&mut self, key: &str) -> bool {
+			self.remove(key).is_some()
+		}
+	}
+
+	#[test]
+	fn test_unschedulable_marker_transitions() {
+		use std::collections::HashMap;
+		use crate::scheduler::{MarkerTransition, UnschedulableMarker, unschedulable_key, update_unschedulable_marker};
+
+		// A year in the past: the expression parses, but never fires again.
+		assert!(tz_cron_to_utc_datetimes("0 0 12 * * * 2019", chrono_tz::UTC, None, &1, DomDowSemantics::And).unwrap().is_empty());
+		let marker = |reason: &str, detected_at: &str| UnschedulableMarker {
+			task_schedule_id: "TS-1".into(),
+			cron_string: "0 0 12 * * * 2019".to_owned(),
+			cron_timezone: "UTC".to_owned(),
+			reason: reason.to_owned(),
+			detected_at: detected_at.to_owned(),
+		};
+		let mut store: HashMap<String, String> = HashMap::new();
+		assert_eq!(update_unschedulable_marker(&mut store, "TS-1", None), MarkerTransition::Unchanged);
+		assert!(store.is_empty());
+
+		// Schedulable -> unschedulable: the marker is written, with the cron string and reason.
+		assert_eq!(update_unschedulable_marker(&mut store, "TS-1", Some(marker("no upcoming dates", "2022-12-02T08:00:00Z"))), MarkerTransition::Marked);
+		let stored: UnschedulableMarker = serde_json::from_str(&store[&unschedulable_key("TS-1")]).unwrap();
+		assert_eq!(stored, marker("no upcoming dates", "2022-12-02T08:00:00Z"));
+
+		// Still unschedulable: no second notification, and the original detection time is kept.
+		assert_eq!(update_unschedulable_marker(&mut store, "TS-1", Some(marker("invalid", "2022-12-02T09:00:00Z"))), MarkerTransition::StillMarked);
+		let stored: UnschedulableMarker = serde_json::from_str(&store[&unschedulable_key("TS-1")]).unwrap();
+		assert_eq!((stored.reason.as_str(), stored.detected_at.as_str()), ("invalid", "2022-12-02T08:00:00Z"));
+
+		// Unschedulable -> schedulable: the marker is cleared automatically.
+		assert_eq!(update_unschedulable_marker(&mut store, "TS-1", None), MarkerTransition::Cleared);
+		assert!(store.is_empty());
+		assert_eq!(unschedulable_key("TS-1"), "btu_scheduler:unschedulable:TS-1");
+	}
+
+	/// A marker whose Task Schedule is no longer enabled is stale; markers of other sites are left to their own refresh.
+	#[test]
+	fn test_stale_unschedulable_markers() {
+		use std::collections::HashSet;
+		use crate::scheduler::{UnschedulableMarker, stale_unschedulable_markers};
+
+		let marker = |task_schedule_id: &str| UnschedulableMarker {
+			task_schedule_id: task_schedule_id.into(),
+			cron_string: "0 0 12 * * * 2019".to_owned(),
+			cron_timezone: "UTC".to_owned(),
+			reason: "no upcoming dates".to_owned(),
+			detected_at: "2022-12-02T08:00:00Z".to_owned(),
+		};
+		let markers = vec![marker("TS-1"), marker("TS-2"), marker("hr.example.com::TS-3"), marker("hr.example.com::TS-4")];
+		let enabled: HashSet<String> = ["TS-1", "TS-4"].iter().map(|id| id.to_string()).collect();
+
+		let stale = |site: Option<&str>| -> Vec<String> {
+			stale_unschedulable_markers(&markers, site, &enabled).iter().map(|id| id.to_string()).collect()
+		};
+		assert_eq!(stale(None), vec!["TS-2"]);
+		assert_eq!(stale(Some("hr.example.com")), vec!["hr.example.com::TS-3"]);
+	}
+
+	// Scheduler events, appended to a vector instead of the Redis stream.
+	impl crate::events::EventSink for Vec<String> {
+		fn append_event(&mut self, json: &str) -> Result<(), redis::RedisError> {
+			self.push(json.to_owned());
+			Ok(())
+		}
+	}
+
+	/// Collects the fields (and the level) of every tracing event, as text.
+	#[derive(Clone, Default)]
+	struct CapturedEvents(std::sync::Arc<std::sync::Mutex<Vec<std::collections::BTreeMap<String, String>>>>);
+
+	impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for CapturedEvents {
+		fn on_event(&self, event: &tracing::Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+			struct Visitor<'a>(&'a mut std::collections::BTreeMap<String, String>);
+			impl<'a> tracing::field::Visit for Visitor<'a> {
+				fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+					self.0.insert(field.name().to_owned(), value.to_owned());
+				}
+				fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+					self.0.insert(field.name().to_owned(), format!("{:?}", value));
+				}
+			}
+			let mut fields = std::collections::BTreeMap::new();
+			fields.insert("level".to_owned(), event.metadata().level().to_string());
+			event.record(&mut Visitor(&mut fields));
+			self.0.lock().unwrap().push(fields);
+		}
+	}
+
+	/// A socket request's correlation ID reaches the log event for its stored Next Execution Time, its scheduler events, and its RQ Job.
+	#[test]
+	fn test_correlation_id_propagation() {
+		use chrono::TimeZone;
+		use tracing_subscriber::prelude::*;
+		use crate::events::{EventKind, SchedulerEvent};
+		use crate::ids::{CorrelationId, TaskScheduleId};
+		use crate::rq::{RQJob, CORRELATION_ID_FIELD};
+		use crate::schedule_queue::{QueueEntry, QueueSource};
+		use crate::scheduler::log_monitored_schedule;
+
+		// A client's ID is kept (trimmed); a missing or empty one is replaced by a short, random one.
+		let correlation_id: CorrelationId = CorrelationId::from_client(Some(" click-42 "));
+		assert_eq!(correlation_id, "click-42");
+		for generated in [CorrelationId::from_client(None), CorrelationId::from_client(Some("  "))] {
+			assert_eq!(generated.as_str().len(), 12);
+			assert!(generated.as_str().chars().all(|character| character.is_ascii_hexdigit()), "{}", generated);
+		}
+		assert_ne!(CorrelationId::generate(), CorrelationId::generate());
+
+		let entry = QueueEntry::new(TaskScheduleId::from("TS-0001"), QueueSource::Socket).with_correlation_id(correlation_id.clone());
+		assert_eq!(entry.correlation_id.as_ref(), Some(&correlation_id));
+
+		// The debug event written after the ZADD carries it as a field.  Without one, the field is absent.
+		let captured = CapturedEvents::default();
+		let next_runtime: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 1, 13, 17, 0, 0).unwrap();
+		tracing::subscriber::with_default(tracing_subscriber::registry().with(captured.clone()), || {
+			log_monitored_schedule(&sample_app_config(), &entry.schedule_id, "every day at 09:00", next_runtime, entry.correlation_id.as_ref());
+			log_monitored_schedule(&sample_app_config(), &entry.schedule_id, "every day at 09:00", next_runtime, None);
+		});
+		let events = captured.0.lock().unwrap();
+		assert_eq!(events.len(), 2);
+		assert_eq!(events[0].get("correlation_id").map(String::as_str), Some("click-42"));
+		assert_eq!(events[0].get("message3").map(String::as_str), Some("Next Execution Time (UTC) for Task Schedule TS-0001 = 2023-01-13T17:00:00+00:00"));
+		assert!(!events[1].contains_key("correlation_id"));
+
+		// The RQ Job stores it in a hash field of its own, which 'btu show-job' prints.
+		let mut job = RQJob::new_with_defaults();
+		assert_eq!(job.correlation_id(), None);
+		job.set_correlation_id(&correlation_id);
+		assert_eq!(job.correlation_id(), Some(correlation_id.clone()));
+		assert!(job.to_redis_fields(false).contains(&(CORRELATION_ID_FIELD.to_owned(), b"click-42".to_vec())));
+		assert!(job.to_string().contains("correlation_id: click-42\n"));
+
+		// And so do the scheduler events for that run.
+		let event = SchedulerEvent::new(EventKind::Enqueued).schedule(&entry.schedule_id).correlation(Some(&correlation_id));
+		assert!(serde_json::to_string(&event).unwrap().ends_with(r#""correlation_id":"click-42"}"#));
+		assert!(!serde_json::to_string(&event.correlation(None)).unwrap().contains("correlation_id"));
+	}
+
+	/// With Redis unreachable, Thread #1 pushes an entry back until MAX_WRITE_ATTEMPTS, and then gives up with an error.
+	#[test]
+	fn test_failed_schedule_write_is_retried() {
+		use tracing_subscriber::prelude::*;
+		use crate::errors::RQError;
+		use crate::ids::CorrelationId;
+		use crate::schedule_queue::{MAX_WRITE_ATTEMPTS, WRITE_RETRY_MAX_DELAY_SECS, QueueEntry, QueueSource, ScheduleQueue, write_retry_delay};
+		use crate::scheduler::{retry_schedule_write, WriteRetry};
+
+		let app_config = sample_app_config();
+		let mut queue = ScheduleQueue::new();
+		queue.push_back(QueueEntry::new("TS-0001".into(), QueueSource::Socket).with_correlation_id(CorrelationId::from("click-42")));
+		queue.push_back(QueueEntry::new("TS-0002".into(), QueueSource::FullRefresh));
+
+		// Process the queue as Thread #1 does; every write fails, just as add_task_schedule_to_rq() does without a connection.
+		let captured = CapturedEvents::default();
+		let mut outcomes: Vec<(String, u32, WriteRetry)> = Vec::new();
+		tracing::subscriber::with_default(tracing_subscriber::registry().with(captured.clone()), || {
+			while let Some(entry) = queue.pop_front() {
+				let error: RQError = crate::rq::get_redis_connection(&app_config, false).map_or(RQError::NoConnection, |_| panic!("Redis is reachable"));
+				outcomes.push((entry.schedule_id.to_string(), entry.attempt, retry_schedule_write(&mut queue, &entry, &error, Utc::now())));
+			}
+		});
+
+		// Both entries take turns at the back of the queue, and keep what they carried.
+		let attempts = |id: &str| -> Vec<(u32, WriteRetry)> {
+			outcomes.iter().filter(|(schedule_id, ..)| schedule_id == id).map(|(_, attempt, outcome)| (*attempt, *outcome)).collect()
+		};
+		assert_eq!(outcomes.len(), 2 * MAX_WRITE_ATTEMPTS as usize);
+		assert_eq!(outcomes[0].0, "TS-0001");
+		assert_eq!(outcomes[1].0, "TS-0002");
+		assert_eq!(attempts("TS-0001"), vec![
+			(1, WriteRetry::Requeued { attempt: 2 }), (2, WriteRetry::Requeued { attempt: 3 }), (3, WriteRetry::Requeued { attempt: 4 }),
+			(4, WriteRetry::Requeued { attempt: 5 }), (5, WriteRetry::GaveUp),
+		]);
+		assert_eq!(attempts("TS-0002").last(), Some(&(5, WriteRetry::GaveUp)));
+		assert!(queue.is_empty());
+
+		let events = captured.0.lock().unwrap();
+		let messages = |level: &str| -> Vec<&String> {
+			events.iter().filter(|event| event["level"] == level).filter_map(|event| event.get("message")).collect()
+		};
+		let retried: Vec<&String> = messages("WARN");
+		assert_eq!(retried.len(), 8);
+		assert!(retried[0].starts_with("Unable to write the Next Execution Times of Task Schedule TS-0001 to Redis (attempt 1 of 5); trying again in 1s: \
+		                                Unable to establish a connection to Redis."), "{}", retried[0]);
+		let gave_up: Vec<&String> = messages("ERROR").into_iter().filter(|message| message.starts_with("Giving up")).collect();
+		assert_eq!(gave_up.len(), 2);
+		assert!(gave_up[0].starts_with("Giving up on Task Schedule TS-0001 (source: socket): its Next Execution Times could not be written \
+		                                to Redis after 5 attempts."), "{}", gave_up[0]);
+
+		// A retry keeps the entry's correlation ID, and its original enqueue time.
+		let now: DateTime<Utc> = Utc::now();
+		let entry = QueueEntry::new("TS-0001".into(), QueueSource::Socket).with_correlation_id(CorrelationId::from("click-42"));
+		let retry = entry.next_attempt(now).unwrap();
+		assert_eq!((retry.attempt, retry.correlation_id.as_ref(), retry.enqueued_at), (2, entry.correlation_id.as_ref(), entry.enqueued_at));
+		assert!(QueueEntry { attempt: MAX_WRITE_ATTEMPTS, ..entry }.next_attempt(now).is_none());
+
+		// Each retry waits twice as long as the one before, up to a limit.
+		let delays: Vec<i64> = (2..=MAX_WRITE_ATTEMPTS).map(|attempt| write_retry_delay(attempt).num_seconds()).collect();
+		assert_eq!(delays, vec![1, 2, 4, 8]);
+		assert_eq!(write_retry_delay(40).num_seconds(), WRITE_RETRY_MAX_DELAY_SECS);
+		assert_eq!(retry.not_before, Some(now + chrono::Duration::seconds(1)));
+
+		// A retry waiting out its delay keeps its place; entries behind it are processed first.
+		let mut queue = ScheduleQueue::new();
+		queue.push_back(retry);
+		queue.push_back(QueueEntry::new("TS-0002".into(), QueueSource::FullRefresh));
+		assert_eq!(queue.pop_ready(now).map(|entry| entry.schedule_id.to_string()), Some("TS-0002".to_owned()));
+		assert!(queue.pop_ready(now).is_none());
+		assert_eq!(queue.pop_ready(now + chrono::Duration::seconds(1)).map(|entry| entry.schedule_id.to_string()), Some("TS-0001".to_owned()));
+	}
+
+	/// With MariaDB unreachable, Thread #1 keeps the entry at the very front of the queue; a missing Task Schedule is dropped.
+	#[test]
+	fn test_failed_schedule_read_is_retried() {
+		use crate::errors::SchedulerError;
+		use crate::schedule_queue::{QueueEntry, QueueSource, ScheduleQueue};
+		use crate::scheduler::{retry_schedule_read, sql_retry_backoff};
+
+		let app_config = sample_app_config();
+		let unavailable = SchedulerError::SqlUnavailable { task_schedule_id: "TS-0002".to_owned(), message: "Connection refused".to_owned() };
+		#[cfg(feature = "sql")]
+		{
+			let error = crate::task_schedule::read_btu_task_schedule(&app_config, &"TS-0002".into()).unwrap_err();
+			assert!(matches!(error, SchedulerError::SqlUnavailable { .. }), "{:?}", error);
+		}
+
+		let mut queue = ScheduleQueue::new();
+		queue.push_priority(QueueEntry::new("TS-0001".into(), QueueSource::Socket));
+		queue.push_back(QueueEntry::new("TS-0002".into(), QueueSource::FullRefresh));
+		queue.push_back(QueueEntry::new("TS-0003".into(), QueueSource::FullRefresh));
+
+		// Meanwhile, a refresh pushed TS-0002 again; the retried entry is still pending only once, and comes first.
+		let entry = queue.pop_front().unwrap();
+		assert_eq!(entry.schedule_id, "TS-0001");
+		assert!(retry_schedule_read(&mut queue, &QueueEntry::new("TS-0002".into(), QueueSource::Startup), &unavailable));
+		let order: Vec<String> = queue.snapshot(10).iter().map(|pending| pending.schedule_id.to_string()).collect();
+		assert_eq!(order, vec!["TS-0002", "TS-0003"]);
+		assert_eq!(queue.pop_front().unwrap().source, QueueSource::Startup);
+
+		let missing = SchedulerError::UnknownTaskSchedule { task_schedule_id: "TS-0003".to_owned() };
+		let entry = queue.pop_front().unwrap();
+		assert!(!retry_schedule_read(&mut queue, &entry, &missing));
+		assert!(queue.is_empty());
+
+		assert_eq!(sql_retry_backoff(&app_config), std::time::Duration::from_secs(15));
+		let mut app_config = app_config;
+		app_config.sql_retry_backoff_secs = Some(2);
+		assert_eq!(sql_retry_backoff(&app_config), std::time::Duration::from_secs(2));
+	}
+
+	#[test]
+	fn test_scheduler_event_stream() {
+		use std::collections::HashMap;
+		use chrono::TimeZone;
+		use redis::streams::StreamId;
+		use crate::events::{record_event, EventKind, SchedulerEvent, StreamedEvent, EVENT_FIELD};
+		use crate::ids::TaskScheduleId;
+
+		let schedule_id = TaskScheduleId::from("TS-0001");
+		let run_at: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 1, 13, 9, 0, 0).unwrap();
+		let scheduled_then_cancelled = |app_config: &AppConfig, sink: &mut Vec<String>| {
+			record_event(app_config, sink, SchedulerEvent::new(EventKind::Scheduled).schedule(&schedule_id).run_at(run_at));
+			record_event(app_config, sink, SchedulerEvent::new(EventKind::Cancelled).schedule(&schedule_id));
+		};
+
+		// Disabled by default: nothing is written.
+		let mut app_config: AppConfig = AppConfig::new_from_toml_string(CONFIG_V2_TOML).unwrap();
+		assert!(!app_config.event_stream_enabled);
+		let mut sink: Vec<String> = Vec::new();
+		scheduled_then_cancelled(&app_config, &mut sink);
+		assert!(sink.is_empty());
+
+		let enabled_toml: String = CONFIG_V2_TOML.replace("tracing_level", "event_stream_enabled = true\ntracing_level");
+		app_config = AppConfig::new_from_toml_string(&enabled_toml).unwrap();
+		assert!(app_config.event_stream_enabled);
+		scheduled_then_cancelled(&app_config, &mut sink);
+		assert_eq!(sink.len(), 2);
+		assert!(sink[0].starts_with(r#"{"event":"scheduled","schedule_id":"TS-0001","at":""#));
+		assert!(sink[0].ends_with(r#""run_at":"2023-01-13T09:00:00Z"}"#), "{}", sink[0]);
+		assert!(!sink[1].contains("null") && !sink[1].contains("job_id"), "empty fields are omitted: {}", sink[1]);
+
+		// Read back as stream entries, in order.  Entries that are not scheduler events are ignored.
+		let entry = |id: &str, json: &str| StreamId {
+			id: id.to_owned(),
+			map: HashMap::from([(EVENT_FIELD.to_owned(), redis::Value::Data(json.as_bytes().to_vec()))])
+		};
+		let entries: Vec<StreamId> = vec![entry("1673600000000-0", &sink[0]), entry("1673600000001-0", "not json"), entry("1673600000002-0", &sink[1])];
+		let events: Vec<StreamedEvent> = entries.iter().filter_map(StreamedEvent::from_stream_id).collect();
+		assert_eq!(events.iter().map(|streamed| (streamed.id.as_str(), streamed.event.event)).collect::<Vec<_>>(),
+		           vec![("1673600000000-0", EventKind::Scheduled), ("1673600000002-0", EventKind::Cancelled)]);
+		assert_eq!(events[0].event.schedule_id.as_ref().map(|id| id.as_str()), Some("TS-0001"));
+		assert_eq!(events[0].event.run_at.as_deref(), Some("2023-01-13T09:00:00Z"));
+
+		// The CLI prints each event as one flat JSON object, including its stream ID.
+		let printed: serde_json::Value = serde_json::to_value(&events[1]).unwrap();
+		assert_eq!((printed["id"].as_str(), printed["event"].as_str()), (Some("1673600000002-0"), Some("cancelled")));
+
+		// Pruned jobs are identified by Job ID.
+		assert_eq!(crate::rq::job_id_from_key("rq:job:abc").unwrap(), "abc");
+		assert!(crate::rq::job_id_from_key("rq:job:abc:dependents").is_none());
+	}
+
+	/// Writes events to the live stream, and reads them back the way 'btu events' and 'btu events --follow' do.  Needs a
+	/// live Redis, configured by BTU_* environment variables: `cargo test -p btu_scheduler --features integration -- test_event_stream_live`
+	#[cfg(feature = "integration")]
+	#[test]
+	fn test_event_stream_live() {
+		use std::time::Duration;
+		use crate::events::{record_event, EventKind, SchedulerEvent, StreamedEvent};
+		use crate::ids::TaskScheduleId;
+		use crate::scheduler::{read_events, wait_for_events};
+
+		let mut app_config: AppConfig = AppConfig::new_from_env().expect("BTU_* environment variables describing a live installation");
+		app_config.event_stream_enabled = true;
+		let mut redis_conn = crate::rq::get_redis_connection(&app_config, false).unwrap();
+		// Other schedulers may write to the same stream; only this test's events are compared.
+		let schedule_id = TaskScheduleId::from(format!("btu_event_test_{}", uuid::Uuid::new_v4()));
+		let ours = |events: Vec<StreamedEvent>| -> Vec<StreamedEvent> {
+			events.into_iter().filter(|streamed| streamed.event.schedule_id.as_ref() == Some(&schedule_id)).collect()
+		};
+		let last_id: String = read_events(&app_config, 1, None).unwrap().last().map_or_else(|| "0-0".to_owned(), |streamed| streamed.id.clone());
+
+		record_event(&app_config, &mut redis_conn, SchedulerEvent::new(EventKind::Scheduled).schedule(&schedule_id).run_at(Utc::now()));
+		record_event(&app_config, &mut redis_conn, SchedulerEvent::new(EventKind::Cancelled).schedule(&schedule_id));
+
+		// Read after the last ID seen, as 'btu events --follow' does on each pass.
+		let followed: Vec<StreamedEvent> = ours(wait_for_events(&app_config, 100, &last_id, Duration::from_secs(1)).unwrap());
+		assert_eq!(followed.iter().map(|streamed| streamed.event.event).collect::<Vec<EventKind>>(), vec![EventKind::Scheduled, EventKind::Cancelled]);
+		assert_eq!(ours(read_events(&app_config, 100, Some(&last_id)).unwrap()), followed);
+		assert!(ours(wait_for_events(&app_config, 100, &followed[1].id, Duration::from_millis(200)).unwrap()).is_empty());
+
+		// A blocked XREAD from "$" wakes for an event written while it waits.
+		let writer_config: AppConfig = app_config.clone();
+		let writer_schedule_id: TaskScheduleId = schedule_id.clone();
+		let writer = std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(300));
+			let mut redis_conn = crate::rq::get_redis_connection(&writer_config, false).unwrap();
+			record_event(&writer_config, &mut redis_conn, SchedulerEvent::new(EventKind::Reset).schedule(&writer_schedule_id));
+		});
+		let mut woken: Vec<StreamedEvent> = Vec::new();
+		for _ in 0..10 {
+			woken = ours(wait_for_events(&app_config, 100, "$", Duration::from_secs(5)).unwrap());
+			if !woken.is_empty() {
+				break;
+			}
+		}
+		writer.join().unwrap();
+		assert_eq!(woken.iter().map(|streamed| streamed.event.event).collect::<Vec<EventKind>>(), vec![EventKind::Reset]);
+	}
+
+
+	#[test]
+	fn test_occurrences_within_polling_horizon() {
+		use chrono::TimeZone;
+		use crate::btu_cron::occurrences_within_polling_horizon;
+
+		let from: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 5).unwrap();
+		let every_15_seconds = tz_cron_to_utc_datetimes("0/15 * * * * * *", chrono_tz::UTC, Some(from), &120, DomDowSemantics::And).unwrap();
+		assert_eq!(every_15_seconds[0], Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 15).unwrap());
+
+		// Two 60-second polling intervals past the first run: 08:00:15 through 08:02:15.
+		let stored = occurrences_within_polling_horizon(&every_15_seconds, 60, 120);
+		assert_eq!(stored.len(), 9);
+		assert_eq!(stored.last(), Some(&Utc.with_ymd_and_hms(2022, 12, 2, 8, 2, 15).unwrap()));
+		assert_eq!(occurrences_within_polling_horizon(&every_15_seconds, 60, 5).len(), 5);
+
+		// Schedules no more frequent than the polling interval keep a single occurrence.
+		let every_minute = tz_cron_to_utc_datetimes("* * * * *", chrono_tz::UTC, Some(from), &3, DomDowSemantics::And).unwrap();
+		assert_eq!(occurrences_within_polling_horizon(&every_minute, 60, 120), vec![every_minute[0]]);
+		assert!(occurrences_within_polling_horizon(&[], 60, 120).is_empty());
+	}
+
+	/// A 15-second schedule against a simulated 60-second poll: every occurrence runs exactly once, four per cycle.
+	#[test]
+	fn test_high_frequency_schedule_simulated_polls() {
+		use std::collections::BTreeSet;
+		use chrono::TimeZone;
+		use crate::btu_cron::occurrences_within_polling_horizon;
+
+		let cron: &str = "0/15 * * * * * *";
+		let polling_interval: i64 = 60;
+		let stored_from = |now: DateTime<Utc>| -> Vec<i64> {
+			let occurrences = tz_cron_to_utc_datetimes(cron, chrono_tz::UTC, Some(now), &120, DomDowSemantics::And).unwrap();
+			occurrences_within_polling_horizon(&occurrences, polling_interval as u64, 120).iter().map(|runtime| runtime.timestamp()).collect()
+		};
+
+		let start: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 5).unwrap();
+		let mut sorted_set: BTreeSet<i64> = stored_from(start).into_iter().collect();  // Thread #1, initial add
+		let mut runs: Vec<i64> = Vec::new();
+		for cycle in 1..=10 {
+			let now: DateTime<Utc> = start + chrono::Duration::seconds(cycle * polling_interval);
+			let due: Vec<i64> = sorted_set.iter().copied().filter(|score| *score <= now.timestamp()).collect();
+			assert_eq!(due.len(), 4, "cycle {} enqueued {:?}", cycle, due);
+			for score in &due {
+				sorted_set.remove(score);
+			}
+			runs.extend(due);
+			sorted_set.extend(stored_from(now));  // each run re-queues the schedule; re-adding a TSIK is a no-op
+		}
+
+		let expected: Vec<i64> = (1..=40).map(|index| start.timestamp() + 10 + 15 * (index - 1)).collect();
+		assert_eq!(runs, expected);
+	}
+
+	#[test]
+	fn test_timed_query_threshold() {
+		use std::time::Duration;
+		use crate::metrics::{query_stats_snapshot, record_query, timed_query, QueryKind};
+
+		// Exactly at the threshold is not slow; beyond it is.
+		assert!(!record_query(QueryKind::Task, Duration::from_millis(2000), 1, Duration::from_millis(2000)));
+		assert!(record_query(QueryKind::Task, Duration::from_millis(2001), 1, Duration::from_millis(2000)));
+
+		let before = query_stats_snapshot()["full_refill"];
+		let slow: Result<Vec<u8>, String> = timed_query(QueryKind::FullRefill, Duration::from_millis(10), || {
+			std::thread::sleep(Duration::from_millis(30));
+			Ok(vec![1, 2, 3])
+		});
+		assert_eq!(slow, Ok(vec![1, 2, 3]));
+		let fast: Result<Option<u8>, String> = timed_query(QueryKind::FullRefill, Duration::from_secs(5), || Err("no database".to_owned()));
+		assert!(fast.is_err());  // failures are passed through, and still timed
+
+		let after = query_stats_snapshot()["full_refill"];
+		assert_eq!(after.count - before.count, 2);
+		assert_eq!(after.slow - before.slow, 1);
+		assert!(after.max_ms >= 30 && after.total_ms - before.total_ms >= 30);
+	}
+
+	// Captured responses from Frappe's 'get_pickled_task' endpoint.
+	static FRAPPE_SUCCESS_JSON: &str = r#"{"message": [128, 4, 149, 43, 0, 0, 0, 0, 0, 0, 0, 46]}"#;
+	static FRAPPE_MISSING_TASK_JSON: &str = r#"{"exc_type": "DoesNotExistError",
+		"exception": "frappe.exceptions.DoesNotExistError: BTU Task TASK-000999 not found\nTraceback (most recent call last):",
+		"exc": "[\"Traceback (most recent call last):\\n ...\"]",
+		"_server_messages": "[\"{\\\"message\\\": \\\"BTU Task TASK-000999 not found\\\"}\"]"}"#;
+	static FRAPPE_MESSAGES_ONLY_JSON: &str = r#"{"_server_messages": "[\"{\\\"message\\\": \\\"Not permitted\\\", \\\"indicator\\\": \\\"red\\\"}\"]"}"#;
+
+	#[test]
+	fn test_frappe_api_responses() {
+		use crate::errors::FrappeApiError;
+		use crate::parse_frappe_api_response;
+
+		assert_eq!(parse_frappe_api_response("TASK-000001", 200, FRAPPE_SUCCESS_JSON.as_bytes()),
+		           Ok(vec![128, 4, 149, 43, 0, 0, 0, 0, 0, 0, 0, 46]));
+
+		// An error body is an error, even with HTTP 200.
+		let expected = FrappeApiError::Exception {
+			task_id: "TASK-000999".to_owned(),
+			status: 200,
+			exc_type: "DoesNotExistError".to_owned(),
+			exception: "frappe.exceptions.DoesNotExistError: BTU Task TASK-000999 not found".to_owned(),
+		};
+		assert_eq!(parse_frappe_api_response("TASK-000999", 200, FRAPPE_MISSING_TASK_JSON.as_bytes()), Err(expected));
+		let error = parse_frappe_api_response("TASK-000999", 404, FRAPPE_MISSING_TASK_JSON.as_bytes()).unwrap_err();
+		assert!(error.to_string().contains("DoesNotExistError while fetching BTU Task 'TASK-000999' (HTTP 404)"), "{}", error);
+
+		let error = parse_frappe_api_response("TASK-000002", 403, FRAPPE_MESSAGES_ONLY_JSON.as_bytes()).unwrap_err();
+		assert_eq!(error.to_string(), "Unexpected response from Frappe while fetching BTU Task 'TASK-000002' (HTTP 403): Not permitted");
+
+		// A successful shape with an error status, and bodies that are not JSON, are not trusted.
+		assert!(parse_frappe_api_response("TASK-000001", 500, FRAPPE_SUCCESS_JSON.as_bytes()).is_err());
+		let error = parse_frappe_api_response("TASK-000001", 502, b"<html>Bad Gateway</html>").unwrap_err();
+		assert!(error.to_string().contains("the body is not JSON"), "{}", error);
+	}
+
+	#[test]
+	fn test_platform_parsers() {
+		use crate::platform::{container_runtime_from_cgroup, parse_meminfo_total_kb, parse_os_release_name, PlatformInfo};
+
+		let debian = "PRETTY_NAME=\"Debian GNU/Linux 11 (bullseye)\"\nNAME=\"Debian GNU/Linux\"\nVERSION_ID=\"11\"\nID=debian\n";
+		assert_eq!(parse_os_release_name(debian).as_deref(), Some("Debian GNU/Linux 11 (bullseye)"));
+		assert_eq!(parse_os_release_name("NAME=Alpine\nID=alpine\n").as_deref(), Some("Alpine"));
+		assert_eq!(parse_os_release_name("ID=unknown\n"), None);
+
+		let meminfo = "MemTotal:        8038112 kB\nMemFree:          341212 kB\nMemAvailable:    5125304 kB\n";
+		assert_eq!(parse_meminfo_total_kb(meminfo), Some(8038112));
+		assert_eq!(parse_meminfo_total_kb("MemTotal: lots\n"), None);
+		assert_eq!(parse_meminfo_total_kb(""), None);
+
+		let docker_v1 = "12:pids:/docker/3f4e9b1c2d\n11:memory:/docker/3f4e9b1c2d\n";
+		assert_eq!(container_runtime_from_cgroup(docker_v1).as_deref(), Some("docker"));
+		assert_eq!(container_runtime_from_cgroup("0::/kubepods/besteffort/pod1234\n").as_deref(), Some("kubernetes"));
+		assert_eq!(container_runtime_from_cgroup("0::/init.scope\n"), None);  // systemd on a host
+
+		let platform = PlatformInfo {
+			os_release: Some("Debian GNU/Linux 11 (bullseye)".to_owned()),
+			libc: "glibc".to_owned(),
+			under_systemd: true,
+			in_container: Some(true),
+			container_runtime: Some("docker".to_owned()),
+			total_memory_kb: Some(8038112),
+		};
+		assert_eq!(platform.to_string(), "Debian GNU/Linux 11 (bullseye), glibc, under systemd, in a docker container, 7849 MiB memory");
+		PlatformInfo::detect();  // probes never panic, whatever this machine is
+	}
+
+	#[test]
+	fn test_old_config_formats_deserialize() {
+		use tracing_subscriber::filter::LevelFilter;
+
+		let v1 = AppConfig::new_from_toml_string(CONFIG_V1_TOML).unwrap();
+		assert_eq!(v1.tracing_level.0, LevelFilter::INFO);
+		assert!(!v1.startup_without_database_connections);
+		assert!(!v1.email_when_queuing);
+		assert_eq!(v1.config_version, None);
+
+		let v2 = AppConfig::new_from_toml_string(CONFIG_V2_TOML).unwrap();
+		assert_eq!(v2.tracing_level.0, LevelFilter::DEBUG);
+		assert!(v2.startup_without_database_connections);
+		assert_eq!(v2.webserver_host_header.as_deref(), Some("erp.example.com"));
+	}
+
+	#[test]
+	fn test_config_upgrade() {
+		use crate::config::{upgrade_config_toml, CONFIG_VERSION};
+
+		let upgrade = upgrade_config_toml(CONFIG_V1_TOML).unwrap();
+		assert_eq!(upgrade.from_version, None);
+		let added: Vec<&str> = upgrade.added.iter().map(|(name, _)| name.as_str()).collect();
+		for name in ["config_version", "tracing_level", "startup_without_database_connections", "email_when_queuing"] {
+			assert!(added.contains(&name), "{} was not reported as added: {:?}", name, added);
+		}
+		assert!(!added.contains(&"mysql_user"));
+		assert_eq!(upgrade.unrecognized, vec!["name".to_owned()]);
+		assert!(upgrade.to_string().contains("  + tracing_level = \"INFO\""), "{}", upgrade);
+
+		// The upgraded file loads strictly, and upgrading it again changes nothing.
+		let upgraded_toml: String = upgrade.to_toml_string().unwrap();
+		let upgraded = AppConfig::new_from_toml_string(&upgraded_toml).unwrap();
+		assert_eq!(upgraded.config_version, Some(CONFIG_VERSION));
+		assert_eq!(upgraded.webserver_token, "token abc:def");
+		let again = upgrade_config_toml(&upgraded_toml).unwrap();
+		assert_eq!(again.from_version, Some(CONFIG_VERSION));
+		assert!(again.added.is_empty() && again.unrecognized.is_empty());
+
+		// Settings without a sensible default are reported, not invented.
+		let without_token: String = CONFIG_V2_TOML.replace("webserver_token = \"token abc:def\"", "");
+		let error_message: String = upgrade_config_toml(&without_token).err().expect("expected an error").to_string();
+		assert_eq!(error_message, "The configuration is missing required settings: webserver_token");
+	}
+
+	#[test]
+	fn test_describe_cron() {
+		use crate::btu_cron::describe_cron;
+		use crate::errors::CronError;
+
+		let cases: [(&str, &str); 32] = [
+			// Wildcards and steps in the time of day.
+			("* * * * *", "every minute"),
+			("*/15 * * * *", "every 15 minutes"),
+			("*/1 * * * *", "every minute"),
+			("5/20 * * * *", "every 20 minutes starting at 5"),
+			("0 * * * *", "every hour at :00"),
+			("15,45 * * * *", "every hour at :15 and :45"),
+			("0 */2 * * *", "every 2 hours at :00"),
+			("0 8-18/2 * * *", "every 2 hours from 8 through 18 at :00"),
+			("5-10 * * * *", "at minutes 5 through 10"),
+			("*/15 9-17 * * *", "every 15 minutes, during hours 9 through 17"),
+			("0,15,30,45 9,10 * * *", "at :00, :15, :30, and :45 during hours 9 and 10"),
+			// Specific times, including seconds from a 7-element expression.
+			("30 9 * * *", "every day at 09:30"),
+			("0 9,17 * * *", "every day at 09:00 and 17:00"),
+			("15 30 9 * * * *", "every day at 09:30:15"),
+			("*/10 * * * * * *", "every 10 seconds"),
+			// Days of the week: numbers (Sunday is 1), names, ranges, and lists.
+			("30 9 * * Mon-Fri", "every weekday at 09:30"),
+			("0 12 * * 2,3,4,5,6", "every weekday at 12:00"),
+			("0 22 * * 1", "every Sunday at 22:00"),
+			("32 3 * * Sun-Wed,Sat", "every Sunday through Wednesday and Saturday at 03:32"),
+			("*/15 9-17 * * Mon-Fri", "every 15 minutes, during hours 9 through 17, every weekday"),
+			// Days of the month, months, and years.
+			("0 0 1 * *", "on the 1st of every month at 00:00"),
+			("0 0 1,2,3,11,22 * *", "on the 1st, 2nd, 3rd, 11th, and 22nd of every month at 00:00"),
+			("0 0 1-10 * *", "on the 1st through 10th of every month at 00:00"),
+			("0 0 */2 * *", "every 2nd day of every month at 00:00"),
+			("0 0 1 1 *", "on the 1st of January at 00:00"),
+			("0 0 1 */3 *", "on the 1st of every 3rd month at 00:00"),
+			("0 12 * Jan,Jul *", "every day in January and July at 12:00"),
+			("30,45 14 ? 1-5 Monday", "every Monday in January through May at 14:30 and 14:45"),
+			("30,45 14 ? 1-5 Monday 2021", "every Monday in January through May at 14:30 and 14:45, in 2021"),
+			("0 0 0 29 2 * 2024-2028/4", "on the 29th of February at 00:00, in every 4th year from 2024 through 2028"),
+			// The 'cron' crate requires both the day of the month and the day of the week to match.
+			("0 0 13 * Fri", "on the 13th of every month at 00:00, if it falls on a Friday"),
+			// Too complex to describe: the field is echoed.
+			("0 0 9 * * Mon-Fri/2 *", "on day of the week 'Mon-Fri/2' at 09:00"),
+		];
+		for (cron, expected) in cases {
+			assert_eq!(describe_cron(cron).as_deref(), Ok(expected), "cron '{}'", cron);
+		}
+
+		assert_eq!(describe_cron("1 2 3"), Err(CronError::WrongQtyOfElements { found: 3 }));
+		assert_eq!(describe_cron("0 25 * * *"), Err(CronError::InvalidExpression));
+		assert_eq!(describe_cron("0 9 * * Funday"), Err(CronError::InvalidExpression));
+
+		// Task Schedules add their time zone, and may have a repeat interval instead.
+		use crate::btu_cron::describe_schedule;
+		assert_eq!(describe_schedule("30 9 * * Mon-Fri", "America/New_York", DomDowSemantics::And), "every weekday at 09:30 America/New_York");
+		assert_eq!(describe_schedule("PT90M", "America/New_York", DomDowSemantics::And), "every 90 minutes");
+		assert_eq!(describe_schedule("0 25 * * *", "UTC", DomDowSemantics::And), "'0 25 * * *' UTC");
+	}
+
+	#[test]
+	fn test_classify_cron() {
+		use crate::btu_cron::classify_cron;
+
+		// (expression, elements, normalized 7-element form, seconds, year)
+		let cases = [
+			("0 9 * * Mon-Fri", 5, "0 0 9 * * Mon-Fri *", None, None),
+			("  0   9 * * *  ", 5, "0 0 9 * * * *", None, None),
+			("0 9 * * * *", 6, "0 0 9 * * * *", None, None),
+			("0 9 1 1 * 2030", 6, "0 0 9 1 1 * 2030", None, Some("2030")),
+			("0 0 9 * * * *", 7, "0 0 9 * * * *", None, None),
+			("30 0 9 * * * *", 7, "30 0 9 * * * *", Some("30"), None),
+			("*/15 * * * * * *", 7, "*/15 * * * * * *", Some("*/15"), None),
+			("* 0 9 * * * *", 7, "* 0 9 * * * *", Some("*"), None),
+			("15 0 9 * * * 2030-2031", 7, "15 0 9 * * * 2030-2031", Some("15"), Some("2030-2031")),
+		];
+		for (expression, elements, cron7, seconds, year) in cases {
+			let classification = classify_cron(expression).unwrap();
+			assert_eq!(classification.elements, elements, "{}", expression);
+			assert_eq!(classification.cron7, cron7, "{}", expression);
+			assert_eq!(classification.seconds.as_deref(), seconds, "{}", expression);
+			assert_eq!(classification.year.as_deref(), year, "{}", expression);
+			assert_eq!(classification.frappe_compatible, seconds.is_none() && year.is_none(), "{}", expression);
+			assert_eq!(classification.notice().is_some(), !classification.frappe_compatible, "{}", expression);
+		}
+
+		assert_eq!(classify_cron("15 0 9 * * * 2030").unwrap().notice().unwrap(),
+		           "Cron has 7 elements ('15 0 9 * * * 2030'): its seconds '15' and year '2030' are honored by the scheduler, \
+		            but invisible in Frappe's 5-element cron field.");
+		assert_eq!(classify_cron("0 9 * * * 2030").unwrap().notice().unwrap(),
+		           "Cron has 6 elements ('0 0 9 * * * 2030'): its year '2030' is honored by the scheduler, but invisible in Frappe's 5-element cron field.");
+		assert_eq!(classify_cron("PT2H"), Err(crate::errors::CronError::WrongQtyOfElements { found: 1 }));
+		assert_eq!(classify_cron("1 2 3 4 5 6 7 8"), Err(crate::errors::CronError::WrongQtyOfElements { found: 8 }));
+	}
+
+	#[test]
+	fn test_dom_dow_semantics() {
+		use chrono::TimeZone;
+		use crate::btu_cron::{describe_cron_with_semantics, dom_dow_notice, restricts_day_of_month_and_week};
+
+		assert!(restricts_day_of_month_and_week("0 9 13 * Fri"));
+		assert!(!restricts_day_of_month_and_week("0 9 13 * *"));
+		assert!(!restricts_day_of_month_and_week("0 9 ? * Fri"));
+		assert!(!restricts_day_of_month_and_week("0 9 * * Fri"));
+
+		let from: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+		let dates = |cron: &str, semantics: DomDowSemantics| -> Vec<String> {
+			tz_cron_to_utc_datetimes(cron, chrono_tz::UTC, Some(from), &5, semantics).unwrap()
+				.iter().map(|runtime| runtime.format("%Y-%m-%d %H:%M").to_string()).collect()
+		};
+		// "and": only Friday the 13th.
+		assert_eq!(dates("0 9 13 * Fri", DomDowSemantics::And),
+		           vec!["2023-01-13 09:00", "2023-10-13 09:00", "2024-09-13 09:00", "2024-12-13 09:00", "2025-06-13 09:00"]);
+		// "or": every 13th, and every Friday.  Friday, January 13th appears once.
+		assert_eq!(dates("0 9 13 * Fri", DomDowSemantics::Or),
+		           vec!["2023-01-06 09:00", "2023-01-13 09:00", "2023-01-20 09:00", "2023-01-27 09:00", "2023-02-03 09:00"]);
+		assert_eq!(dates("0 9 13 * 6", DomDowSemantics::Or), dates("0 9 13 * Fri", DomDowSemantics::Or));  // Friday is day 6
+		// Only one day restricted: both semantics agree.
+		assert_eq!(dates("0 9 13 * *", DomDowSemantics::Or), dates("0 9 13 * *", DomDowSemantics::And));
+
+		assert_eq!(describe_cron_with_semantics("0 9 13 * Fri", DomDowSemantics::Or).unwrap(),
+		           "on the 13th of every month and every Friday at 09:00");
+
+		let upcoming: Vec<DateTime<chrono_tz::Tz>> = tz_cron_to_utc_datetimes("0 9 13 * Fri", chrono_tz::UTC, Some(from), &1, DomDowSemantics::And)
+			.unwrap().iter().map(|runtime| runtime.with_timezone(&chrono_tz::UTC)).collect();
+		assert_eq!(dom_dow_notice("0 9 13 * Fri", DomDowSemantics::And, &upcoming),
+		           "Cron '0 9 13 * Fri' restricts both the day of the month and the day of the week.  BTU runs it only when BOTH match \
+		            (Vixie cron runs when EITHER matches), because cron_dom_dow_semantics = \"and\".  Next 1 runs: Fri 2023-01-13 09:00 UTC");
+
+		// The setting is read from the configuration, and defaults to "and".
+		assert_eq!(AppConfig::new_from_toml_string(CONFIG_V2_TOML).unwrap().cron_dom_dow_semantics, DomDowSemantics::And);
+		let or_toml: String = CONFIG_V2_TOML.replace("tracing_level", "cron_dom_dow_semantics = \"or\"\ntracing_level");
+		assert_eq!(AppConfig::new_from_toml_string(&or_toml).unwrap().cron_dom_dow_semantics, DomDowSemantics::Or);
+		assert!(AppConfig::new_from_toml_string(&CONFIG_V2_TOML.replace("tracing_level", "cron_dom_dow_semantics = \"xor\"\ntracing_level")).is_err());
+	}
+
+	#[test]
+	fn test_config_reload() {
+		use crate::config::ConfigError;
+
+		let running: AppConfig = sample_app_config();
+		assert!(running.validate_settings().is_ok());
+		assert!(matches!(running.read_again(), Err(ConfigError::NotReloadable { .. })));
+
+		// New intervals and levels take effect; the socket keeps its running path until a restart.
+		let edited: String = SAMPLE_TOML.replace("scheduler_polling_interval = 60", "scheduler_polling_interval = 30")
+			.replace("tracing_level = \"INFO\"", "tracing_level = \"DEBUG\"")
+			.replace("btu_scheduler_test.sock", "btu_scheduler_moved.sock");
+		let reload = running.prepare_reload(AppConfig::new_from_toml_string(&edited).unwrap()).unwrap();
+		assert_eq!(reload.changed, vec!["scheduler_polling_interval", "tracing_level"]);
+		assert_eq!(reload.restart_required, vec!["socket_path"]);
+		assert_eq!(reload.app_config.scheduler_polling_interval, 30);
+		assert_eq!(reload.app_config.tracing_level.get_level(), tracing_subscriber::filter::LevelFilter::DEBUG);
+		assert_eq!(reload.app_config.socket_path, "/tmp/btu_scheduler_test.sock");
+		assert!(running.prepare_reload(sample_app_config()).unwrap().changed.is_empty());
+
+		// An invalid configuration is rejected, naming every problem.
+		let invalid: String = SAMPLE_TOML.replace("America/Los_Angeles", "Mars/Olympus_Mons")
+			.replace("rq_port = 11000", "rq_port = 70000")
+			.replace("scheduler_polling_interval = 60", "scheduler_polling_interval = 0");
+		let error = running.prepare_reload(AppConfig::new_from_toml_string(&invalid).unwrap()).err().unwrap();
+		let message: String = error.to_string();
+		for setting in ["time_zone_string", "rq_port", "scheduler_polling_interval"] {
+			assert!(message.contains(setting), "{}", message);
+		}
+
+		// A configuration read from a file is read again from the same file.
+		let path = std::env::temp_dir().join(format!("btu_config_reload_{}.toml", std::process::id()));
+		std::fs::write(&path, SAMPLE_TOML).unwrap();
+		let from_file: AppConfig = AppConfig::new_from_toml_file(path.to_str()).unwrap();
+		std::fs::write(&path, &edited).unwrap();
+		let reread: AppConfig = from_file.read_again().unwrap();
+		assert_eq!(reread.scheduler_polling_interval, 30);
+		assert_eq!(reread.source(), from_file.source());
+		std::fs::remove_file(&path).unwrap();
+		assert!(matches!(from_file.read_again(), Err(ConfigError::ReadFile { .. })));
+	}
+
+	#[test]
+	fn test_cron_strings_are_utc() {
+		use std::str::FromStr;
+		use chrono::TimeZone;
+		use crate::btu_cron::{cron_timezone_label, describe_schedule, effective_cron_timezone};
+
+		let from: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 3, 10, 8, 0, 0).unwrap();  // three days before US Daylight Saving Time begins
+		let pacific: chrono_tz::Tz = chrono_tz::America::Los_Angeles;
+
+		// In UTC-only mode, the stored time zone is replaced, and the results are exactly what the 'cron' crate produces.
+		let timezone: chrono_tz::Tz = effective_cron_timezone(pacific, true);
+		assert_eq!(timezone, chrono_tz::UTC);
+		for cron in ["30 9 * * Mon-Fri", "0 */4 * * *", "15 2 * * *", "*/20 * * * *"] {
+			let cron7: String = cron_str_to_cron_str7(cron).unwrap();
+			let raw: Vec<DateTime<Utc>> = cron::Schedule::from_str(&cron7).unwrap().after(&from).take(6).collect();
+			assert_eq!(tz_cron_to_utc_datetimes(cron, timezone, Some(from), &6, DomDowSemantics::And).unwrap(), raw, "cron '{}'", cron);
+		}
+
+		// Otherwise, nothing changes: 09:30 Pacific is 17:30 UTC before Daylight Saving Time, and 16:30 UTC after.
+		assert_eq!(effective_cron_timezone(pacific, false), pacific);
+		let pacific_runs: Vec<String> = tz_cron_to_utc_datetimes("30 9 * * Mon-Fri", pacific, Some(from), &2, DomDowSemantics::And).unwrap()
+			.iter().map(|runtime| runtime.format("%Y-%m-%d %H:%M").to_string()).collect();
+		assert_eq!(pacific_runs, vec!["2023-03-10 17:30", "2023-03-13 16:30"]);
+
+		// Descriptions say the schedule is UTC-only, instead of naming the stored time zone.
+		assert_eq!(describe_schedule("30 9 * * Mon-Fri", cron_timezone_label("America/Los_Angeles", true), DomDowSemantics::And),
+		           "every weekday at 09:30 UTC (cron_strings_are_utc)");
+		assert_eq!(cron_timezone_label("America/Los_Angeles", false), "America/Los_Angeles");
+
+		// The setting is read from the configuration, and defaults to false.
+		assert!(!AppConfig::new_from_toml_string(CONFIG_V2_TOML).unwrap().cron_strings_are_utc);
+		let utc_toml: String = CONFIG_V2_TOML.replace("tracing_level", "cron_strings_are_utc = true\ntracing_level");
+		assert!(AppConfig::new_from_toml_string(&utc_toml).unwrap().cron_strings_are_utc);
+	}
+
+	#[test]
+	fn test_cron_daylight_saving_time_los_angeles() {
+		use chrono::TimeZone;
+
+		// In 2023, Los Angeles sprang forward on March 12 (02:00 became 03:00), and fell back on November 5 (02:00 became 01:00).
+		let pacific: chrono_tz::Tz = chrono_tz::America::Los_Angeles;
+		let runs = |cron: &str, from: DateTime<Utc>, count: usize| -> Vec<String> {
+			tz_cron_to_utc_datetimes(cron, pacific, Some(from), &count, DomDowSemantics::And).unwrap()
+				.iter().map(|runtime| runtime.format("%Y-%m-%d %H:%M").to_string()).collect()
+		};
+
+		// 07:00 Pacific is 15:00 UTC before the March change, and 14:00 UTC after.
+		let march: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 3, 11, 0, 0, 0).unwrap();
+		assert_eq!(runs("0 7 * * *", march, 3), vec!["2023-03-11 15:00", "2023-03-12 14:00", "2023-03-13 14:00"]);
+
+		// 02:30 Pacific does not exist on March 12, so that day is skipped.
+		assert_eq!(runs("30 2 * * *", march, 3), vec!["2023-03-11 10:30", "2023-03-13 09:30", "2023-03-14 09:30"]);
+
+		// 07:00 Pacific is 14:00 UTC before the November change, and 15:00 UTC after.
+		let november: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 11, 4, 0, 0, 0).unwrap();
+		assert_eq!(runs("0 7 * * *", november, 3), vec!["2023-11-04 14:00", "2023-11-05 15:00", "2023-11-06 15:00"]);
+
+		// 01:30 Pacific happens twice on November 5; it runs once, at the first (08:30 UTC, not 09:30 UTC).
+		assert_eq!(runs("30 1 * * *", november, 3), vec!["2023-11-04 08:30", "2023-11-05 08:30", "2023-11-06 09:30"]);
+
+		// Starting between the two 01:30's, the second one does not run.
+		let between: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 11, 5, 9, 0, 0).unwrap();
+		assert_eq!(runs("30 1 * * *", between, 1), vec!["2023-11-06 09:30"]);
+
+		// The starting point is a UTC instant: at 06:00 UTC on March 11 it is still March 10 in Los Angeles.
+		let late_evening: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 3, 11, 6, 0, 0).unwrap();
+		assert_eq!(runs("0 23 * * *", late_evening, 3), vec!["2023-03-11 07:00", "2023-03-12 07:00", "2023-03-13 06:00"]);
+	}
+
+	/*
+		Cron regression corpus
+
+		'testdata/cron_corpus.toml' lists cron expressions, each with a time zone and a pinned starting moment, and the next
+		3 UTC occurrences that tz_cron_to_utc_datetimes() returned when the file was last generated (or the error it returned).
+		The cases concentrate on Daylight Saving Time changes, half-hour offsets, years, named days, and day numbering.
+
+		The expected values record behavior, not correctness: a 'note' marks cases known to be unusual.  When the algorithm
+		changes on purpose, regenerate the file, and review the changes it prints (and 'git diff') before committing:
+
+			BTU_REGENERATE_CRON_CORPUS=1 cargo test -p btu_scheduler test_cron_corpus -- --nocapture
+	*/
+
+	static CRON_CORPUS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/cron_corpus.toml");
+	static CRON_CORPUS_OCCURRENCES: usize = 3;
+	static CRON_CORPUS_HEADER: &str = "\
+# Cron regression corpus for tz_cron_to_utc_datetimes(); read by 'test_cron_corpus' in src/tests.rs.
+# Add or edit cases freely.  'expected' (or 'error') is what the algorithm returned when this file was last regenerated:
+#     BTU_REGENERATE_CRON_CORPUS=1 cargo test -p btu_scheduler test_cron_corpus -- --nocapture
+
+";
+
+	#[derive(serde::Deserialize)]
+	struct CronCorpus {
+		case: Vec<CronCase>,
+	}
+
+	#[derive(Clone, serde::Deserialize)]
+	struct CronCase {
+		name: String,
+		cron: String,
+		timezone: String,
+		from_utc: String,  // RFC 3339
+		#[serde(default)]
+		semantics: DomDowSemantics,
+		note: Option<String>,
+		#[serde(default)]
+		expected: Vec<String>,  // RFC 3339, UTC
+		error: Option<String>,  // instead of 'expected', when the expression cannot be converted
+	}
+
+	impl CronCase {
+		fn expected_outcome(&self) -> Result<Vec<String>, String> {
+			match &self.error {
+				Some(error) => Err(error.clone()),
+				None => Ok(self.expected.clone())
+			}
+		}
+
+		/// Runs the case.  A panic is an outcome too, so that one failing case cannot hide the others.
+		fn actual_outcome(&self) -> Result<Vec<String>, String> {
+			use chrono::SecondsFormat;
+
+			let timezone: chrono_tz::Tz = self.timezone.parse().unwrap_or_else(|error| panic!("Case '{}': {}", self.name, error));
+			let from_utc: DateTime<Utc> = DateTime::parse_from_rfc3339(&self.from_utc)
+				.unwrap_or_else(|error| panic!("Case '{}': 'from_utc' {}", self.name, error))
+				.with_timezone(&Utc);
+			let outcome = std::panic::catch_unwind(|| {
+				tz_cron_to_utc_datetimes(&self.cron, timezone, Some(from_utc), &CRON_CORPUS_OCCURRENCES, self.semantics)
+			});
+			match outcome {
+				Ok(Ok(occurrences)) => Ok(occurrences.iter().map(|each| each.to_rfc3339_opts(SecondsFormat::Secs, true)).collect()),
+				Ok(Err(error)) => Err(error.to_string()),
+				Err(panic) => {
+					let message: &str = panic.downcast_ref::<&str>().copied()
+						.or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+						.unwrap_or("(no message)");
+					Err(format!("panic: {}", message))
+				}
+			}
+		}
+
+		/// The case as TOML, with an outcome in place of the one it was read with.
+		fn to_toml(&self, outcome: &Result<Vec<String>, String>) -> String {
+			let quoted = |text: &str| toml::Value::String(text.to_owned()).to_string();
+			let mut lines: Vec<String> = vec![
+				"[[case]]".to_owned(),
+				format!("name = {}", quoted(&self.name)),
+				format!("cron = {}", quoted(&self.cron)),
+				format!("timezone = {}", quoted(&self.timezone)),
+				format!("from_utc = {}", quoted(&self.from_utc)),
+			];
+			if self.semantics != DomDowSemantics::And {
+				lines.push(format!("semantics = {}", quoted(&self.semantics.to_string())));
+			}
+			if let Some(note) = &self.note {
+				lines.push(format!("note = {}", quoted(note)));
+			}
+			match outcome {
+				Ok(occurrences) => lines.push(format!("expected = [{}]", occurrences.iter().map(|each| quoted(each)).collect::<Vec<String>>().join(", "))),
+				Err(error) => lines.push(format!("error = {}", quoted(error))),
+			}
+			lines.join("\n") + "\n"
+		}
+	}
+
+	fn describe_outcome(outcome: &Result<Vec<String>, String>) -> String {
+		match outcome {
+			Ok(occurrences) if occurrences.is_empty() => "(no occurrences)".to_owned(),
+			Ok(occurrences) => occurrences.join(", "),
+			Err(error) => format!("error: {}", error),
+		}
+	}
+
+	/// Every case in the corpus must produce its recorded outcome.  All differences are reported together.
+	#[test]
+	fn test_cron_corpus() {
+		let contents: String = std::fs::read_to_string(CRON_CORPUS_PATH).unwrap();
+		let corpus: CronCorpus = toml::from_str(&contents).unwrap();
+		assert!(corpus.case.len() >= 50, "The corpus has only {} cases.", corpus.case.len());
+
+		let mut differences: Vec<String> = Vec::new();
+		let mut regenerated: Vec<String> = Vec::new();
+		for case in &corpus.case {
+			let actual: Result<Vec<String>, String> = case.actual_outcome();
+			let expected: Result<Vec<String>, String> = case.expected_outcome();
+			if actual != expected {
+				differences.push(format!("'{}' ({} in {}, from {})\n      expected: {}\n        actual: {}",
+				                         case.name, case.cron, case.timezone, case.from_utc, describe_outcome(&expected), describe_outcome(&actual)));
+			}
+			regenerated.push(case.to_toml(&actual));
+		}
+
+		if std::env::var_os("BTU_REGENERATE_CRON_CORPUS").is_some() {
+			std::fs::write(CRON_CORPUS_PATH, String::from(CRON_CORPUS_HEADER) + &regenerated.join("\n")).unwrap();
+			println!("Regenerated '{}'; {} of {} cases changed:\n\n{}", CRON_CORPUS_PATH, differences.len(), corpus.case.len(), differences.join("\n"));
+			return;
+		}
+		assert!(differences.is_empty(), "{} of {} cron corpus cases differ (regenerate with BTU_REGENERATE_CRON_CORPUS=1 if this is intended):\n{}",
+		        differences.len(), corpus.case.len(), differences.join("\n"));
+	}
+
+	fn sample_environment() -> Vec<(String, String)> {
+		[
+			("BTU_FULL_REFRESH_INTERNAL_SECS", "900"),
+			("BTU_TIME_ZONE_STRING", "America/Los_Angeles"),
+			("BTU_TRACING_LEVEL", "DEBUG"),
+			("BTU_MYSQL_USER", "root"),
+			("BTU_MYSQL_PASSWORD", "password"),
+			("BTU_MYSQL_HOST", "localhost"),
+			("BTU_MYSQL_DATABASE", "erpnext_db"),
+			("BTU_RQ_HOST", "127.0.0.1"),
+			("BTU_RQ_PORT", "11000"),
+			("BTU_SCHEDULER_POLLING_INTERVAL", "60"),
+			("BTU_SOCKET_PATH", "/tmp/btu_scheduler.sock"),
+			("BTU_SOCKET_FILE_GROUP_OWNER", "erpnext_group"),
+			("BTU_WEBSERVER_IP", "127.0.0.1"),
+			("BTU_WEBSERVER_PORT", "8000"),
+			("BTU_WEBSERVER_TOKEN", "token abc:def"),
+			("PATH", "/usr/bin"),
+		].iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+	}
+
+	#[test]
+	fn test_config_from_environment() {
+		use tracing_subscriber::filter::LevelFilter;
+
+		let mut environment = sample_environment();
+		environment.push(("BTU_STARTUP_WITHOUT_DATABASE_CONNECTIONS".to_owned(), "yes".to_owned()));
+		environment.push(("BTU_INCLUDE_QUEUES".to_owned(), "stg_*, stg_slow,".to_owned()));
+		environment.push(("BTU_WEBSERVER_HOST_HEADER".to_owned(), "123".to_owned()));  // text, even when it looks like a number
+		let app_config = AppConfig::new_from_env_vars(environment).unwrap();
+		assert_eq!(app_config.tracing_level.0, LevelFilter::DEBUG);
+		assert!(app_config.startup_without_database_connections);
+		assert_eq!(app_config.include_queues, Some(vec!["stg_*".to_owned(), "stg_slow".to_owned()]));
+		assert_eq!(app_config.webserver_port, 8000);
+		assert_eq!(app_config.webserver_host_header.as_deref(), Some("123"));
+		assert!(app_config.sites.is_none());
+
+		// Every missing and invalid variable is reported at once.
+		let mut environment: Vec<(String, String)> = sample_environment().into_iter()
+			.filter(|(name, _)| name != "BTU_MYSQL_USER" && name != "BTU_WEBSERVER_TOKEN")
+			.map(|(name, value)| match name.as_str() {
+				"BTU_RQ_PORT" => (name, "eleven thousand".to_owned()),
+				"BTU_WEBSERVER_PORT" => (name, "80000".to_owned()),
+				_ => (name, value)
+			})
+			.collect();
+		environment.push(("BTU_EMAIL_WHEN_QUEUING".to_owned(), "maybe".to_owned()));
+		let error_message: String = AppConfig::new_from_env_vars(environment).err().expect("expected an error").to_string();
+		assert!(error_message.contains("Missing: BTU_MYSQL_USER, BTU_WEBSERVER_TOKEN"), "{}", error_message);
+		assert!(error_message.contains("Invalid: BTU_RQ_PORT: expected a whole number, found 'eleven thousand'"), "{}", error_message);
+		assert!(error_message.contains("Invalid: BTU_EMAIL_WHEN_QUEUING: expected true or false, found 'maybe'"), "{}", error_message);
+		assert!(error_message.contains("Invalid: BTU_WEBSERVER_PORT: "), "{}", error_message);
+		assert_eq!(error_message.matches("Invalid: ").count(), 3, "{}", error_message);
+	}
+
+	/// Secrets must never reach the 'get_config' socket reply, nor 'btu print-config'; including those of each [[site]].
+	#[test]
+	fn test_config_secrets_are_redacted() {
+		use crate::config::{ConfigSource, REDACTED};
+
+		let secrets: [&str; 7] = ["s3cret-mysql", "token top:secret", "s3cret-smtp", "https://k3y@o0.ingest.sentry.io/0",
+		                          "s3cret-hr-mysql", "token hr:secret", "s3cret-redis"];
+		let toml: String = SAMPLE_TOML
+			.replace("mysql_password = \"password\"", &format!("mysql_password = \"{}\"", secrets[0]))
+			.replace("webserver_token = \"token abc:def\"", &format!("webserver_token = \"{}\"", secrets[1]))
+			+ &format!("email_account_password = \"{}\"\nsentry_dsn = \"{}\"\nrq_password = \"{}\"\n", secrets[2], secrets[3], secrets[6])
+			+ &format!("[[site]]\nname = \"hr\"\nmysql_user = \"hr\"\nmysql_password = \"{}\"\nmysql_database = \"hr_db\"\nwebserver_token = \"{}\"\n",
+			           secrets[4], secrets[5]);
+		let app_config = AppConfig::new_from_toml_string(&toml).unwrap();
+
+		let json: serde_json::Value = app_config.redacted_json();
+		let displayed: String = app_config.to_string();
+		for secret in secrets {
+			assert!(!json.to_string().contains(secret), "'{}' leaked into: {}", secret, json);
+			assert!(!displayed.contains(secret), "'{}' leaked into: {}", secret, displayed);
+		}
+		assert_eq!(json["mysql_password"], REDACTED);
+		assert_eq!(json["sentry_dsn"], REDACTED);
+		assert_eq!(json["site"][0]["webserver_token"], REDACTED);
+		assert_eq!(json["rq_port"], 11000);  // everything else is unchanged
+		assert_eq!(json["mysql_user"], "root");
+		assert!(json["email_address_from"].is_null());  // absent settings stay absent
+		assert_eq!(app_config.source(), &ConfigSource::Text);
+
+		// Settings read from the environment are listed by variable name.
+		let app_config = AppConfig::new_from_env_vars(sample_environment()).unwrap();
+		let ConfigSource::Environment(variables) = app_config.source() else { panic!("expected the environment") };
+		assert_eq!(variables.len(), 15);
+		assert!(variables.contains(&"BTU_WEBSERVER_TOKEN".to_owned()) && !variables.contains(&"PATH".to_owned()));
+		assert_eq!(app_config.redacted_json()["webserver_token"], REDACTED);
+	}
+
+	#[test]
+	fn test_redis_password_and_database() {
+		use crate::rq::{describe_connection_error, redis_connection_info};
+
+		// Without either setting: database 0, and no AUTH.
+		let mut app_config = sample_app_config();
+		let info: redis::ConnectionInfo = redis_connection_info(&app_config);
+		assert_eq!(info.addr, redis::ConnectionAddr::Tcp("127.0.0.1".to_owned(), 11000));
+		assert_eq!((info.redis.db, info.redis.password), (0, None));
+
+		// The same as "redis://:password@host:port/db", but a password with URL delimiters needs no escaping.
+		let toml: String = SAMPLE_TOML.to_owned() + "rq_password = \"p@ss:w/rd\"\nrq_database = 2\n";
+		app_config = AppConfig::new_from_toml_string(&toml).unwrap();
+		let info: redis::ConnectionInfo = redis_connection_info(&app_config);
+		assert_eq!((info.redis.db, info.redis.password.as_deref()), (2, Some("p@ss:w/rd")));
+		let from_url: redis::ConnectionInfo = redis::IntoConnectionInfo::into_connection_info("redis://:p%40ss%3Aw%2Frd@127.0.0.1:11000/2").unwrap();
+		assert_eq!((from_url.redis.db, from_url.redis.password), (info.redis.db, info.redis.password));
+
+		// A wrong password is told apart from an unreachable server.
+		let wrong_password = redis::RedisError::from((redis::ErrorKind::AuthenticationFailed, "Password authentication failed"));
+		assert_eq!(describe_connection_error(&app_config, &wrong_password),
+		           "Redis authentication failed at host 127.0.0.1:11000; check 'rq_password'.");
+		let unreachable = redis::RedisError::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+		assert_eq!(describe_connection_error(&app_config, &unreachable),
+		           "Unable to establish a connection to Redis Server at host 127.0.0.1:11000");
+		assert!(crate::rq::get_redis_connection(&app_config, false).is_none());
+	}
+
+	#[test]
+	fn test_parse_dotenv() {
+		use crate::config::parse_dotenv;
+
+		let contents: &str = "
+			# Database
+			BTU_MYSQL_USER=root
+			export BTU_MYSQL_PASSWORD = \"p@ss # not a comment\"
+			BTU_MYSQL_HOST='db.internal'
+			BTU_RQ_PORT=11000  # the Redis Queue port
+			BTU_EMPTY=
+			not a setting
+			BAD KEY=value
+		";
+		let expected: Vec<(String, String)> = [
+			("BTU_MYSQL_USER", "root"),
+			("BTU_MYSQL_PASSWORD", "p@ss # not a comment"),
+			("BTU_MYSQL_HOST", "db.internal"),
+			("BTU_RQ_PORT", "11000"),
+			("BTU_EMPTY", ""),
+		].iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+		assert_eq!(parse_dotenv(contents), expected);
+	}
+
+	#[test]
+	fn test_resolve_paths() {
+		use crate::config::{resolve_paths, resolve_paths_from, ResolvedPaths, CONFIG_FILE_PATH, SOCKET_FILE_PATH};
+
+		let environment = |pairs: &'static [(&'static str, &'static str)]| {
+			move |name: &str| pairs.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+		};
+		let developer = environment(&[("HOME", "/home/dev"), ("USER", "dev"), ("XDG_RUNTIME_DIR", "/run/user/1000")]);
+
+		// A readable system file, and no flags: system mode, with the configuration's own socket.
+		let system: ResolvedPaths = resolve_paths_from(None, false, true, developer);
+		assert_eq!((system.config_file.as_str(), system.socket_path.as_deref(), system.user_mode), (CONFIG_FILE_PATH, None, false));
+
+		// '--user': XDG paths, with $XDG_CONFIG_HOME defaulting to $HOME/.config.
+		let user: ResolvedPaths = resolve_paths_from(None, true, true, developer);
+		assert_eq!(user.config_file.as_str(), "/home/dev/.config/btu_scheduler/btu_scheduler.toml");
+		assert_eq!(user.socket_path.as_deref(), Some("/run/user/1000/btu_scheduler.sock"));
+		assert_eq!(user.to_string(), "user mode (--user): configuration file '/home/dev/.config/btu_scheduler/btu_scheduler.toml', \
+		                              socket '/run/user/1000/btu_scheduler.sock'");
+
+		// An unreadable system file switches to user mode, when there is a $HOME.
+		let automatic: ResolvedPaths = resolve_paths_from(None, false, false, developer);
+		assert!(automatic.user_mode);
+		assert_eq!(automatic.reason, "the system configuration file is not readable");
+		let no_home: ResolvedPaths = resolve_paths_from(None, false, false, environment(&[]));
+		assert!(!no_home.user_mode);
+
+		// '--config' always chooses the file.  With '--user', the socket is still per-user.
+		let explicit: ResolvedPaths = resolve_paths_from(Some("/srv/btu.toml"), false, false, developer);
+		assert_eq!((explicit.config_file.as_str(), explicit.user_mode), ("/srv/btu.toml", false));
+		let explicit_user: ResolvedPaths = resolve_paths_from(Some("/srv/btu.toml"), true, true, developer);
+		assert_eq!(explicit_user.config_file.as_str(), "/srv/btu.toml");
+		assert!(explicit_user.socket_path.is_some());
+
+		// Without $XDG_RUNTIME_DIR, the socket is a per-user file in the temporary directory.
+		let no_runtime_dir: ResolvedPaths = resolve_paths_from(None, true, true, environment(&[("HOME", "/home/dev"), ("USER", "dev")]));
+		assert_eq!(no_runtime_dir.socket_path, Some(std::env::temp_dir().join("btu_scheduler-dev.sock").display().to_string()));
+
+		// Only the shared default socket is replaced; a socket chosen in the file is kept.
+		let mut app_config = AppConfig::new_from_toml_string(CONFIG_V2_TOML).unwrap();
+		assert_eq!(app_config.socket_path, SOCKET_FILE_PATH);
+		assert!(user.apply_socket_path(&mut app_config));
+		assert_eq!(app_config.socket_path, "/run/user/1000/btu_scheduler.sock");
+		assert!(!user.apply_socket_path(&mut app_config));
+		assert!(!system.apply_socket_path(&mut AppConfig::new_from_toml_string(CONFIG_V2_TOML).unwrap()));
+
+		// The real environment.  No other test reads these variables.
+		std::env::set_var("XDG_CONFIG_HOME", "/tmp/btu_test_xdg_config");
+		std::env::set_var("XDG_RUNTIME_DIR", "/tmp/btu_test_xdg_runtime");
+		let from_environment: ResolvedPaths = resolve_paths(None, true);
+		assert_eq!(from_environment.config_file.as_str(), "/tmp/btu_test_xdg_config/btu_scheduler/btu_scheduler.toml");
+		assert_eq!(from_environment.socket_path.as_deref(), Some("/tmp/btu_test_xdg_runtime/btu_scheduler.sock"));
+		std::env::remove_var("XDG_CONFIG_HOME");
+		std::env::remove_var("XDG_RUNTIME_DIR");
+	}
+
+	#[test]
+	fn test_multiple_sites() {
+		use crate::config::{split_schedule_id, upgrade_config_toml};
+		use crate::ids::TaskScheduleId;
+		use crate::schedule_queue::{QueueEntry, QueueSource, ScheduleQueue};
+
+		let two_sites: String = format!("{}{}", CONFIG_V2_TOML, r#"
+			[[site]]
+			name = "accounting.example.com"
+			mysql_user = "accounting"
+			mysql_password = "secret"
+			mysql_database = "accounting_db"
+			webserver_token = "token acc:123"
+
+			[[site]]
+			name = "hr.example.com"
+			mysql_user = "hr"
+			mysql_password = "secret"
+			mysql_host = "db2.internal"
+			mysql_database = "hr_db"
+			webserver_port = 8001
+			webserver_token = "token hr:456"
+		"#);
+		let config = AppConfig::new_from_toml_string(&two_sites).unwrap();
+		let site_configs: Vec<AppConfig> = config.site_configs();
+		let names: Vec<Option<&str>> = site_configs.iter().map(|site| site.site_name()).collect();
+		assert_eq!(names, vec![None, Some("accounting.example.com"), Some("hr.example.com")]);
+
+		// Each site has its own database and token, and inherits what it leaves out.
+		let hr: &AppConfig = &site_configs[2];
+		let hr_printed: String = hr.to_string();
+		assert!(hr_printed.contains("* MySQL Host: db2.internal.") && hr_printed.contains("* MySQL Database: hr_db\n"), "{}", hr_printed);
+		assert_eq!((hr.webserver_ip.as_str(), hr.webserver_port), ("127.0.0.1", 8001));
+		assert_eq!(hr.webserver_token, "token hr:456");
+		assert!(site_configs[1].to_string().contains("* MySQL Host: localhost."));
+		assert!(site_configs[0].to_string().contains("* MySQL Database: erpnext_db\n"));
+		assert!(config.for_site(Some("unknown.example.com")).is_none());
+
+		// Schedule IDs are qualified by site, except on the default site, and split back the same way.
+		assert_eq!(site_configs[0].qualify_schedule_id("TS-0001"), "TS-0001");
+		let qualified: TaskScheduleId = hr.qualify_schedule_id("TS-0001");
+		assert_eq!(qualified, "hr.example.com::TS-0001");
+		assert_eq!(split_schedule_id(qualified.as_str()), (Some("hr.example.com"), "TS-0001"));
+		assert_eq!(split_schedule_id("TS-0001"), (None, "TS-0001"));
+
+		// The same Task Schedule ID on two sites is two different Internal Queue entries.
+		let mut queue = ScheduleQueue::default();
+		assert!(queue.push_back(QueueEntry::new("TS-0001".into(), QueueSource::FullRefresh)));
+		assert!(queue.push_back(QueueEntry::new(qualified, QueueSource::FullRefresh)));
+		assert_eq!(queue.len(), 2);
+
+		// Site names must be unique, and safe in Redis keys.
+		let duplicate: String = two_sites.replace("hr.example.com", "accounting.example.com");
+		let error_message: String = AppConfig::new_from_toml_string(&duplicate).err().expect("expected an error").to_string();
+		assert_eq!(error_message, "Invalid [[site]] 'accounting.example.com' in the configuration: another [[site]] has the same 'name'");
+		let bad_name: String = two_sites.replace("hr.example.com", "hr::site");
+		let error_message: String = AppConfig::new_from_toml_string(&bad_name).err().expect("expected an error").to_string();
+		assert_eq!(error_message, "Invalid [[site]] 'hr::site' in the configuration: 'name' must be letters, digits, '.', '-', or '_'");
+
+		// Sites survive 'config-upgrade'.
+		let upgraded = AppConfig::new_from_toml_string(&upgrade_config_toml(&two_sites).unwrap().to_toml_string().unwrap()).unwrap();
+		assert_eq!(upgraded.site_configs().len(), 3);
+	}
+
+	/// A one-shot web server on a free local port.  It replies with 'reply' after 'delay', and sends back the request it read.
+	fn stub_web_server(reply: &'static str, delay: std::time::Duration) -> (u16, std::sync::mpsc::Receiver<String>) {
+		use std::io::{BufRead, BufReader, Write};
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let port: u16 = listener.local_addr().unwrap().port();
+		let (sender, receiver) = std::sync::mpsc::channel();
+		std::thread::spawn(move || {
+			let (stream, _) = listener.accept().unwrap();
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+			let mut request = String::new();
+			let mut line = String::new();
+			while reader.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+				request.push_str(&line);
+				line.clear();
+			}
+			sender.send(request).unwrap();
+			std::thread::sleep(delay);
+			let mut stream = stream;
+			let _ = stream.write_all(reply.as_bytes());
+		});
+		(port, receiver)
+	}
+
+	fn frappe_client_for(port: u16, timeout_secs: u64) -> crate::frappe_client::FrappeClient {
+		let mut app_config: AppConfig = sample_app_config();
+		app_config.webserver_port = port;
+		app_config.webserver_host_header = Some("erp.example.com".to_owned());
+		app_config.webserver_timeout_secs = Some(timeout_secs);
+		crate::frappe_client::FrappeClient::new(&app_config)
+	}
+
+	#[test]
+	fn test_frappe_client_requests() {
+		use crate::errors::FrappeHttpError;
+
+		// Every request carries the token, and the "Host" header for DNS multi-tenancy.
+		let (port, received) = stub_web_server("HTTP/1.0 200 OK\r\nContent-Type: application/json\r\nContent-Length: 19\r\n\r\n{\"message\": \"pong\"}", std::time::Duration::ZERO);
+		let client = frappe_client_for(port, 5);
+		assert_eq!(client.url("/api/method/ping"), format!("http://127.0.0.1:{}/api/method/ping", port));
+		let value = client.get_json("/api/method/ping").unwrap();
+		assert_eq!(value["message"], "pong");
+		let request: String = received.recv().unwrap().to_lowercase();
+		assert!(request.starts_with("get /api/method/ping http/1.1"), "{}", request);
+		assert!(request.contains("\r\nhost: erp.example.com\r\n"), "{}", request);
+		assert!(request.contains("\r\nauthorization: token abc:def\r\n"), "{}", request);
+
+		// Frappe's exceptions arrive as 5xx responses.
+		let (port, _received) = stub_web_server("HTTP/1.0 500 Internal Server Error\r\nContent-Length: 4\r\n\r\noops", std::time::Duration::ZERO);
+		match frappe_client_for(port, 5).get_json("/api/method/ping") {
+			Err(FrappeHttpError::Status { status, body, .. }) => assert_eq!((status, body.as_str()), (500, "oops")),
+			other => panic!("expected a Status error, not {:?}", other)
+		}
+
+		// A hung web server is abandoned after the timeout.
+		let (port, _received) = stub_web_server("HTTP/1.0 200 OK\r\n\r\n", std::time::Duration::from_secs(4));
+		let started = std::time::Instant::now();
+		match frappe_client_for(port, 1).get_json("/api/method/ping") {
+			Err(FrappeHttpError::Timeout { secs, .. }) => assert_eq!(secs, 1),
+			other => panic!("expected a Timeout error, not {:?}", other)
+		}
+		assert!(started.elapsed() < std::time::Duration::from_secs(3));
+	}
+
+	#[test]
+	fn test_frappe_base_url() {
+		use crate::frappe_client::base_url;
+
+		// (webserver_ip, webserver_port, webserver_use_tls) -> base URL
+		let cases: [(&str, u16, Option<bool>, &str); 15] = [
+			("127.0.0.1", 8000, None, "http://127.0.0.1:8000"),
+			("127.0.0.1", 443, None, "https://127.0.0.1"),
+			("127.0.0.1", 80, None, "http://127.0.0.1"),
+			("127.0.0.1", 8443, Some(true), "https://127.0.0.1:8443"),
+			("127.0.0.1", 443, Some(false), "http://127.0.0.1:443"),
+			("erp.example.com", 8000, None, "http://erp.example.com:8000"),
+			("erp.example.com", 443, None, "https://erp.example.com"),
+			("erp.example.com", 443, Some(true), "https://erp.example.com"),
+			("erp.example.com", 8443, Some(true), "https://erp.example.com:8443"),
+			("erp.example.com", 80, Some(false), "http://erp.example.com"),
+			("erp.example.com", 8000, Some(false), "http://erp.example.com:8000"),
+			(" erp.example.com ", 443, None, "https://erp.example.com"),
+			("https://erp.example.com:8443/", 8000, None, "https://erp.example.com:8443"),
+			("http://10.0.0.5:8000", 443, Some(true), "http://10.0.0.5:8000"),
+			("https://erp.example.com", 443, Some(false), "https://erp.example.com"),
+		];
+		for (webserver_ip, port, use_tls, expected) in cases {
+			let (url, warning) = base_url(webserver_ip, port, use_tls);
+			assert_eq!(url, expected, "{} {} {:?}", webserver_ip, port, use_tls);
+			// Only a full URL makes the port and TLS settings redundant.
+			assert_eq!(warning.is_some(), webserver_ip.contains("://"), "{} {} {:?}", webserver_ip, port, use_tls);
+		}
+
+		// Every call builds its URL with AppConfig::frappe_url(): https on 443, https on another port, and plain http.
+		let mut app_config: AppConfig = sample_app_config();
+		app_config.webserver_ip = "erp.example.com".to_owned();
+		for (port, use_tls, expected) in [(443, None, "https://erp.example.com/api/method/btu.btu_api.endpoints.test_ping"),
+		                                  (8443, Some(true), "https://erp.example.com:8443/api/method/btu.btu_api.endpoints.test_ping"),
+		                                  (8000, None, "http://erp.example.com:8000/api/method/btu.btu_api.endpoints.test_ping")] {
+			app_config.webserver_port = port;
+			app_config.webserver_use_tls = use_tls;
+			assert_eq!(app_config.frappe_url("/api/method/btu.btu_api.endpoints.test_ping"), expected);
+			assert_eq!(crate::frappe_client::FrappeClient::new(&app_config).url("/api/method/btu.btu_api.endpoints.test_ping"), expected);
+		}
+
+		// A [[site]] inherits 'webserver_use_tls', unless it has its own.
+		let mut app_config: AppConfig = sample_app_config();
+		app_config.webserver_use_tls = Some(true);
+		app_config.sites = Some(vec![toml::from_str("name = 'b'\nmysql_user = 'u'\nmysql_password = 'p'\nmysql_database = 'd'\nwebserver_token = 't'").unwrap()]);
+		assert_eq!(app_config.for_site(Some("b")).unwrap().webserver_use_tls, Some(true));
+	}
+
+	/// RQ job hashes and queues, kept in memory instead of Redis.
+	#[derive(Default)]
+	struct MemoryJobs {
+		hashes: std::collections::HashSet<String>,
+		queues: std::collections::HashMap<String, Vec<String>>,
+	}
+
+	impl MemoryJobs {
+		/// Save the job hash, and push its ID onto the queue; as rq::enqueue_job_immediate() would.
+		fn save_and_enqueue(&mut self, queue_name: &str, job_id: &str) {
+			self.hashes.insert(job_id.to_owned());
+			self.queues.entry(queue_name.to_owned()).or_default().push(job_id.to_owned());
+		}
+	}
+
+	impl crate::enqueue_check::EnqueuedJobStore for MemoryJobs {
+		fn job_hash_exists(&mut self, job_id: &crate::ids::RqJobId) -> Result<bool, redis::RedisError> {
+			Ok(self.hashes.contains(job_id.as_str()))
+		}
+
+		fn queue_contains(&mut self, queue_name: &str, job_id: &crate::ids::RqJobId) -> Result<bool, redis::RedisError> {
+			Ok(self.queues.get(queue_name).map_or(false, |queue| queue.iter().any(|member| member == job_id.as_str())))
+		}
+
+		fn remove_from_queue(&mut self, queue_name: &str, job_id: &crate::ids::RqJobId) -> Result<(), redis::RedisError> {
+			if let Some(queue) = self.queues.get_mut(queue_name) {
+				queue.retain(|member| member != job_id.as_str());
+			}
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn test_enqueue_verification() {
+		use crate::enqueue_check::{verify_enqueued, EnqueueCheck, Verification};
+
+		let job_id = crate::ids::RqJobId::from("abc-123");
+		let mut jobs = MemoryJobs::default();
+		jobs.save_and_enqueue("default", "abc-123");
+		let retry_not_expected = |_: &mut MemoryJobs| -> Result<(), String> { panic!("a complete job must not be enqueued again") };
+		assert_eq!(verify_enqueued(&mut jobs, "default", &job_id, retry_not_expected).unwrap(), Verification::Verified);
+
+		// The hash is evicted between the save and the verification: the job is saved and enqueued again, exactly once.
+		jobs.hashes.clear();
+		let missing_hash = EnqueueCheck { hash_exists: false, on_queue: true };
+		let verification = verify_enqueued(&mut jobs, "default", &job_id, |jobs: &mut MemoryJobs| {
+			jobs.save_and_enqueue("default", "abc-123");
+			Ok(())
+		});
+		assert_eq!(verification.unwrap(), Verification::Repaired { first: missing_hash });
+		assert_eq!(jobs.queues["default"], vec!["abc-123".to_owned()]);
+
+		// Evicted again during the retry: the run fails, and says why.
+		jobs.hashes.clear();
+		let verification = verify_enqueued(&mut jobs, "default", &job_id, |jobs: &mut MemoryJobs| {
+			jobs.queues.entry("default".to_owned()).or_default().push("abc-123".to_owned());
+			Ok(())
+		});
+		assert_eq!(verification.unwrap(), Verification::Failed { first: missing_hash, retry: missing_hash.to_string() });
+
+		// A retry that cannot save the job reports its own error.
+		let mut jobs = MemoryJobs::default();
+		jobs.hashes.insert("abc-123".to_owned());
+		let verification = verify_enqueued(&mut jobs, "default", &job_id, |_: &mut MemoryJobs| Err("OOM command not allowed".to_owned()));
+		assert_eq!(verification.unwrap(), Verification::Failed {
+			first: EnqueueCheck { hash_exists: true, on_queue: false },
+			retry: "OOM command not allowed".to_owned()
+		});
+	}
+
+	#[test]
+	fn test_redis_memory_pressure() {
+		use crate::rq::RedisMemory;
+
+		let info = "# Memory\r\nused_memory:950\r\nused_memory_human:950B\r\nmaxmemory:1000\r\nmaxmemory_policy:allkeys-lru\r\n";
+		let memory = RedisMemory::from_info(info).unwrap();
+		assert_eq!(memory, RedisMemory { used_memory: 950, maxmemory: 1000 });
+		assert!(memory.pressure_warning(90).unwrap().contains("95%"));
+		assert_eq!(memory.pressure_warning(96), None);
+		assert_eq!(memory.pressure_warning(0), None);
+		// Without 'maxmemory', Redis never evicts; there is nothing to warn about.
+		assert_eq!(RedisMemory { used_memory: 950, maxmemory: 0 }.pressure_warning(90), None);
+		assert_eq!(RedisMemory::from_info("# Memory\r\nused_memory:950\r\n"), None);
+	}
+
+	/// Failure counters and open circuits, kept in memory instead of Redis.  'expiring' records each counter's cool-down.
+	#[derive(Default)]
+	struct MemoryCircuits {
+		counts: std::collections::HashMap<String, u32>,
+		expiring: std::collections::HashMap<String, u64>,
+		open: std::collections::BTreeSet<String>,
+	}
+
+	impl crate::circuit_breaker::CircuitStore for MemoryCircuits {
+		fn increment_failures(&mut self, key: &str) -> u32 {
+			let count = self.counts.entry(key.to_owned()).or_insert(0);
+			*count += 1;
+			*count
+		}
+		fn read_failures(&mut self, key: &str) -> Option<u32> {
+			self.counts.get(key).copied()
+		}
+		fn remove_failures(&mut self, key: &str) -> bool {
+			self.expiring.remove(key);
+			self.counts.remove(key).is_some()
+		}
+		fn expire_failures(&mut self, key: &str, secs: u64) {
+			self.expiring.insert(key.to_owned(), secs);
+		}
+		fn add_open(&mut self, task_schedule_id: &str) -> bool {
+			self.open.insert(task_schedule_id.to_owned())
+		}
+		fn remove_open(&mut self, task_schedule_id: &str) -> bool {
+			self.open.remove(task_schedule_id)
+		}
+		fn is_member_open(&mut self, task_schedule_id: &str) -> bool {
+			self.open.contains(task_schedule_id)
+		}
+		fn open_members(&mut self) -> Vec<String> {
+			self.open.iter().rev().cloned().collect()
+		}
+	}
+
+	#[test]
+	fn test_circuit_breaker_lifecycle() {
+		use crate::circuit_breaker::{CircuitTransition, OpenCircuit, failure_count_key, is_open, open_circuits, record_failure, record_success, reset};
+
+		let mut store = MemoryCircuits::default();
+		assert_eq!(failure_count_key("TS-1"), "btu_scheduler:failure_count:TS-1");
+
+		// Disabled (the default): nothing is counted.
+		assert_eq!(record_failure(&mut store, "TS-1", 0, None), CircuitTransition::Disabled);
+		assert!(store.counts.is_empty());
+
+		// Failures are counted, and a success resets the count.
+		assert_eq!(record_failure(&mut store, "TS-1", 3, Some(3600)), CircuitTransition::Counted { failures: 1 });
+		assert_eq!(record_failure(&mut store, "TS-1", 3, Some(3600)), CircuitTransition::Counted { failures: 2 });
+		assert_eq!(record_success(&mut store, "TS-1"), CircuitTransition::Reset);
+		assert_eq!(record_success(&mut store, "TS-1"), CircuitTransition::Unchanged);
+		assert!(!is_open(&mut store, "TS-1"));
+
+		// Three failures in a row open the circuit once; only then does the counter start its cool-down.
+		assert_eq!(record_failure(&mut store, "TS-1", 3, Some(3600)), CircuitTransition::Counted { failures: 1 });
+		assert_eq!(record_failure(&mut store, "TS-1", 3, Some(3600)), CircuitTransition::Counted { failures: 2 });
+		assert!(store.expiring.is_empty());
+		assert_eq!(record_failure(&mut store, "TS-1", 3, Some(3600)), CircuitTransition::Opened { failures: 3 });
+		assert_eq!(store.expiring.get(&failure_count_key("TS-1")), Some(&3600));
+		assert_eq!(record_failure(&mut store, "TS-1", 3, Some(3600)), CircuitTransition::StillOpen);
+		assert!(is_open(&mut store, "TS-1"));
+		assert!(!is_open(&mut store, "TS-2"));
+
+		record_failure(&mut store, "TS-0", 1, None);
+		assert_eq!(open_circuits(&mut store), vec![
+			OpenCircuit { task_schedule_id: "TS-0".into(), failures: Some(1) },
+			OpenCircuit { task_schedule_id: "TS-1".into(), failures: Some(4) },
+		]);
+
+		// Reset by an operator.
+		assert!(reset(&mut store, "TS-1"));
+		assert!(!is_open(&mut store, "TS-1"));
+		assert!(!reset(&mut store, "TS-1"));
+
+		// The counter expired: the cool-down has elapsed, and the circuit closes the next time it is checked.
+		store.counts.remove(&failure_count_key("TS-0"));
+		assert_eq!(open_circuits(&mut store), vec![OpenCircuit { task_schedule_id: "TS-0".into(), failures: None }]);
+		assert!(!is_open(&mut store, "TS-0"));
+		assert!(store.open.is_empty());
+	}
+
+	/// Remaining repeat counts, and upcoming runs, kept in memory instead of Redis.
+	#[derive(Default)]
+	struct MemoryRepeats {
+		remaining: std::collections::HashMap<String, crate::repeat_count::RepeatCount>,
+		upcoming: std::collections::HashMap<String, usize>,
+	}
+
+	impl crate::repeat_count::RepeatStore for MemoryRepeats {
+		fn read_count(&mut self, task_schedule_id: &str) -> Option<crate::repeat_count::RepeatCount> {
+			self.remaining.get(task_schedule_id).copied()
+		}
+		fn count_down(&mut self, task_schedule_id: &str, repeat: u32) -> u32 {
+			let current = self.read_count(task_schedule_id);
+			let remaining = crate::repeat_count::remaining_runs(current, repeat).unwrap_or(repeat).saturating_sub(1);
+			self.remaining.insert(task_schedule_id.to_owned(), crate::repeat_count::RepeatCount { remaining, repeat: Some(repeat) });
+			remaining
+		}
+		fn remove_upcoming_runs(&mut self, task_schedule_id: &str) -> usize {
+			self.upcoming.remove(task_schedule_id).unwrap_or_default()
+		}
+	}
+
+	#[test]
+	fn test_repeat_count_stops_requeueing() {
+		use crate::ids::TaskScheduleId;
+		use crate::repeat_count::{RepeatCount, RepeatTransition, count_run_and_requeue, is_exhausted};
+		use crate::schedule_queue::ScheduleQueue;
+
+		let mut store = MemoryRepeats::default();
+		let mut queue = ScheduleQueue::new();
+		let schedule_key = TaskScheduleId::from("TS-1");
+
+		// 'repeat' of 2: the first run is re-queued for its next run.
+		assert!(!is_exhausted(&mut store, "TS-1", Some(2)));
+		assert_eq!(count_run_and_requeue(&mut store, &mut queue, &schedule_key, Some(2)), RepeatTransition::Remaining { remaining: 1 });
+		assert_eq!(queue.pop_front().map(|entry| entry.schedule_id), Some(schedule_key.clone()));
+
+		// The second run is the last: not re-queued, and the upcoming run already written is removed.
+		store.upcoming.insert("TS-1".to_owned(), 1);
+		assert_eq!(count_run_and_requeue(&mut store, &mut queue, &schedule_key, Some(2)), RepeatTransition::Exhausted);
+		assert!(queue.is_empty());
+		assert!(store.upcoming.is_empty());
+
+		// There is no third run.
+		assert!(is_exhausted(&mut store, "TS-1", Some(2)));
+		assert!(!is_exhausted(&mut store, "TS-2", Some(2)));
+
+		// Editing 'repeat' starts the count over, from the new value.
+		assert!(!is_exhausted(&mut store, "TS-1", Some(3)));
+		assert_eq!(count_run_and_requeue(&mut store, &mut queue, &schedule_key, Some(3)), RepeatTransition::Remaining { remaining: 2 });
+		assert_eq!(queue.pop_front().map(|entry| entry.schedule_id), Some(schedule_key.clone()));
+		// A count stored before its 'repeat' was recorded still applies.
+		store.remaining.insert("TS-4".to_owned(), RepeatCount { remaining: 0, repeat: None });
+		assert!(is_exhausted(&mut store, "TS-4", Some(5)));
+
+		// Without a 'repeat' (NULL, or Frappe's 0 for an empty Int), every run is re-queued, and nothing is counted.
+		for repeat in [None, Some(0)] {
+			for _ in 0..3 {
+				assert_eq!(count_run_and_requeue(&mut store, &mut queue, &TaskScheduleId::from("TS-3"), repeat), RepeatTransition::Unlimited);
+				assert_eq!(queue.pop_front().map(|entry| entry.schedule_id.to_string()), Some("TS-3".to_owned()));
+			}
+			assert!(!is_exhausted(&mut store, "TS-3", repeat));
+		}
+		assert!(!store.remaining.contains_key("TS-3"));
+	}
+
+	/// The sorted set of TSIKs, kept in memory instead of Redis.
+	#[derive(Default)]
+	struct MemoryTsiks {
+		members: std::collections::BTreeMap<String, i64>,
+		refuse: bool,  // as when Redis is unreachable
+	}
+
+	impl crate::scheduler::TsikStore for MemoryTsiks {
+		fn restore_tsik(&mut self, tsik: &str, next_unix: i64) -> bool {
+			if !self.refuse {
+				self.members.insert(tsik.to_owned(), next_unix);
+			}
+			!self.refuse
+		}
+	}
+
+	#[test]
+	fn test_failed_run_is_tried_again() {
+		use chrono::{Duration, TimeZone, Utc};
+		use crate::scheduler::{FailedRunRetry, RQScheduledTask, retry_failed_run};
+		use crate::schedule_queue::ScheduleQueue;
+
+		let run_at = Utc.with_ymd_and_hms(2022, 12, 2, 8, 0, 0).unwrap();
+		let instance = RQScheduledTask { task_schedule_id: "TS-1".into(), next_datetime_unix: run_at.timestamp(), next_datetime_utc: run_at };
+		let mut store = MemoryTsiks::default();
+		let mut queue = ScheduleQueue::new();
+
+		// Thread #3 removed the TSIK; then the pickle could not be fetched, because the web server did not answer.  The TSIK is
+		// back with its original score, so the next pass finds it due again; and the schedule's next run is calculated again.
+		let now = run_at + Duration::seconds(30);
+		assert_eq!(retry_failed_run(&mut store, &mut queue, &instance, 3600, now), FailedRunRetry::Restored);
+		assert_eq!(store.members.get("TS-1|1669968000"), Some(&1669968000));
+		assert_eq!(queue.pop_front().map(|entry| entry.schedule_id.to_string()), Some("TS-1".to_owned()));
+
+		// It fails again on that pass, and is restored again: the run is not lost while the outage lasts.
+		store.members.clear();
+		assert_eq!(retry_failed_run(&mut store, &mut queue, &instance, 3600, now + Duration::seconds(60)), FailedRunRetry::Restored);
+		assert_eq!(store.members.len(), 1);
+
+		// After 'failed_run_retry_secs', the run is given up; the schedule's later runs are still calculated.
+		store.members.clear();
+		queue = ScheduleQueue::new();
+		assert_eq!(retry_failed_run(&mut store, &mut queue, &instance, 3600, run_at + Duration::seconds(3601)), FailedRunRetry::TooLate);
+		assert!(store.members.is_empty());
+		assert_eq!(queue.len(), 1);
+
+		// 0 turns retries off; a refused write is reported.
+		assert_eq!(retry_failed_run(&mut store, &mut ScheduleQueue::new(), &instance, 0, now), FailedRunRetry::Disabled);
+		store.refuse = true;
+		assert_eq!(retry_failed_run(&mut store, &mut ScheduleQueue::new(), &instance, 3600, now), FailedRunRetry::Lost);
+		assert!(store.members.is_empty());
+	}
+
+	fn job_hash_with_status(status: Option<&str>, exc_info: Option<&str>) -> std::collections::HashMap<String, Vec<u8>> {
+		let mut job_hash = std::collections::HashMap::new();
+		job_hash.insert("origin".to_owned(), b"default".to_vec());
+		if let Some(status) = status {
+			job_hash.insert("status".to_owned(), status.as_bytes().to_vec());
+		}
+		if let Some(exc_info) = exc_info {
+			job_hash.insert("exc_info".to_owned(), exc_info.as_bytes().to_vec());
+		}
+		job_hash
+	}
+
+	#[test]
+	fn test_wait_for_job_completion_transitions() {
+		use std::time::Duration;
+		use crate::rq::{JobOutcome, JobProgress, classify_job_progress, poll_job_progress};
+
+		assert_eq!(classify_job_progress(&std::collections::HashMap::new()), JobProgress::Missing);
+		assert_eq!(classify_job_progress(&job_hash_with_status(None, None)), JobProgress::Pending(String::new()));
+		assert_eq!(classify_job_progress(&job_hash_with_status(Some("started"), None)), JobProgress::Pending("started".to_owned()));
+		assert_eq!(classify_job_progress(&job_hash_with_status(Some("finished"), None)), JobProgress::Finished);
+		assert_eq!(classify_job_progress(&job_hash_with_status(Some("failed"), Some("Traceback: ZeroDivisionError"))),
+		           JobProgress::Failed { status: "failed".to_owned(), exc_info: Some("Traceback: ZeroDivisionError".to_owned()) });
+
+		// Replays a sequence of job hashes, one per poll; the last one repeats.
+		let replay = |statuses: Vec<Option<std::collections::HashMap<String, Vec<u8>>>>| {
+			let mut polls = statuses.into_iter();
+			let mut last = None;
+			move || {
+				if let Some(next) = polls.next() {
+					last = next;
+				}
+				last.as_ref().map(classify_job_progress)
+			}
+		};
+		let forever = Duration::from_secs(60);
+
+		// queued -> started -> finished
+		let outcome = poll_job_progress(replay(vec![
+			Some(job_hash_with_status(Some("queued"), None)),
+			Some(job_hash_with_status(Some("started"), None)),
+			Some(job_hash_with_status(Some("finished"), None)),
+		]), forever, Duration::ZERO);
+		assert!(matches!(outcome, JobOutcome::Finished { .. }), "{:?}", outcome);
+
+		// A read error (None) is not final; then the job fails, with its exception.
+		let outcome = poll_job_progress(replay(vec![
+			Some(job_hash_with_status(Some("queued"), None)),
+			None,
+			Some(job_hash_with_status(Some("failed"), Some("Traceback"))),
+		]), forever, Duration::ZERO);
+		match outcome {
+			JobOutcome::Failed { status, exc_info, .. } => assert_eq!((status.as_str(), exc_info.as_deref()), ("failed", Some("Traceback"))),
+			other => panic!("expected Failed, not {:?}", other)
+		}
+
+		// The hash disappears after it was seen: the job is gone without a final status.
+		let outcome = poll_job_progress(replay(vec![
+			Some(job_hash_with_status(Some("started"), None)),
+			Some(std::collections::HashMap::new()),
+		]), forever, Duration::ZERO);
+		assert!(matches!(outcome, JobOutcome::Vanished { .. }), "{:?}", outcome);
+
+		// Never picked up by a worker: the wait ends at the timeout, with the last status seen.
+		let started = std::time::Instant::now();
+		let outcome = poll_job_progress(replay(vec![Some(job_hash_with_status(Some("queued"), None))]),
+		                                Duration::from_millis(50), Duration::from_millis(10));
+		match outcome {
+			JobOutcome::TimedOut { last_status, waited } => {
+				assert_eq!(last_status.as_deref(), Some("queued"));
+				assert!(waited >= Duration::from_millis(50));
+			},
+			other => panic!("expected TimedOut, not {:?}", other)
+		}
+		assert!(started.elapsed() < Duration::from_secs(5));
+	}
+
+	#[test]
+	fn test_concurrency_reconcile() {
+		use std::collections::HashMap;
+		use crate::concurrency::{classify_instance, reconcile, running_key, InstanceState, ReleaseReason, RunningInstance};
+		use crate::ids::RqJobId;
+
+		let now: DateTime<Utc> = DateTime::parse_from_rfc3339("2026-03-01T12:00:00Z").unwrap().into();
+		let started = |started_at: &str, timeout: &str| {
+			let mut job_hash = job_hash_with_status(Some("started"), None);
+			job_hash.insert("started_at".to_owned(), started_at.as_bytes().to_vec());
+			job_hash.insert("timeout".to_owned(), timeout.as_bytes().to_vec());
+			job_hash
+		};
+		let in_a_minute: i64 = now.timestamp() + 60;
+
+		assert_eq!(running_key(None, "TASK-0001"), "btu_scheduler:running:TASK-0001");
+		assert_eq!(running_key(Some("erp.example.com"), "TASK-0001"), "btu_scheduler:running:erp.example.com::TASK-0001");
+
+		// Finished early, or failed: the slot is released before its time.
+		assert_eq!(classify_instance(&job_hash_with_status(Some("finished"), None), in_a_minute, now), InstanceState::Released(ReleaseReason::Ended));
+		assert_eq!(classify_instance(&job_hash_with_status(Some("failed"), Some("Traceback")), in_a_minute, now), InstanceState::Released(ReleaseReason::Ended));
+		// Deleted from Redis.
+		assert_eq!(classify_instance(&HashMap::new(), in_a_minute, now), InstanceState::Released(ReleaseReason::Deleted));
+		// Queued, and within its time; then never started, and past its time.
+		assert_eq!(classify_instance(&job_hash_with_status(Some("queued"), None), in_a_minute, now), InstanceState::Active { expires_at: in_a_minute });
+		assert_eq!(classify_instance(&job_hash_with_status(Some("queued"), None), now.timestamp(), now), InstanceState::Released(ReleaseReason::Expired));
+		// Started at 11:59 with its timeout extended to 10 minutes: the slot is held until 12:09, past the recorded time.
+		assert_eq!(classify_instance(&started("2026-03-01T11:59:00Z", "600"), now.timestamp() - 1, now),
+		           InstanceState::Active { expires_at: now.timestamp() + 540 });
+		// Started long ago, and past its timeout.
+		assert_eq!(classify_instance(&started("2026-03-01T10:00:00Z", "600"), now.timestamp() - 1, now), InstanceState::Released(ReleaseReason::Expired));
+		// No timeout at all (-1): held for as long as it runs.
+		assert!(matches!(classify_instance(&started("2026-03-01T10:00:00Z", "-1"), now.timestamp() - 1, now), InstanceState::Active { .. }));
+
+		let recorded: Vec<RunningInstance> = ["job-a", "job-b", "job-c"].iter()
+			.map(|job_id| RunningInstance { job_id: RqJobId::from(*job_id), expires_at: in_a_minute })
+			.collect();
+		let (active, released) = reconcile(recorded, |job_id| match job_id.as_str() {
+			"job-a" => job_hash_with_status(Some("finished"), None),
+			"job-b" => started("2026-03-01T11:59:30Z", "60"),
+			_ => HashMap::new(),
+		}, now);
+		assert_eq!(active, vec![RunningInstance { job_id: RqJobId::from("job-b"), expires_at: in_a_minute }]);
+		assert_eq!(released, vec![(RqJobId::from("job-a"), ReleaseReason::Ended), (RqJobId::from("job-c"), ReleaseReason::Deleted)]);
+	}
+
+	#[test]
+	fn test_humanize_duration_boundaries() {
+		use chrono::Duration;
+		use crate::timeutil::humanize_duration;
+
+		for (seconds, expected) in [
+			(0, "0 seconds"), (1, "1 second"), (59, "59 seconds"),
+			(60, "1 minute"), (119, "1 minute"), (120, "2 minutes"), (3599, "59 minutes"),
+			(3600, "1 hour"), (7199, "1 hour"), (86399, "23 hours"),
+			(86400, "1 day"), (172799, "1 day"), (400 * 86400, "400 days"),
+		] {
+			assert_eq!(humanize_duration(Duration::seconds(seconds)), expected, "{} seconds", seconds);
+		}
+		for (seconds, expected) in [(-1, "1 second ago"), (-59, "59 seconds ago"), (-60, "1 minute ago"), (-86399, "23 hours ago"), (-86400, "1 day ago")] {
+			assert_eq!(humanize_duration(Duration::seconds(seconds)), expected, "{} seconds", seconds);
+		}
+		// Fractions of a second do not count.
+		assert_eq!(humanize_duration(Duration::milliseconds(59_999)), "59 seconds");
+		assert_eq!(humanize_duration(Duration::milliseconds(-999)), "0 seconds");
+	}
+
+	#[test]
+	fn test_humanize_instant() {
+		use chrono::Duration;
+		use crate::timeutil::humanize_instant;
+
+		let now: DateTime<Utc> = DateTime::parse_from_rfc3339("2024-07-01T12:00:00Z").unwrap().into();
+		assert_eq!(humanize_instant(now, now), "now");
+		assert_eq!(humanize_instant(now + Duration::minutes(14) + Duration::seconds(59), now), "in 14 minutes");
+		assert_eq!(humanize_instant(now + Duration::seconds(59), now), "in 59 seconds");
+		assert_eq!(humanize_instant(now - Duration::hours(3), now), "3 hours ago");
+		assert_eq!(humanize_instant(now - Duration::hours(23) - Duration::minutes(59), now), "23 hours ago");
+		assert_eq!(humanize_instant(now - Duration::hours(24), now), "1 day ago");
+		assert_eq!(humanize_instant(now + Duration::days(30), now), "in 30 days");
+	}
+
+	#[test]
+	fn test_compact_instant() {
+		use chrono::{Duration, TimeZone};
+		use crate::timeutil::compact_instant;
+
+		let now: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+		assert_eq!(compact_instant(now, now), "now");
+		assert_eq!(compact_instant(now + Duration::hours(3) + Duration::minutes(12) + Duration::seconds(59), now), "in 3h 12m");
+		assert_eq!(compact_instant(now + Duration::seconds(45), now), "in 45s");
+		assert_eq!(compact_instant(now - Duration::minutes(5) - Duration::seconds(2), now), "5m 2s ago");
+		assert_eq!(compact_instant(now + Duration::days(2), now), "in 2d");
+		assert_eq!(compact_instant(now + Duration::days(2) + Duration::hours(4), now), "in 2d 4h");
+		assert_eq!(compact_instant(now - Duration::hours(1) - Duration::seconds(30), now), "1h ago");
+	}
+
+	/// A time zone that cannot be parsed is shown as UTC, rather than stopping the listing.
+	#[test]
+	fn test_time_zone_falls_back_to_utc() {
+		let mut app_config: AppConfig = sample_app_config();
+		app_config.time_zone_string = "America/Los_Angelos".to_owned();
+		assert!(app_config.tz().is_err());
+		assert_eq!(app_config.tz_or_utc(), chrono_tz::UTC);
+		app_config.time_zone_string = "America/Los_Angeles".to_owned();
+		assert_eq!(app_config.tz_or_utc(), chrono_tz::America::Los_Angeles);
+	}
+
+	#[test]
+	fn test_parse_duration_arg() {
+		use chrono::Duration;
+		use crate::errors::DurationArgError;
+		use crate::timeutil::parse_duration_arg;
+
+		for (text, expected) in [
+			("90s", Duration::seconds(90)), ("15m", Duration::minutes(15)), ("2h", Duration::hours(2)), ("1d", Duration::days(1)),
+			("300", Duration::seconds(300)), ("0", Duration::zero()), ("0m", Duration::zero()), (" 2H ", Duration::hours(2)),
+			("-30m", Duration::minutes(-30)), ("+45s", Duration::seconds(45)), ("-5", Duration::seconds(-5)),
+		] {
+			assert_eq!(parse_duration_arg(text), Ok(expected), "'{}'", text);
+		}
+		for text in ["", "  ", "-", "m", "h2", "2x", "2 h", "1.5h", "2hours", "--2h", "PT2H", "2w"] {
+			assert_eq!(parse_duration_arg(text), Err(DurationArgError::Invalid { text: text.to_owned() }), "'{}'", text);
+		}
+		assert_eq!(parse_duration_arg("99999999999999999d"), Err(DurationArgError::TooLarge { text: "99999999999999999d".to_owned() }));
+		assert!(parse_duration_arg("99999999999999999999").is_err());
+	}
+
+	/// An in-memory stand-in for the sorted set of TSIKs, and the hash of overrides.
+	#[derive(Default)]
+	struct MemoryOverrides {
+		runs: std::collections::BTreeMap<String, i64>,
+		overrides: std::collections::HashMap<String, i64>,
+	}
+
+	impl crate::next_run_override::OverrideStore for MemoryOverrides {
+		fn schedule_runs(&mut self, task_schedule_id: &str) -> Result<Vec<(String, i64)>, redis::RedisError> {
+			let prefix: String = format!("{}|", task_schedule_id);
+			Ok(self.runs.iter().filter(|(tsik, _)| tsik.starts_with(&prefix)).map(|(tsik, unix)| (tsik.clone(), *unix)).collect())
+		}
+		fn replace_runs(&mut self, task_schedule_id: &str, replaced: &[String], next: &str, next_unix: i64) -> Result<(), redis::RedisError> {
+			for tsik in replaced {
+				self.runs.remove(tsik);
+			}
+			self.runs.insert(next.to_owned(), next_unix);
+			self.overrides.insert(task_schedule_id.to_owned(), next_unix);
+			Ok(())
+		}
+		fn read_override(&mut self, task_schedule_id: &str) -> Option<i64> {
+			self.overrides.get(task_schedule_id).copied()
+		}
+		fn remove_override(&mut self, task_schedule_id: &str) -> bool {
+			self.overrides.remove(task_schedule_id).is_some()
+		}
+		fn run_score(&mut self, tsik: &str) -> Option<i64> {
+			self.runs.get(tsik).copied()
+		}
+		fn add_run(&mut self, tsik: &str, unix: i64) {
+			self.runs.insert(tsik.to_owned(), unix);
+		}
+	}
+
+	#[test]
+	fn test_next_run_override_lifecycle() {
+		use crate::errors::RescheduleError;
+		use crate::next_run_override::{active_override, complete_override, reschedule_next_run, RescheduleTarget};
+
+		let now: DateTime<Utc> = DateTime::parse_from_rfc3339("2024-06-30T20:00:00Z").unwrap().into();
+		let tonight: i64 = DateTime::parse_from_rfc3339("2024-07-01T02:00:00Z").unwrap().timestamp();
+		let mut store = MemoryOverrides::default();
+		store.runs.insert(format!("TS-1|{}", tonight), tonight);
+		store.runs.insert(format!("TS-1|{}", tonight + 30), tonight + 30);  // a fast schedule stores several runs
+		store.runs.insert(format!("TS-2|{}", tonight), tonight);
+
+		assert_eq!(RescheduleTarget::parse_by("-30m"), Ok(RescheduleTarget::By(chrono::Duration::minutes(-30))));
+		assert!(matches!(RescheduleTarget::parse_by("soon"), Err(RescheduleError::InvalidOffset { .. })));
+		assert!(RescheduleTarget::parse_until("tonight").is_err());
+
+		// Rejected: in the past, too far ahead, or nothing to move.  Nothing changes.
+		assert!(matches!(reschedule_next_run(&mut store, "TS-1", RescheduleTarget::By(chrono::Duration::hours(-7)), now),
+		                 Err(RescheduleError::NotInFuture { .. })));
+		assert!(matches!(reschedule_next_run(&mut store, "TS-1", RescheduleTarget::By(chrono::Duration::days(8)), now),
+		                 Err(RescheduleError::TooFarAhead { max_days: 7, .. })));
+		assert!(matches!(reschedule_next_run(&mut store, "TS-9", RescheduleTarget::By(chrono::Duration::hours(2)), now),
+		                 Err(RescheduleError::NothingScheduled { .. })));
+		assert_eq!(store.runs.len(), 3);
+
+		// Snoozed by two hours: one run replaces both, and the other schedule is untouched.
+		let rescheduled = reschedule_next_run(&mut store, "TS-1", RescheduleTarget::By(chrono::Duration::hours(2)), now).unwrap();
+		let snoozed: i64 = tonight + 7200;
+		assert_eq!((rescheduled.previous.timestamp(), rescheduled.next.timestamp(), rescheduled.replaced), (tonight, snoozed, 2));
+		assert_eq!(store.runs.keys().cloned().collect::<Vec<String>>(), vec![format!("TS-1|{}", snoozed), format!("TS-2|{}", tonight)]);
+
+		// A full refresh must not put the cron-computed time back; a lost TSIK is restored.
+		assert_eq!(active_override(&mut store, "TS-1", now).map(|next| next.timestamp()), Some(snoozed));
+		store.runs.remove(&format!("TS-1|{}", snoozed));
+		assert_eq!(active_override(&mut store, "TS-1", now).map(|next| next.timestamp()), Some(snoozed));
+		assert_eq!(store.runs.get(&format!("TS-1|{}", snoozed)).copied(), Some(snoozed));
+		assert_eq!(active_override(&mut store, "TS-2", now), None);
+
+		// Another schedule's run, or an earlier run, does not complete the override.  The overridden run does.
+		assert!(!complete_override(&mut store, "TS-2", tonight));
+		assert!(!complete_override(&mut store, "TS-1", tonight));
+		store.runs.remove(&format!("TS-1|{}", snoozed));  // Thread #3 removes the TSIK first
+		assert!(complete_override(&mut store, "TS-1", snoozed));
+		let after_run: DateTime<Utc> = DateTime::parse_from_rfc3339("2024-07-01T04:00:05Z").unwrap().into();
+		assert_eq!(active_override(&mut store, "TS-1", after_run), None);
+
+		// An override whose run was removed some other way, and whose time passed, is forgotten.
+		reschedule_next_run(&mut store, "TS-2", RescheduleTarget::At(now + chrono::Duration::minutes(5)), now).unwrap();
+		store.runs.clear();
+		assert_eq!(active_override(&mut store, "TS-2", now + chrono::Duration::minutes(10)), None);
+		assert!(store.overrides.is_empty());
+	}
+
+	/// Every "btu_scheduler:" key is named in redis_keys, and listed in its registry; so cancelling cannot forget one.
+	#[test]
+	fn test_redis_key_registry_is_complete() {
+		use crate::redis_keys::{schedule_keys, ScheduleKey, BTU_KEY_PREFIX, KEY_FAMILIES, RQ_KEY_CIRCUIT_OPEN, RQ_KEY_CORRELATION_IDS, RQ_KEY_LAST_JOB_IDS, RQ_KEY_NEXT_RUN_OVERRIDE, RQ_KEY_TASK_REPEAT_COUNTS, RQ_KEY_TASK_REPEAT_TOTALS};
+
+		let literal = format!("\"{}", BTU_KEY_PREFIX);
+		let workspace = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().to_owned();
+		let mut registry_literals: Vec<String> = Vec::new();
+		for crate_name in ["btu_scheduler", "btu_daemon", "btu_cli"] {
+			for entry in std::fs::read_dir(workspace.join(crate_name).join("src")).unwrap() {
+				let path = entry.unwrap().path();
+				let file_name: String = path.file_name().unwrap().to_string_lossy().into_owned();
+				if !file_name.ends_with(".rs") || file_name == "tests.rs" {
+					continue;
+				}
+				let source: String = std::fs::read_to_string(&path).unwrap();
+				let literals: Vec<String> = source.match_indices(&literal)
+					.map(|(start, _)| source[start + 1..].split('"').next().unwrap().to_owned())
+					.collect();
+				if crate_name == "btu_scheduler" && file_name == "redis_keys.rs" {
+					registry_literals = literals;
+				} else {
+					assert!(literals.is_empty(), "{}/{} names Redis keys outside redis_keys.rs: {:?}", crate_name, file_name, literals);
+				}
+			}
+		}
+		for key in &registry_literals {
+			assert!(key == BTU_KEY_PREFIX || KEY_FAMILIES.iter().any(|family| family.key == key), "'{}' is missing from KEY_FAMILIES", key);
+		}
+		registry_literals.sort();
+		registry_literals.dedup();
+		assert_eq!(registry_literals.len(), KEY_FAMILIES.len() + 1, "every family has a distinct key");
+
+		let keys: Vec<ScheduleKey> = schedule_keys("site::TS-1");
+		assert_eq!(keys, vec![
+			ScheduleKey::HashField { key: RQ_KEY_CORRELATION_IDS, field: "site::TS-1".to_owned() },
+			ScheduleKey::Key("btu_scheduler:unschedulable:site::TS-1".to_owned()),
+			ScheduleKey::Key("btu_scheduler:failure_count:site::TS-1".to_owned()),
+			ScheduleKey::SetMember { key: RQ_KEY_CIRCUIT_OPEN, member: "site::TS-1".to_owned() },
+			ScheduleKey::HashField { key: RQ_KEY_NEXT_RUN_OVERRIDE, field: "site::TS-1".to_owned() },
+			ScheduleKey::HashField { key: RQ_KEY_LAST_JOB_IDS, field: "site::TS-1".to_owned() },
+			ScheduleKey::HashField { key: RQ_KEY_TASK_REPEAT_COUNTS, field: "site::TS-1".to_owned() },
+			ScheduleKey::HashField { key: RQ_KEY_TASK_REPEAT_TOTALS, field: "site::TS-1".to_owned() },
+		]);
+		assert_eq!(crate::redis_keys::tsik("TS-1", 1669968000), "TS-1|1669968000");
+		assert!(!crate::redis_keys::tsik("TS-10", 1669968000).starts_with(&crate::redis_keys::tsik_prefix("TS-1")));
+		// Glob metacharacters in a Frappe name are matched literally.
+		assert_eq!(crate::redis_keys::tsik_match_pattern("TS-1"), "TS-1|*");
+		assert_eq!(crate::redis_keys::tsik_match_pattern(r"Sync [daily]* \ ok?"), r"Sync \[daily\]\* \\ ok\?|*");
+	}
+
+	/// Needs a live Redis, Frappe web server, and Python RQ worker on queue 'default', configured by BTU_* environment variables:
+	/// `cargo test -p btu_scheduler --features integration -- test_worker_smoke_test_live`
+	#[cfg(feature = "integration")]
+	#[test]
+	fn test_worker_smoke_test_live() {
+		let app_config: AppConfig = AppConfig::new_from_env().expect("BTU_* environment variables describing a live installation");
+		let report = crate::worker_smoke_test(&app_config, "default", std::time::Duration::from_secs(60)).unwrap();
+		assert!(matches!(report.outcome, crate::rq::JobOutcome::Finished { .. }), "{:?}", report);
+		assert!(crate::rq::read_job_by_id(&app_config, &report.job_id).is_err(), "the test job should have been deleted");
+	}
+
+	#[test]
+	fn test_queue_stats_aggregation() {
+		use std::collections::HashMap;
+		use chrono::TimeZone;
+		use crate::queue_stats::{aggregate, report_dates};
+
+		let date = |day: u32| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+		let counts = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+			pairs.iter().map(|(queue, count)| ((*queue).to_owned(), (*count).to_owned())).collect()
+		};
+		assert_eq!(report_dates(date(10), 3), vec![date(8), date(9), date(10)]);
+
+		let buckets = vec![
+			(date(1), counts(&[("default", "500")])),  // before the report's days
+			(date(8), counts(&[("default", "10"), ("long", "2")])),
+			(date(10), counts(&[("default", "5"), ("long", "garbage")])),
+		];
+		let last_enqueue: HashMap<String, String> = [
+			("default".to_owned(), "2024-03-10T12:00:00Z".to_owned()),
+			("idle".to_owned(), "2024-02-01T00:00:00Z".to_owned()),
+		].into_iter().collect();
+		let report = aggregate(date(10), 3, &buckets, &last_enqueue);
+
+		assert_eq!((report.from, report.to, report.days, report.total), (date(8), date(10), 3, 17));
+		let names: Vec<&str> = report.queues.iter().map(|queue| queue.queue_name.as_str()).collect();
+		assert_eq!(names, vec!["default", "idle", "long"]);
+		let default = &report.queues[0];
+		assert_eq!(default.total, 15);
+		assert_eq!(default.per_day.iter().map(|(day, count)| (*day, *count)).collect::<Vec<_>>(), vec![(date(8), 10), (date(10), 5)]);
+		assert_eq!(default.last_enqueue, Some(Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap()));
+		assert!((report.daily_average(default) - 5.0).abs() < f64::EPSILON);
+		// A queue with a last enqueue, but none in the report's days.
+		assert_eq!((report.queues[1].total, report.queues[1].per_day.len()), (0, 0));
+		assert_eq!((report.queues[2].total, report.queues[2].last_enqueue), (2, None));
+
+		// Zero days is treated as one: today.
+		assert_eq!(aggregate(date(10), 0, &buckets, &HashMap::new()).total, 5);
+	}
+
+	/// Every enqueue refreshes the daily hash's expiry, so old days disappear after the retention.
+	#[test]
+	fn test_queue_stats_retention() {
+		use chrono::TimeZone;
+		use crate::queue_stats::{record_enqueue_pipeline, RQ_KEY_QUEUE_LAST_ENQUEUE};
+
+		let now: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 3, 10, 23, 59, 30).unwrap();
+		assert_eq!(pipeline_commands(&record_enqueue_pipeline("default", now, 90)), vec![
+			vec!["HINCRBY".to_owned(), "btu_scheduler:queue_stats:2024-03-10".to_owned(), "default".to_owned(), "1".to_owned()],
+			vec!["EXPIRE".to_owned(), "btu_scheduler:queue_stats:2024-03-10".to_owned(), (90 * 86_400).to_string()],
+			vec!["HSET".to_owned(), RQ_KEY_QUEUE_LAST_ENQUEUE.to_owned(), "default".to_owned(), "2024-03-10T23:59:30Z".to_owned()],
+		]);
+		// A retention of 0 days would delete the hash at once; at least one day is kept.
+		assert_eq!(pipeline_commands(&record_enqueue_pipeline("default", now, 0))[1][2], "86400");
+	}
+
+	/// Each run is added with its time as the score, and only the newest 'task_run_history_size' runs are kept.
+	#[test]
+	fn test_task_run_history() {
+		use chrono::TimeZone;
+		use crate::ids::RqJobId;
+		use crate::run_history::{parse_history, record_run_pipeline, RunOutcome, RunRecord};
+
+		let run_at: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 3, 10, 4, 0, 0).unwrap();
+		let mut record: RunRecord = RunRecord::new(RunOutcome::Enqueued, run_at).job(&RqJobId::new("JOB-1").unwrap());
+		record.at = "2024-03-10T04:00:02Z".to_owned();
+		let json: &str = r#"{"at":"2024-03-10T04:00:02Z","run_at":"2024-03-10T04:00:00Z","outcome":"enqueued","job_id":"JOB-1"}"#;
+		assert_eq!(serde_json::to_string(&record).unwrap(), json);
+		assert_eq!(pipeline_commands(&record_run_pipeline("site::TS-1", &record, 50, 30).unwrap()), vec![
+			vec!["ZADD".to_owned(), "btu_scheduler:task_run_history:site::TS-1".to_owned(), "1710043202".to_owned(), json.to_owned()],
+			vec!["ZREMRANGEBYRANK".to_owned(), "btu_scheduler:task_run_history:site::TS-1".to_owned(), "0".to_owned(), "-51".to_owned()],
+			// The history outlives a cancelled (or deleted) schedule by 30 days, not forever.
+			vec!["EXPIRE".to_owned(), "btu_scheduler:task_run_history:site::TS-1".to_owned(), "2592000".to_owned()],
+		]);
+
+		// Members that are not a RunRecord are passed over; the rest keep their order.
+		let skipped: RunRecord = RunRecord::new(RunOutcome::Skipped, run_at).detail("circuit_open");
+		let members: Vec<String> = vec![serde_json::to_string(&skipped).unwrap(), "not json".to_owned(), json.to_owned()];
+		let history: Vec<RunRecord> = parse_history("key", &members);
+		assert_eq!(history, vec![skipped, record]);
+		assert_eq!(history[0].outcome.to_string(), "skipped");
+		assert_eq!(history[1].run_at_utc(), Some(run_at));
+	}
+
+	/// The full refresh writes every Task Schedule's Next Execution Times with one request.  A pipeline is packed into a single
+	/// buffer, which the connection writes and flushes once, and then reads every reply.
+	#[test]
+	fn test_bulk_schedule_pipeline() {
+		use std::time::Instant;
+		use chrono::{Duration, TimeZone};
+		use crate::scheduler::bulk_schedule_pipeline;
+		use crate::task_schedule::ENABLED_TASK_SCHEDULES_SQL;
+
+		let first: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 3, 10, 4, 0, 0).unwrap();
+		let every_15_secs: Vec<DateTime<Utc>> = vec![first, first + Duration::seconds(15)];
+		let schedule_key = crate::ids::TaskScheduleId::from("site::TS-1");
+		assert_eq!(pipeline_commands(&bulk_schedule_pipeline([(&schedule_key, every_15_secs.as_slice())])), vec![
+			vec!["ZADD", "btu_scheduler:task_execution_times", "1710043200", "site::TS-1|1710043200", "1710043215", "site::TS-1|1710043215"],
+			vec!["DEL", "btu_scheduler:unschedulable:site::TS-1"],
+		]);
+
+		// A large site: 1,800 enabled Task Schedules are one buffer of 3,600 commands, rather than 1,800 round trips.
+		let schedules: Vec<(crate::ids::TaskScheduleId, Vec<DateTime<Utc>>)> = (0..1800)
+			.map(|index| (crate::ids::TaskScheduleId::from(format!("TS-{:06}", index)), vec![first + Duration::minutes(index)]))
+			.collect();
+		let started = Instant::now();
+		let pipeline: redis::Pipeline = bulk_schedule_pipeline(schedules.iter().map(|(schedule_key, runtimes)| (schedule_key, runtimes.as_slice())));
+		let packed: Vec<u8> = pipeline.get_packed_pipeline();
+		let elapsed: std::time::Duration = started.elapsed();
+		assert_eq!(String::from_utf8_lossy(&packed).matches("\r\nZADD\r\n").count(), 1800);
+		assert_eq!(pipeline_commands(&pipeline).len(), 3600);
+		assert!(elapsed < std::time::Duration::from_secs(2), "packing 1,800 Task Schedules took {:?}", elapsed);
+
+		// Every enabled row comes back from one query; only the queue filter's patterns are parameters.
+		assert!(ENABLED_TASK_SCHEDULES_SQL.ends_with("WHERE TaskSchedule.enabled = 1"));
+		assert!(!ENABLED_TASK_SCHEDULES_SQL.contains('?'));
+	}
+
+	/// Names from Frappe are bound as parameters, never spliced into the SQL; quotes and backslashes reach MariaDB intact.
+	#[cfg(feature = "sql")]
+	#[test]
+	fn test_sql_names_are_parameters() {
+		use mysql::{Params, Value};
+		use crate::task::TASK_BY_NAME_SQL;
+		use crate::task_schedule::TASK_SCHEDULE_BY_NAME_SQL;
+
+		for query in [TASK_BY_NAME_SQL, TASK_SCHEDULE_BY_NAME_SQL] {
+			assert_eq!(query.matches('?').count(), 1, "{}", query);
+			assert!(!query.contains("{}"), "{}", query);
+		}
+
+		let awkward_name: &str = r"O'Brien's Nightly Sync \ backslash";
+		let task_id = crate::ids::TaskId::new(awkward_name).unwrap();
+		assert_eq!(task_id.as_str(), awkward_name, "identifiers keep quotes and backslashes");
+		let params: Params = (task_id.as_str(),).into();
+		let Params::Positional(values) = params else { panic!("expected a positional parameter") };
+		assert_eq!(values, vec![Value::Bytes(awkward_name.as_bytes().to_vec())]);
+		// Were the value ever written into SQL text, it would be escaped; spliced with format!(), the quote ended the string.
+		assert_eq!(values[0].as_sql(false), r"'O\'Brien\'s Nightly Sync \\ backslash'");
+	}
+
+	/// Each MySQL error becomes the failure an operator can act on.
+	#[cfg(feature = "sql")]
+	#[test]
+	fn test_classify_sql_error() {
+		use crate::{classify_sql_error, validate_sql_credentials, SqlValidationStatus};
+		use mysql::{DriverError, Error, MySqlError};
+
+		let server_error = |code: u16| Error::MySqlError(MySqlError { state: "42000".to_owned(), message: "denied".to_owned(), code });
+		assert_eq!(classify_sql_error(&Error::IoError(std::io::ErrorKind::ConnectionRefused.into())), SqlValidationStatus::ConnectionRefused);
+		assert_eq!(classify_sql_error(&Error::DriverError(DriverError::ConnectTimeout)), SqlValidationStatus::ConnectionRefused);
+		assert_eq!(classify_sql_error(&Error::DriverError(DriverError::CouldNotConnect(None))), SqlValidationStatus::ConnectionRefused);
+		assert_eq!(classify_sql_error(&server_error(1045)), SqlValidationStatus::AuthFailed);
+		assert_eq!(classify_sql_error(&server_error(1044)), SqlValidationStatus::AuthFailed);
+		assert_eq!(classify_sql_error(&server_error(1049)), SqlValidationStatus::DatabaseMissing);
+		assert_eq!(classify_sql_error(&server_error(1146)), SqlValidationStatus::BtuTablesMissing);
+		assert_eq!(classify_sql_error(&server_error(1064)), SqlValidationStatus::QueryFailed);
+		assert_eq!(classify_sql_error(&Error::DriverError(DriverError::PacketTooLarge)), SqlValidationStatus::QueryFailed);
+
+		// Nothing listens on the test configuration's MariaDB port.
+		let report = validate_sql_credentials(&sample_app_config());
+		assert_eq!(report.status, SqlValidationStatus::ConnectionRefused);
+		assert!(report.describe().contains("127.0.0.1:3306"), "{}", report.describe());
+		let serialized: serde_json::Value = serde_json::to_value(&report).unwrap();
+		assert_eq!(serialized["status"], "connection_refused");
+		assert!(serialized.get("missing_tables").is_none());
+	}
+
+	/// A BTU App without an optional column still has its Task Schedules read; they get the column's default.
+	#[cfg(feature = "sql")]
+	#[test]
+	fn test_optional_column_missing() {
+		use crate::task_schedule::{optional_column_rows, OptionalColumns};
+		use mysql::{Error, MySqlError};
+
+		let server_error = |code: u16| Error::MySqlError(MySqlError { state: "42S22".to_owned(), message: "Unknown column".to_owned(), code });
+		// ER_BAD_FIELD_ERROR: the column does not exist.
+		assert_eq!(optional_column_rows::<Option<String>>("repeat_interval", Err(server_error(1054))), None);
+		// Any other failure also falls back, rather than failing the read.
+		assert_eq!(optional_column_rows::<Option<String>>("repeat_interval", Err(Error::IoError(std::io::ErrorKind::BrokenPipe.into()))), None);
+		let rows: Vec<(String, Option<String>)> = vec![("TS-0001".to_owned(), Some("PT2H".to_owned()))];
+		assert_eq!(optional_column_rows("repeat_interval", Ok(rows.clone())), Some(rows));
+
+		// Without the columns: holidays are not skipped, there is no interval, and the schedule repeats forever.
+		assert_eq!(OptionalColumns::default(), OptionalColumns { skip_holidays: false, repeat_interval: None, repeat: None });
+	}
+
+	/// A deliberately broken configuration fails every check, and each finding says why.
+	#[test]
+	fn test_check_config_reports_each_problem() {
+		use crate::config_check::check_config;
+		use crate::diagnose::{any_failed, DiagnosticFinding, Severity};
+
+		let mut app_config = sample_app_config();
+		app_config.time_zone_string = "America/Atlantis".to_owned();
+		app_config.socket_path = "/no/such/directory/btu_scheduler.sock".to_owned();
+		app_config.socket_file_group_owner = "no_such_btu_group".to_owned();
+		app_config.webserver_port = 9;  // the discard port; nothing listens there
+
+		let findings: Vec<DiagnosticFinding> = check_config(&app_config);
+		let checks: Vec<&str> = findings.iter().map(|finding| finding.check).collect();
+		assert_eq!(checks, vec!["time_zone_string", "mariadb", "redis", "frappe", "socket_path", "socket_file_group_owner"]);
+		let finding = |check: &str| findings.iter().find(|finding| finding.check == check).unwrap();
+		assert!(any_failed(&findings));
+		assert_eq!(finding("time_zone_string").severity, Severity::Fail);
+		assert!(finding("time_zone_string").explanation.starts_with("'America/Atlantis' is not a known time zone: "));
+		#[cfg(feature = "sql")]
+		assert_eq!(finding("mariadb").severity, Severity::Fail);
+		#[cfg(not(feature = "sql"))]
+		assert_eq!(finding("mariadb").severity, Severity::Warn);
+		assert_eq!(finding("redis").severity, Severity::Fail);
+		assert!(finding("redis").explanation.starts_with("Unable to connect to 127.0.0.1:11000: "), "{}", finding("redis").explanation);
+		assert_eq!(finding("frappe").severity, Severity::Fail);
+		assert_eq!(finding("socket_path").explanation, "Directory '/no/such/directory' does not exist.");
+		assert_eq!(finding("socket_file_group_owner").explanation, "There is no Linux group named 'no_such_btu_group'.");
+
+		// The parts that need no server pass, when configured sensibly.
+		app_config.time_zone_string = "America/Los_Angeles".to_owned();
+		app_config.socket_path = "/tmp/btu_scheduler.sock".to_owned();
+		app_config.socket_file_group_owner = "root".to_owned();
+		let findings: Vec<DiagnosticFinding> = check_config(&app_config);
+		for check in ["time_zone_string", "socket_path", "socket_file_group_owner"] {
+			let finding = findings.iter().find(|finding| finding.check == check).unwrap();
+			assert_eq!(finding.severity, Severity::Pass, "{}", finding);
+		}
+	}
+
+	/// Built with '--no-default-features': only the Redis-only parts of the library exist.
+	#[cfg(not(feature = "sql"))]
+	mod without_sql {
+		use super::sample_app_config;
+
+		#[test]
+		fn test_holidays_without_sql() {
+			// A Frappe 'Holiday List' cannot be read; the configuration file's holidays still apply.
+			let mut app_config = sample_app_config();
+			app_config.holiday_dates = Some(vec!["2022-12-25".to_owned()]);
+			app_config.holiday_source = Some("Company Holidays".to_owned());
+			let holidays = crate::scheduler::read_holidays(&app_config);
+			assert_eq!(holidays.len(), 1);
+			assert!(holidays.contains(&chrono::NaiveDate::from_ymd_opt(2022, 12, 25).unwrap()));
+		}
+	}
+
+	/// A frame is read whole, however the bytes arrive; one that is too long is refused before its payload is read.
+	#[test]
+	fn test_socket_frames() {
+		use std::io::Read;
+		use crate::errors::FrameError;
+		use crate::socket_frame::{self, ReceivedMessage};
+
+		// Hands out one byte per read, like a very slow client.
+		struct Trickle(std::io::Cursor<Vec<u8>>);
+		impl Read for Trickle {
+			fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+				let end: usize = buffer.len().min(1);
+				self.0.read(&mut buffer[..end])
+			}
+		}
+		let trickle = |bytes: &[u8]| Trickle(std::io::Cursor::new(bytes.to_vec()));
+
+		let payload: &[u8] = br#"{"request_type": "ping"}"#;
+		let frame: Vec<u8> = socket_frame::encode_frame(payload);
+		assert_eq!(&frame[..4], &[0, 0, 0, payload.len() as u8]);
+		assert_eq!(socket_frame::read_frame(&mut trickle(&frame), 64).unwrap(), payload);
+		assert_eq!(socket_frame::read_message(&mut trickle(&frame), 64).unwrap(), ReceivedMessage { payload: payload.to_vec(), framed: true });
+
+		let result = socket_frame::read_frame(&mut trickle(&frame), 10);
+		assert!(matches!(result, Err(FrameError::TooLarge { length: 24, max_bytes: 10 })), "{:?}", result);
+		// A frame cut short is an error, never a partial message.
+		assert!(matches!(socket_frame::read_frame(&mut trickle(&frame[..20]), 64), Err(FrameError::Io { .. })));
+
+		// Bare JSON (from clients older than protocol version 4) ends with the JSON value.
+		let message: ReceivedMessage = socket_frame::read_message(&mut trickle(br#"{"request_type": "ping"}  trailing"#), 64).unwrap();
+		assert_eq!(message, ReceivedMessage { payload: payload.to_vec(), framed: false });
+		let result = socket_frame::read_message(&mut trickle(br#"{"request_type": "a much longer request than allowed"}"#), 16);
+		assert!(matches!(result, Err(FrameError::TooLarge { max_bytes: 16, .. })), "{:?}", result);
+		// Malformed or incomplete JSON is returned as it arrived, for the caller to reject.
+		assert_eq!(socket_frame::read_message(&mut trickle(br#"{"request_type"#), 64).unwrap().payload, br#"{"request_type"#);
+	}
+
+	/// A SchedulerBackend without databases: it records what the runtime asked of it.
+	#[derive(Default)]
+	struct FakeBackend {
+		scores: std::collections::HashMap<String, i64>,        // the TSIKs "in Redis"
+		next_run: Option<DateTime<Utc>>,                        // what schedule_task() writes
+		sql_unavailable: bool,
+		scheduled: Vec<crate::ids::TaskScheduleId>,
+		refreshes: Vec<crate::schedule_queue::QueueSource>,
+		refresh_fails: bool,
+		due: Vec<crate::ids::TaskScheduleId>,                   // runs that run_eligible() enqueues
+		maintenance: Vec<crate::runtime::MaintenanceTask>,
+	}
+
+	impl crate::runtime::SchedulerBackend for FakeBackend {
+		fn scheduled_score(&mut self, _app_config: &AppConfig, tsik: &str) -> Option<i64> {
+			self.scores.get(tsik).copied()
+		}
+		fn schedule_task(&mut self, _site_config: &AppConfig, task_schedule_id: &crate::ids::TaskScheduleId,
+		                 _correlation_id: Option<&crate::ids::CorrelationId>) -> Result<Option<DateTime<Utc>>, crate::runtime::ScheduleTaskError> {
+			if self.sql_unavailable {
+				return Err(crate::runtime::ScheduleTaskError::Read(crate::errors::SchedulerError::SqlUnavailable {
+					task_schedule_id: task_schedule_id.to_string(), message: "connection refused".to_owned()
+				}));
+			}
+			self.scheduled.push(task_schedule_id.clone());
+			if let Some(next_run) = self.next_run {
+				self.scores.insert(crate::redis_keys::tsik(task_schedule_id.as_str(), next_run.timestamp()), next_run.timestamp());
+			}
+			Ok(self.next_run)
+		}
+		fn abandon_schedule_write(&mut self, _site_config: &AppConfig, _task_schedule: &crate::task_schedule::BtuTaskSchedule,
+		                          _error: &crate::errors::RQError) {}
+		fn full_refresh(&mut self, _app_config: &AppConfig, _queue: &std::sync::Mutex<crate::schedule_queue::ScheduleQueue>,
+		                source: crate::schedule_queue::QueueSource) -> Result<crate::runtime::RefreshSummary, String> {
+			self.refreshes.push(source);
+			if self.refresh_fails {
+				return Err("MariaDB is unreachable".to_owned());
+			}
+			Ok(crate::runtime::RefreshSummary { refreshed: 3, queued: 0 })
+		}
+		fn run_eligible(&mut self, _app_config: &AppConfig, queue: &mut crate::schedule_queue::ScheduleQueue, now: DateTime<Utc>) -> crate::scheduler::CycleSummary {
+			use crate::schedule_queue::{QueueEntry, QueueSource};
+			let mut summary = crate::scheduler::CycleSummary::new(now, now.timestamp());
+			for task_schedule_id in self.due.drain(..) {
+				// As run_immediate_scheduled_task() does, once the RQ Job is enqueued.
+				queue.push_priority(QueueEntry::new(task_schedule_id, QueueSource::PostRunRequeue));
+				summary.eligible += 1;
+				summary.enqueued += 1;
+			}
+			summary
+		}
+		fn run_maintenance(&mut self, _app_config: &AppConfig, task: crate::runtime::MaintenanceTask) {
+			self.maintenance.push(task);
+		}
+	}
+
+	/// Thread #2's ticks, on a fake clock: the full refresh every 'full_refresh_internal_secs', and each housekeeping job at its interval.
+	#[test]
+	fn test_runtime_refresh_timing() {
+		use std::time::{Duration, Instant};
+		use crate::runtime::{MaintenanceTask, SchedulerRuntime};
+		use crate::schedule_queue::QueueSource;
+
+		let app_config = sample_app_config();  // full_refresh_internal_secs = 900
+		let start = Instant::now();
+		let at = |secs: u64| start + Duration::from_secs(secs);
+		let runtime = SchedulerRuntime::new(start);
+		let mut backend = FakeBackend::default();
+
+		assert!(runtime.tick_full_refresh(&app_config, &mut backend, at(1)).is_none());
+		assert!(runtime.tick_full_refresh(&app_config, &mut backend, at(900)).is_none());
+		assert_eq!(runtime.tick_full_refresh(&app_config, &mut backend, at(901)).unwrap().unwrap().refreshed, 3);
+		assert_eq!(backend.refreshes, vec![QueueSource::FullRefresh]);
+		// The countdown starts again from the refresh.
+		assert!(runtime.tick_full_refresh(&app_config, &mut backend, at(1801)).is_none());
+		assert!(runtime.tick_full_refresh(&app_config, &mut backend, at(1802)).is_some());
+
+		// A failed refresh does not reset the countdown, so the next tick tries again.
+		backend.refresh_fails = true;
+		assert!(runtime.tick_full_refresh(&app_config, &mut backend, at(2703)).unwrap().is_err());
+		assert!(runtime.tick_full_refresh(&app_config, &mut backend, at(2704)).unwrap().is_err());
+		backend.refresh_fails = false;
+		assert!(runtime.tick_full_refresh(&app_config, &mut backend, at(2705)).unwrap().is_ok());
+		assert!(runtime.tick_full_refresh(&app_config, &mut backend, at(2706)).is_none());
+		assert_eq!(backend.refreshes.len(), 5);
+
+		// The startup refresh is not on the clock.
+		assert!(runtime.full_refresh(&app_config, &mut backend, QueueSource::Startup).is_ok());
+		assert_eq!(backend.refreshes.last(), Some(&QueueSource::Startup));
+
+		// Housekeeping, on a second runtime, so its clock starts again.
+		let runtime = SchedulerRuntime::new(start);
+		assert!(runtime.tick_maintenance(&app_config, &mut backend, at(60)).is_empty());
+		assert_eq!(runtime.tick_maintenance(&app_config, &mut backend, at(61)), vec![MaintenanceTask::JobWatchdog]);
+		assert!(runtime.tick_maintenance(&app_config, &mut backend, at(100)).is_empty());
+		assert_eq!(runtime.tick_maintenance(&app_config, &mut backend, at(301)), vec![MaintenanceTask::JobWatchdog, MaintenanceTask::RedisMemoryCheck]);
+		assert_eq!(runtime.tick_maintenance(&app_config, &mut backend, at(3601)), MaintenanceTask::ALL.to_vec());
+		assert_eq!(backend.maintenance.len(), 6);
+	}
+
+	/// After a run, the Task Schedule is back on the Internal Queue, ahead of the refresh backlog; Thread #1's next tick schedules it.
+	#[test]
+	fn test_runtime_requeue_after_run() {
+		use chrono::TimeZone;
+		use crate::ids::TaskScheduleId;
+		use crate::runtime::{SchedulerRuntime, INTERNAL_QUEUE_PAUSE};
+		use crate::schedule_queue::{QueueEntry, QueueSource, ScheduleFingerprint};
+		use crate::scheduler::sql_retry_backoff;
+
+		let app_config = sample_app_config();
+		let now: DateTime<Utc> = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+		let runtime = SchedulerRuntime::new(std::time::Instant::now());
+		let mut backend = FakeBackend { next_run: Some(now + chrono::Duration::hours(1)), ..FakeBackend::default() };
+		let queue = runtime.queue();
+
+		// Nothing queued: Thread #1 waits.
+		assert_eq!(runtime.tick_internal_queue(&app_config, &mut backend, now), INTERNAL_QUEUE_PAUSE);
+		assert!(backend.scheduled.is_empty());
+
+		queue.lock().unwrap().push_back(QueueEntry::new("TS-REFRESH".into(), QueueSource::FullRefresh));
+		backend.due = vec!["TS-RAN".into()];
+		let summary = runtime.tick_promote_ready(&app_config, &mut backend, now).unwrap();
+		assert_eq!((summary.enqueued, summary.cutoff_unix), (1, now.timestamp()));
+		assert_eq!(queue.lock().unwrap().len(), 2);
+
+		runtime.tick_internal_queue(&app_config, &mut backend, now);
+		runtime.tick_internal_queue(&app_config, &mut backend, now);
+		assert_eq!(backend.scheduled, vec![TaskScheduleId::from("TS-RAN"), TaskScheduleId::from("TS-REFRESH")]);
+		assert!(queue.lock().unwrap().is_empty());
+
+		// A full-refresh entry that has not changed, and is still scheduled, needs no work; the next entry follows at once.
+		let refreshed = || QueueEntry::new("TS-REFRESH".into(), QueueSource::FullRefresh)
+			.with_fingerprint(ScheduleFingerprint::new("2024-01-01 00:00:00", "0 * * * *|"));
+		queue.lock().unwrap().push_back(refreshed());
+		assert_eq!(runtime.tick_internal_queue(&app_config, &mut backend, now), INTERNAL_QUEUE_PAUSE);
+		queue.lock().unwrap().push_back(refreshed());
+		assert_eq!(runtime.tick_internal_queue(&app_config, &mut backend, now), std::time::Duration::ZERO);
+		assert_eq!(backend.scheduled.len(), 3);
+
+		// MariaDB unreachable: the entry stays at the front, and Thread #1 backs off.
+		backend.sql_unavailable = true;
+		queue.lock().unwrap().push_back(QueueEntry::new("TS-WAITING".into(), QueueSource::Socket));
+		assert_eq!(runtime.tick_internal_queue(&app_config, &mut backend, now), sql_retry_backoff(&app_config));
+		assert_eq!(queue.lock().unwrap().pop_front().map(|entry| entry.schedule_id), Some("TS-WAITING".into()));
+	}
+
 }  // end mod tests
 
 	/* Feature below is Not-Yet-Implemented.
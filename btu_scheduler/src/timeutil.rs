@@ -0,0 +1,103 @@
+// timeutil.rs
+
+/*
+	Durations, for people: "14 minutes", "in 2 hours", "3 days ago"; and the durations people type in CLI flags: "90s",
+	"15m", "2h", "1d", or a bare number of seconds.
+
+	The output is always English, and never depends on the system's locale: no separators in numbers, no translated units.
+	Amounts are rounded down to the largest whole unit (14 minutes and 59 seconds is "14 minutes"), and days are the largest
+	unit; months and years have no fixed length.  Where space is short, compact_instant() uses the units people type, and
+	the two largest of them: "in 3h 12m".
+*/
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::errors::DurationArgError;
+
+const UNITS: [(char, i64); 4] = [('s', 1), ('m', 60), ('h', 3600), ('d', 86400)];
+
+/// A duration in its largest whole unit, such as "14 minutes" or "2 days".  Negative durations are in the past: "3 hours ago".
+pub fn humanize_duration(duration: Duration) -> String {
+	let seconds: i64 = duration.num_seconds();
+	let magnitude: String = humanize_magnitude(seconds.unsigned_abs());
+	if seconds < 0 {
+		format!("{} ago", magnitude)
+	} else {
+		magnitude
+	}
+}
+
+/// When an instant is, relative to now: "in 14 minutes", "3 hours ago", or "now".
+pub fn humanize_instant(instant: DateTime<Utc>, now: DateTime<Utc>) -> String {
+	let duration: Duration = instant - now;
+	match duration.num_seconds() {
+		0 => "now".to_owned(),
+		seconds if seconds > 0 => format!("in {}", humanize_magnitude(seconds.unsigned_abs())),
+		seconds => format!("{} ago", humanize_magnitude(seconds.unsigned_abs())),
+	}
+}
+
+/// When an instant is, relative to now, in its two largest units: "in 3h 12m", "45s ago", or "now".  A zero second unit is
+/// left out: "in 2d".
+pub fn compact_instant(instant: DateTime<Utc>, now: DateTime<Utc>) -> String {
+	let seconds: i64 = (instant - now).num_seconds();
+	match seconds {
+		0 => "now".to_owned(),
+		seconds if seconds > 0 => format!("in {}", compact_magnitude(seconds.unsigned_abs())),
+		seconds => format!("{} ago", compact_magnitude(seconds.unsigned_abs())),
+	}
+}
+
+fn compact_magnitude(seconds: u64) -> String {
+	let seconds: i64 = i64::try_from(seconds).unwrap_or(i64::MAX);
+	let index: usize = UNITS.iter().rposition(|(_, unit_secs)| seconds >= *unit_secs).unwrap_or(0);
+	let (unit, unit_secs): (char, i64) = UNITS[index];
+	let mut text: String = format!("{}{}", seconds / unit_secs, unit);
+	if let Some((next_unit, next_secs)) = index.checked_sub(1).map(|next| UNITS[next]) {
+		let remainder: i64 = seconds % unit_secs / next_secs;
+		if remainder > 0 {
+			text.push_str(&format!(" {}{}", remainder, next_unit));
+		}
+	}
+	text
+}
+
+fn humanize_magnitude(seconds: u64) -> String {
+	let (amount, unit): (u64, &str) = match seconds {
+		0..=59 => (seconds, "second"),
+		60..=3599 => (seconds / 60, "minute"),
+		3600..=86399 => (seconds / 3600, "hour"),
+		_ => (seconds / 86400, "day"),
+	};
+	if amount == 1 {
+		format!("1 {}", unit)
+	} else {
+		format!("{} {}s", amount, unit)
+	}
+}
+
+/// Parse a CLI flag's duration: a whole number followed by s, m, h, or d ("90s", "15m", "2h", "1d"), or a bare number of
+/// seconds.  A leading '-' makes it negative, for flags that move a time earlier.
+pub fn parse_duration_arg(text: &str) -> Result<Duration, DurationArgError> {
+	let invalid = || DurationArgError::Invalid { text: text.to_owned() };
+	let trimmed: &str = text.trim();
+	let (sign, magnitude): (i64, &str) = match trimmed.strip_prefix('-') {
+		Some(rest) => (-1, rest),
+		None => (1, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+	};
+	let (digits, unit_secs): (&str, i64) = match magnitude.char_indices().last() {
+		Some((index, unit)) if unit.is_ascii_alphabetic() => {
+			let unit_secs: i64 = UNITS.iter().find(|(candidate, _)| *candidate == unit.to_ascii_lowercase()).ok_or_else(invalid)?.1;
+			(&magnitude[..index], unit_secs)
+		},
+		_ => (magnitude, 1),
+	};
+	if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+		return Err(invalid());
+	}
+	let seconds: i64 = digits.parse::<i64>().ok()
+		.and_then(|amount| amount.checked_mul(unit_secs))
+		.filter(|seconds| *seconds <= Duration::max_value().num_seconds())
+		.ok_or(DurationArgError::TooLarge { text: text.to_owned() })?;
+	Ok(Duration::seconds(sign * seconds))
+}
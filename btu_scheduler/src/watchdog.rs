@@ -0,0 +1,275 @@
+// watchdog.rs
+
+/*
+	RQ enforces a job's timeout inside the worker.  A hung Python process can ignore it, and block its queue for hours.
+
+	As a safety net, BTU remembers every job it enqueues (in the Redis hash 'btu_scheduler:watched_jobs'), and the daemon
+	periodically compares each started job's 'started_at' with its timeout, plus a grace margin.  A job that overruns is
+	reported once: logged, written to the event stream, and emailed (when email is configured).
+
+	With 'kill_overrunning_jobs', the job is also moved to the failed state, as RQ itself would record a failure:
+	status 'failed', 'ended_at', and 'exc_info' on the job hash; removed from the StartedJobRegistry ('rq:wip:<queue>');
+	added to the FailedJobRegistry ('rq:failed:<queue>').  The worker process itself is not signaled; it belongs to RQ.
+*/
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use redis::{Commands, RedisError};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, warn};
+
+#[cfg(feature = "email-feat")]
+use crate::email::{self, make_email_body_preamble};
+
+use crate::config::AppConfig;
+use crate::events::{self, EventKind, SchedulerEvent};
+use crate::ids::{RqJobId, TaskScheduleId};
+use crate::rq;
+
+pub use crate::redis_keys::RQ_KEY_WATCHED_JOBS;
+/// Default for 'job_watchdog_grace_secs'.
+pub const DEFAULT_WATCHDOG_GRACE_SECS: u32 = 300;
+/// How long a failed job stays in RQ's FailedJobRegistry.  This is RQ's own default 'failure_ttl' (one year).
+pub const RQ_FAILURE_TTL_SECS: i64 = 31_536_000;
+
+/// A job that BTU enqueued, and the limits it is held to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WatchedJob {
+	pub job_id: RqJobId,
+	pub schedule_id: TaskScheduleId,
+	pub queue_name: String,
+	pub timeout_secs: u32,
+	pub enqueued_at: String,  // RFC 3339, UTC
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub overran_at: Option<String>,  // RFC 3339, UTC; when the watchdog first reported the overrun (it is reported once)
+}
+
+impl WatchedJob {
+	pub fn new(job_id: &RqJobId, schedule_id: &TaskScheduleId, queue_name: &str, timeout_secs: u32, enqueued_at: DateTime<Utc>) -> Self {
+		WatchedJob {
+			job_id: job_id.clone(),
+			schedule_id: schedule_id.clone(),
+			queue_name: queue_name.to_owned(),
+			timeout_secs,
+			enqueued_at: enqueued_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+			overran_at: None,
+		}
+	}
+}
+
+/// What the watchdog concluded about one watched job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchVerdict {
+	Forget,     // finished, failed, stopped, or deleted; no longer watched
+	Waiting,    // not started by a worker yet
+	Running,    // started, and within its timeout plus grace (or its start time is unreadable)
+	Unlimited,  // started, with a timeout of -1; RQ lets it run forever
+	Overran { elapsed_secs: i64, limit_secs: i64 },
+}
+
+/// The job's timeout in seconds.  RQ stores it as text: "3600", or "3600.0" in some versions.  -1 means no limit.
+/// Falls back to the timeout recorded when BTU enqueued the job.
+pub fn job_timeout_secs(job_hash: &HashMap<String, Vec<u8>>, watched: &WatchedJob) -> i64 {
+	rq::hashmap_value_to_optstring(job_hash, "timeout")
+		.and_then(|timeout| timeout.trim().parse::<f64>().ok())
+		.map_or(i64::from(watched.timeout_secs), |timeout| timeout as i64)
+}
+
+/**
+  Classify a watched job from its 'rq:job:<id>' hash (empty when the key no longer exists).\
+  Older RQ versions may not set 'status'; a 'started_at' then counts as started, and an 'ended_at' as finished.
+*/
+pub fn classify_watched_job(job_hash: &HashMap<String, Vec<u8>>, watched: &WatchedJob, now: DateTime<Utc>, grace_secs: u32) -> WatchVerdict {
+	if job_hash.is_empty() {
+		return WatchVerdict::Forget;
+	}
+	let status: String = rq::hashmap_value_to_optstring(job_hash, "status").unwrap_or_default();
+	let started: bool = match status.as_str() {
+		"started" => true,
+		"" if job_hash.contains_key("ended_at") => return WatchVerdict::Forget,
+		"" => job_hash.contains_key("started_at"),
+		"queued" | "deferred" | "scheduled" => false,
+		_ => return WatchVerdict::Forget,  // finished, failed, stopped, canceled
+	};
+	if !started {
+		return WatchVerdict::Waiting;
+	}
+	let timeout_secs: i64 = job_timeout_secs(job_hash, watched);
+	if timeout_secs < 0 {
+		return WatchVerdict::Unlimited;
+	}
+	let Some(started_at) = rq::hashmap_value_to_utcdatetime(job_hash, "started_at") else {
+		return WatchVerdict::Running;
+	};
+	let elapsed_secs: i64 = (now - started_at).num_seconds();
+	let limit_secs: i64 = timeout_secs + i64::from(grace_secs);
+	if elapsed_secs > limit_secs {
+		WatchVerdict::Overran { elapsed_secs, limit_secs }
+	}
+	else {
+		WatchVerdict::Running
+	}
+}
+
+/// Is a worker running the job now?  Without a 'status' (older RQ versions), a 'started_at' and no 'ended_at'.
+pub fn job_is_started(job_hash: &HashMap<String, Vec<u8>>) -> bool {
+	match rq::hashmap_value_to_optstring(job_hash, "status").as_deref() {
+		Some("started") => true,
+		None | Some("") => job_hash.contains_key("started_at") && !job_hash.contains_key("ended_at"),
+		Some(_) => false,
+	}
+}
+
+pub fn started_registry_key(queue_name: &str) -> String {
+	format!("rq:wip:{}", queue_name)
+}
+
+pub fn failed_registry_key(queue_name: &str) -> String {
+	format!("rq:failed:{}", queue_name)
+}
+
+/// The StartedJobRegistry members that belong to a job.  Before RQ 2.0, the member is the Job ID; since, it is "<job_id>:<execution_id>".
+pub fn started_registry_members_for_job(members: &[String], job_id: &str) -> Vec<String> {
+	members.iter()
+		.filter(|member| member.as_str() == job_id || member.strip_prefix(job_id).map_or(false, |rest| rest.starts_with(':')))
+		.cloned()
+		.collect()
+}
+
+/// The job hash fields written when the watchdog fails an overrunning job.  RQ reads a plain-text 'exc_info' (it only
+/// decompresses when the value is zlib data).
+pub fn failure_fields(now: DateTime<Utc>, elapsed_secs: i64, limit_secs: i64) -> [(&'static str, String); 3] {
+	[
+		("status", "failed".to_owned()),
+		("ended_at", rq::utc_to_rq_string(now)),
+		("exc_info", format!("BTU Scheduler timed out this job: it was still running {} seconds after it started, \
+		                      beyond its timeout plus grace margin of {} seconds.  The worker may still be running it.", elapsed_secs, limit_secs)),
+	]
+}
+
+/// Start watching a job that BTU just enqueued.  Best-effort: a failure is only logged.
+pub fn watch_job(redis_conn: &mut redis::Connection, watched: &WatchedJob) {
+	let json: String = serde_json::to_string(watched).unwrap_or_default();
+	if let Err(error) = redis_conn.hset::<_, _, _, ()>(RQ_KEY_WATCHED_JOBS, watched.job_id.as_str(), json) {
+		warn!("Unable to watch RQ Job '{}' for overruns: {}", watched.job_id, error);
+	}
+}
+
+/// How an attempt to fail an overrunning job ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailOutcome {
+	Failed,       // moved to the failed state
+	NotStarted,   // the job finished (or was otherwise moved on) before it could be failed; it was left alone
+}
+
+/**
+  Move an overrunning job to the failed state, the way RQ records a failure, in one transaction.\
+  The job hash and the StartedJobRegistry are WATCHed, and the job's status is checked again inside the transaction: a job
+  that finishes between the watchdog's check and this write is left alone, rather than overwritten as failed.
+*/
+pub fn fail_overrunning_job(redis_conn: &mut redis::Connection, job_id: &RqJobId, queue_name: &str, now: DateTime<Utc>,
+                            elapsed_secs: i64, limit_secs: i64) -> Result<FailOutcome, RedisError> {
+	let job_key: String = rq::job_key(job_id.as_str());
+	let started_key: String = started_registry_key(queue_name);
+	redis::transaction(redis_conn, &[&job_key, &started_key], |redis_conn, pipe| {
+		let job_hash: HashMap<String, Vec<u8>> = redis_conn.hgetall(&job_key)?;
+		if !job_is_started(&job_hash) {
+			return Ok(Some(FailOutcome::NotStarted));
+		}
+		let members: Vec<String> = redis_conn.zrange(&started_key, 0, -1)?;
+		let started_members: Vec<String> = started_registry_members_for_job(&members, job_id.as_str());
+		pipe.hset_multiple(&job_key, &failure_fields(now, elapsed_secs, limit_secs)).ignore()
+			.zadd(failed_registry_key(queue_name), job_id.as_str(), now.timestamp() + RQ_FAILURE_TTL_SECS).ignore();
+		if !started_members.is_empty() {
+			pipe.zrem(&started_key, started_members).ignore();
+		}
+		// None when a watched key changed before EXEC; the status is then read again.
+		let result: Option<()> = pipe.query(redis_conn)?;
+		Ok(result.map(|()| FailOutcome::Failed))
+	})
+}
+
+/// What one watchdog pass found, and did.
+#[derive(Debug, Default)]
+pub struct WatchdogReport {
+	pub jobs_watched: usize,
+	pub jobs_forgotten: usize,
+	pub overrunning: Vec<RqJobId>,  // newly found this pass
+	pub jobs_failed: usize,
+}
+
+/// Check every watched job against its timeout plus grace margin.  Does nothing unless 'job_watchdog_enabled' is true.
+pub fn check_watched_jobs(app_config: &AppConfig) -> Result<WatchdogReport, std::io::Error> {
+	let mut report = WatchdogReport::default();
+	if !app_config.job_watchdog_enabled {
+		return Ok(report);
+	}
+	let Some(mut redis_conn) = rq::get_redis_connection(app_config, false) else {
+		return Err(std::io::Error::new(std::io::ErrorKind::NotConnected, "Unable to establish connection to Redis."));
+	};
+	let redis_error = |error: RedisError| std::io::Error::new(std::io::ErrorKind::Other, format!("Redis error while checking watched jobs: {}", error));
+	let grace_secs: u32 = app_config.job_watchdog_grace_secs.unwrap_or(DEFAULT_WATCHDOG_GRACE_SECS);
+	let now: DateTime<Utc> = Utc::now();
+
+	let watched_jobs: HashMap<String, String> = redis_conn.hgetall(RQ_KEY_WATCHED_JOBS).map_err(redis_error)?;
+	report.jobs_watched = watched_jobs.len();
+	for (job_id, json) in watched_jobs {
+		let Ok(mut watched) = serde_json::from_str::<WatchedJob>(&json) else {
+			debug!("Forgetting unreadable entry '{}' of '{}'.", job_id, RQ_KEY_WATCHED_JOBS);
+			let _: Result<u32, RedisError> = redis_conn.hdel(RQ_KEY_WATCHED_JOBS, &job_id);
+			continue;
+		};
+		let job_hash: HashMap<String, Vec<u8>> = redis_conn.hgetall(rq::job_key(job_id.as_str())).map_err(redis_error)?;
+		match classify_watched_job(&job_hash, &watched, now, grace_secs) {
+			WatchVerdict::Forget => {
+				let _: Result<u32, RedisError> = redis_conn.hdel(RQ_KEY_WATCHED_JOBS, &job_id);
+				report.jobs_forgotten += 1;
+			},
+			WatchVerdict::Overran { elapsed_secs, limit_secs } if watched.overran_at.is_none() => {
+				let queue_name: String = rq::hashmap_value_to_optstring(&job_hash, "origin").unwrap_or_else(|| watched.queue_name.clone());
+				let mut reason: String = format!("overran timeout: running for {} seconds; the limit (timeout plus grace) is {} seconds", elapsed_secs, limit_secs);
+				error!("RQ Job '{}' (Task Schedule {}, queue '{}') {}.", job_id, watched.schedule_id, queue_name, reason);
+				if app_config.kill_overrunning_jobs {
+					match fail_overrunning_job(&mut redis_conn, &watched.job_id, &queue_name, now, elapsed_secs, limit_secs) {
+						Ok(FailOutcome::Failed) => {
+							warn!("RQ Job '{}' was moved to the failed state ('kill_overrunning_jobs'), so queue '{}' can proceed.", job_id, queue_name);
+							reason.push_str("; moved to the failed state");
+							report.jobs_failed += 1;
+						},
+						Ok(FailOutcome::NotStarted) => {
+							info!("RQ Job '{}' is no longer running; it was not moved to the failed state.", job_id);
+							reason.push_str("; it ended before it could be moved to the failed state");
+						},
+						Err(error) => error!("Unable to move RQ Job '{}' to the failed state: {}", job_id, error)
+					}
+				}
+				events::record_event(app_config, &mut redis_conn,
+				                     SchedulerEvent::new(EventKind::Overran).schedule(&watched.schedule_id).job(&watched.job_id).reason(reason.clone()));
+				notify_overrun(app_config, &watched, &reason);
+				watched.overran_at = Some(now.to_rfc3339_opts(SecondsFormat::Secs, true));
+				watch_job(&mut redis_conn, &watched);
+				report.overrunning.push(watched.job_id);
+			},
+			WatchVerdict::Overran { .. } | WatchVerdict::Waiting | WatchVerdict::Running | WatchVerdict::Unlimited => {}
+		}
+	}
+	if !report.overrunning.is_empty() {
+		info!("Job watchdog: {} watched RQ Jobs; {} newly found overrunning; {} moved to the failed state.",
+		      report.jobs_watched, report.overrunning.len(), report.jobs_failed);
+	}
+	Ok(report)
+}
+
+/// Email the recipients (when email is configured) about a job that overran its timeout.
+#[allow(unused_variables)]
+fn notify_overrun(app_config: &AppConfig, watched: &WatchedJob, reason: &str) {
+	#[cfg(feature = "email-feat")]  // Only compile this code when email feature is enabled:
+	if app_config.email_addresses.as_ref().map_or(false, |addresses| !addresses.is_empty()) {
+		let body: String = format!("{}\nRQ Job '{}' for BTU Task Schedule {} (queue '{}') {}.",
+			make_email_body_preamble(app_config), watched.job_id, watched.schedule_id, watched.queue_name, reason);
+		if let Err(error) = email::send_email(app_config, "BTU job overran its timeout", &body) {
+			error!("Error while attempting to send an email: {:?}", error);
+		}
+	}
+}